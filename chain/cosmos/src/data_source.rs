@@ -86,6 +86,10 @@ impl blockchain::DataSource<Chain> for DataSource {
         kinds
     }
 
+    fn declared_entities(&self) -> &[String] {
+        &self.mapping.entities
+    }
+
     fn end_block(&self) -> Option<BlockNumber> {
         self.source.end_block
     }
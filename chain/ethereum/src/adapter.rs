@@ -972,6 +972,16 @@ pub trait EthereumAdapter: Send + Sync + 'static {
         cache: Arc<dyn EthereumCallCache>,
     ) -> Box<dyn Future<Item = Vec<Token>, Error = EthereumContractCallError> + Send>;
 
+    /// Call the functions of smart contracts with one request to the
+    /// Ethereum node, using and filling `cache` just like `contract_call`.
+    /// The results are in the same order as `calls`
+    async fn contract_calls(
+        &self,
+        logger: &Logger,
+        calls: Vec<EthereumContractCall>,
+        cache: Arc<dyn EthereumCallCache>,
+    ) -> Result<Vec<Result<Vec<Token>, EthereumContractCallError>>, EthereumContractCallError>;
+
     fn get_balance(
         &self,
         logger: &Logger,
@@ -10,7 +10,8 @@ use graph::prelude::futures03::future::try_join;
 use graph::prelude::futures03::stream::FuturesOrdered;
 use graph::prelude::{Link, SubgraphManifestValidationError};
 use graph::slog::{o, trace};
-use std::collections::HashSet;
+use serde::de;
+use std::collections::{BTreeMap, HashSet};
 use std::num::NonZeroU32;
 use std::str::FromStr;
 use std::sync::Arc;
@@ -20,7 +21,7 @@ use graph::{
     blockchain::{self, Blockchain},
     prelude::{
         async_trait,
-        ethabi::{Address, Contract, Event, Function, LogParam, ParamType, RawLog},
+        ethabi::{Address, Contract, Event, Function, LogParam, ParamType, RawLog, Token},
         serde_json, warn,
         web3::types::{Log, Transaction, H256},
         BlockNumber, CheapClone, Deserialize, EthereumCall, LightEthereumBlock,
@@ -142,6 +143,10 @@ impl blockchain::DataSource<Chain> for DataSource {
         kinds
     }
 
+    fn declared_entities(&self) -> &[String] {
+        &self.mapping.entities
+    }
+
     fn start_block(&self) -> BlockNumber {
         self.start_block
     }
@@ -347,6 +352,25 @@ impl blockchain::DataSource<Chain> for DataSource {
             }
         }
 
+        // Validate that declared calls refer to functions in the ABIs and
+        // to parameters of the event
+        for event_handler in &self.mapping.event_handlers {
+            let event = match self.contract_event_with_signature(&event_handler.event) {
+                Some(event) => event,
+                None => continue,
+            };
+            for decl in event_handler.calls.decls.iter() {
+                if let Err(e) = decl.validate(&self.mapping, event) {
+                    errors.push(anyhow!(
+                        "invalid call `{}` for event handler `{}`: {}",
+                        decl.label,
+                        event_handler.handler,
+                        e
+                    ));
+                }
+            }
+        }
+
         errors
     }
 
@@ -735,6 +759,24 @@ impl DataSource {
                     }
                 };
 
+                // Declared calls only save the handler from making them; if
+                // one can not be resolved, the handler makes it itself
+                let calls = event_handler
+                    .calls
+                    .decls
+                    .iter()
+                    .filter_map(|decl| {
+                        decl.resolve(&self.mapping, &log, &params)
+                            .map_err(|e| {
+                                trace!(logger, "Skipping declared call";
+                                    "label" => &decl.label,
+                                    "handler" => &event_handler.handler,
+                                    "error" => e.to_string());
+                            })
+                            .ok()
+                    })
+                    .collect();
+
                 let logging_extras = Arc::new(o! {
                     "signature" => event_handler.event.to_string(),
                     "address" => format!("{}", &log.address),
@@ -748,6 +790,7 @@ impl DataSource {
                         log,
                         params,
                         receipt: receipt.map(|r| r.cheap_clone()),
+                        calls,
                     },
                     handler,
                     block.block_ptr(),
@@ -1166,6 +1209,8 @@ pub struct MappingEventHandler {
     pub handler: String,
     #[serde(default)]
     pub receipt: bool,
+    #[serde(default)]
+    pub calls: CallDecls,
 }
 
 impl MappingEventHandler {
@@ -1175,6 +1220,218 @@ impl MappingEventHandler {
     }
 }
 
+/// The calls that the manifest declares for an event handler. The calls
+/// of all handlers of a block are made with one request before any of them
+/// runs, and the handlers' own calls use their results instead of going to
+/// the Ethereum node. In the manifest, they are a map from a label to an
+/// expression like `ERC20[event.address].balanceOf(event.params.to)`
+#[derive(Clone, Debug, Default, Hash, Eq, PartialEq)]
+pub struct CallDecls {
+    pub decls: Arc<Vec<CallDecl>>,
+}
+
+impl<'de> de::Deserialize<'de> for CallDecls {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        let decls: BTreeMap<String, String> = de::Deserialize::deserialize(deserializer)?;
+        let decls = decls
+            .into_iter()
+            .map(|(label, expr)| {
+                CallExpr::from_str(&expr)
+                    .map(|expr| CallDecl { label, expr })
+                    .map_err(de::Error::custom)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(CallDecls {
+            decls: Arc::new(decls),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct CallDecl {
+    /// A name for the call that is only used in logs
+    pub label: String,
+    pub expr: CallExpr,
+}
+
+impl CallDecl {
+    /// Check that the call can be made for events with the signature
+    /// `event` from a data source with the ABIs in `mapping`
+    fn validate(&self, mapping: &Mapping, event: &Event) -> Result<(), Error> {
+        let abi = mapping.find_abi(&self.expr.abi)?;
+        self.expr.function(&abi)?;
+        let args = std::iter::once(&self.expr.address).chain(self.expr.args.iter());
+        for arg in args {
+            if let CallArg::Param(name) = arg {
+                if !event.inputs.iter().any(|input| &input.name == name) {
+                    return Err(anyhow!(
+                        "event `{}` has no parameter `{}`",
+                        event.name,
+                        name
+                    ));
+                }
+            }
+        }
+        if let CallArg::Param(name) = &self.expr.address {
+            let input = event.inputs.iter().find(|input| &input.name == name);
+            if !matches!(input, Some(input) if input.kind == ParamType::Address) {
+                return Err(anyhow!("event parameter `{}` is not an address", name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Turn the declaration into a call for the event `log` with the
+    /// decoded `params`
+    fn resolve(
+        &self,
+        mapping: &Mapping,
+        log: &Log,
+        params: &[LogParam],
+    ) -> Result<DeclaredCall, Error> {
+        let arg = |arg: &CallArg| match arg {
+            CallArg::Address => Ok(Token::Address(log.address)),
+            CallArg::Param(name) => params
+                .iter()
+                .find(|param| &param.name == name)
+                .map(|param| param.value.clone())
+                .ok_or_else(|| anyhow!("event has no parameter `{}`", name)),
+        };
+
+        let abi = mapping.find_abi(&self.expr.abi)?;
+        let function = self.expr.function(&abi)?.clone();
+        let address = match arg(&self.expr.address)? {
+            Token::Address(address) => address,
+            token => return Err(anyhow!("`{}` is not an address", token)),
+        };
+        let args = self
+            .expr
+            .args
+            .iter()
+            .map(arg)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(DeclaredCall {
+            label: self.label.clone(),
+            contract_name: abi.name.clone(),
+            address,
+            function,
+            args,
+        })
+    }
+}
+
+/// A call expression `Contract[address].function(arg, ..)`, where
+/// `Contract` is the name of an ABI of the data source
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct CallExpr {
+    pub abi: String,
+    pub address: CallArg,
+    pub func: String,
+    pub args: Vec<CallArg>,
+}
+
+impl CallExpr {
+    /// The function in `abi` that this expression calls. Overloaded
+    /// functions are told apart by their number of arguments
+    fn function<'a>(&self, abi: &'a MappingABI) -> Result<&'a Function, Error> {
+        abi.contract
+            .functions_by_name(&self.func)
+            .map_err(|_| anyhow!("ABI `{}` has no function `{}`", abi.name, self.func))?
+            .iter()
+            .find(|function| function.inputs.len() == self.args.len())
+            .ok_or_else(|| {
+                anyhow!(
+                    "function `{}` of ABI `{}` does not take {} arguments",
+                    self.func,
+                    abi.name,
+                    self.args.len()
+                )
+            })
+    }
+}
+
+impl FromStr for CallExpr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            anyhow!(
+                "invalid call `{}`, expected `Contract[address].function(arg, ..)`",
+                s
+            )
+        };
+
+        let (abi, rest) = s.trim().split_once('[').ok_or_else(invalid)?;
+        let (address, rest) = rest.split_once(']').ok_or_else(invalid)?;
+        let rest = rest.strip_prefix('.').ok_or_else(invalid)?;
+        let (func, rest) = rest.split_once('(').ok_or_else(invalid)?;
+        let args = rest.strip_suffix(')').ok_or_else(invalid)?;
+
+        if !is_ident(abi) || !is_ident(func) {
+            return Err(invalid());
+        }
+        let args = match args.trim() {
+            "" => vec![],
+            args => args
+                .split(',')
+                .map(CallArg::from_str)
+                .collect::<Result<Vec<_>, _>>()?,
+        };
+
+        Ok(CallExpr {
+            abi: abi.to_owned(),
+            address: address.parse()?,
+            func: func.to_owned(),
+            args,
+        })
+    }
+}
+
+/// An argument of a declared call
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub enum CallArg {
+    /// `event.address`, the address of the contract that emitted the event
+    Address,
+    /// `event.params.<name>`, a parameter of the event
+    Param(String),
+}
+
+impl FromStr for CallArg {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s == "event.address" {
+            return Ok(CallArg::Address);
+        }
+        match s.strip_prefix("event.params.") {
+            Some(name) if is_ident(name) => Ok(CallArg::Param(name.to_owned())),
+            _ => Err(anyhow!(
+                "invalid call argument `{}`, expected `event.address` or `event.params.<name>`",
+                s
+            )),
+        }
+    }
+}
+
+fn is_ident(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// A declared call for a specific event
+#[derive(Clone, Debug)]
+pub struct DeclaredCall {
+    pub label: String,
+    pub contract_name: String,
+    pub address: Address,
+    pub function: Function,
+    pub args: Vec<Token>,
+}
+
 /// Hashes a string to a H256 hash.
 fn string_to_h256(s: &str) -> H256 {
     let mut result = [0u8; 32];
@@ -7,6 +7,7 @@ use graph::blockchain::ChainIdentifier;
 use graph::components::transaction_receipt::LightTransactionReceipt;
 use graph::data::subgraph::UnifiedMappingApiVersion;
 use graph::data::subgraph::API_VERSION_0_0_7;
+use graph::prelude::ethabi::Function;
 use graph::prelude::ethabi::ParamType;
 use graph::prelude::ethabi::Token;
 use graph::prelude::tokio::try_join;
@@ -464,13 +465,7 @@ impl EthereumAdapter {
         let web3 = self.web3.clone();
         let logger = Logger::new(&logger, o!("provider" => self.provider.clone()));
 
-        // Ganache does not support calls by block hash.
-        // See https://github.com/trufflesuite/ganache-cli/issues/973
-        let block_id = if !self.supports_eip_1898 {
-            BlockId::Number(block_ptr.number.into())
-        } else {
-            BlockId::Hash(block_ptr.hash_as_h256())
-        };
+        let block_id = self.call_block_id(&block_ptr);
         let retry_log_message = format!("eth_call RPC call for block {}", block_ptr);
         retry(retry_log_message, &logger)
             .when(|result| match result {
@@ -483,133 +478,84 @@ impl EthereumAdapter {
                 let call_data = call_data.clone();
                 let web3 = web3.cheap_clone();
                 async move {
-                    let req = CallRequest {
-                        to: Some(contract_address),
-                        gas: gas.map(|val| web3::types::U256::from(val)),
-                        data: Some(call_data.clone()),
-                        from: None,
-                        gas_price: None,
-                        value: None,
-                        access_list: None,
-                        max_fee_per_gas: None,
-                        max_priority_fee_per_gas: None,
-                        transaction_type: None,
-                    };
+                    let req = call_request(contract_address, call_data, gas);
                     let result = web3.eth().call(req, Some(block_id)).boxed().await;
 
-                    // Try to check if the call was reverted. The JSON-RPC response for reverts is
-                    // not standardized, so we have ad-hoc checks for each Ethereum client.
-
-                    // 0xfe is the "designated bad instruction" of the EVM, and Solidity uses it for
-                    // asserts.
-                    const PARITY_BAD_INSTRUCTION_FE: &str = "Bad instruction fe";
-
-                    // 0xfd is REVERT, but on some contracts, and only on older blocks,
-                    // this happens. Makes sense to consider it a revert as well.
-                    const PARITY_BAD_INSTRUCTION_FD: &str = "Bad instruction fd";
-
-                    const PARITY_BAD_JUMP_PREFIX: &str = "Bad jump";
-                    const PARITY_STACK_LIMIT_PREFIX: &str = "Out of stack";
-
-                    // See f0af4ab0-6b7c-4b68-9141-5b79346a5f61.
-                    const PARITY_OUT_OF_GAS: &str = "Out of gas";
-
-                    const PARITY_VM_EXECUTION_ERROR: i64 = -32015;
-                    const PARITY_REVERT_PREFIX: &str = "Reverted 0x";
-                    const XDAI_REVERT: &str = "revert";
-
-                    // Deterministic Geth execution errors. We might need to expand this as
-                    // subgraphs come across other errors. See
-                    // https://github.com/ethereum/go-ethereum/blob/cd57d5cd38ef692de8fbedaa56598b4e9fbfbabc/core/vm/errors.go
-                    const GETH_EXECUTION_ERRORS: &[&str] = &[
-                        // The "revert" substring covers a few known error messages, including:
-                        // Hardhat: "error: transaction reverted",
-                        // Ganache and Moonbeam: "vm exception while processing transaction: revert",
-                        // Geth: "execution reverted"
-                        // And others.
-                        "revert",
-                        "invalid jump destination",
-                        "invalid opcode",
-                        // Ethereum says 1024 is the stack sizes limit, so this is deterministic.
-                        "stack limit reached 1024",
-                        // See f0af4ab0-6b7c-4b68-9141-5b79346a5f61 for why the gas limit is considered deterministic.
-                        "out of gas",
-                        "stack underflow",
-                    ];
-
-                    let env_geth_call_errors = ENV_VARS.geth_eth_call_errors.iter();
-                    let mut geth_execution_errors = GETH_EXECUTION_ERRORS
-                        .iter()
-                        .copied()
-                        .chain(env_geth_call_errors.map(|s| s.as_str()));
-
-                    let as_solidity_revert_with_reason = |bytes: &[u8]| {
-                        let solidity_revert_function_selector =
-                            &tiny_keccak::keccak256(b"Error(string)")[..4];
-
-                        match bytes.len() >= 4 && &bytes[..4] == solidity_revert_function_selector {
-                            false => None,
-                            true => ethabi::decode(&[ParamType::String], &bytes[4..])
-                                .ok()
-                                .and_then(|tokens| tokens[0].clone().into_string()),
-                        }
-                    };
+                    call_result(result)
+                }
+            })
+            .map_err(|e| e.into_inner().unwrap_or(EthereumContractCallError::Timeout))
+            .boxed()
+            .compat()
+    }
 
-                    match result {
-                        // A successful response.
-                        Ok(bytes) => Ok(bytes),
+    /// Make the `calls` with one JSON-RPC batch request. Each call consists
+    /// of the address of the contract, the call data, the block at which
+    /// to make the call, and the gas limit. The results are in the same
+    /// order as `calls`. The whole batch is retried if the Ethereum node
+    /// fails to answer any call for a reason other than a revert
+    async fn call_batch(
+        &self,
+        logger: Logger,
+        calls: Vec<(Address, Bytes, BlockPtr, Option<u32>)>,
+    ) -> Result<Vec<Result<Bytes, EthereumContractCallError>>, EthereumContractCallError> {
+        let web3 = self.web3.clone();
+        let logger = Logger::new(&logger, o!("provider" => self.provider.clone()));
 
-                        // Check for Geth revert.
-                        Err(web3::Error::Rpc(rpc_error))
-                            if geth_execution_errors
-                                .any(|e| rpc_error.message.to_lowercase().contains(e)) =>
-                        {
-                            Err(EthereumContractCallError::Revert(rpc_error.message))
-                        }
+        let reqs: Vec<_> = calls
+            .into_iter()
+            .map(|(address, call_data, block_ptr, gas)| {
+                (
+                    call_request(address, call_data, gas),
+                    self.call_block_id(&block_ptr),
+                )
+            })
+            .collect();
+        let retry_log_message = format!("batch eth_call RPC call with {} calls", reqs.len());
+        retry(retry_log_message, &logger)
+            .limit(ENV_VARS.request_retries)
+            .timeout_secs(ENV_VARS.json_rpc_timeout.as_secs())
+            .run(move || {
+                let reqs = reqs.clone();
+                let web3 = web3.cheap_clone();
+                async move {
+                    let batching_web3 = Web3::new(Batch::new(web3.transport().clone()));
+                    let eth = batching_web3.eth();
+                    let calls: Vec<_> = reqs
+                        .into_iter()
+                        .map(|(req, block_id)| eth.call(req, Some(block_id)))
+                        .collect();
 
-                        // Check for Parity revert.
-                        Err(web3::Error::Rpc(ref rpc_error))
-                            if rpc_error.code.code() == PARITY_VM_EXECUTION_ERROR =>
-                        {
-                            match rpc_error.data.as_ref().and_then(|d| d.as_str()) {
-                                Some(data)
-                                    if data.starts_with(PARITY_REVERT_PREFIX)
-                                        || data.starts_with(PARITY_BAD_JUMP_PREFIX)
-                                        || data.starts_with(PARITY_STACK_LIMIT_PREFIX)
-                                        || data == PARITY_BAD_INSTRUCTION_FE
-                                        || data == PARITY_BAD_INSTRUCTION_FD
-                                        || data == PARITY_OUT_OF_GAS
-                                        || data == XDAI_REVERT =>
-                                {
-                                    let reason = if data == PARITY_BAD_INSTRUCTION_FE {
-                                        PARITY_BAD_INSTRUCTION_FE.to_owned()
-                                    } else {
-                                        let payload = data.trim_start_matches(PARITY_REVERT_PREFIX);
-                                        hex::decode(payload)
-                                            .ok()
-                                            .and_then(|payload| {
-                                                as_solidity_revert_with_reason(&payload)
-                                            })
-                                            .unwrap_or("no reason".to_owned())
-                                    };
-                                    Err(EthereumContractCallError::Revert(reason))
-                                }
+                    batching_web3
+                        .transport()
+                        .submit_batch()
+                        .await
+                        .map_err(EthereumContractCallError::Web3Error)?;
 
-                                // The VM execution error was not identified as a revert.
-                                _ => Err(EthereumContractCallError::Web3Error(web3::Error::Rpc(
-                                    rpc_error.clone(),
-                                ))),
+                    let mut results = Vec::with_capacity(calls.len());
+                    for call in calls {
+                        match call_result(call.await) {
+                            Err(EthereumContractCallError::Web3Error(e)) => {
+                                return Err(EthereumContractCallError::Web3Error(e))
                             }
+                            result => results.push(result),
                         }
-
-                        // The error was not identified as a revert.
-                        Err(err) => Err(EthereumContractCallError::Web3Error(err)),
                     }
+                    Ok(results)
                 }
             })
+            .await
             .map_err(|e| e.into_inner().unwrap_or(EthereumContractCallError::Timeout))
-            .boxed()
-            .compat()
+    }
+
+    fn call_block_id(&self, block_ptr: &BlockPtr) -> BlockId {
+        // Ganache does not support calls by block hash.
+        // See https://github.com/trufflesuite/ganache-cli/issues/973
+        if !self.supports_eip_1898 {
+            BlockId::Number(block_ptr.number.into())
+        } else {
+            BlockId::Hash(block_ptr.hash_as_h256())
+        }
     }
 
     /// Request blocks by hash through JSON-RPC.
@@ -1310,24 +1256,9 @@ impl EthereumAdapterTrait for EthereumAdapter {
         call: EthereumContractCall,
         cache: Arc<dyn EthereumCallCache>,
     ) -> Box<dyn Future<Item = Vec<Token>, Error = EthereumContractCallError> + Send> {
-        // Emit custom error for type mismatches.
-        for (token, kind) in call
-            .args
-            .iter()
-            .zip(call.function.inputs.iter().map(|p| &p.kind))
-        {
-            if !token.type_check(kind) {
-                return Box::new(future::err(EthereumContractCallError::TypeError(
-                    token.clone(),
-                    kind.clone(),
-                )));
-            }
-        }
-
-        // Encode the call parameters according to the ABI
-        let call_data = match call.function.encode_input(&call.args) {
+        let call_data = match encode_call(&call) {
             Ok(data) => data,
-            Err(e) => return Box::new(future::err(EthereumContractCallError::EncodingError(e))),
+            Err(e) => return Box::new(future::err(e)),
         };
 
         debug!(logger, "eth_call";
@@ -1385,23 +1316,105 @@ impl EthereumAdapterTrait for EthereumAdapter {
                 }
             }
             // Decode the return values according to the ABI
-            .and_then(move |output| {
-                if output.is_empty() {
-                    // We got a `0x` response. For old Geth, this can mean a revert. It can also be
-                    // that the contract actually returned an empty response. A view call is meant
-                    // to return something, so we treat empty responses the same as reverts.
-                    Err(EthereumContractCallError::Revert("empty response".into()))
-                } else {
-                    // Decode failures are reverts. The reasoning is that if Solidity fails to
-                    // decode an argument, that's a revert, so the same goes for the output.
-                    call.function.decode_output(&output).map_err(|e| {
-                        EthereumContractCallError::Revert(format!("failed to decode output: {}", e))
-                    })
-                }
-            }),
+            .and_then(move |output| decode_output(&call.function, &output)),
         )
     }
 
+    async fn contract_calls(
+        &self,
+        logger: &Logger,
+        calls: Vec<EthereumContractCall>,
+        cache: Arc<dyn EthereumCallCache>,
+    ) -> Result<Vec<Result<Vec<Token>, EthereumContractCallError>>, EthereumContractCallError> {
+        // The output of each call, or `None` if we need to ask the
+        // Ethereum node for it
+        let mut outputs = Vec::with_capacity(calls.len());
+        let mut missing = Vec::new();
+        for (idx, call) in calls.iter().enumerate() {
+            let call_data = match encode_call(call) {
+                Ok(call_data) => call_data,
+                Err(e) => {
+                    outputs.push(Some(Err(e)));
+                    continue;
+                }
+            };
+            let cached = cache
+                .get_call(call.address, &call_data, call.block_ptr.clone())
+                .map_err(|e| error!(logger, "call cache get error"; "error" => e.to_string()))
+                .ok()
+                .flatten();
+            match cached {
+                Some(output) => outputs.push(Some(Ok(output))),
+                None => {
+                    outputs.push(None);
+                    missing.push((idx, call_data));
+                }
+            }
+        }
+
+        if !missing.is_empty() {
+            debug!(logger, "eth_call batch";
+                "calls" => calls.len(),
+                "cached" => calls.len() - missing.len());
+
+            let batch = missing
+                .iter()
+                .map(|(idx, call_data)| {
+                    let call = &calls[*idx];
+                    (
+                        call.address,
+                        Bytes(call_data.clone()),
+                        call.block_ptr.clone(),
+                        call.gas,
+                    )
+                })
+                .collect();
+            let results = self.call_batch(logger.clone(), batch).await?;
+
+            let mut for_cache = Vec::new();
+            for ((idx, call_data), result) in missing.into_iter().zip(results) {
+                let result = result.map(|output| output.0);
+                if let Ok(output) = &result {
+                    if !output.is_empty() {
+                        let call = &calls[idx];
+                        for_cache.push((
+                            call.address,
+                            call_data,
+                            call.block_ptr.clone(),
+                            output.clone(),
+                        ));
+                    }
+                }
+                outputs[idx] = Some(result);
+            }
+
+            // Unlike for `contract_call`, we wait for the cache to be
+            // written since the calls are made so that they are in the
+            // cache when the handler makes them
+            let cache = cache.cheap_clone();
+            let logger = logger.cheap_clone();
+            let _ = graph::spawn_blocking_allow_panic(move || {
+                for (address, call_data, block_ptr, output) in for_cache {
+                    if let Err(e) = cache.set_call(address, &call_data, block_ptr, &output) {
+                        error!(logger, "call cache set error"; "error" => e.to_string());
+                    }
+                }
+            })
+            .await;
+        }
+
+        Ok(calls
+            .iter()
+            .zip(outputs)
+            .map(|(call, output)| {
+                // Every output was filled in above
+                output
+                    .unwrap()
+                    .and_then(|output| decode_output(&call.function, &output))
+            })
+            .collect())
+    }
+
     /// Load Ethereum blocks in bulk, returning results as they come back as a Stream.
     async fn load_blocks(
         &self,
@@ -1958,6 +1971,173 @@ async fn filter_call_triggers_from_unsuccessful_transactions(
     Ok(block)
 }
 
+/// Check the arguments of `call` against the ABI and encode them
+pub(crate) fn encode_call(
+    call: &EthereumContractCall,
+) -> Result<Vec<u8>, EthereumContractCallError> {
+    // Emit custom error for type mismatches.
+    for (token, kind) in call
+        .args
+        .iter()
+        .zip(call.function.inputs.iter().map(|p| &p.kind))
+    {
+        if !token.type_check(kind) {
+            return Err(EthereumContractCallError::TypeError(
+                token.clone(),
+                kind.clone(),
+            ));
+        }
+    }
+
+    // Encode the call parameters according to the ABI
+    call.function
+        .encode_input(&call.args)
+        .map_err(EthereumContractCallError::EncodingError)
+}
+
+/// Decode the return values of a call to `function` according to the ABI
+fn decode_output(
+    function: &Function,
+    output: &[u8],
+) -> Result<Vec<Token>, EthereumContractCallError> {
+    if output.is_empty() {
+        // We got a `0x` response. For old Geth, this can mean a revert. It can also be
+        // that the contract actually returned an empty response. A view call is meant
+        // to return something, so we treat empty responses the same as reverts.
+        Err(EthereumContractCallError::Revert("empty response".into()))
+    } else {
+        // Decode failures are reverts. The reasoning is that if Solidity fails to
+        // decode an argument, that's a revert, so the same goes for the output.
+        function.decode_output(output).map_err(|e| {
+            EthereumContractCallError::Revert(format!("failed to decode output: {}", e))
+        })
+    }
+}
+
+fn call_request(contract_address: Address, call_data: Bytes, gas: Option<u32>) -> CallRequest {
+    CallRequest {
+        to: Some(contract_address),
+        gas: gas.map(|val| web3::types::U256::from(val)),
+        data: Some(call_data),
+        from: None,
+        gas_price: None,
+        value: None,
+        access_list: None,
+        max_fee_per_gas: None,
+        max_priority_fee_per_gas: None,
+        transaction_type: None,
+    }
+}
+
+/// Turn the response to an `eth_call` into the result of the call,
+/// identifying reverts
+fn call_result(result: Result<Bytes, web3::Error>) -> Result<Bytes, EthereumContractCallError> {
+    // Try to check if the call was reverted. The JSON-RPC response for reverts is
+    // not standardized, so we have ad-hoc checks for each Ethereum client.
+
+    // 0xfe is the "designated bad instruction" of the EVM, and Solidity uses it for
+    // asserts.
+    const PARITY_BAD_INSTRUCTION_FE: &str = "Bad instruction fe";
+
+    // 0xfd is REVERT, but on some contracts, and only on older blocks,
+    // this happens. Makes sense to consider it a revert as well.
+    const PARITY_BAD_INSTRUCTION_FD: &str = "Bad instruction fd";
+
+    const PARITY_BAD_JUMP_PREFIX: &str = "Bad jump";
+    const PARITY_STACK_LIMIT_PREFIX: &str = "Out of stack";
+
+    // See f0af4ab0-6b7c-4b68-9141-5b79346a5f61.
+    const PARITY_OUT_OF_GAS: &str = "Out of gas";
+
+    const PARITY_VM_EXECUTION_ERROR: i64 = -32015;
+    const PARITY_REVERT_PREFIX: &str = "Reverted 0x";
+    const XDAI_REVERT: &str = "revert";
+
+    // Deterministic Geth execution errors. We might need to expand this as
+    // subgraphs come across other errors. See
+    // https://github.com/ethereum/go-ethereum/blob/cd57d5cd38ef692de8fbedaa56598b4e9fbfbabc/core/vm/errors.go
+    const GETH_EXECUTION_ERRORS: &[&str] = &[
+        // The "revert" substring covers a few known error messages, including:
+        // Hardhat: "error: transaction reverted",
+        // Ganache and Moonbeam: "vm exception while processing transaction: revert",
+        // Geth: "execution reverted"
+        // And others.
+        "revert",
+        "invalid jump destination",
+        "invalid opcode",
+        // Ethereum says 1024 is the stack sizes limit, so this is deterministic.
+        "stack limit reached 1024",
+        // See f0af4ab0-6b7c-4b68-9141-5b79346a5f61 for why the gas limit is considered deterministic.
+        "out of gas",
+        "stack underflow",
+    ];
+
+    let env_geth_call_errors = ENV_VARS.geth_eth_call_errors.iter();
+    let mut geth_execution_errors = GETH_EXECUTION_ERRORS
+        .iter()
+        .copied()
+        .chain(env_geth_call_errors.map(|s| s.as_str()));
+
+    let as_solidity_revert_with_reason = |bytes: &[u8]| {
+        let solidity_revert_function_selector = &tiny_keccak::keccak256(b"Error(string)")[..4];
+
+        match bytes.len() >= 4 && &bytes[..4] == solidity_revert_function_selector {
+            false => None,
+            true => ethabi::decode(&[ParamType::String], &bytes[4..])
+                .ok()
+                .and_then(|tokens| tokens[0].clone().into_string()),
+        }
+    };
+
+    match result {
+        // A successful response.
+        Ok(bytes) => Ok(bytes),
+
+        // Check for Geth revert.
+        Err(web3::Error::Rpc(rpc_error))
+            if geth_execution_errors.any(|e| rpc_error.message.to_lowercase().contains(e)) =>
+        {
+            Err(EthereumContractCallError::Revert(rpc_error.message))
+        }
+
+        // Check for Parity revert.
+        Err(web3::Error::Rpc(ref rpc_error))
+            if rpc_error.code.code() == PARITY_VM_EXECUTION_ERROR =>
+        {
+            match rpc_error.data.as_ref().and_then(|d| d.as_str()) {
+                Some(data)
+                    if data.starts_with(PARITY_REVERT_PREFIX)
+                        || data.starts_with(PARITY_BAD_JUMP_PREFIX)
+                        || data.starts_with(PARITY_STACK_LIMIT_PREFIX)
+                        || data == PARITY_BAD_INSTRUCTION_FE
+                        || data == PARITY_BAD_INSTRUCTION_FD
+                        || data == PARITY_OUT_OF_GAS
+                        || data == XDAI_REVERT =>
+                {
+                    let reason = if data == PARITY_BAD_INSTRUCTION_FE {
+                        PARITY_BAD_INSTRUCTION_FE.to_owned()
+                    } else {
+                        let payload = data.trim_start_matches(PARITY_REVERT_PREFIX);
+                        hex::decode(payload)
+                            .ok()
+                            .and_then(|payload| as_solidity_revert_with_reason(&payload))
+                            .unwrap_or("no reason".to_owned())
+                    };
+                    Err(EthereumContractCallError::Revert(reason))
+                }
+
+                // The VM execution error was not identified as a revert.
+                _ => Err(EthereumContractCallError::Web3Error(web3::Error::Rpc(
+                    rpc_error.clone(),
+                ))),
+            }
+        }
+
+        // The error was not identified as a revert.
+        Err(err) => Err(EthereumContractCallError::Web3Error(err)),
+    }
+}
+
 /// Deprecated. Wraps the [`fetch_transaction_receipts_in_batch`] in a retry loop.
 async fn fetch_transaction_receipts_in_batch_with_retry(
     web3: Arc<Web3<Transport>>,
@@ -2248,15 +2428,29 @@ async fn get_transaction_receipts_for_transaction_hashes(
 mod tests {
 
     use crate::trigger::{EthereumBlockTriggerType, EthereumTrigger};
+    use crate::{
+        EthereumAdapter, EthereumAdapterTrait, EthereumContractCall, EthereumContractCallError,
+        ProviderEthRpcMetrics, Transport,
+    };
 
     use super::{parse_block_triggers, EthereumBlock, EthereumBlockFilter, EthereumBlockWithCalls};
     use graph::blockchain::BlockPtr;
+    use graph::components::store::CachedEthereumCall;
+    use graph::endpoint::EndpointMetrics;
     use graph::prelude::ethabi::ethereum_types::U64;
-    use graph::prelude::web3::types::{Address, Block, Bytes, H256};
-    use graph::prelude::EthereumCall;
-    use std::collections::HashSet;
+    use graph::prelude::ethabi::{self, Function, Token};
+    use graph::prelude::serde_json::{self, json, Value};
+    use graph::prelude::web3::types::{Address, Block, Bytes, H256, U256};
+    use graph::prelude::{tokio, EthereumCall, EthereumCallCache, MetricsRegistry};
+    use graph::slog::{o, Discard, Logger};
+    use graph::url::Url;
+    use http::HeaderMap;
+    use std::collections::{HashMap, HashSet};
+    use std::io::{BufRead, BufReader, Read, Write};
     use std::iter::FromIterator;
-    use std::sync::Arc;
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
 
     #[test]
     fn parse_block_triggers_every_block() {
@@ -2374,4 +2568,307 @@ mod tests {
     fn bytes(value: Vec<u8>) -> Bytes {
         Bytes::from(value)
     }
+
+    /// An Ethereum node that answers calls of `double(uint256)` with twice
+    /// the argument, except that calls with 13 revert. It counts the HTTP
+    /// requests and the `eth_call`s that it answers
+    struct MockNode {
+        url: String,
+        requests: Arc<AtomicUsize>,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl MockNode {
+        fn start() -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let requests = Arc::new(AtomicUsize::new(0));
+            let calls = Arc::new(AtomicUsize::new(0));
+
+            let (requests2, calls2) = (requests.clone(), calls.clone());
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let (requests, calls) = (requests2.clone(), calls2.clone());
+                    std::thread::spawn(move || Self::serve(stream.unwrap(), &requests, &calls));
+                }
+            });
+
+            MockNode {
+                url,
+                requests,
+                calls,
+            }
+        }
+
+        /// Answer the HTTP requests on `stream` until the client closes it
+        fn serve(mut stream: TcpStream, requests: &AtomicUsize, calls: &AtomicUsize) {
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                    return;
+                }
+                let mut length = 0;
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    let header = line.trim_end();
+                    if header.is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+
+                requests.fetch_add(1, Ordering::SeqCst);
+                let response = match serde_json::from_slice(&body).unwrap() {
+                    Value::Array(batch) => Value::Array(
+                        batch
+                            .iter()
+                            .map(|request| Self::answer(request, calls))
+                            .collect(),
+                    ),
+                    request => Self::answer(&request, calls),
+                }
+                .to_string();
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                    response.len(),
+                    response
+                )
+                .unwrap();
+            }
+        }
+
+        fn answer(request: &Value, calls: &AtomicUsize) -> Value {
+            let id = request["id"].clone();
+            if request["method"] != "eth_call" {
+                return json!({ "jsonrpc": "2.0", "id": id, "result": "mock" });
+            }
+
+            calls.fetch_add(1, Ordering::SeqCst);
+            let data = request["params"][0]["data"].as_str().unwrap();
+            let data = hex::decode(data.trim_start_matches("0x")).unwrap();
+            let arg = U256::from_big_endian(&data[4..36]);
+            if arg == U256::from(13) {
+                let error = json!({ "code": 3, "message": "execution reverted" });
+                return json!({ "jsonrpc": "2.0", "id": id, "error": error });
+            }
+            let output = ethabi::encode(&[Token::Uint(arg * 2)]);
+            json!({ "jsonrpc": "2.0", "id": id, "result": format!("0x{}", hex::encode(output)) })
+        }
+
+        fn requests(&self) -> usize {
+            self.requests.load(Ordering::SeqCst)
+        }
+
+        fn calls(&self) -> usize {
+            self.calls.load(Ordering::SeqCst)
+        }
+
+        async fn adapter(&self) -> EthereumAdapter {
+            let transport = Transport::new_rpc(
+                Url::parse(&self.url).unwrap(),
+                HeaderMap::new(),
+                Arc::new(EndpointMetrics::mock()),
+                "mock",
+            );
+            let metrics = Arc::new(ProviderEthRpcMetrics::new(
+                Arc::new(MetricsRegistry::mock()),
+            ));
+            EthereumAdapter::new(
+                Logger::root(Discard, o!()),
+                "mock".to_string(),
+                transport,
+                metrics,
+                true,
+                false,
+            )
+            .await
+        }
+    }
+
+    #[derive(Default)]
+    struct TestCallCache {
+        calls: Mutex<HashMap<(Address, Vec<u8>), Vec<u8>>>,
+    }
+
+    impl EthereumCallCache for TestCallCache {
+        fn get_call(
+            &self,
+            contract_address: Address,
+            encoded_call: &[u8],
+            _block: BlockPtr,
+        ) -> Result<Option<Vec<u8>>, anyhow::Error> {
+            let calls = self.calls.lock().unwrap();
+            Ok(calls
+                .get(&(contract_address, encoded_call.to_vec()))
+                .cloned())
+        }
+
+        fn get_calls_in_block(
+            &self,
+            _block: BlockPtr,
+        ) -> Result<Vec<CachedEthereumCall>, anyhow::Error> {
+            Ok(vec![])
+        }
+
+        fn set_call(
+            &self,
+            contract_address: Address,
+            encoded_call: &[u8],
+            _block: BlockPtr,
+            return_value: &[u8],
+        ) -> Result<(), anyhow::Error> {
+            let mut calls = self.calls.lock().unwrap();
+            calls.insert(
+                (contract_address, encoded_call.to_vec()),
+                return_value.to_vec(),
+            );
+            Ok(())
+        }
+    }
+
+    fn double() -> Function {
+        const ABI: &str = r#"[{
+            "type": "function",
+            "name": "double",
+            "stateMutability": "view",
+            "inputs": [{ "name": "x", "type": "uint256" }],
+            "outputs": [{ "name": "", "type": "uint256" }]
+        }]"#;
+        let contract = ethabi::Contract::load(ABI.as_bytes()).unwrap();
+        contract.function("double").unwrap().clone()
+    }
+
+    fn double_call(arg: Token) -> EthereumContractCall {
+        EthereumContractCall {
+            address: address(7),
+            block_ptr: BlockPtr::from((hash(1), 1)),
+            function: double(),
+            args: vec![arg],
+            gas: None,
+        }
+    }
+
+    fn uint(value: u64) -> Token {
+        Token::Uint(U256::from(value))
+    }
+
+    #[tokio::test]
+    async fn contract_calls_are_batched() {
+        let node = MockNode::start();
+        let adapter = node.adapter().await;
+        let cache = Arc::new(TestCallCache::default());
+        let logger = Logger::root(Discard, o!());
+
+        let requests = node.requests();
+        let calls = vec![
+            double_call(uint(1)),
+            double_call(uint(13)),
+            double_call(Token::String("not a number".to_string())),
+            double_call(uint(2)),
+        ];
+        let results = adapter
+            .contract_calls(&logger, calls, cache.clone())
+            .await
+            .unwrap();
+
+        // All calls that can be encoded go to the node in one request, and
+        // calls that fail do not affect the others
+        assert_eq!(requests + 1, node.requests());
+        assert_eq!(3, node.calls());
+        assert_eq!(4, results.len());
+        assert_eq!(&vec![uint(2)], results[0].as_ref().unwrap());
+        assert!(matches!(
+            results[1],
+            Err(EthereumContractCallError::Revert(_))
+        ));
+        assert!(matches!(
+            results[2],
+            Err(EthereumContractCallError::TypeError(_, _))
+        ));
+        assert_eq!(&vec![uint(4)], results[3].as_ref().unwrap());
+
+        // Only successful calls are cached
+        assert_eq!(2, cache.calls.lock().unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn contract_calls_use_the_call_cache() {
+        let node = MockNode::start();
+        let adapter = node.adapter().await;
+        let cache = Arc::new(TestCallCache::default());
+        let logger = Logger::root(Discard, o!());
+
+        // A cached result is used instead of asking the node
+        let call_data = super::encode_call(&double_call(uint(1))).unwrap();
+        let cached = ethabi::encode(&[uint(100)]);
+        cache
+            .set_call(
+                address(7),
+                &call_data,
+                BlockPtr::from((hash(1), 1)),
+                &cached,
+            )
+            .unwrap();
+
+        let requests = node.requests();
+        let calls = vec![double_call(uint(1)), double_call(uint(2))];
+        let results = adapter
+            .contract_calls(&logger, calls.clone(), cache.clone())
+            .await
+            .unwrap();
+        assert_eq!(requests + 1, node.requests());
+        assert_eq!(1, node.calls());
+        assert_eq!(&vec![uint(100)], results[0].as_ref().unwrap());
+        assert_eq!(&vec![uint(4)], results[1].as_ref().unwrap());
+
+        // Now that both calls are cached, the node is not asked at all
+        let results = adapter
+            .contract_calls(&logger, calls, cache.clone())
+            .await
+            .unwrap();
+        assert_eq!(requests + 1, node.requests());
+        assert_eq!(&vec![uint(4)], results[1].as_ref().unwrap());
+    }
+
+    #[tokio::test]
+    async fn call_batch_answers_in_order() {
+        let node = MockNode::start();
+        let adapter = node.adapter().await;
+        let logger = Logger::root(Discard, o!());
+
+        let requests = node.requests();
+        let ptr = BlockPtr::from((hash(1), 1));
+        let batch = [3, 13, 5]
+            .into_iter()
+            .map(|arg| {
+                let call = double_call(uint(arg));
+                let call_data = super::encode_call(&call).unwrap();
+                (call.address, Bytes(call_data), ptr.clone(), None)
+            })
+            .collect();
+        let results = adapter.call_batch(logger, batch).await.unwrap();
+
+        assert_eq!(requests + 1, node.requests());
+        let outputs: Vec<_> = results
+            .into_iter()
+            .map(|result| result.ok().map(|output| output.0))
+            .collect();
+        assert_eq!(
+            vec![
+                Some(ethabi::encode(&[uint(6)])),
+                None,
+                Some(ethabi::encode(&[uint(10)]))
+            ],
+            outputs
+        );
+    }
 }
@@ -1,16 +1,17 @@
-use std::{sync::Arc, time::Instant};
+use std::{collections::HashSet, sync::Arc, time::Instant};
 
 use crate::adapter::EthereumGetBalanceError;
 use crate::data_source::MappingABI;
+use crate::ethereum_adapter::encode_call;
 use crate::{
-    capabilities::NodeCapabilities, network::EthereumNetworkAdapters, Chain, DataSource,
-    EthereumAdapter, EthereumAdapterTrait, EthereumContractCall, EthereumContractCallError,
-    ENV_VARS,
+    capabilities::NodeCapabilities, network::EthereumNetworkAdapters, trigger::MappingTrigger,
+    Chain, DataSource, EthereumAdapter, EthereumAdapterTrait, EthereumContractCall,
+    EthereumContractCallError, ENV_VARS,
 };
 use anyhow::{anyhow, Context, Error};
 use blockchain::HostFn;
-use graph::blockchain::ChainIdentifier;
-use graph::components::subgraph::HostMetrics;
+use graph::blockchain::{ChainIdentifier, DeclaredCalls};
+use graph::components::subgraph::{HostMetrics, MappingError};
 use graph::data::store::scalar::BigInt;
 use graph::data::subgraph::API_VERSION_0_0_9;
 use graph::prelude::web3::types::H160;
@@ -20,12 +21,13 @@ use graph::{
     blockchain::{self, BlockPtr, HostFnCtx},
     cheap_clone::CheapClone,
     prelude::{
+        async_trait,
         ethabi::{self, Address, Token},
-        EthereumCallCache, Future01CompatExt,
+        EthereumCallCache, Future01CompatExt, LightEthereumBlockExt,
     },
     runtime::{asc_get, asc_new, AscPtr, HostExportError},
     semver::Version,
-    slog::{info, trace, Logger},
+    slog::{debug, info, trace, warn, Logger},
 };
 use graph_runtime_wasm::asc_abi::class::{AscBigInt, AscEnumArray, EthereumValueKind};
 
@@ -60,23 +62,29 @@ pub struct RuntimeAdapter {
     pub chain_identifier: Arc<ChainIdentifier>,
 }
 
-impl blockchain::RuntimeAdapter<Chain> for RuntimeAdapter {
-    fn host_fns(&self, ds: &DataSource) -> Result<Vec<HostFn>, Error> {
-        let abis = ds.mapping.abis.clone();
-        let call_cache = self.call_cache.cheap_clone();
-        let eth_adapters = self.eth_adapters.cheap_clone();
-        let archive = ds.mapping.requires_archive()?;
-
+impl RuntimeAdapter {
+    fn eth_call_gas(&self) -> Option<u32> {
         // Check if the current network version is in the eth_call_no_gas list
         let should_skip_gas = ENV_VARS
             .eth_call_no_gas
             .contains(&self.chain_identifier.net_version);
 
-        let eth_call_gas = if should_skip_gas {
+        if should_skip_gas {
             None
         } else {
             Some(ETH_CALL_GAS)
-        };
+        }
+    }
+}
+
+#[async_trait]
+impl blockchain::RuntimeAdapter<Chain> for RuntimeAdapter {
+    fn host_fns(&self, ds: &DataSource) -> Result<Vec<HostFn>, Error> {
+        let abis = ds.mapping.abis.clone();
+        let call_cache = self.call_cache.cheap_clone();
+        let eth_adapters = self.eth_adapters.cheap_clone();
+        let archive = ds.mapping.requires_archive()?;
+        let eth_call_gas = self.eth_call_gas();
 
         let ethereum_call = HostFn {
             name: "ethereum.call",
@@ -112,6 +120,99 @@ impl blockchain::RuntimeAdapter<Chain> for RuntimeAdapter {
 
         Ok(vec![ethereum_call, ethereum_get_balance])
     }
+
+    fn prepares_triggers(&self, ds: &DataSource) -> bool {
+        ds.mapping
+            .event_handlers
+            .iter()
+            .any(|handler| !handler.calls.decls.is_empty())
+    }
+
+    /// Make the calls that the manifest declares for the handlers of all
+    /// `triggers` of a block with one request so that the handlers can use
+    /// their results. Failures are only logged since a handler whose
+    /// declared call has no result makes the call itself and deals with
+    /// any errors then
+    async fn prepare_triggers(
+        &self,
+        logger: &Logger,
+        block_ptr: &BlockPtr,
+        triggers: &[(&DataSource, &MappingTrigger)],
+    ) -> Result<DeclaredCalls, MappingError> {
+        let mut declared_calls = DeclaredCalls::default();
+        let gas = self.eth_call_gas();
+        let mut archive = false;
+        let mut batch = Vec::new();
+        let mut seen = HashSet::new();
+        for (ds, trigger) in triggers {
+            let declared = match trigger {
+                MappingTrigger::Log { calls, .. } if !calls.is_empty() => calls,
+                _ => continue,
+            };
+            archive |= ds.mapping.requires_archive()?;
+            for call in declared {
+                let call = EthereumContractCall {
+                    address: call.address,
+                    block_ptr: block_ptr.cheap_clone(),
+                    function: call.function.clone(),
+                    args: call.args.clone(),
+                    gas,
+                };
+                // Several handlers often declare the same call
+                if let Ok(call_data) = encode_call(&call) {
+                    let key = (call.address, call_data);
+                    if seen.insert(key.clone()) {
+                        batch.push((call, key));
+                    }
+                }
+            }
+        }
+        if batch.is_empty() {
+            return Ok(declared_calls);
+        }
+
+        let eth_adapter = match self.eth_adapters.call_or_cheapest(Some(&NodeCapabilities {
+            archive,
+            traces: false,
+        })) {
+            Ok(eth_adapter) => eth_adapter,
+            Err(e) => {
+                warn!(logger, "No adapter for declared calls"; "error" => e.to_string());
+                return Ok(declared_calls);
+            }
+        };
+
+        let start = Instant::now();
+        let (calls, keys): (Vec<_>, Vec<_>) = batch.into_iter().unzip();
+        let count = calls.len();
+        match eth_adapter
+            .contract_calls(logger, calls, self.call_cache.cheap_clone())
+            .await
+        {
+            Ok(results) => {
+                for ((address, call_data), result) in keys.into_iter().zip(results) {
+                    match result {
+                        Ok(tokens) => declared_calls.insert(address, call_data, Some(tokens)),
+                        Err(EthereumContractCallError::Revert(_)) => {
+                            declared_calls.insert(address, call_data, None)
+                        }
+                        Err(e) => {
+                            debug!(logger, "Declared call failed";
+                                "contract" => address.to_string(),
+                                "error" => e.to_string());
+                        }
+                    }
+                }
+                trace!(logger, "Made declared calls";
+                    "calls" => count,
+                    "time_ms" => start.elapsed().as_millis());
+            }
+            Err(e) => {
+                warn!(logger, "Declared calls failed"; "error" => e.to_string());
+            }
+        }
+        Ok(declared_calls)
+    }
 }
 
 /// function ethereum.call(call: SmartContractCall): Array<Token> | null
@@ -140,6 +241,7 @@ fn ethereum_call(
         call_cache,
         &ctx.logger,
         &ctx.block_ptr,
+        &ctx.declared_calls,
         call,
         abis,
         eth_call_gas,
@@ -195,6 +297,7 @@ fn eth_call(
     call_cache: Arc<dyn EthereumCallCache>,
     logger: &Logger,
     block_ptr: &BlockPtr,
+    declared_calls: &DeclaredCalls,
     unresolved_call: UnresolvedContractCall,
     abis: &[Arc<MappingABI>],
     eth_call_gas: Option<u32>,
@@ -265,12 +368,25 @@ fn eth_call(
         gas: eth_call_gas,
     };
 
-    // Run Ethereum call in tokio runtime
+    // Use the result of the call if the manifest declares it, and run
+    // the Ethereum call in tokio runtime otherwise
+    let declared = encode_call(&call)
+        .ok()
+        .and_then(|call_data| declared_calls.get(call.address, &call_data).cloned());
     let logger1 = logger.clone();
     let call_cache = call_cache.clone();
-    let result = match graph::block_on(
-            eth_adapter.contract_call(&logger1, call, call_cache).compat()
-        ) {
+    let call_result = match declared {
+        Some(Some(tokens)) => Ok(tokens),
+        Some(None) => Err(EthereumContractCallError::Revert(
+            "declared call reverted".into(),
+        )),
+        None => graph::block_on(
+            eth_adapter
+                .contract_call(&logger1, call, call_cache)
+                .compat(),
+        ),
+    };
+    let result = match call_result {
             Ok(tokens) => Ok(Some(tokens)),
             Err(EthereumContractCallError::Revert(reason)) => {
                 info!(logger, "Contract call reverted"; "reason" => reason);
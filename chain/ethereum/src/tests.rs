@@ -11,6 +11,7 @@ use graph::{
 
 use crate::{
     chain::BlockFinality,
+    data_source::{CallArg, CallExpr},
     trigger::{EthereumBlockTriggerType, EthereumTrigger, LogRef},
 };
 
@@ -208,3 +209,46 @@ fn test_trigger_dedup() {
         vec![log1, log2, call1, log3, call2, call3, block2, block1]
     );
 }
+
+#[test]
+fn test_call_expr_parsing() {
+    let expr: CallExpr = "ERC20[event.address].balanceOf(event.params.to)"
+        .parse()
+        .unwrap();
+    assert_eq!(
+        expr,
+        CallExpr {
+            abi: "ERC20".to_string(),
+            address: CallArg::Address,
+            func: "balanceOf".to_string(),
+            args: vec![CallArg::Param("to".to_string())],
+        }
+    );
+
+    let expr: CallExpr = " Pool[event.params.pool].slot0( ) ".parse().unwrap();
+    assert_eq!(expr.address, CallArg::Param("pool".to_string()));
+    assert!(expr.args.is_empty());
+
+    let expr: CallExpr = "Pair[event.address].quote(event.params.a, event.params.b)"
+        .parse()
+        .unwrap();
+    assert_eq!(
+        expr.args,
+        vec![
+            CallArg::Param("a".to_string()),
+            CallArg::Param("b".to_string())
+        ]
+    );
+
+    for invalid in [
+        "ERC20.balanceOf(event.params.to)",
+        "ERC20[event.address]balanceOf(event.params.to)",
+        "ERC20[event.address].balanceOf(event.params.to",
+        "ERC20[event.address].balanceOf(event.params)",
+        "ERC20[event.params.to.x].balanceOf()",
+        "ERC20[tx.from].balanceOf()",
+        "[event.address].balanceOf()",
+    ] {
+        assert!(invalid.parse::<CallExpr>().is_err(), "{}", invalid);
+    }
+}
@@ -1,4 +1,5 @@
 use graph::blockchain::MappingTriggerTrait;
+use graph::blockchain::PrefetchHints;
 use graph::blockchain::TriggerData;
 use graph::data::subgraph::API_VERSION_0_0_2;
 use graph::data::subgraph::API_VERSION_0_0_6;
@@ -28,6 +29,7 @@ use graph_runtime_wasm::module::ToAscPtr;
 use std::ops::Deref;
 use std::{cmp::Ordering, sync::Arc};
 
+use crate::data_source::DeclaredCall;
 use crate::runtime::abi::AscEthereumBlock;
 use crate::runtime::abi::AscEthereumBlock_0_0_6;
 use crate::runtime::abi::AscEthereumCall;
@@ -48,6 +50,7 @@ pub enum MappingTrigger {
         log: Arc<Log>,
         params: Vec<LogParam>,
         receipt: Option<Arc<TransactionReceipt>>,
+        calls: Vec<DeclaredCall>,
     },
     Call {
         block: Arc<LightEthereumBlock>,
@@ -102,6 +105,7 @@ impl std::fmt::Debug for MappingTrigger {
                 log,
                 params,
                 receipt: _,
+                calls: _,
             } => MappingTriggerWithoutBlock::Log {
                 _transaction: transaction.cheap_clone(),
                 _log: log.cheap_clone(),
@@ -139,6 +143,7 @@ impl ToAscPtr for MappingTrigger {
                 log,
                 params,
                 receipt,
+                calls: _,
             } => {
                 let api_version = heap.api_version();
                 let ethereum_event_data = EthereumEventData {
@@ -413,6 +418,60 @@ impl TriggerData for EthereumTrigger {
     fn address_match(&self) -> Option<&[u8]> {
         self.address().map(|address| address.as_bytes())
     }
+
+    fn prefetch_hints(&self) -> Option<PrefetchHints> {
+        // Only the first words of the data of a log or the input of a call
+        // are used since the position of everything after a dynamically
+        // sized parameter depends on its size
+        const MAX_WORDS: usize = 8;
+
+        // Addresses are padded to a full word. Every word is passed along
+        // both as a whole and as an address so that positions only depend
+        // on the kind of trigger
+        fn push_words(values: &mut Vec<Vec<u8>>, bytes: &[u8]) {
+            for word in bytes.chunks_exact(32).take(MAX_WORDS) {
+                values.push(word.to_vec());
+                values.push(word[12..].to_vec());
+            }
+        }
+
+        match self {
+            EthereumTrigger::Log(log_ref) => {
+                let log = log_ref.log();
+                let mut kind = b"log".to_vec();
+                kind.extend_from_slice(log.topics.first()?.as_bytes());
+
+                let mut values = vec![
+                    log.address.as_bytes().to_vec(),
+                    log.transaction_hash
+                        .map(|hash| hash.as_bytes().to_vec())
+                        .unwrap_or_default(),
+                ];
+                for topic in log.topics.iter().skip(1) {
+                    push_words(&mut values, topic.as_bytes());
+                }
+                push_words(&mut values, &log.data.0);
+                Some(PrefetchHints { kind, values })
+            }
+            EthereumTrigger::Call(call) => {
+                let selector = call.input.0.get(..4)?;
+                let mut kind = b"call".to_vec();
+                kind.extend_from_slice(call.to.as_bytes());
+                kind.extend_from_slice(selector);
+
+                let mut values = vec![
+                    call.from.as_bytes().to_vec(),
+                    call.to.as_bytes().to_vec(),
+                    call.transaction_hash
+                        .map(|hash| hash.as_bytes().to_vec())
+                        .unwrap_or_default(),
+                ];
+                push_words(&mut values, &call.input.0[4..]);
+                Some(PrefetchHints { kind, values })
+            }
+            EthereumTrigger::Block(..) => None,
+        }
+    }
 }
 
 /// Ethereum block data.
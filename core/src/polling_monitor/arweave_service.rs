@@ -2,7 +2,9 @@ use anyhow::Error;
 use bytes::Bytes;
 use futures::future::BoxFuture;
 use graph::{
-    components::link_resolver::{ArweaveClient, ArweaveResolver, FileSizeLimit},
+    components::link_resolver::{
+        ArweaveClient, ArweaveResolver, ContentCache, ContentKind, FileSizeLimit,
+    },
     data_source::offchain::Base64,
     prelude::CheapClone,
 };
@@ -16,11 +18,13 @@ pub fn arweave_service(
     timeout: Duration,
     rate_limit: u16,
     max_file_size: FileSizeLimit,
+    content_cache: Option<Arc<ContentCache>>,
 ) -> ArweaveService {
     let arweave = ArweaveServiceInner {
         client,
         timeout,
         max_file_size,
+        content_cache,
     };
 
     let svc = ServiceBuilder::new()
@@ -38,6 +42,7 @@ struct ArweaveServiceInner {
     client: Arc<ArweaveClient>,
     timeout: Duration,
     max_file_size: FileSizeLimit,
+    content_cache: Option<Arc<ContentCache>>,
 }
 
 impl CheapClone for ArweaveServiceInner {
@@ -46,17 +51,34 @@ impl CheapClone for ArweaveServiceInner {
             client: self.client.cheap_clone(),
             timeout: self.timeout,
             max_file_size: self.max_file_size.cheap_clone(),
+            content_cache: self.content_cache.cheap_clone(),
         }
     }
 }
 
 impl ArweaveServiceInner {
     async fn call_inner(self, req: Base64) -> Result<Option<Bytes>, Error> {
-        self.client
+        if let Some(content_cache) = &self.content_cache {
+            if let Some(data) = content_cache.get(ContentKind::Arweave, &req).await {
+                let fits = match self.max_file_size {
+                    FileSizeLimit::Unlimited => true,
+                    FileSizeLimit::MaxBytes(max) => data.len() as u64 <= max,
+                };
+                if fits {
+                    return Ok(Some(data.into()));
+                }
+            }
+        }
+
+        let data = self
+            .client
             .get_with_limit(&req, &self.max_file_size)
-            .await
-            .map(Bytes::from)
-            .map(Some)
-            .map_err(Error::from)
+            .await?;
+        if let Some(content_cache) = &self.content_cache {
+            content_cache
+                .insert(ContentKind::Arweave, &req, data.clone())
+                .await;
+        }
+        Ok(Some(Bytes::from(data)))
     }
 }
@@ -0,0 +1,202 @@
+use anyhow::{anyhow, Error};
+use bytes::{Bytes, BytesMut};
+use futures::future::BoxFuture;
+use graph::{
+    data_source::offchain::HttpUrl,
+    prelude::{
+        reqwest::{self, redirect, StatusCode},
+        CheapClone,
+    },
+    url::Url,
+};
+use std::{sync::Arc, time::Duration};
+use tower::{buffer::Buffer, ServiceBuilder, ServiceExt};
+
+/// The number of redirects we follow before giving up
+const MAX_REDIRECTS: usize = 10;
+
+pub type HttpService = Buffer<HttpUrl, BoxFuture<'static, Result<Option<HttpContent>, Error>>>;
+
+/// What fetching the content of a `file/http` data source resulted in
+#[derive(Clone, Debug, PartialEq)]
+pub enum HttpContent {
+    /// The content served at the URL
+    Fetched(Bytes),
+    /// The URL is not allowed. Since that will never change, the URL is
+    /// not fetched and not polled again, and the data source fails
+    Disallowed,
+}
+
+/// A service that fetches the content of `file/http` data sources. Only
+/// URLs that start with one of the URLs in `allow` are fetched, and
+/// redirects are only followed to such URLs
+pub fn http_service(
+    allow: Vec<Url>,
+    max_file_size: u64,
+    timeout: Duration,
+    rate_limit: u16,
+) -> HttpService {
+    let allow = Arc::new(allow);
+    let redirect_allow = allow.cheap_clone();
+    let client = reqwest::Client::builder()
+        .redirect(redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error("too many redirects")
+            } else if is_allowed(&redirect_allow, attempt.url()) {
+                attempt.follow()
+            } else {
+                let error = format!("redirect to {} is not allowed", attempt.url());
+                attempt.error(error)
+            }
+        }))
+        .timeout(timeout)
+        .build()
+        .expect("failed to build HTTP client");
+
+    let http = HttpServiceInner {
+        client,
+        allow,
+        max_file_size,
+    };
+
+    let svc = ServiceBuilder::new()
+        .rate_limit(rate_limit.into(), Duration::from_secs(1))
+        .service_fn(move |req| http.cheap_clone().call_inner(req))
+        .boxed();
+
+    // The `Buffer` makes it so the rate limit is shared among clones.
+    // Make it unbounded to avoid any risk of starvation.
+    Buffer::new(svc, u32::MAX as usize)
+}
+
+/// Whether `url` has the same scheme, host and port as one of the URLs in
+/// `allow` and its path starts with the path of that URL
+fn is_allowed(allow: &[Url], url: &Url) -> bool {
+    allow.iter().any(|prefix| {
+        if prefix.scheme() != url.scheme()
+            || prefix.host_str() != url.host_str()
+            || prefix.port_or_known_default() != url.port_or_known_default()
+        {
+            return false;
+        }
+        // `https://example.com/api` allows `https://example.com/api/x` but
+        // not `https://example.com/apix`
+        match url.path().strip_prefix(prefix.path()) {
+            Some(rest) => prefix.path().ends_with('/') || rest.is_empty() || rest.starts_with('/'),
+            None => false,
+        }
+    })
+}
+
+#[derive(Clone)]
+struct HttpServiceInner {
+    client: reqwest::Client,
+    allow: Arc<Vec<Url>>,
+    max_file_size: u64,
+}
+
+impl CheapClone for HttpServiceInner {
+    fn cheap_clone(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            allow: self.allow.cheap_clone(),
+            max_file_size: self.max_file_size,
+        }
+    }
+}
+
+impl HttpServiceInner {
+    async fn call_inner(self, req: HttpUrl) -> Result<Option<HttpContent>, Error> {
+        let url = match Url::parse(req.as_str()) {
+            Ok(url) if is_allowed(&self.allow, &url) => url,
+            _ => return Ok(Some(HttpContent::Disallowed)),
+        };
+
+        let mut rsp = match self.client.get(url.clone()).send().await {
+            Ok(rsp) => rsp,
+            Err(e) if e.is_timeout() => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        match rsp.status() {
+            StatusCode::NOT_FOUND | StatusCode::GATEWAY_TIMEOUT => return Ok(None),
+            status if !status.is_success() => {
+                return Err(anyhow!("fetching {} failed with status {}", url, status))
+            }
+            _ => {}
+        }
+
+        let too_large = |size: u64| {
+            anyhow!(
+                "HTTP file {} is too large. It can be at most {} bytes but is at least {} bytes",
+                url,
+                self.max_file_size,
+                size
+            )
+        };
+        if let Some(size) = rsp.content_length() {
+            if size > self.max_file_size {
+                return Err(too_large(size));
+            }
+        }
+
+        // The content length is not always known, so we also check the
+        // size of what we actually receive
+        let mut content = BytesMut::new();
+        loop {
+            match rsp.chunk().await {
+                Ok(Some(chunk)) => {
+                    content.extend_from_slice(&chunk);
+                    if content.len() as u64 > self.max_file_size {
+                        return Err(too_large(content.len() as u64));
+                    }
+                }
+                Ok(None) => break,
+                Err(e) if e.is_timeout() => return Ok(None),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(Some(HttpContent::Fetched(content.freeze())))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use graph::prelude::tokio;
+
+    use super::*;
+
+    #[test]
+    fn allowed_urls() {
+        let allow = vec![
+            Url::parse("https://example.com/api").unwrap(),
+            Url::parse("http://localhost:8080/").unwrap(),
+        ];
+        let check = |url: &str| is_allowed(&allow, &Url::parse(url).unwrap());
+
+        assert!(check("https://example.com/api"));
+        assert!(check("https://example.com/api/file.json"));
+        assert!(check("https://example.com:443/api/file.json"));
+        assert!(check("http://localhost:8080/anything?x=1"));
+
+        assert!(!check("https://example.com/apix"));
+        assert!(!check("https://example.com/other"));
+        assert!(!check("http://example.com/api/file.json"));
+        assert!(!check("https://example.org/api/file.json"));
+        assert!(!check("http://localhost:8081/anything"));
+        assert!(!check("http://localhost/anything"));
+    }
+
+    #[tokio::test]
+    async fn disallowed_urls_are_not_fetched() {
+        let svc = http_service(
+            vec![Url::parse("https://example.com/api/").unwrap()],
+            1024,
+            Duration::from_secs(1),
+            10,
+        );
+        for url in ["https://example.org/api/file.json", "not a url"] {
+            let content = svc.clone().oneshot(HttpUrl::from(url)).await.unwrap();
+            assert_eq!(Some(HttpContent::Disallowed), content);
+        }
+    }
+}
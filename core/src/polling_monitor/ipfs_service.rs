@@ -2,10 +2,11 @@ use anyhow::{anyhow, Error};
 use bytes::Bytes;
 use futures::future::BoxFuture;
 use graph::{
+    components::link_resolver::{CarArchive, ContentCache, ContentKind},
     ipfs_client::{CidFile, IpfsClient, StatApi},
     prelude::CheapClone,
 };
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 use tower::{buffer::Buffer, ServiceBuilder, ServiceExt};
 
 const CLOUDFLARE_TIMEOUT: u16 = 524;
@@ -18,11 +19,13 @@ pub fn ipfs_service(
     max_file_size: u64,
     timeout: Duration,
     rate_limit: u16,
+    content_cache: Option<Arc<ContentCache>>,
 ) -> IpfsService {
     let ipfs = IpfsServiceInner {
         client,
         max_file_size,
         timeout,
+        content_cache,
     };
 
     let svc = ServiceBuilder::new()
@@ -35,11 +38,46 @@ pub fn ipfs_service(
     Buffer::new(svc, u32::MAX as usize)
 }
 
+/// A service that serves files from the blocks in `archive` instead of
+/// from an IPFS node. Files that are not in the archive are never found
+pub fn local_ipfs_service(archive: Arc<CarArchive>, max_file_size: u64) -> IpfsService {
+    let svc = ServiceBuilder::new()
+        .service_fn(move |req| cat_local(archive.cheap_clone(), req, max_file_size))
+        .boxed();
+
+    Buffer::new(svc, u32::MAX as usize)
+}
+
+async fn cat_local(
+    archive: Arc<CarArchive>,
+    req: CidFile,
+    max_file_size: u64,
+) -> Result<Option<Bytes>, Error> {
+    let cid_str = match req.path {
+        Some(path) => format!("{}/{}", req.cid, path),
+        None => req.cid.to_string(),
+    };
+    let data = match archive.cat(&cid_str)? {
+        Some(data) => data,
+        None => return Ok(None),
+    };
+    if data.len() as u64 > max_file_size {
+        return Err(anyhow!(
+            "IPFS file {} is too large. It can be at most {} bytes but is {} bytes",
+            cid_str,
+            max_file_size,
+            data.len()
+        ));
+    }
+    Ok(Some(data.into()))
+}
+
 #[derive(Clone)]
 struct IpfsServiceInner {
     client: IpfsClient,
     max_file_size: u64,
     timeout: Duration,
+    content_cache: Option<Arc<ContentCache>>,
 }
 
 impl CheapClone for IpfsServiceInner {
@@ -48,6 +86,7 @@ impl CheapClone for IpfsServiceInner {
             client: self.client.cheap_clone(),
             max_file_size: self.max_file_size,
             timeout: self.timeout,
+            content_cache: self.content_cache.cheap_clone(),
         }
     }
 }
@@ -65,6 +104,14 @@ impl IpfsServiceInner {
             None => cid.to_string(),
         };
 
+        if let Some(content_cache) = &self.content_cache {
+            if let Some(data) = content_cache.get(ContentKind::Ipfs, &cid_str).await {
+                if data.len() as u64 <= self.max_file_size {
+                    return Ok(Some(data.into()));
+                }
+            }
+        }
+
         let size = match self
             .client
             .stat_size(StatApi::Files, cid_str.clone(), self.timeout)
@@ -87,11 +134,13 @@ impl IpfsServiceInner {
             ));
         }
 
-        Ok(self
-            .client
-            .cat_all(&cid_str, self.timeout)
-            .await
-            .map(Some)?)
+        let data = self.client.cat_all(&cid_str, self.timeout).await?;
+        if let Some(content_cache) = &self.content_cache {
+            content_cache
+                .insert(ContentKind::Ipfs, &cid_str, data.to_vec())
+                .await;
+        }
+        Ok(Some(data))
     }
 }
 
@@ -149,7 +198,7 @@ mod test {
         let cid = Cid::from_str(&ipfs_folder.hash).unwrap();
         let file = "random.txt".to_string();
 
-        let svc = super::ipfs_service(local, 100000, Duration::from_secs(5), 10);
+        let svc = super::ipfs_service(local, 100000, Duration::from_secs(5), 10, None);
 
         let content = svc
             .oneshot(super::CidFile {
@@ -32,6 +32,9 @@ pub(super) struct SubgraphInstance<C: Blockchain, T: RuntimeHostBuilder<C>> {
     offchain_hosts: OffchainHosts<C, T>,
 
     /// Maps the hash of a module to a channel to the thread in which the module is instantiated.
+    /// When triggers are processed in parallel, the name of the data source is part of the hash
+    /// so that each data source gets its own thread and handlers of different data sources can
+    /// run at the same time.
     module_cache: HashMap<[u8; 32], Sender<T::Req>>,
 
     /// This manages the sequence of causality regions for the subgraph.
@@ -99,7 +102,12 @@ where
         };
 
         let mapping_request_sender = {
-            let module_hash = tiny_keccak::keccak256(module_bytes.as_ref());
+            let mut module_hash = tiny_keccak::keccak256(module_bytes.as_ref());
+            if ENV_VARS.mappings.parallel_triggers {
+                module_hash = tiny_keccak::keccak256(
+                    &[&module_hash[..], data_source.name().as_bytes()].concat(),
+                );
+            }
             if let Some(sender) = self.module_cache.get(&module_hash) {
                 sender.clone()
             } else {
@@ -240,4 +248,8 @@ where
     pub fn first_host(&self) -> Option<&Arc<T::Host>> {
         self.onchain_hosts.hosts().first()
     }
+
+    pub fn host_builder(&self) -> &T {
+        &self.host_builder
+    }
 }
@@ -1,12 +1,15 @@
 mod instance;
+mod parallel;
+mod prefetch;
 
 use crate::polling_monitor::{
-    spawn_monitor, ArweaveService, IpfsService, PollingMonitor, PollingMonitorMetrics,
+    spawn_monitor, ArweaveService, HttpContent, HttpService, IpfsService, PollingMonitor,
+    PollingMonitorMetrics,
 };
 use anyhow::{self, Error};
 use bytes::Bytes;
 use graph::{
-    blockchain::{BlockTime, Blockchain},
+    blockchain::{Block as _, BlockTime, Blockchain, PrefetchHints},
     components::{
         store::{DeploymentId, SubgraphFork},
         subgraph::{HostMetrics, MappingError, RuntimeHost as _, SharedProofOfIndexing},
@@ -14,14 +17,14 @@ use graph::{
     data::subgraph::SubgraphManifest,
     data_source::{
         causality_region::CausalityRegionSeq,
-        offchain::{self, Base64},
+        offchain::{self, Base64, HttpUrl},
         CausalityRegion, DataSource, DataSourceTemplate, TriggerData,
     },
     ipfs_client::CidFile,
     prelude::{
         BlockNumber, BlockPtr, BlockState, CancelGuard, CheapClone, DeploymentHash,
         MetricsRegistry, RuntimeHostBuilder, SubgraphCountMetric, SubgraphInstanceMetrics,
-        TriggerProcessor,
+        TriggerProcessor, ENV_VARS,
     },
     slog::Logger,
     tokio::sync::mpsc,
@@ -30,6 +33,8 @@ use std::sync::{Arc, RwLock};
 use std::{collections::HashMap, time::Instant};
 
 use self::instance::SubgraphInstance;
+pub use self::parallel::ParallelTriggers;
+use self::prefetch::Prefetcher;
 
 #[derive(Clone, Debug)]
 pub struct SubgraphKeepAlive {
@@ -74,6 +79,7 @@ where
     pub offchain_monitor: OffchainMonitor,
     pub filter: Option<C::TriggerFilter>,
     trigger_processor: Box<dyn TriggerProcessor<C, T>>,
+    prefetcher: Option<Prefetcher>,
 }
 
 impl<C: Blockchain, T: RuntimeHostBuilder<C>> IndexingContext<C, T> {
@@ -99,34 +105,111 @@ impl<C: Blockchain, T: RuntimeHostBuilder<C>> IndexingContext<C, T> {
             offchain_monitor,
             filter: None,
             trigger_processor,
+            prefetcher: ENV_VARS.mappings.entity_prefetch.then(Prefetcher::new),
         }
     }
 
+    /// Load the entities that the handlers for triggers with `hints` will
+    /// probably load into the entity cache of `state`
+    pub fn prefetch(
+        &self,
+        hints: impl Iterator<Item = PrefetchHints>,
+        state: &mut BlockState,
+    ) -> Result<(), MappingError> {
+        let prefetcher = match &self.prefetcher {
+            Some(prefetcher) => prefetcher,
+            None => return Ok(()),
+        };
+        let keys = prefetcher.keys(hints);
+        if !keys.is_empty() {
+            state
+                .entity_cache
+                .prefetch(keys)
+                .map_err(|e| MappingError::Unknown(e.into()))?;
+        }
+        Ok(())
+    }
+
+    /// Decode `triggers` for the hosts that prepare their triggers and
+    /// let the host builder prepare them all at once, e.g., by making the
+    /// calls that the manifest declares for their handlers with one
+    /// request. The results are put into `state` for the handlers to use
+    pub async fn prepare_triggers(
+        &self,
+        logger: &Logger,
+        block: &Arc<C::Block>,
+        triggers: &[TriggerData<C>],
+        state: &mut BlockState,
+    ) -> Result<(), MappingError> {
+        let mut decoded = Vec::new();
+        for trigger in triggers {
+            for host in self.instance.hosts_for_trigger(trigger) {
+                if !host.prepares_triggers() {
+                    continue;
+                }
+                // Errors are reported when the triggers are processed
+                if let Ok(Some(mapping_trigger)) = host.match_and_decode(trigger, block, logger) {
+                    decoded.push((host.data_source(), mapping_trigger));
+                }
+            }
+        }
+        if decoded.is_empty() {
+            return Ok(());
+        }
+
+        let triggers: Vec<_> = decoded
+            .iter()
+            .map(|(data_source, mapping_trigger)| (*data_source, &mapping_trigger.trigger))
+            .collect();
+        let declared_calls = self
+            .instance
+            .host_builder()
+            .prepare_triggers(logger, &block.ptr(), &triggers)
+            .await?;
+        state.declared_calls = Arc::new(declared_calls);
+        Ok(())
+    }
+
     pub async fn process_trigger(
         &self,
         logger: &Logger,
         block: &Arc<C::Block>,
         trigger: &TriggerData<C>,
-        state: BlockState,
+        mut state: BlockState,
         proof_of_indexing: &SharedProofOfIndexing,
         causality_region: &str,
         debug_fork: &Option<Arc<dyn SubgraphFork>>,
         subgraph_metrics: &Arc<SubgraphInstanceMetrics>,
         instrument: bool,
     ) -> Result<BlockState, MappingError> {
-        self.process_trigger_in_hosts(
-            logger,
-            self.instance.hosts_for_trigger(trigger),
-            block,
-            trigger,
-            state,
-            proof_of_indexing,
-            causality_region,
-            debug_fork,
-            subgraph_metrics,
-            instrument,
-        )
-        .await
+        // Learn which entities the handlers load for the prefetcher
+        let hints = self
+            .prefetcher
+            .as_ref()
+            .and_then(|_| trigger.prefetch_hints());
+        if hints.is_some() {
+            state.entity_cache.track_loads();
+        }
+
+        let mut state = self
+            .process_trigger_in_hosts(
+                logger,
+                self.instance.hosts_for_trigger(trigger),
+                block,
+                trigger,
+                state,
+                proof_of_indexing,
+                causality_region,
+                debug_fork,
+                subgraph_metrics,
+                instrument,
+            )
+            .await?;
+
+        if let (Some(prefetcher), Some(hints)) = (&self.prefetcher, hints) {
+            prefetcher.learn(&hints, state.entity_cache.take_loads());
+        }
+        Ok(state)
     }
 
     pub async fn process_block(
@@ -261,6 +344,21 @@ impl<C: Blockchain, T: RuntimeHostBuilder<C>> IndexingContext<C, T> {
         self.instance.causality_region_next_value()
     }
 
+    /// The causality region of the offchain data source that will handle
+    /// `trigger`, if there is one
+    pub fn offchain_causality_region(
+        &self,
+        trigger: &offchain::TriggerData,
+    ) -> Option<CausalityRegion> {
+        let source = &trigger.source;
+        let trigger = TriggerData::<C>::Offchain(trigger.clone());
+        self.instance
+            .hosts_for_trigger(&trigger)
+            .filter_map(|host| host.data_source().as_offchain())
+            .find(|ds| &ds.source == source && !ds.is_processed())
+            .map(|ds| ds.causality_region)
+    }
+
     pub fn hosts_len(&self) -> usize {
         self.instance.hosts_len()
     }
@@ -283,6 +381,8 @@ pub struct OffchainMonitor {
     ipfs_monitor_rx: mpsc::UnboundedReceiver<(CidFile, Bytes)>,
     arweave_monitor: PollingMonitor<Base64>,
     arweave_monitor_rx: mpsc::UnboundedReceiver<(Base64, Bytes)>,
+    http_monitor: PollingMonitor<HttpUrl>,
+    http_monitor_rx: mpsc::UnboundedReceiver<(HttpUrl, HttpContent)>,
 }
 
 impl OffchainMonitor {
@@ -292,12 +392,14 @@ impl OffchainMonitor {
         subgraph_hash: &DeploymentHash,
         ipfs_service: IpfsService,
         arweave_service: ArweaveService,
+        http_service: HttpService,
     ) -> Self {
         let metrics = Arc::new(PollingMonitorMetrics::new(registry, subgraph_hash));
         // The channel is unbounded, as it is expected that `fn ready_offchain_events` is called
         // frequently, or at least with the same frequency that requests are sent.
         let (ipfs_monitor_tx, ipfs_monitor_rx) = mpsc::unbounded_channel();
         let (arweave_monitor_tx, arweave_monitor_rx) = mpsc::unbounded_channel();
+        let (http_monitor_tx, http_monitor_rx) = mpsc::unbounded_channel();
 
         let ipfs_monitor = spawn_monitor(
             ipfs_service,
@@ -306,12 +408,21 @@ impl OffchainMonitor {
             metrics.cheap_clone(),
         );
 
-        let arweave_monitor = spawn_monitor(arweave_service, arweave_monitor_tx, logger, metrics);
+        let arweave_monitor = spawn_monitor(
+            arweave_service,
+            arweave_monitor_tx,
+            logger.cheap_clone(),
+            metrics.cheap_clone(),
+        );
+
+        let http_monitor = spawn_monitor(http_service, http_monitor_tx, logger, metrics);
         Self {
             ipfs_monitor,
             ipfs_monitor_rx,
             arweave_monitor,
             arweave_monitor_rx,
+            http_monitor,
+            http_monitor_rx,
         }
     }
 
@@ -319,6 +430,7 @@ impl OffchainMonitor {
         match source {
             offchain::Source::Ipfs(cid_file) => self.ipfs_monitor.monitor(cid_file),
             offchain::Source::Arweave(base64) => self.arweave_monitor.monitor(base64),
+            offchain::Source::Http(url) => self.http_monitor.monitor(url),
         };
         Ok(())
     }
@@ -353,6 +465,26 @@ impl OffchainMonitor {
             }
         }
 
+        loop {
+            match self.http_monitor_rx.try_recv() {
+                Ok((url, HttpContent::Fetched(data))) => triggers.push(offchain::TriggerData {
+                    source: offchain::Source::Http(url),
+                    data: Arc::new(data),
+                }),
+                Ok((url, HttpContent::Disallowed)) => {
+                    anyhow::bail!(
+                        "the `file/http` data source for {} failed since fetching that URL \
+                         is not allowed by the `[http_sources]` configuration",
+                        url.as_str()
+                    )
+                }
+                Err(TryRecvError::Disconnected) => {
+                    anyhow::bail!("http monitor unexpectedly terminated")
+                }
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+
         Ok(triggers)
     }
 }
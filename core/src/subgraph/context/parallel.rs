@@ -0,0 +1,430 @@
+//! Processing the triggers of a block in parallel.
+//!
+//! Normally, the triggers of a block are processed strictly in order and
+//! every handler sees the changes of all handlers that ran before it. When
+//! the data sources that handle the triggers of a block fall into groups
+//! that declare disjoint sets of entity types in the manifest, the handlers
+//! of one group can not observe the changes of another group. Each group
+//! can then be processed on its own `BlockState`, concurrently with the
+//! other groups, and the results are combined so that they are the same as
+//! if the triggers had been processed in order:
+//!
+//! - the changes of the groups are added to the block state one group after
+//!   the other; since the groups access disjoint entity types, the order in
+//!   which that happens does not matter
+//! - the PoI events of each trigger are recorded and replayed in the order
+//!   of the triggers, since the PoI depends on the order of events
+//!
+//! The declarations in the manifest are not enforced for onchain data
+//! sources, which is why the entity types that each group actually accessed
+//! are checked afterwards. If a group accessed entity types that it did not
+//! declare, or if the result could otherwise differ from processing the
+//! triggers in order, the results are discarded and the triggers need to be
+//! processed in order. That is also the case when a handler fails, since
+//! the failure might be caused by processing the groups separately.
+use std::collections::{BTreeSet, HashSet};
+use std::sync::Arc;
+
+use atomic_refcell::AtomicRefCell;
+use futures::future::join_all;
+use graph::{
+    blockchain::{Blockchain, DeclaredCalls},
+    cheap_clone::CheapClone,
+    components::{
+        store::{SubgraphFork, WritableStore},
+        subgraph::{MappingError, RecordedEvents, RuntimeHost as _, SharedProofOfIndexing},
+    },
+    data_source::TriggerData,
+    prelude::{BlockState, RuntimeHostBuilder, SubgraphInstanceMetrics},
+    schema::EntityType,
+    slog::Logger,
+    util::lfu_cache::LfuCache,
+};
+
+use super::IndexingContext;
+
+/// The outcome of trying to process the triggers of a block in parallel
+pub enum ParallelTriggers {
+    /// The triggers were processed and their changes were added to the
+    /// block state
+    Processed,
+    /// The triggers can not be split into groups that are independent of
+    /// each other
+    Dependent,
+    /// The triggers were processed, but the results were discarded since
+    /// they could differ from processing the triggers in order for the
+    /// given reason
+    Discarded(&'static str),
+    /// The `data_sources` accessed `entity_type` without declaring it in
+    /// the manifest
+    Undeclared {
+        data_sources: Vec<String>,
+        entity_type: String,
+    },
+}
+
+/// Some of the triggers of a block together with the data sources that
+/// handle them and the entity types they declare
+#[derive(Default)]
+struct TriggerGroup {
+    data_sources: BTreeSet<String>,
+    entities: BTreeSet<String>,
+    /// Indexes into the triggers of the block
+    triggers: Vec<usize>,
+}
+
+impl TriggerGroup {
+    fn overlaps(&self, other: &TriggerGroup) -> bool {
+        !self.data_sources.is_disjoint(&other.data_sources)
+            || !self.entities.is_disjoint(&other.entities)
+    }
+
+    fn absorb(&mut self, other: TriggerGroup) {
+        self.data_sources.extend(other.data_sources);
+        self.entities.extend(other.entities);
+        self.triggers.extend(other.triggers);
+    }
+}
+
+/// The block state of a `TriggerGroup` and the PoI events of each of its
+/// triggers
+type Processed = (BlockState, Vec<(usize, RecordedEvents)>);
+
+/// The result of processing one `TriggerGroup`. The entity types that the
+/// group accessed are known even if one of its handlers failed
+struct GroupResult {
+    accessed: HashSet<EntityType>,
+    processed: Result<Processed, MappingError>,
+}
+
+/// Check that the groups only accessed the entity types they declare and
+/// that combining their results gives the same result as processing the
+/// triggers in order. Return the result of each group if that is the case
+fn combine(
+    groups: &[TriggerGroup],
+    results: Vec<GroupResult>,
+) -> Result<Vec<Processed>, ParallelTriggers> {
+    // Handlers might fail because they read entity types that another
+    // group changes; we therefore need to check the accessed entity types
+    // of all groups, even the ones that failed
+    for (group, result) in groups.iter().zip(&results) {
+        let undeclared = result
+            .accessed
+            .iter()
+            .find(|entity_type| !group.entities.contains(entity_type.as_str()));
+        if let Some(entity_type) = undeclared {
+            return Err(ParallelTriggers::Undeclared {
+                data_sources: group.data_sources.iter().cloned().collect(),
+                entity_type: entity_type.to_string(),
+            });
+        }
+    }
+
+    // Processing the triggers in order reports the error with the right
+    // context, or succeeds if the error was caused by processing the
+    // groups separately
+    let processed = results
+        .into_iter()
+        .map(|result| result.processed)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| ParallelTriggers::Discarded("mapping errors"))?;
+
+    // The order of deterministic errors, of generated ids and of
+    // created data sources depends on the order of the handlers
+    if processed.iter().any(|(state, _)| state.has_errors()) {
+        return Err(ParallelTriggers::Discarded("deterministic errors"));
+    }
+    if processed
+        .iter()
+        .any(|(state, _)| state.entity_cache.generated_ids())
+    {
+        return Err(ParallelTriggers::Discarded("generated ids"));
+    }
+    if processed
+        .iter()
+        .filter(|(state, _)| state.has_created_data_sources())
+        .count()
+        > 1
+    {
+        return Err(ParallelTriggers::Discarded("data sources created"));
+    }
+    Ok(processed)
+}
+
+impl<C: Blockchain, T: RuntimeHostBuilder<C>> IndexingContext<C, T> {
+    /// Try to process `triggers` in parallel, adding their changes to
+    /// `state` and their events to `proof_of_indexing` if that succeeds.
+    /// Unless this returns `ParallelTriggers::Processed`, neither is
+    /// changed and the triggers need to be processed in order, which also
+    /// reports any errors of the handlers
+    pub async fn process_triggers_in_parallel(
+        &self,
+        logger: &Logger,
+        block: &Arc<C::Block>,
+        triggers: &[TriggerData<C>],
+        store: &Arc<dyn WritableStore>,
+        state: &mut BlockState,
+        proof_of_indexing: &SharedProofOfIndexing,
+        causality_region: &str,
+        debug_fork: &Option<Arc<dyn SubgraphFork>>,
+        subgraph_metrics: &Arc<SubgraphInstanceMetrics>,
+        instrument: bool,
+    ) -> ParallelTriggers {
+        let groups = match self.trigger_groups(logger, block, triggers) {
+            Some(groups) => groups,
+            None => return ParallelTriggers::Dependent,
+        };
+
+        let declared_calls = state.declared_calls.cheap_clone();
+        let results = join_all(groups.iter().map(|group| {
+            self.process_trigger_group(
+                logger,
+                block,
+                triggers,
+                group,
+                store,
+                &declared_calls,
+                proof_of_indexing,
+                causality_region,
+                debug_fork,
+                subgraph_metrics,
+                instrument,
+            )
+        }))
+        .await;
+
+        let processed = match combine(&groups, results) {
+            Ok(processed) => processed,
+            Err(outcome) => return outcome,
+        };
+
+        let mut events = Vec::new();
+        for (group_state, group_events) in processed {
+            state.extend(group_state);
+            events.extend(group_events);
+        }
+        if let Some(proof_of_indexing) = proof_of_indexing {
+            events.sort_by_key(|(idx, _)| *idx);
+            let mut proof_of_indexing = proof_of_indexing.borrow_mut();
+            for (_, events) in events {
+                proof_of_indexing.replay(logger, events);
+            }
+        }
+
+        ParallelTriggers::Processed
+    }
+
+    /// Split `triggers` into groups whose data sources declare disjoint
+    /// sets of entity types. Return `None` if there are fewer than two
+    /// groups, or if a data source that handles one of the triggers does
+    /// not declare the entity types it accesses
+    fn trigger_groups(
+        &self,
+        logger: &Logger,
+        block: &Arc<C::Block>,
+        triggers: &[TriggerData<C>],
+    ) -> Option<Vec<TriggerGroup>> {
+        let mut groups: Vec<TriggerGroup> = Vec::new();
+
+        for (idx, trigger) in triggers.iter().enumerate() {
+            let mut group = TriggerGroup {
+                triggers: vec![idx],
+                ..Default::default()
+            };
+
+            for host in self.instance.hosts_for_trigger(trigger) {
+                // Errors are reported when the triggers are processed in
+                // order
+                if host
+                    .match_and_decode(trigger, block, logger)
+                    .ok()?
+                    .is_none()
+                {
+                    continue;
+                }
+                let data_source = host.data_source();
+                let entities = data_source.declared_entities();
+                if entities.is_empty() {
+                    return None;
+                }
+                group.data_sources.insert(data_source.name().to_owned());
+                group
+                    .entities
+                    .extend(entities.into_iter().map(str::to_owned));
+            }
+
+            // Triggers that no data source handles do not need processing
+            if group.data_sources.is_empty() {
+                continue;
+            }
+
+            let (overlapping, disjoint): (Vec<_>, Vec<_>) =
+                groups.into_iter().partition(|other| other.overlaps(&group));
+            groups = disjoint;
+            for other in overlapping {
+                group.absorb(other);
+            }
+            groups.push(group);
+        }
+
+        if groups.len() < 2 {
+            return None;
+        }
+        for group in &mut groups {
+            group.triggers.sort_unstable();
+        }
+        groups.sort_by_key(|group| group.triggers[0]);
+        Some(groups)
+    }
+
+    /// Process the triggers of `group` in order on a new `BlockState`,
+    /// recording their PoI events
+    async fn process_trigger_group(
+        &self,
+        logger: &Logger,
+        block: &Arc<C::Block>,
+        triggers: &[TriggerData<C>],
+        group: &TriggerGroup,
+        store: &Arc<dyn WritableStore>,
+        declared_calls: &Arc<DeclaredCalls>,
+        proof_of_indexing: &SharedProofOfIndexing,
+        causality_region: &str,
+        debug_fork: &Option<Arc<dyn SubgraphFork>>,
+        subgraph_metrics: &Arc<SubgraphInstanceMetrics>,
+        instrument: bool,
+    ) -> GroupResult {
+        let mut state = BlockState::new(store.clone(), LfuCache::new());
+        state.declared_calls = declared_calls.cheap_clone();
+        let accessed = state.entity_cache.track_entity_types();
+
+        let processed = async {
+            self.prefetch(
+                group
+                    .triggers
+                    .iter()
+                    .filter_map(|&idx| triggers[idx].prefetch_hints()),
+                &mut state,
+            )?;
+
+            let recorder: SharedProofOfIndexing = proof_of_indexing
+                .as_ref()
+                .map(|poi| Arc::new(AtomicRefCell::new(poi.borrow().recorder())));
+
+            let mut events = Vec::with_capacity(group.triggers.len());
+            for &idx in &group.triggers {
+                state = self
+                    .process_trigger(
+                        logger,
+                        block,
+                        &triggers[idx],
+                        state,
+                        &recorder,
+                        causality_region,
+                        debug_fork,
+                        subgraph_metrics,
+                        instrument,
+                    )
+                    .await?;
+                if let Some(recorder) = &recorder {
+                    events.push((idx, recorder.borrow_mut().take_recorded()));
+                }
+            }
+            Ok::<_, MappingError>((state, events))
+        }
+        .await;
+
+        let accessed = accessed.lock().unwrap().clone();
+        GroupResult {
+            accessed,
+            processed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use graph::components::store::EmptyStore;
+    use graph::prelude::{anyhow::anyhow, DeploymentHash};
+    use graph::schema::InputSchema;
+
+    use super::*;
+
+    const SCHEMA: &str = "
+        type Account @entity { id: ID! }
+        type Token @entity { id: ID! }";
+
+    fn schema() -> InputSchema {
+        InputSchema::parse_latest(SCHEMA, DeploymentHash::new("test").unwrap()).unwrap()
+    }
+
+    fn group(data_source: &str, entity: &str, trigger: usize) -> TriggerGroup {
+        TriggerGroup {
+            data_sources: BTreeSet::from([data_source.to_string()]),
+            entities: BTreeSet::from([entity.to_string()]),
+            triggers: vec![trigger],
+        }
+    }
+
+    fn succeeded(accessed: &[&str]) -> GroupResult {
+        let schema = schema();
+        let state = BlockState::new(EmptyStore::new(schema.clone()), LfuCache::new());
+        GroupResult {
+            accessed: accessed
+                .iter()
+                .map(|name| schema.entity_type(name).unwrap())
+                .collect(),
+            processed: Ok((state, Vec::new())),
+        }
+    }
+
+    fn failed(accessed: &[&str]) -> GroupResult {
+        let schema = schema();
+        GroupResult {
+            accessed: accessed
+                .iter()
+                .map(|name| schema.entity_type(name).unwrap())
+                .collect(),
+            processed: Err(MappingError::Unknown(anyhow!("handler failed"))),
+        }
+    }
+
+    #[test]
+    fn combine_results() {
+        let groups = [group("accounts", "Account", 0), group("tokens", "Token", 1)];
+
+        let processed = combine(
+            &groups,
+            vec![succeeded(&["Account"]), succeeded(&["Token"])],
+        );
+        assert_eq!(2, processed.ok().unwrap().len());
+
+        // Errors are reported when processing the triggers in order
+        let res = combine(&groups, vec![succeeded(&["Account"]), failed(&["Token"])]);
+        assert!(matches!(
+            res,
+            Err(ParallelTriggers::Discarded("mapping errors"))
+        ));
+    }
+
+    #[test]
+    fn failed_group_reads_undeclared_type() {
+        let groups = [group("accounts", "Account", 0), group("tokens", "Token", 1)];
+
+        // The handler for `tokens` read an `Account`, which might be why it
+        // failed; the undeclared access is reported rather than the error
+        let res = combine(
+            &groups,
+            vec![succeeded(&["Account"]), failed(&["Token", "Account"])],
+        );
+        match res {
+            Err(ParallelTriggers::Undeclared {
+                data_sources,
+                entity_type,
+            }) => {
+                assert_eq!(vec!["tokens".to_string()], data_sources);
+                assert_eq!("Account", entity_type);
+            }
+            _ => panic!("expected the undeclared access to be reported"),
+        }
+    }
+}
@@ -0,0 +1,289 @@
+//! Prefetching the entities that handlers will load.
+//!
+//! Every entity that a handler loads and that is not in the entity cache
+//! yet requires a query. The entities that handlers load are usually
+//! identified by values of the trigger, for example the account that sent
+//! a transfer, whose address is the id of an `Account` entity. The
+//! `Prefetcher` learns from the entities that the handlers for a kind of
+//! trigger loaded which values of the trigger they use as ids of which
+//! entity types, and loads the entities for all triggers of a block with
+//! one query per entity type before any handler runs.
+//!
+//! Prefetching only changes which entities are in the entity cache, never
+//! what handlers see when they load an entity. Wrong guesses only cost the
+//! time to load entities that are not needed.
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::Mutex;
+
+use graph::{
+    blockchain::PrefetchHints,
+    data::store::{Id, IdType},
+    prelude::CheapClone,
+    schema::{EntityKey, EntityType},
+};
+
+/// How many triggers of a kind we need to have seen before we prefetch
+/// anything for them
+const MIN_TRIGGERS: u32 = 4;
+
+/// The fraction of the triggers of a kind for which a pattern needs to
+/// have matched an entity that was loaded for us to use it for
+/// prefetching
+const MIN_HIT_RATE: f64 = 0.5;
+
+/// When we have seen that many triggers of a kind, we halve all counts so
+/// that what handlers did recently matters more than what they did long ago
+const DECAY_TRIGGERS: u32 = 1000;
+
+/// How the id of an entity is derived from a value of a trigger
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum IdForm {
+    /// The value is the id of an entity with a `Bytes` id
+    Bytes,
+    /// The id is the value as a lowercase hex string prefixed with `0x`
+    Hex,
+    /// The id is the value as an unsigned integer, either as a string or as
+    /// an `Int8`
+    Decimal,
+}
+
+/// Interpret `value` as an unsigned big-endian integer. Return `None` if
+/// it does not fit into a `u128`
+fn decimal(value: &[u8]) -> Option<u128> {
+    let start = value.len().saturating_sub(16);
+    if value[..start].iter().any(|b| *b != 0) {
+        return None;
+    }
+    Some(
+        value[start..]
+            .iter()
+            .fold(0u128, |n, b| (n << 8) | *b as u128),
+    )
+}
+
+/// The id that `form` derives from `value` for an entity type whose ids
+/// have type `id_type`
+fn derive_id(value: &[u8], form: IdForm, id_type: IdType) -> Option<Id> {
+    if value.is_empty() {
+        return None;
+    }
+    match (form, id_type) {
+        (IdForm::Bytes, IdType::Bytes) => Some(Id::Bytes(value.into())),
+        (IdForm::Hex, IdType::String) => {
+            Some(Id::String(format!("0x{}", hex::encode(value)).into()))
+        }
+        (IdForm::Decimal, IdType::String) => {
+            decimal(value).map(|n| Id::String(n.to_string().into()))
+        }
+        (IdForm::Decimal, IdType::Int8) => decimal(value)
+            .and_then(|n| i64::try_from(n).ok())
+            .map(Id::Int8),
+        _ => None,
+    }
+}
+
+/// An id in the form that makes it easy to compare it with the values of
+/// a trigger
+enum ParsedId {
+    Bytes(IdForm, Vec<u8>),
+    Decimal(u128),
+}
+
+impl ParsedId {
+    /// Parse `id` if it could have been derived from a value with one of
+    /// the forms in `IdForm`
+    fn new(id: &Id) -> Option<Self> {
+        match id {
+            Id::Bytes(bytes) => Some(ParsedId::Bytes(IdForm::Bytes, bytes.to_vec())),
+            Id::String(s) => {
+                if let Some(digits) = s.strip_prefix("0x") {
+                    if digits.bytes().any(|b| b.is_ascii_uppercase()) {
+                        return None;
+                    }
+                    return hex::decode(digits)
+                        .ok()
+                        .map(|bytes| ParsedId::Bytes(IdForm::Hex, bytes));
+                }
+                let n = s.parse::<u128>().ok()?;
+                // Only ids without leading zeros are the result of
+                // formatting a number
+                (n.to_string() == s.as_str()).then_some(ParsedId::Decimal(n))
+            }
+            Id::Int8(n) => u128::try_from(*n).ok().map(ParsedId::Decimal),
+        }
+    }
+
+    fn form(&self, value: &[u8]) -> Option<IdForm> {
+        match self {
+            ParsedId::Bytes(form, bytes) => (bytes.as_slice() == value).then_some(*form),
+            ParsedId::Decimal(n) => (decimal(value) == Some(*n)).then_some(IdForm::Decimal),
+        }
+    }
+}
+
+/// Entities of `entity_type` whose id is derived from the value at
+/// `position` in the values of a trigger
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+struct Pattern {
+    entity_type: EntityType,
+    position: usize,
+    form: IdForm,
+}
+
+impl Pattern {
+    fn key(&self, values: &[Vec<u8>]) -> Option<EntityKey> {
+        let value = values.get(self.position)?;
+        let id_type = self.entity_type.id_type().ok()?;
+        let id = derive_id(value, self.form, id_type)?;
+        Some(self.entity_type.key(id))
+    }
+}
+
+/// What we learned about one kind of trigger
+#[derive(Default)]
+struct KindStats {
+    /// The number of triggers of this kind that we learned from
+    triggers: u32,
+    /// For each pattern, the number of triggers for which it matched an
+    /// entity that was loaded
+    hits: HashMap<Pattern, u32>,
+}
+
+pub(crate) struct Prefetcher {
+    kinds: Mutex<HashMap<Vec<u8>, KindStats>>,
+}
+
+impl Prefetcher {
+    pub fn new() -> Self {
+        Prefetcher {
+            kinds: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Learn from the keys that the handlers for a trigger with `hints`
+    /// loaded
+    pub fn learn(&self, hints: &PrefetchHints, loads: Vec<EntityKey>) {
+        let mut patterns = HashSet::new();
+        for key in loads {
+            let parsed = match ParsedId::new(&key.entity_id) {
+                Some(parsed) => parsed,
+                None => continue,
+            };
+            for (position, value) in hints.values.iter().enumerate() {
+                if let Some(form) = parsed.form(value) {
+                    patterns.insert(Pattern {
+                        entity_type: key.entity_type.cheap_clone(),
+                        position,
+                        form,
+                    });
+                }
+            }
+        }
+
+        let mut kinds = self.kinds.lock().unwrap();
+        let stats = kinds.entry(hints.kind.clone()).or_default();
+        stats.triggers += 1;
+        for pattern in patterns {
+            *stats.hits.entry(pattern).or_default() += 1;
+        }
+        if stats.triggers >= DECAY_TRIGGERS {
+            stats.triggers /= 2;
+            stats.hits.retain(|_, hits| {
+                *hits /= 2;
+                *hits > 0
+            });
+        }
+    }
+
+    /// The keys of the entities that the handlers for triggers with
+    /// `hints` will probably load
+    pub fn keys(&self, hints: impl Iterator<Item = PrefetchHints>) -> BTreeSet<EntityKey> {
+        let kinds = self.kinds.lock().unwrap();
+        let mut keys = BTreeSet::new();
+        for hints in hints {
+            let stats = match kinds.get(&hints.kind) {
+                Some(stats) if stats.triggers >= MIN_TRIGGERS => stats,
+                _ => continue,
+            };
+            let min_hits = stats.triggers as f64 * MIN_HIT_RATE;
+            for (pattern, hits) in &stats.hits {
+                if *hits as f64 >= min_hits {
+                    keys.extend(pattern.key(&hints.values));
+                }
+            }
+        }
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use graph::{prelude::DeploymentHash, schema::InputSchema};
+
+    use super::*;
+
+    const SCHEMA: &str = "
+        type Account @entity { id: ID! }
+        type Token @entity(immutable: true) { id: Bytes! }
+        type Transfer @entity { id: ID! }";
+
+    fn hints(kind: &[u8], values: &[&[u8]]) -> PrefetchHints {
+        PrefetchHints {
+            kind: kind.to_vec(),
+            values: values.iter().map(|value| value.to_vec()).collect(),
+        }
+    }
+
+    #[test]
+    fn decimal_values() {
+        assert_eq!(Some(0), decimal(&[0; 32]));
+        assert_eq!(Some(258), decimal(&[0, 0, 1, 2]));
+        let mut word = [0u8; 32];
+        word[15] = 1;
+        assert_eq!(None, decimal(&word));
+    }
+
+    #[test]
+    fn learn_and_prefetch() {
+        let schema =
+            InputSchema::parse_latest(SCHEMA, DeploymentHash::new("test").unwrap()).unwrap();
+        let account = schema.entity_type("Account").unwrap();
+        let token = schema.entity_type("Token").unwrap();
+        let transfer = schema.entity_type("Transfer").unwrap();
+
+        let prefetcher = Prefetcher::new();
+        let sender: &[u8] = &[1, 2, 10, 11];
+        let token_id: &[u8] = &[0, 0, 1, 0];
+
+        for i in 0..MIN_TRIGGERS {
+            let transfer_hints = || hints(b"transfer", &[b"tx", sender, token_id]);
+            let loads = vec![
+                account.key(Id::String("0x01020a0b".into())),
+                token.key(Id::Bytes(b"tx".as_slice().into())),
+                account.key(Id::String("256".into())),
+                transfer.key(Id::String(format!("other-{}", i).into())),
+            ];
+            // Nothing is prefetched until we have seen enough triggers
+            assert!(prefetcher
+                .keys(std::iter::once(transfer_hints()))
+                .is_empty());
+            prefetcher.learn(&transfer_hints(), loads);
+        }
+
+        let sender: &[u8] = &[5, 6];
+        let token_id: &[u8] = &[0, 7];
+        let keys = prefetcher.keys(
+            vec![
+                hints(b"transfer", &[b"hash", sender, token_id]),
+                hints(b"approval", &[b"hash", sender, token_id]),
+            ]
+            .into_iter(),
+        );
+        let expected = BTreeSet::from([
+            account.key(Id::String("0x0506".into())),
+            account.key(Id::String("7".into())),
+            token.key(Id::Bytes(b"hash".as_slice().into())),
+        ]);
+        assert_eq!(expected, keys);
+    }
+}
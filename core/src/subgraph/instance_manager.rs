@@ -1,4 +1,4 @@
-use crate::polling_monitor::{ArweaveService, IpfsService};
+use crate::polling_monitor::{ArweaveService, HttpService, IpfsService};
 use crate::subgraph::context::{IndexingContext, SubgraphKeepAlive};
 use crate::subgraph::inputs::IndexingInputs;
 use crate::subgraph::loader::load_dynamic_data_sources;
@@ -31,6 +31,7 @@ pub struct SubgraphInstanceManager<S: SubgraphStore> {
     link_resolver: Arc<dyn LinkResolver>,
     ipfs_service: IpfsService,
     arweave_service: ArweaveService,
+    http_service: HttpService,
     static_filters: bool,
     env_vars: Arc<EnvVars>,
 }
@@ -181,6 +182,7 @@ impl<S: SubgraphStore> SubgraphInstanceManager<S> {
         link_resolver: Arc<dyn LinkResolver>,
         ipfs_service: IpfsService,
         arweave_service: ArweaveService,
+        http_service: HttpService,
         static_filters: bool,
     ) -> Self {
         let logger = logger_factory.component_logger("SubgraphInstanceManager", None);
@@ -197,6 +199,7 @@ impl<S: SubgraphStore> SubgraphInstanceManager<S> {
             static_filters,
             env_vars,
             arweave_service,
+            http_service,
         }
     }
 
@@ -382,6 +385,7 @@ impl<S: SubgraphStore> SubgraphInstanceManager<S> {
             &manifest.id,
             self.ipfs_service.clone(),
             self.arweave_service.clone(),
+            self.http_service.clone(),
         );
 
         // Initialize deployment_head with current deployment head. Any sort of trouble in
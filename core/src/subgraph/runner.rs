@@ -1,4 +1,4 @@
-use crate::subgraph::context::IndexingContext;
+use crate::subgraph::context::{IndexingContext, ParallelTriggers};
 use crate::subgraph::error::BlockProcessingError;
 use crate::subgraph::inputs::IndexingInputs;
 use crate::subgraph::state::IndexingState;
@@ -12,7 +12,10 @@ use graph::components::store::{EmptyStore, GetScope, ReadStore, StoredDynamicDat
 use graph::components::subgraph::InstanceDSTemplate;
 use graph::components::{
     store::ModificationsAndCache,
-    subgraph::{MappingError, PoICausalityRegion, ProofOfIndexing, SharedProofOfIndexing},
+    subgraph::{
+        MappingError, PoICausalityRegion, ProofOfIndexing, ProofOfIndexingEvent,
+        SharedProofOfIndexing,
+    },
 };
 use graph::data::store::scalar::Bytes;
 use graph::data::subgraph::{
@@ -75,6 +78,7 @@ where
                 ),
                 entity_lfu_cache: LfuCache::new(),
                 cached_head_ptr: None,
+                parallel_triggers: env_vars.mappings.parallel_triggers,
             },
             logger,
             metrics,
@@ -472,6 +476,15 @@ where
             return Err(BlockProcessingError::Canceled);
         }
 
+        // Check for offchain events and process them. The content of
+        // `file/http` data sources is pinned in the PoI before it is
+        // finished for this block; their entity modifications are added to
+        // the set to be transacted below
+        let offchain_events = self.ctx.offchain_monitor.ready_offchain_events()?;
+        let (offchain_mods, processed_offchain_data_sources, persisted_off_chain_data_sources) =
+            self.handle_offchain_triggers(offchain_events, &block, &proof_of_indexing)
+                .await?;
+
         if let Some(proof_of_indexing) = proof_of_indexing {
             let proof_of_indexing = Arc::try_unwrap(proof_of_indexing).unwrap().into_inner();
             update_proof_of_indexing(
@@ -508,12 +521,6 @@ where
             "accesses" => evict_stats.accesses,
             "evict_time_ms" => evict_stats.evict_time.as_millis());
 
-        // Check for offchain events and process them, including their entity modifications in the
-        // set to be transacted.
-        let offchain_events = self.ctx.offchain_monitor.ready_offchain_events()?;
-        let (offchain_mods, processed_offchain_data_sources, persisted_off_chain_data_sources) =
-            self.handle_offchain_triggers(offchain_events, &block)
-                .await?;
         mods.extend(offchain_mods);
 
         // Put the cache back in the state, asserting that the placeholder cache was not used.
@@ -627,6 +634,54 @@ where
             std::mem::take(&mut self.state.entity_lfu_cache),
         );
 
+        let triggers: Vec<_> = triggers.collect();
+        self.ctx
+            .prepare_triggers(&self.logger, block, &triggers, &mut block_state)
+            .await?;
+
+        if self.state.parallel_triggers {
+            let outcome = self
+                .ctx
+                .process_triggers_in_parallel(
+                    &self.logger,
+                    block,
+                    &triggers,
+                    &self.inputs.store,
+                    &mut block_state,
+                    proof_of_indexing,
+                    causality_region,
+                    &self.inputs.debug_fork,
+                    &self.metrics.subgraph,
+                    self.inputs.instrument,
+                )
+                .await;
+            match outcome {
+                ParallelTriggers::Processed => return Ok(block_state),
+                ParallelTriggers::Dependent => {}
+                ParallelTriggers::Discarded(reason) => {
+                    debug!(self.logger, "Processing triggers in order";
+                        "block_number" => block.number(),
+                        "reason" => reason);
+                }
+                ParallelTriggers::Undeclared {
+                    data_sources,
+                    entity_type,
+                } => {
+                    warn!(self.logger,
+                        "Data sources access an entity type they do not declare, \
+                         processing triggers in order from now on";
+                        "data_sources" => data_sources.join(", "),
+                        "entity_type" => entity_type);
+                    self.state.parallel_triggers = false;
+                }
+            }
+        }
+
+        self.ctx.prefetch(
+            triggers.iter().filter_map(TriggerData::prefetch_hints),
+            &mut block_state,
+        )?;
+
         for trigger in triggers {
             block_state = self
                 .ctx
@@ -993,6 +1048,7 @@ where
         &mut self,
         triggers: Vec<offchain::TriggerData>,
         block: &Arc<C::Block>,
+        block_proof_of_indexing: &SharedProofOfIndexing,
     ) -> Result<
         (
             Vec<EntityModification>,
@@ -1011,8 +1067,17 @@ where
             let schema = ReadStore::input_schema(&self.inputs.store);
             let mut block_state = BlockState::new(EmptyStore::new(schema), LfuCache::new());
 
-            // PoI ignores offchain events.
+            // The content of `file/http` data sources is pinned in the PoI,
+            // but otherwise PoI ignores offchain events.
             // See also: poi-ignores-offchain
+            if let Some(causality_region) = self.ctx.offchain_causality_region(&trigger) {
+                pin_offchain_content(
+                    &self.logger,
+                    &trigger,
+                    &PoICausalityRegion::from_offchain(causality_region),
+                    block_proof_of_indexing,
+                );
+            }
             let proof_of_indexing = None;
             let causality_region = "";
 
@@ -1484,6 +1549,38 @@ where
     }
 }
 
+/// Add the hash of the content of a `file/http` data source to the proof
+/// of indexing. Unlike IPFS and Arweave files, their content is not
+/// addressed by its hash, and indexers that fetched different content for
+/// the same URL must not agree on a PoI. The hash is written to the PoI
+/// stream of the causality region of the data source that handles the
+/// content so that the PoI of the onchain causality region does not depend
+/// on when the content arrived. The handlers of offchain data sources still
+/// do not affect the PoI.
+/// See also: poi-ignores-offchain
+fn pin_offchain_content(
+    logger: &Logger,
+    trigger: &offchain::TriggerData,
+    causality_region: &str,
+    proof_of_indexing: &SharedProofOfIndexing,
+) {
+    let (proof_of_indexing, url) = match (proof_of_indexing, &trigger.source) {
+        (Some(proof_of_indexing), offchain::Source::Http(url)) => (proof_of_indexing, url),
+        _ => return,
+    };
+    let hash = hex::encode(tiny_keccak::keccak256(&trigger.data[..]));
+    let mut proof_of_indexing = proof_of_indexing.borrow_mut();
+    proof_of_indexing.start_handler(causality_region);
+    proof_of_indexing.write(
+        logger,
+        causality_region,
+        &ProofOfIndexingEvent::OffchainContent {
+            source: url.as_str(),
+            hash: &hash,
+        },
+    );
+}
+
 /// Transform the proof of indexing changes into entity updates that will be
 /// inserted when as_modifications is called.
 async fn update_proof_of_indexing(
@@ -1523,8 +1620,9 @@ async fn update_proof_of_indexing(
         // Create the special POI entity key specific to this causality_region
         // There are two things called causality regions here, one is the causality region for
         // the poi which is a string and the PoI entity id. The other is the data source
-        // causality region to which the PoI belongs as an entity. Currently offchain events do
-        // not affect PoI so it is assumed to be `ONCHAIN`.
+        // causality region to which the PoI belongs as an entity. Currently offchain handlers do
+        // not affect PoI, and the content hashes of `file/http` data sources are written by the
+        // block processing itself, so it is assumed to be `ONCHAIN`.
         // See also: poi-ignores-offchain
         let entity_key = entity_cache
             .schema
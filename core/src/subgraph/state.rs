@@ -16,4 +16,8 @@ pub struct IndexingState {
     pub skip_ptr_updates_timer: Instant,
     pub entity_lfu_cache: EntityLfuCache,
     pub cached_head_ptr: Option<BlockPtr>,
+    /// Whether to try processing the triggers of a block in parallel.
+    /// Turned off when data sources access entity types they do not
+    /// declare
+    pub parallel_triggers: bool,
 }
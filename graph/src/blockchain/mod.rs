@@ -21,6 +21,7 @@ use crate::{
         store::{DeploymentCursorTracker, DeploymentLocator, StoredDynamicDataSource},
         subgraph::HostMetrics,
         subgraph::InstanceDSTemplateInfo,
+        subgraph::MappingError,
     },
     data::subgraph::{UnifiedMappingApiVersion, MIN_SPEC_VERSION},
     data_source::{self, DataSourceTemplateInfo},
@@ -277,6 +278,13 @@ pub trait DataSource<C: Blockchain>: 'static + Sized + Send + Sync + Clone {
 
     fn handler_kinds(&self) -> HashSet<&str>;
 
+    /// The entity types that the `entities` key of the mapping in the
+    /// manifest declares. Onchain data sources can access other entity
+    /// types, too, since the declaration has never been enforced for them
+    fn declared_entities(&self) -> &[String] {
+        &[]
+    }
+
     /// Checks if `trigger` matches this data source, and if so decodes it into a `MappingTrigger`.
     /// A return of `Ok(None)` mean the trigger does not match.
     ///
@@ -361,6 +369,28 @@ pub trait TriggerData {
     /// When this does return `Some`, make sure that the `DataSource::address` of matching data
     /// sources is equal to the addresssed returned here.
     fn address_match(&self) -> Option<&[u8]>;
+
+    /// The values of this trigger from which handlers commonly derive the
+    /// ids of the entities they load, used to prefetch entities before
+    /// the handlers run. Returning `None` is always correct.
+    fn prefetch_hints(&self) -> Option<PrefetchHints> {
+        None
+    }
+}
+
+/// Values of a trigger like addresses, hashes, and parameters from which
+/// handlers commonly derive the ids of the entities they load.
+///
+/// Handlers for the same `kind` of trigger tend to load entities whose ids
+/// are derived from the values at the same position in `values`, which
+/// makes it possible to learn which entities the handlers for a trigger
+/// will load. The position of a value must therefore only depend on the
+/// `kind` and not on the values themselves.
+pub struct PrefetchHints {
+    /// Identifies triggers whose values have the same layout, e.g., the
+    /// signature of an event
+    pub kind: Vec<u8>,
+    pub values: Vec<Vec<u8>>,
 }
 
 pub trait MappingTriggerTrait {
@@ -375,6 +405,52 @@ pub struct HostFnCtx<'a> {
     pub heap: &'a mut dyn AscHeap,
     pub gas: GasCounter,
     pub metrics: Arc<HostMetrics>,
+    pub declared_calls: Arc<DeclaredCalls>,
+}
+
+/// The results of the contract calls that the manifest declares for the
+/// handlers of a block. They are made for all handlers of the block at
+/// once before any of them runs, and host functions use them instead of
+/// making the same calls again. Calls are identified by the address of the
+/// contract and the encoded call; a result of `None` means that the call
+/// reverted
+#[derive(Clone, Debug, Default)]
+pub struct DeclaredCalls {
+    results: HashMap<ethabi::Address, HashMap<Vec<u8>, Option<Vec<ethabi::Token>>>>,
+}
+
+impl DeclaredCalls {
+    pub fn insert(
+        &mut self,
+        address: ethabi::Address,
+        call_data: Vec<u8>,
+        result: Option<Vec<ethabi::Token>>,
+    ) {
+        self.results
+            .entry(address)
+            .or_default()
+            .insert(call_data, result);
+    }
+
+    /// The result of calling `address` with `call_data`, or `None` if the
+    /// call was not declared
+    pub fn get(
+        &self,
+        address: ethabi::Address,
+        call_data: &[u8],
+    ) -> Option<&Option<Vec<ethabi::Token>>> {
+        self.results
+            .get(&address)
+            .and_then(|results| results.get(call_data))
+    }
+
+    pub fn len(&self) -> usize {
+        self.results.values().map(HashMap::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
 }
 
 /// Host fn that receives one u32 argument and returns an u32.
@@ -394,8 +470,29 @@ impl CheapClone for HostFn {
     }
 }
 
+#[async_trait]
 pub trait RuntimeAdapter<C: Blockchain>: Send + Sync {
     fn host_fns(&self, ds: &C::DataSource) -> Result<Vec<HostFn>, Error>;
+
+    /// Whether `prepare_triggers` needs to see the triggers that `ds`
+    /// handles. Triggers are only decoded ahead of time for such data
+    /// sources
+    fn prepares_triggers(&self, _ds: &C::DataSource) -> bool {
+        false
+    }
+
+    /// Called once for each block, before any handler runs, with the
+    /// triggers of all data sources for which `prepares_triggers` is true,
+    /// for example to make the calls to the chain that the manifest
+    /// declares for all handlers of the block with one request
+    async fn prepare_triggers(
+        &self,
+        _logger: &Logger,
+        _block_ptr: &BlockPtr,
+        _triggers: &[(&C::DataSource, &C::MappingTrigger)],
+    ) -> Result<DeclaredCalls, MappingError> {
+        Ok(DeclaredCalls::default())
+    }
 }
 
 pub trait NodeCapabilities<C: Blockchain> {
@@ -359,17 +359,6 @@ impl BlockTime {
     pub fn as_secs_since_epoch(&self) -> i64 {
         self.0.timestamp()
     }
-
-    /// Return the number of the last bucket that starts before `self`
-    /// assuming buckets have the given `length`
-    pub(crate) fn bucket(&self, length: Duration) -> usize {
-        // Treat any time before the epoch as zero, i.e., the epoch; in
-        // practice, we will only deal with block times that are pretty far
-        // after the epoch
-        let ts = self.0.timestamp_millis().max(0);
-        let nr = ts as u128 / length.as_millis();
-        usize::try_from(nr).unwrap()
-    }
 }
 
 impl From<Duration> for BlockTime {
@@ -1,7 +1,10 @@
 use futures::prelude::*;
 
 use crate::data::query::QueryResults;
-use crate::data::query::{Query, QueryTarget};
+use crate::data::query::{
+    FeedCursor, FeedEvent, PersistedQueries, Query, QueryExecutionError, QueryPlan, QueryTarget,
+    SqlQueryObject, SqlQueryReq,
+};
 use crate::data::subscription::{Subscription, SubscriptionError, SubscriptionResult};
 use crate::prelude::DeploymentHash;
 
@@ -41,6 +44,39 @@ pub trait GraphQlRunner: Send + Sync + 'static {
         target: QueryTarget,
     ) -> Result<SubscriptionResult, SubscriptionError>;
 
+    /// Runs a SQL query against the tables of the deployment for `target`
+    async fn run_sql_query(
+        self: Arc<Self>,
+        req: SqlQueryReq,
+        target: QueryTarget,
+    ) -> Result<Vec<SqlQueryObject>, QueryExecutionError>;
+
+    /// Returns the next events in the entity change feed of the
+    /// deployment for `target` after `cursor`
+    async fn entity_feed(
+        self: Arc<Self>,
+        target: QueryTarget,
+        cursor: Option<FeedCursor>,
+    ) -> Result<Vec<FeedEvent>, QueryExecutionError>;
+
+    /// Computes how a GraphQL query would be executed without running it:
+    /// its complexity and the SQL queries for the fields that are fetched
+    /// from the store. If `explain` is `true`, the plan includes the plans
+    /// that Postgres chooses for those queries
+    async fn explain_query(
+        self: Arc<Self>,
+        query: Query,
+        target: QueryTarget,
+        explain: bool,
+    ) -> Result<QueryPlan, Vec<QueryExecutionError>>;
+
+    /// Returns the persisted queries registered for the deployment for
+    /// `target` and whether it only executes those
+    async fn persisted_queries(
+        self: Arc<Self>,
+        target: QueryTarget,
+    ) -> Result<Arc<PersistedQueries>, QueryExecutionError>;
+
     fn metrics(&self) -> Arc<dyn GraphQLMetrics>;
 }
 
@@ -0,0 +1,511 @@
+//! Reading CAR (content addressable archive) files.
+//!
+//! A CAR file contains the blocks of one or more IPFS DAGs. `CarArchive`
+//! reads all blocks of a CARv1 or CARv2 file into memory, checking that
+//! each block matches the hash in its CID, and reads UnixFS files and
+//! directories from them so that the content of IPFS files can be used
+//! without an IPFS node.
+use std::collections::{HashMap, HashSet};
+use std::io::Cursor;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Error};
+use cid::{Cid, Version};
+use prost::Message;
+use sha2::{Digest, Sha256};
+
+/// The multicodec of blocks that contain the raw content of a file
+const RAW: u64 = 0x55;
+/// The multicodec of UnixFS nodes
+const DAG_PB: u64 = 0x70;
+
+/// The multihashes that we can verify
+const IDENTITY: u64 = 0x00;
+const SHA2_256: u64 = 0x12;
+
+/// A CARv2 file starts with the length of the pragma `{"version": 2}`
+/// followed by the pragma in DAG-CBOR
+const CAR_V2_PRAGMA: [u8; 11] = [
+    0x0a, 0xa1, 0x67, 0x76, 0x65, 0x72, 0x73, 0x69, 0x6f, 0x6e, 0x02,
+];
+/// The length of the CARv2 header that follows the pragma
+const CAR_V2_HEADER_LEN: usize = 40;
+
+/// How deeply the nodes of a UnixFS DAG can be nested
+const MAX_DEPTH: usize = 64;
+
+/// The UnixFS node types that we support
+const UNIXFS_RAW: i32 = 0;
+const UNIXFS_DIRECTORY: i32 = 1;
+const UNIXFS_FILE: i32 = 2;
+
+/// A dag-pb node
+#[derive(Clone, PartialEq, ::prost::Message)]
+struct PbNode {
+    #[prost(bytes = "vec", optional, tag = "1")]
+    data: Option<Vec<u8>>,
+    #[prost(message, repeated, tag = "2")]
+    links: Vec<PbLink>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+struct PbLink {
+    #[prost(bytes = "vec", optional, tag = "1")]
+    hash: Option<Vec<u8>>,
+    #[prost(string, optional, tag = "2")]
+    name: Option<String>,
+}
+
+/// The UnixFS data in the `data` of a dag-pb node
+#[derive(Clone, PartialEq, ::prost::Message)]
+struct UnixFsData {
+    #[prost(int32, optional, tag = "1")]
+    kind: Option<i32>,
+    #[prost(bytes = "vec", optional, tag = "2")]
+    data: Option<Vec<u8>>,
+}
+
+enum Node {
+    /// A file, or a part of one, with its data and the CIDs of the blocks
+    /// with the rest of its content
+    File(Vec<u8>, Vec<Cid>),
+    /// A directory with the names and CIDs of its entries
+    Directory(Vec<(String, Cid)>),
+}
+
+/// The verified blocks of a CAR file
+#[derive(Default)]
+pub struct CarArchive {
+    blocks: HashMap<Cid, Vec<u8>>,
+}
+
+impl CarArchive {
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let data = std::fs::read(path)
+            .with_context(|| format!("failed to read CAR file {}", path.display()))?;
+        Self::parse(&data).with_context(|| format!("invalid CAR file {}", path.display()))
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        if !data.starts_with(&CAR_V2_PRAGMA) {
+            return Self::parse_v1(data);
+        }
+
+        // A CARv2 file wraps a CARv1 payload whose position is given in
+        // the header
+        let header = data
+            .get(CAR_V2_PRAGMA.len()..CAR_V2_PRAGMA.len() + CAR_V2_HEADER_LEN)
+            .ok_or_else(|| anyhow!("the CARv2 header is truncated"))?;
+        let offset = u64::from_le_bytes(header[16..24].try_into().unwrap());
+        let size = u64::from_le_bytes(header[24..32].try_into().unwrap());
+        let payload = usize::try_from(offset)
+            .ok()
+            .zip(usize::try_from(size).ok())
+            .and_then(|(offset, size)| data.get(offset..offset.checked_add(size)?))
+            .ok_or_else(|| anyhow!("the CARv2 payload is out of bounds"))?;
+        Self::parse_v1(payload)
+    }
+
+    /// Read the blocks in the directory tree at `path`. Files with the
+    /// extension `.car` are read as CAR files, and files that are named
+    /// after a CID contain the block with that CID. Other files are ignored
+    pub fn read_dir(path: &Path) -> Result<Self, Error> {
+        let mut archive = CarArchive::default();
+        let entries = std::fs::read_dir(path)
+            .with_context(|| format!("failed to read directory {}", path.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_dir() {
+                archive.extend(Self::read_dir(&path)?);
+            } else if path.extension().map_or(false, |ext| ext == "car") {
+                archive.extend(Self::read(&path)?);
+            } else if let Some(cid) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| Cid::try_from(name).ok())
+            {
+                let block = std::fs::read(&path)
+                    .with_context(|| format!("failed to read block {}", path.display()))?;
+                verify(&cid, &block)?;
+                archive.blocks.insert(cid, block);
+            }
+        }
+        Ok(archive)
+    }
+
+    /// Add the blocks of `other` to this archive
+    pub fn extend(&mut self, other: CarArchive) {
+        self.blocks.extend(other.blocks);
+    }
+
+    fn parse_v1(mut data: &[u8]) -> Result<Self, Error> {
+        // The header is a DAG-CBOR map that lists the roots of the archive.
+        // We do not need it since `roots` finds them from the blocks
+        let header_len = read_len(&mut data)?;
+        if header_len > data.len() {
+            bail!("the CAR header is truncated");
+        }
+        data = &data[header_len..];
+
+        let mut blocks = HashMap::new();
+        while !data.is_empty() {
+            let len = read_len(&mut data)?;
+            if len > data.len() {
+                bail!("the last block is truncated");
+            }
+            let (section, rest) = data.split_at(len);
+            data = rest;
+
+            let mut cursor = Cursor::new(section);
+            let cid = Cid::read_bytes(&mut cursor).context("invalid CID")?;
+            let block = &section[cursor.position() as usize..];
+            verify(&cid, block)?;
+            blocks.insert(cid, block.to_vec());
+        }
+        Ok(CarArchive { blocks })
+    }
+
+    /// The number of blocks in the archive
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// The block with `cid`, or `None` if the archive does not contain it
+    pub fn block(&self, cid: &Cid) -> Option<&[u8]> {
+        if let Some(block) = self.blocks.get(cid) {
+            return Some(block.as_slice());
+        }
+        // A CIDv0 and a CIDv1 with the dag-pb codec address the same block
+        let other = match cid.version() {
+            Version::V0 => Cid::new_v1(DAG_PB, *cid.hash()),
+            Version::V1 if cid.codec() == DAG_PB => Cid::new_v0(*cid.hash()).ok()?,
+            Version::V1 => return None,
+        };
+        self.blocks.get(&other).map(Vec::as_slice)
+    }
+
+    fn node(&self, cid: &Cid) -> Result<Node, Error> {
+        let block = self
+            .block(cid)
+            .ok_or_else(|| anyhow!("block {} is not in the archive", cid))?;
+        match cid.codec() {
+            RAW => Ok(Node::File(block.to_vec(), vec![])),
+            DAG_PB => {
+                let node = PbNode::decode(block)
+                    .with_context(|| format!("block {} is not a dag-pb node", cid))?;
+                let unixfs = UnixFsData::decode(node.data.as_deref().unwrap_or_default())
+                    .with_context(|| format!("block {} is not a UnixFS node", cid))?;
+                let links = node
+                    .links
+                    .into_iter()
+                    .map(|link| {
+                        let hash = link.hash.unwrap_or_default();
+                        let child = Cid::try_from(hash.as_slice())
+                            .with_context(|| format!("block {} has an invalid link", cid))?;
+                        Ok((link.name.unwrap_or_default(), child))
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+                match unixfs.kind {
+                    Some(UNIXFS_RAW) | Some(UNIXFS_FILE) => Ok(Node::File(
+                        unixfs.data.unwrap_or_default(),
+                        links.into_iter().map(|(_, child)| child).collect(),
+                    )),
+                    Some(UNIXFS_DIRECTORY) => Ok(Node::Directory(links)),
+                    Some(kind) => bail!("block {} has the unsupported UnixFS type {}", cid, kind),
+                    None => bail!("block {} does not have a UnixFS type", cid),
+                }
+            }
+            codec => bail!("block {} has the unsupported codec 0x{:x}", cid, codec),
+        }
+    }
+
+    fn read_file(&self, cid: &Cid, depth: usize, content: &mut Vec<u8>) -> Result<(), Error> {
+        if depth > MAX_DEPTH {
+            bail!("the DAG of file {} is nested too deeply", cid);
+        }
+        match self.node(cid)? {
+            Node::File(data, children) => {
+                content.extend_from_slice(&data);
+                for child in &children {
+                    self.read_file(child, depth + 1, content)?;
+                }
+                Ok(())
+            }
+            Node::Directory(_) => bail!("{} is a directory", cid),
+        }
+    }
+
+    /// The CID of the file or directory at `path`, which is a CID that is
+    /// optionally followed by a path within the directory with that CID,
+    /// like `Qm../images/1.png`. Return `None` if the archive does not
+    /// contain it
+    pub fn resolve(&self, path: &str) -> Result<Option<Cid>, Error> {
+        let mut segments = path
+            .trim_start_matches("/ipfs/")
+            .split('/')
+            .filter(|segment| !segment.is_empty());
+        let root = segments
+            .next()
+            .ok_or_else(|| anyhow!("the path is empty"))?;
+        let mut cid = Cid::try_from(root).with_context(|| format!("invalid CID {}", root))?;
+        for segment in segments {
+            if self.block(&cid).is_none() {
+                return Ok(None);
+            }
+            let entries = match self.node(&cid)? {
+                Node::Directory(entries) => entries,
+                Node::File(..) => bail!("{} in {} is not a directory", cid, path),
+            };
+            match entries.into_iter().find(|(name, _)| name == segment) {
+                Some((_, entry)) => cid = entry,
+                None => return Ok(None),
+            }
+        }
+        Ok(self.block(&cid).map(|_| cid))
+    }
+
+    /// The content of the file at `path`, or `None` if the archive does not
+    /// contain it. See `resolve` for the form of `path`
+    pub fn cat(&self, path: &str) -> Result<Option<Vec<u8>>, Error> {
+        let cid = match self.resolve(path)? {
+            Some(cid) => cid,
+            None => return Ok(None),
+        };
+        let mut content = Vec::new();
+        self.read_file(&cid, 0, &mut content)?;
+        Ok(Some(content))
+    }
+
+    /// The CIDs of the UnixFS blocks that no other block in the archive
+    /// links to
+    pub fn roots(&self) -> Vec<Cid> {
+        let linked: HashSet<Cid> = self
+            .blocks
+            .iter()
+            .filter(|(cid, _)| cid.codec() == DAG_PB)
+            .filter_map(|(_, block)| PbNode::decode(block.as_slice()).ok())
+            .flat_map(|node| node.links)
+            .filter_map(|link| Cid::try_from(link.hash?.as_slice()).ok())
+            .collect();
+        let mut roots: Vec<_> = self
+            .blocks
+            .keys()
+            .filter(|cid| cid.codec() == RAW || cid.codec() == DAG_PB)
+            .filter(|cid| !linked.contains(cid))
+            .cloned()
+            .collect();
+        roots.sort_by_key(|cid| cid.to_string());
+        roots
+    }
+
+    /// All files in the archive together with the path under which they
+    /// are read from IPFS: the CID for files that are a root of the
+    /// archive, and the CID of the root followed by the path within it for
+    /// files in directories
+    pub fn files(&self) -> Result<Vec<(String, Vec<u8>)>, Error> {
+        let mut files = Vec::new();
+        for root in self.roots() {
+            self.collect_files(&root, root.to_string(), 0, &mut files)?;
+        }
+        Ok(files)
+    }
+
+    fn collect_files(
+        &self,
+        cid: &Cid,
+        path: String,
+        depth: usize,
+        files: &mut Vec<(String, Vec<u8>)>,
+    ) -> Result<(), Error> {
+        if depth > MAX_DEPTH {
+            bail!("the directory {} is nested too deeply", path);
+        }
+        match self.node(cid)? {
+            Node::Directory(entries) => {
+                for (name, entry) in entries {
+                    self.collect_files(&entry, format!("{}/{}", path, name), depth + 1, files)?;
+                }
+            }
+            Node::File(..) => {
+                let mut content = Vec::new();
+                self.read_file(cid, 0, &mut content)?;
+                files.push((path, content));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_len(data: &mut &[u8]) -> Result<usize, Error> {
+    let len = prost::encoding::decode_varint(data)?;
+    usize::try_from(len).map_err(|_| anyhow!("the length {} is too large", len))
+}
+
+/// Check that `block` has the hash in `cid`
+fn verify(cid: &Cid, block: &[u8]) -> Result<(), Error> {
+    let hash = cid.hash();
+    let matches = match hash.code() {
+        IDENTITY => hash.digest() == block,
+        SHA2_256 => hash.digest() == Sha256::digest(block).as_slice(),
+        code => bail!(
+            "block {} uses the hash function 0x{:x} which can not be verified",
+            cid,
+            code
+        ),
+    };
+    if !matches {
+        bail!("block {} does not match its hash", cid);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use cid::multihash::Multihash;
+
+    use super::*;
+
+    fn cid(codec: u64, block: &[u8]) -> Cid {
+        let digest = Sha256::digest(block);
+        Cid::new_v1(codec, Multihash::wrap(SHA2_256, &digest).unwrap())
+    }
+
+    fn dag_pb(kind: i32, data: Option<&[u8]>, links: Vec<(&str, Cid)>) -> Vec<u8> {
+        let unixfs = UnixFsData {
+            kind: Some(kind),
+            data: data.map(|data| data.to_vec()),
+        };
+        PbNode {
+            data: Some(unixfs.encode_to_vec()),
+            links: links
+                .into_iter()
+                .map(|(name, cid)| PbLink {
+                    hash: Some(cid.to_bytes()),
+                    name: Some(name.to_string()),
+                })
+                .collect(),
+        }
+        .encode_to_vec()
+    }
+
+    fn car(blocks: &[(Cid, Vec<u8>)]) -> Vec<u8> {
+        // `{"roots": [], "version": 1}` in DAG-CBOR
+        let header = b"\xa2eroots\x80gversion\x01";
+        let mut car = Vec::new();
+        prost::encoding::encode_varint(header.len() as u64, &mut car);
+        car.extend_from_slice(header);
+        for (cid, block) in blocks {
+            let cid = cid.to_bytes();
+            prost::encoding::encode_varint((cid.len() + block.len()) as u64, &mut car);
+            car.extend_from_slice(&cid);
+            car.extend_from_slice(block);
+        }
+        car
+    }
+
+    /// A directory with the files `a.txt` and `b.txt`, the latter split
+    /// into two blocks
+    fn blocks() -> (Cid, Vec<(Cid, Vec<u8>)>) {
+        let hello = b"hello".to_vec();
+        let world = b" world".to_vec();
+        let hello_cid = cid(RAW, &hello);
+        let world_cid = cid(RAW, &world);
+        let file = dag_pb(UNIXFS_FILE, None, vec![("", hello_cid), ("", world_cid)]);
+        let file_cid = cid(DAG_PB, &file);
+        let dir = dag_pb(
+            UNIXFS_DIRECTORY,
+            None,
+            vec![("a.txt", hello_cid), ("b.txt", file_cid)],
+        );
+        let dir_cid = cid(DAG_PB, &dir);
+        let blocks = vec![
+            (dir_cid, dir),
+            (file_cid, file),
+            (hello_cid, hello),
+            (world_cid, world),
+        ];
+        (dir_cid, blocks)
+    }
+
+    #[test]
+    fn read_files() {
+        let (dir, blocks) = blocks();
+        let archive = CarArchive::parse(&car(&blocks)).unwrap();
+
+        assert_eq!(4, archive.len());
+        assert_eq!(vec![dir], archive.roots());
+        assert_eq!(
+            vec![
+                (format!("{}/a.txt", dir), b"hello".to_vec()),
+                (format!("{}/b.txt", dir), b"hello world".to_vec()),
+            ],
+            archive.files().unwrap()
+        );
+
+        let cat = |path: String| archive.cat(&path).unwrap();
+        assert_eq!(
+            Some(b"hello world".to_vec()),
+            cat(format!("/ipfs/{}/b.txt", dir))
+        );
+        assert_eq!(Some(b"hello".to_vec()), cat(blocks[2].0.to_string()));
+        assert_eq!(None, cat(format!("{}/c.txt", dir)));
+        assert!(archive.cat(&dir.to_string()).is_err());
+        assert!(archive.cat(&format!("{}/a.txt/x", dir)).is_err());
+    }
+
+    #[test]
+    fn read_car_v2() {
+        let (dir, blocks) = blocks();
+        let payload = car(&blocks);
+        let offset = (CAR_V2_PRAGMA.len() + CAR_V2_HEADER_LEN) as u64;
+
+        let mut car = CAR_V2_PRAGMA.to_vec();
+        car.extend_from_slice(&[0; 16]);
+        car.extend_from_slice(&offset.to_le_bytes());
+        car.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+        car.extend_from_slice(&0u64.to_le_bytes());
+        car.extend_from_slice(&payload);
+
+        let archive = CarArchive::parse(&car).unwrap();
+        assert_eq!(vec![dir], archive.roots());
+    }
+
+    #[test]
+    fn read_block_dir() {
+        let (dir, blocks) = blocks();
+        let tmp = std::env::temp_dir().join(format!("graph-car-dir-{}", std::process::id()));
+        std::fs::remove_dir_all(&tmp).ok();
+        std::fs::create_dir_all(tmp.join("blocks")).unwrap();
+        // The directory and the file in one CAR file, their content as
+        // separate blocks
+        std::fs::write(tmp.join("dir.car"), car(&blocks[..2])).unwrap();
+        for (cid, block) in &blocks[2..] {
+            std::fs::write(tmp.join("blocks").join(cid.to_string()), block).unwrap();
+        }
+        std::fs::write(tmp.join("README"), b"not a block").unwrap();
+
+        let archive = CarArchive::read_dir(&tmp).unwrap();
+        assert_eq!(4, archive.len());
+        assert_eq!(
+            Some(b"hello world".to_vec()),
+            archive.cat(&format!("{}/b.txt", dir)).unwrap()
+        );
+
+        std::fs::write(tmp.join("blocks").join(blocks[2].0.to_string()), b"hellO").unwrap();
+        assert!(CarArchive::read_dir(&tmp).is_err());
+
+        std::fs::remove_dir_all(&tmp).unwrap();
+    }
+
+    #[test]
+    fn reject_tampered_blocks() {
+        let (_, mut blocks) = blocks();
+        blocks[2].1 = b"hellO".to_vec();
+        let err = CarArchive::parse(&car(&blocks)).err().unwrap();
+        assert!(err.to_string().contains("does not match its hash"));
+    }
+}
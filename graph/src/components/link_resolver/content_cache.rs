@@ -0,0 +1,338 @@
+//! A cache for the content of IPFS and Arweave files on disk.
+//!
+//! IPFS and Arweave files are addressed by their content, which makes it
+//! safe to keep their content around for as long as we like. The cache
+//! keeps each file in its own file on disk, named after the hash of the
+//! path under which it is read, and removes the files that were used least
+//! recently when the size of all files exceeds the size of the cache.
+//! Since the cache is shared by `ipfs.cat`, `ipfs.map` and file data
+//! sources, and survives restarts, files only need to be fetched again
+//! when they were evicted.
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use anyhow::{Context, Error};
+use slog::{warn, Logger};
+
+use crate::env::EnvVars;
+use crate::prelude::CheapClone;
+
+/// Where the content comes from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContentKind {
+    Ipfs,
+    Arweave,
+}
+
+impl ContentKind {
+    const ALL: [ContentKind; 2] = [ContentKind::Ipfs, ContentKind::Arweave];
+
+    fn dir(&self) -> &'static str {
+        match self {
+            ContentKind::Ipfs => "ipfs",
+            ContentKind::Arweave => "arweave",
+        }
+    }
+}
+
+#[derive(Default)]
+struct CacheIndex {
+    /// The size of each file and the tick at which it was last used
+    files: HashMap<String, (u64, u64)>,
+    /// The files ordered by the tick at which they were last used
+    lru: BTreeMap<u64, String>,
+    /// The size of all files
+    size: u64,
+    tick: u64,
+}
+
+impl CacheIndex {
+    /// Mark `name` as used and return whether it is in the cache
+    fn touch(&mut self, name: &str) -> bool {
+        self.tick += 1;
+        match self.files.get_mut(name) {
+            Some((_, used)) => {
+                self.lru.remove(used);
+                *used = self.tick;
+                self.lru.insert(self.tick, name.to_string());
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn insert(&mut self, name: String, size: u64) {
+        self.remove(&name);
+        self.tick += 1;
+        self.size += size;
+        self.lru.insert(self.tick, name.clone());
+        self.files.insert(name, (size, self.tick));
+    }
+
+    fn remove(&mut self, name: &str) {
+        if let Some((size, used)) = self.files.remove(name) {
+            self.lru.remove(&used);
+            self.size -= size;
+        }
+    }
+
+    /// Remove the files that were used least recently from the index until
+    /// their size is at most `max_size` and return their names
+    fn evict(&mut self, max_size: u64) -> Vec<String> {
+        let mut evicted = Vec::new();
+        while self.size > max_size {
+            let name = match self.lru.values().next() {
+                Some(name) => name.clone(),
+                None => break,
+            };
+            self.remove(&name);
+            evicted.push(name);
+        }
+        evicted
+    }
+}
+
+/// Distinguishes the temporary files of concurrent writes
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+pub struct ContentCache {
+    logger: Logger,
+    dir: PathBuf,
+    max_size: u64,
+    index: Mutex<CacheIndex>,
+}
+
+impl ContentCache {
+    /// Open the cache in `dir`, creating the directory if it does not
+    /// exist. Files that are already in `dir` are used in the order in
+    /// which they were written
+    pub fn open(logger: &Logger, dir: &Path, max_size: u64) -> Result<Self, Error> {
+        let mut existing = Vec::new();
+        for kind in ContentKind::ALL {
+            let kind_dir = dir.join(kind.dir());
+            fs::create_dir_all(&kind_dir).with_context(|| {
+                format!("failed to create content cache {}", kind_dir.display())
+            })?;
+            for entry in fs::read_dir(&kind_dir)? {
+                let entry = entry?;
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                if file_name.ends_with(".tmp") {
+                    // Left behind by a write that was interrupted
+                    fs::remove_file(entry.path()).ok();
+                    continue;
+                }
+                let metadata = entry.metadata()?;
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                let name = format!("{}/{}", kind.dir(), file_name);
+                existing.push((modified, name, metadata.len()));
+            }
+        }
+        existing.sort();
+
+        let cache = ContentCache {
+            logger: logger.clone(),
+            dir: dir.to_path_buf(),
+            max_size,
+            index: Mutex::new(CacheIndex::default()),
+        };
+        {
+            let mut index = cache.index.lock().unwrap();
+            for (_, name, size) in existing {
+                index.insert(name, size);
+            }
+            let evicted = index.evict(max_size);
+            cache.remove_files(evicted);
+        }
+        Ok(cache)
+    }
+
+    /// Open the cache configured with `GRAPH_CONTENT_CACHE_DIR`, or return
+    /// `None` if it is not set
+    pub fn from_env(logger: &Logger, env_vars: &EnvVars) -> Result<Option<Arc<Self>>, Error> {
+        env_vars
+            .mappings
+            .content_cache_dir
+            .as_ref()
+            .map(|dir| Self::open(logger, dir, env_vars.mappings.content_cache_size).map(Arc::new))
+            .transpose()
+    }
+
+    /// The name of the file with the content for `key`
+    fn name(kind: ContentKind, key: &str) -> String {
+        let hash = tiny_keccak::keccak256(key.as_bytes());
+        format!("{}/{}", kind.dir(), hex::encode(hash))
+    }
+
+    fn remove_files(&self, names: Vec<String>) {
+        for name in names {
+            if let Err(e) = fs::remove_file(self.dir.join(&name)) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    warn!(self.logger, "Failed to remove file from content cache";
+                        "file" => &name, "error" => e.to_string());
+                }
+            }
+        }
+    }
+
+    fn get_blocking(&self, kind: ContentKind, key: &str) -> Option<Vec<u8>> {
+        let name = Self::name(kind, key);
+        let known = self.index.lock().unwrap().touch(&name);
+        match fs::read(self.dir.join(&name)) {
+            Ok(data) if known => Some(data),
+            Ok(data) => {
+                // The file was added by another process, e.g., `graphman
+                // content-cache seed`, after we opened the cache
+                let mut index = self.index.lock().unwrap();
+                index.insert(name, data.len() as u64);
+                let evicted = index.evict(self.max_size);
+                drop(index);
+                self.remove_files(evicted);
+                Some(data)
+            }
+            Err(e) if !known && e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => {
+                // The file might have been evicted since we looked it up
+                if e.kind() != io::ErrorKind::NotFound {
+                    warn!(self.logger, "Failed to read file from content cache";
+                        "key" => key, "error" => e.to_string());
+                }
+                self.index.lock().unwrap().remove(&name);
+                None
+            }
+        }
+    }
+
+    fn insert_blocking(&self, kind: ContentKind, key: &str, data: &[u8]) -> bool {
+        let size = data.len() as u64;
+        if size > self.max_size {
+            return false;
+        }
+        let name = Self::name(kind, key);
+        if self.index.lock().unwrap().touch(&name) {
+            return true;
+        }
+
+        let path = self.dir.join(&name);
+        let tmp = path.with_extension(format!(
+            "{}.tmp",
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        if let Err(e) = fs::write(&tmp, data).and_then(|()| fs::rename(&tmp, &path)) {
+            warn!(self.logger, "Failed to write file to content cache";
+                "key" => key, "error" => e.to_string());
+            fs::remove_file(&tmp).ok();
+            return false;
+        }
+
+        let mut index = self.index.lock().unwrap();
+        index.insert(name, size);
+        let evicted = index.evict(self.max_size);
+        drop(index);
+        self.remove_files(evicted);
+        true
+    }
+
+    /// The content for `key` if it is in the cache
+    pub async fn get(self: &Arc<Self>, kind: ContentKind, key: &str) -> Option<Vec<u8>> {
+        let cache = self.cheap_clone();
+        let key = key.to_string();
+        crate::spawn_blocking_allow_panic(move || cache.get_blocking(kind, &key))
+            .await
+            .ok()
+            .flatten()
+    }
+
+    /// Add `data` as the content for `key` to the cache, evicting other
+    /// files if needed. Return `false` if the data was not added because it
+    /// is larger than the cache or could not be written
+    pub async fn insert(self: &Arc<Self>, kind: ContentKind, key: &str, data: Vec<u8>) -> bool {
+        let cache = self.cheap_clone();
+        let key = key.to_string();
+        crate::spawn_blocking_allow_panic(move || cache.insert_blocking(kind, &key, &data))
+            .await
+            .unwrap_or(false)
+    }
+
+    /// The number of files in the cache and their size
+    pub fn usage(&self) -> (usize, u64) {
+        let index = self.index.lock().unwrap();
+        (index.files.len(), index.size)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use slog::o;
+
+    use super::*;
+
+    fn tmp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "graph-content-cache-{}-{}",
+            name,
+            std::process::id()
+        ));
+        fs::remove_dir_all(&dir).ok();
+        dir
+    }
+
+    #[tokio::test]
+    async fn insert_get_and_evict() {
+        let logger = Logger::root(slog::Discard, o!());
+        let dir = tmp_dir("evict");
+        let cache = Arc::new(ContentCache::open(&logger, &dir, 10).unwrap());
+
+        assert!(cache.insert(ContentKind::Ipfs, "a", b"aaaa".to_vec()).await);
+        assert!(
+            cache
+                .insert(ContentKind::Arweave, "a", b"bbbb".to_vec())
+                .await
+        );
+        assert!(!cache.insert(ContentKind::Ipfs, "big", vec![0; 11]).await);
+        assert_eq!(
+            Some(b"aaaa".to_vec()),
+            cache.get(ContentKind::Ipfs, "a").await
+        );
+        assert_eq!(
+            Some(b"bbbb".to_vec()),
+            cache.get(ContentKind::Arweave, "a").await
+        );
+        assert_eq!(None, cache.get(ContentKind::Ipfs, "b").await);
+
+        // The Arweave file was used least recently and is evicted
+        cache.get(ContentKind::Ipfs, "a").await;
+        assert!(cache.insert(ContentKind::Ipfs, "c", b"cccc".to_vec()).await);
+        assert_eq!(None, cache.get(ContentKind::Arweave, "a").await);
+        assert_eq!((2, 8), cache.usage());
+
+        // The files survive reopening the cache
+        drop(cache);
+        let cache = Arc::new(ContentCache::open(&logger, &dir, 10).unwrap());
+        assert_eq!((2, 8), cache.usage());
+        assert_eq!(
+            Some(b"cccc".to_vec()),
+            cache.get(ContentKind::Ipfs, "c").await
+        );
+
+        // Files that were added by another process are found
+        let other = Arc::new(ContentCache::open(&logger, &dir, 10).unwrap());
+        assert!(other.insert(ContentKind::Ipfs, "d", b"dd".to_vec()).await);
+        assert_eq!(
+            Some(b"dd".to_vec()),
+            cache.get(ContentKind::Ipfs, "d").await
+        );
+        assert_eq!((3, 10), cache.usage());
+
+        // Reopening with a smaller size evicts files
+        drop(cache);
+        let cache = ContentCache::open(&logger, &dir, 4).unwrap();
+        assert!(cache.usage().1 <= 4);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
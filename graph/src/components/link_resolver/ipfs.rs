@@ -8,7 +8,7 @@ use async_trait::async_trait;
 use bytes::BytesMut;
 use futures::{stream::poll_fn, try_ready};
 use futures::{Async, Poll};
-use futures03::stream::FuturesUnordered;
+use futures03::stream::{BoxStream, FuturesUnordered};
 use lru_time_cache::LruCache;
 use serde_json::Value;
 
@@ -17,6 +17,8 @@ use crate::{
     prelude::{LinkResolver as LinkResolverTrait, *},
 };
 
+use super::{ContentCache, ContentKind};
+
 fn retry_policy<I: Send + Sync>(
     always_retry: bool,
     op: &'static str,
@@ -95,7 +97,11 @@ async fn select_fastest_client_with_stat(
 }
 
 // Returns an error if the stat is bigger than `max_file_bytes`
-fn restrict_file_size(path: &str, size: u64, max_file_bytes: usize) -> Result<(), Error> {
+pub(super) fn restrict_file_size(
+    path: &str,
+    size: u64,
+    max_file_bytes: usize,
+) -> Result<(), Error> {
     if size > max_file_bytes as u64 {
         return Err(anyhow!(
             "IPFS file {} is too large. It can be at most {} bytes but is {} bytes",
@@ -111,6 +117,7 @@ fn restrict_file_size(path: &str, size: u64, max_file_bytes: usize) -> Result<()
 pub struct IpfsResolver {
     clients: Arc<Vec<IpfsClient>>,
     cache: Arc<Mutex<LruCache<String, Vec<u8>>>>,
+    content_cache: Option<Arc<ContentCache>>,
     timeout: Duration,
     retry: bool,
     env_vars: Arc<EnvVars>,
@@ -123,11 +130,78 @@ impl IpfsResolver {
             cache: Arc::new(Mutex::new(LruCache::with_capacity(
                 env_vars.mappings.max_ipfs_cache_size as usize,
             ))),
+            content_cache: None,
             timeout: env_vars.mappings.ipfs_timeout,
             retry: false,
             env_vars,
         }
     }
+
+    /// Keep the content of files on disk in `content_cache`
+    pub fn with_content_cache(mut self, content_cache: Option<Arc<ContentCache>>) -> Self {
+        self.content_cache = content_cache;
+        self
+    }
+
+    /// Fetch the file at `path` from the content cache, or from the fastest
+    /// client and add it to the content cache. The file must be at most
+    /// `max_file_size` bytes
+    async fn cat_cached(
+        &self,
+        logger: &Logger,
+        path: &str,
+        max_file_size: usize,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<u8>, Error> {
+        if let Some(content_cache) = &self.content_cache {
+            if let Some(data) = content_cache.get(ContentKind::Ipfs, path).await {
+                trace!(logger, "IPFS content cache hit"; "hash" => path);
+                restrict_file_size(path, data.len() as u64, max_file_size)?;
+                return Ok(data);
+            }
+        }
+
+        let (size, client) = select_fastest_client_with_stat(
+            self.clients.cheap_clone(),
+            logger.cheap_clone(),
+            StatApi::Files,
+            path.to_string(),
+            self.timeout,
+            self.retry,
+        )
+        .await?;
+
+        restrict_file_size(path, size, max_file_size)?;
+
+        let req_path = path.to_string();
+        let data = retry_policy(self.retry, "ipfs.cat", logger)
+            .run(move || {
+                let path = req_path.clone();
+                let client = client.clone();
+                async move {
+                    client
+                        .cat(&path, timeout)
+                        .await?
+                        .try_fold(Vec::new(), |mut data, chunk| async move {
+                            data.extend_from_slice(&chunk);
+                            Ok(data)
+                        })
+                        .await
+                }
+            })
+            .await?;
+
+        // The size reported by `files/stat` is not guaranteed to be exact, so check the limit again.
+        restrict_file_size(path, data.len() as u64, max_file_size)?;
+
+        if let Some(content_cache) = &self.content_cache {
+            content_cache
+                .insert(ContentKind::Ipfs, path, data.clone())
+                .await;
+        }
+
+        Ok(data)
+    }
 }
 
 impl Debug for IpfsResolver {
@@ -135,6 +209,7 @@ impl Debug for IpfsResolver {
         f.debug_struct("LinkResolver")
             .field("timeout", &self.timeout)
             .field("retry", &self.retry)
+            .field("content_cache", &self.content_cache.is_some())
             .field("env_vars", &self.env_vars)
             .finish()
     }
@@ -171,33 +246,12 @@ impl LinkResolverTrait for IpfsResolver {
         }
         trace!(logger, "IPFS cache miss"; "hash" => &path);
 
-        let (size, client) = select_fastest_client_with_stat(
-            self.clients.cheap_clone(),
-            logger.cheap_clone(),
-            StatApi::Files,
-            path.clone(),
-            self.timeout,
-            self.retry,
-        )
-        .await?;
-
         let max_cache_file_size = self.env_vars.mappings.max_ipfs_cache_file_size;
         let max_file_size = self.env_vars.mappings.max_ipfs_file_bytes;
-        restrict_file_size(&path, size, max_file_size)?;
-
-        let req_path = path.clone();
-        let timeout = self.timeout;
-        let data = retry_policy(self.retry, "ipfs.cat", logger)
-            .run(move || {
-                let path = req_path.clone();
-                let client = client.clone();
-                async move { Ok(client.cat_all(&path, timeout).await?.to_vec()) }
-            })
+        let data = self
+            .cat_cached(logger, &path, max_file_size, Some(self.timeout))
             .await?;
 
-        // The size reported by `files/stat` is not guaranteed to be exact, so check the limit again.
-        restrict_file_size(&path, data.len() as u64, max_file_size)?;
-
         // Only cache files if they are not too large
         if data.len() <= max_cache_file_size {
             let mut cache = self.cache.lock().unwrap();
@@ -247,81 +301,97 @@ impl LinkResolverTrait for IpfsResolver {
     async fn json_stream(&self, logger: &Logger, link: &Link) -> Result<JsonValueStream, Error> {
         // Discard the `/ipfs/` prefix (if present) to get the hash.
         let path = link.link.trim_start_matches("/ipfs/");
-
-        let (size, client) = select_fastest_client_with_stat(
-            self.clients.cheap_clone(),
-            logger.cheap_clone(),
-            StatApi::Files,
-            path.to_string(),
-            self.timeout,
-            self.retry,
-        )
-        .await?;
-
         let max_file_size = self.env_vars.mappings.max_ipfs_map_file_size;
-        restrict_file_size(path, size, max_file_size)?;
 
-        let mut stream = client.cat(path, None).await?.fuse().boxed().compat();
-
-        let mut buf = BytesMut::with_capacity(1024);
-
-        // Count the number of lines we've already successfully deserialized.
-        // We need that to adjust the line number in error messages from serde_json
-        // to translate from line numbers in the snippet we are deserializing
-        // to the line number in the overall file
-        let mut count = 0;
-
-        let stream: JsonValueStream = Box::pin(
-            poll_fn(move || -> Poll<Option<JsonStreamValue>, Error> {
-                loop {
-                    if let Some(offset) = buf.iter().position(|b| *b == b'\n') {
-                        let line_bytes = buf.split_to(offset + 1);
-                        count += 1;
-                        if line_bytes.len() > 1 {
-                            let line = std::str::from_utf8(&line_bytes)?;
-                            let res = match serde_json::from_str::<Value>(line) {
-                                Ok(v) => Ok(Async::Ready(Some(JsonStreamValue {
-                                    value: v,
-                                    line: count,
-                                }))),
-                                Err(e) => {
-                                    // Adjust the line number in the serde error. This
-                                    // is fun because we can only get at the full error
-                                    // message, and not the error message without line number
-                                    let msg = e.to_string();
-                                    let msg = msg.split(" at line ").next().unwrap();
-                                    Err(anyhow!(
-                                        "{} at line {} column {}: '{}'",
-                                        msg,
-                                        e.line() + count - 1,
-                                        e.column(),
-                                        line
-                                    ))
-                                }
-                            };
-                            return res;
-                        }
-                    } else {
-                        // We only get here if there is no complete line in buf, and
-                        // it is therefore ok to immediately pass an Async::NotReady
-                        // from stream through.
-                        // If we get a None from poll, but still have something in buf,
-                        // that means the input was not terminated with a newline. We
-                        // add that so that the last line gets picked up in the next
-                        // run through the loop.
-                        match try_ready!(stream.poll().map_err(|e| anyhow::anyhow!("{}", e))) {
-                            Some(b) => buf.extend_from_slice(&b),
-                            None if !buf.is_empty() => buf.extend_from_slice(&[b'\n']),
-                            None => return Ok(Async::Ready(None)),
-                        }
+        let chunks: BoxStream<'static, Result<bytes::Bytes, reqwest::Error>> =
+            if self.content_cache.is_some() {
+                // Read the whole file so that it can be added to the content cache
+                let data = self.cat_cached(logger, path, max_file_size, None).await?;
+                futures03::stream::once(futures03::future::ok(data.into())).boxed()
+            } else {
+                let (size, client) = select_fastest_client_with_stat(
+                    self.clients.cheap_clone(),
+                    logger.cheap_clone(),
+                    StatApi::Files,
+                    path.to_string(),
+                    self.timeout,
+                    self.retry,
+                )
+                .await?;
+
+                restrict_file_size(path, size, max_file_size)?;
+
+                client.cat(path, None).await?.boxed()
+            };
+        Ok(json_value_stream(chunks))
+    }
+}
+
+/// Split `chunks` into lines and deserialize each line into a JSON value
+pub(super) fn json_value_stream<E>(
+    chunks: BoxStream<'static, Result<bytes::Bytes, E>>,
+) -> JsonValueStream
+where
+    E: std::fmt::Display + Send + 'static,
+{
+    let mut stream = chunks.fuse().boxed().compat();
+
+    let mut buf = BytesMut::with_capacity(1024);
+
+    // Count the number of lines we've already successfully deserialized.
+    // We need that to adjust the line number in error messages from serde_json
+    // to translate from line numbers in the snippet we are deserializing
+    // to the line number in the overall file
+    let mut count = 0;
+
+    Box::pin(
+        poll_fn(move || -> Poll<Option<JsonStreamValue>, Error> {
+            loop {
+                if let Some(offset) = buf.iter().position(|b| *b == b'\n') {
+                    let line_bytes = buf.split_to(offset + 1);
+                    count += 1;
+                    if line_bytes.len() > 1 {
+                        let line = std::str::from_utf8(&line_bytes)?;
+                        let res = match serde_json::from_str::<Value>(line) {
+                            Ok(v) => Ok(Async::Ready(Some(JsonStreamValue {
+                                value: v,
+                                line: count,
+                            }))),
+                            Err(e) => {
+                                // Adjust the line number in the serde error. This
+                                // is fun because we can only get at the full error
+                                // message, and not the error message without line number
+                                let msg = e.to_string();
+                                let msg = msg.split(" at line ").next().unwrap();
+                                Err(anyhow!(
+                                    "{} at line {} column {}: '{}'",
+                                    msg,
+                                    e.line() + count - 1,
+                                    e.column(),
+                                    line
+                                ))
+                            }
+                        };
+                        return res;
+                    }
+                } else {
+                    // We only get here if there is no complete line in buf, and
+                    // it is therefore ok to immediately pass an Async::NotReady
+                    // from stream through.
+                    // If we get a None from poll, but still have something in buf,
+                    // that means the input was not terminated with a newline. We
+                    // add that so that the last line gets picked up in the next
+                    // run through the loop.
+                    match try_ready!(stream.poll().map_err(|e| anyhow::anyhow!("{}", e))) {
+                        Some(b) => buf.extend_from_slice(&b),
+                        None if !buf.is_empty() => buf.extend_from_slice(&[b'\n']),
+                        None => return Ok(Async::Ready(None)),
                     }
                 }
-            })
-            .compat(),
-        );
-
-        Ok(stream)
-    }
+            }
+        })
+        .compat(),
+    )
 }
 
 #[cfg(test)]
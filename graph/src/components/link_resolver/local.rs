@@ -0,0 +1,166 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use bytes::Bytes;
+use cid::Cid;
+use futures03::future;
+use futures03::stream::{self, StreamExt};
+use slog::{trace, Logger};
+
+use crate::data::subgraph::Link;
+use crate::env::EnvVars;
+use crate::prelude::{CheapClone, Error};
+
+use super::ipfs::{json_value_stream, restrict_file_size};
+use super::{CarArchive, JsonValueStream, LinkResolver};
+
+/// A `LinkResolver` that serves IPFS files from local CAR files and
+/// directories of blocks instead of from an IPFS node. Every block is
+/// checked against its CID when it is loaded, so that the files are
+/// exactly the ones that an IPFS node would serve
+#[derive(Clone)]
+pub struct LocalResolver {
+    archive: Arc<CarArchive>,
+    env_vars: Arc<EnvVars>,
+}
+
+impl LocalResolver {
+    /// Load the blocks from `paths`, each of which is either a CAR file or
+    /// a directory as described in `CarArchive::read_dir`
+    pub fn new(paths: &[PathBuf], env_vars: Arc<EnvVars>) -> Result<Self, Error> {
+        let mut archive = CarArchive::default();
+        for path in paths {
+            if path.is_dir() {
+                archive.extend(CarArchive::read_dir(path)?);
+            } else {
+                archive.extend(CarArchive::read(path)?);
+            }
+        }
+        Ok(LocalResolver {
+            archive: Arc::new(archive),
+            env_vars,
+        })
+    }
+
+    /// The blocks that this resolver serves
+    pub fn archive(&self) -> Arc<CarArchive> {
+        self.archive.cheap_clone()
+    }
+
+    fn cat_limited(&self, link: &Link, max_file_size: usize) -> Result<Vec<u8>, Error> {
+        // Discard the `/ipfs/` prefix (if present) to get the hash.
+        let path = link.link.trim_start_matches("/ipfs/");
+        let data = self
+            .archive
+            .cat(path)?
+            .ok_or_else(|| anyhow!("IPFS file {} is not in the local archives", path))?;
+        restrict_file_size(path, data.len() as u64, max_file_size)?;
+        Ok(data)
+    }
+}
+
+impl std::fmt::Debug for LocalResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalResolver")
+            .field("blocks", &self.archive.len())
+            .field("env_vars", &self.env_vars)
+            .finish()
+    }
+}
+
+impl CheapClone for LocalResolver {
+    fn cheap_clone(&self) -> Self {
+        self.clone()
+    }
+}
+
+#[async_trait]
+impl LinkResolver for LocalResolver {
+    /// Reading local files does not time out
+    fn with_timeout(&self, _timeout: Duration) -> Box<dyn LinkResolver> {
+        Box::new(self.cheap_clone())
+    }
+
+    /// Retrying would not help to find files that are not in the archives
+    fn with_retries(&self) -> Box<dyn LinkResolver> {
+        Box::new(self.cheap_clone())
+    }
+
+    async fn cat(&self, logger: &Logger, link: &Link) -> Result<Vec<u8>, Error> {
+        trace!(logger, "Local IPFS cat"; "hash" => &link.link);
+        self.cat_limited(link, self.env_vars.mappings.max_ipfs_file_bytes)
+    }
+
+    async fn get_block(&self, logger: &Logger, link: &Link) -> Result<Vec<u8>, Error> {
+        trace!(logger, "Local IPFS block get"; "hash" => &link.link);
+        let cid = Cid::try_from(link.link.as_str())?;
+        let block = self
+            .archive
+            .block(&cid)
+            .ok_or_else(|| anyhow!("IPFS block {} is not in the local archives", cid))?;
+        let max_file_size = self.env_vars.mappings.max_ipfs_file_bytes;
+        restrict_file_size(&link.link, block.len() as u64, max_file_size)?;
+        Ok(block.to_vec())
+    }
+
+    async fn json_stream(&self, logger: &Logger, link: &Link) -> Result<JsonValueStream, Error> {
+        trace!(logger, "Local IPFS JSON stream"; "hash" => &link.link);
+        let data = self.cat_limited(link, self.env_vars.mappings.max_ipfs_map_file_size)?;
+        let chunks = stream::once(future::ok::<_, Error>(Bytes::from(data))).boxed();
+        Ok(json_value_stream(chunks))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cid::multihash::Multihash;
+    use futures03::TryStreamExt;
+    use sha2::{Digest, Sha256};
+    use slog::o;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn serve_local_files() {
+        let text = b"{\"a\": 1}\n{\"a\": 2}\n";
+        let digest = Sha256::digest(text);
+        let cid = Cid::new_v1(0x55, Multihash::wrap(0x12, &digest).unwrap());
+
+        let dir = std::env::temp_dir().join(format!("graph-local-resolver-{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(cid.to_string()), text).unwrap();
+
+        let mut env_vars = EnvVars::default();
+        env_vars.mappings.max_ipfs_file_bytes = 10;
+        let resolver = LocalResolver::new(&[dir.clone()], Arc::new(env_vars)).unwrap();
+        let logger = Logger::root(slog::Discard, o!());
+        let link = Link {
+            link: format!("/ipfs/{}", cid),
+        };
+
+        let values: Vec<_> = resolver
+            .json_stream(&logger, &link)
+            .await
+            .unwrap()
+            .map_ok(|value| value.value["a"].as_i64().unwrap())
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(vec![1, 2], values);
+
+        // The file is larger than `max_ipfs_file_bytes`
+        let err = resolver.cat(&logger, &link).await.unwrap_err();
+        assert!(err.to_string().contains("is too large"));
+
+        let missing = Link {
+            link: "QmTSW4jNBCMBtzvpgAZyzzSqGD3Gp4TsCi8YHuqM4vHgvb".to_string(),
+        };
+        assert!(resolver.cat(&logger, &missing).await.is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -7,11 +7,17 @@ use crate::prelude::Error;
 use std::fmt::Debug;
 
 mod arweave;
+mod car;
+mod content_cache;
 mod ipfs;
+mod local;
 
 pub use arweave::*;
 use async_trait::async_trait;
+pub use car::CarArchive;
+pub use content_cache::{ContentCache, ContentKind};
 pub use ipfs::*;
+pub use local::LocalResolver;
 
 /// Resolves links to subgraph manifests and resources referenced by them.
 #[async_trait]
@@ -50,6 +50,12 @@ pub mod server;
 /// Components dealing with storing entities.
 pub mod store;
 
+/// Components that pass changes to deployments on to external systems.
+pub mod sink;
+
+/// Components for sharing query results between query nodes.
+pub mod query_cache;
+
 pub mod link_resolver;
 
 pub mod trigger_processor;
@@ -0,0 +1,471 @@
+//! A cache for query results that is shared by all query nodes.
+//!
+//! Each query node keeps the results of queries against recent blocks in
+//! memory, which means that every node computes the result of a popular
+//! query itself. With a shared cache, nodes also look for results that
+//! other nodes computed before running a query. Results are stored under a
+//! hash of the deployment, the block pointer, including the block hash, and
+//! the normalized query, so that a result is never used for a different
+//! block, even after a reorg. When the chain head listener reports a new
+//! chain head, the results for blocks that are too far behind it are
+//! removed; all results also expire after a while in case that never
+//! happens, e.g., for chains without a block ingestor.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Error};
+use async_trait::async_trait;
+use redis::aio::ConnectionManager;
+use slog::{debug, warn, Logger};
+use tokio::sync::{broadcast, OnceCell};
+
+use crate::components::store::BlockNumber;
+use crate::data::value::{Object, Value, Word};
+use crate::env::ENV_VARS;
+
+/// The prefix for all keys that we use in a Redis server
+const REDIS_PREFIX: &str = "graph-node:query";
+
+/// Storage for the results of queries that query nodes share
+#[async_trait]
+pub trait SharedQueryCache: Send + Sync + 'static {
+    /// Return the result stored under `key` if there is one
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Store `result` under `key`. The result is for a query against
+    /// `block` of `network`
+    async fn insert(
+        &self,
+        network: &str,
+        block: BlockNumber,
+        key: &str,
+        result: Vec<u8>,
+    ) -> Result<(), Error>;
+
+    /// Remove the results for all blocks of `network` before `block`
+    async fn invalidate(&self, network: &str, block: BlockNumber) -> Result<(), Error>;
+}
+
+/// Create the shared cache configured with `GRAPH_QUERY_SHARED_CACHE_URL`,
+/// or return `None` if it is not set
+pub fn shared_query_cache_from_env() -> Result<Option<Arc<dyn SharedQueryCache>>, Error> {
+    let url = match &ENV_VARS.graphql.query_shared_cache_url {
+        Some(url) => url,
+        None => return Ok(None),
+    };
+    if url.starts_with("redis://") || url.starts_with("rediss://") {
+        let cache = RedisQueryCache::new(url, ENV_VARS.graphql.query_shared_cache_ttl)?;
+        Ok(Some(Arc::new(cache)))
+    } else {
+        bail!("the shared query cache URL must start with `redis://` or `rediss://`")
+    }
+}
+
+/// A shared cache in a server that speaks the Redis protocol. Every result
+/// is stored with an expiry, and a sorted set for each network holds the
+/// keys of its results by block number so that results for old blocks can
+/// be found and removed
+pub struct RedisQueryCache {
+    client: redis::Client,
+    ttl: Duration,
+    conn: OnceCell<ConnectionManager>,
+}
+
+impl RedisQueryCache {
+    /// Create a cache for the server at `url`, e.g., `redis://cache:6379/0`.
+    /// Results expire after `ttl`. We only connect to the server when we
+    /// first need to, and reconnect when the connection breaks
+    pub fn new(url: &str, ttl: Duration) -> Result<Self, Error> {
+        Ok(RedisQueryCache {
+            client: redis::Client::open(url)?,
+            ttl,
+            conn: OnceCell::new(),
+        })
+    }
+
+    async fn conn(&self) -> Result<ConnectionManager, Error> {
+        let conn = self
+            .conn
+            .get_or_try_init(|| ConnectionManager::new(self.client.clone()))
+            .await?;
+        Ok(conn.clone())
+    }
+
+    fn key(key: &str) -> String {
+        format!("{}:result:{}", REDIS_PREFIX, key)
+    }
+
+    fn index(network: &str) -> String {
+        format!("{}:blocks:{}", REDIS_PREFIX, network)
+    }
+}
+
+#[async_trait]
+impl SharedQueryCache for RedisQueryCache {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        let mut conn = self.conn().await?;
+        let result = redis::cmd("GET")
+            .arg(Self::key(key))
+            .query_async::<_, Option<Vec<u8>>>(&mut conn)
+            .await?;
+        Ok(result)
+    }
+
+    async fn insert(
+        &self,
+        network: &str,
+        block: BlockNumber,
+        key: &str,
+        result: Vec<u8>,
+    ) -> Result<(), Error> {
+        let mut conn = self.conn().await?;
+        let key = Self::key(key);
+        let index = Self::index(network);
+        let ttl = self.ttl.as_millis() as u64;
+        redis::pipe()
+            .cmd("SET")
+            .arg(&key)
+            .arg(result)
+            .arg("PX")
+            .arg(ttl)
+            .ignore()
+            .cmd("ZADD")
+            .arg(&index)
+            .arg(block)
+            .arg(&key)
+            .ignore()
+            .cmd("PEXPIRE")
+            .arg(&index)
+            .arg(ttl)
+            .ignore()
+            .query_async::<_, ()>(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn invalidate(&self, network: &str, block: BlockNumber) -> Result<(), Error> {
+        let mut conn = self.conn().await?;
+        let index = Self::index(network);
+        let keys = redis::cmd("ZRANGEBYSCORE")
+            .arg(&index)
+            .arg("-inf")
+            .arg(format!("({}", block))
+            .query_async::<_, Vec<String>>(&mut conn)
+            .await?;
+        if keys.is_empty() {
+            return Ok(());
+        }
+        // Only remove the keys we found from the index; other query nodes
+        // might be adding results at the same time
+        redis::pipe()
+            .cmd("DEL")
+            .arg(&keys)
+            .ignore()
+            .cmd("ZREM")
+            .arg(&index)
+            .arg(&keys)
+            .ignore()
+            .query_async::<_, ()>(&mut conn)
+            .await?;
+        Ok(())
+    }
+}
+
+/// A shared cache that lives in the memory of this process. It is only
+/// shared by the users of the same instance, and meant for tests
+#[derive(Default)]
+pub struct MemoryQueryCache {
+    /// The network, block, and result for each key
+    results: Mutex<HashMap<String, (String, BlockNumber, Vec<u8>)>>,
+}
+
+impl MemoryQueryCache {
+    pub fn len(&self) -> usize {
+        self.results.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[async_trait]
+impl SharedQueryCache for MemoryQueryCache {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        let results = self.results.lock().unwrap();
+        Ok(results.get(key).map(|(_, _, result)| result.clone()))
+    }
+
+    async fn insert(
+        &self,
+        network: &str,
+        block: BlockNumber,
+        key: &str,
+        result: Vec<u8>,
+    ) -> Result<(), Error> {
+        let mut results = self.results.lock().unwrap();
+        results.insert(key.to_string(), (network.to_string(), block, result));
+        Ok(())
+    }
+
+    async fn invalidate(&self, network: &str, block: BlockNumber) -> Result<(), Error> {
+        let mut results = self.results.lock().unwrap();
+        results.retain(|_, (nw, blk, _)| nw != network || *blk >= block);
+        Ok(())
+    }
+}
+
+/// Remove the results for blocks that are `keep` or more blocks behind the
+/// chain head from `cache` whenever `heads` reports a new chain head for a
+/// network. Runs until `heads` is closed
+pub async fn invalidate_on_chain_head(
+    logger: Logger,
+    cache: Arc<dyn SharedQueryCache>,
+    keep: BlockNumber,
+    mut heads: broadcast::Receiver<(String, BlockNumber)>,
+) {
+    loop {
+        match heads.recv().await {
+            Ok((network, head)) => {
+                let block = head.saturating_sub(keep.max(1) - 1);
+                if let Err(e) = cache.invalidate(&network, block).await {
+                    warn!(logger, "Failed to invalidate the shared query cache";
+                        "network" => &network, "error" => e.to_string());
+                }
+            }
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                // The next chain head covers the ones we missed
+                debug!(logger, "Missed chain head updates for the shared query cache";
+                    "skipped" => skipped);
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+/// The version of the encoding of results. Results are only stored for a
+/// short time, so a new version simply needs a new key
+pub const RESULT_FORMAT: u8 = 1;
+
+const NULL: u8 = 0;
+const BOOLEAN: u8 = 1;
+const INT: u8 = 2;
+const FLOAT: u8 = 3;
+const STRING: u8 = 4;
+const ENUM: u8 = 5;
+const LIST: u8 = 6;
+const OBJECT: u8 = 7;
+
+/// Encode the data of a query result. Unlike JSON, the encoding keeps the
+/// order of the fields of objects and the difference between strings and
+/// enums
+pub fn encode_result(data: &Object) -> Vec<u8> {
+    let mut buf = vec![RESULT_FORMAT];
+    encode_object(data, &mut buf);
+    buf
+}
+
+/// Decode data that was encoded with `encode_result`
+pub fn decode_result(mut buf: &[u8]) -> Result<Object, Error> {
+    match take(&mut buf, 1)?[0] {
+        RESULT_FORMAT => {}
+        format => bail!("unknown query result format {}", format),
+    }
+    match decode_value(&mut buf, 0)? {
+        Value::Object(data) if buf.is_empty() => Ok(data),
+        Value::Object(_) => bail!("query result has trailing bytes"),
+        _ => bail!("query result is not an object"),
+    }
+}
+
+fn encode_len(len: usize, buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&(len as u64).to_le_bytes());
+}
+
+fn encode_str(s: &str, buf: &mut Vec<u8>) {
+    encode_len(s.len(), buf);
+    buf.extend_from_slice(s.as_bytes());
+}
+
+fn encode_object(object: &Object, buf: &mut Vec<u8>) {
+    let entries: Vec<_> = object.iter().collect();
+    buf.push(OBJECT);
+    encode_len(entries.len(), buf);
+    for (key, value) in entries {
+        encode_str(key, buf);
+        encode_value(value, buf);
+    }
+}
+
+fn encode_value(value: &Value, buf: &mut Vec<u8>) {
+    match value {
+        Value::Null => buf.push(NULL),
+        Value::Boolean(b) => buf.extend_from_slice(&[BOOLEAN, *b as u8]),
+        Value::Int(i) => {
+            buf.push(INT);
+            buf.extend_from_slice(&i.to_le_bytes());
+        }
+        Value::Float(f) => {
+            buf.push(FLOAT);
+            buf.extend_from_slice(&f.to_le_bytes());
+        }
+        Value::String(s) => {
+            buf.push(STRING);
+            encode_str(s, buf);
+        }
+        Value::Enum(s) => {
+            buf.push(ENUM);
+            encode_str(s, buf);
+        }
+        Value::List(values) => {
+            buf.push(LIST);
+            encode_len(values.len(), buf);
+            for value in values {
+                encode_value(value, buf);
+            }
+        }
+        Value::Object(object) => encode_object(object, buf),
+    }
+}
+
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
+    if buf.len() < len {
+        bail!("query result is truncated");
+    }
+    let (head, tail) = buf.split_at(len);
+    *buf = tail;
+    Ok(head)
+}
+
+fn take_u64(buf: &mut &[u8]) -> Result<u64, Error> {
+    Ok(u64::from_le_bytes(take(buf, 8)?.try_into().unwrap()))
+}
+
+fn decode_len(buf: &mut &[u8]) -> Result<usize, Error> {
+    let len = take_u64(buf)?;
+    // Every element takes at least one byte, which guards against
+    // allocating huge amounts of memory for garbage
+    match usize::try_from(len) {
+        Ok(len) if len <= buf.len() => Ok(len),
+        _ => bail!("query result is truncated"),
+    }
+}
+
+fn decode_str(buf: &mut &[u8]) -> Result<String, Error> {
+    let len = decode_len(buf)?;
+    let s = std::str::from_utf8(take(buf, len)?)?;
+    Ok(s.to_string())
+}
+
+fn decode_value(buf: &mut &[u8], depth: usize) -> Result<Value, Error> {
+    if depth > ENV_VARS.graphql.max_depth as usize + 1 {
+        bail!("query result is nested too deeply");
+    }
+    let value = match take(buf, 1)?[0] {
+        NULL => Value::Null,
+        BOOLEAN => Value::Boolean(take(buf, 1)?[0] != 0),
+        INT => Value::Int(take_u64(buf)? as i64),
+        FLOAT => Value::Float(f64::from_bits(take_u64(buf)?)),
+        STRING => Value::String(decode_str(buf)?),
+        ENUM => Value::Enum(decode_str(buf)?),
+        LIST => {
+            let len = decode_len(buf)?;
+            let values = (0..len)
+                .map(|_| decode_value(buf, depth + 1))
+                .collect::<Result<Vec<_>, _>>()?;
+            Value::List(values)
+        }
+        OBJECT => {
+            let len = decode_len(buf)?;
+            let entries = (0..len)
+                .map(|_| {
+                    let key = Word::from(decode_str(buf)?);
+                    let value = decode_value(buf, depth + 1)?;
+                    Ok((key, value))
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            Value::Object(Object::from_iter(entries))
+        }
+        tag => return Err(anyhow!("invalid tag {} in query result", tag)),
+    };
+    Ok(value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn object(entries: Vec<(&str, Value)>) -> Object {
+        Object::from_iter(
+            entries
+                .into_iter()
+                .map(|(key, value)| (Word::from(key), value)),
+        )
+    }
+
+    #[test]
+    fn encode_and_decode_results() {
+        let data = object(vec![
+            (
+                "tokens",
+                Value::List(vec![
+                    Value::Object(object(vec![
+                        ("symbol", Value::String("GRT".to_string())),
+                        ("id", Value::String("0x01".to_string())),
+                        ("decimals", Value::Int(18)),
+                        ("price", Value::Float(0.25)),
+                        ("kind", Value::Enum("ERC20".to_string())),
+                        ("active", Value::Boolean(true)),
+                        ("owner", Value::Null),
+                    ])),
+                    Value::Object(object(vec![])),
+                ]),
+            ),
+            ("_meta", Value::Object(object(vec![]))),
+        ]);
+
+        let encoded = encode_result(&data);
+        let decoded = decode_result(&encoded).unwrap();
+        assert_eq!(data, decoded);
+        // Field order is kept
+        let keys: Vec<_> = decoded.iter().map(|(key, _)| key).collect();
+        assert_eq!(vec!["tokens", "_meta"], keys);
+
+        assert!(decode_result(&encoded[..encoded.len() - 1]).is_err());
+        assert!(decode_result(&[RESULT_FORMAT + 1]).is_err());
+        assert!(decode_result(&[RESULT_FORMAT, LIST, 0, 0, 0, 0, 0, 0, 0, 0]).is_err());
+    }
+
+    #[tokio::test]
+    async fn invalidate_old_blocks() {
+        let logger = Logger::root(slog::Discard, slog::o!());
+        let cache = Arc::new(MemoryQueryCache::default());
+        for (network, block) in [
+            ("mainnet", 10),
+            ("mainnet", 11),
+            ("mainnet", 12),
+            ("gnosis", 1),
+        ] {
+            let key = format!("{}-{}", network, block);
+            cache
+                .insert(network, block, &key, key.as_bytes().to_vec())
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            Some(b"mainnet-11".to_vec()),
+            cache.get("mainnet-11").await.unwrap()
+        );
+
+        let (sender, receiver) = broadcast::channel(4);
+        sender.send(("mainnet".to_string(), 12)).unwrap();
+        drop(sender);
+        invalidate_on_chain_head(logger, cache.clone(), 2, receiver).await;
+
+        assert_eq!(3, cache.len());
+        assert_eq!(None, cache.get("mainnet-10").await.unwrap());
+        assert!(cache.get("mainnet-11").await.unwrap().is_some());
+        assert!(cache.get("gnosis-1").await.unwrap().is_some());
+    }
+}
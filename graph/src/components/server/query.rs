@@ -1,4 +1,4 @@
-use crate::data::query::QueryError;
+use crate::data::query::{QueryError, QueryExecutionError};
 use futures::prelude::*;
 use std::error::Error;
 use std::fmt;
@@ -19,6 +19,12 @@ impl From<QueryError> for GraphQLServerError {
     }
 }
 
+impl From<QueryExecutionError> for GraphQLServerError {
+    fn from(e: QueryExecutionError) -> Self {
+        GraphQLServerError::QueryError(QueryError::from(e))
+    }
+}
+
 impl From<StoreError> for GraphQLServerError {
     fn from(e: StoreError) -> Self {
         match e {
@@ -0,0 +1,821 @@
+//! A sink that publishes the entity changes of deployments to topics on a
+//! Kafka-compatible broker.
+//!
+//! Every deployment gets its own topic with a single partition so that
+//! consumers see the changes of a deployment in the order in which they
+//! were made. Each record in a topic describes either all changes in one
+//! block or a revert, and carries the number of that block in its `block`
+//! header. The sink uses the block of the last record in a topic to decide
+//! what it still needs to publish, which makes publishing idempotent:
+//! publishing changes again after a lost acknowledgement from the broker
+//! does not produce duplicates. When a deployment starts, the sink
+//! publishes a revert if the topic is ahead of the deployment, e.g.,
+//! because the deployment was rewound.
+//!
+//! Changes are published after they have been committed to the database
+//! from a queue that a dedicated thread works through in order. The sink
+//! never skips a record: publishing is retried until the broker accepts
+//! it, and when the queue is full, the writer waits until there is room,
+//! which slows indexing down to the pace of the broker. The sink also
+//! never publishes a block if the topic is missing records for earlier
+//! blocks, which happens when graph-node stops before it has published
+//! everything it queued. It then stops publishing the deployment, which
+//! is logged and counted in the `deployment_sink_kafka_stopped` metric,
+//! until the deployment is rewound to the last block in the topic.
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use prometheus::IntCounterVec;
+use rskafka::client::error::{Error as RsKafkaError, ProtocolError};
+use rskafka::client::partition::{Compression, OffsetAt, PartitionClient, UnknownTopicHandling};
+use rskafka::client::{Client, ClientBuilder};
+use rskafka::record::Record;
+use serde_json::json;
+use slog::{error, info, warn, Logger};
+use tokio::sync::OnceCell;
+
+use crate::blockchain::BlockPtr;
+use crate::components::metrics::MetricsRegistry;
+use crate::components::store::write::Batch;
+use crate::components::store::BlockNumber;
+use crate::data::subgraph::DeploymentHash;
+use crate::util::backoff::ExponentialBackoff;
+
+use super::DeploymentSink;
+
+/// The header that holds the block number of a record
+const BLOCK_HEADER: &str = "block";
+/// The header that holds the kind of a record, `block` or `revert`
+const TYPE_HEADER: &str = "type";
+/// The block number we use for a revert that removes all changes
+const NO_BLOCK: BlockNumber = -1;
+/// The largest record we are prepared to read when looking for the last
+/// record in a topic
+const MAX_FETCH_BYTES: i32 = 100 * 1024 * 1024;
+/// How many jobs we queue for the broker before the writer has to wait
+const QUEUE_SIZE: usize = 1000;
+
+#[derive(Clone, Debug)]
+pub struct KafkaConfig {
+    /// The addresses of the brokers to bootstrap from
+    pub brokers: Vec<String>,
+    /// The topic for a deployment is this prefix followed by the
+    /// deployment hash
+    pub topic_prefix: String,
+    /// The replication factor for topics that the sink creates
+    pub replication_factor: i16,
+    /// Only publish changes for these deployments; if empty, publish
+    /// changes for all deployments
+    pub deployments: HashSet<DeploymentHash>,
+    /// How long to wait for the broker before retrying
+    pub timeout: Duration,
+    /// How often to try to publish a record before failures are logged
+    /// as errors rather than warnings. Publishing is retried until it
+    /// succeeds
+    pub max_attempts: usize,
+}
+
+/// A record in a topic
+#[derive(Clone, Debug, PartialEq)]
+pub struct KafkaRecord {
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+    pub headers: BTreeMap<String, Vec<u8>>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl KafkaRecord {
+    fn new(
+        kind: &str,
+        block: BlockNumber,
+        value: serde_json::Value,
+        timestamp: DateTime<Utc>,
+    ) -> Self {
+        let headers = BTreeMap::from_iter([
+            (TYPE_HEADER.to_string(), kind.as_bytes().to_vec()),
+            (BLOCK_HEADER.to_string(), block.to_string().into_bytes()),
+        ]);
+        KafkaRecord {
+            key: block.to_string().into_bytes(),
+            value: value.to_string().into_bytes(),
+            headers,
+            timestamp,
+        }
+    }
+
+    /// The block that this record is for
+    fn block(&self) -> Result<BlockNumber, anyhow::Error> {
+        self.headers
+            .get(BLOCK_HEADER)
+            .and_then(|block| std::str::from_utf8(block).ok())
+            .and_then(|block| block.parse().ok())
+            .ok_or_else(|| anyhow!("record does not have a valid `{}` header", BLOCK_HEADER))
+    }
+}
+
+/// Access to the topics on a broker. Each topic has exactly one partition
+#[async_trait]
+pub trait KafkaTopics: Send + Sync + 'static {
+    /// Return the last record in `topic`, or `None` if the topic is empty
+    /// or does not exist
+    async fn last_record(&self, topic: &str) -> Result<Option<KafkaRecord>, anyhow::Error>;
+
+    /// Append `record` to `topic`, creating the topic if it does not
+    /// exist yet
+    async fn append(&self, topic: &str, record: KafkaRecord) -> Result<(), anyhow::Error>;
+}
+
+/// Topics on a broker that speaks the Kafka protocol
+pub struct RsKafkaTopics {
+    brokers: Vec<String>,
+    replication_factor: i16,
+    timeout: Duration,
+    client: OnceCell<Client>,
+    partitions: tokio::sync::Mutex<HashMap<String, Arc<PartitionClient>>>,
+}
+
+impl RsKafkaTopics {
+    /// Create topics for the given `brokers`. We only connect to the
+    /// brokers when we first need to
+    pub fn new(config: &KafkaConfig) -> Self {
+        RsKafkaTopics {
+            brokers: config.brokers.clone(),
+            replication_factor: config.replication_factor,
+            timeout: config.timeout,
+            client: OnceCell::new(),
+            partitions: tokio::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn partition(&self, topic: &str) -> Result<Arc<PartitionClient>, anyhow::Error> {
+        let mut partitions = self.partitions.lock().await;
+        if let Some(partition) = partitions.get(topic) {
+            return Ok(partition.clone());
+        }
+
+        let client = self
+            .client
+            .get_or_try_init(|| ClientBuilder::new(self.brokers.clone()).build())
+            .await?;
+        let timeout_ms = self.timeout.as_millis() as i32;
+        match client
+            .controller_client()?
+            .create_topic(topic, 1, self.replication_factor, timeout_ms)
+            .await
+        {
+            Ok(()) => {}
+            Err(RsKafkaError::ServerError {
+                protocol_error: ProtocolError::TopicAlreadyExists,
+                ..
+            }) => {}
+            Err(e) => return Err(e.into()),
+        }
+        let partition = client
+            .partition_client(topic, 0, UnknownTopicHandling::Retry)
+            .await?;
+        let partition = Arc::new(partition);
+        partitions.insert(topic.to_string(), partition.clone());
+        Ok(partition)
+    }
+}
+
+#[async_trait]
+impl KafkaTopics for RsKafkaTopics {
+    async fn last_record(&self, topic: &str) -> Result<Option<KafkaRecord>, anyhow::Error> {
+        let partition = self.partition(topic).await?;
+        let earliest = partition.get_offset(OffsetAt::Earliest).await?;
+        let latest = partition.get_offset(OffsetAt::Latest).await?;
+        if latest <= earliest {
+            return Ok(None);
+        }
+        let max_wait_ms = self.timeout.as_millis() as i32;
+        let (records, _) = partition
+            .fetch_records(latest - 1, 1..MAX_FETCH_BYTES, max_wait_ms)
+            .await?;
+        // The broker returns whole record batches, which might contain
+        // records before the one we asked for
+        let record = records
+            .into_iter()
+            .find(|record| record.offset == latest - 1)
+            .ok_or_else(|| {
+                anyhow!(
+                    "the broker did not return the record at offset {}",
+                    latest - 1
+                )
+            })?
+            .record;
+        Ok(Some(KafkaRecord {
+            key: record.key.unwrap_or_default(),
+            value: record.value.unwrap_or_default(),
+            headers: record.headers,
+            timestamp: record.timestamp,
+        }))
+    }
+
+    async fn append(&self, topic: &str, record: KafkaRecord) -> Result<(), anyhow::Error> {
+        let record = Record {
+            key: Some(record.key),
+            value: Some(record.value),
+            headers: record.headers,
+            timestamp: record.timestamp,
+        };
+        self.partition(topic)
+            .await?
+            .produce(vec![record], Compression::NoCompression)
+            .await?;
+        Ok(())
+    }
+}
+
+type Job = Box<dyn FnOnce(&tokio::runtime::Runtime) + Send>;
+
+/// Why publishing failed
+#[derive(Debug)]
+enum PublishError {
+    /// Talking to the broker failed; trying again might succeed
+    Broker(anyhow::Error),
+    /// The last record in the topic is for block `tail`, but the records
+    /// we were asked to publish follow block `prev`. Publishing them would
+    /// leave out the blocks in between
+    Gap {
+        tail: BlockNumber,
+        prev: BlockNumber,
+    },
+}
+
+impl From<anyhow::Error> for PublishError {
+    fn from(e: anyhow::Error) -> Self {
+        PublishError::Broker(e)
+    }
+}
+
+/// The state of the sink that the worker thread uses
+struct Inner {
+    logger: Logger,
+    topics: Arc<dyn KafkaTopics>,
+    max_attempts: usize,
+    stopped: Box<IntCounterVec>,
+    /// The block of the last record in each topic as far as we know. We
+    /// forget it whenever talking to the broker fails since we can then
+    /// not be sure whether a record was appended or not
+    tails: Mutex<HashMap<String, BlockNumber>>,
+    /// The topics that we stopped publishing to because they are missing
+    /// records. They stay stopped until the deployment starts again
+    halted: Mutex<HashSet<String>>,
+}
+
+impl Inner {
+    async fn tail(&self, topic: &str) -> Result<BlockNumber, anyhow::Error> {
+        if let Some(block) = self.tails.lock().unwrap().get(topic) {
+            return Ok(*block);
+        }
+        let block = match self.topics.last_record(topic).await? {
+            Some(record) => record.block()?,
+            None => NO_BLOCK,
+        };
+        self.tails.lock().unwrap().insert(topic.to_string(), block);
+        Ok(block)
+    }
+
+    async fn append(&self, topic: &str, record: KafkaRecord) -> Result<(), anyhow::Error> {
+        let block = record.block()?;
+        self.topics.append(topic, record).await?;
+        self.tails.lock().unwrap().insert(topic.to_string(), block);
+        Ok(())
+    }
+
+    fn is_halted(&self, topic: &str) -> bool {
+        self.halted.lock().unwrap().contains(topic)
+    }
+
+    /// Run `f` until it succeeds. Failures to talk to the broker are
+    /// retried forever since giving up would lose records; if `f` finds
+    /// that the topic is missing records, stop publishing to it
+    async fn retry<F, R>(&self, topic: &str, what: &str, f: F)
+    where
+        F: Fn() -> R,
+        R: Future<Output = Result<(), PublishError>>,
+    {
+        let mut backoff =
+            ExponentialBackoff::new(Duration::from_millis(100), Duration::from_secs(30));
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            match f().await {
+                Ok(()) => return,
+                Err(PublishError::Broker(e)) => {
+                    // We can not be sure whether the broker stored the
+                    // record or not
+                    self.tails.lock().unwrap().remove(topic);
+                    if attempt < self.max_attempts {
+                        warn!(self.logger, "Failed to publish to Kafka, retrying";
+                              "topic" => topic, "what" => what,
+                              "attempt" => attempt, "error" => e.to_string());
+                    } else {
+                        error!(self.logger, "Failed to publish to Kafka, retrying";
+                               "topic" => topic, "what" => what,
+                               "attempt" => attempt, "error" => e.to_string());
+                    }
+                    backoff.sleep_async().await;
+                }
+                Err(PublishError::Gap { tail, prev }) => {
+                    error!(self.logger, "Kafka topic is missing records, stopped publishing \
+                                         until the deployment is rewound to its last block";
+                           "topic" => topic, "what" => what,
+                           "last_block" => tail, "missing_after" => prev);
+                    self.halted.lock().unwrap().insert(topic.to_string());
+                    self.stopped.with_label_values(&[topic]).inc();
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Append the records for the blocks in `records` that come after the
+    /// last record in the topic. The records follow block `prev`, and
+    /// unless the topic is empty, it must already have the records up to
+    /// `prev`
+    async fn publish(
+        &self,
+        topic: &str,
+        prev: Option<BlockNumber>,
+        records: &[KafkaRecord],
+    ) -> Result<(), PublishError> {
+        let tail = self.tail(topic).await?;
+        if let Some(prev) = prev {
+            if tail != NO_BLOCK && tail < prev {
+                return Err(PublishError::Gap { tail, prev });
+            }
+        }
+        for record in records {
+            if record.block()? > tail {
+                self.append(topic, record.clone()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Publish `record`, which must be a revert, if the topic has
+    /// records for blocks after the one that we revert to
+    async fn revert(&self, topic: &str, record: &KafkaRecord) -> Result<(), PublishError> {
+        if self.tail(topic).await? > record.block()? {
+            self.append(topic, record.clone()).await?;
+        }
+        Ok(())
+    }
+}
+
+/// A sink that publishes entity changes and reverts to a Kafka-compatible
+/// broker. All communication with the broker happens on a dedicated
+/// thread; the methods of the sink only queue what needs to be published
+/// and only wait when the queue is full
+pub struct KafkaSink {
+    logger: Logger,
+    config: KafkaConfig,
+    inner: Arc<Inner>,
+    jobs: Mutex<mpsc::SyncSender<Job>>,
+    /// The last block that we queued records for in each topic
+    queued: Mutex<HashMap<String, BlockNumber>>,
+}
+
+impl KafkaSink {
+    pub fn new(logger: &Logger, config: KafkaConfig, registry: &MetricsRegistry) -> Self {
+        let topics = Arc::new(RsKafkaTopics::new(&config));
+        Self::with_topics(logger, config, registry, topics)
+    }
+
+    /// Create a sink that publishes to `topics`
+    pub fn with_topics(
+        logger: &Logger,
+        config: KafkaConfig,
+        registry: &MetricsRegistry,
+        topics: Arc<dyn KafkaTopics>,
+    ) -> Self {
+        let logger = logger.new(slog::o!("component" => "KafkaSink"));
+        let stopped = registry
+            .new_int_counter_vec(
+                "deployment_sink_kafka_stopped",
+                "Counts how often publishing to a Kafka topic stopped because it is missing records",
+                &["topic"],
+            )
+            .expect("failed to register the `deployment_sink_kafka_stopped` counter");
+        let inner = Arc::new(Inner {
+            logger: logger.clone(),
+            topics,
+            max_attempts: config.max_attempts,
+            stopped,
+            tails: Mutex::new(HashMap::new()),
+            halted: Mutex::new(HashSet::new()),
+        });
+
+        let (sender, receiver) = mpsc::sync_channel::<Job>(QUEUE_SIZE);
+        std::thread::Builder::new()
+            .name("kafka-sink".to_string())
+            .spawn(move || {
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .expect("failed to create runtime for the Kafka sink");
+                for job in receiver {
+                    job(&runtime);
+                }
+            })
+            .expect("failed to start the Kafka sink thread");
+
+        KafkaSink {
+            logger,
+            config,
+            inner,
+            jobs: Mutex::new(sender),
+            queued: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Queue `f` to be run for `topic` on the worker thread. If the queue
+    /// is full, wait until there is room so that nothing gets lost
+    fn run<F, Fut>(&self, topic: String, f: F)
+    where
+        F: FnOnce(Arc<Inner>, String) -> Fut + Send + 'static,
+        Fut: Future<Output = ()>,
+    {
+        let inner = self.inner.clone();
+        let job: Job = Box::new(move |runtime| runtime.block_on(f(inner, topic)));
+        if self.jobs.lock().unwrap().send(job).is_err() {
+            // The worker thread only stops if a job panicked, and we can
+            // not publish anything anymore
+            panic!("the Kafka sink thread stopped");
+        }
+    }
+
+    /// Remember that we queued records up to `block` for `topic`, and
+    /// return the block we queued records for before that
+    fn set_queued(&self, topic: &str, block: BlockNumber) -> Option<BlockNumber> {
+        self.queued.lock().unwrap().insert(topic.to_string(), block)
+    }
+
+    /// Wait until everything that was queued before has been published
+    #[cfg(test)]
+    fn flush(&self) {
+        let (done, wait) = mpsc::sync_channel(1);
+        let job: Job = Box::new(move |_| {
+            done.send(()).ok();
+        });
+        self.jobs.lock().unwrap().send(job).unwrap();
+        wait.recv().unwrap();
+    }
+
+    fn wants(&self, deployment: &DeploymentHash) -> bool {
+        self.config.deployments.is_empty() || self.config.deployments.contains(deployment)
+    }
+
+    fn topic(&self, deployment: &DeploymentHash) -> String {
+        format!("{}{}", self.config.topic_prefix, deployment)
+    }
+
+    fn revert_record(deployment: &DeploymentHash, block: Option<&BlockPtr>) -> KafkaRecord {
+        let value = json!({
+            "type": "revert",
+            "deployment": deployment,
+            "block": block.map(|ptr| json!({ "number": ptr.number, "hash": ptr.hash_hex() })),
+        });
+        let number = block.map(|ptr| ptr.number).unwrap_or(NO_BLOCK);
+        KafkaRecord::new("revert", number, value, Utc::now())
+    }
+
+    /// One record for each block in `batch` that changed entities, and
+    /// one for the last block in the batch even if it did not change
+    /// anything so that the topic always records how far the deployment
+    /// has progressed
+    fn block_records(deployment: &DeploymentHash, batch: &Batch) -> Vec<KafkaRecord> {
+        let mut blocks = BTreeMap::new();
+        blocks.insert(batch.block_ptr.number, vec![]);
+        for change in super::entity_changes(batch, |entity_type| !entity_type.is_poi(), true) {
+            blocks
+                .entry(change.block)
+                .or_insert_with(Vec::new)
+                .push(change);
+        }
+
+        blocks
+            .into_iter()
+            .map(|(number, changes)| {
+                let hash = (number == batch.block_ptr.number).then(|| batch.block_ptr.hash_hex());
+                let timestamp = batch
+                    .block_times
+                    .iter()
+                    .find(|(block, _)| *block == number)
+                    .and_then(|(_, time)| DateTime::from_timestamp(time.as_secs_since_epoch(), 0))
+                    .unwrap_or_else(Utc::now);
+                let value = json!({
+                    "type": "block",
+                    "deployment": deployment,
+                    "block": { "number": number, "hash": hash },
+                    "timestamp": timestamp.timestamp(),
+                    "changes": changes,
+                });
+                KafkaRecord::new("block", number, value, timestamp)
+            })
+            .collect()
+    }
+}
+
+impl DeploymentSink for KafkaSink {
+    fn started(&self, deployment: &DeploymentHash, head: Option<&BlockPtr>) {
+        if !self.wants(deployment) {
+            return;
+        }
+        let topic = self.topic(deployment);
+        let record = Self::revert_record(deployment, head);
+        info!(self.logger, "Checking Kafka topic"; "topic" => &topic,
+              "head" => record.block().unwrap_or(NO_BLOCK));
+        self.set_queued(&topic, record.block().unwrap_or(NO_BLOCK));
+        self.run(topic, move |inner, topic| async move {
+            // The deployment might have been rewound; we therefore can
+            // not rely on what we remember about the topic, and give a
+            // topic we stopped publishing to another chance
+            inner.tails.lock().unwrap().remove(&topic);
+            inner.halted.lock().unwrap().remove(&topic);
+            inner
+                .retry(&topic, "revert", || inner.revert(&topic, &record))
+                .await
+        });
+    }
+
+    fn written(&self, deployment: &DeploymentHash, batch: &Batch) {
+        if !self.wants(deployment) {
+            return;
+        }
+        let topic = self.topic(deployment);
+        let records = Self::block_records(deployment, batch);
+        let prev = self.set_queued(&topic, batch.block_ptr.number);
+        self.run(topic, move |inner, topic| async move {
+            if inner.is_halted(&topic) {
+                return;
+            }
+            inner
+                .retry(&topic, "changes", || inner.publish(&topic, prev, &records))
+                .await
+        });
+    }
+
+    fn reverted(&self, deployment: &DeploymentHash, block: &BlockPtr) {
+        if !self.wants(deployment) {
+            return;
+        }
+        let topic = self.topic(deployment);
+        let record = Self::revert_record(deployment, Some(block));
+        self.set_queued(&topic, block.number);
+        self.run(topic, move |inner, topic| async move {
+            if inner.is_halted(&topic) {
+                return;
+            }
+            inner
+                .retry(&topic, "revert", || inner.revert(&topic, &record))
+                .await
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use web3::types::H256;
+
+    use crate::blockchain::block_stream::FirehoseCursor;
+    use crate::blockchain::BlockTime;
+    use crate::components::store::write::EntityModification;
+    use crate::entity;
+    use crate::schema::InputSchema;
+
+    use super::*;
+
+    const GQL: &str = "type Thing @entity { id: ID!, count: Int! }";
+
+    /// A stand-in for a broker that keeps topics in memory
+    #[derive(Default)]
+    struct MemoryTopics {
+        topics: Mutex<HashMap<String, Vec<KafkaRecord>>>,
+        /// Store the next record but report an error, as if the
+        /// acknowledgement from the broker got lost
+        lose_ack: AtomicBool,
+        /// Fail all requests, as if the broker was unreachable
+        down: AtomicBool,
+    }
+
+    impl MemoryTopics {
+        fn records(&self, topic: &str) -> Vec<(String, BlockNumber)> {
+            self.topics
+                .lock()
+                .unwrap()
+                .get(topic)
+                .into_iter()
+                .flatten()
+                .map(|record| {
+                    let kind = String::from_utf8(record.headers[TYPE_HEADER].clone()).unwrap();
+                    (kind, record.block().unwrap())
+                })
+                .collect()
+        }
+    }
+
+    #[async_trait]
+    impl KafkaTopics for MemoryTopics {
+        async fn last_record(&self, topic: &str) -> Result<Option<KafkaRecord>, anyhow::Error> {
+            if self.down.load(Ordering::SeqCst) {
+                return Err(anyhow!("broker is down"));
+            }
+            let topics = self.topics.lock().unwrap();
+            Ok(topics
+                .get(topic)
+                .and_then(|records| records.last().cloned()))
+        }
+
+        async fn append(&self, topic: &str, record: KafkaRecord) -> Result<(), anyhow::Error> {
+            if self.down.load(Ordering::SeqCst) {
+                return Err(anyhow!("broker is down"));
+            }
+            let mut topics = self.topics.lock().unwrap();
+            topics.entry(topic.to_string()).or_default().push(record);
+            if self.lose_ack.swap(false, Ordering::SeqCst) {
+                return Err(anyhow!("lost acknowledgement"));
+            }
+            Ok(())
+        }
+    }
+
+    fn ptr(block: BlockNumber) -> BlockPtr {
+        BlockPtr::from((H256::from_low_u64_be(block as u64), block))
+    }
+
+    fn batch(schema: &InputSchema, block: BlockNumber) -> Batch {
+        let thing = schema.entity_type("Thing").unwrap();
+        let data = Arc::new(entity! { schema => id: "one", count: block });
+        let emod = EntityModification::Insert {
+            key: thing.parse_key("one").unwrap(),
+            data,
+            block,
+            end: None,
+        };
+        let ptr = ptr(block);
+        Batch::new(
+            ptr.clone(),
+            BlockTime::for_test(&ptr),
+            FirehoseCursor::None,
+            vec![emod],
+            vec![],
+            vec![],
+            vec![],
+            false,
+        )
+        .unwrap()
+    }
+
+    fn sink(topics: Arc<MemoryTopics>) -> KafkaSink {
+        let config = KafkaConfig {
+            brokers: vec![],
+            topic_prefix: "test.".to_string(),
+            replication_factor: 1,
+            deployments: HashSet::new(),
+            timeout: Duration::from_secs(1),
+            max_attempts: 2,
+        };
+        let registry = MetricsRegistry::mock();
+        KafkaSink::with_topics(&crate::log::discard(), config, &registry, topics)
+    }
+
+    #[test]
+    fn publishes_exactly_once() {
+        let hash = DeploymentHash::new("kafka").unwrap();
+        let schema = InputSchema::parse_latest(GQL, hash.clone()).unwrap();
+        let topics = Arc::new(MemoryTopics::default());
+        let sink = sink(topics.clone());
+        let records = || {
+            sink.flush();
+            topics.records("test.kafka")
+        };
+        let block = |n| ("block".to_string(), n);
+        let revert = |n| ("revert".to_string(), n);
+
+        // Nothing to reconcile for a new deployment
+        sink.started(&hash, None);
+        assert_eq!(Vec::<(String, BlockNumber)>::new(), records());
+
+        // Blocks 2 and 3 are written in one batch
+        let mut batch23 = batch(&schema, 2);
+        batch23.append(batch(&schema, 3)).unwrap();
+        sink.written(&hash, &batch(&schema, 1));
+        sink.written(&hash, &batch23);
+        assert_eq!(vec![block(1), block(2), block(3)], records());
+
+        // The broker stores block 4 but we don't hear back, and we
+        // retry; we also publish block 4 a second time
+        topics.lose_ack.store(true, Ordering::SeqCst);
+        sink.written(&hash, &batch(&schema, 4));
+        sink.written(&hash, &batch(&schema, 4));
+        assert_eq!(vec![block(1), block(2), block(3), block(4)], records());
+
+        sink.reverted(&hash, &ptr(2));
+        sink.reverted(&hash, &ptr(2));
+        assert_eq!(
+            vec![block(1), block(2), block(3), block(4), revert(2)],
+            records()
+        );
+
+        // The deployment restarts from an earlier block than what the
+        // topic has seen
+        sink.started(&hash, Some(&ptr(1)));
+        sink.started(&hash, Some(&ptr(1)));
+        sink.written(&hash, &batch(&schema, 2));
+        assert_eq!(
+            vec![
+                block(1),
+                block(2),
+                block(3),
+                block(4),
+                revert(2),
+                revert(1),
+                block(2)
+            ],
+            records()
+        );
+    }
+
+    #[test]
+    fn retries_until_broker_is_back() {
+        let hash = DeploymentHash::new("kafka").unwrap();
+        let schema = InputSchema::parse_latest(GQL, hash.clone()).unwrap();
+        let topics = Arc::new(MemoryTopics::default());
+        let sink = sink(topics.clone());
+        let block = |n| ("block".to_string(), n);
+
+        sink.written(&hash, &batch(&schema, 1));
+        sink.flush();
+
+        // Publishing block 2 keeps failing while the broker is down, and
+        // block 3 waits behind it; both are published once the broker is
+        // back
+        topics.down.store(true, Ordering::SeqCst);
+        sink.written(&hash, &batch(&schema, 2));
+        sink.written(&hash, &batch(&schema, 3));
+        std::thread::sleep(Duration::from_millis(500));
+        assert_eq!(vec![block(1)], topics.records("test.kafka"));
+
+        topics.down.store(false, Ordering::SeqCst);
+        sink.flush();
+        assert_eq!(
+            vec![block(1), block(2), block(3)],
+            topics.records("test.kafka")
+        );
+        assert_eq!(
+            0,
+            sink.inner.stopped.with_label_values(&["test.kafka"]).get()
+        );
+    }
+
+    #[test]
+    fn stops_when_topic_is_missing_records() {
+        let hash = DeploymentHash::new("kafka").unwrap();
+        let schema = InputSchema::parse_latest(GQL, hash.clone()).unwrap();
+        let topics = Arc::new(MemoryTopics::default());
+        let block = |n| ("block".to_string(), n);
+
+        let sink1 = sink(topics.clone());
+        sink1.written(&hash, &batch(&schema, 1));
+        sink1.flush();
+
+        // Graph Node stopped after writing blocks 2 and 3 but before
+        // publishing them. Publishing block 4 would leave a gap
+        let sink = sink(topics.clone());
+        let stopped = || {
+            sink.flush();
+            sink.inner.stopped.with_label_values(&["test.kafka"]).get()
+        };
+        sink.started(&hash, Some(&ptr(3)));
+        sink.written(&hash, &batch(&schema, 4));
+        assert_eq!(1, stopped());
+        sink.written(&hash, &batch(&schema, 5));
+        assert_eq!(1, stopped());
+        assert_eq!(vec![block(1)], topics.records("test.kafka"));
+
+        // Rewinding the deployment to the last block in the topic closes
+        // the gap
+        sink.started(&hash, Some(&ptr(1)));
+        sink.written(&hash, &batch(&schema, 2));
+        assert_eq!(1, stopped());
+        assert_eq!(vec![block(1), block(2)], topics.records("test.kafka"));
+
+        // A new topic starts with whatever block the deployment writes
+        // next
+        let other = DeploymentHash::new("other").unwrap();
+        sink.started(&other, Some(&ptr(3)));
+        sink.written(&other, &batch(&schema, 4));
+        sink.flush();
+        assert_eq!(vec![block(4)], topics.records("test.other"));
+    }
+}
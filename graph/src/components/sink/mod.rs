@@ -0,0 +1,101 @@
+//! Sinks pass what happens to deployments on to systems outside of
+//! graph-node.
+//!
+//! The writable store calls every configured sink after a change to a
+//! deployment has been committed to the database. Sinks must not block the
+//! writer; sinks that need to do I/O queue what they need and do the actual
+//! work in the background.
+
+use std::collections::HashSet;
+
+use crate::blockchain::BlockPtr;
+use crate::components::store::write::{Batch, EntityModification};
+use crate::data::query::{FeedChange, FeedChanges, FeedOp};
+use crate::data::subgraph::schema::SubgraphError;
+use crate::data::subgraph::DeploymentHash;
+use crate::schema::EntityType;
+
+pub mod kafka;
+pub mod webhook;
+
+pub use kafka::{KafkaConfig, KafkaSink, KafkaTopics, RsKafkaTopics};
+pub use webhook::{WebhookConfig, WebhookEventKind, WebhookSink};
+
+/// A receiver of notifications about changes to deployments. All methods
+/// do nothing by default so that sinks only need to implement the ones
+/// they are interested in
+pub trait DeploymentSink: Send + Sync + 'static {
+    /// `deployment` was started, and `head` is the last block it has
+    /// processed. Sinks can use this to reconcile what they have seen with
+    /// the state of the deployment
+    fn started(&self, _deployment: &DeploymentHash, _head: Option<&BlockPtr>) {}
+
+    /// The changes in `batch` were written for `deployment`. If the batch
+    /// contains non-fatal errors, `unhealthy` is called in addition to this
+    fn written(&self, _deployment: &DeploymentHash, _batch: &Batch) {}
+
+    /// `deployment` was reverted to `block`; all changes for later blocks
+    /// have been removed
+    fn reverted(&self, _deployment: &DeploymentHash, _block: &BlockPtr) {}
+
+    /// `deployment` failed with `error` and stopped indexing
+    fn failed(&self, _deployment: &DeploymentHash, _error: &SubgraphError) {}
+
+    /// `deployment` encountered the non-fatal `errors` and is now unhealthy
+    fn unhealthy(&self, _deployment: &DeploymentHash, _errors: &[SubgraphError]) {}
+
+    /// `deployment` caught up with the chain head
+    fn synced(&self, _deployment: &DeploymentHash) {}
+}
+
+/// The changes that `batch` makes to entities whose type is accepted by
+/// `filter`, ordered by block, entity type, and id. The data of inserted
+/// and updated entities is only included if `with_data` is `true`
+pub fn entity_changes(
+    batch: &Batch,
+    filter: impl Fn(&EntityType) -> bool,
+    with_data: bool,
+) -> Vec<FeedChange> {
+    let mut changes = FeedChanges::new();
+    for group in batch.groups() {
+        if !filter(&group.entity_type) {
+            continue;
+        }
+
+        let mut ended = HashSet::new();
+        let mut started = Vec::new();
+        for emod in group.rows() {
+            let id = emod.id().to_string();
+            match emod {
+                EntityModification::Insert {
+                    data, block, end, ..
+                } => {
+                    let data = with_data.then(|| data.to_json());
+                    started.push((*block, id.clone(), FeedOp::Insert, data));
+                    if let Some(end) = end {
+                        ended.insert((*end, id));
+                    }
+                }
+                EntityModification::Overwrite {
+                    data, block, end, ..
+                } => {
+                    let data = with_data.then(|| data.to_json());
+                    started.push((*block, id.clone(), FeedOp::Update, data));
+                    if let Some(end) = end {
+                        ended.insert((*end, id));
+                    }
+                }
+                EntityModification::Remove { block, .. } => {
+                    ended.insert((*block, id));
+                }
+            }
+        }
+        changes.add(group.entity_type.as_str(), started, ended);
+    }
+    changes.into_changes(|block| {
+        batch
+            .block_ptr_for(block)
+            .map(|ptr| ptr.hash_hex())
+            .unwrap_or_default()
+    })
+}
@@ -0,0 +1,330 @@
+//! A sink that POSTs notifications about deployments to an HTTP endpoint
+use std::collections::HashSet;
+use std::time::Duration;
+
+use reqwest::header::HeaderMap;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use slog::{error, warn, Logger};
+use tokio::sync::mpsc;
+
+use crate::blockchain::BlockPtr;
+use crate::components::store::write::Batch;
+use crate::data::subgraph::schema::SubgraphError;
+use crate::data::subgraph::DeploymentHash;
+use crate::util::backoff::ExponentialBackoff;
+
+use super::DeploymentSink;
+
+/// How many notifications we queue for delivery before we start dropping
+/// new ones
+const QUEUE_SIZE: usize = 1000;
+
+/// The kinds of notifications that can be sent to a webhook
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookEventKind {
+    /// The deployment failed and stopped indexing
+    Failed,
+    /// The deployment encountered a non-fatal error
+    Unhealthy,
+    /// The deployment caught up with the chain head
+    Synced,
+    /// The deployment was reverted to an earlier block
+    Reverted,
+    /// Entities of one of the configured types changed
+    Entities,
+}
+
+impl WebhookEventKind {
+    /// The kinds of notifications that are sent if none are configured
+    pub fn defaults() -> Vec<WebhookEventKind> {
+        vec![
+            WebhookEventKind::Failed,
+            WebhookEventKind::Unhealthy,
+            WebhookEventKind::Synced,
+        ]
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WebhookConfig {
+    /// The URL to which notifications are POSTed
+    pub url: String,
+    /// Additional headers to send with each request, e.g., for
+    /// authentication
+    pub headers: HeaderMap,
+    pub events: HashSet<WebhookEventKind>,
+    /// The names of the entity types for which changes are sent
+    pub entity_types: HashSet<String>,
+    /// Only send notifications for these deployments; if empty, send
+    /// notifications for all deployments
+    pub deployments: HashSet<DeploymentHash>,
+    /// How often to try to deliver a notification before giving up on it
+    pub max_attempts: usize,
+    pub timeout: Duration,
+}
+
+/// A sink that sends notifications as JSON to an HTTP endpoint.
+/// Notifications are delivered one at a time and in the order in which
+/// they happened; failed deliveries are retried with exponential backoff
+pub struct WebhookSink {
+    logger: Logger,
+    config: WebhookConfig,
+    sender: mpsc::Sender<Value>,
+}
+
+impl WebhookSink {
+    /// Create a new sink and start the task that delivers notifications.
+    /// Must be called from within a Tokio runtime
+    pub fn new(logger: &Logger, config: WebhookConfig) -> Self {
+        let logger =
+            logger.new(slog::o!("component" => "WebhookSink", "url" => config.url.clone()));
+        let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+        crate::spawn(Self::deliver(logger.clone(), config.clone(), receiver));
+        WebhookSink {
+            logger,
+            config,
+            sender,
+        }
+    }
+
+    async fn deliver(logger: Logger, config: WebhookConfig, mut receiver: mpsc::Receiver<Value>) {
+        let client = Client::new();
+        while let Some(payload) = receiver.recv().await {
+            let mut backoff =
+                ExponentialBackoff::new(Duration::from_millis(500), Duration::from_secs(60));
+            for attempt in 1..=config.max_attempts {
+                let res = client
+                    .post(&config.url)
+                    .headers(config.headers.clone())
+                    .timeout(config.timeout)
+                    .json(&payload)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status());
+                match res {
+                    Ok(_) => break,
+                    Err(e) if attempt < config.max_attempts => {
+                        warn!(logger, "Failed to deliver webhook notification, retrying";
+                              "attempt" => attempt, "error" => e.to_string());
+                        backoff.sleep_async().await;
+                    }
+                    Err(e) => {
+                        error!(logger, "Failed to deliver webhook notification, giving up";
+                               "attempts" => attempt, "error" => e.to_string(),
+                               "event" => payload["event"].as_str().unwrap_or(""));
+                    }
+                }
+            }
+        }
+    }
+
+    fn wants(&self, kind: WebhookEventKind, deployment: &DeploymentHash) -> bool {
+        self.config.events.contains(&kind)
+            && (self.config.deployments.is_empty() || self.config.deployments.contains(deployment))
+    }
+
+    fn send(&self, payload: Value) {
+        if let Err(e) = self.sender.try_send(payload) {
+            warn!(self.logger, "Dropping webhook notification"; "error" => e.to_string());
+        }
+    }
+}
+
+fn block_json(ptr: &BlockPtr) -> Value {
+    json!({ "number": ptr.number, "hash": ptr.hash_hex() })
+}
+
+fn error_json(error: &SubgraphError) -> Value {
+    json!({
+        "message": error.message,
+        "block": error.block_ptr.as_ref().map(block_json),
+        "handler": error.handler,
+        "deterministic": error.deterministic,
+    })
+}
+
+/// The changes to entities of the given types in `batch`, ordered by
+/// block, entity type, and id
+fn entity_changes(entity_types: &HashSet<String>, batch: &Batch) -> Vec<Value> {
+    super::entity_changes(
+        batch,
+        |entity_type| entity_types.contains(entity_type.as_str()),
+        false,
+    )
+    .into_iter()
+    .map(|change| {
+        json!({
+            "entityType": change.entity_type,
+            "id": change.id,
+            "op": change.op,
+            "block": change.block,
+        })
+    })
+    .collect()
+}
+
+impl DeploymentSink for WebhookSink {
+    fn written(&self, deployment: &DeploymentHash, batch: &Batch) {
+        if !self.wants(WebhookEventKind::Entities, deployment) {
+            return;
+        }
+        let changes = entity_changes(&self.config.entity_types, batch);
+        if changes.is_empty() {
+            return;
+        }
+        self.send(json!({
+            "event": WebhookEventKind::Entities,
+            "deployment": deployment,
+            "block": block_json(&batch.block_ptr),
+            "changes": changes,
+        }));
+    }
+
+    fn reverted(&self, deployment: &DeploymentHash, block: &BlockPtr) {
+        if self.wants(WebhookEventKind::Reverted, deployment) {
+            self.send(json!({
+                "event": WebhookEventKind::Reverted,
+                "deployment": deployment,
+                "block": block_json(block),
+            }));
+        }
+    }
+
+    fn failed(&self, deployment: &DeploymentHash, error: &SubgraphError) {
+        if self.wants(WebhookEventKind::Failed, deployment) {
+            self.send(json!({
+                "event": WebhookEventKind::Failed,
+                "deployment": deployment,
+                "error": error_json(error),
+            }));
+        }
+    }
+
+    fn unhealthy(&self, deployment: &DeploymentHash, errors: &[SubgraphError]) {
+        if self.wants(WebhookEventKind::Unhealthy, deployment) {
+            self.send(json!({
+                "event": WebhookEventKind::Unhealthy,
+                "deployment": deployment,
+                "errors": errors.iter().map(error_json).collect::<Vec<_>>(),
+            }));
+        }
+    }
+
+    fn synced(&self, deployment: &DeploymentHash) {
+        if self.wants(WebhookEventKind::Synced, deployment) {
+            self.send(json!({
+                "event": WebhookEventKind::Synced,
+                "deployment": deployment,
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use web3::types::H256;
+
+    use crate::blockchain::block_stream::FirehoseCursor;
+    use crate::blockchain::BlockTime;
+    use crate::components::store::write::EntityModification;
+    use crate::components::store::BlockNumber;
+    use crate::entity;
+    use crate::schema::InputSchema;
+
+    use super::*;
+
+    const GQL: &str = r#"
+      type Thing @entity { id: ID!, count: Int! }
+      type Other @entity { id: ID! }
+    "#;
+
+    fn batch(block: BlockNumber, mods: Vec<EntityModification>) -> Batch {
+        let ptr = BlockPtr::from((H256::from_low_u64_be(block as u64), block));
+        Batch::new(
+            ptr.clone(),
+            BlockTime::for_test(&ptr),
+            FirehoseCursor::None,
+            mods,
+            vec![],
+            vec![],
+            vec![],
+            false,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn entity_changes_classifies_ops() {
+        let hash = DeploymentHash::new("webhook").unwrap();
+        let schema = InputSchema::parse_latest(GQL, hash).unwrap();
+        let thing = schema.entity_type("Thing").unwrap();
+        let other = schema.entity_type("Other").unwrap();
+
+        let write = |id: &str, block: BlockNumber, insert: bool| {
+            let key = thing.parse_key(id).unwrap();
+            let data = Arc::new(entity! { schema => id: id, count: block });
+            if insert {
+                EntityModification::Insert {
+                    key,
+                    data,
+                    block,
+                    end: None,
+                }
+            } else {
+                EntityModification::Overwrite {
+                    key,
+                    data,
+                    block,
+                    end: None,
+                }
+            }
+        };
+
+        let mut batch1 = batch(
+            1,
+            vec![
+                write("one", 1, true),
+                write("two", 1, false),
+                EntityModification::Remove {
+                    key: other.parse_key("x").unwrap(),
+                    block: 1,
+                },
+            ],
+        );
+        let batch2 = batch(
+            2,
+            vec![
+                write("one", 2, false),
+                EntityModification::Remove {
+                    key: thing.parse_key("two").unwrap(),
+                    block: 2,
+                },
+            ],
+        );
+        batch1.append(batch2).unwrap();
+
+        let entity_types = HashSet::from_iter(["Thing".to_string()]);
+        let changes = entity_changes(&entity_types, &batch1)
+            .into_iter()
+            .map(|change| {
+                (
+                    change["block"].as_i64().unwrap(),
+                    change["id"].as_str().unwrap().to_string(),
+                    change["op"].as_str().unwrap().to_string(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let exp = vec![
+            (1, "one".to_string(), "insert".to_string()),
+            (1, "two".to_string(), "update".to_string()),
+            (2, "one".to_string(), "update".to_string()),
+            (2, "two".to_string(), "delete".to_string()),
+        ];
+        assert_eq!(exp, changes);
+    }
+}
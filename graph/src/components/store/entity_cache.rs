@@ -1,15 +1,15 @@
 use anyhow::anyhow;
 use std::borrow::Borrow;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fmt::{self, Debug};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use crate::cheap_clone::CheapClone;
 use crate::components::store::write::EntityModification;
 use crate::components::store::{self as s, Entity, EntityOperation};
 use crate::data::store::{EntityValidationError, Id, IdType, IntoEntityIterator};
 use crate::prelude::ENV_VARS;
-use crate::schema::{EntityKey, InputSchema};
+use crate::schema::{EntityKey, EntityType, InputSchema};
 use crate::util::intern::Error as InternError;
 use crate::util::lfu_cache::{EvictStats, LfuCache};
 
@@ -17,6 +17,11 @@ use super::{BlockNumber, DerivedEntityQuery, LoadRelatedRequest, StoreError};
 
 pub type EntityLfuCache = LfuCache<EntityKey, Option<Arc<Entity>>>;
 
+/// The entity types that were accessed through an `EntityCache`. It is
+/// shared so that it is still available when the cache is gone, for
+/// example, because a handler failed
+pub type AccessedEntityTypes = Arc<Mutex<HashSet<EntityType>>>;
+
 /// The scope in which the `EntityCache` should perform a `get` operation
 pub enum GetScope {
     /// Get from all previously stored entities in the store
@@ -105,6 +110,14 @@ pub struct EntityCache {
     /// generated IDs, the `EntityCache` needs to be newly instantiated for
     /// each block
     seq: u32,
+
+    /// The entity types that were read or written through this cache
+    /// since `track_entity_types` was called
+    accessed_entity_types: Option<AccessedEntityTypes>,
+
+    /// The keys that were looked up in the store through this cache since
+    /// `track_loads` was called
+    loads: Option<Vec<EntityKey>>,
 }
 
 impl Debug for EntityCache {
@@ -132,6 +145,8 @@ impl EntityCache {
             schema: store.input_schema(),
             store,
             seq: 0,
+            accessed_entity_types: None,
+            loads: None,
         }
     }
 
@@ -152,6 +167,65 @@ impl EntityCache {
             schema: store.input_schema(),
             store,
             seq: 0,
+            accessed_entity_types: None,
+            loads: None,
+        }
+    }
+
+    /// Keep track of the entity types that are read or written through
+    /// this cache from now on and return where they are tracked
+    pub fn track_entity_types(&mut self) -> AccessedEntityTypes {
+        let types = AccessedEntityTypes::default();
+        self.accessed_entity_types = Some(types.cheap_clone());
+        types
+    }
+
+    /// The entity types that were read or written through this cache, or
+    /// `None` if `track_entity_types` was never called
+    pub fn accessed_entity_types(&self) -> Option<HashSet<EntityType>> {
+        self.accessed_entity_types
+            .as_ref()
+            .map(|types| types.lock().unwrap().clone())
+    }
+
+    /// Keep track of the keys that are looked up in the store through this
+    /// cache from now on, forgetting the keys that were tracked so far
+    pub fn track_loads(&mut self) {
+        self.loads = Some(Vec::new());
+    }
+
+    /// Return the keys that were looked up in the store since tracking
+    /// started or since the last call to this method
+    pub fn take_loads(&mut self) -> Vec<EntityKey> {
+        self.loads.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Load the entities for `keys` that are not in the cache yet from the
+    /// store with one query per entity type so that looking them up later
+    /// does not require a query per entity
+    pub fn prefetch(&mut self, keys: BTreeSet<EntityKey>) -> Result<usize, StoreError> {
+        let keys: BTreeSet<_> = keys
+            .into_iter()
+            .filter(|key| !self.current.contains_key(key))
+            .collect();
+        if keys.is_empty() {
+            return Ok(0);
+        }
+
+        let mut entities = self.store.get_many(keys.clone())?;
+        for key in keys.iter() {
+            let entity = entities.remove(key).map(Arc::new);
+            self.current.insert(key.clone(), entity);
+        }
+        Ok(keys.len())
+    }
+
+    fn accessed(&mut self, entity_type: &EntityType) {
+        if let Some(types) = self.accessed_entity_types.as_ref() {
+            let mut types = types.lock().unwrap();
+            if !types.contains(entity_type) {
+                types.insert(entity_type.cheap_clone());
+            }
         }
     }
 
@@ -182,10 +256,15 @@ impl EntityCache {
         key: &EntityKey,
         scope: GetScope,
     ) -> Result<Option<Arc<Entity>>, StoreError> {
+        self.accessed(&key.entity_type);
+
         // Get the current entity, apply any updates from `updates`, then
         // from `handler_updates`.
         let mut entity: Option<Arc<Entity>> = match scope {
             GetScope::Store => {
+                if let Some(loads) = self.loads.as_mut() {
+                    loads.push(key.clone());
+                }
                 if !self.current.contains_key(key) {
                     let entity = self.store.get(key)?;
                     self.current.insert(key.clone(), entity.map(Arc::new));
@@ -231,6 +310,8 @@ impl EntityCache {
             causality_region: eref.causality_region,
         };
 
+        self.accessed(&query.entity_type);
+
         let mut entity_map = self.store.get_derived(&query)?;
 
         for (key, entity) in entity_map.iter() {
@@ -389,6 +470,7 @@ impl EntityCache {
 
     fn entity_op(&mut self, key: EntityKey, op: EntityOp) {
         use std::collections::hash_map::Entry;
+        self.accessed(&key.entity_type);
         let updates = match self.in_handler {
             true => &mut self.handler_updates,
             false => &mut self.updates,
@@ -418,6 +500,13 @@ impl EntityCache {
         Ok(id)
     }
 
+    /// Whether `generate_id` was called. Since generated ids depend on the
+    /// order in which they were generated, the changes of such a cache can
+    /// not be combined with those of another cache in an arbitrary order
+    pub fn generated_ids(&self) -> bool {
+        self.seq > 0
+    }
+
     /// Return the changes that have been made via `set` and `remove` as
     /// `EntityModification`, making sure to only produce one when a change
     /// to the current state is actually needed.
@@ -171,3 +171,9 @@ impl From<std::fmt::Error> for StoreError {
         StoreError::Unknown(anyhow!("{}", e.to_string()))
     }
 }
+
+impl From<std::io::Error> for StoreError {
+    fn from(e: std::io::Error) -> Self {
+        StoreError::Unknown(e.into())
+    }
+}
@@ -3,7 +3,9 @@ mod err;
 mod traits;
 pub mod write;
 
-pub use entity_cache::{EntityCache, EntityLfuCache, GetScope, ModificationsAndCache};
+pub use entity_cache::{
+    AccessedEntityTypes, EntityCache, EntityLfuCache, GetScope, ModificationsAndCache,
+};
 use futures03::future::{FutureExt, TryFutureExt};
 use slog::{trace, Logger};
 
@@ -537,6 +539,84 @@ impl EntityQuery {
     }
 }
 
+/// An aggregate that can be computed over the entities matched by an
+/// `EntityAggregateQuery`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum EntityAggregate {
+    /// The number of entities
+    Count,
+    /// The sum of a numeric attribute
+    Sum(Attribute),
+    /// The smallest value of a numeric attribute
+    Min(Attribute),
+    /// The largest value of a numeric attribute
+    Max(Attribute),
+}
+
+impl fmt::Display for EntityAggregate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EntityAggregate::Count => write!(f, "count"),
+            EntityAggregate::Sum(attr) => write!(f, "sum({})", attr),
+            EntityAggregate::Min(attr) => write!(f, "min({})", attr),
+            EntityAggregate::Max(attr) => write!(f, "max({})", attr),
+        }
+    }
+}
+
+/// A query that computes aggregates over the entities of one type that
+/// match a filter, optionally grouped by some of their attributes. Each
+/// row of the result contains the values of the `group_by` attributes
+/// followed by the values of the `aggregates`, in the order in which they
+/// are listed in the query. Rows are sorted by the `group_by` attributes
+#[derive(Clone, Debug)]
+pub struct EntityAggregateQuery {
+    /// ID of the subgraph.
+    pub subgraph_id: DeploymentHash,
+
+    /// The block height at which to execute the query
+    pub block: BlockNumber,
+
+    /// The entity type whose entities are aggregated
+    pub entity_type: EntityType,
+
+    /// Filter to filter entities by before aggregating them
+    pub filter: Option<EntityFilter>,
+
+    /// The attributes by which to group entities
+    pub group_by: Vec<Attribute>,
+
+    /// The aggregates to compute for each group
+    pub aggregates: Vec<EntityAggregate>,
+
+    /// A range to limit the number of groups in the result
+    pub range: EntityRange,
+
+    /// Optional logger for anything related to this query
+    pub logger: Option<Logger>,
+
+    pub query_id: Option<String>,
+
+    pub trace: bool,
+}
+
+impl EntityAggregateQuery {
+    pub fn new(subgraph_id: DeploymentHash, block: BlockNumber, entity_type: EntityType) -> Self {
+        EntityAggregateQuery {
+            subgraph_id,
+            block,
+            entity_type,
+            filter: None,
+            group_by: Vec::new(),
+            aggregates: Vec::new(),
+            range: EntityRange::default(),
+            logger: None,
+            query_id: None,
+            trace: false,
+        }
+    }
+}
+
 /// Operation types that lead to entity changes.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "lowercase")]
@@ -10,7 +10,7 @@ use crate::components::server::index_node::VersionInfo;
 use crate::components::subgraph::SubgraphVersionSwitchingMode;
 use crate::components::transaction_receipt;
 use crate::components::versions::ApiVersion;
-use crate::data::query::Trace;
+use crate::data::query::{FeedCursor, FeedEvent, PersistedQueries, SqlPlan, SqlQueryObject, Trace};
 use crate::data::store::QueryObject;
 use crate::data::subgraph::{status, DeploymentFeatures};
 use crate::data::{query::QueryTarget, subgraph::schema::*};
@@ -402,6 +402,13 @@ pub trait QueryStoreManager: Send + Sync + 'static {
         target: QueryTarget,
         for_subscription: bool,
     ) -> Result<Arc<dyn QueryStore + Send + Sync>, QueryExecutionError>;
+
+    /// Get the persisted queries registered for the deployment that
+    /// `target` refers to
+    async fn persisted_queries(
+        &self,
+        target: QueryTarget,
+    ) -> Result<Arc<PersistedQueries>, QueryExecutionError>;
 }
 
 pub trait BlockStore: Send + Sync + 'static {
@@ -554,6 +561,49 @@ pub trait QueryStore: Send + Sync {
         query: EntityQuery,
     ) -> Result<(Vec<QueryObject>, Trace), QueryExecutionError>;
 
+    /// Compute the aggregates described by `query`. Each entry in the
+    /// result is one row as described in `EntityAggregateQuery`
+    fn aggregate_query_values(
+        &self,
+        query: EntityAggregateQuery,
+    ) -> Result<(Vec<Vec<Value>>, Trace), QueryExecutionError>;
+
+    /// Return the SQL query that `find_query_values` would run for
+    /// `query` without running it. If `explain` is `true`, also return the
+    /// plan that Postgres chooses for the query
+    fn explain_query_values(
+        &self,
+        query: EntityQuery,
+        explain: bool,
+    ) -> Result<SqlPlan, QueryExecutionError>;
+
+    /// Like `explain_query_values`, but for the query that
+    /// `aggregate_query_values` would run
+    fn explain_aggregate_query_values(
+        &self,
+        query: EntityAggregateQuery,
+        explain: bool,
+    ) -> Result<SqlPlan, QueryExecutionError>;
+
+    /// Run the SQL query `sql` against the tables of this deployment as
+    /// they were at `block`. The query must be a single `select` statement
+    /// that only uses the restricted set of SQL features that is described
+    /// in `docs/sql-queries.md`
+    fn execute_sql(
+        &self,
+        sql: &str,
+        block: BlockNumber,
+    ) -> Result<Vec<SqlQueryObject>, QueryExecutionError>;
+
+    /// Return the next events in the entity change feed of this
+    /// deployment after `cursor`. Without a cursor, the feed starts at the
+    /// current deployment head. An empty result means that the consumer
+    /// has seen all changes up to the deployment head
+    fn entity_feed(
+        &self,
+        cursor: Option<FeedCursor>,
+    ) -> Result<Vec<FeedEvent>, QueryExecutionError>;
+
     async fn is_deployment_synced(&self) -> Result<bool, Error>;
 
     async fn block_ptr(&self) -> Result<Option<BlockPtr>, StoreError>;
@@ -634,6 +684,16 @@ pub trait StatusStore: Send + Sync + 'static {
         block: BlockPtr,
     ) -> Result<Option<[u8; 32]>, StoreError>;
 
+    /// The digests from which the proof of indexing at `block_number` is
+    /// computed, one for each causality region and keyed by its name.
+    /// Comparing them with those of another indexer shows which causality
+    /// regions are responsible for differing proofs of indexing
+    async fn get_poi_digests(
+        &self,
+        subgraph_id: &DeploymentHash,
+        block_number: BlockNumber,
+    ) -> Result<Option<BTreeMap<String, Vec<u8>>>, StoreError>;
+
     /// Like `get_proof_of_indexing` but returns a Proof of Indexing signed by
     /// address `0x00...0`, which allows it to be shared in public without
     /// revealing the indexers _real_ Proof of Indexing.
@@ -346,6 +346,11 @@ impl RowGroup {
         ClampsByBlockIterator::new(self)
     }
 
+    /// Iterate over all changes in this group, ordered by block
+    pub fn rows(&self) -> impl Iterator<Item = &EntityModification> {
+        self.rows.iter()
+    }
+
     /// Iterate over all changes that require writing a new entity version
     pub fn writes(&self) -> impl Iterator<Item = &EntityModification> {
         self.rows.iter().filter(|row| row.is_write())
@@ -624,6 +629,10 @@ pub struct Batch {
     /// the subgraph performed a write. Entries are in ascending order of
     /// block number
     pub block_times: Vec<(BlockNumber, BlockTime)>,
+    /// The pointer for each block we've seen as batches have been appended
+    /// to this one, in ascending order of block number, so that changes
+    /// can be attributed to the hash of the block in which they happened
+    pub block_ptrs: Vec<BlockPtr>,
     /// The first block for which this batch contains changes
     pub first_block: BlockNumber,
     /// The firehose cursor corresponding to `block_ptr`
@@ -670,10 +679,12 @@ impl Batch {
         let offchain_to_remove = DataSources::new(block_ptr.cheap_clone(), offchain_to_remove);
         let first_block = block_ptr.number;
         let block_times = vec![(block, block_time)];
+        let block_ptrs = vec![block_ptr.cheap_clone()];
         Ok(Self {
             block_ptr,
             first_block,
             block_times,
+            block_ptrs,
             firehose_cursor,
             mods,
             data_sources,
@@ -691,6 +702,7 @@ impl Batch {
 
         self.block_ptr = batch.block_ptr;
         self.block_times.append(&mut batch.block_times);
+        self.block_ptrs.append(&mut batch.block_ptrs);
         self.firehose_cursor = batch.firehose_cursor;
         self.mods.append(batch.mods)?;
         self.data_sources.append(batch.data_sources);
@@ -719,6 +731,11 @@ impl Batch {
         self.mods.entity_count()
     }
 
+    /// The pointer of block `number` if this batch covers that block
+    pub fn block_ptr_for(&self, number: BlockNumber) -> Option<&BlockPtr> {
+        self.block_ptrs.iter().find(|ptr| ptr.number == number)
+    }
+
     /// Find out whether the latest operation for the entity with type
     /// `entity_type` and `id` is going to write that entity, i.e., insert
     /// or overwrite it, or if it is going to remove it. If no change will
@@ -6,7 +6,7 @@ use anyhow::Error;
 use async_trait::async_trait;
 use futures::sync::mpsc;
 
-use crate::blockchain::BlockTime;
+use crate::blockchain::{BlockTime, DeclaredCalls};
 use crate::components::metrics::gas::GasMetrics;
 use crate::components::store::SubgraphFork;
 use crate::data_source::{
@@ -96,6 +96,12 @@ pub trait RuntimeHost<C: Blockchain>: Send + Sync + 'static {
     /// Convenience function to avoid leaking internal representation of
     /// mutable number. Calling this on OnChain Datasources is a noop.
     fn set_done_at(&self, block: Option<BlockNumber>);
+
+    /// Whether the triggers of this host need to be passed to
+    /// `RuntimeHostBuilder::prepare_triggers`
+    fn prepares_triggers(&self) -> bool {
+        false
+    }
 }
 
 pub struct HostMetrics {
@@ -200,6 +206,7 @@ impl Drop for HostFnExecutionTimer {
     }
 }
 
+#[async_trait]
 pub trait RuntimeHostBuilder<C: Blockchain>: Clone + Send + Sync + 'static {
     type Host: RuntimeHost<C> + PartialEq;
     type Req: 'static + Send;
@@ -223,4 +230,17 @@ pub trait RuntimeHostBuilder<C: Blockchain>: Clone + Send + Sync + 'static {
         subgraph_id: DeploymentHash,
         metrics: Arc<HostMetrics>,
     ) -> Result<mpsc::Sender<Self::Req>, anyhow::Error>;
+
+    /// Prepare the decoded triggers of a block before any handler runs,
+    /// for example by making the calls that the manifest declares for
+    /// them. The triggers only come from hosts for which
+    /// `RuntimeHost::prepares_triggers` is true
+    async fn prepare_triggers(
+        &self,
+        _logger: &Logger,
+        _block_ptr: &BlockPtr,
+        _triggers: &[(&DataSource<C>, &MappingTrigger<C>)],
+    ) -> Result<DeclaredCalls, MappingError> {
+        Ok(DeclaredCalls::default())
+    }
 }
@@ -1,5 +1,5 @@
 use crate::{
-    blockchain::{Blockchain, DataSourceTemplate as _},
+    blockchain::{Blockchain, DataSourceTemplate as _, DeclaredCalls},
     components::store::{EntityLfuCache, ReadStore, StoredDynamicDataSource},
     data::subgraph::schema::SubgraphError,
     data_source::{DataSourceTemplate, DataSourceTemplateInfo},
@@ -76,6 +76,10 @@ pub struct BlockState {
 
     // Marks whether a handler is currently executing.
     in_handler: bool,
+
+    // The results of the calls that the manifest declares for the handlers
+    // of the block.
+    pub declared_calls: Arc<DeclaredCalls>,
 }
 
 impl BlockState {
@@ -88,6 +92,7 @@ impl BlockState {
             handler_created_data_sources: Vec::new(),
             processed_data_sources: Vec::new(),
             in_handler: false,
+            declared_calls: Arc::new(DeclaredCalls::default()),
         }
     }
 }
@@ -104,6 +109,7 @@ impl BlockState {
             handler_created_data_sources,
             processed_data_sources,
             in_handler,
+            declared_calls: _,
         } = self;
 
         match in_handler {
@@ -0,0 +1,488 @@
+//! Find the first block at which the proofs of indexing of two indexers
+//! for the same deployment diverge.
+//!
+//! Proofs of indexing are cumulative: the digest for each causality region
+//! at a block is computed from the digest at the previous block. Once the
+//! proofs of indexing of two indexers differ, they therefore differ at all
+//! later blocks, too, which makes it possible to find the first block at
+//! which they differ by comparing them at only a few blocks.
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Error};
+use async_trait::async_trait;
+use web3::types::Address;
+
+use crate::blockchain::{BlockHash, BlockPtr};
+use crate::components::store::{BlockNumber, BlockPtrForNumber, EntityOperation, Store};
+use crate::data::subgraph::DeploymentHash;
+
+/// How many blocks we compare in each step of the search. Index nodes
+/// answer at most 10 requests for public proofs of indexing at once
+const PROBES: usize = 9;
+
+/// The digests of a proof of indexing, keyed by the name of the causality
+/// region
+pub type PoiDigests = BTreeMap<String, Vec<u8>>;
+
+/// The changes to entities in one block, keyed by entity type and id. The
+/// value is the entity after the change as a JSON object, or `None` if the
+/// entity was deleted
+pub type EntityChanges = BTreeMap<(String, String), Option<serde_json::Value>>;
+
+/// Turn the changes that the store reports for a block into
+/// `EntityChanges`
+pub fn entity_changes(operations: Vec<EntityOperation>) -> EntityChanges {
+    operations
+        .into_iter()
+        .map(|operation| match operation {
+            EntityOperation::Set { key, data } => (
+                (key.entity_type.to_string(), key.entity_id.to_string()),
+                Some(data.to_json()),
+            ),
+            EntityOperation::Remove { key } => (
+                (key.entity_type.to_string(), key.entity_id.to_string()),
+                None,
+            ),
+        })
+        .collect()
+}
+
+/// Parse a proof of indexing given as a hex string, with or without a
+/// leading `0x`
+pub fn parse_poi(poi: &str) -> Result<[u8; 32], Error> {
+    let bytes = hex::decode(poi.trim_start_matches("0x"))?;
+    bytes
+        .try_into()
+        .map_err(|_| anyhow!("proof of indexing `{}` does not have 32 bytes", poi))
+}
+
+/// A source of proofs of indexing for one deployment, e.g., the local
+/// store or the index node of another indexer
+#[async_trait]
+pub trait PoiSource: Send + Sync {
+    /// The blocks for which the source has proofs of indexing, or `None`
+    /// if it can provide them for any block
+    fn blocks(&self) -> Option<Vec<BlockNumber>> {
+        None
+    }
+
+    /// The public proof of indexing for each of `blocks`, together with
+    /// the block for which it was computed, or `None` if the source has no
+    /// proof of indexing for a block. If a block hash is given, the proof
+    /// of indexing must be computed for that hash; sources that can only
+    /// determine the hash themselves ignore it
+    async fn public_pois(
+        &self,
+        blocks: &[(BlockNumber, Option<BlockHash>)],
+    ) -> Result<Vec<Option<(BlockPtr, [u8; 32])>>, Error>;
+
+    /// The digests of the proof of indexing at `block`, or `None` if the
+    /// source does not have them
+    async fn digests(&self, _block: BlockNumber) -> Result<Option<PoiDigests>, Error> {
+        Ok(None)
+    }
+
+    /// The changes to entities in `block`, or `None` if the source does
+    /// not have them
+    async fn entity_changes(&self, _block: BlockNumber) -> Result<Option<EntityChanges>, Error> {
+        Ok(None)
+    }
+}
+
+/// The proofs of indexing of a deployment in the local store. Block hashes
+/// that the caller does not know are looked up with `block_ptrs`
+pub struct StorePois<'a, S> {
+    store: Arc<S>,
+    deployment: DeploymentHash,
+    block_ptrs: &'a dyn BlockPtrForNumber,
+}
+
+impl<'a, S: Store> StorePois<'a, S> {
+    pub fn new(
+        store: Arc<S>,
+        deployment: DeploymentHash,
+        block_ptrs: &'a dyn BlockPtrForNumber,
+    ) -> Self {
+        Self {
+            store,
+            deployment,
+            block_ptrs,
+        }
+    }
+}
+
+#[async_trait]
+impl<'a, S: Store> PoiSource for StorePois<'a, S> {
+    async fn public_pois(
+        &self,
+        blocks: &[(BlockNumber, Option<BlockHash>)],
+    ) -> Result<Vec<Option<(BlockPtr, [u8; 32])>>, Error> {
+        let mut pois = Vec::with_capacity(blocks.len());
+        for (number, hash) in blocks {
+            let poi = match hash {
+                Some(hash) => {
+                    let ptr = BlockPtr::new(hash.clone(), *number);
+                    // Public proofs of indexing are signed by the zero address
+                    self.store
+                        .get_proof_of_indexing(
+                            &self.deployment,
+                            &Some(Address::zero()),
+                            ptr.clone(),
+                        )
+                        .await?
+                        .map(|poi| (ptr, poi))
+                }
+                None => self
+                    .store
+                    .get_public_proof_of_indexing(&self.deployment, *number, self.block_ptrs)
+                    .await?
+                    .and_then(|(block, poi)| {
+                        block
+                            .hash
+                            .map(|hash| (BlockPtr::new(hash, block.number), poi))
+                    }),
+            };
+            pois.push(poi);
+        }
+        Ok(pois)
+    }
+
+    async fn digests(&self, block: BlockNumber) -> Result<Option<PoiDigests>, Error> {
+        Ok(self.store.get_poi_digests(&self.deployment, block).await?)
+    }
+
+    async fn entity_changes(&self, block: BlockNumber) -> Result<Option<EntityChanges>, Error> {
+        let changes = self
+            .store
+            .subgraph_store()
+            .entity_changes_in_block(&self.deployment, block)?;
+        Ok(Some(entity_changes(changes)))
+    }
+}
+
+/// Proofs of indexing for a list of blocks, e.g., the result of a
+/// `publicProofsOfIndexing` query against another indexer
+pub struct PoiList {
+    pois: Vec<(BlockPtr, [u8; 32])>,
+}
+
+impl PoiList {
+    pub fn new(pois: Vec<(BlockPtr, [u8; 32])>) -> Self {
+        Self { pois }
+    }
+}
+
+#[async_trait]
+impl PoiSource for PoiList {
+    fn blocks(&self) -> Option<Vec<BlockNumber>> {
+        Some(self.pois.iter().map(|(ptr, _)| ptr.number).collect())
+    }
+
+    async fn public_pois(
+        &self,
+        blocks: &[(BlockNumber, Option<BlockHash>)],
+    ) -> Result<Vec<Option<(BlockPtr, [u8; 32])>>, Error> {
+        Ok(blocks
+            .iter()
+            .map(|(number, _)| {
+                self.pois
+                    .iter()
+                    .find(|(ptr, _)| ptr.number == *number)
+                    .cloned()
+            })
+            .collect())
+    }
+}
+
+/// The first block at which the proofs of indexing of two sources differ
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    /// The last block before `first_diverging` at which the proofs of
+    /// indexing agree, or `None` if they already differ at the first block
+    /// of the search. If the reference only has proofs of indexing for
+    /// some blocks, the blocks between this one and `first_diverging` were
+    /// not compared
+    pub last_agreeing: Option<BlockNumber>,
+    pub first_diverging: BlockPtr,
+    pub local: [u8; 32],
+    pub reference: [u8; 32],
+}
+
+/// The blocks that we can compare
+enum Candidates {
+    Range(BlockNumber, BlockNumber),
+    List(Vec<BlockNumber>),
+}
+
+impl Candidates {
+    fn len(&self) -> usize {
+        match self {
+            Candidates::Range(start, end) => (end - start + 1).max(0) as usize,
+            Candidates::List(blocks) => blocks.len(),
+        }
+    }
+
+    fn get(&self, idx: usize) -> BlockNumber {
+        match self {
+            Candidates::Range(start, _) => start + idx as BlockNumber,
+            Candidates::List(blocks) => blocks[idx],
+        }
+    }
+}
+
+/// The result of comparing the proofs of indexing at one block
+struct Probe {
+    block: BlockPtr,
+    local: [u8; 32],
+    reference: [u8; 32],
+}
+
+impl Probe {
+    fn agrees(&self) -> bool {
+        self.local == self.reference
+    }
+}
+
+async fn compare(
+    local: &dyn PoiSource,
+    reference: &dyn PoiSource,
+    blocks: &[BlockNumber],
+) -> Result<Vec<Probe>, Error> {
+    let request: Vec<_> = blocks.iter().map(|block| (*block, None)).collect();
+    let theirs = reference.public_pois(&request).await?;
+    if theirs.len() != blocks.len() {
+        return Err(anyhow!(
+            "asked the reference for {} proofs of indexing but got {}",
+            blocks.len(),
+            theirs.len()
+        ));
+    }
+    let theirs = blocks
+        .iter()
+        .zip(theirs)
+        .map(|(block, poi)| {
+            poi.ok_or_else(|| anyhow!("the reference has no proof of indexing for block {}", block))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Use the block hashes of the reference so that differences in the
+    // block hashes do not show up as differences in the data
+    let request: Vec<_> = theirs
+        .iter()
+        .map(|(ptr, _)| (ptr.number, Some(ptr.hash.clone())))
+        .collect();
+    let ours = local.public_pois(&request).await?;
+    if ours.len() != theirs.len() {
+        return Err(anyhow!(
+            "asked for {} local proofs of indexing but got {}",
+            theirs.len(),
+            ours.len()
+        ));
+    }
+
+    theirs
+        .into_iter()
+        .zip(ours)
+        .map(|((block, reference), local)| {
+            let (_, local) = local.ok_or_else(|| {
+                anyhow!("there is no local proof of indexing for block {}", block)
+            })?;
+            Ok(Probe {
+                block,
+                local,
+                reference,
+            })
+        })
+        .collect()
+}
+
+/// Find the first block between `start` and `end`, inclusive, at which
+/// the proofs of indexing of `local` and `reference` differ. Return
+/// `None` if they agree at `end`.
+///
+/// If the reference only has proofs of indexing for some blocks, only
+/// those blocks are compared
+pub async fn bisect(
+    local: &dyn PoiSource,
+    reference: &dyn PoiSource,
+    start: BlockNumber,
+    end: BlockNumber,
+) -> Result<Option<Divergence>, Error> {
+    let candidates = match reference.blocks() {
+        Some(mut blocks) => {
+            blocks.retain(|block| *block >= start && *block <= end);
+            blocks.sort_unstable();
+            blocks.dedup();
+            Candidates::List(blocks)
+        }
+        None => Candidates::Range(start, end),
+    };
+    if candidates.len() == 0 {
+        return Err(anyhow!(
+            "the reference has no proofs of indexing for blocks {} to {}",
+            start,
+            end
+        ));
+    }
+
+    let last = candidates.len() - 1;
+    let mut probes = compare(local, reference, &[candidates.get(last)]).await?;
+    let mut diverging = probes.pop().unwrap();
+    if diverging.agrees() {
+        return Ok(None);
+    }
+
+    // `lo` is the index of a block at which the proofs of indexing agree,
+    // `hi` the index of a block at which they differ
+    let probe = compare(local, reference, &[candidates.get(0)])
+        .await?
+        .pop()
+        .unwrap();
+    if !probe.agrees() {
+        return Ok(Some(Divergence {
+            last_agreeing: None,
+            first_diverging: probe.block,
+            local: probe.local,
+            reference: probe.reference,
+        }));
+    }
+    let (mut lo, mut hi) = (0, last);
+
+    while hi - lo > 1 {
+        let mut idxs: Vec<_> = (1..=PROBES)
+            .map(|k| lo + (hi - lo) * k / (PROBES + 1))
+            .filter(|idx| *idx > lo && *idx < hi)
+            .collect();
+        idxs.dedup();
+        let blocks: Vec<_> = idxs.iter().map(|idx| candidates.get(*idx)).collect();
+        let probes = compare(local, reference, &blocks).await?;
+
+        let mut next = (lo, hi);
+        for (idx, probe) in idxs.into_iter().zip(probes) {
+            if probe.agrees() {
+                next.0 = idx;
+            } else {
+                next.1 = idx;
+                diverging = probe;
+                break;
+            }
+        }
+        (lo, hi) = next;
+    }
+
+    Ok(Some(Divergence {
+        last_agreeing: Some(candidates.get(lo)),
+        first_diverging: diverging.block,
+        local: diverging.local,
+        reference: diverging.reference,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A source whose proof of indexing changes at block `diverges`
+    struct TestSource {
+        diverges: BlockNumber,
+        blocks: Option<Vec<BlockNumber>>,
+    }
+
+    #[async_trait]
+    impl PoiSource for TestSource {
+        fn blocks(&self) -> Option<Vec<BlockNumber>> {
+            self.blocks.clone()
+        }
+
+        async fn public_pois(
+            &self,
+            blocks: &[(BlockNumber, Option<BlockHash>)],
+        ) -> Result<Vec<Option<(BlockPtr, [u8; 32])>>, Error> {
+            Ok(blocks
+                .iter()
+                .map(|(number, _)| {
+                    let poi = if *number >= self.diverges {
+                        [1; 32]
+                    } else {
+                        [0; 32]
+                    };
+                    Some((BlockPtr::new(BlockHash::zero(), *number), poi))
+                })
+                .collect())
+        }
+    }
+
+    fn source(diverges: BlockNumber) -> TestSource {
+        TestSource {
+            diverges,
+            blocks: None,
+        }
+    }
+
+    async fn check(
+        local: BlockNumber,
+        reference: &TestSource,
+        start: BlockNumber,
+        end: BlockNumber,
+    ) -> Option<(Option<BlockNumber>, BlockNumber)> {
+        bisect(&source(local), reference, start, end)
+            .await
+            .unwrap()
+            .map(|div| (div.last_agreeing, div.first_diverging.number))
+    }
+
+    #[tokio::test]
+    async fn finds_first_divergent_block() {
+        let reference = source(BlockNumber::MAX);
+
+        assert_eq!(
+            Some((Some(1233), 1234)),
+            check(1234, &reference, 0, 10_000_000).await
+        );
+        assert_eq!(Some((Some(10), 11)), check(11, &reference, 10, 11).await);
+        assert_eq!(Some((None, 10)), check(5, &reference, 10, 100).await);
+        assert_eq!(None, check(101, &reference, 10, 100).await);
+    }
+
+    #[tokio::test]
+    async fn only_compares_blocks_the_reference_has() {
+        let reference = TestSource {
+            diverges: BlockNumber::MAX,
+            blocks: Some((0..100).map(|block| block * 100).collect()),
+        };
+
+        assert_eq!(
+            Some((Some(1200), 1300)),
+            check(1234, &reference, 0, 10_000).await
+        );
+        assert_eq!(
+            Some((Some(1200), 1300)),
+            check(1300, &reference, 50, 10_000).await
+        );
+        assert_eq!(None, check(1234, &reference, 0, 1000).await);
+        assert!(bisect(&source(0), &reference, 1, 99).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn compares_with_a_list_of_pois() {
+        let local = source(1234);
+        let pois = (0..20)
+            .map(|block| {
+                let ptr = BlockPtr::new(BlockHash::zero(), block * 100);
+                (ptr, [0; 32])
+            })
+            .collect();
+        let reference = PoiList::new(pois);
+
+        let div = bisect(&local, &reference, 0, 1900).await.unwrap().unwrap();
+        assert_eq!(Some(1200), div.last_agreeing);
+        assert_eq!(1300, div.first_diverging.number);
+        assert_eq!([1; 32], div.local);
+        assert_eq!([0; 32], div.reference);
+
+        assert_eq!(None, bisect(&local, &reference, 0, 1200).await.unwrap());
+        assert!(parse_poi(&format!("0x{}", hex::encode([7u8; 32]))).is_ok());
+        assert!(parse_poi("0x1234").is_err());
+    }
+}
@@ -56,6 +56,10 @@ pub enum ProofOfIndexingEvent<'a> {
     ///
     /// for the first and second cases respectively.
     DeterministicError { redacted_events: u64 },
+    /// For when the content of an offchain data source whose content is not
+    /// addressed by its hash was handled. The `hash` is the hex-encoded
+    /// keccak256 hash of the content that was fetched from `source`.
+    OffchainContent { source: &'a str, hash: &'a str },
 }
 
 impl stable_hash_legacy::StableHash for ProofOfIndexingEvent<'_> {
@@ -84,6 +88,10 @@ impl stable_hash_legacy::StableHash for ProofOfIndexingEvent<'_> {
             DeterministicError { redacted_events } => {
                 redacted_events.stable_hash(sequence_number.next_child(), state)
             }
+            OffchainContent { source, hash } => {
+                source.stable_hash(sequence_number.next_child(), state);
+                hash.stable_hash(sequence_number.next_child(), state);
+            }
         }
     }
 }
@@ -113,6 +121,11 @@ impl stable_hash::StableHash for ProofOfIndexingEvent<'_> {
                 redacted_events.stable_hash(field_address.child(0), state);
                 3
             }
+            Self::OffchainContent { source, hash } => {
+                source.stable_hash(field_address.child(0), state);
+                hash.stable_hash(field_address.child(1), state);
+                4
+            }
         };
 
         state.write(field_address, &[variant]);
@@ -150,6 +163,10 @@ impl fmt::Debug for ProofOfIndexingEvent<'_> {
             Self::DeterministicError { redacted_events } => {
                 builder.field("redacted_events", redacted_events);
             }
+            Self::OffchainContent { source, hash } => {
+                builder.field("source", source);
+                builder.field("hash", hash);
+            }
         }
         builder.finish()
     }
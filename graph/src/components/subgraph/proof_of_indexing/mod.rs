@@ -1,9 +1,10 @@
+pub mod bisect;
 mod event;
 mod online;
 mod reference;
 
 pub use event::ProofOfIndexingEvent;
-pub use online::{ProofOfIndexing, ProofOfIndexingFinisher};
+pub use online::{ProofOfIndexing, ProofOfIndexingFinisher, RecordedEvents};
 pub use reference::PoICausalityRegion;
 
 use atomic_refcell::AtomicRefCell;
@@ -341,4 +342,70 @@ mod tests {
             check(case, &mut results);
         }
     }
+
+    /// Replaying the events that recorders recorded must produce the same
+    /// digests as writing the events directly
+    #[test]
+    fn recorded_events_replay() {
+        let logger = Logger::root(Discard, o!());
+        let id = DeploymentHash::new("Qm123").unwrap();
+        let schema =
+            InputSchema::parse_latest("type User @entity { id: String!, val: Int }", id).unwrap();
+        let data = schema
+            .make_entity(hashmap! {
+                "id".into() => Value::String("id".to_owned()),
+                "val".into() => Value::Int(1)
+            })
+            .unwrap();
+
+        let set = ProofOfIndexingEvent::SetEntity {
+            entity_type: "User",
+            id: "id",
+            data: &data,
+        };
+        let remove = ProofOfIndexingEvent::RemoveEntity {
+            entity_type: "User",
+            id: "id",
+        };
+
+        for version in [ProofOfIndexingVersion::Legacy, ProofOfIndexingVersion::Fast] {
+            let mut direct = ProofOfIndexing::new(17, version);
+            direct.start_handler("eth");
+            direct.write(&logger, "eth", &set);
+            direct.write(&logger, "ipfs", &remove);
+            direct.start_handler("eth");
+            direct.write(&logger, "eth", &remove);
+            direct.write(&logger, "eth", &set);
+            direct.write_deterministic_error(&logger, "eth");
+
+            let mut replayed = ProofOfIndexing::new(17, version);
+            let mut first = replayed.recorder();
+            first.start_handler("eth");
+            first.write(&logger, "eth", &set);
+            first.write(&logger, "ipfs", &remove);
+            let mut second = replayed.recorder();
+            second.start_handler("eth");
+            second.write(&logger, "eth", &remove);
+            second.write(&logger, "eth", &set);
+            second.write_deterministic_error(&logger, "eth");
+            replayed.replay(&logger, first.take_recorded());
+            replayed.replay(&logger, second.take_recorded());
+
+            assert!(first.take().is_empty());
+            assert!(second.take().is_empty());
+
+            let digests = |poi: ProofOfIndexing| {
+                let mut digests: Vec<_> = poi
+                    .take()
+                    .into_iter()
+                    .map(|(region, stream)| (region, stream.pause(None)))
+                    .collect();
+                digests.sort();
+                digests
+            };
+            let direct = digests(direct);
+            assert_eq!(2, direct.len());
+            assert_eq!(direct, digests(replayed));
+        }
+    }
 }
@@ -6,7 +6,7 @@ use super::{ProofOfIndexingEvent, ProofOfIndexingVersion};
 use crate::{
     blockchain::BlockPtr,
     data::store::Id,
-    prelude::{debug, BlockNumber, DeploymentHash, Logger, ENV_VARS},
+    prelude::{debug, BlockNumber, DeploymentHash, Entity, Logger, ENV_VARS},
     util::stable_hash_glue::AsBytes,
 };
 use stable_hash::{fast::FastStableHasher, FieldAddress, StableHash, StableHasher};
@@ -168,8 +168,78 @@ pub struct ProofOfIndexing {
     /// state with other data sources. This may also give us some freedom to change
     /// the order of triggers in the future.
     per_causality_region: HashMap<Id, BlockEventStream>,
+    /// For a `ProofOfIndexing` created with `recorder`, the events that
+    /// were recorded instead of being added to the digests
+    recorded: Option<Vec<RecordedEvent>>,
 }
 
+/// An event that a `ProofOfIndexing` recorded to be replayed later
+enum RecordedEvent {
+    StartHandler {
+        causality_region: String,
+    },
+    RemoveEntity {
+        causality_region: String,
+        entity_type: String,
+        id: String,
+    },
+    SetEntity {
+        causality_region: String,
+        entity_type: String,
+        id: String,
+        data: Entity,
+    },
+    DeterministicError {
+        causality_region: String,
+        redacted_events: Option<u64>,
+    },
+    OffchainContent {
+        causality_region: String,
+        source: String,
+        hash: String,
+    },
+}
+
+impl RecordedEvent {
+    fn new(causality_region: &str, event: &ProofOfIndexingEvent<'_>) -> Self {
+        let causality_region = causality_region.to_owned();
+        match event {
+            ProofOfIndexingEvent::RemoveEntity { entity_type, id } => RecordedEvent::RemoveEntity {
+                causality_region,
+                entity_type: entity_type.to_string(),
+                id: id.to_string(),
+            },
+            ProofOfIndexingEvent::SetEntity {
+                entity_type,
+                id,
+                data,
+            } => RecordedEvent::SetEntity {
+                causality_region,
+                entity_type: entity_type.to_string(),
+                id: id.to_string(),
+                data: (*data).clone(),
+            },
+            ProofOfIndexingEvent::DeterministicError { redacted_events } => {
+                RecordedEvent::DeterministicError {
+                    causality_region,
+                    redacted_events: Some(*redacted_events),
+                }
+            }
+            ProofOfIndexingEvent::OffchainContent { source, hash } => {
+                RecordedEvent::OffchainContent {
+                    causality_region,
+                    source: source.to_string(),
+                    hash: hash.to_string(),
+                }
+            }
+        }
+    }
+}
+
+/// The events that a `ProofOfIndexing` created with `recorder` recorded
+#[derive(Default)]
+pub struct RecordedEvents(Vec<RecordedEvent>);
+
 impl fmt::Debug for ProofOfIndexing {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_tuple("ProofOfIndexing").field(&"...").finish()
@@ -182,12 +252,109 @@ impl ProofOfIndexing {
             version,
             block_number,
             per_causality_region: HashMap::new(),
+            recorded: None,
+        }
+    }
+
+    /// Create a `ProofOfIndexing` for the same block that records events
+    /// instead of adding them to the digests. Since the digests depend on
+    /// the order of events, this makes it possible to run handlers in a
+    /// different order than the one in which their events need to be
+    /// added, and to `replay` the events in the right order afterwards
+    pub fn recorder(&self) -> Self {
+        Self {
+            version: self.version,
+            block_number: self.block_number,
+            per_causality_region: HashMap::new(),
+            recorded: Some(Vec::new()),
+        }
+    }
+
+    /// Remove the events that were recorded so far and return them
+    pub fn take_recorded(&mut self) -> RecordedEvents {
+        RecordedEvents(
+            self.recorded
+                .as_mut()
+                .map(std::mem::take)
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Add the `events` that a recorder recorded as if they had been
+    /// written to this `ProofOfIndexing`
+    pub fn replay(&mut self, logger: &Logger, events: RecordedEvents) {
+        for event in events.0 {
+            match event {
+                RecordedEvent::StartHandler { causality_region } => {
+                    self.start_handler(&causality_region)
+                }
+                RecordedEvent::RemoveEntity {
+                    causality_region,
+                    entity_type,
+                    id,
+                } => self.write(
+                    logger,
+                    &causality_region,
+                    &ProofOfIndexingEvent::RemoveEntity {
+                        entity_type: &entity_type,
+                        id: &id,
+                    },
+                ),
+                RecordedEvent::SetEntity {
+                    causality_region,
+                    entity_type,
+                    id,
+                    data,
+                } => self.write(
+                    logger,
+                    &causality_region,
+                    &ProofOfIndexingEvent::SetEntity {
+                        entity_type: &entity_type,
+                        id: &id,
+                        data: &data,
+                    },
+                ),
+                RecordedEvent::DeterministicError {
+                    causality_region,
+                    redacted_events: None,
+                } => self.write_deterministic_error(logger, &causality_region),
+                RecordedEvent::DeterministicError {
+                    causality_region,
+                    redacted_events: Some(redacted_events),
+                } => self.write(
+                    logger,
+                    &causality_region,
+                    &ProofOfIndexingEvent::DeterministicError { redacted_events },
+                ),
+                RecordedEvent::OffchainContent {
+                    causality_region,
+                    source,
+                    hash,
+                } => self.write(
+                    logger,
+                    &causality_region,
+                    &ProofOfIndexingEvent::OffchainContent {
+                        source: &source,
+                        hash: &hash,
+                    },
+                ),
+            }
         }
     }
 }
 
 impl ProofOfIndexing {
     pub fn write_deterministic_error(&mut self, logger: &Logger, causality_region: &str) {
+        // The number of redacted events depends on the events before this
+        // one, which a recorder does not know
+        if let Some(recorded) = self.recorded.as_mut() {
+            recorded.push(RecordedEvent::DeterministicError {
+                causality_region: causality_region.to_owned(),
+                redacted_events: None,
+            });
+            return;
+        }
+
         let redacted_events = self.with_causality_region(causality_region, |entry| {
             entry.vec_length - entry.handler_start
         });
@@ -216,10 +383,22 @@ impl ProofOfIndexing {
             );
         }
 
+        if let Some(recorded) = self.recorded.as_mut() {
+            recorded.push(RecordedEvent::new(causality_region, event));
+            return;
+        }
+
         self.with_causality_region(causality_region, |entry| entry.write(event))
     }
 
     pub fn start_handler(&mut self, causality_region: &str) {
+        if let Some(recorded) = self.recorded.as_mut() {
+            recorded.push(RecordedEvent::StartHandler {
+                causality_region: causality_region.to_owned(),
+            });
+            return;
+        }
+
         self.with_causality_region(causality_region, |entry| entry.start_handler())
     }
 
@@ -1,4 +1,5 @@
 use super::ProofOfIndexingEvent;
+use crate::data_source::CausalityRegion;
 use crate::prelude::DeploymentHash;
 use crate::util::stable_hash_glue::{impl_stable_hash, AsBytes};
 use std::collections::HashMap;
@@ -41,6 +42,10 @@ impl PoICausalityRegion<'_> {
     pub fn from_network(network: &str) -> String {
         format!("ethereum/{}", network)
     }
+
+    pub fn from_offchain(causality_region: CausalityRegion) -> String {
+        format!("offchain/{}", causality_region)
+    }
 }
 
 #[derive(Default)]
@@ -4,7 +4,7 @@ use std::slice::Iter;
 /// Used for checking if a response hit the cache.
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub enum CacheStatus {
-    /// Hit is a hit in the generational cache.
+    /// Hit is a hit in the generational cache or the shared cache.
     Hit,
 
     /// Shared is a hit in the herd cache.
@@ -46,6 +46,7 @@ pub enum QueryExecutionError {
     SubgraphDeploymentIdError(String),
     RangeArgumentsError(&'static str, u32, i64),
     InvalidFilterError,
+    InvalidCursor(String),
     EntityFieldError(String, String),
     ListTypesError(String, Vec<String>),
     ListFilterError(String),
@@ -78,6 +79,12 @@ pub enum QueryExecutionError {
     IdMissing,
     IdNotString,
     ConstraintViolation(String),
+    SqlError(String),
+    PersistedQueryNotFound,
+    PersistedQueryHashMismatch,
+    QueryNotAllowlisted,
+    RateLimited(String),
+    ComplexityBudgetExhausted(String),
 }
 
 impl QueryExecutionError {
@@ -104,6 +111,7 @@ impl QueryExecutionError {
             | MultipleSubscriptionFields
             | SubgraphDeploymentIdError(_)
             | InvalidFilterError
+            | InvalidCursor(_)
             | EntityFieldError(_, _)
             | ListTypesError(_, _)
             | ListFilterError(_)
@@ -138,7 +146,13 @@ impl QueryExecutionError {
             | DeploymentNotFound(_)
             | IdMissing
             | IdNotString
-            | ConstraintViolation(_) => false,
+            | ConstraintViolation(_)
+            | SqlError(_)
+            | PersistedQueryNotFound
+            | PersistedQueryHashMismatch
+            | QueryNotAllowlisted
+            | RateLimited(_)
+            | ComplexityBudgetExhausted(_) => false,
         }
     }
 }
@@ -223,6 +237,7 @@ impl fmt::Display for QueryExecutionError {
                 write!(f, "The `{}` argument must be between 0 and {}, but is {}", arg, max, actual)
             }
             InvalidFilterError => write!(f, "Filter must by an object"),
+            InvalidCursor(s) => write!(f, "Invalid cursor: {}", s),
             EntityFieldError(e, a) => {
                 write!(f, "Entity `{}` has no attribute `{}`", e, a)
             }
@@ -289,6 +304,14 @@ impl fmt::Display for QueryExecutionError {
             IdMissing => write!(f, "entity is missing an `id` attribute"),
             IdNotString => write!(f, "entity `id` attribute is not a string"),
             ConstraintViolation(msg) => write!(f, "internal constraint violated: {}", msg),
+            SqlError(msg) => write!(f, "SQL query failed: {}", msg),
+            // Apollo clients look for exactly this message to decide that
+            // they need to send the full query text
+            PersistedQueryNotFound => write!(f, "PersistedQueryNotFound"),
+            PersistedQueryHashMismatch => write!(f, "provided sha256Hash does not match query"),
+            QueryNotAllowlisted => write!(f, "the query is not on the allowlist of this deployment"),
+            RateLimited(key) => write!(f, "the query rate limit for `{}` has been exceeded", key),
+            ComplexityBudgetExhausted(key) => write!(f, "the query complexity budget for `{}` has been used up", key),
         }
     }
 }
@@ -0,0 +1,261 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::components::store::BlockNumber;
+
+use super::QueryExecutionError;
+
+/// The position of a consumer in the entity change feed of a deployment.
+/// Consumers pass the cursor of the last event they processed back to
+/// resume the feed from there.
+///
+/// Besides the last block the consumer has seen, the cursor records the
+/// last revert of the deployment the consumer knows about so that reverts
+/// that happen while the consumer is disconnected are not lost
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FeedCursor {
+    pub block: BlockNumber,
+    pub revert: i64,
+}
+
+impl fmt::Display for FeedCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.block, self.revert)
+    }
+}
+
+impl FromStr for FeedCursor {
+    type Err = QueryExecutionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || QueryExecutionError::InvalidCursor(s.to_string());
+
+        let (block, revert) = s.split_once('-').ok_or_else(invalid)?;
+        let block = block.parse::<BlockNumber>().map_err(|_| invalid())?;
+        let revert = revert.parse::<i64>().map_err(|_| invalid())?;
+        if block < 0 || revert < 0 {
+            return Err(invalid());
+        }
+        Ok(FeedCursor { block, revert })
+    }
+}
+
+/// The kind of change that was made to an entity
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedOp {
+    Insert,
+    Update,
+    Delete,
+}
+
+impl FeedOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FeedOp::Insert => "insert",
+            FeedOp::Update => "update",
+            FeedOp::Delete => "delete",
+        }
+    }
+}
+
+impl FromStr for FeedOp {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "insert" => Ok(FeedOp::Insert),
+            "update" => Ok(FeedOp::Update),
+            "delete" => Ok(FeedOp::Delete),
+            _ => Err(anyhow::anyhow!("invalid feed operation `{}`", s)),
+        }
+    }
+}
+
+/// A change to one entity
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeedChange {
+    /// The block in which the change happened
+    pub block: BlockNumber,
+    /// The hash of `block`
+    pub hash: String,
+    pub entity_type: String,
+    pub id: String,
+    pub op: FeedOp,
+    /// The entity after the change; `None` for deletions
+    pub data: Option<serde_json::Value>,
+}
+
+/// Turns the versions of entities that started and ended in some blocks
+/// into a list of changes. Writing an entity that already exists ends its
+/// current version and starts a new one, which is why changes can not be
+/// classified by looking at versions that started alone: a version that
+/// ends in the block in which a new version of the same entity starts was
+/// updated, and one that ends without a new version starting was deleted
+#[derive(Default)]
+pub struct FeedChanges {
+    // Keyed by (block, entity type, id) so that iterating over the map
+    // produces the changes in the order we want
+    changes: BTreeMap<(BlockNumber, String, String), (FeedOp, Option<serde_json::Value>)>,
+}
+
+impl FeedChanges {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the changes to entities of type `entity_type`. Each entry in
+    /// `started` is the block and id of a version that started, the
+    /// operation to report unless an earlier version ended in the same
+    /// block, and the data of the version. Each entry in `ended` is the
+    /// block and id of a version that ended
+    pub fn add(
+        &mut self,
+        entity_type: &str,
+        started: Vec<(BlockNumber, String, FeedOp, Option<serde_json::Value>)>,
+        mut ended: HashSet<(BlockNumber, String)>,
+    ) {
+        for (block, id, op, data) in started {
+            let op = if ended.remove(&(block, id.clone())) {
+                FeedOp::Update
+            } else {
+                op
+            };
+            self.changes
+                .insert((block, entity_type.to_string(), id), (op, data));
+        }
+        for (block, id) in ended {
+            self.changes
+                .insert((block, entity_type.to_string(), id), (FeedOp::Delete, None));
+        }
+    }
+
+    /// The changes ordered by block, entity type, and id. The hash of
+    /// each change's block is looked up with `hash`
+    pub fn into_changes(self, hash: impl Fn(BlockNumber) -> String) -> Vec<FeedChange> {
+        self.changes
+            .into_iter()
+            .map(|((block, entity_type, id), (op, data))| FeedChange {
+                block,
+                hash: hash(block),
+                entity_type,
+                id,
+                op,
+                data,
+            })
+            .collect()
+    }
+}
+
+/// An event in the entity change feed of a deployment
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum FeedEvent {
+    /// All changes in the blocks after the previous event up to and
+    /// including `block`, whose hash is `hash`
+    Changes {
+        block: BlockNumber,
+        hash: String,
+        changes: Vec<FeedChange>,
+        #[serde(skip)]
+        cursor: FeedCursor,
+    },
+    /// The deployment was reverted to `block`; consumers must undo all
+    /// changes they have seen for blocks after `block`
+    Revert {
+        block: BlockNumber,
+        hash: String,
+        #[serde(skip)]
+        cursor: FeedCursor,
+    },
+}
+
+impl FeedEvent {
+    /// The cursor with which the feed can be resumed after this event
+    pub fn cursor(&self) -> FeedCursor {
+        match self {
+            FeedEvent::Changes { cursor, .. } | FeedEvent::Revert { cursor, .. } => *cursor,
+        }
+    }
+
+    /// The name of the event for server-sent events
+    pub fn name(&self) -> &'static str {
+        match self {
+            FeedEvent::Changes { .. } => "changes",
+            FeedEvent::Revert { .. } => "revert",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_roundtrip() {
+        let cursor = FeedCursor {
+            block: 17,
+            revert: 4,
+        };
+        assert_eq!("17-4", cursor.to_string());
+        assert_eq!(cursor, "17-4".parse().unwrap());
+
+        assert!("17".parse::<FeedCursor>().is_err());
+        assert!("-1-4".parse::<FeedCursor>().is_err());
+        assert!("17-x".parse::<FeedCursor>().is_err());
+    }
+
+    #[test]
+    fn classify_changes() {
+        use FeedOp::*;
+
+        let mut changes = FeedChanges::new();
+        let started = vec![
+            (1, "a".to_string(), Insert, None),
+            (2, "a".to_string(), Insert, None),
+            (2, "b".to_string(), Update, None),
+        ];
+        let ended = HashSet::from([(2, "a".to_string()), (3, "a".to_string())]);
+        changes.add("Thing", started, ended);
+        changes.add(
+            "Other",
+            vec![(2, "a".to_string(), Insert, None)],
+            HashSet::new(),
+        );
+
+        let changes: Vec<_> = changes
+            .into_changes(|block| format!("0x{block:02x}"))
+            .into_iter()
+            .map(|change| {
+                (
+                    change.block,
+                    change.hash,
+                    change.entity_type,
+                    change.id,
+                    change.op,
+                )
+            })
+            .collect();
+        let change = |block, entity_type: &str, id: &str, op| {
+            (
+                block,
+                format!("0x{block:02x}"),
+                entity_type.to_string(),
+                id.to_string(),
+                op,
+            )
+        };
+        let exp = vec![
+            change(1, "Thing", "a", Insert),
+            change(2, "Other", "a", Insert),
+            change(2, "Thing", "a", Update),
+            change(2, "Thing", "b", Update),
+            change(3, "Thing", "a", Delete),
+        ];
+        assert_eq!(exp, changes);
+    }
+}
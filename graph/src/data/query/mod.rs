@@ -1,11 +1,19 @@
 mod cache_status;
 mod error;
+mod feed;
+mod persisted;
+mod plan;
 mod query;
 mod result;
+mod sql;
 mod trace;
 
 pub use self::cache_status::CacheStatus;
 pub use self::error::{QueryError, QueryExecutionError};
+pub use self::feed::{FeedChange, FeedChanges, FeedCursor, FeedEvent, FeedOp};
+pub use self::persisted::PersistedQueries;
+pub use self::plan::{FieldPlan, QueryPlan, SqlPlan};
 pub use self::query::{Query, QueryTarget, QueryVariables};
 pub use self::result::{QueryResult, QueryResults};
+pub use self::sql::{SqlQueryObject, SqlQueryReq};
 pub use self::trace::Trace;
@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+
+use sha2::{Digest, Sha256};
+
+/// The persisted queries that are registered for a deployment. Clients
+/// can send the hash of a registered query instead of its text, following
+/// the conventions of Apollo's automatic persisted queries. When
+/// `allowlist_only` is set, the deployment only executes registered
+/// queries and rejects everything else before the query is parsed
+#[derive(Clone, Debug, Default)]
+pub struct PersistedQueries {
+    /// Query documents keyed by the hash computed by `PersistedQueries::hash`
+    queries: HashMap<String, String>,
+    pub allowlist_only: bool,
+}
+
+impl PersistedQueries {
+    pub fn new(queries: impl IntoIterator<Item = (String, String)>, allowlist_only: bool) -> Self {
+        PersistedQueries {
+            queries: queries.into_iter().collect(),
+            allowlist_only,
+        }
+    }
+
+    /// The hash under which `query` is registered: the hex-encoded SHA-256
+    /// digest of the exact query text
+    pub fn hash(query: &str) -> String {
+        hex::encode(Sha256::digest(query.as_bytes()))
+    }
+
+    /// Return the query document registered under `hash`
+    pub fn get(&self, hash: &str) -> Option<&str> {
+        self.queries
+            .get(&hash.to_ascii_lowercase())
+            .map(String::as_str)
+    }
+
+    /// Return `true` if the deployment may execute `query`
+    pub fn allows(&self, query: &str) -> bool {
+        !self.allowlist_only || self.get(&Self::hash(query)).is_some()
+    }
+
+    /// The registered queries as pairs of hash and query document
+    pub fn queries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.queries
+            .iter()
+            .map(|(hash, query)| (hash.as_str(), query.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.queries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queries.is_empty()
+    }
+}
+
+#[test]
+fn persisted_queries() {
+    const QUERY: &str = "{ tokens { id } }";
+
+    let hash = PersistedQueries::hash(QUERY);
+    assert_eq!(64, hash.len());
+
+    let queries = PersistedQueries::new(vec![(hash.clone(), QUERY.to_string())], false);
+    assert_eq!(Some(QUERY), queries.get(&hash));
+    assert_eq!(Some(QUERY), queries.get(&hash.to_ascii_uppercase()));
+    assert!(queries.allows("{ other { id } }"));
+
+    let queries = PersistedQueries {
+        allowlist_only: true,
+        ..queries
+    };
+    assert!(queries.allows(QUERY));
+    assert!(!queries.allows("{ other { id } }"));
+}
@@ -0,0 +1,44 @@
+use serde::Serialize;
+
+/// The SQL query that the store would run to fetch the entities for a
+/// field, and the plan that Postgres chooses for it if it was requested
+#[derive(Clone, Debug, Serialize)]
+pub struct SqlPlan {
+    pub query: String,
+    /// The output of `explain` for the query, one line per entry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explain: Option<Vec<String>>,
+}
+
+/// How the entities for one field of a GraphQL query would be fetched
+#[derive(Clone, Debug, Serialize)]
+pub struct FieldPlan {
+    /// The response key of the field, i.e., its alias or its name
+    pub field: String,
+    #[serde(flatten)]
+    pub sql: SqlPlan,
+    /// The plans for the fields that are nested in this one
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldPlan>,
+}
+
+impl FieldPlan {
+    pub fn new(field: &str, sql: SqlPlan) -> Self {
+        FieldPlan {
+            field: field.to_string(),
+            sql,
+            fields: Vec::new(),
+        }
+    }
+}
+
+/// How a GraphQL query would be executed, computed without running it
+#[derive(Clone, Debug, Serialize)]
+pub struct QueryPlan {
+    /// The complexity of the query
+    pub complexity: u64,
+    /// The complexity above which queries are rejected, if there is a limit
+    pub max_complexity: Option<u64>,
+    /// The plans for the toplevel fields of the query
+    pub fields: Vec<FieldPlan>,
+}
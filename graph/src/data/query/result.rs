@@ -47,12 +47,16 @@ pub type Data = Object;
 /// A collection of query results that is serialized as a single result.
 pub struct QueryResults {
     results: Vec<Arc<QueryResult>>,
+    /// The complexity of the query that produced these results, or 0 if
+    /// the query never got far enough to compute it
+    complexity: u64,
 }
 
 impl QueryResults {
     pub fn empty() -> Self {
         QueryResults {
             results: Vec::new(),
+            complexity: 0,
         }
     }
 
@@ -82,6 +86,14 @@ impl QueryResults {
     pub fn errors(&self) -> Vec<QueryError> {
         self.results.iter().flat_map(|r| r.errors.clone()).collect()
     }
+
+    pub fn complexity(&self) -> u64 {
+        self.complexity
+    }
+
+    pub fn set_complexity(&mut self, complexity: u64) {
+        self.complexity = complexity;
+    }
 }
 
 impl Serialize for QueryResults {
@@ -149,6 +161,7 @@ impl From<Data> for QueryResults {
     fn from(x: Data) -> Self {
         QueryResults {
             results: vec![Arc::new(x.into())],
+            complexity: 0,
         }
     }
 }
@@ -157,13 +170,17 @@ impl From<QueryResult> for QueryResults {
     fn from(x: QueryResult) -> Self {
         QueryResults {
             results: vec![Arc::new(x)],
+            complexity: 0,
         }
     }
 }
 
 impl From<Arc<QueryResult>> for QueryResults {
     fn from(x: Arc<QueryResult>) -> Self {
-        QueryResults { results: vec![x] }
+        QueryResults {
+            results: vec![x],
+            complexity: 0,
+        }
     }
 }
 
@@ -171,6 +188,7 @@ impl From<QueryExecutionError> for QueryResults {
     fn from(x: QueryExecutionError) -> Self {
         QueryResults {
             results: vec![Arc::new(x.into())],
+            complexity: 0,
         }
     }
 }
@@ -179,6 +197,7 @@ impl From<Vec<QueryExecutionError>> for QueryResults {
     fn from(x: Vec<QueryExecutionError>) -> Self {
         QueryResults {
             results: vec![Arc::new(x.into())],
+            complexity: 0,
         }
     }
 }
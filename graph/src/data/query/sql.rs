@@ -0,0 +1,17 @@
+use serde::Deserialize;
+
+use crate::components::store::BlockNumber;
+
+/// A request to run a SQL query against the tables of a deployment
+#[derive(Clone, Debug, Deserialize)]
+pub struct SqlQueryReq {
+    /// The SQL query; it must consist of a single `select` statement
+    pub query: String,
+    /// The block at which to run the query. If it is not set, the query
+    /// runs against the latest block that the deployment has processed
+    pub block: Option<BlockNumber>,
+}
+
+/// One row in the result of a SQL query, a JSON object that maps column
+/// names to their values
+pub type SqlQueryObject = serde_json::Value;
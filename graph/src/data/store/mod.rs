@@ -851,6 +851,21 @@ impl Entity {
         v
     }
 
+    /// Turn the entity into a plain JSON object whose values are
+    /// represented the same way as in GraphQL responses
+    pub fn to_json(&self) -> serde_json::Value {
+        let map = self
+            .sorted_ref()
+            .into_iter()
+            .map(|(key, value)| {
+                let value = serde_json::to_value(r::Value::from(value.clone()))
+                    .unwrap_or(serde_json::Value::Null);
+                (key.to_string(), value)
+            })
+            .collect();
+        serde_json::Value::Object(map)
+    }
+
     fn check_id(&self) -> Result<(), EntityValidationError> {
         match self.get("id") {
             None => Err(EntityValidationError::MissingIDAttribute {
@@ -18,6 +18,13 @@ use crate::schema::EntityType;
 use crate::util::stable_hash_glue::impl_stable_hash;
 
 pub const POI_TABLE: &str = "poi2$";
+/// The table in which deployments that opt into it record the digests of
+/// their proof of indexing for every block
+pub const POI_HISTORY_TABLE: &str = "poi_history$";
+/// The tables in which deployments that opt into it record the changes to
+/// their entities and the blocks they wrote for the entity change feed
+pub const FEED_TABLE: &str = "feed$";
+pub const FEED_BLOCK_TABLE: &str = "feed_block$";
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug, Deserialize)]
 #[serde(rename_all = "lowercase")]
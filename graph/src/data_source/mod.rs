@@ -9,7 +9,7 @@ mod tests;
 use crate::{
     blockchain::{
         Block, BlockPtr, BlockTime, Blockchain, DataSource as _, DataSourceTemplate as _,
-        MappingTriggerTrait, TriggerData as _, UnresolvedDataSource as _,
+        MappingTriggerTrait, PrefetchHints, TriggerData as _, UnresolvedDataSource as _,
         UnresolvedDataSourceTemplate as _,
     },
     components::{
@@ -179,6 +179,15 @@ impl<C: Blockchain> DataSource<C> {
         }
     }
 
+    /// The names of the entity types that the manifest declares for this
+    /// data source, regardless of whether access is restricted to them
+    pub fn declared_entities(&self) -> Vec<&str> {
+        match self {
+            Self::Onchain(ds) => ds.declared_entities().iter().map(String::as_str).collect(),
+            Self::Offchain(ds) => ds.mapping.entities.iter().map(EntityType::as_str).collect(),
+        }
+    }
+
     pub fn handler_kinds(&self) -> HashSet<&str> {
         match self {
             Self::Onchain(ds) => ds.handler_kinds(),
@@ -477,6 +486,13 @@ impl<C: Blockchain> TriggerData<C> {
             Self::Offchain(trigger) => format!("{:?}", trigger.source),
         }
     }
+
+    pub fn prefetch_hints(&self) -> Option<PrefetchHints> {
+        match self {
+            Self::Onchain(trigger) => trigger.prefetch_hints(),
+            Self::Offchain(_) => None,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -30,6 +30,7 @@ lazy_static! {
     pub static ref OFFCHAIN_KINDS: HashMap<&'static str, OffchainDataSourceKind> = [
         ("file/ipfs", OffchainDataSourceKind::Ipfs),
         ("file/arweave", OffchainDataSourceKind::Arweave),
+        ("file/http", OffchainDataSourceKind::Http),
     ]
     .into_iter()
     .collect();
@@ -42,6 +43,7 @@ const NOT_DONE_VALUE: i32 = -1;
 pub enum OffchainDataSourceKind {
     Ipfs,
     Arweave,
+    Http,
 }
 impl OffchainDataSourceKind {
     pub fn try_parse_source(&self, bs: Bytes) -> Result<Source, anyhow::Error> {
@@ -54,6 +56,10 @@ impl OffchainDataSourceKind {
                 let base64 = Word::from(String::from_utf8(bs.to_vec())?);
                 Source::Arweave(base64)
             }
+            OffchainDataSourceKind::Http => {
+                let url = String::from_utf8(bs.to_vec())?;
+                Source::Http(parse_http_url(&url)?)
+            }
         };
         Ok(source)
     }
@@ -190,6 +196,11 @@ impl DataSource {
                 Err(e) => return Err(DataSourceCreationError::Ignore(source, e)),
             },
             OffchainDataSourceKind::Arweave => Source::Arweave(Word::from(source)),
+            OffchainDataSourceKind::Http => match parse_http_url(&source) {
+                Ok(url) => Source::Http(url),
+                // Ignore data sources created with an invalid URL.
+                Err(e) => return Err(DataSourceCreationError::Ignore(source, e)),
+            },
         };
 
         Ok(Self {
@@ -311,10 +322,28 @@ impl DataSource {
 
 pub type Base64 = Word;
 
+/// An `http` or `https` URL
+pub type HttpUrl = Word;
+
+/// Check that `url` is an `http` or `https` URL. The URL is kept as it was
+/// given so that the `address` of the data source is exactly what the
+/// mapping passed in
+fn parse_http_url(url: &str) -> Result<HttpUrl, Error> {
+    let parsed = url::Url::parse(url).with_context(|| format!("invalid URL `{}`", url))?;
+    if parsed.scheme() != "http" && parsed.scheme() != "https" {
+        return Err(anyhow!("URL `{}` must use http or https", url));
+    }
+    Ok(Word::from(url))
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Source {
     Ipfs(CidFile),
     Arweave(Base64),
+    /// Content served at a URL. Unlike for the other sources, the content
+    /// is not determined by the source, which is why the hash of the
+    /// content that the handler processed is added to the PoI
+    Http(HttpUrl),
 }
 
 impl Source {
@@ -328,6 +357,7 @@ impl Source {
         match self {
             Source::Ipfs(ref cid) => Some(cid.to_bytes()),
             Source::Arweave(ref base64) => Some(base64.as_bytes().to_vec()),
+            Source::Http(ref url) => Some(url.as_bytes().to_vec()),
         }
     }
 }
@@ -337,6 +367,7 @@ impl Into<Bytes> for Source {
         match self {
             Source::Ipfs(ref link) => Bytes::from(link.to_bytes()),
             Source::Arweave(ref base64) => Bytes::from(base64.as_bytes()),
+            Source::Http(ref url) => Bytes::from(url.as_bytes()),
         }
     }
 }
@@ -551,5 +582,20 @@ mod test {
             .try_parse_source(arweave_source.into())
             .unwrap();
         assert! { matches!(s, Source::Arweave(b64) if b64.eq(&base64))};
+
+        let url = "https://example.com/tokens/1.json";
+        let http_source = Source::Http(Word::from(url));
+        let s = OffchainDataSourceKind::Http
+            .try_parse_source(http_source.into())
+            .unwrap();
+        assert! { matches!(s, Source::Http(u) if u.eq(&url))};
+    }
+
+    #[test]
+    fn test_http_source_requires_http_url() {
+        let kind = OffchainDataSourceKind::Http;
+        for url in ["ftp://example.com/1.json", "example.com/1.json", ""] {
+            assert!(kind.try_parse_source(Bytes::from(url.as_bytes())).is_err());
+        }
     }
 }
@@ -53,6 +53,24 @@ pub struct EnvVarsGraphQl {
     /// Set by the environment variable `GRAPH_QUERY_CACHE_STALE_PERIOD`. The
     /// default value is 100.
     pub query_cache_stale_period: u64,
+    /// The URL of a Redis server, like `redis://cache:6379`, in which query
+    /// nodes share the results of queries with each other.
+    ///
+    /// Set by the environment variable `GRAPH_QUERY_SHARED_CACHE_URL`. Not
+    /// set by default, which disables the shared cache.
+    pub query_shared_cache_url: Option<String>,
+    /// How long to wait for the shared cache before running a query without
+    /// it.
+    ///
+    /// Set by the environment variable `GRAPH_QUERY_SHARED_CACHE_TIMEOUT`
+    /// (expressed in milliseconds). The default value is 100ms.
+    pub query_shared_cache_timeout: Duration,
+    /// How long results are kept in the shared cache unless they are removed
+    /// earlier because their block is too far behind the chain head.
+    ///
+    /// Set by the environment variable `GRAPH_QUERY_SHARED_CACHE_TTL`
+    /// (expressed in seconds). The default value is 600s.
+    pub query_shared_cache_ttl: Duration,
     /// Set by the environment variable `GRAPH_GRAPHQL_QUERY_TIMEOUT` (expressed in
     /// seconds). No default value is provided.
     pub query_timeout: Option<Duration>,
@@ -94,6 +112,25 @@ pub struct EnvVarsGraphQl {
     /// header `X-GraphTraceQuery` set to this value will include a trace of
     /// the SQL queries that were run.
     pub query_trace_token: String,
+    /// Set by the flag `GRAPH_ENABLE_SQL_QUERIES`. Off by default.
+    /// Enables the `/subgraphs/id/<ID>/sql` endpoint
+    pub enable_sql_queries: bool,
+    /// Set by the flag `GRAPH_ENABLE_ENTITY_FEED`. Off by default.
+    /// Enables the `/subgraphs/id/<ID>/changes` endpoint
+    pub enable_entity_feed: bool,
+    /// Set by the environment variable `GRAPH_ENTITY_FEED_POLL_INTERVAL`
+    /// (expressed in milliseconds). How long to wait before checking a
+    /// deployment for new changes when a consumer of the entity change
+    /// feed has seen all of them. The default value is 1000ms.
+    pub entity_feed_poll_interval: Duration,
+    /// Set by the environment variable `GRAPH_ENTITY_FEED_MAX_BLOCKS`. The
+    /// maximum number of blocks whose changes are put into one event of
+    /// the entity change feed, and therefore read from the database with
+    /// one poll. The default value is 100.
+    pub entity_feed_max_blocks: BlockNumber,
+    /// Set by the flag `GRAPH_ENABLE_QUERY_EXPLAIN`. Off by default.
+    /// Enables the `/subgraphs/id/<ID>/explain` endpoint
+    pub enable_query_explain: bool,
 }
 
 // This does not print any values avoid accidentally leaking any sensitive env vars
@@ -126,6 +163,11 @@ impl From<InnerGraphQl> for EnvVarsGraphQl {
             query_cache_blocks: x.query_cache_blocks,
             query_cache_max_mem: x.query_cache_max_mem_in_mb.0 * 1000 * 1000,
             query_cache_stale_period: x.query_cache_stale_period,
+            query_shared_cache_url: x.query_shared_cache_url,
+            query_shared_cache_timeout: Duration::from_millis(
+                x.query_shared_cache_timeout_in_millis,
+            ),
+            query_shared_cache_ttl: Duration::from_secs(x.query_shared_cache_ttl_in_secs),
             query_timeout: x.query_timeout_in_secs.map(Duration::from_secs),
             max_complexity: x.max_complexity.map(|x| x.0),
             max_depth: x.max_depth.0,
@@ -138,6 +180,11 @@ impl From<InnerGraphQl> for EnvVarsGraphQl {
             disable_bool_filters: x.disable_bool_filters.0,
             disable_child_sorting: x.disable_child_sorting.0,
             query_trace_token: x.query_trace_token,
+            enable_sql_queries: x.enable_sql_queries.0,
+            enable_entity_feed: x.enable_entity_feed.0,
+            entity_feed_poll_interval: Duration::from_millis(x.entity_feed_poll_interval_in_millis),
+            entity_feed_max_blocks: x.entity_feed_max_blocks,
+            enable_query_explain: x.enable_query_explain.0,
         }
     }
 }
@@ -163,6 +210,12 @@ pub struct InnerGraphQl {
     query_cache_max_mem_in_mb: NoUnderscores<usize>,
     #[envconfig(from = "GRAPH_QUERY_CACHE_STALE_PERIOD", default = "100")]
     query_cache_stale_period: u64,
+    #[envconfig(from = "GRAPH_QUERY_SHARED_CACHE_URL")]
+    query_shared_cache_url: Option<String>,
+    #[envconfig(from = "GRAPH_QUERY_SHARED_CACHE_TIMEOUT", default = "100")]
+    query_shared_cache_timeout_in_millis: u64,
+    #[envconfig(from = "GRAPH_QUERY_SHARED_CACHE_TTL", default = "600")]
+    query_shared_cache_ttl_in_secs: u64,
     #[envconfig(from = "GRAPH_GRAPHQL_QUERY_TIMEOUT")]
     query_timeout_in_secs: Option<u64>,
     #[envconfig(from = "GRAPH_GRAPHQL_MAX_COMPLEXITY")]
@@ -187,4 +240,14 @@ pub struct InnerGraphQl {
     pub disable_child_sorting: EnvVarBoolean,
     #[envconfig(from = "GRAPH_GRAPHQL_TRACE_TOKEN", default = "")]
     query_trace_token: String,
+    #[envconfig(from = "GRAPH_ENABLE_SQL_QUERIES", default = "false")]
+    enable_sql_queries: EnvVarBoolean,
+    #[envconfig(from = "GRAPH_ENABLE_ENTITY_FEED", default = "false")]
+    enable_entity_feed: EnvVarBoolean,
+    #[envconfig(from = "GRAPH_ENTITY_FEED_POLL_INTERVAL", default = "1000")]
+    entity_feed_poll_interval_in_millis: u64,
+    #[envconfig(from = "GRAPH_ENTITY_FEED_MAX_BLOCKS", default = "100")]
+    entity_feed_max_blocks: BlockNumber,
+    #[envconfig(from = "GRAPH_ENABLE_QUERY_EXPLAIN", default = "false")]
+    enable_query_explain: EnvVarBoolean,
 }
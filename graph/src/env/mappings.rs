@@ -1,4 +1,5 @@
 use std::fmt;
+use std::path::PathBuf;
 
 use super::*;
 
@@ -15,6 +16,11 @@ pub struct EnvVarsMapping {
     /// Set by the environment variable `GRAPH_ENTITY_CACHE_SIZE` (expressed in
     /// kilobytes). The default value is 10 megabytes.
     pub entity_cache_size: usize,
+    /// Learn which entities the handlers for a trigger load and load them
+    /// with batched queries before running the handlers.
+    ///
+    /// Set by the flag `GRAPH_ENTITY_PREFETCH`. Off by default.
+    pub entity_prefetch: bool,
     /// Set by the environment variable `GRAPH_MAX_API_VERSION`. The default
     /// value is `0.0.8`.
     pub max_api_version: Version,
@@ -54,9 +60,40 @@ pub struct EnvVarsMapping {
     /// Set by the environment variable `GRAPH_IPFS_REQUEST_LIMIT`. Defaults to 100.
     pub ipfs_request_limit: u16,
 
+    /// Keep the content of IPFS and Arweave files on disk in this
+    /// directory so that it does not need to be fetched again.
+    ///
+    /// Set by the environment variable `GRAPH_CONTENT_CACHE_DIR`. Off by
+    /// default.
+    pub content_cache_dir: Option<PathBuf>,
+    /// The size of the files in the content cache that, when exceeded,
+    /// causes the files that were used least recently to be removed.
+    ///
+    /// Set by the environment variable `GRAPH_CONTENT_CACHE_SIZE`
+    /// (expressed in megabytes). The default value is 10 gigabytes.
+    pub content_cache_size: u64,
+
     /// Set by the flag `GRAPH_ALLOW_NON_DETERMINISTIC_IPFS`. Off by
     /// default.
     pub allow_non_deterministic_ipfs: bool,
+
+    /// Profile mapping handlers and write the profile of each deployment
+    /// into this directory.
+    ///
+    /// Set by the environment variable `GRAPH_MAPPING_PROFILE_DIR`. Off by
+    /// default.
+    pub profile_dir: Option<PathBuf>,
+    /// How often the stack of a handler is sampled while profiling.
+    ///
+    /// Set by the environment variable `GRAPH_MAPPING_PROFILE_INTERVAL`
+    /// (expressed in microseconds). The default value is 1000 (1ms).
+    pub profile_interval: Duration,
+
+    /// Process the triggers of data sources that declare disjoint sets of
+    /// entities concurrently.
+    ///
+    /// Set by the flag `GRAPH_PARALLEL_TRIGGERS`. Off by default.
+    pub parallel_triggers: bool,
 }
 
 // This does not print any values avoid accidentally leaking any sensitive env vars
@@ -71,6 +108,7 @@ impl From<InnerMappingHandlers> for EnvVarsMapping {
         Self {
             entity_cache_dead_weight: x.entity_cache_dead_weight.0,
             entity_cache_size: x.entity_cache_size_in_kb * 1000,
+            entity_prefetch: x.entity_prefetch.0,
 
             max_api_version: x.max_api_version,
             timeout: x.mapping_handler_timeout_in_secs.map(Duration::from_secs),
@@ -82,7 +120,12 @@ impl From<InnerMappingHandlers> for EnvVarsMapping {
             max_ipfs_map_file_size: x.max_ipfs_map_file_size.0,
             max_ipfs_file_bytes: x.max_ipfs_file_bytes.0,
             ipfs_request_limit: x.ipfs_request_limit,
+            content_cache_dir: x.content_cache_dir.map(PathBuf::from),
+            content_cache_size: x.content_cache_size_in_mb * 1_000_000,
             allow_non_deterministic_ipfs: x.allow_non_deterministic_ipfs.0,
+            profile_dir: x.profile_dir.map(PathBuf::from),
+            profile_interval: Duration::from_micros(x.profile_interval_in_micros),
+            parallel_triggers: x.parallel_triggers.0,
         }
     }
 }
@@ -93,6 +136,8 @@ pub struct InnerMappingHandlers {
     entity_cache_dead_weight: EnvVarBoolean,
     #[envconfig(from = "GRAPH_ENTITY_CACHE_SIZE", default = "10000")]
     entity_cache_size_in_kb: usize,
+    #[envconfig(from = "GRAPH_ENTITY_PREFETCH", default = "false")]
+    entity_prefetch: EnvVarBoolean,
     #[envconfig(from = "GRAPH_MAX_API_VERSION", default = "0.0.9")]
     max_api_version: Version,
     #[envconfig(from = "GRAPH_MAPPING_HANDLER_TIMEOUT")]
@@ -113,6 +158,19 @@ pub struct InnerMappingHandlers {
     max_ipfs_file_bytes: WithDefaultUsize<usize, { 25 * 1024 * 1024 }>,
     #[envconfig(from = "GRAPH_IPFS_REQUEST_LIMIT", default = "100")]
     ipfs_request_limit: u16,
+    #[envconfig(from = "GRAPH_CONTENT_CACHE_DIR")]
+    content_cache_dir: Option<String>,
+    #[envconfig(from = "GRAPH_CONTENT_CACHE_SIZE", default = "10000")]
+    content_cache_size_in_mb: u64,
     #[envconfig(from = "GRAPH_ALLOW_NON_DETERMINISTIC_IPFS", default = "false")]
     allow_non_deterministic_ipfs: EnvVarBoolean,
+
+    // Profiling.
+    #[envconfig(from = "GRAPH_MAPPING_PROFILE_DIR")]
+    profile_dir: Option<String>,
+    #[envconfig(from = "GRAPH_MAPPING_PROFILE_INTERVAL", default = "1000")]
+    profile_interval_in_micros: u64,
+
+    #[envconfig(from = "GRAPH_PARALLEL_TRIGGERS", default = "false")]
+    parallel_triggers: EnvVarBoolean,
 }
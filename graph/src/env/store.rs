@@ -116,6 +116,14 @@ pub struct EnvVarsStore {
     pub use_brin_for_all_query_types: bool,
     /// Temporary env var to disable certain lookups in the chain store
     pub disable_block_cache_for_lookup: bool,
+    /// Whether to record which columns GraphQL queries filter and sort by
+    /// and how long they take, for use by `graphman index advise`. Set by
+    /// `GRAPH_STORE_RECORD_QUERY_WORKLOAD`. The default is `false`
+    pub record_query_workload: bool,
+    /// Queries that take longer than this are counted as slow in the
+    /// recorded query workload. Set by `GRAPH_STORE_SLOW_QUERY_THRESHOLD`
+    /// in milliseconds. The default is 1000ms
+    pub slow_query_threshold: Duration,
 }
 
 // This does not print any values avoid accidentally leaking any sensitive env vars
@@ -160,6 +168,8 @@ impl From<InnerStore> for EnvVarsStore {
             create_gin_indexes: x.create_gin_indexes,
             use_brin_for_all_query_types: x.use_brin_for_all_query_types,
             disable_block_cache_for_lookup: x.disable_block_cache_for_lookup,
+            record_query_workload: x.record_query_workload,
+            slow_query_threshold: Duration::from_millis(x.slow_query_threshold_in_millis),
         }
     }
 }
@@ -219,6 +229,10 @@ pub struct InnerStore {
     use_brin_for_all_query_types: bool,
     #[envconfig(from = "GRAPH_STORE_DISABLE_BLOCK_CACHE_FOR_LOOKUP", default = "false")]
     disable_block_cache_for_lookup: bool,
+    #[envconfig(from = "GRAPH_STORE_RECORD_QUERY_WORKLOAD", default = "false")]
+    record_query_workload: bool,
+    #[envconfig(from = "GRAPH_STORE_SLOW_QUERY_THRESHOLD", default = "1000")]
+    slow_query_threshold_in_millis: u64,
 }
 
 #[derive(Clone, Copy, Debug)]
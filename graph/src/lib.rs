@@ -120,7 +120,7 @@ pub mod prelude {
     };
     pub use crate::components::graphql::{GraphQLMetrics, GraphQlRunner, SubscriptionResultFuture};
     pub use crate::components::link_resolver::{
-        IpfsResolver, JsonStreamValue, JsonValueStream, LinkResolver,
+        IpfsResolver, JsonStreamValue, JsonValueStream, LinkResolver, LocalResolver,
     };
     pub use crate::components::metrics::{
         stopwatch::StopwatchMetrics, subgraph::*, Collector, Counter, CounterVec, Gauge, GaugeVec,
@@ -131,10 +131,10 @@ pub mod prelude {
     pub use crate::components::server::subscription::SubscriptionServer;
     pub use crate::components::store::{
         write::EntityModification, AttributeNames, BlockNumber, CachedEthereumCall, ChainStore,
-        Child, ChildMultiplicity, EntityCache, EntityChange, EntityChangeOperation,
-        EntityCollection, EntityFilter, EntityLink, EntityOperation, EntityOrder,
-        EntityOrderByChild, EntityOrderByChildInfo, EntityQuery, EntityRange, EntityWindow,
-        EthereumCallCache, ParentLink, PartialBlockPtr, PoolWaitStats, QueryStore,
+        Child, ChildMultiplicity, EntityAggregate, EntityAggregateQuery, EntityCache, EntityChange,
+        EntityChangeOperation, EntityCollection, EntityFilter, EntityLink, EntityOperation,
+        EntityOrder, EntityOrderByChild, EntityOrderByChildInfo, EntityQuery, EntityRange,
+        EntityWindow, EthereumCallCache, ParentLink, PartialBlockPtr, PoolWaitStats, QueryStore,
         QueryStoreManager, StoreError, StoreEvent, StoreEventStream, StoreEventStreamBox,
         SubgraphStore, UnfailOutcome, WindowAttribute, BLOCK_NUMBER_MAX,
     };
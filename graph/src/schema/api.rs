@@ -9,9 +9,11 @@ use thiserror::Error;
 
 use crate::cheap_clone::CheapClone;
 use crate::data::graphql::{ObjectOrInterface, ObjectTypeExt, TypeExt};
-use crate::data::store::IdType;
+use crate::data::store::{IdType, ValueType, ID};
 use crate::env::ENV_VARS;
-use crate::schema::{ast, META_FIELD_NAME, META_FIELD_TYPE, SCHEMA_TYPE_NAME};
+use crate::schema::{
+    ast, AGGREGATE_SUFFIX, CURSOR_FIELD_NAME, META_FIELD_NAME, META_FIELD_TYPE, SCHEMA_TYPE_NAME,
+};
 
 use crate::data::graphql::ext::{
     camel_cased_names, DefinitionExt, DirectiveExt, DocumentExt, ValueExt,
@@ -384,6 +386,26 @@ fn init_api_schema(input_schema: &InputSchema) -> Result<Schema, APISchemaError>
         }
     }
 
+    /// Add the `_cursor` field to entity types and interfaces unless they
+    /// already declare a field with that name
+    fn add_cursor_field(type_name: &str, fields: &mut Vec<s::Field>, input_schema: &InputSchema) {
+        let is_entity = matches!(
+            input_schema.kind_of_declared_type(type_name),
+            Some(TypeKind::Object) | Some(TypeKind::Interface)
+        );
+        if !is_entity || fields.iter().any(|field| field.name == CURSOR_FIELD_NAME) {
+            return;
+        }
+        fields.push(s::Field {
+            position: Pos::default(),
+            description: None,
+            name: CURSOR_FIELD_NAME.to_string(),
+            arguments: vec![],
+            field_type: s::Type::NamedType("String".to_string()),
+            directives: vec![],
+        });
+    }
+
     fn add_type_def(
         api: &mut s::Document,
         type_def: &s::TypeDefinition,
@@ -394,6 +416,7 @@ fn init_api_schema(input_schema: &InputSchema) -> Result<Schema, APISchemaError>
                 if ot.name != SCHEMA_TYPE_NAME {
                     let mut ot = ot.clone();
                     add_collection_arguments(&mut ot.fields, input_schema);
+                    add_cursor_field(&ot.name, &mut ot.fields, input_schema);
                     let typedef = s::TypeDefinition::Object(ot);
                     let def = s::Definition::TypeDefinition(typedef);
                     api.definitions.push(def);
@@ -402,6 +425,7 @@ fn init_api_schema(input_schema: &InputSchema) -> Result<Schema, APISchemaError>
             s::TypeDefinition::Interface(it) => {
                 let mut it = it.clone();
                 add_collection_arguments(&mut it.fields, input_schema);
+                add_cursor_field(&it.name, &mut it.fields, input_schema);
                 let typedef = s::TypeDefinition::Interface(it);
                 let def = s::Definition::TypeDefinition(typedef);
                 api.definitions.push(def);
@@ -471,6 +495,9 @@ fn add_types_for_object_types(
     for (name, object_type) in schema.object_types() {
         add_order_by_type(&mut api.document, name, &object_type.fields)?;
         add_filter_type(api, name, &object_type.fields)?;
+        if supports_aggregates(schema, name) {
+            add_aggregate_types(&mut api.document, name, &object_type.fields, schema);
+        }
     }
     Ok(())
 }
@@ -497,6 +524,140 @@ fn add_types_for_aggregation_types(
     Ok(())
 }
 
+/// The names of the types that ad-hoc aggregations over collections of
+/// `type_name` need
+fn aggregate_type_names(type_name: &str) -> [String; 6] {
+    [
+        format!("{}{}", type_name, AGGREGATE_SUFFIX),
+        format!("{}{}_group", type_name, AGGREGATE_SUFFIX),
+        format!("{}{}_sum", type_name, AGGREGATE_SUFFIX),
+        format!("{}{}_min", type_name, AGGREGATE_SUFFIX),
+        format!("{}{}_max", type_name, AGGREGATE_SUFFIX),
+        format!("{}_groupBy", type_name),
+    ]
+}
+
+/// Ad-hoc aggregations are only available for object types for which the
+/// input schema does not already use any of the type names they need
+fn supports_aggregates(input_schema: &InputSchema, type_name: &str) -> bool {
+    aggregate_type_names(type_name).iter().all(|name| {
+        input_schema.kind_of_declared_type(name).is_none() && !input_schema.is_enum_type(name)
+    })
+}
+
+/// Adds the types for ad-hoc aggregations over a collection of
+/// `type_name`: the result type `<type_name>_aggregate` and the types of
+/// its fields, and the `<type_name>_groupBy` enum. Entities can be grouped
+/// by any scalar attribute, and `sum`, `min` and `max` are available for
+/// numeric attributes
+fn add_aggregate_types(
+    api: &mut s::Document,
+    type_name: &str,
+    fields: &[Field],
+    input_schema: &InputSchema,
+) {
+    fn named(name: &str) -> s::Type {
+        s::Type::NamedType(name.to_string())
+    }
+
+    fn field(name: &str, field_type: s::Type) -> s::Field {
+        s::Field {
+            position: Pos::default(),
+            description: None,
+            name: name.to_string(),
+            arguments: vec![],
+            field_type,
+            directives: vec![],
+        }
+    }
+
+    fn object_type(name: &str, fields: Vec<s::Field>) -> s::Definition {
+        s::Definition::TypeDefinition(s::TypeDefinition::Object(s::ObjectType {
+            position: Pos::default(),
+            description: None,
+            name: name.to_string(),
+            implements_interfaces: vec![],
+            directives: vec![],
+            fields,
+        }))
+    }
+
+    let [aggregate, group, sum, min, max, group_by] = aggregate_type_names(type_name);
+
+    let group_fields: Vec<_> = fields
+        .iter()
+        .filter(|field| !field.is_list() && !field.is_derived())
+        .collect();
+    // References store the id of the referenced entity, and therefore have
+    // a `value_type` even though they are not scalars. Aggregating the
+    // primary key is meaningless even if it is numeric
+    let numeric_fields: Vec<_> = group_fields
+        .iter()
+        .filter(|field| {
+            field.value_type.is_numeric()
+                && field.name.as_str() != ID.as_str()
+                && input_schema
+                    .kind_of_declared_type(field.field_type.get_base_type())
+                    .is_none()
+        })
+        .collect();
+
+    let count_type = s::Type::NonNullType(Box::new(named(ValueType::Int8.to_str())));
+    let mut aggregate_fields = vec![field("count", count_type), field("group", named(&group))];
+    let group_type = group_fields
+        .iter()
+        .map(|f| field(&f.name, named(f.value_type.to_str())))
+        .collect();
+    api.definitions.push(object_type(&group, group_type));
+
+    if !numeric_fields.is_empty() {
+        let sum_type = numeric_fields
+            .iter()
+            .map(|f| {
+                // Summing up `Int` and `Int8` can overflow, we therefore
+                // use the next larger type for the sum
+                let value_type = match f.value_type {
+                    ValueType::Int => ValueType::Int8,
+                    ValueType::Int8 => ValueType::BigInt,
+                    value_type => value_type,
+                };
+                field(&f.name, named(value_type.to_str()))
+            })
+            .collect();
+        let value_type: Vec<_> = numeric_fields
+            .iter()
+            .map(|f| field(&f.name, named(f.value_type.to_str())))
+            .collect();
+        api.definitions.push(object_type(&sum, sum_type));
+        api.definitions.push(object_type(&min, value_type.clone()));
+        api.definitions.push(object_type(&max, value_type));
+
+        aggregate_fields.push(field("sum", named(&sum)));
+        aggregate_fields.push(field("min", named(&min)));
+        aggregate_fields.push(field("max", named(&max)));
+    }
+    api.definitions
+        .push(object_type(&aggregate, aggregate_fields));
+
+    let values = group_fields
+        .iter()
+        .map(|f| s::EnumValue {
+            position: Pos::default(),
+            description: None,
+            name: f.name.to_string(),
+            directives: vec![],
+        })
+        .collect();
+    let typedef = s::TypeDefinition::Enum(s::EnumType {
+        position: Pos::default(),
+        description: None,
+        name: group_by,
+        directives: vec![],
+        values,
+    });
+    api.definitions.push(s::Definition::TypeDefinition(typedef));
+}
+
 /// Adds a `<type_name>_orderBy` enum type for the given fields to the schema.
 fn add_order_by_type(
     api: &mut s::Document,
@@ -698,6 +859,21 @@ impl FilterOps {
         args.extend(order_by);
         args.push(filter);
 
+        // Cursors are only supported for object and interface types since
+        // aggregations are always sorted by timestamp
+        if let FilterOps::Object = self {
+            args.push(input_value(
+                "after",
+                "",
+                s::Type::NamedType("String".to_string()),
+            ));
+            args.push(input_value(
+                "before",
+                "",
+                s::Type::NamedType("String".to_string()),
+            ));
+        }
+
         args
     }
 }
@@ -1046,6 +1222,12 @@ fn add_query_type(api: &mut s::Document, input_schema: &InputSchema) -> Result<(
         .chain(input_schema.interface_types().map(|(name, _)| name))
         .flat_map(|name| query_fields_for_type(name, FilterOps::Object))
         .collect::<Vec<s::Field>>();
+    let mut aggregate_fields = input_schema
+        .object_types()
+        .map(|(name, _)| name)
+        .filter(|name| supports_aggregates(input_schema, name))
+        .map(query_field_for_aggregates)
+        .collect::<Vec<s::Field>>();
     let mut agg_fields = input_schema
         .aggregation_types()
         .map(|(name, _)| name)
@@ -1057,6 +1239,7 @@ fn add_query_type(api: &mut s::Document, input_schema: &InputSchema) -> Result<(
         .iter()
         .filter_map(|fulltext| query_field_for_fulltext(fulltext))
         .collect();
+    fields.append(&mut aggregate_fields);
     fields.append(&mut agg_fields);
     fields.append(&mut fulltext_fields);
     fields.push(meta_field());
@@ -1261,6 +1444,50 @@ fn query_fields_for_type(type_name: &str, ops: FilterOps) -> Vec<s::Field> {
     ]
 }
 
+/// Generates the `Query` field for ad-hoc aggregations over the
+/// collection of `type_name` (e.g. `users_aggregate`)
+fn query_field_for_aggregates(type_name: &str) -> s::Field {
+    let mut skip = input_value("skip", "", s::Type::NamedType("Int".to_string()));
+    skip.default_value = Some(s::Value::Int(0.into()));
+
+    let mut first = input_value("first", "", s::Type::NamedType("Int".to_string()));
+    first.default_value = Some(s::Value::Int(100.into()));
+
+    let arguments = vec![
+        skip,
+        first,
+        input_value(
+            "where",
+            "",
+            s::Type::NamedType(format!("{}_filter", type_name)),
+        ),
+        input_value(
+            "groupBy",
+            "",
+            s::Type::ListType(Box::new(s::Type::NonNullType(Box::new(
+                s::Type::NamedType(format!("{}_groupBy", type_name)),
+            )))),
+        ),
+        block_argument(),
+        subgraph_error_argument(),
+    ];
+
+    let (_, plural) = camel_cased_names(type_name);
+    s::Field {
+        position: Pos::default(),
+        description: Some(format!("Aggregates over the collection of `{}`", type_name)),
+        name: format!("{}{}", plural, AGGREGATE_SUFFIX),
+        arguments,
+        field_type: s::Type::NonNullType(Box::new(s::Type::ListType(Box::new(
+            s::Type::NonNullType(Box::new(s::Type::NamedType(format!(
+                "{}{}",
+                type_name, AGGREGATE_SUFFIX
+            )))),
+        )))),
+        directives: vec![],
+    }
+}
+
 fn query_fields_for_agg_type(type_name: &str) -> Vec<s::Field> {
     let mut collection_arguments = FilterOps::Aggregation.collection_arguments(type_name);
     collection_arguments.push(block_argument());
@@ -2001,6 +2228,8 @@ mod tests {
                 "orderBy",
                 "orderDirection",
                 "where",
+                "after",
+                "before",
                 "block",
                 "subgraphError",
             ]
@@ -2096,6 +2325,8 @@ mod tests {
                 "orderBy",
                 "orderDirection",
                 "where",
+                "after",
+                "before",
                 "block",
                 "subgraphError"
             ]
@@ -2212,6 +2443,113 @@ type Gravatar @entity {
         query_field(&schema, "bands");
     }
 
+    #[test]
+    fn aggregate_types() {
+        const SCHEMA: &str = r#"
+        type Token @entity {
+            id: Bytes!
+            name: String!
+            decimals: Int!
+            volume: BigDecimal!
+            owner: Account!
+            tags: [String!]!
+        }
+
+        type Account @entity {
+            id: Int8!
+            tokens: [Token!]! @derivedFrom(field: "owner")
+        }
+
+        type Note @entity {
+            id: ID!
+            text: String!
+        }
+
+        type Note_aggregate @entity {
+            id: ID!
+        }
+        "#;
+        let schema = parse(SCHEMA);
+
+        let field_names = |name: &str| match schema.get_named_type(name) {
+            Some(s::TypeDefinition::Object(t)) => t
+                .fields
+                .iter()
+                .map(|field| (field.name.clone(), field.field_type.to_string()))
+                .collect::<Vec<_>>(),
+            _ => panic!("missing object type `{}`", name),
+        };
+        let pairs = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(a, b)| (a.to_string(), b.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        let tokens = query_field(&schema, "tokens_aggregate");
+        assert_eq!("[Token_aggregate!]!", tokens.field_type.to_string());
+        assert_eq!(
+            vec![
+                "skip",
+                "first",
+                "where",
+                "groupBy",
+                "block",
+                "subgraphError"
+            ],
+            tokens
+                .arguments
+                .iter()
+                .map(|arg| arg.name.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        assert_eq!(
+            pairs(&[
+                ("count", "Int8!"),
+                ("group", "Token_aggregate_group"),
+                ("sum", "Token_aggregate_sum"),
+                ("min", "Token_aggregate_min"),
+                ("max", "Token_aggregate_max")
+            ]),
+            field_names("Token_aggregate")
+        );
+        assert_eq!(
+            pairs(&[
+                ("id", "Bytes"),
+                ("name", "String"),
+                ("decimals", "Int"),
+                ("volume", "BigDecimal"),
+                ("owner", "Int8")
+            ]),
+            field_names("Token_aggregate_group")
+        );
+        assert_eq!(
+            pairs(&[("decimals", "Int8"), ("volume", "BigDecimal")]),
+            field_names("Token_aggregate_sum")
+        );
+        assert_eq!(
+            pairs(&[("decimals", "Int"), ("volume", "BigDecimal")]),
+            field_names("Token_aggregate_min")
+        );
+
+        // Accounts only have an `id`, which is numeric but can not be
+        // aggregated
+        assert_eq!(
+            pairs(&[("count", "Int8!"), ("group", "Account_aggregate_group")]),
+            field_names("Account_aggregate")
+        );
+        assert!(schema.get_named_type("Account_aggregate_sum").is_none());
+
+        // `Note_aggregate` is taken by an entity type
+        let query_type = schema.get_named_type("Query").unwrap();
+        let s::TypeDefinition::Object(query_type) = query_type else {
+            panic!("Query is not an object type")
+        };
+        assert!(query_type.field("notes_aggregate").is_none());
+        assert!(schema.get_named_type("Note_groupBy").is_none());
+    }
+
     #[test]
     fn aggregation() {
         const SCHEMA: &str = r#"
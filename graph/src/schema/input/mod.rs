@@ -5,6 +5,7 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::{anyhow, Error};
+use chrono::{DateTime, Datelike, TimeZone, Utc};
 use semver::Version;
 use store::Entity;
 
@@ -48,6 +49,8 @@ pub mod kw {
     pub const INTERVALS: &str = "intervals";
     pub const INTERVAL: &str = "interval";
     pub const CUMULATIVE: &str = "cumulative";
+    pub const WEIGHT: &str = "weight";
+    pub const PERCENTILE: &str = "percentile";
 }
 
 /// The internal representation of a subgraph schema, i.e., the
@@ -598,6 +601,10 @@ pub enum AggregateFn {
     Count,
     First,
     Last,
+    Avg,
+    Stddev,
+    Percentile,
+    CountDistinct,
 }
 
 impl FromStr for AggregateFn {
@@ -611,6 +618,10 @@ impl FromStr for AggregateFn {
             "count" => Ok(AggregateFn::Count),
             "first" => Ok(AggregateFn::First),
             "last" => Ok(AggregateFn::Last),
+            "avg" => Ok(AggregateFn::Avg),
+            "stddev" => Ok(AggregateFn::Stddev),
+            "percentile" => Ok(AggregateFn::Percentile),
+            "count_distinct" => Ok(AggregateFn::CountDistinct),
             _ => Err(anyhow!("invalid aggregate function `{}`", s)),
         }
     }
@@ -620,11 +631,23 @@ impl AggregateFn {
     pub fn has_arg(&self) -> bool {
         use AggregateFn::*;
         match self {
-            Sum | Max | Min | First | Last => true,
+            Sum | Max | Min | First | Last | Avg | Stddev | Percentile | CountDistinct => true,
             Count => false,
         }
     }
 
+    /// Whether the values of this function for one bucket can be combined
+    /// with the value from a previous bucket to form a cumulative
+    /// aggregate. That is not possible for functions like `avg` where the
+    /// aggregated value does not carry enough information
+    pub fn can_be_cumulative(&self) -> bool {
+        use AggregateFn::*;
+        match self {
+            Sum | Max | Min | Count | First | Last => true,
+            Avg | Stddev | Percentile | CountDistinct => false,
+        }
+    }
+
     fn as_str(&self) -> &'static str {
         use AggregateFn::*;
         match self {
@@ -634,50 +657,120 @@ impl AggregateFn {
             Count => "count",
             First => "first",
             Last => "last",
+            Avg => "avg",
+            Stddev => "stddev",
+            Percentile => "percentile",
+            CountDistinct => "count_distinct",
         }
     }
 }
 
 /// The supported intervals for timeseries in order of decreasing
-/// granularity. The intervals must all be divisible by the smallest
-/// interval
+/// granularity. The boundaries of buckets for larger intervals are always
+/// also boundaries of buckets for `day` and smaller intervals, but `week`
+/// and `month` buckets do not line up with each other
 #[derive(Clone, Copy, PartialEq, Eq, Debug, PartialOrd, Ord, Hash)]
 pub enum AggregationInterval {
+    Minute,
+    FiveMinutes,
     Hour,
     Day,
+    Week,
+    Month,
 }
 
 impl AggregationInterval {
     pub fn as_str(&self) -> &'static str {
         match self {
+            AggregationInterval::Minute => "minute",
+            AggregationInterval::FiveMinutes => "five_minutes",
             AggregationInterval::Hour => "hour",
             AggregationInterval::Day => "day",
+            AggregationInterval::Week => "week",
+            AggregationInterval::Month => "month",
         }
     }
 
-    pub fn as_duration(&self) -> Duration {
+    /// The length of buckets for this interval, or `None` if buckets do
+    /// not all have the same length as is the case for `month`
+    pub fn as_duration(&self) -> Option<Duration> {
         use AggregationInterval::*;
         match self {
-            Hour => Duration::from_secs(3600),
-            Day => Duration::from_secs(3600 * 24),
+            Minute => Some(Duration::from_secs(60)),
+            FiveMinutes => Some(Duration::from_secs(300)),
+            Hour => Some(Duration::from_secs(3600)),
+            Day => Some(Duration::from_secs(3600 * 24)),
+            Week => Some(Duration::from_secs(3600 * 24 * 7)),
+            Month => None,
+        }
+    }
+
+    /// The offset of the first bucket boundary after the Unix epoch for
+    /// intervals with a fixed length. Weekly buckets start on Mondays, but
+    /// the epoch was a Thursday
+    pub fn offset(&self) -> Duration {
+        use AggregationInterval::*;
+        match self {
+            Minute | FiveMinutes | Hour | Day | Month => Duration::from_secs(0),
+            Week => Duration::from_secs(3600 * 24 * 4),
+        }
+    }
+
+    /// Return the start of the bucket that contains `time`
+    pub fn bucket_start(&self, time: BlockTime) -> BlockTime {
+        // Treat any time before the epoch as the epoch; in practice, we
+        // will only deal with block times that are pretty far after the
+        // epoch
+        let secs = time.as_secs_since_epoch().max(0);
+        match self.as_duration() {
+            Some(length) => {
+                let length = length.as_secs() as i64;
+                let offset = self.offset().as_secs() as i64;
+                let start = (secs - offset).div_euclid(length) * length + offset;
+                BlockTime::since_epoch(start, 0)
+            }
+            None => {
+                let date = DateTime::from_timestamp(secs, 0).unwrap();
+                Self::month_start(date.year(), date.month())
+            }
+        }
+    }
+
+    /// Return the start of the bucket following the bucket that starts at
+    /// `start`
+    fn next_bucket_start(&self, start: BlockTime) -> BlockTime {
+        let secs = start.as_secs_since_epoch();
+        match self.as_duration() {
+            Some(length) => BlockTime::since_epoch(secs + length.as_secs() as i64, 0),
+            None => {
+                let date = DateTime::from_timestamp(secs, 0).unwrap();
+                match date.month() {
+                    12 => Self::month_start(date.year() + 1, 1),
+                    month => Self::month_start(date.year(), month + 1),
+                }
+            }
         }
     }
 
+    fn month_start(year: i32, month: u32) -> BlockTime {
+        let start = Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).unwrap();
+        BlockTime::since_epoch(start.timestamp(), 0)
+    }
+
     /// Return time ranges for all buckets that intersect `from..to` except
     /// the last one. In other words, return time ranges for all buckets
     /// that overlap `from..to` and end before `to`. The ranges are in
     /// increasing order of the start time
     pub fn buckets(&self, from: BlockTime, to: BlockTime) -> Vec<Range<BlockTime>> {
-        let first = from.bucket(self.as_duration());
-        let last = to.bucket(self.as_duration());
-        (first..last)
-            .map(|nr| self.as_duration() * nr as u32)
-            .map(|start| {
-                let lower = BlockTime::from(start);
-                let upper = BlockTime::from(start + self.as_duration());
-                lower..upper
-            })
-            .collect()
+        let last = self.bucket_start(to);
+        let mut start = self.bucket_start(from);
+        let mut buckets = Vec::new();
+        while start < last {
+            let end = self.next_bucket_start(start);
+            buckets.push(start..end);
+            start = end;
+        }
+        buckets
     }
 }
 
@@ -712,6 +805,53 @@ fn buckets() {
     );
     assert_eq!(vec![eight_am..nine_am], Hour.buckets(one_hour, two_hour));
     assert_eq!(Vec::<Range<BlockTime>>::new(), Day.buckets(start, two_hour));
+
+    // 2006-07-16 07:45Z
+    let seven_45 = BlockTime::since_epoch(START + 5 * 60, 0);
+    assert_eq!(vec![start..seven_45], FiveMinutes.buckets(start, seven_45));
+    assert_eq!(
+        vec![start..BlockTime::since_epoch(START + 60, 0)],
+        Minute.buckets(start, BlockTime::since_epoch(START + 90, 0))
+    );
+}
+
+#[test]
+fn calendar_buckets() {
+    fn time(s: &str) -> BlockTime {
+        let time = DateTime::parse_from_rfc3339(s).unwrap();
+        BlockTime::since_epoch(time.timestamp(), 0)
+    }
+
+    use AggregationInterval::*;
+
+    // 2006-07-16 was a Sunday
+    let sunday = time("2006-07-16T07:40:00Z");
+    let monday = time("2006-07-17T00:00:00Z");
+    let prev_monday = time("2006-07-10T00:00:00Z");
+    let next_monday = time("2006-07-24T00:00:00Z");
+    assert_eq!(prev_monday, Week.bucket_start(sunday));
+    assert_eq!(monday, Week.bucket_start(monday));
+    assert_eq!(
+        vec![prev_monday..monday, monday..next_monday],
+        Week.buckets(sunday, time("2006-07-25T12:00:00Z"))
+    );
+
+    // Months have different lengths
+    let jan = time("2024-01-01T00:00:00Z");
+    let feb = time("2024-02-01T00:00:00Z");
+    let mar = time("2024-03-01T00:00:00Z");
+    assert_eq!(feb, Month.bucket_start(time("2024-02-29T23:59:59Z")));
+    assert_eq!(
+        vec![jan..feb, feb..mar],
+        Month.buckets(time("2024-01-31T12:00:00Z"), time("2024-03-01T00:00:00Z"))
+    );
+
+    // Months wrap around at the end of the year
+    let dec = time("2023-12-01T00:00:00Z");
+    assert_eq!(
+        vec![dec..jan],
+        Month.buckets(time("2023-12-24T00:00:00Z"), time("2024-01-15T00:00:00Z"))
+    );
 }
 
 impl FromStr for AggregationInterval {
@@ -719,8 +859,12 @@ impl FromStr for AggregationInterval {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
+            "minute" => Ok(AggregationInterval::Minute),
+            "five_minutes" => Ok(AggregationInterval::FiveMinutes),
             "hour" => Ok(AggregationInterval::Hour),
             "day" => Ok(AggregationInterval::Day),
+            "week" => Ok(AggregationInterval::Week),
+            "month" => Ok(AggregationInterval::Month),
             _ => Err(anyhow!("invalid aggregation interval `{}`", s)),
         }
     }
@@ -771,6 +915,12 @@ pub struct Aggregate {
     pub value_type: ValueType,
     /// Whether the aggregation is cumulative
     pub cumulative: bool,
+    /// For `avg`, an optional expression over the source table by which
+    /// each value is weighted
+    pub weight: Option<Word>,
+    /// For `percentile`, the percentile to compute as a number between 0
+    /// and 1
+    pub percentile: Option<f64>,
 }
 
 impl Aggregate {
@@ -797,12 +947,22 @@ impl Aggregate {
                 _ => unreachable!("validation ensures this is a boolean"),
             })
             .unwrap_or(false);
+        let weight = dir
+            .argument(kw::WEIGHT)
+            .map(|arg| Word::from(arg.as_str().unwrap()));
+        let percentile = dir.argument(kw::PERCENTILE).map(|arg| match arg {
+            Value::Float(f) => *f,
+            Value::Int(n) => n.as_i64().unwrap() as f64,
+            _ => unreachable!("validation ensures this is a number"),
+        });
 
         Aggregate {
             name: Word::from(name),
             func,
             arg,
             cumulative,
+            weight,
+            percentile,
             field_type: field_type.clone(),
             value_type: field_type.get_base_type().parse().unwrap(),
         }
@@ -2466,6 +2626,14 @@ mod validations {
                                 }
                             };
                             match agg.argument(kw::CUMULATIVE) {
+                                Some(s::Value::Boolean(true)) if !func.can_be_cumulative() => {
+                                    errors.push(Err::AggregationNonCumulativeFn(
+                                        agg_type.name.to_owned(),
+                                        field.name.to_owned(),
+                                        func.as_str().to_owned(),
+                                    ));
+                                    continue;
+                                }
                                 Some(s::Value::Boolean(_)) | None => { /* ok */ }
                                 Some(_) => {
                                     errors.push(Err::AggregationInvalidCumulative(
@@ -2475,6 +2643,36 @@ mod validations {
                                     continue;
                                 }
                             };
+                            let weight = match agg.argument(kw::WEIGHT) {
+                                Some(s::Value::String(weight)) if func == AggregateFn::Avg => {
+                                    Some(weight)
+                                }
+                                Some(_) => {
+                                    errors.push(Err::AggregationInvalidWeight(
+                                        agg_type.name.to_owned(),
+                                        field.name.to_owned(),
+                                    ));
+                                    continue;
+                                }
+                                None => None,
+                            };
+                            let percentile_ok = match (&func, agg.argument(kw::PERCENTILE)) {
+                                (AggregateFn::Percentile, Some(s::Value::Float(p))) => {
+                                    (0.0..=1.0).contains(p)
+                                }
+                                (AggregateFn::Percentile, Some(s::Value::Int(n))) => {
+                                    matches!(n.as_i64(), Some(0) | Some(1))
+                                }
+                                (_, Some(_)) => false,
+                                (func, None) => func != &AggregateFn::Percentile,
+                            };
+                            if !percentile_ok {
+                                errors.push(Err::AggregationInvalidPercentile(
+                                    agg_type.name.to_owned(),
+                                    field.name.to_owned(),
+                                ));
+                                continue;
+                            }
                             let field_type = match field.field_type.value_type() {
                                 Ok(field_type) => field_type,
                                 Err(_) => {
@@ -2491,40 +2689,53 @@ mod validations {
                             // a bunch of local variables that would make
                             // setting up that struct a bit awkward, so we
                             // use a closure instead
-                            let check_ident = |ident: &str| -> Result<(), SchemaValidationError> {
-                                let arg_type = match source.field(ident) {
-                                    Some(arg_field) => match arg_field.field_type.value_type() {
-                                        Ok(arg_type) if arg_type.is_numeric() => arg_type,
-                                        Ok(_) | Err(_) => {
-                                            return Err(Err::AggregationNonNumericArg(
+                            //
+                            // `count_distinct` counts values of any type,
+                            // all other functions need numeric arguments
+                            let check_ident =
+                                |expr: &str, ident: &str| -> Result<(), SchemaValidationError> {
+                                    let arg_type = match source.field(ident) {
+                                        Some(_) if func == AggregateFn::CountDistinct => {
+                                            return Ok(());
+                                        }
+                                        Some(arg_field) => {
+                                            match arg_field.field_type.value_type() {
+                                                Ok(arg_type) if arg_type.is_numeric() => arg_type,
+                                                Ok(_) | Err(_) => {
+                                                    return Err(Err::AggregationNonNumericArg(
+                                                        agg_type.name.to_owned(),
+                                                        field.name.to_owned(),
+                                                        source.name.to_owned(),
+                                                        expr.to_owned(),
+                                                    ));
+                                                }
+                                            }
+                                        }
+                                        None => {
+                                            return Err(Err::AggregationUnknownArg(
                                                 agg_type.name.to_owned(),
                                                 field.name.to_owned(),
-                                                source.name.to_owned(),
-                                                arg.to_owned(),
+                                                expr.to_owned(),
                                             ));
                                         }
-                                    },
-                                    None => {
-                                        return Err(Err::AggregationUnknownArg(
+                                    };
+                                    if arg_type > field_type {
+                                        return Err(Err::AggregationNonMatchingArg(
                                             agg_type.name.to_owned(),
                                             field.name.to_owned(),
-                                            arg.to_owned(),
+                                            expr.to_owned(),
+                                            arg_type.to_str().to_owned(),
+                                            field_type.to_str().to_owned(),
                                         ));
                                     }
+                                    Ok(())
                                 };
-                                if arg_type > field_type {
-                                    return Err(Err::AggregationNonMatchingArg(
-                                        agg_type.name.to_owned(),
-                                        field.name.to_owned(),
-                                        arg.to_owned(),
-                                        arg_type.to_str().to_owned(),
-                                        field_type.to_str().to_owned(),
-                                    ));
+                            for expr in std::iter::once(arg).chain(weight) {
+                                if let Err(mut errs) =
+                                    sqlexpr::parse(expr, |ident: &str| check_ident(expr, ident))
+                                {
+                                    errors.append(&mut errs);
                                 }
-                                Ok(())
-                            };
-                            if let Err(mut errs) = sqlexpr::parse(arg, check_ident) {
-                                errors.append(&mut errs);
                             }
                         }
                         None => {
@@ -42,6 +42,15 @@ pub const INTROSPECTION_SCHEMA_FIELD_NAME: &str = "__schema";
 pub const META_FIELD_TYPE: &str = "_Meta_";
 pub const META_FIELD_NAME: &str = "_meta";
 
+/// The field on entity types that holds an opaque cursor for use with the
+/// `after` and `before` arguments of collection fields
+pub const CURSOR_FIELD_NAME: &str = "_cursor";
+
+/// The suffix for the `Query` fields and the types used for ad-hoc
+/// aggregations over entity collections, e.g., `users_aggregate` and
+/// `User_aggregate`
+pub const AGGREGATE_SUFFIX: &str = "_aggregate";
+
 pub const INTROSPECTION_TYPE_FIELD_NAME: &str = "__type";
 
 pub const BLOCK_FIELD_TYPE: &str = "_Block_";
@@ -177,6 +186,12 @@ pub enum SchemaValidationError {
     AggregationNonNumericArg(String, String, String, String),
     #[error("Field {1} in aggregation {0} has an invalid value for `cumulative`. It needs to be a boolean")]
     AggregationInvalidCumulative(String, String),
+    #[error("Field {1} in aggregation {0} uses the function `{2}` which can not be cumulative")]
+    AggregationNonCumulativeFn(String, String, String),
+    #[error("Field {1} in aggregation {0} has an invalid `weight`. It needs to be a string and can only be used with `avg`")]
+    AggregationInvalidWeight(String, String),
+    #[error("Field {1} in aggregation {0} has an invalid `percentile`. It must be a number between 0 and 1 and can only be used with `percentile`")]
+    AggregationInvalidPercentile(String, String),
     #[error("Aggregations are not supported with spec version {0}; please migrate the subgraph to the latest version")]
     AggregationsNotSupported(Version),
     #[error("Using Int8 as the type for the `id` field is not supported with spec version {0}; please migrate the subgraph to the latest version")]
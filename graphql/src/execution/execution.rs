@@ -2,6 +2,9 @@ use super::cache::QueryBlockCache;
 use async_recursion::async_recursion;
 use crossbeam::atomic::AtomicCell;
 use graph::{
+    components::query_cache::{
+        decode_result, encode_result, shared_query_cache_from_env, SharedQueryCache, RESULT_FORMAT,
+    },
     data::{
         query::Trace,
         value::{Object, Word},
@@ -12,6 +15,7 @@ use graph::{
 };
 use lazy_static::lazy_static;
 use parking_lot::MutexGuard;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Instant;
 use std::{borrow::ToOwned, collections::HashSet};
 
@@ -43,6 +47,86 @@ lazy_static! {
             caches
     };
     static ref QUERY_HERD_CACHE: HerdCache<Arc<QueryResult>> = HerdCache::new("query_herd_cache");
+    // Query results cache shared with other query nodes, if one is configured
+    static ref SHARED_QUERY_CACHE: Option<Arc<dyn SharedQueryCache>> =
+        shared_query_cache_from_env().expect("invalid GRAPH_QUERY_SHARED_CACHE_URL");
+}
+
+/// The cache for query results that is shared with other query nodes, if
+/// one is configured
+pub fn shared_query_cache() -> Option<Arc<dyn SharedQueryCache>> {
+    SHARED_QUERY_CACHE.clone()
+}
+
+/// The key for `key` in the shared query cache. Since the hash of a query
+/// is only stable for one version of `graph-node`, nodes running different
+/// versions do not share results
+fn shared_cache_key(key: &QueryHash) -> String {
+    format!(
+        "{}:{}:{}",
+        env!("CARGO_PKG_VERSION"),
+        RESULT_FORMAT,
+        graph::prelude::hex::encode(key)
+    )
+}
+
+/// Look for the result for `key` in the shared query cache. Problems with
+/// the shared cache are treated like a miss
+async fn shared_cache_get(
+    ctx: &ExecutionContext<impl Resolver>,
+    key: &QueryHash,
+) -> Option<Arc<QueryResult>> {
+    let cache = SHARED_QUERY_CACHE.as_ref()?;
+    let timeout = ENV_VARS.graphql.query_shared_cache_timeout;
+    let encoded = match tokio::time::timeout(timeout, cache.get(&shared_cache_key(key))).await {
+        Ok(Ok(encoded)) => encoded?,
+        Ok(Err(e)) => {
+            debug!(ctx.logger, "Failed to read from the shared query cache";
+                "error" => e.to_string());
+            return None;
+        }
+        Err(_) => {
+            debug!(ctx.logger, "Timed out reading from the shared query cache");
+            return None;
+        }
+    };
+    match decode_result(&encoded) {
+        Ok(data) => {
+            let mut result = QueryResult::new(data);
+            result.deployment = Some(ctx.query.schema.id().clone());
+            Some(Arc::new(result))
+        }
+        Err(e) => {
+            warn!(ctx.logger, "Invalid result in the shared query cache"; "error" => e.to_string());
+            None
+        }
+    }
+}
+
+/// Add `result` to the shared query cache in the background
+fn shared_cache_insert(
+    logger: &Logger,
+    network: &str,
+    block: BlockNumber,
+    key: &QueryHash,
+    result: Arc<QueryResult>,
+) {
+    let cache = match SHARED_QUERY_CACHE.as_ref() {
+        Some(cache) => cache.cheap_clone(),
+        None => return,
+    };
+    let logger = logger.cheap_clone();
+    let network = network.to_string();
+    let key = shared_cache_key(key);
+    graph::spawn(async move {
+        let encoded = match result.data() {
+            Some(data) => encode_result(data),
+            None => return,
+        };
+        if let Err(e) = cache.insert(&network, block, &key, encoded).await {
+            debug!(logger, "Failed to write to the shared query cache"; "error" => e.to_string());
+        }
+    });
 }
 
 struct WeightedResult {
@@ -353,7 +437,17 @@ pub(crate) async fn execute_root_selection_set<R: Resolver>(
     let execute_ctx = ctx.cheap_clone();
     let execute_selection_set = selection_set.cheap_clone();
     let execute_root_type = root_type.cheap_clone();
+    let shared_hit = Arc::new(AtomicBool::new(false));
+    let execute_shared_hit = shared_hit.cheap_clone();
     let run_query = async move {
+        // Another query node might have run this query already
+        if let Some(key) = &key {
+            if let Some(result) = shared_cache_get(&execute_ctx, key).await {
+                execute_shared_hit.store(true, Ordering::SeqCst);
+                return result;
+            }
+        }
+
         let _permit = execute_ctx.resolver.query_permit().await;
 
         let logger = execute_ctx.logger.clone();
@@ -410,6 +504,7 @@ pub(crate) async fn execute_root_selection_set<R: Resolver>(
     if herd_hit {
         ctx.cache_status.store(CacheStatus::Shared);
     }
+    let shared_hit = shared_hit.load(Ordering::SeqCst);
 
     // Check if this query should be cached.
     // Share errors from the herd cache, but don't store them in generational cache.
@@ -453,6 +548,18 @@ pub(crate) async fn execute_root_selection_set<R: Resolver>(
             );
             ctx.cache_status.store(CacheStatus::Insert);
         }
+
+        if shared_hit {
+            ctx.cache_status.store(CacheStatus::Hit);
+        } else {
+            shared_cache_insert(
+                &ctx.logger,
+                network,
+                block_ptr.number,
+                &key,
+                result.cheap_clone(),
+            );
+        }
     }
 
     result
@@ -124,6 +124,8 @@ pub struct Query {
     pub selection_set: Arc<a::SelectionSet>,
     /// The ShapeHash of the original query
     pub shape_hash: u64,
+    /// The complexity of the query as computed by `check_complexity`
+    pub complexity: u64,
 
     pub network: Option<String>,
 
@@ -259,10 +261,8 @@ impl Query {
         };
 
         // It's important to check complexity first, so `validate_fields`
-        // doesn't risk a stack overflow from invalid queries. We don't
-        // really care about the resulting complexity, only that all the
-        // checks that `check_complexity` performs pass successfully
-        let _ = raw_query.check_complexity(max_complexity, max_depth)?;
+        // doesn't risk a stack overflow from invalid queries
+        let complexity = raw_query.check_complexity(max_complexity, max_depth)?;
         raw_query.validate_fields()?;
         let selection_set = raw_query.convert()?;
 
@@ -270,6 +270,7 @@ impl Query {
             schema,
             selection_set: Arc::new(selection_set),
             shape_hash: query.shape_hash,
+            complexity,
             kind,
             network,
             logger,
@@ -26,7 +26,7 @@ mod metrics;
 
 /// Prelude that exports the most important traits and types.
 pub mod prelude {
-    pub use super::execution::{ast as a, ExecutionContext, Query, Resolver};
+    pub use super::execution::{ast as a, shared_query_cache, ExecutionContext, Query, Resolver};
     pub use super::introspection::IntrospectionResolver;
     pub use super::query::{execute_query, ext::BlockConstraint, QueryExecutionOptions};
     pub use super::store::StoreResolver;
@@ -1,8 +1,12 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::sync::Arc;
 use std::time::Instant;
 
 use crate::metrics::GraphQLMetrics;
-use crate::prelude::{QueryExecutionOptions, StoreResolver, SubscriptionExecutionOptions};
+use crate::prelude::{
+    ExecutionContext, QueryExecutionOptions, StoreResolver, SubscriptionExecutionOptions,
+};
 use crate::query::execute_query;
 use crate::subscription::execute_prepared_subscription;
 use graph::prelude::MetricsRegistry;
@@ -16,7 +20,10 @@ use graph::{
 };
 use graph::{data::graphql::load_manager::LoadManager, prelude::QueryStoreManager};
 use graph::{
-    data::query::{QueryResults, QueryTarget},
+    data::query::{
+        FeedCursor, FeedEvent, PersistedQueries, QueryPlan, QueryResults, QueryTarget,
+        SqlQueryObject, SqlQueryReq,
+    },
     prelude::QueryStore,
 };
 
@@ -178,6 +185,7 @@ where
             query_res.trace.finish(query_start.elapsed());
             result.append(query_res);
         }
+        result.set_complexity(query.complexity);
 
         query.log_execution(max_block);
         self.deployment_changed(store.as_ref(), state, max_block as u64)
@@ -277,6 +285,127 @@ where
         )
     }
 
+    async fn run_sql_query(
+        self: Arc<Self>,
+        req: SqlQueryReq,
+        target: QueryTarget,
+    ) -> Result<Vec<SqlQueryObject>, QueryExecutionError> {
+        let store = self.store.query_store(target, false).await?;
+        let state = store.deployment_state().await?;
+
+        let block = req.block.unwrap_or(state.latest_block.number);
+        state
+            .block_queryable(block)
+            .map_err(|msg| QueryExecutionError::ValueParseError("block".to_owned(), msg))?;
+
+        // SQL queries have no shape like GraphQL queries; use the hash of
+        // the query text so that they can still be blocked or throttled
+        let mut hasher = DefaultHasher::new();
+        req.query.hash(&mut hasher);
+        self.load_manager
+            .decide(
+                &store.wait_stats().map_err(QueryExecutionError::from)?,
+                store.shard(),
+                store.deployment_id(),
+                hasher.finish(),
+                &req.query,
+            )
+            .to_result()?;
+
+        let _permit = store.query_permit().await?;
+        graph::spawn_blocking_allow_panic(move || store.execute_sql(&req.query, block))
+            .await
+            .map_err(|e| QueryExecutionError::Panic(e.to_string()))?
+    }
+
+    async fn entity_feed(
+        self: Arc<Self>,
+        target: QueryTarget,
+        cursor: Option<FeedCursor>,
+    ) -> Result<Vec<FeedEvent>, QueryExecutionError> {
+        let store = self.store.query_store(target, false).await?;
+
+        let _permit = store.query_permit().await?;
+        graph::spawn_blocking_allow_panic(move || store.entity_feed(cursor))
+            .await
+            .map_err(|e| QueryExecutionError::Panic(e.to_string()))?
+    }
+
+    async fn explain_query(
+        self: Arc<Self>,
+        query: Query,
+        target: QueryTarget,
+        explain: bool,
+    ) -> Result<QueryPlan, Vec<QueryExecutionError>> {
+        let store = self.store.query_store(target, false).await?;
+        let state = store.deployment_state().await?;
+        let network = Some(store.network_name().to_string());
+        let schema = store.api_schema()?;
+
+        // Compute the complexity without enforcing the limit so that the
+        // plan shows the complexity of queries that would be rejected, too
+        let max_complexity = ENV_VARS.graphql.max_complexity;
+        let query = crate::execution::Query::new(
+            &self.logger,
+            schema,
+            network,
+            query,
+            None,
+            ENV_VARS.graphql.max_depth,
+            self.graphql_metrics.cheap_clone(),
+        )?;
+
+        let mut fields = Vec::new();
+        for (bc, (selection_set, error_policy)) in query.block_constraint()? {
+            let resolver = StoreResolver::at_block(
+                &self.logger,
+                store.cheap_clone(),
+                &state,
+                self.subscription_manager.cheap_clone(),
+                bc,
+                error_policy,
+                query.schema.id().clone(),
+                self.graphql_metrics.cheap_clone(),
+                self.load_manager.cheap_clone(),
+            )
+            .await?;
+            let ctx = ExecutionContext {
+                logger: self.logger.cheap_clone(),
+                resolver,
+                query: query.cheap_clone(),
+                deadline: None,
+                max_first: ENV_VARS.graphql.max_first,
+                max_skip: ENV_VARS.graphql.max_skip,
+                cache_status: Default::default(),
+                trace: false,
+            };
+
+            let _permit = store
+                .query_permit()
+                .await
+                .map_err(QueryExecutionError::from)?;
+            let plans = graph::spawn_blocking_allow_panic(move || {
+                crate::store::explain(&ctx, &selection_set, explain)
+            })
+            .await
+            .map_err(|e| QueryExecutionError::Panic(e.to_string()))??;
+            fields.extend(plans);
+        }
+
+        Ok(QueryPlan {
+            complexity: query.complexity,
+            max_complexity,
+            fields,
+        })
+    }
+
+    async fn persisted_queries(
+        self: Arc<Self>,
+        target: QueryTarget,
+    ) -> Result<Arc<PersistedQueries>, QueryExecutionError> {
+        self.store.persisted_queries(target).await
+    }
+
     fn metrics(&self) -> Arc<dyn GraphQLMetricsTrait> {
         self.graphql_metrics.clone()
     }
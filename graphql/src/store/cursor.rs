@@ -0,0 +1,256 @@
+//! Opaque cursors for keyset pagination of collection fields.
+//!
+//! A cursor records the position of an entity in a collection that is
+//! sorted by `(orderBy, id)`. Passing it as the `after` or `before`
+//! argument of a collection field turns it into a filter on the sort key
+//! and the `id` so that the database can start reading right at the
+//! cursor instead of having to skip over all entities that come before it
+//! like it has to do for `skip`.
+//!
+//! Cursors are only meaningful for the sort order that was used to produce
+//! them; using them with a different `orderBy` results in an error.
+//! Sorting by attributes of child entities is not supported.
+
+use graph::data::query::QueryExecutionError;
+use graph::data::store::Value;
+use graph::data::value::Object;
+use graph::prelude::{hex, r, serde_json, EntityFilter, EntityOrder};
+use graph::schema::ObjectOrInterface;
+
+use crate::execution::ast as a;
+
+pub(crate) const ARG_AFTER: &str = "after";
+pub(crate) const ARG_BEFORE: &str = "before";
+
+const KEY_ORDER_BY: &str = "o";
+const KEY_VALUE: &str = "v";
+const KEY_ID: &str = "id";
+
+/// The position of an entity in a collection sorted by `order_by`
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Cursor {
+    /// The attribute the collection was sorted by, or `None` if the
+    /// collection was sorted by `id`
+    order_by: Option<String>,
+    /// The value of the `order_by` attribute of the entity. Always
+    /// `r::Value::Null` if `order_by` is `None`
+    value: r::Value,
+    id: r::Value,
+}
+
+impl Cursor {
+    /// Create the cursor for `entity` when it is part of a collection that
+    /// is sorted by `order_by`. Return `None` if the entity does not have
+    /// an `id`. Since the `orderBy` attribute is always selected, a missing
+    /// value for it means that it is `null`
+    pub fn for_entity(order_by: Option<&str>, entity: &Object) -> Option<Self> {
+        let id = entity.get(KEY_ID)?.clone();
+        let value = order_by
+            .and_then(|attr| entity.get(attr).cloned())
+            .unwrap_or(r::Value::Null);
+        Some(Cursor {
+            order_by: order_by.map(str::to_string),
+            value,
+            id,
+        })
+    }
+
+    pub fn encode(&self) -> String {
+        let json = serde_json::json!({
+            KEY_ORDER_BY: self.order_by,
+            KEY_VALUE: self.value,
+            KEY_ID: self.id,
+        });
+        hex::encode(json.to_string())
+    }
+
+    pub fn decode(text: &str) -> Result<Self, QueryExecutionError> {
+        let invalid = || QueryExecutionError::InvalidCursor(text.to_string());
+
+        let bytes = hex::decode(text).map_err(|_| invalid())?;
+        let json: serde_json::Value = serde_json::from_slice(&bytes).map_err(|_| invalid())?;
+        let mut obj = match json {
+            serde_json::Value::Object(obj) => obj,
+            _ => return Err(invalid()),
+        };
+        let order_by = match obj.remove(KEY_ORDER_BY) {
+            Some(serde_json::Value::String(attr)) => Some(attr),
+            Some(serde_json::Value::Null) | None => None,
+            Some(_) => return Err(invalid()),
+        };
+        let value = obj
+            .remove(KEY_VALUE)
+            .map(r::Value::from)
+            .unwrap_or(r::Value::Null);
+        let id = match obj.remove(KEY_ID) {
+            Some(id @ serde_json::Value::String(_)) => r::Value::from(id),
+            _ => return Err(invalid()),
+        };
+        Ok(Cursor {
+            order_by,
+            value,
+            id,
+        })
+    }
+}
+
+/// The attribute by which the collection for `field` is sorted for the
+/// purposes of constructing cursors. Return `None` if it is sorted by `id`
+/// and `Err(())` if cursors are not supported for the sort order
+pub(crate) fn cursor_order_by(field: &a::Field) -> Result<Option<String>, ()> {
+    match field.argument_value("orderBy") {
+        Some(r::Value::Enum(name)) if name.contains("__") => Err(()),
+        Some(r::Value::Enum(name)) if name == KEY_ID => Ok(None),
+        Some(r::Value::Enum(name)) => Ok(Some(name.clone())),
+        _ => Ok(None),
+    }
+}
+
+/// Build the filter that restricts the collection for `field` to the
+/// entities that come after the `after` cursor and before the `before`
+/// cursor in the given `order`. Return `None` if the field has neither
+/// argument
+pub(crate) fn build_cursor_filter(
+    entity: &ObjectOrInterface,
+    field: &a::Field,
+    order: &EntityOrder,
+) -> Result<Option<EntityFilter>, QueryExecutionError> {
+    let after = cursor_argument(field, ARG_AFTER)?;
+    let before = cursor_argument(field, ARG_BEFORE)?;
+    if after.is_none() && before.is_none() {
+        return Ok(None);
+    }
+
+    let (order_by, ascending) = match order {
+        EntityOrder::Default => (None, true),
+        EntityOrder::Ascending(attr, _) => (Some(attr.as_str()), true),
+        EntityOrder::Descending(attr, _) => (Some(attr.as_str()), false),
+        EntityOrder::ChildAscending(_)
+        | EntityOrder::ChildDescending(_)
+        | EntityOrder::Unordered => {
+            return Err(QueryExecutionError::NotSupported(
+                "Cursors can not be used when sorting by child attributes".to_string(),
+            ))
+        }
+    };
+    let order_by = order_by.filter(|attr| *attr != KEY_ID);
+
+    let mut filters = Vec::new();
+    if let Some(cursor) = after {
+        filters.push(keyset_filter(entity, order_by, &cursor, ascending)?);
+    }
+    if let Some(cursor) = before {
+        filters.push(keyset_filter(entity, order_by, &cursor, !ascending)?);
+    }
+    Ok(Some(EntityFilter::And(filters)))
+}
+
+fn cursor_argument(field: &a::Field, name: &str) -> Result<Option<Cursor>, QueryExecutionError> {
+    match field.argument_value(name) {
+        Some(r::Value::String(text)) => Cursor::decode(text).map(Some),
+        Some(r::Value::Null) | None => Ok(None),
+        Some(_) => Err(QueryExecutionError::InvalidCursor(name.to_string())),
+    }
+}
+
+/// Build the filter for all entities that come after `cursor` in the
+/// collection when `upwards` is `true`, or before it when `upwards` is
+/// `false`, assuming the collection is sorted ascending by
+/// `(order_by, id)`.
+///
+/// The filter needs to mimic how Postgres sorts: `asc` puts nulls after
+/// all other values and `desc` puts them before all other values, which
+/// makes `desc` the exact reverse of `asc`
+fn keyset_filter(
+    entity: &ObjectOrInterface,
+    order_by: Option<&str>,
+    cursor: &Cursor,
+    upwards: bool,
+) -> Result<EntityFilter, QueryExecutionError> {
+    use EntityFilter as f;
+
+    if cursor.order_by.as_deref() != order_by {
+        return Err(QueryExecutionError::InvalidCursor(format!(
+            "the cursor was created for a collection sorted by `{}` but is used with a \
+             collection sorted by `{}`",
+            cursor.order_by.as_deref().unwrap_or(KEY_ID),
+            order_by.unwrap_or(KEY_ID)
+        )));
+    }
+
+    let id = store_value(entity, KEY_ID, &cursor.id)?;
+    let id_filter = if upwards {
+        f::GreaterThan(KEY_ID.to_string(), id)
+    } else {
+        f::LessThan(KEY_ID.to_string(), id)
+    };
+
+    let attr = match order_by {
+        Some(attr) => attr.to_string(),
+        None => return Ok(id_filter),
+    };
+    let value = store_value(entity, &attr, &cursor.value)?;
+
+    let filter = match (value, upwards) {
+        (Value::Null, true) => f::And(vec![f::Equal(attr, Value::Null), id_filter]),
+        (Value::Null, false) => f::Or(vec![
+            f::Not(attr.clone(), Value::Null),
+            f::And(vec![f::Equal(attr, Value::Null), id_filter]),
+        ]),
+        (value, true) => f::Or(vec![
+            f::GreaterThan(attr.clone(), value.clone()),
+            f::And(vec![f::Equal(attr.clone(), value), id_filter]),
+            f::Equal(attr, Value::Null),
+        ]),
+        (value, false) => f::Or(vec![
+            f::LessThan(attr.clone(), value.clone()),
+            f::And(vec![f::Equal(attr, value), id_filter]),
+        ]),
+    };
+    Ok(filter)
+}
+
+fn store_value(
+    entity: &ObjectOrInterface,
+    attr: &str,
+    value: &r::Value,
+) -> Result<Value, QueryExecutionError> {
+    let field = entity.field(attr).ok_or_else(|| {
+        QueryExecutionError::EntityFieldError(entity.typename().to_owned(), attr.to_string())
+    })?;
+    Value::from_query_value(value, &field.field_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use graph::data::value::Object;
+    use graph::prelude::{hex, r};
+
+    use super::Cursor;
+
+    #[test]
+    fn cursor_roundtrip() {
+        let entity = Object::from_iter(vec![
+            ("id".into(), r::Value::String("0xcafe".to_string())),
+            ("name".into(), r::Value::String("Alice".to_string())),
+            ("age".into(), r::Value::Int(42)),
+            ("nick".into(), r::Value::Null),
+        ]);
+
+        for order_by in [None, Some("name"), Some("age"), Some("nick")] {
+            let cursor = Cursor::for_entity(order_by, &entity).unwrap();
+            let decoded = Cursor::decode(&cursor.encode()).unwrap();
+            assert_eq!(cursor, decoded);
+        }
+
+        let no_id = Object::from_iter(vec![("age".into(), r::Value::Int(42))]);
+        assert_eq!(None, Cursor::for_entity(Some("age"), &no_id));
+    }
+
+    #[test]
+    fn cursor_rejects_garbage() {
+        assert!(Cursor::decode("not hex").is_err());
+        assert!(Cursor::decode(&hex::encode("[1, 2]")).is_err());
+        assert!(Cursor::decode(&hex::encode(r#"{"o": "name", "v": 1}"#)).is_err());
+    }
+}
@@ -1,5 +1,7 @@
+mod cursor;
 mod prefetch;
 mod query;
 mod resolver;
 
+pub use self::prefetch::explain;
 pub use self::resolver::StoreResolver;
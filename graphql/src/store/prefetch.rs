@@ -2,16 +2,20 @@
 //! final result
 
 use graph::data::graphql::ObjectTypeExt;
-use graph::data::query::Trace;
+use graph::data::query::{FieldPlan, Trace};
 use graph::data::store::Id;
 use graph::data::store::IdList;
 use graph::data::store::IdType;
 use graph::data::store::QueryObject;
 use graph::data::value::{Object, Word};
 use graph::prelude::{r, CacheWeight, CheapClone};
+use graph::schema::ast as sast;
 use graph::schema::kw;
 use graph::schema::AggregationInterval;
 use graph::schema::Field;
+use graph::schema::TypeKind;
+use graph::schema::{is_introspection_field, META_FIELD_NAME};
+use graph::schema::{AGGREGATE_SUFFIX, CURSOR_FIELD_NAME};
 use graph::slog::warn;
 use graph::util::cache_weight;
 use std::collections::{BTreeMap, HashMap};
@@ -20,14 +24,16 @@ use std::time::Instant;
 
 use graph::data::graphql::TypeExt;
 use graph::prelude::{
-    AttributeNames, ChildMultiplicity, EntityCollection, EntityFilter, EntityLink, EntityOrder,
-    EntityWindow, ParentLink, QueryExecutionError, Value as StoreValue, WindowAttribute, ENV_VARS,
+    AttributeNames, ChildMultiplicity, EntityAggregate, EntityAggregateQuery, EntityCollection,
+    EntityFilter, EntityLink, EntityOrder, EntityQuery, EntityWindow, ParentLink,
+    QueryExecutionError, Value as StoreValue, WindowAttribute, ENV_VARS,
 };
 use graph::schema::{EntityType, InputSchema, ObjectOrInterface};
 
 use crate::execution::ast as a;
 use crate::metrics::GraphQLMetrics;
-use crate::store::query::build_query;
+use crate::store::cursor::{cursor_order_by, Cursor};
+use crate::store::query::{build_aggregate_query, build_query};
 use crate::store::StoreResolver;
 
 pub const ARG_ID: &str = "id";
@@ -103,6 +109,17 @@ impl From<QueryObject> for Node {
     }
 }
 
+impl From<Object> for Node {
+    fn from(entity: Object) -> Self {
+        Node {
+            children_weight: entity.weight(),
+            parent: None,
+            entity,
+            children: BTreeMap::default(),
+        }
+    }
+}
+
 impl CacheWeight for Node {
     fn indirect_weight(&self) -> usize {
         self.children_weight + cache_weight::btree::node_size(&self.children)
@@ -139,13 +156,7 @@ fn is_root_node<'a>(mut nodes: impl Iterator<Item = &'a Node>) -> bool {
 }
 
 fn make_root_node() -> Vec<Node> {
-    let entity = Object::empty();
-    vec![Node {
-        children_weight: entity.weight(),
-        parent: None,
-        entity,
-        children: BTreeMap::default(),
-    }]
+    vec![Node::from(Object::empty())]
 }
 
 /// Recursively convert a `Node` into the corresponding `q::Value`, which is
@@ -226,6 +237,14 @@ impl Node {
             self.children_weight -= nodes_weight(&old) + key_weight;
         }
     }
+
+    fn set_cursor(&mut self, cursor: &Cursor) {
+        let key = Word::from(CURSOR_FIELD_NAME);
+        let value = r::Value::String(cursor.encode());
+
+        self.children_weight += key.weight() + value.weight();
+        self.entity.extend(Some((key, value)));
+    }
 }
 
 /// Describe a field that we join on. The distinction between scalar and
@@ -441,6 +460,48 @@ impl<'a> Join<'a> {
         }
         Ok(windows)
     }
+
+    /// Parents that stand in for the actual parents when we compute the
+    /// queries for a GraphQL query without running it. There is one
+    /// parent for each join condition, and it references one child if the
+    /// parent holds the ids of its children
+    fn placeholder_parents(&self) -> Result<Vec<Node>, QueryExecutionError> {
+        self.conds
+            .iter()
+            .map(|cond| -> Result<Node, QueryExecutionError> {
+                let mut entity = vec![
+                    (
+                        Word::from("id"),
+                        placeholder_id(cond.parent_type.id_type()?),
+                    ),
+                    (
+                        Word::from("__typename"),
+                        r::Value::String(cond.parent_type.typename().to_string()),
+                    ),
+                ];
+                if let JoinRelation::Derived(field) = &cond.relation {
+                    let child_id = placeholder_id(cond.child_type.id_type()?);
+                    match field {
+                        JoinField::Scalar(name) => entity.push((name.clone(), child_id)),
+                        JoinField::List(name) => {
+                            entity.push((name.clone(), r::Value::List(vec![child_id])))
+                        }
+                    }
+                }
+                Ok(Node::from(Object::from_iter(entity)))
+            })
+            .collect()
+    }
+}
+
+/// An arbitrary, but valid, id of type `id_type`
+fn placeholder_id(id_type: IdType) -> r::Value {
+    let id = match id_type {
+        IdType::String => "id",
+        IdType::Bytes => "0x00",
+        IdType::Int8 => "0",
+    };
+    r::Value::String(id.to_string())
 }
 
 /// Distinguish between a root GraphQL query and nested queries. For root
@@ -455,6 +516,42 @@ enum MaybeJoin<'a> {
 }
 
 impl<'a> MaybeJoin<'a> {
+    /// Determine how the children of type `child_type` in `field` are
+    /// joined to parents of type `parent_type`
+    fn new(
+        schema: &'a InputSchema,
+        parent_type: &str,
+        parent_interval: Option<AggregationInterval>,
+        child_type: ObjectOrInterface<'a>,
+        field: &a::Field,
+        at_root: bool,
+    ) -> Result<Self, QueryExecutionError> {
+        if at_root {
+            return Ok(MaybeJoin::Root { child_type });
+        }
+
+        let object_type = schema
+            .object_or_aggregation(parent_type, parent_interval)
+            .ok_or_else(|| {
+                QueryExecutionError::ConstraintViolation(format!(
+                    "the type `{}`(interval {}) is not an object type",
+                    parent_type,
+                    parent_interval
+                        .map(|intv| intv.as_str())
+                        .unwrap_or("<none>")
+                ))
+            })?;
+        let field_type = object_type
+            .field(&field.name)
+            .expect("field names are valid");
+        Ok(MaybeJoin::Nested(Join::new(
+            schema,
+            object_type.cheap_clone(),
+            child_type,
+            field_type,
+        )))
+    }
+
     fn child_type(&self) -> &ObjectOrInterface<'_> {
         match self {
             MaybeJoin::Root { child_type } => child_type,
@@ -529,6 +626,134 @@ fn add_children(
     Ok(())
 }
 
+/// Set the `_cursor` attribute of `children`, the result of querying the
+/// collection `field`, if the query selects it. Cursors are left unset
+/// where they are not supported; the field is nullable for that reason
+fn add_cursors(child_type: &ObjectOrInterface<'_>, field: &a::Field, children: &mut [Node]) {
+    if field.multiplicity == ChildMultiplicity::Single || child_type.is_aggregation() {
+        return;
+    }
+    // Fulltext queries are sorted by rank which cursors can not express
+    if field.argument_value("text").is_some() {
+        return;
+    }
+    // Types that declare their own `_cursor` field do not get a cursor
+    if child_type.field(CURSOR_FIELD_NAME).is_some() {
+        return;
+    }
+    let selected = field
+        .selection_set
+        .fields()
+        .any(|(_, mut fields)| fields.any(|f| f.name == CURSOR_FIELD_NAME));
+    if !selected {
+        return;
+    }
+    let order_by = match cursor_order_by(field) {
+        Ok(order_by) => order_by,
+        Err(()) => return,
+    };
+    for child in children {
+        if let Some(cursor) = Cursor::for_entity(order_by.as_deref(), &child.entity) {
+            child.set_cursor(&cursor);
+        }
+    }
+}
+
+/// If `type_name` is the result type of ad-hoc aggregations over a
+/// collection of entities, i.e., `<entity type>_aggregate`, return the
+/// type of those entities
+fn aggregated_type<'a>(
+    input_schema: &'a InputSchema,
+    type_name: &str,
+) -> Option<ObjectOrInterface<'a>> {
+    // The input schema might have a type whose name ends in `_aggregate`,
+    // in which case the API schema does not have aggregations for it
+    if input_schema.kind_of_declared_type(type_name).is_some() {
+        return None;
+    }
+    let entity_name = type_name.strip_suffix(AGGREGATE_SUFFIX)?;
+    match input_schema.kind_of_declared_type(entity_name) {
+        Some(TypeKind::Object) => input_schema.object_or_interface(entity_name, None),
+        _ => None,
+    }
+}
+
+/// Turn the `rows` that `query` produced into nodes for the ad-hoc
+/// aggregation `field` whose type is `typename`. The objects for `group`,
+/// `sum`, `min` and `max` become children of the nodes, just like the
+/// objects for references in entities
+fn aggregate_nodes(
+    query: &EntityAggregateQuery,
+    typename: &str,
+    field: &a::Field,
+    rows: Vec<Vec<StoreValue>>,
+) -> Vec<Node> {
+    fn object(typename: String, values: impl Iterator<Item = (String, StoreValue)>) -> Object {
+        Object::from_iter(
+            std::iter::once((Word::from("__typename"), r::Value::String(typename)))
+                .chain(values.map(|(name, value)| (Word::from(name), r::Value::from(value)))),
+        )
+    }
+
+    /// The attribute over which `aggregate` is computed if it belongs to
+    /// the field `name` of the result type
+    fn selected_attr<'a>(name: &str, aggregate: &'a EntityAggregate) -> Option<&'a str> {
+        match (name, aggregate) {
+            ("sum", EntityAggregate::Sum(attr))
+            | ("min", EntityAggregate::Min(attr))
+            | ("max", EntityAggregate::Max(attr)) => Some(attr),
+            _ => None,
+        }
+    }
+
+    let ngroup = query.group_by.len();
+    rows.into_iter()
+        .map(|row| {
+            // Every row has the group values, followed by the aggregates
+            let aggregates = || query.aggregates.iter().zip(row[ngroup..].iter());
+            let count = aggregates()
+                .find(|(aggregate, _)| **aggregate == EntityAggregate::Count)
+                .map(|(_, value)| value.clone())
+                .unwrap_or(StoreValue::Null);
+
+            let mut node = Node::from(object(
+                typename.to_string(),
+                std::iter::once(("count".to_string(), count)),
+            ));
+            for (_, fields) in field.selection_set.fields() {
+                for field in fields {
+                    let values: Vec<_> = match field.name.as_str() {
+                        // Without `groupBy`, `group` is `null`
+                        "group" if ngroup == 0 => {
+                            node.set_children(field.response_key().to_string(), vec![]);
+                            continue;
+                        }
+                        "group" => query
+                            .group_by
+                            .iter()
+                            .cloned()
+                            .zip(row[..ngroup].iter().cloned())
+                            .collect(),
+                        "sum" | "min" | "max" => aggregates()
+                            .filter_map(|(aggregate, value)| {
+                                selected_attr(&field.name, aggregate)
+                                    .map(|attr| (attr.to_string(), value.clone()))
+                            })
+                            .collect(),
+                        _ => continue,
+                    };
+                    let child = Node::from(object(
+                        format!("{}_{}", typename, field.name),
+                        values.into_iter(),
+                    ));
+                    node.set_children(field.response_key().to_string(), vec![Rc::new(child)]);
+                }
+            }
+            node
+        })
+        .collect()
+}
+
 /// Run the query in `ctx` in such a manner that we only perform one query
 /// per 'level' in the query. A query like `musicians { id bands { id } }`
 /// will perform two queries: one for musicians, and one for bands, regardless
@@ -579,6 +804,26 @@ pub fn run(
     Ok((r::Value::Object(obj), trace))
 }
 
+/// Compute the SQL queries that `run` would send to the store for
+/// `selection_set` without running them. If `explain` is `true`, include
+/// the plans that Postgres chooses for them
+pub fn explain(
+    ctx: &ExecutionContext,
+    selection_set: &a::SelectionSet,
+    explain: bool,
+) -> Result<Vec<FieldPlan>, Vec<QueryExecutionError>> {
+    // Introspection and `_meta` fields are not fetched from the store
+    let query_type: sast::ObjectType = ctx.query.schema.query_type.cheap_clone().into();
+    let mut data_set = a::SelectionSet::empty_from(selection_set);
+    for field in selection_set.fields_for(&query_type)? {
+        if !is_introspection_field(&field.name) && field.name != META_FIELD_NAME {
+            data_set.push(field)?;
+        }
+    }
+
+    Loader::new(&ctx.resolver, ctx).explain_selection_set(&data_set, None, true, explain)
+}
+
 struct Loader<'a> {
     resolver: &'a StoreResolver,
     ctx: &'a ExecutionContext,
@@ -628,37 +873,48 @@ impl<'a> Loader<'a> {
                 let field_type = object_type
                     .field(&field.name)
                     .expect("field names are valid");
-                let child_type = input_schema
-                    .object_or_interface(field_type.field_type.get_base_type(), child_interval)
-                    .expect("we only collect fields that are objects or interfaces");
+                let base_type = field_type.field_type.get_base_type();
 
-                let join = if at_root {
-                    MaybeJoin::Root { child_type }
+                // Ad-hoc aggregations are only available on the root
+                // `Query` type and are computed in a single query
+                let aggregated = if at_root {
+                    aggregated_type(&input_schema, base_type)
                 } else {
-                    let object_type = input_schema
-                        .object_or_aggregation(&object_type.name, parent_interval)
-                        .ok_or_else(|| {
-                            vec![QueryExecutionError::ConstraintViolation(format!(
-                                "the type `{}`(interval {}) is not an object type",
-                                object_type.name,
-                                parent_interval
-                                    .map(|intv| intv.as_str())
-                                    .unwrap_or("<none>")
-                            ))]
-                        })?;
-                    let field_type = object_type
-                        .field(&field.name)
-                        .expect("field names are valid");
-                    MaybeJoin::Nested(Join::new(
-                        &input_schema,
-                        object_type.cheap_clone(),
-                        child_type,
-                        field_type,
-                    ))
+                    None
                 };
+                if let Some(entity) = aggregated {
+                    match self.fetch_aggregates(&entity, base_type, field) {
+                        Ok((children, trace)) => {
+                            add_children(
+                                &input_schema,
+                                &mut parents,
+                                children,
+                                field.response_key(),
+                            )?;
+                            self.check_result_size(&parents)?;
+                            parent_trace.push(field.response_key(), trace);
+                        }
+                        Err(e) => errors.push(e),
+                    }
+                    continue;
+                }
+
+                let child_type = input_schema
+                    .object_or_interface(base_type, child_interval)
+                    .expect("we only collect fields that are objects or interfaces");
+
+                let join = MaybeJoin::new(
+                    &input_schema,
+                    &object_type.name,
+                    parent_interval,
+                    child_type,
+                    field,
+                    at_root,
+                )?;
 
                 match self.fetch(&parents, &join, field) {
-                    Ok((children, trace)) => {
+                    Ok((mut children, trace)) => {
+                        add_cursors(join.child_type(), field, &mut children);
                         match self.execute_selection_set(
                             children,
                             trace,
@@ -692,6 +948,76 @@ impl<'a> Loader<'a> {
         }
     }
 
+    /// Compute the SQL queries that `execute_selection_set` would run for
+    /// `selection_set` without running them. Since the parents of nested
+    /// fields are not known before the query runs, the queries for them
+    /// are built for placeholder parents
+    fn explain_selection_set(
+        &self,
+        selection_set: &a::SelectionSet,
+        parent_interval: Option<AggregationInterval>,
+        at_root: bool,
+        explain: bool,
+    ) -> Result<Vec<FieldPlan>, Vec<QueryExecutionError>> {
+        let input_schema = self.resolver.store.input_schema()?;
+        let mut plans = Vec::new();
+
+        for (object_type, fields) in selection_set.interior_fields() {
+            for field in fields {
+                let child_interval = field.aggregation_interval()?;
+                let field_type = object_type
+                    .field(&field.name)
+                    .expect("field names are valid");
+                let base_type = field_type.field_type.get_base_type();
+
+                let aggregated = if at_root {
+                    aggregated_type(&input_schema, base_type)
+                } else {
+                    None
+                };
+                if let Some(entity) = aggregated {
+                    let query = self.aggregate_query(&entity, field)?;
+                    let sql = self
+                        .resolver
+                        .store
+                        .explain_aggregate_query_values(query, explain)?;
+                    plans.push(FieldPlan::new(field.response_key(), sql));
+                    continue;
+                }
+
+                let child_type = input_schema
+                    .object_or_interface(base_type, child_interval)
+                    .expect("we only collect fields that are objects or interfaces");
+                let join = MaybeJoin::new(
+                    &input_schema,
+                    &object_type.name,
+                    parent_interval,
+                    child_type,
+                    field,
+                    at_root,
+                )?;
+                let mut parents = match &join {
+                    MaybeJoin::Root { .. } => make_root_node(),
+                    MaybeJoin::Nested(join) => join.placeholder_parents()?,
+                };
+                let parents: Vec<_> = parents.iter_mut().collect();
+
+                if let Some(query) = self.child_query(&parents, &join, field)? {
+                    let sql = self.resolver.store.explain_query_values(query, explain)?;
+                    let mut plan = FieldPlan::new(field.response_key(), sql);
+                    plan.fields = self.explain_selection_set(
+                        &field.selection_set,
+                        child_interval,
+                        false,
+                        explain,
+                    )?;
+                    plans.push(plan);
+                }
+            }
+        }
+        Ok(plans)
+    }
+
     /// Query child entities for `parents` from the store. The `join` indicates
     /// in which child field to look for the parent's id/join field. When
     /// `is_single` is `true`, there is at most one child per parent.
@@ -701,6 +1027,24 @@ impl<'a> Loader<'a> {
         join: &MaybeJoin<'_>,
         field: &a::Field,
     ) -> Result<(Vec<Node>, Trace), QueryExecutionError> {
+        let query = match self.child_query(parents, join, field)? {
+            Some(query) => query,
+            None => return Ok((vec![], Trace::None)),
+        };
+        self.resolver
+            .store
+            .find_query_values(query)
+            .map(|(values, trace)| (values.into_iter().map(Node::from).collect(), trace))
+    }
+
+    /// Build the query for the child entities of `parents` in `field`.
+    /// Return `None` if none of the parents can have any children
+    fn child_query(
+        &self,
+        parents: &[&mut Node],
+        join: &MaybeJoin<'_>,
+        field: &a::Field,
+    ) -> Result<Option<EntityQuery>, QueryExecutionError> {
         let input_schema = self.resolver.store.input_schema()?;
         let child_type = join.child_type();
         let mut query = build_query(
@@ -742,14 +1086,46 @@ impl<'a> Loader<'a> {
                 &query.collection,
             )?;
             if windows.is_empty() {
-                return Ok((vec![], Trace::None));
+                return Ok(None);
             }
             query.collection = EntityCollection::Window(windows);
         }
-        self.resolver
-            .store
-            .find_query_values(query)
-            .map(|(values, trace)| (values.into_iter().map(Node::from).collect(), trace))
+        Ok(Some(query))
+    }
+
+    /// Compute the ad-hoc aggregation `field` over the collection of
+    /// `entity`. The type of `field` is `typename`
+    fn fetch_aggregates(
+        &self,
+        entity: &ObjectOrInterface<'_>,
+        typename: &str,
+        field: &a::Field,
+    ) -> Result<(Vec<Node>, Trace), QueryExecutionError> {
+        let query = self.aggregate_query(entity, field)?;
+        let (rows, trace) = self.resolver.store.aggregate_query_values(query.clone())?;
+        Ok((aggregate_nodes(&query, typename, field, rows), trace))
+    }
+
+    /// Build the query for the ad-hoc aggregation `field` over the
+    /// collection of `entity`
+    fn aggregate_query(
+        &self,
+        entity: &ObjectOrInterface<'_>,
+        field: &a::Field,
+    ) -> Result<EntityAggregateQuery, QueryExecutionError> {
+        let input_schema = self.resolver.store.input_schema()?;
+        let mut query = build_aggregate_query(
+            entity,
+            self.resolver.block_number(),
+            field,
+            self.ctx.max_first,
+            self.ctx.max_skip,
+            &input_schema,
+        )?;
+        query.trace = self.ctx.trace;
+        query.query_id = Some(self.ctx.query.query_id.clone());
+        query.logger = Some(self.ctx.logger.cheap_clone());
+        Ok(query)
     }
 
     fn check_result_size(&self, parents: &[&mut Node]) -> Result<(), QueryExecutionError> {
@@ -3,8 +3,8 @@ use std::mem::discriminant;
 
 use graph::cheap_clone::CheapClone;
 use graph::components::store::{
-    BlockNumber, Child, EntityCollection, EntityFilter, EntityOrder, EntityOrderByChild,
-    EntityOrderByChildInfo, EntityQuery, EntityRange,
+    BlockNumber, Child, EntityAggregate, EntityAggregateQuery, EntityCollection, EntityFilter,
+    EntityOrder, EntityOrderByChild, EntityOrderByChildInfo, EntityQuery, EntityRange,
 };
 use graph::data::graphql::TypeExt as _;
 use graph::data::query::QueryExecutionError;
@@ -16,6 +16,7 @@ use graph::schema::ast::{self as sast, FilterOp};
 use graph::schema::{ApiSchema, EntityType, InputSchema, ObjectOrInterface};
 
 use crate::execution::ast as a;
+use crate::store::cursor::build_cursor_filter;
 
 #[derive(Debug)]
 enum OrderDirection {
@@ -118,10 +119,80 @@ pub(crate) fn build_query<'a>(
         }
         (None, _) => EntityOrder::Default,
     };
+    if let Some(cursor_filter) = build_cursor_filter(entity, field, &order)? {
+        query.filter = Some(cursor_filter.and_maybe(query.filter));
+    }
     query = query.order(order);
     Ok(query)
 }
 
+/// Builds an `EntityAggregateQuery` for the ad-hoc aggregation `field`
+/// over the collection of `entity`. The aggregates that are computed are
+/// `count` and whatever the selection set of `field` asks for
+pub(crate) fn build_aggregate_query(
+    entity: &ObjectOrInterface<'_>,
+    block: BlockNumber,
+    field: &a::Field,
+    max_first: u32,
+    max_skip: u32,
+    schema: &InputSchema,
+) -> Result<EntityAggregateQuery, QueryExecutionError> {
+    let mut query =
+        EntityAggregateQuery::new(schema.id().cheap_clone(), block, entity.entity_type());
+    query.range = build_range(field, max_first, max_skip)?;
+    query.filter = build_filter(entity, field, schema)?;
+
+    match field.argument_value("groupBy") {
+        Some(r::Value::List(attrs)) => {
+            for attr in attrs {
+                match attr {
+                    r::Value::Enum(attr) | r::Value::String(attr) => {
+                        if !query.group_by.contains(attr) {
+                            query.group_by.push(attr.clone());
+                        }
+                    }
+                    _ => {
+                        return Err(QueryExecutionError::InvalidArgumentError(
+                            field.position,
+                            "groupBy".to_string(),
+                            attr.clone().into(),
+                        ))
+                    }
+                }
+            }
+        }
+        Some(r::Value::Null) | None => {}
+        Some(value) => {
+            return Err(QueryExecutionError::InvalidArgumentError(
+                field.position,
+                "groupBy".to_string(),
+                value.clone().into(),
+            ))
+        }
+    }
+
+    query.aggregates.push(EntityAggregate::Count);
+    for (_, fields) in field.selection_set.fields() {
+        for field in fields {
+            let aggregate: fn(Attribute) -> EntityAggregate = match field.name.as_str() {
+                "sum" => EntityAggregate::Sum,
+                "min" => EntityAggregate::Min,
+                "max" => EntityAggregate::Max,
+                _ => continue,
+            };
+            for (_, attrs) in field.selection_set.fields() {
+                for attr in attrs.filter(|attr| !attr.name.starts_with("__")) {
+                    let aggregate = aggregate(attr.name.to_string());
+                    if !query.aggregates.contains(&aggregate) {
+                        query.aggregates.push(aggregate);
+                    }
+                }
+            }
+        }
+    }
+    Ok(query)
+}
+
 /// Parses GraphQL arguments into a EntityRange, if present.
 fn build_range(
     field: &a::Field,
@@ -5,7 +5,7 @@ use graph::bail;
 use graph::endpoint::EndpointMetrics;
 use graph::env::ENV_VARS;
 use graph::log::logger_with_levels;
-use graph::prelude::{MetricsRegistry, BLOCK_NUMBER_MAX};
+use graph::prelude::{BlockNumber, MetricsRegistry, BLOCK_NUMBER_MAX};
 use graph::{data::graphql::load_manager::LoadManager, prelude::chrono, prometheus::Registry};
 use graph::{
     prelude::{
@@ -249,6 +249,26 @@ pub enum Command {
         /// Prometheus push gateway endpoint.
         prometheus_host: Option<String>,
     },
+    /// Replay blocks of a deployment and compare the result with the store
+    ///
+    /// Run the mappings of the deployment for the blocks after `--from` up
+    /// to and including `--to`, starting from the entities as they were
+    /// at block `--from`. Nothing that the mappings write is stored; the
+    /// changes they make are kept in memory and compared to the changes
+    /// that are stored for each block. Only differences are printed.
+    ///
+    /// Like `run`, this command only supports subgraphs on Ethereum
+    /// networks.
+    Replay {
+        /// The deployment (see `help info`)
+        deployment: DeploymentSearch,
+        /// Replay the blocks after this block
+        #[clap(long)]
+        from: BlockNumber,
+        /// The last block to replay
+        #[clap(long)]
+        to: BlockNumber,
+    },
     /// Check and interrogate the configuration
     ///
     /// Print information about a configuration file without
@@ -279,6 +299,26 @@ pub enum Command {
         /// The variables in the form `key=value`
         vars: Vec<String>,
     },
+    /// Run a read-only SQL query against the tables of a deployment
+    ///
+    /// Entity types can be used as table names; the query only sees the
+    /// entities that are visible at the given block, or the latest block
+    /// the deployment has processed
+    Sql {
+        /// Save the JSON query result in this file
+        #[clap(long, short)]
+        output: Option<String>,
+        /// Run the query at this block instead of the latest block
+        #[clap(long, short)]
+        block: Option<BlockNumber>,
+
+        /// The subgraph to query
+        ///
+        /// Either a deployment id `Qm..` or a subgraph name
+        target: String,
+        /// The SQL query
+        query: String,
+    },
     /// Get information about chains and manipulate them
     #[clap(subcommand)]
     Chain(ChainCommand),
@@ -320,10 +360,72 @@ pub enum Command {
         once: bool,
     },
 
+    /// Export the entities of a deployment into CSV files
+    ///
+    /// Writes one CSV file per entity type into `directory`, together with
+    /// a file `dump.json` that describes the dump. By default, the files
+    /// contain the entities as they are at the deployment head. With
+    /// `--block`, they contain the entities at an earlier block, and with
+    /// `--history`, they contain all entity versions with their block
+    /// ranges
+    Dump {
+        /// The deployment to dump (see `help info`)
+        deployment: DeploymentSearch,
+        /// The directory into which to write the dump
+        directory: String,
+        /// Dump the entities as of this block
+        #[clap(long, short, conflicts_with = "history")]
+        block: Option<BlockNumber>,
+        /// Dump all entity versions instead of a snapshot
+        #[clap(long)]
+        history: bool,
+    },
+
+    /// Create a deployment from a dump
+    ///
+    /// The dump in `directory` must have been taken with `dump --history`
+    /// and the deployment must not exist yet. The deployment is created in
+    /// `shard`, its data is loaded from the dump, and it is assigned to
+    /// `node` which will continue indexing from the block at which the
+    /// dump was taken
+    Restore {
+        /// The directory that contains the dump
+        directory: String,
+        /// The name of the database shard in which to create the deployment
+        shard: String,
+        /// The name of the node that should index the deployment
+        node: String,
+    },
+
+    /// Compare proofs of indexing with those of other indexers
+    #[clap(subcommand)]
+    Poi(PoiCommand),
+
     /// General database management
     #[clap(subcommand)]
     Database(DatabaseCommand),
 
+    /// Manage the on-disk cache for the content of IPFS and Arweave files
+    #[clap(subcommand)]
+    ContentCache(ContentCacheCommand),
+
+    /// Manage the persisted queries and the query allowlist of a deployment
+    #[clap(subcommand)]
+    PersistedQuery(PersistedQueryCommand),
+
+    /// Record the changes to a deployment's entities for the entity feed
+    ///
+    /// The changes are recorded as the deployment writes them, starting
+    /// with the block the deployment has processed last; the feed is
+    /// pruned together with the rest of the deployment's history
+    EntityFeed {
+        /// Stop recording the feed and remove it
+        #[clap(long, short)]
+        clear: bool,
+        /// The deployment (see `help info`)
+        deployment: DeploymentSearch,
+    },
+
     /// Delete a deployment and all it's indexed data
     ///
     /// The deployment can be specified as either a subgraph name, an IPFS
@@ -458,6 +560,47 @@ pub enum ListenCommand {
         entity_types: Vec<String>,
     },
 }
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum PoiCommand {
+    /// Find the first block at which the proof of indexing of a deployment
+    /// differs from that of another indexer
+    ///
+    /// The `reference` is either the URL of the GraphQL endpoint of another
+    /// indexer's index node, e.g., `http://indexer:8030/graphql`, or the
+    /// name of a JSON file with the result of a `publicProofsOfIndexing`
+    /// query. With a file, only the blocks that it contains are compared.
+    /// For the first block that differs, the command prints which causality
+    /// regions diverge and, if the reference provides them, how the entity
+    /// changes in that block differ
+    Bisect {
+        /// The deployment to check (see `help info`)
+        deployment: DeploymentSearch,
+        /// The index node URL or file with proofs of indexing to compare with
+        reference: String,
+        /// The first block to compare. Defaults to the earliest block of
+        /// the deployment
+        #[clap(long, short)]
+        start: Option<BlockNumber>,
+        /// The last block to compare. Defaults to the deployment head
+        #[clap(long, short)]
+        end: Option<BlockNumber>,
+    },
+    /// Record the digests of the proof of indexing for every block
+    ///
+    /// With the history, proofs of indexing for past blocks can be looked
+    /// up directly instead of being computed from the PoI table. The
+    /// history is filled with what the PoI table still has, and it is
+    /// pruned together with the rest of the deployment's history
+    History {
+        /// Stop recording the history and remove it
+        #[clap(long, short)]
+        clear: bool,
+        /// The deployment (see `help info`)
+        deployment: DeploymentSearch,
+    },
+}
+
 #[derive(Clone, Debug, Subcommand)]
 pub enum CopyCommand {
     /// Create a copy of an existing subgraph
@@ -737,6 +880,87 @@ pub enum IndexCommand {
         #[clap(empty_values = false)]
         index_name: String,
     },
+
+    /// Proposes indexes based on the recorded query workload
+    ///
+    /// Proposes a composite index for each combination of filter and sort
+    /// columns that was seen in slow queries and that no existing index
+    /// covers. Queries are only recorded while
+    /// `GRAPH_STORE_RECORD_QUERY_WORKLOAD` is set. Also lists indexes that
+    /// Postgres has never used since its statistics were last reset; these
+    /// are candidates for `graphman index drop`.
+    Advise {
+        /// Create the proposed indexes, concurrently. This may be
+        /// time-consuming.
+        #[clap(long)]
+        create: bool,
+        /// The deployment (see `help info`).
+        #[clap(empty_values = false)]
+        deployment: DeploymentSearch,
+    },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum ContentCacheCommand {
+    /// Add the files in CAR files to the content cache
+    ///
+    /// Every file in the archives is added under the path under which
+    /// subgraphs read it from IPFS: the CID of files that are a root of an
+    /// archive, and the CID of the root followed by the path within it for
+    /// files in directories. The blocks of the archives are checked against
+    /// their hashes. A running `graph-node` that uses the same cache
+    /// directory finds the files without being restarted
+    Seed {
+        /// The directory of the content cache
+        #[clap(long, env = "GRAPH_CONTENT_CACHE_DIR")]
+        dir: String,
+        /// The CAR files
+        #[clap(required = true)]
+        files: Vec<String>,
+    },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum PersistedQueryCommand {
+    /// Register the queries in the given files for a deployment
+    ///
+    /// Each file must contain one GraphQL query. Leading and trailing
+    /// whitespace is removed, and the query is registered under the
+    /// hex-encoded SHA-256 hash of the remaining text, which is printed for
+    /// each file. Running query nodes pick up changes within a minute
+    Add {
+        /// The deployment (see `help info`)
+        deployment: DeploymentSearch,
+        /// The files with the queries
+        #[clap(required = true)]
+        files: Vec<String>,
+    },
+    /// Remove a persisted query from a deployment
+    Remove {
+        /// The deployment (see `help info`)
+        deployment: DeploymentSearch,
+        /// The hash of the query
+        hash: String,
+    },
+    /// List the persisted queries of a deployment
+    List {
+        /// The deployment (see `help info`)
+        deployment: DeploymentSearch,
+        /// Print the text of each query, too
+        #[clap(long, short)]
+        show: bool,
+    },
+    /// Only execute persisted queries for a deployment
+    ///
+    /// While the allowlist is enabled, the deployment rejects every query
+    /// that is not registered with `persisted-query add` before parsing it
+    Allowlist {
+        /// The deployment (see `help info`)
+        deployment: DeploymentSearch,
+        /// Execute any query for the deployment again
+        #[clap(long)]
+        disable: bool,
+    },
 }
 
 #[derive(Clone, Debug, Subcommand)]
@@ -1242,6 +1466,33 @@ async fn main() -> anyhow::Result<()> {
             )
             .await
         }
+        Replay {
+            deployment,
+            from,
+            to,
+        } => {
+            let logger = ctx.logger.clone();
+            let config = ctx.config();
+            let registry = ctx.metrics_registry().clone();
+            let node_id = ctx.node_id().clone();
+            let store_builder = ctx.store_builder().await;
+            let ipfs_url = ctx.ipfs_url.clone();
+            let arweave_url = ctx.arweave_url.clone();
+
+            commands::replay::run(
+                logger,
+                store_builder,
+                ipfs_url,
+                arweave_url,
+                config,
+                registry,
+                node_id,
+                deployment,
+                from,
+                to,
+            )
+            .await
+        }
         Listen(cmd) => {
             use ListenCommand::*;
             match cmd {
@@ -1287,6 +1538,12 @@ async fn main() -> anyhow::Result<()> {
             query,
             vars,
         } => commands::query::run(ctx.graphql_runner(), target, query, vars, output, trace).await,
+        Sql {
+            output,
+            block,
+            target,
+            query,
+        } => commands::sql::run(ctx.graphql_runner(), target, query, block, output).await,
         Chain(cmd) => {
             use ChainCommand::*;
             match cmd {
@@ -1493,6 +1750,9 @@ async fn main() -> anyhow::Result<()> {
                     commands::index::drop(subgraph_store, primary_pool, deployment, &index_name)
                         .await
                 }
+                Advise { deployment, create } => {
+                    commands::index::advise(subgraph_store, primary_pool, deployment, create).await
+                }
             }
         }
         Database(cmd) => {
@@ -1533,6 +1793,75 @@ async fn main() -> anyhow::Result<()> {
             )
             .await
         }
+        Dump {
+            deployment,
+            directory,
+            block,
+            history,
+        } => {
+            let (store, primary_pool) = ctx.store_and_primary();
+            commands::dump::run(
+                store.subgraph_store(),
+                primary_pool,
+                deployment,
+                directory,
+                history,
+                block,
+            )
+        }
+        Restore {
+            directory,
+            shard,
+            node,
+        } => {
+            let shards: Vec<_> = ctx.config.stores.keys().cloned().collect();
+            let logger = ctx.logger.clone();
+            commands::restore::run(ctx.subgraph_store(), logger, directory, shard, shards, node)
+        }
+        Poi(cmd) => match cmd {
+            PoiCommand::Bisect {
+                deployment,
+                reference,
+                start,
+                end,
+            } => {
+                let (store, primary_pool) = ctx.store_and_primary();
+                commands::poi::bisect(store, primary_pool, deployment, reference, start, end).await
+            }
+            PoiCommand::History { clear, deployment } => {
+                let (store, primary_pool) = ctx.store_and_primary();
+                commands::poi::history(store, primary_pool, deployment, clear).await
+            }
+        },
+        ContentCache(cmd) => match cmd {
+            ContentCacheCommand::Seed { dir, files } => {
+                commands::content_cache::seed(&ctx.logger, &dir, files).await
+            }
+        },
+        PersistedQuery(cmd) => {
+            use PersistedQueryCommand::*;
+
+            let primary = ctx.primary_pool();
+            match cmd {
+                Add { deployment, files } => {
+                    commands::persisted_query::add(primary, &deployment, files)
+                }
+                Remove { deployment, hash } => {
+                    commands::persisted_query::remove(primary, &deployment, &hash)
+                }
+                List { deployment, show } => {
+                    commands::persisted_query::list(primary, &deployment, show)
+                }
+                Allowlist {
+                    deployment,
+                    disable,
+                } => commands::persisted_query::allowlist(primary, &deployment, disable),
+            }
+        }
+        EntityFeed { clear, deployment } => {
+            let (store, primary_pool) = ctx.store_and_primary();
+            commands::entity_feed::run(store, primary_pool, deployment, clear).await
+        }
         Drop {
             deployment,
             current,
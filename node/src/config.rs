@@ -1,6 +1,7 @@
 use graph::{
     anyhow::Error,
     blockchain::BlockchainKind,
+    components::sink::{KafkaConfig, WebhookConfig, WebhookEventKind},
     env::ENV_VARS,
     firehose::{SubgraphLimit, SUBGRAPHS_PER_CONN},
     itertools::Itertools,
@@ -12,10 +13,11 @@ use graph::{
             de::{self, value, SeqAccess, Visitor},
             Deserialize, Deserializer, Serialize,
         },
-        serde_json, serde_regex, toml, Logger, NodeId, StoreError,
+        serde_json, serde_regex, toml, DeploymentHash, Logger, NodeId, StoreError,
     },
 };
 use graph_chain_ethereum::{self as ethereum, NodeCapabilities};
+use graph_server_http::{ApiKey, Limits, QueryLimitsConfig};
 use graph_store_postgres::{DeploymentPlacer, Shard as ShardName, PRIMARY_SHARD};
 
 use http::{HeaderMap, Uri};
@@ -74,6 +76,13 @@ pub struct Config {
     pub stores: BTreeMap<String, Shard>,
     pub chains: ChainSection,
     pub deployment: Deployment,
+    #[serde(default, rename = "webhook")]
+    pub webhooks: Vec<Webhook>,
+    #[serde(default)]
+    pub kafka: Vec<Kafka>,
+    #[serde(default)]
+    pub http_sources: HttpSources,
+    pub query_limits: Option<QueryLimits>,
 }
 
 fn validate_name(s: &str) -> Result<()> {
@@ -146,6 +155,20 @@ impl Config {
 
         self.chains.validate()?;
 
+        for webhook in &self.webhooks {
+            webhook.validate()?;
+        }
+
+        for kafka in &self.kafka {
+            kafka.validate()?;
+        }
+
+        self.http_sources.validate()?;
+
+        if let Some(query_limits) = &self.query_limits {
+            query_limits.validate()?;
+        }
+
         Ok(())
     }
 
@@ -188,6 +211,10 @@ impl Config {
             stores,
             chains,
             deployment,
+            webhooks: vec![],
+            kafka: vec![],
+            http_sources: HttpSources::default(),
+            query_limits: None,
         })
     }
 
@@ -947,6 +974,321 @@ impl std::fmt::Display for Transport {
     }
 }
 
+/// An HTTP endpoint that gets notified about changes to deployments
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Webhook {
+    pub url: String,
+    #[serde(
+        skip_serializing,
+        default,
+        deserialize_with = "deserialize_http_headers"
+    )]
+    pub headers: HeaderMap,
+    #[serde(default = "WebhookEventKind::defaults")]
+    pub events: Vec<WebhookEventKind>,
+    /// The entity types for which `entities` notifications are sent
+    #[serde(default)]
+    pub entity_types: Vec<String>,
+    /// Only send notifications for these deployments; all deployments if
+    /// empty
+    #[serde(default)]
+    pub deployments: Vec<String>,
+    #[serde(default = "default_webhook_max_attempts")]
+    pub max_attempts: usize,
+    #[serde(
+        default = "default_webhook_timeout",
+        deserialize_with = "deserialize_duration_millis"
+    )]
+    pub timeout: Duration,
+}
+
+impl Webhook {
+    fn validate(&self) -> Result<()> {
+        let url = Url::parse(&self.url).context(format!("invalid webhook url {}", self.url))?;
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(anyhow!("webhook url {} must use http or https", self.url));
+        }
+        if self.events.contains(&WebhookEventKind::Entities) && self.entity_types.is_empty() {
+            return Err(anyhow!(
+                "webhook {} wants `entities` notifications but does not list any entity types",
+                self.url
+            ));
+        }
+        if self.max_attempts == 0 {
+            return Err(anyhow!(
+                "webhook {} must allow at least one attempt",
+                self.url
+            ));
+        }
+        for deployment in &self.deployments {
+            DeploymentHash::new(deployment.as_str()).map_err(|hash| {
+                anyhow!("webhook {} lists invalid deployment {}", self.url, hash)
+            })?;
+        }
+        Ok(())
+    }
+
+    pub fn to_config(&self) -> WebhookConfig {
+        WebhookConfig {
+            url: self.url.clone(),
+            headers: self.headers.clone(),
+            events: self.events.iter().cloned().collect(),
+            entity_types: self.entity_types.iter().cloned().collect(),
+            deployments: self
+                .deployments
+                .iter()
+                .map(|deployment| {
+                    DeploymentHash::new(deployment.as_str())
+                        .expect("webhook deployments have been validated")
+                })
+                .collect(),
+            max_attempts: self.max_attempts,
+            timeout: self.timeout,
+        }
+    }
+}
+
+fn default_webhook_max_attempts() -> usize {
+    10
+}
+
+fn default_webhook_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
+/// A Kafka-compatible broker to which the entity changes of deployments
+/// are published
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Kafka {
+    pub brokers: Vec<String>,
+    #[serde(default = "default_kafka_topic_prefix")]
+    pub topic_prefix: String,
+    #[serde(default = "default_kafka_replication_factor")]
+    pub replication_factor: i16,
+    /// Only publish changes for these deployments; all deployments if
+    /// empty
+    #[serde(default)]
+    pub deployments: Vec<String>,
+    #[serde(
+        default = "default_kafka_timeout",
+        deserialize_with = "deserialize_duration_millis"
+    )]
+    pub timeout: Duration,
+    #[serde(default = "default_kafka_max_attempts")]
+    pub max_attempts: usize,
+}
+
+impl Kafka {
+    fn validate(&self) -> Result<()> {
+        if self.brokers.is_empty() {
+            return Err(anyhow!("kafka sections must list at least one broker"));
+        }
+        let brokers = self.brokers.join(",");
+        let valid_topic = |c: char| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-';
+        if !self.topic_prefix.chars().all(valid_topic) {
+            return Err(anyhow!(
+                "kafka topic prefix `{}` for {} may only contain ASCII letters, digits, \
+                 `.`, `_`, and `-`",
+                self.topic_prefix,
+                brokers
+            ));
+        }
+        if self.replication_factor < 1 {
+            return Err(anyhow!(
+                "kafka replication factor for {} must be at least 1",
+                brokers
+            ));
+        }
+        if self.max_attempts == 0 {
+            return Err(anyhow!("kafka {} must allow at least one attempt", brokers));
+        }
+        for deployment in &self.deployments {
+            DeploymentHash::new(deployment.as_str())
+                .map_err(|hash| anyhow!("kafka {} lists invalid deployment {}", brokers, hash))?;
+        }
+        Ok(())
+    }
+
+    pub fn to_config(&self) -> KafkaConfig {
+        KafkaConfig {
+            brokers: self.brokers.clone(),
+            topic_prefix: self.topic_prefix.clone(),
+            replication_factor: self.replication_factor,
+            deployments: self
+                .deployments
+                .iter()
+                .map(|deployment| {
+                    DeploymentHash::new(deployment.as_str())
+                        .expect("kafka deployments have been validated")
+                })
+                .collect(),
+            timeout: self.timeout,
+            max_attempts: self.max_attempts,
+        }
+    }
+}
+
+fn default_kafka_topic_prefix() -> String {
+    "graph-node.".to_string()
+}
+
+fn default_kafka_replication_factor() -> i16 {
+    1
+}
+
+fn default_kafka_timeout() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_kafka_max_attempts() -> usize {
+    10
+}
+
+/// The URLs from which `file/http` data sources may fetch their content
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct HttpSources {
+    /// Only URLs that start with one of these URLs are fetched; no `file/http`
+    /// data sources are fetched if this is empty
+    #[serde(default)]
+    pub allow: Vec<String>,
+    #[serde(default = "default_http_sources_max_file_size")]
+    pub max_file_size: u64,
+    #[serde(
+        default = "default_http_sources_timeout",
+        deserialize_with = "deserialize_duration_millis"
+    )]
+    pub timeout: Duration,
+    /// The number of requests per second that are sent at most
+    #[serde(default = "default_http_sources_rate_limit")]
+    pub rate_limit: u16,
+}
+
+impl Default for HttpSources {
+    fn default() -> Self {
+        Self {
+            allow: vec![],
+            max_file_size: default_http_sources_max_file_size(),
+            timeout: default_http_sources_timeout(),
+            rate_limit: default_http_sources_rate_limit(),
+        }
+    }
+}
+
+impl HttpSources {
+    fn validate(&self) -> Result<()> {
+        for url in &self.allow {
+            let parsed =
+                Url::parse(url).context(format!("invalid http_sources allow url {}", url))?;
+            if parsed.scheme() != "http" && parsed.scheme() != "https" {
+                return Err(anyhow!(
+                    "http_sources allow url {} must use http or https",
+                    url
+                ));
+            }
+            if parsed.query().is_some() || parsed.fragment().is_some() {
+                return Err(anyhow!(
+                    "http_sources allow url {} must not have a query or a fragment",
+                    url
+                ));
+            }
+        }
+        if self.rate_limit == 0 {
+            return Err(anyhow!("http_sources rate_limit must be at least 1"));
+        }
+        Ok(())
+    }
+
+    pub fn allowed_urls(&self) -> Vec<Url> {
+        self.allow
+            .iter()
+            .map(|url| Url::parse(url).expect("http_sources urls have been validated"))
+            .collect()
+    }
+}
+
+fn default_http_sources_max_file_size() -> u64 {
+    25 * 1024 * 1024
+}
+
+fn default_http_sources_timeout() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_http_sources_rate_limit() -> u16 {
+    100
+}
+
+/// Limits for the rate and the complexity of the queries that clients
+/// send to the GraphQL HTTP server. Clients are identified by an API key
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct QueryLimits {
+    /// The header that contains the API key
+    #[serde(default = "default_query_limits_header")]
+    pub header: String,
+    /// The limits that all requests without a known API key share
+    pub queries_per_minute: Option<u64>,
+    pub complexity_per_minute: Option<u64>,
+    #[serde(default, rename = "key")]
+    pub keys: Vec<QueryLimitsKey>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct QueryLimitsKey {
+    /// The name under which metrics for the key are reported
+    pub name: String,
+    #[serde(skip_serializing)]
+    pub key: String,
+    pub queries_per_minute: Option<u64>,
+    pub complexity_per_minute: Option<u64>,
+}
+
+impl QueryLimits {
+    fn validate(&self) -> Result<()> {
+        http::header::HeaderName::from_bytes(self.header.as_bytes())
+            .context(format!("invalid query_limits header {}", self.header))?;
+        let mut names = BTreeSet::new();
+        let mut keys = BTreeSet::new();
+        for key in &self.keys {
+            if key.name.is_empty() || key.key.is_empty() {
+                return Err(anyhow!("query_limits keys must have a name and a key"));
+            }
+            if !names.insert(&key.name) {
+                return Err(anyhow!("query_limits key name {} is not unique", key.name));
+            }
+            if !keys.insert(&key.key) {
+                return Err(anyhow!("query_limits key {} reuses a key", key.name));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_config(&self) -> QueryLimitsConfig {
+        QueryLimitsConfig {
+            header: self.header.clone(),
+            anonymous: Limits {
+                queries_per_minute: self.queries_per_minute,
+                complexity_per_minute: self.complexity_per_minute,
+            },
+            keys: self
+                .keys
+                .iter()
+                .map(|key| ApiKey {
+                    name: key.name.clone(),
+                    key: key.key.clone(),
+                    limits: Limits {
+                        queries_per_minute: key.queries_per_minute,
+                        complexity_per_minute: key.complexity_per_minute,
+                    },
+                })
+                .collect(),
+        }
+    }
+}
+
+fn default_query_limits_header() -> String {
+    "X-Api-Key".to_string()
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Deployment {
     #[serde(rename = "rule")]
@@ -1201,9 +1543,11 @@ mod tests {
     use crate::config::{default_polling_interval, ChainSection, Web3Rule};
 
     use super::{
-        Chain, Config, FirehoseProvider, Provider, ProviderDetails, Transport, Web3Provider,
+        Chain, Config, FirehoseProvider, HttpSources, Kafka, Provider, ProviderDetails,
+        QueryLimits, Transport, Web3Provider, Webhook,
     };
     use graph::blockchain::BlockchainKind;
+    use graph::components::sink::WebhookEventKind;
     use graph::firehose::SubgraphLimit;
     use graph::prelude::regex::Regex;
     use graph::prelude::{toml, NodeId};
@@ -1211,6 +1555,7 @@ mod tests {
     use std::collections::BTreeSet;
     use std::fs::read_to_string;
     use std::path::{Path, PathBuf};
+    use std::time::Duration;
 
     #[test]
     fn it_works_on_standard_config() {
@@ -1226,6 +1571,133 @@ mod tests {
         assert_eq!(4, actual.chains.chains.len());
         assert_eq!(2, actual.stores.len());
         assert_eq!(3, actual.deployment.rules.len());
+        assert_eq!(1, actual.webhooks.len());
+        assert!(actual.query_limits.is_some());
+        assert_eq!(1, actual.kafka.len());
+    }
+
+    #[test]
+    fn it_works_on_kafka() {
+        let kafka: Kafka = toml::from_str(
+            r#"
+            brokers = ["localhost:9092"]
+        "#,
+        )
+        .unwrap();
+        kafka.validate().unwrap();
+        assert_eq!("graph-node.", kafka.topic_prefix);
+        assert_eq!(1, kafka.replication_factor);
+        assert_eq!(10, kafka.max_attempts);
+
+        let kafka: Kafka = toml::from_str(
+            r#"
+            brokers = ["localhost:9092"]
+            topic_prefix = "subgraphs/"
+        "#,
+        )
+        .unwrap();
+        assert!(kafka.validate().is_err());
+
+        let kafka: Kafka = toml::from_str(
+            r#"
+            brokers = []
+        "#,
+        )
+        .unwrap();
+        assert!(kafka.validate().is_err());
+    }
+
+    #[test]
+    fn it_works_on_http_sources() {
+        let http: HttpSources = toml::from_str(
+            r#"
+            allow = ["https://api.example.com/v1/", "http://localhost:8080"]
+        "#,
+        )
+        .unwrap();
+        http.validate().unwrap();
+        assert_eq!(2, http.allowed_urls().len());
+        assert_eq!(100, http.rate_limit);
+        assert_eq!(Duration::from_secs(60), http.timeout);
+
+        let http: HttpSources = toml::from_str(
+            r#"
+            allow = ["ftp://example.com/"]
+        "#,
+        )
+        .unwrap();
+        assert!(http.validate().is_err());
+
+        let http: HttpSources = toml::from_str(
+            r#"
+            allow = ["https://example.com/?key=secret"]
+        "#,
+        )
+        .unwrap();
+        assert!(http.validate().is_err());
+    }
+
+    #[test]
+    fn it_works_on_query_limits() {
+        let limits: QueryLimits = toml::from_str(
+            r#"
+            queries_per_minute = 600
+            [[key]]
+            name = "gateway"
+            key = "secret"
+            complexity_per_minute = 1000000
+        "#,
+        )
+        .unwrap();
+        limits.validate().unwrap();
+        assert_eq!("X-Api-Key", limits.header);
+        let config = limits.to_config();
+        assert_eq!(Some(600), config.anonymous.queries_per_minute);
+        assert_eq!(None, config.keys[0].limits.queries_per_minute);
+        assert_eq!(Some(1000000), config.keys[0].limits.complexity_per_minute);
+
+        let limits: QueryLimits = toml::from_str(
+            r#"
+            [[key]]
+            name = "gateway"
+            key = "secret"
+            [[key]]
+            name = "other"
+            key = "secret"
+        "#,
+        )
+        .unwrap();
+        assert!(limits.validate().is_err());
+    }
+
+    #[test]
+    fn it_works_on_webhooks() {
+        let webhook: Webhook = toml::from_str(
+            r#"
+            url = "http://localhost:8080/hook"
+        "#,
+        )
+        .unwrap();
+        webhook.validate().unwrap();
+        assert_eq!(WebhookEventKind::defaults(), webhook.events);
+        assert_eq!(10, webhook.max_attempts);
+
+        let webhook: Webhook = toml::from_str(
+            r#"
+            url = "http://localhost:8080/hook"
+            events = ["entities"]
+        "#,
+        )
+        .unwrap();
+        assert!(webhook.validate().is_err());
+
+        let webhook: Webhook = toml::from_str(
+            r#"
+            url = "ftp://localhost/hook"
+        "#,
+        )
+        .unwrap();
+        assert!(webhook.validate().is_err());
     }
 
     #[test]
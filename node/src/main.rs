@@ -9,7 +9,8 @@ use graph::blockchain::{
     BasicBlockchainBuilder, Blockchain, BlockchainBuilder, BlockchainKind, BlockchainMap,
     ChainIdentifier,
 };
-use graph::components::link_resolver::{ArweaveClient, FileSizeLimit};
+use graph::components::link_resolver::{ArweaveClient, ContentCache, FileSizeLimit};
+use graph::components::query_cache::invalidate_on_chain_head;
 use graph::components::store::BlockStore;
 use graph::components::subgraph::Settings;
 use graph::data::graphql::load_manager::LoadManager;
@@ -26,12 +27,14 @@ use graph_chain_ethereum as ethereum;
 use graph_chain_near::{self as near, HeaderOnlyBlock as NearFirehoseHeaderOnlyBlock};
 use graph_chain_starknet::{self as starknet, Block as StarknetBlock};
 use graph_chain_substreams as substreams;
-use graph_core::polling_monitor::{arweave_service, ipfs_service};
+use graph_core::polling_monitor::{
+    arweave_service, http_service, ipfs_service, local_ipfs_service,
+};
 use graph_core::{
     SubgraphAssignmentProvider as IpfsSubgraphAssignmentProvider, SubgraphInstanceManager,
     SubgraphRegistrar as IpfsSubgraphRegistrar,
 };
-use graph_graphql::prelude::GraphQlRunner;
+use graph_graphql::prelude::{shared_query_cache, GraphQlRunner};
 use graph_node::chain::{
     connect_ethereum_networks, connect_firehose_networks, create_all_ethereum_networks,
     create_firehose_networks, create_ipfs_clients, create_substreams_networks,
@@ -39,7 +42,7 @@ use graph_node::chain::{
 use graph_node::config::Config;
 use graph_node::opt;
 use graph_node::store_builder::StoreBuilder;
-use graph_server_http::GraphQLServer as GraphQLQueryServer;
+use graph_server_http::{GraphQLServer as GraphQLQueryServer, QueryLimiter};
 use graph_server_index_node::IndexNodeServer;
 use graph_server_json_rpc::JsonRpcServer;
 use graph_server_metrics::PrometheusMetricsServer;
@@ -48,7 +51,7 @@ use graph_store_postgres::{register_jobs as register_store_jobs, ChainHeadUpdate
 use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::io::{BufRead, BufReader};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 use tokio::sync::mpsc;
 
@@ -232,15 +235,37 @@ async fn main() {
     let logger_factory =
         LoggerFactory::new(logger.clone(), elastic_config, metrics_registry.clone());
 
-    // Try to create IPFS clients for each URL specified in `--ipfs`
-    let ipfs_clients: Vec<_> = create_ipfs_clients(&logger, &opt.ipfs);
-    let ipfs_client = ipfs_clients.first().cloned().expect("Missing IPFS client");
-    let ipfs_service = ipfs_service(
-        ipfs_client,
-        ENV_VARS.mappings.max_ipfs_file_bytes as u64,
-        ENV_VARS.mappings.ipfs_timeout,
-        ENV_VARS.mappings.ipfs_request_limit,
-    );
+    let content_cache =
+        ContentCache::from_env(&logger, &env_vars).expect("failed to open the content cache");
+
+    // Serve IPFS files from the archives specified in `--ipfs-local`, or
+    // from the IPFS nodes specified in `--ipfs`
+    let (link_resolver, ipfs_service): (Arc<dyn LinkResolver>, _) = if opt.ipfs_local.is_empty() {
+        // Try to create IPFS clients for each URL specified in `--ipfs`
+        let ipfs_clients: Vec<_> = create_ipfs_clients(&logger, &opt.ipfs);
+        let ipfs_client = ipfs_clients.first().cloned().expect("Missing IPFS client");
+        let ipfs_service = ipfs_service(
+            ipfs_client,
+            ENV_VARS.mappings.max_ipfs_file_bytes as u64,
+            ENV_VARS.mappings.ipfs_timeout,
+            ENV_VARS.mappings.ipfs_request_limit,
+            content_cache.cheap_clone(),
+        );
+        let link_resolver = IpfsResolver::new(ipfs_clients, env_vars.cheap_clone())
+            .with_content_cache(content_cache.cheap_clone());
+        (Arc::new(link_resolver), ipfs_service)
+    } else {
+        let paths: Vec<_> = opt.ipfs_local.iter().map(PathBuf::from).collect();
+        let link_resolver = LocalResolver::new(&paths, env_vars.cheap_clone())
+            .expect("failed to read the local IPFS archives");
+        info!(logger, "Serving IPFS files from local archives";
+            "paths" => opt.ipfs_local.join(", "), "blocks" => link_resolver.archive().len());
+        let ipfs_service = local_ipfs_service(
+            link_resolver.archive(),
+            ENV_VARS.mappings.max_ipfs_file_bytes as u64,
+        );
+        (Arc::new(link_resolver), ipfs_service)
+    };
     let arweave_resolver = Arc::new(ArweaveClient::new(
         logger.cheap_clone(),
         opt.arweave
@@ -256,11 +281,16 @@ async fn main() {
             0 => FileSizeLimit::Unlimited,
             n => FileSizeLimit::MaxBytes(n as u64),
         },
+        content_cache,
+    );
+
+    let http_service = http_service(
+        config.http_sources.allowed_urls(),
+        config.http_sources.max_file_size,
+        config.http_sources.timeout,
+        config.http_sources.rate_limit,
     );
 
-    // Convert the clients into a link resolver. Since we want to get past
-    // possible temporary DNS failures, make the resolver retry
-    let link_resolver = Arc::new(IpfsResolver::new(ipfs_clients, env_vars.cheap_clone()));
     let mut metrics_server =
         PrometheusMetricsServer::new(&logger_factory, prometheus_registry.clone());
 
@@ -319,6 +349,17 @@ async fn main() {
         let chain_head_update_listener = store_builder.chain_head_update_listener();
         let primary_pool = store_builder.primary_pool();
 
+        // Remove results for old blocks from the query cache that we share
+        // with other query nodes
+        if let Some(cache) = shared_query_cache() {
+            graph::spawn(invalidate_on_chain_head(
+                logger.clone(),
+                cache,
+                env_vars.graphql.query_cache_blocks as BlockNumber,
+                chain_head_update_listener.head_updates(),
+            ));
+        }
+
         // To support the ethereum block ingestor, ethereum networks are referenced both by the
         // `blockchain_map` and `ethereum_chains`. Future chains should be referred to only in
         // `blockchain_map`.
@@ -517,6 +558,11 @@ async fn main() {
         ));
         let mut graphql_server =
             GraphQLQueryServer::new(&logger_factory, graphql_runner.clone(), node_id.clone());
+        if let Some(query_limits) = &config.query_limits {
+            let query_limiter = QueryLimiter::new(query_limits.to_config(), &metrics_registry)
+                .expect("failed to set up query limits");
+            graphql_server = graphql_server.with_query_limiter(Arc::new(query_limiter));
+        }
         let subscription_server =
             GraphQLSubscriptionServer::new(&logger, graphql_runner.clone(), network_store.clone());
 
@@ -573,6 +619,7 @@ async fn main() {
             link_resolver.clone(),
             ipfs_service,
             arweave_service,
+            http_service,
             static_filters,
         );
 
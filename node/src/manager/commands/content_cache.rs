@@ -0,0 +1,37 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use graph::{
+    anyhow::Context,
+    components::link_resolver::{CarArchive, ContentCache, ContentKind},
+    env::ENV_VARS,
+    prelude::anyhow::Error,
+    slog::Logger,
+};
+
+pub async fn seed(logger: &Logger, dir: &str, cars: Vec<String>) -> Result<(), Error> {
+    let cache = ContentCache::open(logger, Path::new(dir), ENV_VARS.mappings.content_cache_size)?;
+    let cache = Arc::new(cache);
+
+    for car in cars {
+        let archive = CarArchive::read(Path::new(&car))
+            .with_context(|| format!("failed to read CAR file {}", car))?;
+        let files = archive
+            .files()
+            .with_context(|| format!("failed to read the files in {}", car))?;
+
+        let (mut added, mut skipped) = (0, 0);
+        for (path, data) in files {
+            if cache.insert(ContentKind::Ipfs, &path, data).await {
+                added += 1;
+            } else {
+                skipped += 1;
+            }
+        }
+        println!("{}: added {} files, skipped {} files", car, added, skipped);
+    }
+
+    let (files, size) = cache.usage();
+    println!("content cache holds {} files with {} bytes", files, size);
+    Ok(())
+}
@@ -0,0 +1,42 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use graph::prelude::{anyhow, BlockNumber};
+use graph_store_postgres::{connection_pool::ConnectionPool, SubgraphStore};
+
+use crate::manager::deployment::DeploymentSearch;
+
+pub fn run(
+    store: Arc<SubgraphStore>,
+    primary_pool: ConnectionPool,
+    search: DeploymentSearch,
+    directory: String,
+    history: bool,
+    block: Option<BlockNumber>,
+) -> Result<(), anyhow::Error> {
+    let locator = search.locate_unique(&primary_pool)?;
+
+    let dir = PathBuf::from(directory);
+    fs::create_dir_all(&dir)?;
+
+    match (history, block) {
+        (true, _) => println!("Dumping full history of {locator} into {}", dir.display()),
+        (false, Some(block)) => {
+            println!("Dumping {locator} at block {block} into {}", dir.display())
+        }
+        (false, None) => println!("Dumping {locator} at its head into {}", dir.display()),
+    }
+    let metadata = store.dump(&locator, &dir, history, block)?;
+
+    println!(
+        "{:<48} {:>12}",
+        format!("table (head: #{})", metadata.head_number),
+        "rows"
+    );
+    println!("{:-<61}", "");
+    for table in &metadata.tables {
+        println!("{:<48} {:>12}", table.table, table.rows);
+    }
+    Ok(())
+}
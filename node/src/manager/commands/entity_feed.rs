@@ -0,0 +1,27 @@
+use std::sync::Arc;
+
+use graph::prelude::anyhow;
+use graph_store_postgres::{connection_pool::ConnectionPool, Store};
+
+use crate::manager::deployment::DeploymentSearch;
+
+pub async fn run(
+    store: Arc<Store>,
+    primary_pool: ConnectionPool,
+    search: DeploymentSearch,
+    clear: bool,
+) -> Result<(), anyhow::Error> {
+    let locator = search.locate_unique(&primary_pool)?;
+
+    store
+        .subgraph_store()
+        .set_entity_feed(&locator, !clear)
+        .await?;
+    if clear {
+        println!("{}: stopped recording the entity feed", locator);
+    } else {
+        println!("{}: recording the entity feed", locator);
+    }
+    println!("Index nodes notice the change within GRAPH_QUERY_STATS_REFRESH_INTERVAL seconds");
+    Ok(())
+}
@@ -5,7 +5,7 @@ use graph::{
     prelude::{anyhow, StoreError},
 };
 use graph_store_postgres::{
-    command_support::index::{CreateIndex, Method},
+    command_support::index::{CreateIndex, IndexAdvice, Method},
     connection_pool::ConnectionPool,
     SubgraphStore,
 };
@@ -201,3 +201,100 @@ pub async fn drop(
     println!("Dropped index {index_name}");
     Ok(())
 }
+
+/// Print the indexes that the recorded query workload suggests for the
+/// deployment, and the indexes that were never used. With `create`, also
+/// create the proposed indexes
+pub async fn advise(
+    store: Arc<SubgraphStore>,
+    pool: ConnectionPool,
+    search: DeploymentSearch,
+    create: bool,
+) -> Result<(), anyhow::Error> {
+    let deployment_locator = search.locate_unique(&pool)?;
+    let IndexAdvice {
+        proposals,
+        unused,
+        replicas,
+    } = store.advise_indexes(&deployment_locator).await?;
+
+    let mut term = Terminal::new();
+
+    term.bold()?;
+    writeln!(
+        term,
+        "{:^76}",
+        format!("Index advice for sgd{}", deployment_locator.id)
+    )?;
+    term.reset()?;
+    writeln!(term, "{: ^12} IPFS hash: {}", "", deployment_locator.hash)?;
+    writeln!(term, "{:-^76}", "")?;
+
+    if proposals.is_empty() {
+        writeln!(term, "No indexes to propose")?;
+    }
+    for proposal in &proposals {
+        term.green()?;
+        term.bold()?;
+        write!(term, "{}", proposal.table)?;
+        term.reset()?;
+        writeln!(
+            term,
+            ": {} of {} queries slow, {}ms in total",
+            proposal.slow_queries, proposal.queries, proposal.total_ms
+        )?;
+        term.blue()?;
+        writeln!(term, "  {};", proposal.index.to_sql(true, true)?)?;
+        term.reset()?;
+    }
+
+    writeln!(term, "{:-^76}", "")?;
+    if unused.is_empty() {
+        writeln!(term, "No unused indexes")?;
+    } else {
+        writeln!(
+            term,
+            "Unused indexes that can be removed with `graphman index drop`:"
+        )?;
+    }
+    for index in &unused {
+        writeln!(
+            term,
+            "  {} on {} ({:.1} MB)",
+            index.name,
+            index.table,
+            index.size as f64 / 1_000_000.0
+        )?;
+    }
+    if replicas > 0 && !unused.is_empty() {
+        writeln!(
+            term,
+            "warning: index usage was added up across the main server and {replicas} read \
+             replica(s); each server resets its statistics separately, so an index may \
+             have been used on a server since it was last reset"
+        )?;
+    }
+
+    if create && !proposals.is_empty() {
+        writeln!(term, "{:-^76}", "")?;
+        for proposal in proposals {
+            let name = match &proposal.index {
+                CreateIndex::Parsed { name, .. } => name.clone(),
+                CreateIndex::Unknown { defn } => defn.clone(),
+            };
+            writeln!(term, "Creating index {name}. Please wait.")?;
+            match store
+                .create_index(&deployment_locator, proposal.index)
+                .await
+            {
+                Ok(()) => writeln!(term, "Index creation completed.")?,
+                Err(StoreError::Canceled) => {
+                    eprintln!("Index creation attempt failed. Please retry.");
+                    ::std::process::exit(1);
+                }
+                Err(other) => return Err(anyhow::anyhow!(other)),
+            }
+        }
+    }
+    Ok(())
+}
@@ -2,19 +2,27 @@ pub mod assign;
 pub mod chain;
 pub mod check_blocks;
 pub mod config;
+pub mod content_cache;
 pub mod copy;
 pub mod create;
 pub mod database;
 pub mod deploy;
 pub mod drop;
+pub mod dump;
+pub mod entity_feed;
 pub mod index;
 pub mod info;
 pub mod listen;
+pub mod persisted_query;
+pub mod poi;
 pub mod prune;
 pub mod query;
 pub mod remove;
+pub mod replay;
+pub mod restore;
 pub mod rewind;
 pub mod run;
+pub mod sql;
 pub mod stats;
 pub mod txn_speed;
 pub mod unused_deployments;
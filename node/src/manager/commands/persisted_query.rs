@@ -0,0 +1,97 @@
+use std::fs;
+
+use graph::prelude::anyhow::{anyhow, Context, Error};
+use graph_store_postgres::{command_support::catalog, connection_pool::ConnectionPool};
+
+use crate::manager::deployment::DeploymentSearch;
+
+pub fn add(
+    primary: ConnectionPool,
+    search: &DeploymentSearch,
+    files: Vec<String>,
+) -> Result<(), Error> {
+    let locator = search.locate_unique(&primary)?;
+
+    // Check all queries before registering any of them
+    let mut queries = Vec::new();
+    for file in files {
+        // Register the exact contents of the file since clients send the
+        // hash of exactly the text they would otherwise send
+        let query =
+            fs::read_to_string(&file).with_context(|| format!("failed to read {}", file))?;
+        graphql_parser::parse_query::<&str>(&query)
+            .map_err(|e| anyhow!("{} is not a valid GraphQL query: {}", file, e))?;
+        queries.push((file, query));
+    }
+
+    let conn = catalog::Connection::new(primary.get()?);
+    for (file, query) in queries {
+        let hash = conn.add_persisted_query(&locator.hash, &query)?;
+        println!("{}: {}", file, hash);
+    }
+    Ok(())
+}
+
+pub fn remove(primary: ConnectionPool, search: &DeploymentSearch, hash: &str) -> Result<(), Error> {
+    let locator = search.locate_unique(&primary)?;
+
+    let conn = catalog::Connection::new(primary.get()?);
+    if conn.remove_persisted_query(&locator.hash, hash)? {
+        println!("removed persisted query {} from {}", hash, locator);
+    } else {
+        println!("{} has no persisted query {}", locator, hash);
+    }
+    Ok(())
+}
+
+pub fn list(primary: ConnectionPool, search: &DeploymentSearch, show: bool) -> Result<(), Error> {
+    let locator = search.locate_unique(&primary)?;
+
+    let conn = catalog::Connection::new(primary.get()?);
+    let persisted = conn.persisted_queries(&locator.hash)?;
+
+    let mut queries: Vec<_> = persisted.queries().collect();
+    queries.sort();
+    for (hash, query) in queries {
+        if show {
+            println!("{}\n{}\n", hash, query);
+        } else {
+            println!("{}", hash);
+        }
+    }
+
+    let mode = if persisted.allowlist_only {
+        "only persisted queries"
+    } else {
+        "any query"
+    };
+    println!(
+        "{} has {} persisted queries and executes {}",
+        locator,
+        persisted.len(),
+        mode
+    );
+    Ok(())
+}
+
+pub fn allowlist(
+    primary: ConnectionPool,
+    search: &DeploymentSearch,
+    disable: bool,
+) -> Result<(), Error> {
+    let locator = search.locate_unique(&primary)?;
+
+    let conn = catalog::Connection::new(primary.get()?);
+    conn.set_allowlist_only(&locator.hash, !disable)?;
+    if disable {
+        println!("{} now executes any query", locator);
+    } else {
+        let persisted = conn.persisted_queries(&locator.hash)?;
+        println!(
+            "{} now only executes its {} persisted queries",
+            locator,
+            persisted.len()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,383 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::sync::Arc;
+
+use graph::blockchain::BlockHash;
+use graph::components::store::{BlockPtrForNumber, StatusStore};
+use graph::components::subgraph::bisect::{
+    self, parse_poi, EntityChanges, PoiDigests, PoiList, PoiSource, StorePois,
+};
+use graph::data::subgraph::status;
+use graph::prelude::{
+    anyhow::{self, anyhow, bail, Context as _},
+    async_trait, hex, reqwest,
+    serde_json::{self, json, Value},
+    BlockNumber, BlockPtr, DeploymentHash,
+};
+use graph_store_postgres::{connection_pool::ConnectionPool, Store};
+
+use crate::manager::deployment::DeploymentSearch;
+use crate::manager::display::print_entity_differences;
+
+/// Index nodes answer at most this many requests for public proofs of
+/// indexing at once
+const MAX_POI_REQUESTS: usize = 10;
+
+/// Block hashes that are not in the chain store can not be looked up from
+/// `graphman`
+struct NoBlockPtrs;
+
+#[async_trait]
+impl BlockPtrForNumber for NoBlockPtrs {
+    async fn block_ptr_for_number(
+        &self,
+        _network: String,
+        _number: BlockNumber,
+    ) -> Result<Option<BlockPtr>, anyhow::Error> {
+        Ok(None)
+    }
+}
+
+fn parse_number(value: &Value) -> Option<BlockNumber> {
+    match value {
+        Value::Number(number) => number.as_i64().map(|number| number as BlockNumber),
+        Value::String(number) => number.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Parse one entry of the result of a `publicProofsOfIndexing` query
+fn parse_public_poi(value: &Value) -> anyhow::Result<Option<(BlockPtr, [u8; 32])>> {
+    let number = parse_number(&value["block"]["number"])
+        .ok_or_else(|| anyhow!("invalid block number in {}", value))?;
+    let poi = match value["proofOfIndexing"].as_str() {
+        Some(poi) => parse_poi(poi)?,
+        None => return Ok(None),
+    };
+    let hash = match value["block"]["hash"].as_str() {
+        Some(hash) => hash.parse::<BlockHash>()?,
+        None => return Ok(None),
+    };
+    Ok(Some((BlockPtr::new(hash, number), poi)))
+}
+
+/// The proofs of indexing that the index node of another indexer reports
+struct IndexNodePois {
+    client: reqwest::Client,
+    url: String,
+    deployment: DeploymentHash,
+}
+
+impl IndexNodePois {
+    async fn query(&self, query: &str, variables: Value) -> anyhow::Result<Value> {
+        let mut response: Value = self
+            .client
+            .post(&self.url)
+            .json(&json!({ "query": query, "variables": variables }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if let Some(errors) = response.get("errors") {
+            bail!("the index node at {} responded with {}", self.url, errors);
+        }
+        Ok(response["data"].take())
+    }
+}
+
+#[async_trait]
+impl PoiSource for IndexNodePois {
+    async fn public_pois(
+        &self,
+        blocks: &[(BlockNumber, Option<BlockHash>)],
+    ) -> anyhow::Result<Vec<Option<(BlockPtr, [u8; 32])>>> {
+        const QUERY: &str = r#"
+            query pois($requests: [PublicProofOfIndexingRequest!]!) {
+                publicProofsOfIndexing(requests: $requests) {
+                    block { number hash }
+                    proofOfIndexing
+                }
+            }"#;
+
+        let mut pois = Vec::with_capacity(blocks.len());
+        for chunk in blocks.chunks(MAX_POI_REQUESTS) {
+            let requests: Vec<_> = chunk
+                .iter()
+                .map(|(number, _)| {
+                    json!({ "deployment": self.deployment, "blockNumber": number.to_string() })
+                })
+                .collect();
+            let data = self.query(QUERY, json!({ "requests": requests })).await?;
+            let results = data["publicProofsOfIndexing"]
+                .as_array()
+                .ok_or_else(|| anyhow!("unexpected response {}", data))?;
+            for result in results {
+                pois.push(parse_public_poi(result)?);
+            }
+        }
+        Ok(pois)
+    }
+
+    async fn digests(&self, block: BlockNumber) -> anyhow::Result<Option<PoiDigests>> {
+        const QUERY: &str = r#"
+            query digests($deployment: String!, $block: Int!) {
+                proofOfIndexingDigests(subgraph: $deployment, blockNumber: $block) {
+                    causalityRegion
+                    digest
+                }
+            }"#;
+
+        let data = self
+            .query(
+                QUERY,
+                json!({ "deployment": self.deployment, "block": block }),
+            )
+            .await?;
+        let digests = match data["proofOfIndexingDigests"].as_array() {
+            Some(digests) => digests,
+            None => return Ok(None),
+        };
+        digests
+            .iter()
+            .map(|digest| {
+                let region = digest["causalityRegion"]
+                    .as_str()
+                    .ok_or_else(|| anyhow!("invalid digest {}", digest))?;
+                let bytes = digest["digest"]
+                    .as_str()
+                    .ok_or_else(|| anyhow!("invalid digest {}", digest))?;
+                let bytes = hex::decode(bytes.trim_start_matches("0x"))?;
+                Ok((region.to_string(), bytes))
+            })
+            .collect::<anyhow::Result<_>>()
+            .map(Some)
+    }
+
+    async fn entity_changes(&self, block: BlockNumber) -> anyhow::Result<Option<EntityChanges>> {
+        const QUERY: &str = r#"
+            query changes($deployment: String!, $block: Int!) {
+                entityChangesInBlock(subgraphId: $deployment, blockNumber: $block) {
+                    updates { type entities }
+                    deletions { type entities }
+                }
+            }"#;
+
+        let mut data = self
+            .query(
+                QUERY,
+                json!({ "deployment": self.deployment, "block": block }),
+            )
+            .await?;
+        let changes = data["entityChangesInBlock"].take();
+
+        let mut entity_changes = EntityChanges::new();
+        for updates in changes["updates"].as_array().into_iter().flatten() {
+            let entity_type = updates["type"].as_str().unwrap_or_default();
+            for entity in updates["entities"].as_array().into_iter().flatten() {
+                let id = entity["id"].as_str().unwrap_or_default();
+                entity_changes.insert(
+                    (entity_type.to_string(), id.to_string()),
+                    Some(entity.clone()),
+                );
+            }
+        }
+        for deletions in changes["deletions"].as_array().into_iter().flatten() {
+            let entity_type = deletions["type"].as_str().unwrap_or_default();
+            for id in deletions["entities"].as_array().into_iter().flatten() {
+                let id = id.as_str().unwrap_or_default();
+                entity_changes.insert((entity_type.to_string(), id.to_string()), None);
+            }
+        }
+        Ok(Some(entity_changes))
+    }
+}
+
+/// Load proofs of indexing from a file that contains the result of a
+/// `publicProofsOfIndexing` query, either the complete response or just
+/// the list of results
+fn load_pois(path: &str, deployment: &DeploymentHash) -> anyhow::Result<PoiList> {
+    let text = fs::read_to_string(path).with_context(|| format!("can not read {}", path))?;
+    let value: Value = serde_json::from_str(&text)?;
+    let results = match &value {
+        Value::Array(results) => results,
+        _ => value["data"]["publicProofsOfIndexing"]
+            .as_array()
+            .ok_or_else(|| anyhow!("{} does not contain proofs of indexing", path))?,
+    };
+    let mut pois = Vec::new();
+    for result in results {
+        let matches = result["deployment"]
+            .as_str()
+            .map_or(true, |hash| hash == deployment.as_str());
+        if matches {
+            pois.extend(parse_public_poi(result)?);
+        }
+    }
+    Ok(PoiList::new(pois))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+fn print_digests(local: &PoiDigests, reference: Option<&PoiDigests>) {
+    println!("{:<40} {:<68} status", "causality region", "local digest");
+    println!("{:-<120}", "");
+    let regions: BTreeSet<_> = local
+        .keys()
+        .chain(reference.into_iter().flat_map(|digests| digests.keys()))
+        .collect();
+    for region in regions {
+        let ours = local.get(region).map(|digest| to_hex(digest));
+        let status = match reference.map(|digests| digests.get(region)) {
+            None => "",
+            Some(theirs) if theirs.map(|digest| to_hex(digest)) == ours => "same",
+            Some(None) => "only local",
+            Some(Some(_)) if ours.is_none() => "only reference",
+            Some(Some(_)) => "differs",
+        };
+        println!(
+            "{:<40} {:<68} {}",
+            region,
+            ours.as_deref().unwrap_or("-"),
+            status
+        );
+        if status == "differs" || status == "only reference" {
+            let theirs = reference.and_then(|digests| digests.get(region)).unwrap();
+            println!("{:<40} {:<68} reference", "", to_hex(theirs));
+        }
+    }
+}
+
+fn print_entity_changes(local: &EntityChanges, reference: Option<&EntityChanges>) {
+    let reference = match reference {
+        Some(reference) => reference,
+        None => {
+            for ((entity_type, id), data) in local {
+                match data {
+                    Some(data) => println!("  set {}[{}]: {}", entity_type, id, data),
+                    None => println!("  remove {}[{}]", entity_type, id),
+                }
+            }
+            return;
+        }
+    };
+
+    let (same, _) = print_entity_differences(local, reference, ("local", "reference"), || ());
+    println!("  {} changes are the same", same);
+}
+
+pub async fn bisect(
+    store: Arc<Store>,
+    primary_pool: ConnectionPool,
+    search: DeploymentSearch,
+    reference: String,
+    start: Option<BlockNumber>,
+    end: Option<BlockNumber>,
+) -> Result<(), anyhow::Error> {
+    let locator = search.locate_unique(&primary_pool)?;
+    let deployment = locator.hash.clone();
+
+    let info = store
+        .status(status::Filter::DeploymentIds(vec![locator.id]))?
+        .pop()
+        .ok_or_else(|| anyhow!("deployment {} not found", locator))?;
+    let chain = info
+        .chains
+        .first()
+        .ok_or_else(|| anyhow!("deployment {} does not index any chain", locator))?;
+    let start = start.unwrap_or(chain.earliest_block_number);
+    let end = match (end, &chain.latest_block) {
+        (Some(end), _) => end,
+        (None, Some(latest)) => latest.number(),
+        (None, None) => bail!("deployment {} has not processed any blocks yet", locator),
+    };
+
+    let local = StorePois::new(store.clone(), deployment.clone(), &NoBlockPtrs);
+    println!(
+        "Comparing proofs of indexing for {} between blocks {} and {} with {}",
+        locator, start, end, reference
+    );
+    let remote = reference.starts_with("http://") || reference.starts_with("https://");
+    let reference: Box<dyn PoiSource> = if remote {
+        Box::new(IndexNodePois {
+            client: reqwest::Client::new(),
+            url: reference,
+            deployment: deployment.clone(),
+        })
+    } else {
+        Box::new(load_pois(&reference, &deployment)?)
+    };
+
+    let divergence = match bisect::bisect(&local, reference.as_ref(), start, end).await? {
+        Some(divergence) => divergence,
+        None => {
+            println!("The proofs of indexing agree at block {}", end);
+            return Ok(());
+        }
+    };
+
+    let block = divergence.first_diverging.number;
+    println!(
+        "The proofs of indexing first differ at block {} ({})",
+        block,
+        divergence.first_diverging.hash_hex()
+    );
+    match divergence.last_agreeing {
+        Some(agreeing) if agreeing < block - 1 => println!(
+            "They agree at block {}; the reference has no proofs of indexing in between",
+            agreeing
+        ),
+        Some(_) => {}
+        None => println!("They already differ at the first block that was compared"),
+    }
+    println!("  local:     {}", to_hex(&divergence.local));
+    println!("  reference: {}", to_hex(&divergence.reference));
+
+    println!();
+    println!("Digests at block {}", block);
+    let theirs = reference.digests(block).await.unwrap_or_else(|e| {
+        println!("  can not get the digests of the reference: {:#}", e);
+        None
+    });
+    match local.digests(block).await? {
+        Some(ours) => print_digests(&ours, theirs.as_ref()),
+        None => println!("  there are no local digests for block {}", block),
+    }
+
+    println!();
+    println!("Entity changes in block {}", block);
+    let theirs = reference.entity_changes(block).await.unwrap_or_else(|e| {
+        println!("  can not get the entity changes of the reference: {:#}", e);
+        None
+    });
+    if let Some(ours) = local.entity_changes(block).await? {
+        print_entity_changes(&ours, theirs.as_ref());
+    }
+    Ok(())
+}
+
+pub async fn history(
+    store: Arc<Store>,
+    primary_pool: ConnectionPool,
+    search: DeploymentSearch,
+    clear: bool,
+) -> Result<(), anyhow::Error> {
+    let locator = search.locate_unique(&primary_pool)?;
+
+    let blocks = store
+        .subgraph_store()
+        .set_poi_history(&locator, !clear)
+        .await?;
+    if clear {
+        println!("{}: stopped recording the PoI history", locator);
+    } else {
+        println!(
+            "{}: recording the PoI history; copied digests for {} blocks",
+            locator, blocks
+        );
+    }
+    println!("Index nodes notice the change within GRAPH_QUERY_STATS_REFRESH_INTERVAL seconds");
+    Ok(())
+}
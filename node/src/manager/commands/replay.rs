@@ -0,0 +1,402 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use graph::blockchain::BlockchainKind;
+use graph::cheap_clone::CheapClone;
+use graph::components::store::{
+    ChainStore as _, DeploymentCursorTracker as _, DeploymentId, DeploymentLocator, EnsLookup,
+    StatusStore as _, SubgraphFork, SubgraphStore as SubgraphStoreTrait, WritableStore,
+};
+use graph::components::subgraph::bisect;
+use graph::data::subgraph::{schema::DeploymentCreate, status, DeploymentFeatures};
+use graph::prelude::{
+    anyhow::{self, anyhow, bail},
+    async_trait, serde_yaml, tokio, ApiVersion, BlockNumber, BlockPtr, DeploymentHash,
+    EntityOperation, LinkResolver as _, Logger, MetricsRegistry, NodeId, StoreError, SubgraphName,
+    SubgraphVersionSwitchingMode,
+};
+use graph::schema::{ApiSchema, InputSchema};
+use graph_chain_ethereum as ethereum;
+use graph_core::SubgraphTriggerProcessor;
+use graph_store_postgres::{ReplayStore, SubgraphStore};
+
+use crate::config::Config;
+use crate::manager::commands::run::Indexer;
+use crate::manager::deployment::DeploymentSearch;
+use crate::manager::display::print_entity_differences;
+use crate::store_builder::StoreBuilder;
+
+/// A subgraph store that hands out a `ReplayStore` instead of the real
+/// `WritableStore` for the deployment, and that ignores all attempts to
+/// change the deployment's metadata
+struct ReplaySubgraphStore {
+    store: Arc<SubgraphStore>,
+    deployment: DeploymentLocator,
+    start: BlockPtr,
+    replay: Mutex<Option<Arc<ReplayStore>>>,
+}
+
+impl ReplaySubgraphStore {
+    fn new(store: Arc<SubgraphStore>, deployment: DeploymentLocator, start: BlockPtr) -> Self {
+        Self {
+            store,
+            deployment,
+            start,
+            replay: Mutex::new(None),
+        }
+    }
+
+    /// The `ReplayStore` that the runner uses, once it has asked for it
+    fn replay(&self) -> Option<Arc<ReplayStore>> {
+        self.replay.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl SubgraphStoreTrait for ReplaySubgraphStore {
+    fn ens_lookup(&self) -> Arc<dyn EnsLookup> {
+        self.store.ens_lookup()
+    }
+
+    fn is_deployed(&self, id: &DeploymentHash) -> Result<bool, StoreError> {
+        self.store.is_deployed(id)
+    }
+
+    async fn subgraph_features(
+        &self,
+        deployment: &DeploymentHash,
+    ) -> Result<Option<DeploymentFeatures>, StoreError> {
+        self.store.subgraph_features(deployment).await
+    }
+
+    fn create_subgraph_deployment(
+        &self,
+        _name: SubgraphName,
+        _schema: &InputSchema,
+        _deployment: DeploymentCreate,
+        _node_id: NodeId,
+        _network: String,
+        _mode: SubgraphVersionSwitchingMode,
+    ) -> Result<DeploymentLocator, StoreError> {
+        Err(StoreError::Unknown(anyhow!(
+            "can not create deployments while replaying"
+        )))
+    }
+
+    fn create_subgraph_features(&self, _features: DeploymentFeatures) -> Result<(), StoreError> {
+        Ok(())
+    }
+
+    fn create_subgraph(&self, _name: SubgraphName) -> Result<String, StoreError> {
+        Err(StoreError::Unknown(anyhow!(
+            "can not create subgraphs while replaying"
+        )))
+    }
+
+    fn remove_subgraph(&self, _name: SubgraphName) -> Result<(), StoreError> {
+        Err(StoreError::Unknown(anyhow!(
+            "can not remove subgraphs while replaying"
+        )))
+    }
+
+    fn reassign_subgraph(
+        &self,
+        _deployment: &DeploymentLocator,
+        _node_id: &NodeId,
+    ) -> Result<(), StoreError> {
+        Err(StoreError::Unknown(anyhow!(
+            "can not reassign deployments while replaying"
+        )))
+    }
+
+    fn pause_subgraph(&self, _deployment: &DeploymentLocator) -> Result<(), StoreError> {
+        Err(StoreError::Unknown(anyhow!(
+            "can not pause deployments while replaying"
+        )))
+    }
+
+    fn resume_subgraph(&self, _deployment: &DeploymentLocator) -> Result<(), StoreError> {
+        Err(StoreError::Unknown(anyhow!(
+            "can not resume deployments while replaying"
+        )))
+    }
+
+    fn assigned_node(&self, deployment: &DeploymentLocator) -> Result<Option<NodeId>, StoreError> {
+        self.store.assigned_node(deployment)
+    }
+
+    fn assignment_status(
+        &self,
+        deployment: &DeploymentLocator,
+    ) -> Result<Option<(NodeId, bool)>, StoreError> {
+        self.store.assignment_status(deployment)
+    }
+
+    fn assignments(&self, node: &NodeId) -> Result<Vec<DeploymentLocator>, StoreError> {
+        self.store.assignments(node)
+    }
+
+    fn active_assignments(&self, node: &NodeId) -> Result<Vec<DeploymentLocator>, StoreError> {
+        self.store.active_assignments(node)
+    }
+
+    fn subgraph_exists(&self, name: &SubgraphName) -> Result<bool, StoreError> {
+        self.store.subgraph_exists(name)
+    }
+
+    fn entity_changes_in_block(
+        &self,
+        subgraph_id: &DeploymentHash,
+        block_number: BlockNumber,
+    ) -> Result<Vec<EntityOperation>, StoreError> {
+        self.store
+            .entity_changes_in_block(subgraph_id, block_number)
+    }
+
+    fn input_schema(&self, subgraph_id: &DeploymentHash) -> Result<InputSchema, StoreError> {
+        self.store.input_schema(subgraph_id)
+    }
+
+    fn graft_pending(&self, id: &DeploymentHash) -> Result<bool, StoreError> {
+        self.store.graft_pending(id)
+    }
+
+    fn api_schema(
+        &self,
+        subgraph_id: &DeploymentHash,
+        api_version: &ApiVersion,
+    ) -> Result<Arc<ApiSchema>, StoreError> {
+        self.store.api_schema(subgraph_id, api_version)
+    }
+
+    fn debug_fork(
+        &self,
+        subgraph_id: &DeploymentHash,
+        logger: Logger,
+    ) -> Result<Option<Arc<dyn SubgraphFork>>, StoreError> {
+        self.store.debug_fork(subgraph_id, logger)
+    }
+
+    async fn writable(
+        self: Arc<Self>,
+        logger: Logger,
+        deployment: DeploymentId,
+        manifest_idx_and_name: Arc<Vec<(u32, String)>>,
+    ) -> Result<Arc<dyn WritableStore>, StoreError> {
+        if deployment != self.deployment.id {
+            return Err(StoreError::Unknown(anyhow!(
+                "can only replay deployment {}",
+                self.deployment
+            )));
+        }
+        let replay = self
+            .store
+            .replay_writable(
+                logger,
+                &self.deployment,
+                self.start.clone(),
+                manifest_idx_and_name,
+            )
+            .await?;
+        *self.replay.lock().unwrap() = Some(replay.cheap_clone());
+        Ok(replay)
+    }
+
+    async fn stop_subgraph(&self, _deployment: &DeploymentLocator) -> Result<(), StoreError> {
+        Ok(())
+    }
+
+    async fn least_block_ptr(&self, id: &DeploymentHash) -> Result<Option<BlockPtr>, StoreError> {
+        self.store.least_block_ptr(id).await
+    }
+
+    async fn is_healthy(&self, id: &DeploymentHash) -> Result<bool, StoreError> {
+        self.store.is_healthy(id).await
+    }
+
+    fn locators(&self, hash: &str) -> Result<Vec<DeploymentLocator>, StoreError> {
+        self.store.locators(hash)
+    }
+
+    fn active_locator(&self, hash: &str) -> Result<Option<DeploymentLocator>, StoreError> {
+        self.store.active_locator(hash)
+    }
+
+    async fn set_manifest_raw_yaml(
+        &self,
+        _hash: &DeploymentHash,
+        _raw_yaml: String,
+    ) -> Result<(), StoreError> {
+        Ok(())
+    }
+
+    fn instrument(&self, deployment: &DeploymentLocator) -> Result<bool, StoreError> {
+        self.store.instrument(deployment)
+    }
+}
+
+pub async fn run(
+    logger: Logger,
+    store_builder: StoreBuilder,
+    ipfs_url: Vec<String>,
+    arweave_url: String,
+    config: Config,
+    metrics_registry: Arc<MetricsRegistry>,
+    node_id: NodeId,
+    search: DeploymentSearch,
+    from: BlockNumber,
+    to: BlockNumber,
+) -> Result<(), anyhow::Error> {
+    if from >= to {
+        bail!("the block to replay from ({from}) must be before the last block to replay ({to})");
+    }
+
+    let primary_pool = store_builder.primary_pool();
+    let locator = search.locate_unique(&primary_pool)?;
+    let network = search
+        .lookup(&primary_pool)?
+        .into_iter()
+        .find(|deployment| deployment.locator() == locator)
+        .map(|deployment| deployment.chain)
+        .ok_or_else(|| anyhow!("deployment {} not found", locator))?;
+
+    let indexer = Indexer::new(
+        &logger,
+        store_builder,
+        &network,
+        ipfs_url,
+        &arweave_url,
+        &config,
+        metrics_registry,
+        &node_id,
+    )
+    .await?;
+    let subgraph_store = indexer.network_store.subgraph_store();
+
+    let info = indexer
+        .network_store
+        .status(status::Filter::DeploymentIds(vec![locator.id]))?
+        .pop()
+        .ok_or_else(|| anyhow!("deployment {} not found", locator))?;
+    let chain = info
+        .chains
+        .first()
+        .ok_or_else(|| anyhow!("deployment {} does not index any chain", locator))?;
+    if from < chain.earliest_block_number {
+        bail!(
+            "deployment {} only has entities since block {}, can not replay from block {}",
+            locator,
+            chain.earliest_block_number,
+            from
+        );
+    }
+    match &chain.latest_block {
+        Some(latest) if latest.number() >= to => { /* ok */ }
+        _ => bail!(
+            "deployment {} has not processed block {} yet and can not be compared to a replay",
+            locator,
+            to
+        ),
+    }
+
+    let start = match indexer
+        .chain_store
+        .block_hashes_by_block_number(from)?
+        .as_slice()
+    {
+        [hash] => BlockPtr::new(hash.clone(), from),
+        [] => bail!("block {} is not in the chain store", from),
+        _ => bail!(
+            "there are several blocks with number {} in the chain store",
+            from
+        ),
+    };
+
+    let file_bytes = indexer
+        .link_resolver
+        .cat(&logger, &locator.hash.to_ipfs_link())
+        .await?;
+    let manifest: serde_yaml::Mapping = serde_yaml::from_slice(&file_bytes)?;
+    if BlockchainKind::from_manifest(&manifest)? != BlockchainKind::Ethereum {
+        bail!("only subgraphs on Ethereum networks can be replayed");
+    }
+
+    let replay_store = Arc::new(ReplaySubgraphStore::new(
+        subgraph_store.cheap_clone(),
+        locator.clone(),
+        start,
+    ));
+    let instance_manager = indexer.instance_manager(replay_store.cheap_clone());
+    let runner = instance_manager
+        .build_subgraph_runner::<ethereum::Chain>(
+            indexer.logger_factory.subgraph_logger(&locator),
+            indexer.env_vars.cheap_clone(),
+            locator.clone(),
+            manifest,
+            Some(to),
+            Box::new(SubgraphTriggerProcessor {}),
+        )
+        .await?;
+    let replay = replay_store
+        .replay()
+        .ok_or_else(|| anyhow!("the runner for {} did not ask for a store", locator))?;
+
+    println!("Replaying blocks {} to {} of {}", from + 1, to, locator);
+    let (sender, mut receiver) = tokio::sync::oneshot::channel();
+    graph::spawn_thread(format!("replay-{}", locator), move || {
+        let res = graph::block_on(tokio::task::unconstrained(runner.run()));
+        let _ = sender.send(res);
+    });
+    // The runner keeps retrying after some errors; we stop as soon as the
+    // mappings fail since the replay can not make any progress after that
+    loop {
+        tokio::select! {
+            res = &mut receiver => {
+                res??;
+                break;
+            }
+            _ = tokio::time::sleep(Duration::from_millis(500)) => {
+                if replay.error().is_some() {
+                    break;
+                }
+            }
+        }
+    }
+
+    let last = replay.block_ptr().map(|ptr| ptr.number).unwrap_or(from);
+    let mut differing = 0;
+    for block in from + 1..=last {
+        // The store does not report changes to the proof of indexing
+        let replayed = replay
+            .entity_changes(block)
+            .into_iter()
+            .filter(|op| match op {
+                EntityOperation::Set { key, .. } | EntityOperation::Remove { key } => {
+                    !key.entity_type.is_poi()
+                }
+            })
+            .collect();
+        let replayed = bisect::entity_changes(replayed);
+        let stored =
+            bisect::entity_changes(subgraph_store.entity_changes_in_block(&locator.hash, block)?);
+        let header = || println!("block {}:", block);
+        let (_, changes) =
+            print_entity_differences(&replayed, &stored, ("replayed", "stored"), header);
+        if changes > 0 {
+            differing += 1;
+        }
+    }
+
+    println!(
+        "Replayed {} blocks, the changes in {} of them differ from the stored changes",
+        last - from,
+        differing
+    );
+    if let Some(error) = replay.error() {
+        println!("Replaying failed: {}", error.message);
+        if let Some(block_ptr) = error.block_ptr {
+            println!("  at block {}", block_ptr);
+        }
+    }
+    Ok(())
+}
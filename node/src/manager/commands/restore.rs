@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use graph::prelude::{anyhow, Logger, NodeId};
+use graph_store_postgres::{command_support::dump::DumpMetadata, Shard, SubgraphStore};
+
+pub fn run(
+    store: Arc<SubgraphStore>,
+    logger: Logger,
+    directory: String,
+    shard: String,
+    shards: Vec<String>,
+    node: String,
+) -> Result<(), anyhow::Error> {
+    if !shards.contains(&shard) {
+        anyhow::bail!(
+            "unknown shard {shard}, only shards {} are configured",
+            shards.join(", ")
+        )
+    }
+    let shard = Shard::new(shard)?;
+    let node = NodeId::new(node.clone()).map_err(|()| anyhow!("invalid node id `{}`", node))?;
+
+    let dir = PathBuf::from(directory);
+    let metadata = DumpMetadata::read(&dir)?;
+    println!(
+        "Restoring {} from {} into shard {shard}",
+        metadata.deployment,
+        dir.display()
+    );
+
+    let locator = store.restore(&logger, &dir, shard, node.clone())?;
+
+    println!(
+        "Restored {locator} with head at block #{} and assigned it to {node}",
+        metadata.head_number
+    );
+    Ok(())
+}
@@ -16,24 +16,27 @@ use graph::anyhow::{bail, format_err};
 use graph::blockchain::client::ChainClient;
 use graph::blockchain::{BlockchainKind, BlockchainMap};
 use graph::cheap_clone::CheapClone;
-use graph::components::link_resolver::{ArweaveClient, FileSizeLimit};
+use graph::components::link_resolver::{ArweaveClient, ContentCache, FileSizeLimit};
 use graph::components::store::{BlockStore as _, DeploymentLocator};
 use graph::components::subgraph::Settings;
 use graph::endpoint::EndpointMetrics;
 use graph::env::EnvVars;
 use graph::firehose::FirehoseEndpoints;
 use graph::prelude::{
-    anyhow, tokio, BlockNumber, DeploymentHash, IpfsResolver, LoggerFactory, NodeId,
-    SubgraphAssignmentProvider, SubgraphCountMetric, SubgraphName, SubgraphRegistrar,
+    anyhow, tokio, BlockNumber, DeploymentHash, IpfsResolver, LoggerFactory, MetricsRegistry,
+    NodeId, SubgraphAssignmentProvider, SubgraphCountMetric, SubgraphName, SubgraphRegistrar,
     SubgraphStore, SubgraphVersionSwitchingMode, ENV_VARS,
 };
 use graph::slog::{debug, info, Logger};
 use graph_chain_ethereum as ethereum;
-use graph_core::polling_monitor::{arweave_service, ipfs_service};
+use graph_core::polling_monitor::{
+    arweave_service, http_service, ipfs_service, ArweaveService, HttpService, IpfsService,
+};
 use graph_core::{
     SubgraphAssignmentProvider as IpfsSubgraphAssignmentProvider, SubgraphInstanceManager,
     SubgraphRegistrar as IpfsSubgraphRegistrar,
 };
+use graph_store_postgres::{ChainStore, Store};
 
 fn locate(store: &dyn SubgraphStore, hash: &str) -> Result<DeploymentLocator, anyhow::Error> {
     let mut locators = store.locators(hash)?;
@@ -44,6 +47,193 @@ fn locate(store: &dyn SubgraphStore, hash: &str) -> Result<DeploymentLocator, an
     }
 }
 
+/// The components that are needed to index subgraphs on one Ethereum
+/// network from `graphman`
+pub(crate) struct Indexer {
+    pub env_vars: Arc<EnvVars>,
+    pub logger_factory: LoggerFactory,
+    pub link_resolver: Arc<IpfsResolver>,
+    pub network_store: Arc<Store>,
+    pub chain_store: Arc<ChainStore>,
+    pub blockchain_map: Arc<BlockchainMap>,
+    pub sg_metrics: Arc<SubgraphCountMetric>,
+    metrics_registry: Arc<MetricsRegistry>,
+    ipfs_service: IpfsService,
+    arweave_service: ArweaveService,
+    http_service: HttpService,
+}
+
+impl Indexer {
+    pub async fn new(
+        logger: &Logger,
+        store_builder: StoreBuilder,
+        network_name: &str,
+        ipfs_url: Vec<String>,
+        arweave_url: &str,
+        config: &Config,
+        metrics_registry: Arc<MetricsRegistry>,
+        node_id: &NodeId,
+    ) -> Result<Self, anyhow::Error> {
+        let env_vars = Arc::new(EnvVars::from_env().unwrap());
+        let logger_factory = LoggerFactory::new(logger.clone(), None, metrics_registry.clone());
+
+        // FIXME: Hard-coded IPFS config, take it from config file instead?
+        let ipfs_clients: Vec<_> = create_ipfs_clients(logger, &ipfs_url);
+        let ipfs_client = ipfs_clients.first().cloned().expect("Missing IPFS client");
+        let content_cache = ContentCache::from_env(logger, &env_vars)?;
+        let ipfs_service = ipfs_service(
+            ipfs_client,
+            env_vars.mappings.max_ipfs_file_bytes as u64,
+            env_vars.mappings.ipfs_timeout,
+            env_vars.mappings.ipfs_request_limit,
+            content_cache.cheap_clone(),
+        );
+        let arweave_resolver = Arc::new(ArweaveClient::new(
+            logger.cheap_clone(),
+            arweave_url.parse().expect("invalid arweave url"),
+        ));
+        let arweave_service = arweave_service(
+            arweave_resolver.cheap_clone(),
+            env_vars.mappings.ipfs_timeout,
+            env_vars.mappings.ipfs_request_limit,
+            match env_vars.mappings.max_ipfs_file_bytes {
+                0 => FileSizeLimit::Unlimited,
+                n => FileSizeLimit::MaxBytes(n as u64),
+            },
+            content_cache.cheap_clone(),
+        );
+        let http_service = http_service(
+            config.http_sources.allowed_urls(),
+            config.http_sources.max_file_size,
+            config.http_sources.timeout,
+            config.http_sources.rate_limit,
+        );
+
+        let endpoint_metrics = Arc::new(EndpointMetrics::new(
+            logger.clone(),
+            &config.chains.providers(),
+            metrics_registry.cheap_clone(),
+        ));
+
+        // Convert the clients into a link resolver. Since we want to get past
+        // possible temporary DNS failures, make the resolver retry
+        let link_resolver = Arc::new(
+            IpfsResolver::new(ipfs_clients, env_vars.cheap_clone())
+                .with_content_cache(content_cache),
+        );
+
+        let eth_rpc_metrics = Arc::new(ProviderEthRpcMetrics::new(metrics_registry.clone()));
+        let eth_networks = create_ethereum_networks_for_chain(
+            logger,
+            eth_rpc_metrics,
+            config,
+            network_name,
+            endpoint_metrics.cheap_clone(),
+        )
+        .await
+        .expect("Failed to parse Ethereum networks");
+        let firehose_networks_by_kind =
+            create_firehose_networks(logger.clone(), config, endpoint_metrics);
+        let firehose_networks = firehose_networks_by_kind.get(&BlockchainKind::Ethereum);
+        let firehose_endpoints = firehose_networks
+            .and_then(|v| v.networks.get(network_name))
+            .map_or_else(FirehoseEndpoints::new, |v| v.clone());
+
+        let eth_adapters = match eth_networks.networks.get(network_name) {
+            Some(adapters) => adapters.clone(),
+            None => {
+                return Err(format_err!(
+                    "No ethereum adapters found, but required in this state of graphman run command"
+                ))
+            }
+        };
+
+        let eth_adapters2 = eth_adapters.clone();
+
+        let (_, ethereum_idents) = connect_ethereum_networks(logger, eth_networks).await?;
+
+        let chain_head_update_listener = store_builder.chain_head_update_listener();
+        let network_identifiers = ethereum_idents.into_iter().collect();
+        let network_store = store_builder.network_store(network_identifiers);
+
+        let chain_store = network_store
+            .block_store()
+            .chain_store(network_name)
+            .unwrap_or_else(|| panic!("No chain store for {}", network_name));
+
+        let client = Arc::new(ChainClient::new(firehose_endpoints, eth_adapters));
+
+        let chain_config = config.chains.chains.get(network_name).unwrap();
+        let chain = ethereum::Chain::new(
+            logger_factory.clone(),
+            network_name.to_string(),
+            node_id.clone(),
+            metrics_registry.clone(),
+            chain_store.cheap_clone(),
+            chain_store.cheap_clone(),
+            client.clone(),
+            chain_head_update_listener,
+            Arc::new(EthereumStreamBuilder {}),
+            Arc::new(EthereumBlockRefetcher {}),
+            Arc::new(EthereumAdapterSelector::new(
+                logger_factory.clone(),
+                client,
+                metrics_registry.clone(),
+                chain_store.cheap_clone(),
+            )),
+            Arc::new(EthereumRuntimeAdapter {
+                call_cache: chain_store.cheap_clone(),
+                eth_adapters: Arc::new(eth_adapters2),
+                chain_identifier: Arc::new(chain_store.chain_identifier.clone()),
+            }),
+            graph::env::ENV_VARS.reorg_threshold,
+            chain_config.polling_interval,
+            // We assume the tested chain is always ingestible for now
+            true,
+        );
+
+        let mut blockchain_map = BlockchainMap::new();
+        blockchain_map.insert(network_name.to_string(), Arc::new(chain));
+
+        let sg_metrics = Arc::new(SubgraphCountMetric::new(metrics_registry.clone()));
+
+        Ok(Self {
+            env_vars,
+            logger_factory,
+            link_resolver,
+            network_store,
+            chain_store,
+            blockchain_map: Arc::new(blockchain_map),
+            sg_metrics,
+            metrics_registry,
+            ipfs_service,
+            arweave_service,
+            http_service,
+        })
+    }
+
+    /// Create a `SubgraphInstanceManager` that uses `subgraph_store` for
+    /// the subgraphs it runs
+    pub fn instance_manager<S: SubgraphStore>(
+        &self,
+        subgraph_store: Arc<S>,
+    ) -> SubgraphInstanceManager<S> {
+        SubgraphInstanceManager::new(
+            &self.logger_factory,
+            self.env_vars.cheap_clone(),
+            subgraph_store,
+            self.blockchain_map.clone(),
+            self.sg_metrics.cheap_clone(),
+            self.metrics_registry.clone(),
+            self.link_resolver.cheap_clone(),
+            self.ipfs_service.clone(),
+            self.arweave_service.clone(),
+            self.http_service.clone(),
+            ENV_VARS.experimental_static_filters,
+        )
+    }
+}
+
 pub async fn run(
     logger: Logger,
     store_builder: StoreBuilder,
@@ -61,141 +251,23 @@ pub async fn run(
         subgraph, stop_block
     );
 
-    let env_vars = Arc::new(EnvVars::from_env().unwrap());
-    let metrics_registry = metrics_ctx.registry.clone();
-    let logger_factory = LoggerFactory::new(logger.clone(), None, metrics_ctx.registry.clone());
-
-    // FIXME: Hard-coded IPFS config, take it from config file instead?
-    let ipfs_clients: Vec<_> = create_ipfs_clients(&logger, &ipfs_url);
-    let ipfs_client = ipfs_clients.first().cloned().expect("Missing IPFS client");
-    let ipfs_service = ipfs_service(
-        ipfs_client,
-        env_vars.mappings.max_ipfs_file_bytes as u64,
-        env_vars.mappings.ipfs_timeout,
-        env_vars.mappings.ipfs_request_limit,
-    );
-    let arweave_resolver = Arc::new(ArweaveClient::new(
-        logger.cheap_clone(),
-        arweave_url.parse().expect("invalid arweave url"),
-    ));
-    let arweave_service = arweave_service(
-        arweave_resolver.cheap_clone(),
-        env_vars.mappings.ipfs_timeout,
-        env_vars.mappings.ipfs_request_limit,
-        match env_vars.mappings.max_ipfs_file_bytes {
-            0 => FileSizeLimit::Unlimited,
-            n => FileSizeLimit::MaxBytes(n as u64),
-        },
-    );
-
-    let endpoint_metrics = Arc::new(EndpointMetrics::new(
-        logger.clone(),
-        &config.chains.providers(),
-        metrics_registry.cheap_clone(),
-    ));
-
-    // Convert the clients into a link resolver. Since we want to get past
-    // possible temporary DNS failures, make the resolver retry
-    let link_resolver = Arc::new(IpfsResolver::new(ipfs_clients, env_vars.cheap_clone()));
-
-    let eth_rpc_metrics = Arc::new(ProviderEthRpcMetrics::new(metrics_registry.clone()));
-    let eth_networks = create_ethereum_networks_for_chain(
+    let indexer = Indexer::new(
         &logger,
-        eth_rpc_metrics,
-        &config,
+        store_builder,
         &network_name,
-        endpoint_metrics.cheap_clone(),
+        ipfs_url,
+        &arweave_url,
+        &config,
+        metrics_ctx.registry.clone(),
+        &node_id,
     )
-    .await
-    .expect("Failed to parse Ethereum networks");
-    let firehose_networks_by_kind =
-        create_firehose_networks(logger.clone(), &config, endpoint_metrics);
-    let firehose_networks = firehose_networks_by_kind.get(&BlockchainKind::Ethereum);
-    let firehose_endpoints = firehose_networks
-        .and_then(|v| v.networks.get(&network_name))
-        .map_or_else(FirehoseEndpoints::new, |v| v.clone());
-
-    let eth_adapters = match eth_networks.networks.get(&network_name) {
-        Some(adapters) => adapters.clone(),
-        None => {
-            return Err(format_err!(
-                "No ethereum adapters found, but required in this state of graphman run command"
-            ))
-        }
-    };
-
-    let eth_adapters2 = eth_adapters.clone();
-
-    let (_, ethereum_idents) = connect_ethereum_networks(&logger, eth_networks).await?;
-    // let (near_networks, near_idents) = connect_firehose_networks::<NearFirehoseHeaderOnlyBlock>(
-    //     &logger,
-    //     firehose_networks_by_kind
-    //         .remove(&BlockchainKind::Near)
-    //         .unwrap_or_else(|| FirehoseNetworks::new()),
-    // )
-    // .await;
-
-    let chain_head_update_listener = store_builder.chain_head_update_listener();
-    let network_identifiers = ethereum_idents.into_iter().collect();
-    let network_store = store_builder.network_store(network_identifiers);
-
-    let subgraph_store = network_store.subgraph_store();
-    let chain_store = network_store
-        .block_store()
-        .chain_store(network_name.as_ref())
-        .unwrap_or_else(|| panic!("No chain store for {}", &network_name));
-
-    let client = Arc::new(ChainClient::new(firehose_endpoints, eth_adapters));
-
-    let chain_config = config.chains.chains.get(&network_name).unwrap();
-    let chain = ethereum::Chain::new(
-        logger_factory.clone(),
-        network_name.clone(),
-        node_id.clone(),
-        metrics_registry.clone(),
-        chain_store.cheap_clone(),
-        chain_store.cheap_clone(),
-        client.clone(),
-        chain_head_update_listener,
-        Arc::new(EthereumStreamBuilder {}),
-        Arc::new(EthereumBlockRefetcher {}),
-        Arc::new(EthereumAdapterSelector::new(
-            logger_factory.clone(),
-            client,
-            metrics_registry.clone(),
-            chain_store.cheap_clone(),
-        )),
-        Arc::new(EthereumRuntimeAdapter {
-            call_cache: chain_store.cheap_clone(),
-            eth_adapters: Arc::new(eth_adapters2),
-            chain_identifier: Arc::new(chain_store.chain_identifier.clone()),
-        }),
-        graph::env::ENV_VARS.reorg_threshold,
-        chain_config.polling_interval,
-        // We assume the tested chain is always ingestible for now
-        true,
-    );
-
-    let mut blockchain_map = BlockchainMap::new();
-    blockchain_map.insert(network_name.clone(), Arc::new(chain));
-
-    let static_filters = ENV_VARS.experimental_static_filters;
-
-    let sg_metrics = Arc::new(SubgraphCountMetric::new(metrics_registry.clone()));
-
-    let blockchain_map = Arc::new(blockchain_map);
-    let subgraph_instance_manager = SubgraphInstanceManager::new(
-        &logger_factory,
-        env_vars.cheap_clone(),
-        subgraph_store.clone(),
-        blockchain_map.clone(),
-        sg_metrics.cheap_clone(),
-        metrics_registry.clone(),
-        link_resolver.cheap_clone(),
-        ipfs_service,
-        arweave_service,
-        static_filters,
-    );
+    .await?;
+    let logger_factory = indexer.logger_factory.clone();
+    let link_resolver = indexer.link_resolver.cheap_clone();
+    let subgraph_store = indexer.network_store.subgraph_store();
+    let blockchain_map = indexer.blockchain_map.clone();
+    let sg_metrics = indexer.sg_metrics.cheap_clone();
+    let subgraph_instance_manager = indexer.instance_manager(subgraph_store.clone());
 
     // Create IPFS-based subgraph provider
     let subgraph_provider = Arc::new(IpfsSubgraphAssignmentProvider::new(
@@ -0,0 +1,50 @@
+use std::fs::File;
+use std::io::Write;
+use std::sync::Arc;
+
+use graph::data::query::SqlQueryReq;
+use graph::log::escape_control_chars;
+use graph::{
+    data::query::QueryTarget,
+    prelude::{
+        anyhow::{self, anyhow},
+        serde_json, BlockNumber, DeploymentHash, GraphQlRunner as _, SubgraphName,
+    },
+};
+use graph_graphql::prelude::GraphQlRunner;
+use graph_store_postgres::Store;
+
+use crate::manager::PanicSubscriptionManager;
+
+pub async fn run(
+    runner: Arc<GraphQlRunner<Store, PanicSubscriptionManager>>,
+    target: String,
+    query: String,
+    block: Option<BlockNumber>,
+    output: Option<String>,
+) -> Result<(), anyhow::Error> {
+    let target = if target.starts_with("Qm") {
+        let id =
+            DeploymentHash::new(target).map_err(|id| anyhow!("illegal deployment id `{}`", id))?;
+        QueryTarget::Deployment(id, Default::default())
+    } else {
+        let name = SubgraphName::new(target.clone())
+            .map_err(|()| anyhow!("illegal subgraph name `{}`", target))?;
+        QueryTarget::Name(name, Default::default())
+    };
+
+    let req = SqlQueryReq { query, block };
+    let rows = runner.run_sql_query(req, target).await?;
+
+    // Escape control characters in the query output, as a precaution
+    // against injecting control characters in a terminal.
+    let json = escape_control_chars(serde_json::to_string_pretty(&rows)?);
+    match output {
+        Some(output) => {
+            let mut f = File::create(output)?;
+            writeln!(f, "{}", json)?;
+        }
+        None => println!("{}", json),
+    }
+    Ok(())
+}
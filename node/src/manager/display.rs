@@ -1,3 +1,8 @@
+use std::collections::BTreeSet;
+
+use graph::components::subgraph::bisect::EntityChanges;
+use graph::prelude::serde_json::Value;
+
 pub struct List {
     pub headers: Vec<String>,
     pub rows: Vec<Vec<String>>,
@@ -52,3 +57,43 @@ impl List {
         }
     }
 }
+
+/// Print how the changes to entities in `ours` differ from the ones in
+/// `theirs`, calling `header` before printing the first difference. The
+/// two sides are labeled with `names` in the output. Return how many
+/// changes are the same on both sides and how many differ
+pub fn print_entity_differences(
+    ours: &EntityChanges,
+    theirs: &EntityChanges,
+    names: (&str, &str),
+    header: impl FnOnce(),
+) -> (usize, usize) {
+    fn change(change: Option<&Option<Value>>) -> String {
+        match change {
+            Some(Some(data)) => data.to_string(),
+            Some(None) => "removed".to_string(),
+            None => "unchanged".to_string(),
+        }
+    }
+
+    let width = names.0.len().max(names.1.len()) + 1;
+    let keys: BTreeSet<_> = ours.keys().chain(theirs.keys()).collect();
+    let mut header = Some(header);
+    let (mut same, mut differing) = (0, 0);
+    for key in keys {
+        let (entity_type, id) = key;
+        let (ours, theirs) = (ours.get(key), theirs.get(key));
+        if ours == theirs {
+            same += 1;
+            continue;
+        }
+        differing += 1;
+        if let Some(header) = header.take() {
+            header();
+        }
+        println!("  {}[{}] differs", entity_type, id);
+        println!("    {:width$} {}", format!("{}:", names.0), change(ours));
+        println!("    {:width$} {}", format!("{}:", names.1), change(theirs));
+    }
+    (same, differing)
+}
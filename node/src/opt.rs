@@ -108,6 +108,15 @@ pub struct Opt {
         help = "HTTP addresses of IPFS nodes"
     )]
     pub ipfs: Vec<String>,
+    #[clap(
+        long,
+        value_name = "PATH",
+        env = "GRAPH_IPFS_LOCAL",
+        conflicts_with = "ipfs",
+        help = "CAR files or directories with CAR files and blocks from which IPFS files are \
+                served instead of from an IPFS node"
+    )]
+    pub ipfs_local: Vec<String>,
     #[clap(
         long,
         value_name = "{HOST:PORT|URL}",
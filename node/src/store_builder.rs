@@ -4,6 +4,7 @@ use std::{collections::HashMap, sync::Arc};
 
 use futures::future::join_all;
 use graph::blockchain::ChainIdentifier;
+use graph::components::sink::{DeploymentSink, KafkaSink, WebhookSink};
 use graph::prelude::{o, MetricsRegistry, NodeId};
 use graph::url::Url;
 use graph::{
@@ -150,6 +151,15 @@ impl StoreBuilder {
                 .map(|(name, pool, _, _)| (name.clone(), pool.clone())),
         );
 
+        let webhooks = config.webhooks.iter().map(|webhook| {
+            Arc::new(WebhookSink::new(logger, webhook.to_config())) as Arc<dyn DeploymentSink>
+        });
+        let kafka = config.kafka.iter().map(|kafka| {
+            Arc::new(KafkaSink::new(logger, kafka.to_config(), &registry))
+                as Arc<dyn DeploymentSink>
+        });
+        let sinks = webhooks.chain(kafka).collect();
+
         let store = Arc::new(SubgraphStore::new(
             logger,
             shards,
@@ -157,6 +167,7 @@ impl StoreBuilder {
             notification_sender,
             fork_base,
             registry,
+            sinks,
         ));
 
         (store, pools, coord)
@@ -5,7 +5,7 @@ use async_trait::async_trait;
 use futures::sync::mpsc::Sender;
 use futures03::channel::oneshot::channel;
 
-use graph::blockchain::{BlockTime, Blockchain, HostFn, RuntimeAdapter};
+use graph::blockchain::{BlockTime, Blockchain, DeclaredCalls, HostFn, RuntimeAdapter};
 use graph::components::store::{EnsLookup, SubgraphFork};
 use graph::components::subgraph::{MappingError, SharedProofOfIndexing};
 use graph::data_source::{
@@ -52,6 +52,7 @@ impl<C: Blockchain> RuntimeHostBuilder<C> {
     }
 }
 
+#[async_trait]
 impl<C: Blockchain> RuntimeHostBuilderTrait<C> for RuntimeHostBuilder<C>
 where
     <C as Blockchain>::MappingTrigger: ToAscPtr,
@@ -100,6 +101,24 @@ where
             self.ens_lookup.cheap_clone(),
         )
     }
+
+    async fn prepare_triggers(
+        &self,
+        logger: &Logger,
+        block_ptr: &BlockPtr,
+        triggers: &[(&DataSource<C>, &MappingTrigger<C>)],
+    ) -> Result<DeclaredCalls, MappingError> {
+        let triggers: Vec<_> = triggers
+            .iter()
+            .filter_map(|(ds, trigger)| match (ds, trigger) {
+                (DataSource::Onchain(ds), MappingTrigger::Onchain(trigger)) => Some((ds, trigger)),
+                _ => None,
+            })
+            .collect();
+        self.runtime_adapter
+            .prepare_triggers(logger, block_ptr, &triggers)
+            .await
+    }
 }
 
 pub struct RuntimeHost<C: Blockchain> {
@@ -108,6 +127,7 @@ pub struct RuntimeHost<C: Blockchain> {
     mapping_request_sender: Sender<WasmRequest<C>>,
     host_exports: Arc<HostExports>,
     metrics: Arc<HostMetrics>,
+    prepares_triggers: bool,
 }
 
 impl<C> RuntimeHost<C>
@@ -146,12 +166,17 @@ where
             .transpose()?
             .unwrap_or_default();
 
+        let prepares_triggers = data_source
+            .as_onchain()
+            .map_or(false, |ds| runtime_adapter.prepares_triggers(ds));
+
         Ok(RuntimeHost {
             host_fns: Arc::new(host_fns),
             data_source,
             mapping_request_sender,
             host_exports,
             metrics,
+            prepares_triggers,
         })
     }
 
@@ -373,6 +398,10 @@ impl<C: Blockchain> RuntimeHostTrait<C> for RuntimeHost<C> {
             DataSource::Offchain(ds) => ds.set_done_at(block),
         }
     }
+
+    fn prepares_triggers(&self) -> bool {
+        self.prepares_triggers
+    }
 }
 
 impl<C: Blockchain> PartialEq for RuntimeHost<C> {
@@ -27,6 +27,7 @@ use graph::runtime::AscPtr;
 use graph::runtime::{asc_new, gas::GasCounter, DeterministicHostError, HostExportError};
 
 use super::asc_get;
+use super::profiler::Profiler;
 use super::AscHeapCtx;
 
 pub(crate) struct WasmInstanceContext<'a> {
@@ -94,6 +95,9 @@ pub struct WasmInstanceData {
 
     pub(crate) experimental_features: ExperimentalFeatures,
 
+    // The profile of the handler that is running, if handlers are profiled.
+    pub(crate) profiler: Option<Profiler>,
+
     // This option is needed to break the cyclic dependency between, instance, store, and context.
     // during execution it should always be populated.
     asc_heap: Option<AscHeapCtx>,
@@ -114,6 +118,7 @@ impl WasmInstanceData {
             possible_reorg: false,
             deterministic_host_trap: false,
             experimental_features,
+            profiler: None,
         }
     }
 
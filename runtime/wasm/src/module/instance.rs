@@ -5,7 +5,7 @@ use anyhow::Error;
 use graph::slog::SendSyncRefUnwindSafeKV;
 
 use semver::Version;
-use wasmtime::{AsContextMut, Linker, Store, Trap};
+use wasmtime::{AsContextMut, Linker, Store, Trap, TypedFunc};
 
 use graph::blockchain::{Blockchain, HostFnCtx};
 use graph::data::store;
@@ -19,6 +19,7 @@ use graph::runtime::{
 };
 use graph::{components::subgraph::MappingError, runtime::AscPtr};
 
+use super::profiler::{DeploymentProfile, Profiler};
 use super::IntoWasmRet;
 use super::{IntoTrap, WasmInstanceContext};
 use crate::error::DeterminismLevel;
@@ -178,7 +179,7 @@ impl WasmInstance {
 
         // This `match` will return early if there was a non-deterministic trap.
         let deterministic_error: Option<Error> =
-            match func.call(self.store.as_context_mut(), arg.wasm_ptr()) {
+            match self.call_handler(handler, &func, arg.wasm_ptr()) {
                 Ok(()) => {
                     assert!(self.instance_ctx().as_ref().possible_reorg == false);
                     assert!(self.instance_ctx().as_ref().deterministic_host_trap == false);
@@ -256,6 +257,33 @@ impl WasmInstance {
         let gas = self.gas.get();
         Ok((self.take_ctx().take_state(), gas))
     }
+
+    /// Call the `handler` function `func`, and profile the call if mapping
+    /// handlers are profiled
+    fn call_handler(
+        &mut self,
+        handler: &str,
+        func: &TypedFunc<u32, ()>,
+        arg: u32,
+    ) -> Result<(), Error> {
+        let ctx = &self.store.data().ctx;
+        if let Some(profile) = DeploymentProfile::for_deployment(&ctx.host_exports.subgraph_id) {
+            let profiler = Profiler::new(
+                profile,
+                ctx.logger.cheap_clone(),
+                &ctx.host_exports.data_source.name,
+                handler,
+            );
+            self.store.data_mut().profiler = Some(profiler);
+        }
+
+        let result = func.call(self.store.as_context_mut(), arg);
+
+        if let Some(profiler) = self.store.data_mut().profiler.take() {
+            profiler.finish();
+        }
+        result
+    }
 }
 
 impl WasmInstance {
@@ -317,12 +345,13 @@ impl WasmInstance {
                             let host_metrics = caller.data().host_metrics.cheap_clone();
                             let _section = host_metrics.stopwatch.start_section($section);
 
-                            #[allow(unused_mut)]
                             let mut ctx = WasmInstanceContext::new(&mut caller);
+                            let profile = ctx.enter_host_fn(&gas);
                             let result = ctx.$rust_name(
                                 &gas,
                                 $($param.into()),*
                             );
+                            ctx.exit_host_fn(profile, $wasm_name, &gas);
                             match result {
                                 Ok(result) => Ok(result.into_wasm_ret()),
                                 Err(e) => {
@@ -369,14 +398,22 @@ impl WasmInstance {
                         let _section =
                             stopwatch.start_section(&format!("host_export_{}", name_for_metrics));
 
+                        let profile = WasmInstanceContext::new(&mut caller).enter_host_fn(&gas);
                         let ctx = HostFnCtx {
                             logger: caller.data().ctx.logger.cheap_clone(),
                             block_ptr: caller.data().ctx.block_ptr.cheap_clone(),
                             gas: gas.cheap_clone(),
                             metrics: host_metrics.cheap_clone(),
+                            declared_calls: caller.data().ctx.state.declared_calls.cheap_clone(),
                             heap: &mut WasmInstanceContext::new(&mut caller),
                         };
-                        let ret = (host_fn.func)(ctx, call_ptr).map_err(|e| match e {
+                        let ret = (host_fn.func)(ctx, call_ptr);
+                        WasmInstanceContext::new(&mut caller).exit_host_fn(
+                            profile,
+                            host_fn.name,
+                            &gas,
+                        );
+                        let ret = ret.map_err(|e| match e {
                             HostExportError::Deterministic(e) => {
                                 caller.data_mut().deterministic_host_trap = true;
                                 e
@@ -515,7 +552,7 @@ impl WasmInstance {
 
         // link the `gas` function
         // See also e3f03e62-40e4-4f8c-b4a1-d0375cca0b76
-        {
+        if ENV_VARS.mappings.profile_dir.is_none() {
             let gas = gas.cheap_clone();
             linker.func_wrap("gas", "gas", move |gas_used: u32| -> anyhow::Result<()> {
                 // Gas metering has a relevant execution cost cost, being called tens of thousands
@@ -530,6 +567,31 @@ impl WasmInstance {
 
                 Ok(())
             })?;
+        } else {
+            // When handlers are profiled, the gas function also samples the
+            // wasm stack, which requires access to the store
+            let gas = gas.cheap_clone();
+            linker.func_wrap(
+                "gas",
+                "gas",
+                move |mut caller: wasmtime::Caller<'_, WasmInstanceData>,
+                      gas_used: u32|
+                      -> anyhow::Result<()> {
+                    if let Err(e) =
+                        gas.consume_host_fn_with_metrics(gas_used.saturating_into(), "gas")
+                    {
+                        deterministic_host_trap.store(true, Ordering::SeqCst);
+                        return Err(e.into());
+                    }
+
+                    if let Some(mut profiler) = caller.data_mut().profiler.take() {
+                        profiler.gas(&caller, gas_used as u64);
+                        caller.data_mut().profiler = Some(profiler);
+                    }
+
+                    Ok(())
+                },
+            )?;
         }
 
         let instance = linker.instantiate(store.as_context_mut(), &valid_module.module)?;
@@ -0,0 +1,313 @@
+//! Profiling of mapping handlers.
+//!
+//! When `GRAPH_MAPPING_PROFILE_DIR` is set, every handler invocation is
+//! profiled. The wasm stack is sampled from the `gas` host function, which
+//! the gas metering injects into every basic block of a module: whenever
+//! more than `GRAPH_MAPPING_PROFILE_INTERVAL` has passed since the last
+//! sample, the time and the gas since the last sample are attributed to
+//! the current stack. Calls to host functions are not sampled but always
+//! recorded with the time and gas they took.
+//!
+//! Stacks start with the name of the data source and the handler, followed
+//! by the wasm functions, and, for time spent in host functions, the host
+//! function as `host:<name>`. The profile of each deployment is written
+//! in the folded stack format that tools like `inferno` and
+//! `flamegraph.pl` turn into flame graphs, once into `<deployment>.time.folded`
+//! with the time in microseconds and once into `<deployment>.gas.folded`
+//! with the gas
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use graph::prelude::{lazy_static, warn, DeploymentHash, Logger, ENV_VARS};
+use graph::runtime::gas::GasCounter;
+use wasmtime::{AsContext, WasmBacktrace};
+
+use super::WasmInstanceContext;
+
+/// How often the profile of a deployment is written to disk at most
+const WRITE_INTERVAL: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    static ref PROFILES: Mutex<HashMap<DeploymentHash, Arc<DeploymentProfile>>> =
+        Mutex::new(HashMap::new());
+}
+
+#[derive(Clone, Copy, Default)]
+struct Sample {
+    micros: u64,
+    gas: u64,
+}
+
+type Samples = HashMap<String, Sample>;
+
+fn add_samples(samples: &mut Samples, other: Samples) {
+    for (stack, sample) in other {
+        let entry = samples.entry(stack).or_default();
+        entry.micros += sample.micros;
+        entry.gas += sample.gas;
+    }
+}
+
+/// Turn `name` into a frame for the folded stack format, which uses `;`
+/// to separate frames and a space to separate the stack from the count
+fn frame(name: &str) -> String {
+    name.replace(';', ":").replace(' ', "_")
+}
+
+struct ProfileData {
+    samples: Samples,
+    last_write: Option<Instant>,
+}
+
+/// The profile of all handlers of a deployment since the node started
+pub(crate) struct DeploymentProfile {
+    time_path: PathBuf,
+    gas_path: PathBuf,
+    data: Mutex<ProfileData>,
+}
+
+impl DeploymentProfile {
+    /// The profile for `deployment`, or `None` if profiling is turned off
+    pub(crate) fn for_deployment(deployment: &DeploymentHash) -> Option<Arc<Self>> {
+        let dir = ENV_VARS.mappings.profile_dir.as_ref()?;
+        let mut profiles = PROFILES.lock().unwrap();
+        let profile = profiles.entry(deployment.clone()).or_insert_with(|| {
+            Arc::new(DeploymentProfile {
+                time_path: dir.join(format!("{}.time.folded", deployment)),
+                gas_path: dir.join(format!("{}.gas.folded", deployment)),
+                data: Mutex::new(ProfileData {
+                    samples: HashMap::new(),
+                    last_write: None,
+                }),
+            })
+        });
+        Some(profile.clone())
+    }
+
+    fn add(&self, logger: &Logger, samples: Samples) {
+        let mut data = self.data.lock().unwrap();
+        add_samples(&mut data.samples, samples);
+
+        if data
+            .last_write
+            .map(|last| last.elapsed() >= WRITE_INTERVAL)
+            .unwrap_or(true)
+        {
+            data.last_write = Some(Instant::now());
+            let res = Self::write(&self.time_path, &data.samples, |sample| sample.micros)
+                .and_then(|()| Self::write(&self.gas_path, &data.samples, |sample| sample.gas));
+            if let Err(e) = res {
+                warn!(logger, "Failed to write mapping profile";
+                    "path" => self.time_path.display().to_string(),
+                    "error" => e.to_string());
+            }
+        }
+    }
+
+    /// Write the `samples` to `path`, using `count` for the count of each
+    /// stack. We write to a temporary file first so that readers never see
+    /// a partially written profile
+    fn write(path: &Path, samples: &Samples, count: impl Fn(&Sample) -> u64) -> io::Result<()> {
+        let mut stacks: Vec<_> = samples
+            .iter()
+            .map(|(stack, sample)| (stack, count(sample)))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        stacks.sort();
+
+        let tmp = path.with_extension("folded.tmp");
+        let mut file = io::BufWriter::new(fs::File::create(&tmp)?);
+        for (stack, count) in stacks {
+            writeln!(file, "{} {}", stack, count)?;
+        }
+        file.flush()?;
+        drop(file);
+        fs::rename(tmp, path)
+    }
+}
+
+/// The profile of one invocation of a handler
+pub(crate) struct Profiler {
+    profile: Arc<DeploymentProfile>,
+    logger: Logger,
+    root: String,
+    interval: Duration,
+    last_sample: Instant,
+    /// The gas used since the last sample
+    pending_gas: u64,
+    samples: Samples,
+}
+
+impl Profiler {
+    pub(crate) fn new(
+        profile: Arc<DeploymentProfile>,
+        logger: Logger,
+        data_source: &str,
+        handler: &str,
+    ) -> Self {
+        Profiler {
+            profile,
+            logger,
+            root: format!("{};{}", frame(data_source), frame(handler)),
+            interval: ENV_VARS.mappings.profile_interval,
+            last_sample: Instant::now(),
+            pending_gas: 0,
+            samples: HashMap::new(),
+        }
+    }
+
+    /// The current wasm stack in folded stack format
+    fn stack(&self, ctx: impl AsContext) -> String {
+        let backtrace = WasmBacktrace::capture(ctx);
+        let mut stack = self.root.clone();
+        // Frames are ordered from the innermost to the outermost call
+        for info in backtrace.frames().iter().rev() {
+            stack.push(';');
+            match info.func_name() {
+                Some(name) => stack.push_str(&frame(name)),
+                None => stack.push_str(&format!("wasm-function[{}]", info.func_index())),
+            }
+        }
+        stack
+    }
+
+    /// Attribute the time since the last sample and `gas` to `stack`
+    fn record(&mut self, stack: String, gas: u64) {
+        let now = Instant::now();
+        let micros = now.duration_since(self.last_sample).as_micros() as u64;
+        self.last_sample = now;
+
+        let sample = self.samples.entry(stack).or_default();
+        sample.micros += micros;
+        sample.gas += gas;
+    }
+
+    /// Called whenever the wasm code used `gas`; takes a sample if it is
+    /// time for one
+    pub(crate) fn gas(&mut self, ctx: impl AsContext, gas: u64) {
+        self.pending_gas += gas;
+        if self.last_sample.elapsed() >= self.interval {
+            let stack = self.stack(ctx);
+            let gas = std::mem::take(&mut self.pending_gas);
+            self.record(stack, gas);
+        }
+    }
+
+    /// Add the profile of this handler invocation to the profile of the
+    /// deployment
+    pub(crate) fn finish(mut self) {
+        let gas = std::mem::take(&mut self.pending_gas);
+        self.record(self.root.clone(), gas);
+        self.profile.add(&self.logger, self.samples);
+    }
+}
+
+/// The state of the profiler while a host function runs
+pub(crate) struct HostFnProfile {
+    profiler: Profiler,
+    stack: String,
+    gas: u64,
+}
+
+impl WasmInstanceContext<'_> {
+    /// Take a sample before a call to a host function. The profiler is
+    /// taken out of the instance while the host function runs so that wasm
+    /// code that the host function calls, like the allocator, does not
+    /// take samples
+    pub(crate) fn enter_host_fn(&mut self, gas: &GasCounter) -> Option<HostFnProfile> {
+        let mut profiler = self.as_mut().profiler.take()?;
+        let stack = profiler.stack(&*self);
+        let pending_gas = std::mem::take(&mut profiler.pending_gas);
+        profiler.record(stack.clone(), pending_gas);
+        Some(HostFnProfile {
+            profiler,
+            stack,
+            gas: gas.get().value(),
+        })
+    }
+
+    /// Attribute the time and gas that the host function `name` used to
+    /// it
+    pub(crate) fn exit_host_fn(
+        &mut self,
+        profile: Option<HostFnProfile>,
+        name: &str,
+        gas: &GasCounter,
+    ) {
+        if let Some(HostFnProfile {
+            mut profiler,
+            stack,
+            gas: start,
+        }) = profile
+        {
+            let gas = gas.get().value().saturating_sub(start);
+            profiler.record(format!("{};host:{}", stack, frame(name)), gas);
+            self.as_mut().profiler = Some(profiler);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(micros: u64, gas: u64) -> Sample {
+        Sample { micros, gas }
+    }
+
+    #[test]
+    fn frame_names() {
+        assert_eq!("handleTransfer", frame("handleTransfer"));
+        // Separators of the folded stack format are replaced
+        assert_eq!("a:b", frame("a;b"));
+        assert_eq!("handle_Transfer_event", frame("handle Transfer event"));
+        assert_eq!("x:_y", frame("x; y"));
+    }
+
+    #[test]
+    fn write_folded_stacks() {
+        let mut samples = Samples::new();
+        add_samples(
+            &mut samples,
+            HashMap::from([
+                ("ds;handler".to_string(), sample(5, 0)),
+                ("ds;handler;main".to_string(), sample(10, 100)),
+            ]),
+        );
+        // A later invocation of the same handler hits the same stacks
+        // again and adds new ones
+        add_samples(
+            &mut samples,
+            HashMap::from([
+                ("ds;handler;main".to_string(), sample(7, 20)),
+                ("ds;handler;main;host:store.set".to_string(), sample(3, 1)),
+            ]),
+        );
+
+        let dir = std::env::temp_dir().join(format!("graph-profiler-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let time_path = dir.join("deployment.time.folded");
+        let gas_path = dir.join("deployment.gas.folded");
+
+        DeploymentProfile::write(&time_path, &samples, |sample| sample.micros).unwrap();
+        DeploymentProfile::write(&gas_path, &samples, |sample| sample.gas).unwrap();
+
+        // Stacks are sorted, repeated stacks have their counts added up,
+        // and stacks with a count of 0 are left out
+        assert_eq!(
+            "ds;handler 5\nds;handler;main 17\nds;handler;main;host:store.set 3\n",
+            fs::read_to_string(&time_path).unwrap()
+        );
+        assert_eq!(
+            "ds;handler;main 120\nds;handler;main;host:store.set 1\n",
+            fs::read_to_string(&gas_path).unwrap()
+        );
+        assert!(!time_path.with_extension("folded.tmp").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
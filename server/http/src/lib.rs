@@ -6,10 +6,12 @@ extern crate http;
 extern crate hyper;
 extern crate serde;
 
+mod query_limits;
 mod request;
 mod server;
 mod service;
 
+pub use self::query_limits::{ApiKey, Limits, QueryLimiter, QueryLimitsConfig};
 pub use self::server::GraphQLServer;
 pub use self::service::{GraphQLService, GraphQLServiceResponse};
 
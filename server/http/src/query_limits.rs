@@ -0,0 +1,349 @@
+//! Limits on the rate and the complexity of the queries that individual
+//! clients can run. Clients are identified by an API key that they send
+//! in a header or as a bearer token
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use graph::data::query::QueryResults;
+use graph::prelude::{anyhow::anyhow, Error, MetricsRegistry, QueryExecutionError};
+use graph::prometheus::IntCounterVec;
+use http::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, RETRY_AFTER};
+use http::{Response, StatusCode};
+
+/// The name under which requests without a known key are reported
+const ANONYMOUS: &str = "anonymous";
+
+/// How many queries and how much query complexity a client may use. A
+/// client can use up a full minute's worth of its limits in a burst
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Limits {
+    pub queries_per_minute: Option<u64>,
+    pub complexity_per_minute: Option<u64>,
+}
+
+/// An API key and the limits for the client that uses it
+#[derive(Clone, Debug)]
+pub struct ApiKey {
+    /// The name of the client in metrics; the key itself is never reported
+    pub name: String,
+    pub key: String,
+    pub limits: Limits,
+}
+
+#[derive(Clone, Debug)]
+pub struct QueryLimitsConfig {
+    /// The header that contains the API key. Clients can also send the key
+    /// as a bearer token in the `Authorization` header
+    pub header: String,
+    /// The limits that all requests without a known key share
+    pub anonymous: Limits,
+    pub keys: Vec<ApiKey>,
+}
+
+/// A token bucket that holds up to a minute's worth of tokens and refills
+/// continuously. Tokens can be taken after the fact, which can make the
+/// number of tokens negative
+struct Bucket {
+    capacity: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(per_minute: u64) -> Self {
+        Bucket {
+            capacity: per_minute as f64,
+            tokens: per_minute as f64,
+            updated: Instant::now(),
+        }
+    }
+
+    fn available(&mut self, now: Instant) -> f64 {
+        let refill =
+            now.saturating_duration_since(self.updated).as_secs_f64() * self.capacity / 60.0;
+        self.tokens = (self.tokens + refill).min(self.capacity);
+        self.updated = now;
+        self.tokens
+    }
+
+    fn take(&mut self, amount: f64, now: Instant) {
+        self.available(now);
+        self.tokens -= amount;
+    }
+
+    /// How long it takes until at least one token is available
+    fn wait(&self) -> Duration {
+        if self.capacity == 0.0 {
+            return Duration::from_secs(60);
+        }
+        Duration::from_secs_f64(((1.0 - self.tokens) * 60.0 / self.capacity).max(0.0))
+    }
+}
+
+/// The state of the limits for one client
+pub struct Client {
+    name: String,
+    queries: Option<Mutex<Bucket>>,
+    complexity: Option<Mutex<Bucket>>,
+    counters: Counters,
+}
+
+/// A query that was rejected because the client exceeded its limits
+pub struct Rejection {
+    pub error: QueryExecutionError,
+    pub retry_after: Duration,
+}
+
+impl Rejection {
+    /// A `429 Too Many Requests` response with the error and a
+    /// `Retry-After` header that tells the client when it can try again
+    pub fn into_http_response<T: From<String>>(self) -> Response<T> {
+        let mut response = QueryResults::from(self.error).as_http_response();
+        *response.status_mut() = StatusCode::TOO_MANY_REQUESTS;
+        let retry_after = self.retry_after.as_secs_f64().ceil().max(1.0) as u64;
+        response
+            .headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(retry_after));
+        response
+    }
+}
+
+impl Client {
+    fn new(name: String, limits: &Limits, counters: Counters) -> Self {
+        Client {
+            name,
+            queries: limits
+                .queries_per_minute
+                .map(|n| Mutex::new(Bucket::new(n))),
+            complexity: limits
+                .complexity_per_minute
+                .map(|n| Mutex::new(Bucket::new(n))),
+            counters,
+        }
+    }
+
+    /// Decide whether the client may run another query. If it may, return
+    /// the complexity that is left in its budget, or `None` if it has no
+    /// complexity budget
+    pub fn admit(&self) -> Result<Option<u64>, Rejection> {
+        let now = Instant::now();
+
+        let remaining = match &self.complexity {
+            Some(bucket) => {
+                let mut bucket = bucket.lock().unwrap();
+                if bucket.available(now) < 1.0 {
+                    return Err(self.reject(
+                        "over_budget",
+                        QueryExecutionError::ComplexityBudgetExhausted(self.name.clone()),
+                        bucket.wait(),
+                    ));
+                }
+                Some(bucket.tokens as u64)
+            }
+            None => None,
+        };
+
+        if let Some(bucket) = &self.queries {
+            let mut bucket = bucket.lock().unwrap();
+            if bucket.available(now) < 1.0 {
+                return Err(self.reject(
+                    "rate_limited",
+                    QueryExecutionError::RateLimited(self.name.clone()),
+                    bucket.wait(),
+                ));
+            }
+            bucket.take(1.0, now);
+        }
+
+        self.counters
+            .queries
+            .with_label_values(&[&self.name, "accepted"])
+            .inc();
+        Ok(remaining)
+    }
+
+    /// Charge the complexity of a query that the client ran against its
+    /// budget
+    pub fn charge(&self, complexity: u64) {
+        if let Some(bucket) = &self.complexity {
+            bucket
+                .lock()
+                .unwrap()
+                .take(complexity as f64, Instant::now());
+        }
+        self.counters
+            .complexity
+            .with_label_values(&[&self.name])
+            .inc_by(complexity);
+    }
+
+    /// Return `true` if the client has used up its complexity budget
+    pub fn over_budget(&self) -> bool {
+        match &self.complexity {
+            Some(bucket) => bucket.lock().unwrap().available(Instant::now()) < 1.0,
+            None => false,
+        }
+    }
+
+    fn reject(&self, result: &str, error: QueryExecutionError, retry_after: Duration) -> Rejection {
+        self.counters
+            .queries
+            .with_label_values(&[&self.name, result])
+            .inc();
+        Rejection { error, retry_after }
+    }
+}
+
+#[derive(Clone)]
+struct Counters {
+    queries: Box<IntCounterVec>,
+    complexity: Box<IntCounterVec>,
+}
+
+/// Finds the `Client` for a request from the API key in its headers
+pub struct QueryLimiter {
+    header: HeaderName,
+    clients: HashMap<String, Arc<Client>>,
+    anonymous: Arc<Client>,
+}
+
+impl QueryLimiter {
+    pub fn new(config: QueryLimitsConfig, registry: &MetricsRegistry) -> Result<Self, Error> {
+        let header = HeaderName::from_bytes(config.header.as_bytes())
+            .map_err(|e| anyhow!("invalid API key header `{}`: {}", config.header, e))?;
+
+        let counters = Counters {
+            queries: registry.new_int_counter_vec(
+                "query_key_queries",
+                "Number of queries per API key that were accepted or rejected",
+                &["key", "result"],
+            )?,
+            complexity: registry.new_int_counter_vec(
+                "query_key_complexity",
+                "Total complexity of the queries run per API key",
+                &["key"],
+            )?,
+        };
+
+        let mut clients = HashMap::new();
+        for ApiKey { name, key, limits } in config.keys {
+            let client = Arc::new(Client::new(name, &limits, counters.clone()));
+            if clients.insert(key, client).is_some() {
+                return Err(anyhow!("the same API key is configured more than once"));
+            }
+        }
+        let anonymous = Arc::new(Client::new(
+            ANONYMOUS.to_string(),
+            &config.anonymous,
+            counters,
+        ));
+
+        Ok(QueryLimiter {
+            header,
+            clients,
+            anonymous,
+        })
+    }
+
+    /// Return the client that sent a request with `headers`. Requests
+    /// without a key or with an unknown key are treated as anonymous
+    pub fn client(&self, headers: &HeaderMap) -> Arc<Client> {
+        let key = headers
+            .get(&self.header)
+            .and_then(|value| value.to_str().ok())
+            .or_else(|| {
+                headers
+                    .get(AUTHORIZATION)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.strip_prefix("Bearer "))
+            })
+            .map(str::trim);
+
+        key.and_then(|key| self.clients.get(key))
+            .unwrap_or(&self.anonymous)
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use graph::prelude::MetricsRegistry;
+    use http::header::HeaderValue;
+
+    use super::*;
+
+    fn limiter() -> QueryLimiter {
+        let config = QueryLimitsConfig {
+            header: "X-Api-Key".to_string(),
+            anonymous: Limits {
+                queries_per_minute: Some(2),
+                complexity_per_minute: None,
+            },
+            keys: vec![ApiKey {
+                name: "gateway".to_string(),
+                key: "secret".to_string(),
+                limits: Limits {
+                    queries_per_minute: None,
+                    complexity_per_minute: Some(100),
+                },
+            }],
+        };
+        QueryLimiter::new(config, &MetricsRegistry::mock()).unwrap()
+    }
+
+    #[test]
+    fn identifies_clients() {
+        let limiter = limiter();
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(ANONYMOUS, limiter.client(&headers).name);
+
+        headers.insert("x-api-key", HeaderValue::from_static("secret"));
+        assert_eq!("gateway", limiter.client(&headers).name);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer secret"));
+        assert_eq!("gateway", limiter.client(&headers).name);
+
+        headers.insert("x-api-key", HeaderValue::from_static("unknown"));
+        assert_eq!(ANONYMOUS, limiter.client(&headers).name);
+    }
+
+    #[test]
+    fn limits_query_rate() {
+        let limiter = limiter();
+        let client = limiter.client(&HeaderMap::new());
+
+        assert_eq!(None, client.admit().ok().unwrap());
+        assert_eq!(None, client.admit().ok().unwrap());
+        let rejection = client.admit().err().unwrap();
+        assert!(matches!(
+            rejection.error,
+            QueryExecutionError::RateLimited(_)
+        ));
+        assert!(rejection.retry_after > Duration::ZERO);
+        assert!(rejection.retry_after <= Duration::from_secs(30));
+    }
+
+    #[test]
+    fn limits_complexity() {
+        let limiter = limiter();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", HeaderValue::from_static("secret"));
+        let client = limiter.client(&headers);
+
+        assert_eq!(Some(100), client.admit().ok().unwrap());
+        client.charge(60);
+        assert_eq!(Some(40), client.admit().ok().unwrap());
+        assert!(!client.over_budget());
+        client.charge(40);
+        assert!(client.over_budget());
+        let rejection = client.admit().err().unwrap();
+        assert!(matches!(
+            rejection.error,
+            QueryExecutionError::ComplexityBudgetExhausted(_)
+        ));
+    }
+}
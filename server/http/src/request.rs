@@ -2,47 +2,118 @@ use graph::prelude::serde_json;
 use hyper::body::Bytes;
 
 use graph::components::server::query::GraphQLServerError;
+use graph::data::query::{PersistedQueries, SqlQueryReq};
 use graph::prelude::*;
 
-pub fn parse_graphql_request(body: &Bytes, trace: bool) -> Result<Query, GraphQLServerError> {
-    // Parse request body as JSON
-    let json: serde_json::Value = serde_json::from_slice(body)
-        .map_err(|e| GraphQLServerError::ClientError(format!("{}", e)))?;
-
-    // Ensure the JSON data is an object
-    let obj = json.as_object().ok_or_else(|| {
-        GraphQLServerError::ClientError(String::from("Request data is not an object"))
-    })?;
-
-    // Ensure the JSON data has a "query" field
-    let query_value = obj.get("query").ok_or_else(|| {
-        GraphQLServerError::ClientError(String::from(
-            "The \"query\" field is missing in request data",
-        ))
-    })?;
-
-    // Ensure the "query" field is a string
-    let query_string = query_value.as_str().ok_or_else(|| {
-        GraphQLServerError::ClientError(String::from("The \"query\" field is not a string"))
-    })?;
-
-    // Parse the "query" field of the JSON body
-    let document = graphql_parser::parse_query(query_string)
-        .map_err(|e| GraphQLServerError::from(QueryError::ParseError(Arc::new(e.into()))))?
-        .into_static();
-
-    // Parse the "variables" field of the JSON body, if present
-    let variables = match obj.get("variables") {
-        None | Some(serde_json::Value::Null) => Ok(None),
-        Some(variables @ serde_json::Value::Object(_)) => serde_json::from_value(variables.clone())
-            .map_err(|e| GraphQLServerError::ClientError(e.to_string()))
-            .map(Some),
-        _ => Err(GraphQLServerError::ClientError(
-            "Invalid query variables provided".to_string(),
-        )),
-    }?;
-
-    Ok(Query::new(document, variables, trace))
+/// A GraphQL request whose query document has not been parsed yet.
+/// Clients can send the hash of a persisted query in
+/// `extensions.persistedQuery.sha256Hash` instead of, or in addition to,
+/// the query text
+pub struct GraphQLRequest {
+    query: Option<String>,
+    hash: Option<String>,
+    variables: Option<QueryVariables>,
+}
+
+impl GraphQLRequest {
+    pub fn parse(body: &Bytes) -> Result<Self, GraphQLServerError> {
+        // Parse request body as JSON
+        let json: serde_json::Value = serde_json::from_slice(body)
+            .map_err(|e| GraphQLServerError::ClientError(format!("{}", e)))?;
+
+        // Ensure the JSON data is an object
+        let obj = json.as_object().ok_or_else(|| {
+            GraphQLServerError::ClientError(String::from("Request data is not an object"))
+        })?;
+
+        // Ensure the "query" field is a string if it is present
+        let query = match obj.get("query") {
+            None | Some(serde_json::Value::Null) => None,
+            Some(serde_json::Value::String(query)) => Some(query.clone()),
+            Some(_) => {
+                return Err(GraphQLServerError::ClientError(String::from(
+                    "The \"query\" field is not a string",
+                )))
+            }
+        };
+
+        // Look for the hash of a persisted query in the "extensions" field
+        let hash = match obj
+            .get("extensions")
+            .and_then(|extensions| extensions.get("persistedQuery"))
+            .and_then(|persisted| persisted.get("sha256Hash"))
+        {
+            None | Some(serde_json::Value::Null) => None,
+            Some(serde_json::Value::String(hash)) => Some(hash.clone()),
+            Some(_) => {
+                return Err(GraphQLServerError::ClientError(String::from(
+                    "The \"sha256Hash\" of the persisted query is not a string",
+                )))
+            }
+        };
+
+        // Parse the "variables" field of the JSON body, if present
+        let variables = match obj.get("variables") {
+            None | Some(serde_json::Value::Null) => Ok(None),
+            Some(variables @ serde_json::Value::Object(_)) => {
+                serde_json::from_value(variables.clone())
+                    .map_err(|e| GraphQLServerError::ClientError(e.to_string()))
+                    .map(Some)
+            }
+            _ => Err(GraphQLServerError::ClientError(
+                "Invalid query variables provided".to_string(),
+            )),
+        }?;
+
+        Ok(GraphQLRequest {
+            query,
+            hash,
+            variables,
+        })
+    }
+
+    /// Resolve the query text, using `persisted` to look up queries that
+    /// were sent by hash, and parse it. If `persisted` only allows
+    /// registered queries, reject all other queries without parsing them
+    pub fn into_query(
+        self,
+        persisted: &PersistedQueries,
+        trace: bool,
+    ) -> Result<Query, GraphQLServerError> {
+        let query_string = match (self.query, self.hash) {
+            (Some(query), Some(hash)) => {
+                if PersistedQueries::hash(&query) != hash.to_ascii_lowercase() {
+                    return Err(QueryExecutionError::PersistedQueryHashMismatch.into());
+                }
+                query
+            }
+            (Some(query), None) => query,
+            (None, Some(hash)) => persisted
+                .get(&hash)
+                .ok_or(QueryExecutionError::PersistedQueryNotFound)?
+                .to_string(),
+            (None, None) => {
+                return Err(GraphQLServerError::ClientError(String::from(
+                    "The \"query\" field is missing in request data",
+                )))
+            }
+        };
+
+        if !persisted.allows(&query_string) {
+            return Err(QueryExecutionError::QueryNotAllowlisted.into());
+        }
+
+        // Parse the query text
+        let document = graphql_parser::parse_query(&query_string)
+            .map_err(|e| GraphQLServerError::from(QueryError::ParseError(Arc::new(e.into()))))?
+            .into_static();
+
+        Ok(Query::new(document, self.variables, trace))
+    }
+}
+
+pub fn parse_sql_request(body: &Bytes) -> Result<SqlQueryReq, GraphQLServerError> {
+    serde_json::from_slice(body).map_err(|e| GraphQLServerError::ClientError(format!("{}", e)))
 }
 
 #[cfg(test)]
@@ -50,15 +121,18 @@ mod tests {
 
     use std::collections::HashMap;
 
+    use graph::prelude::serde_json::json;
     use graph::{
+        components::server::query::GraphQLServerError,
         data::{
-            query::QueryTarget,
+            query::{PersistedQueries, QueryTarget},
             value::{Object, Word},
         },
         prelude::*,
     };
+    use hyper::body::Bytes;
 
-    use super::parse_graphql_request;
+    use super::GraphQLRequest;
 
     lazy_static! {
         static ref TARGET: QueryTarget = QueryTarget::Name(
@@ -67,6 +141,10 @@ mod tests {
         );
     }
 
+    fn parse_graphql_request(body: &Bytes, trace: bool) -> Result<Query, GraphQLServerError> {
+        GraphQLRequest::parse(body)?.into_query(&PersistedQueries::default(), trace)
+    }
+
     #[test]
     fn rejects_invalid_json() {
         let request = parse_graphql_request(&hyper::body::Bytes::from("!@#)%"), false);
@@ -179,4 +257,58 @@ mod tests {
         assert_eq!(query.document, expected_query);
         assert_eq!(query.variables, Some(expected_variables));
     }
+
+    #[test]
+    fn resolves_persisted_queries() {
+        const QUERY: &str = "{ user { name } }";
+        let hash = PersistedQueries::hash(QUERY);
+        let persisted = PersistedQueries::new(vec![(hash.clone(), QUERY.to_string())], false);
+        let expected_query = graphql_parser::parse_query(QUERY).unwrap().into_static();
+
+        let body = json!({
+            "extensions": { "persistedQuery": { "version": 1, "sha256Hash": hash } }
+        })
+        .to_string();
+        let query = GraphQLRequest::parse(&Bytes::from(body.clone()))
+            .unwrap()
+            .into_query(&persisted, false)
+            .expect("Should find the persisted query");
+        assert_eq!(query.document, expected_query);
+
+        let err = GraphQLRequest::parse(&Bytes::from(body))
+            .unwrap()
+            .into_query(&PersistedQueries::default(), false)
+            .expect_err("Should reject unknown hashes");
+        assert!(err.to_string().contains("PersistedQueryNotFound"));
+
+        let body = json!({
+            "query": "{ other }",
+            "extensions": { "persistedQuery": { "version": 1, "sha256Hash": hash } }
+        })
+        .to_string();
+        GraphQLRequest::parse(&Bytes::from(body))
+            .unwrap()
+            .into_query(&persisted, false)
+            .expect_err("Should reject a hash that does not match the query");
+    }
+
+    #[test]
+    fn rejects_queries_not_on_allowlist() {
+        const QUERY: &str = "{ user { name } }";
+        let hash = PersistedQueries::hash(QUERY);
+        let persisted = PersistedQueries::new(vec![(hash, QUERY.to_string())], true);
+
+        GraphQLRequest::parse(&Bytes::from("{\"query\": \"{ user { name } }\"}"))
+            .unwrap()
+            .into_query(&persisted, false)
+            .expect("Should accept allowlisted queries");
+
+        // The query is rejected before it is parsed, so even broken queries
+        // produce an allowlist error
+        let err = GraphQLRequest::parse(&Bytes::from("{\"query\": \"foo\"}"))
+            .unwrap()
+            .into_query(&persisted, false)
+            .expect_err("Should reject queries that are not on the allowlist");
+        assert!(err.to_string().contains("allowlist"));
+    }
 }
@@ -5,6 +5,7 @@ use hyper::service::make_service_fn;
 use hyper::Server;
 use thiserror::Error;
 
+use crate::query_limits::QueryLimiter;
 use crate::service::GraphQLService;
 use graph::prelude::{GraphQLServer as GraphQLServerTrait, GraphQlRunner, *};
 
@@ -20,6 +21,7 @@ pub struct GraphQLServer<Q> {
     logger: Logger,
     graphql_runner: Arc<Q>,
     node_id: NodeId,
+    query_limiter: Option<Arc<QueryLimiter>>,
 }
 
 impl<Q> GraphQLServer<Q> {
@@ -37,8 +39,15 @@ impl<Q> GraphQLServer<Q> {
             logger,
             graphql_runner,
             node_id,
+            query_limiter: None,
         }
     }
+
+    /// Limit the rate and the complexity of the queries of each client
+    pub fn with_query_limiter(mut self, query_limiter: Arc<QueryLimiter>) -> Self {
+        self.query_limiter = Some(query_limiter);
+        self
+    }
 }
 
 impl<Q> GraphQLServerTrait for GraphQLServer<Q>
@@ -66,13 +75,17 @@ where
         let logger_for_service = self.logger.clone();
         let graphql_runner = self.graphql_runner.clone();
         let node_id = self.node_id.clone();
+        let query_limiter = self.query_limiter.clone();
         let new_service = make_service_fn(move |_| {
-            let graphql_service = GraphQLService::new(
+            let mut graphql_service = GraphQLService::new(
                 logger_for_service.clone(),
                 graphql_runner.clone(),
                 ws_port,
                 node_id.clone(),
             );
+            if let Some(query_limiter) = &query_limiter {
+                graphql_service = graphql_service.with_query_limiter(query_limiter.clone());
+            }
 
             futures03::future::ok::<_, Error>(graphql_service)
         });
@@ -10,16 +10,21 @@ use graph::prelude::serde_json::json;
 use graph::prelude::*;
 use graph::semver::VersionReq;
 use graph::url::form_urlencoded;
-use graph::{components::server::query::GraphQLServerError, data::query::QueryTarget};
+use graph::{
+    components::server::query::GraphQLServerError,
+    data::query::{FeedCursor, FeedEvent, QueryTarget},
+};
 use http::header;
 use http::header::{
-    ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
-    CONTENT_TYPE, LOCATION,
+    HeaderMap, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+    ACCESS_CONTROL_ALLOW_ORIGIN, CONTENT_TYPE, LOCATION,
 };
+use hyper::body::Bytes;
 use hyper::service::Service;
 use hyper::{Body, Method, Request, Response, StatusCode};
 
-use crate::request::parse_graphql_request;
+use crate::query_limits::{Client, QueryLimiter, Rejection};
+use crate::request::{parse_sql_request, GraphQLRequest};
 
 pub type GraphQLServiceResult = Result<Response<Body>, GraphQLServerError>;
 /// An asynchronous response to a GraphQL request.
@@ -33,6 +38,7 @@ pub struct GraphQLService<Q> {
     graphql_runner: Arc<Q>,
     ws_port: u16,
     node_id: NodeId,
+    query_limiter: Option<Arc<QueryLimiter>>,
 }
 
 impl<Q> Clone for GraphQLService<Q> {
@@ -42,6 +48,7 @@ impl<Q> Clone for GraphQLService<Q> {
             graphql_runner: self.graphql_runner.clone(),
             ws_port: self.ws_port,
             node_id: self.node_id.clone(),
+            query_limiter: self.query_limiter.clone(),
         }
     }
 }
@@ -57,9 +64,16 @@ where
             graphql_runner,
             ws_port,
             node_id,
+            query_limiter: None,
         }
     }
 
+    /// Limit the rate and the complexity of the queries of each client
+    pub fn with_query_limiter(mut self, query_limiter: Arc<QueryLimiter>) -> Self {
+        self.query_limiter = Some(query_limiter);
+        self
+    }
+
     fn graphiql_html(&self) -> String {
         include_str!("../assets/index.html")
             .replace("__WS_PORT__", format!("{}", self.ws_port).as_str())
@@ -179,18 +193,46 @@ where
                     })
                     .unwrap_or(false)
         };
+        let (client, budget) = match self.admit(request.headers()) {
+            Ok(admitted) => admitted,
+            Err(rejection) => return Ok(rejection.into_http_response()),
+        };
+
         let body = hyper::body::to_bytes(request.into_body())
             .map_err(|_| GraphQLServerError::InternalError("Failed to read request body".into()))
             .await?;
-        let query = parse_graphql_request(&body, trace);
+        let query = self.parse_graphql_request(&target, &body, trace).await;
         let query_parsing_time = start.elapsed();
 
-        let result = match query {
-            Ok(query) => service.graphql_runner.run_query(query, target).await,
-            Err(GraphQLServerError::QueryError(e)) => QueryResult::from(e).into(),
-            Err(e) => return Err(e),
+        let result = match (query, budget) {
+            // Queries must not be more complex than what is left of the
+            // client's budget
+            (Ok(query), Some(budget)) => {
+                let max_complexity = ENV_VARS
+                    .graphql
+                    .max_complexity
+                    .map_or(budget, |max| max.min(budget));
+                service
+                    .graphql_runner
+                    .run_query_with_complexity(
+                        query,
+                        target,
+                        Some(max_complexity),
+                        Some(ENV_VARS.graphql.max_depth),
+                        Some(ENV_VARS.graphql.max_first),
+                        Some(ENV_VARS.graphql.max_skip),
+                    )
+                    .await
+            }
+            (Ok(query), None) => service.graphql_runner.run_query(query, target).await,
+            (Err(GraphQLServerError::QueryError(e)), _) => QueryResult::from(e).into(),
+            (Err(e), _) => return Err(e),
         };
 
+        if let Some(client) = &client {
+            client.charge(result.complexity());
+        }
+
         self.graphql_runner
             .metrics()
             .observe_query_parsing(query_parsing_time, &result);
@@ -201,6 +243,303 @@ where
         Ok(result.as_http_response())
     }
 
+    /// Check that the client that sent a request with `headers` may run
+    /// another query. Return the client if queries are limited, so that
+    /// the query can be charged to it, and the complexity that is left in
+    /// its budget
+    fn admit(&self, headers: &HeaderMap) -> Result<(Option<Arc<Client>>, Option<u64>), Rejection> {
+        let client = match &self.query_limiter {
+            Some(limiter) => limiter.client(headers),
+            None => return Ok((None, None)),
+        };
+        let budget = client.admit()?;
+        Ok((Some(client), budget))
+    }
+
+    /// Parse the request `body`, looking up persisted queries that are sent
+    /// by hash. If the deployment only allows persisted queries, reject all
+    /// other queries before they are parsed
+    async fn parse_graphql_request(
+        &self,
+        target: &QueryTarget,
+        body: &Bytes,
+        trace: bool,
+    ) -> Result<Query, GraphQLServerError> {
+        let request = GraphQLRequest::parse(body)?;
+        let persisted = self
+            .graphql_runner
+            .cheap_clone()
+            .persisted_queries(target.clone())
+            .await?;
+        request.into_query(&persisted, trace)
+    }
+
+    fn handle_sql_query_by_id(self, id: String, request: Request<Body>) -> GraphQLServiceResponse {
+        if !ENV_VARS.graphql.enable_sql_queries {
+            return self.handle_not_found();
+        }
+
+        match DeploymentHash::new(id) {
+            Err(_) => self.handle_not_found(),
+            Ok(id) => self
+                .handle_sql_query(QueryTarget::Deployment(id, ApiVersion::default()), request)
+                .boxed(),
+        }
+    }
+
+    /// Return an error if `target` only executes allowlisted queries. SQL
+    /// queries and query plans would bypass the allowlist, and are therefore
+    /// not available for such deployments
+    async fn check_not_allowlist_only(
+        &self,
+        target: &QueryTarget,
+    ) -> Result<(), QueryExecutionError> {
+        let persisted = self
+            .graphql_runner
+            .cheap_clone()
+            .persisted_queries(target.clone())
+            .await?;
+        if persisted.allowlist_only {
+            return Err(QueryExecutionError::QueryNotAllowlisted);
+        }
+        Ok(())
+    }
+
+    async fn handle_sql_query(
+        self,
+        target: QueryTarget,
+        request: Request<Body>,
+    ) -> GraphQLServiceResult {
+        let (client, _) = match self.admit(request.headers()) {
+            Ok(admitted) => admitted,
+            Err(rejection) => return Ok(rejection.into_http_response()),
+        };
+
+        let result = match self.check_not_allowlist_only(&target).await {
+            Ok(()) => {
+                let body = hyper::body::to_bytes(request.into_body())
+                    .map_err(|_| {
+                        GraphQLServerError::InternalError("Failed to read request body".into())
+                    })
+                    .await?;
+                let req = parse_sql_request(&body)?;
+                self.graphql_runner.run_sql_query(req, target).await
+            }
+            Err(e) => Err(e),
+        };
+
+        // SQL queries have no complexity; every row they return counts as
+        // one unit of complexity
+        if let (Some(client), Ok(rows)) = (&client, &result) {
+            client.charge(rows.len() as u64);
+        }
+
+        let response_obj = match result {
+            Ok(rows) => json!({ "data": rows }),
+            Err(e) => json!({ "errors": [{ "message": e.to_string() }] }),
+        };
+        let response_str = serde_json::to_string(&response_obj).unwrap();
+
+        Ok(Response::builder()
+            .status(200)
+            .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(response_str))
+            .unwrap())
+    }
+
+    fn handle_explain_query_by_id(
+        self,
+        id: String,
+        request: Request<Body>,
+    ) -> GraphQLServiceResponse {
+        if !ENV_VARS.graphql.enable_query_explain {
+            return self.handle_not_found();
+        }
+
+        match DeploymentHash::new(id) {
+            Err(_) => self.handle_not_found(),
+            Ok(id) => self
+                .handle_explain_query(QueryTarget::Deployment(id, ApiVersion::default()), request)
+                .boxed(),
+        }
+    }
+
+    /// Respond with the plan for the GraphQL query in the request without
+    /// running the query. Postgres' plans for the SQL queries are only
+    /// included if the request has the URL parameter `explain=true`
+    async fn handle_explain_query(
+        self,
+        target: QueryTarget,
+        request: Request<Body>,
+    ) -> GraphQLServiceResult {
+        let explain = request
+            .uri()
+            .query()
+            .map(|query| {
+                form_urlencoded::parse(query.as_bytes())
+                    .any(|(key, value)| key == "explain" && value == "true")
+            })
+            .unwrap_or(false);
+        if let Err(rejection) = self.admit(request.headers()) {
+            return Ok(rejection.into_http_response());
+        }
+        let query = match self.check_not_allowlist_only(&target).await {
+            Ok(()) => {
+                let body = hyper::body::to_bytes(request.into_body())
+                    .map_err(|_| {
+                        GraphQLServerError::InternalError("Failed to read request body".into())
+                    })
+                    .await?;
+                self.parse_graphql_request(&target, &body, false).await
+            }
+            Err(e) => Err(e.into()),
+        };
+
+        let response_obj = match query {
+            Ok(query) => {
+                match self
+                    .graphql_runner
+                    .explain_query(query, target, explain)
+                    .await
+                {
+                    Ok(plan) => json!({ "data": plan }),
+                    Err(errors) => {
+                        let errors: Vec<_> = errors
+                            .iter()
+                            .map(|e| json!({ "message": e.to_string() }))
+                            .collect();
+                        json!({ "errors": errors })
+                    }
+                }
+            }
+            Err(GraphQLServerError::QueryError(e)) => {
+                json!({ "errors": [{ "message": e.to_string() }] })
+            }
+            Err(e) => return Err(e),
+        };
+        let response_str = serde_json::to_string(&response_obj).unwrap();
+
+        Ok(Response::builder()
+            .status(200)
+            .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(response_str))
+            .unwrap())
+    }
+
+    fn handle_entity_feed_by_id(
+        self,
+        id: String,
+        request: Request<Body>,
+    ) -> GraphQLServiceResponse {
+        if !ENV_VARS.graphql.enable_entity_feed {
+            return self.handle_not_found();
+        }
+
+        match DeploymentHash::new(id) {
+            Err(_) => self.handle_not_found(),
+            Ok(id) => self
+                .handle_entity_feed(QueryTarget::Deployment(id, ApiVersion::default()), request)
+                .boxed(),
+        }
+    }
+
+    /// Stream the entity change feed for `target` as server-sent events.
+    /// The feed starts after the cursor passed in the `cursor` query
+    /// parameter or, when a client reconnects, in the `Last-Event-ID`
+    /// header, and at the current deployment head if neither is present
+    async fn handle_entity_feed(
+        self,
+        target: QueryTarget,
+        request: Request<Body>,
+    ) -> GraphQLServiceResult {
+        let cursor = request
+            .headers()
+            .get("last-event-id")
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned)
+            .or_else(|| {
+                request.uri().query().and_then(|query| {
+                    form_urlencoded::parse(query.as_bytes())
+                        .find(|(key, _)| key == "cursor")
+                        .map(|(_, value)| value.into_owned())
+                })
+            })
+            .map(|cursor| cursor.parse::<FeedCursor>())
+            .transpose()
+            .map_err(|e| GraphQLServerError::ClientError(e.to_string()))?;
+        let (client, _) = match self.admit(request.headers()) {
+            Ok(admitted) => admitted,
+            Err(rejection) => return Ok(rejection.into_http_response()),
+        };
+
+        // Make sure the deployment exists and the cursor can be used
+        // before we commit to a streaming response
+        let events = self
+            .graphql_runner
+            .cheap_clone()
+            .entity_feed(target.clone(), cursor)
+            .await
+            .map_err(|e| GraphQLServerError::ClientError(e.to_string()))?;
+
+        let (mut sender, body) = Body::channel();
+        let runner = self.graphql_runner.cheap_clone();
+        let logger = self.logger.cheap_clone();
+        graph::spawn(async move {
+            let mut events = events;
+            let mut cursor = cursor;
+            loop {
+                let caught_up = events.is_empty();
+                for event in events {
+                    // Every change that the feed sends counts as one unit
+                    // of complexity
+                    if let (Some(client), FeedEvent::Changes { changes, .. }) = (&client, &event) {
+                        client.charge(changes.len() as u64);
+                    }
+                    let message = format!(
+                        "id: {}\nevent: {}\ndata: {}\n\n",
+                        event.cursor(),
+                        event.name(),
+                        serde_json::to_string(&event).unwrap()
+                    );
+                    if sender.send_data(message.into()).await.is_err() {
+                        // The client went away
+                        return;
+                    }
+                    cursor = Some(event.cursor());
+                }
+                // Stop the feed once the client has used up its budget; it
+                // can reconnect with the last cursor when it has budget again
+                if client.as_ref().map_or(false, |client| client.over_budget()) {
+                    return;
+                }
+                if caught_up {
+                    graph::tokio::time::sleep(ENV_VARS.graphql.entity_feed_poll_interval).await;
+                }
+                events = match runner
+                    .cheap_clone()
+                    .entity_feed(target.clone(), cursor)
+                    .await
+                {
+                    Ok(events) => events,
+                    Err(e) => {
+                        warn!(logger, "Stopping entity change feed"; "error" => e.to_string());
+                        return;
+                    }
+                };
+            }
+        });
+
+        Ok(Response::builder()
+            .status(200)
+            .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+            .header(CONTENT_TYPE, "text/event-stream")
+            .header(header::CACHE_CONTROL, "no-cache")
+            .body(body)
+            .unwrap())
+    }
+
     // Handles OPTIONS requests
     fn handle_graphql_options(&self, _request: Request<Body>) -> GraphQLServiceResponse {
         async {
@@ -383,6 +722,19 @@ where
                 self.handle_graphql_query_by_id(subgraph_id.to_owned(), req)
             }
             (Method::OPTIONS, ["subgraphs", "id", _]) => self.handle_graphql_options(req),
+            (Method::POST, &["subgraphs", "id", subgraph_id, "sql"]) => {
+                self.handle_sql_query_by_id(subgraph_id.to_owned(), req)
+            }
+            (Method::OPTIONS, ["subgraphs", "id", _, "sql"]) => self.handle_graphql_options(req),
+            (Method::POST, &["subgraphs", "id", subgraph_id, "explain"]) => {
+                self.handle_explain_query_by_id(subgraph_id.to_owned(), req)
+            }
+            (Method::OPTIONS, ["subgraphs", "id", _, "explain"]) => {
+                self.handle_graphql_options(req)
+            }
+            (Method::GET, &["subgraphs", "id", subgraph_id, "changes"]) => {
+                self.handle_entity_feed_by_id(subgraph_id.to_owned(), req)
+            }
             (Method::POST, path @ ["subgraphs", "name", ..]) => {
                 let subgraph_name = filter_and_join_segments(&path[2..]);
                 self.handle_graphql_query_by_name(subgraph_name, req)
@@ -471,7 +823,10 @@ mod tests {
     use hyper::service::Service;
     use hyper::{Body, Method, Request};
 
-    use graph::data::query::{QueryResults, QueryTarget};
+    use graph::data::query::{
+        FeedCursor, FeedEvent, PersistedQueries, QueryPlan, QueryResults, QueryTarget,
+        SqlQueryObject, SqlQueryReq,
+    };
     use graph::prelude::*;
 
     use crate::test_utils;
@@ -523,6 +878,38 @@ mod tests {
             unreachable!();
         }
 
+        async fn run_sql_query(
+            self: Arc<Self>,
+            _req: SqlQueryReq,
+            _target: QueryTarget,
+        ) -> Result<Vec<SqlQueryObject>, QueryExecutionError> {
+            unimplemented!();
+        }
+
+        async fn entity_feed(
+            self: Arc<Self>,
+            _target: QueryTarget,
+            _cursor: Option<FeedCursor>,
+        ) -> Result<Vec<FeedEvent>, QueryExecutionError> {
+            unimplemented!();
+        }
+
+        async fn explain_query(
+            self: Arc<Self>,
+            _query: Query,
+            _target: QueryTarget,
+            _explain: bool,
+        ) -> Result<QueryPlan, Vec<QueryExecutionError>> {
+            unimplemented!();
+        }
+
+        async fn persisted_queries(
+            self: Arc<Self>,
+            _target: QueryTarget,
+        ) -> Result<Arc<PersistedQueries>, QueryExecutionError> {
+            Ok(Arc::new(PersistedQueries::default()))
+        }
+
         fn metrics(&self) -> Arc<dyn GraphQLMetrics> {
             Arc::new(TestGraphQLMetrics)
         }
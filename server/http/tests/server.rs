@@ -3,7 +3,10 @@ use hyper::{Body, Client, Request};
 use std::time::Duration;
 
 use graph::data::{
-    query::{QueryResults, QueryTarget},
+    query::{
+        FeedCursor, FeedEvent, PersistedQueries, QueryPlan, QueryResults, QueryTarget,
+        SqlQueryObject, SqlQueryReq,
+    },
     value::{Object, Word},
 };
 use graph::prelude::*;
@@ -74,6 +77,38 @@ impl GraphQlRunner for TestGraphQlRunner {
         unreachable!();
     }
 
+    async fn run_sql_query(
+        self: Arc<Self>,
+        _req: SqlQueryReq,
+        _target: QueryTarget,
+    ) -> Result<Vec<SqlQueryObject>, QueryExecutionError> {
+        unimplemented!();
+    }
+
+    async fn entity_feed(
+        self: Arc<Self>,
+        _target: QueryTarget,
+        _cursor: Option<FeedCursor>,
+    ) -> Result<Vec<FeedEvent>, QueryExecutionError> {
+        unimplemented!();
+    }
+
+    async fn explain_query(
+        self: Arc<Self>,
+        _query: Query,
+        _target: QueryTarget,
+        _explain: bool,
+    ) -> Result<QueryPlan, Vec<QueryExecutionError>> {
+        unimplemented!();
+    }
+
+    async fn persisted_queries(
+        self: Arc<Self>,
+        _target: QueryTarget,
+    ) -> Result<Arc<PersistedQueries>, QueryExecutionError> {
+        Ok(Arc::new(PersistedQueries::default()))
+    }
+
     fn metrics(&self) -> Arc<dyn GraphQLMetrics> {
         Arc::new(TestGraphQLMetrics)
     }
@@ -0,0 +1,171 @@
+use http::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use http::StatusCode;
+use hyper::service::Service;
+use hyper::{Body, Method, Request};
+use std::time::Duration;
+
+use graph::data::query::{
+    FeedCursor, FeedEvent, PersistedQueries, QueryPlan, QueryResults, QueryTarget, SqlQueryObject,
+    SqlQueryReq,
+};
+use graph::prelude::serde_json::{self, json};
+use graph::prelude::*;
+
+use graph_server_http::{GraphQLService, Limits, QueryLimiter, QueryLimitsConfig};
+
+pub struct TestGraphQLMetrics;
+
+impl GraphQLMetrics for TestGraphQLMetrics {
+    fn observe_query_execution(&self, _duration: Duration, _results: &QueryResults) {}
+    fn observe_query_parsing(&self, _duration: Duration, _results: &QueryResults) {}
+    fn observe_query_validation(&self, _duration: Duration, _id: &DeploymentHash) {}
+    fn observe_query_validation_error(&self, _error_codes: Vec<&str>, _id: &DeploymentHash) {}
+    fn observe_query_blocks_behind(&self, _blocks_behind: i32, _id: &DeploymentHash) {}
+}
+
+/// A query runner whose SQL queries return one row. The deployment
+/// `allowlisted` only executes allowlisted queries
+pub struct TestGraphQlRunner;
+
+#[async_trait]
+impl GraphQlRunner for TestGraphQlRunner {
+    async fn run_query_with_complexity(
+        self: Arc<Self>,
+        _query: Query,
+        _target: QueryTarget,
+        _complexity: Option<u64>,
+        _max_depth: Option<u8>,
+        _max_first: Option<u32>,
+        _max_skip: Option<u32>,
+    ) -> QueryResults {
+        unimplemented!();
+    }
+
+    async fn run_query(self: Arc<Self>, _query: Query, _target: QueryTarget) -> QueryResults {
+        unimplemented!();
+    }
+
+    async fn run_subscription(
+        self: Arc<Self>,
+        _subscription: Subscription,
+        _target: QueryTarget,
+    ) -> Result<SubscriptionResult, SubscriptionError> {
+        unreachable!();
+    }
+
+    async fn run_sql_query(
+        self: Arc<Self>,
+        _req: SqlQueryReq,
+        _target: QueryTarget,
+    ) -> Result<Vec<SqlQueryObject>, QueryExecutionError> {
+        Ok(vec![json!({ "id": "1" })])
+    }
+
+    async fn entity_feed(
+        self: Arc<Self>,
+        _target: QueryTarget,
+        _cursor: Option<FeedCursor>,
+    ) -> Result<Vec<FeedEvent>, QueryExecutionError> {
+        unimplemented!();
+    }
+
+    async fn explain_query(
+        self: Arc<Self>,
+        _query: Query,
+        _target: QueryTarget,
+        _explain: bool,
+    ) -> Result<QueryPlan, Vec<QueryExecutionError>> {
+        unimplemented!();
+    }
+
+    async fn persisted_queries(
+        self: Arc<Self>,
+        target: QueryTarget,
+    ) -> Result<Arc<PersistedQueries>, QueryExecutionError> {
+        let allowlist_only = match target {
+            QueryTarget::Deployment(id, _) => id.as_str() == "allowlisted",
+            QueryTarget::Name(_, _) => false,
+        };
+        Ok(Arc::new(PersistedQueries::new(vec![], allowlist_only)))
+    }
+
+    fn metrics(&self) -> Arc<dyn GraphQLMetrics> {
+        Arc::new(TestGraphQLMetrics)
+    }
+}
+
+fn service() -> GraphQLService<TestGraphQlRunner> {
+    // The SQL endpoint is only served when it is turned on, and every test
+    // turns it on before anything reads the environment
+    std::env::set_var("GRAPH_ENABLE_SQL_QUERIES", "true");
+
+    let logger = Logger::root(slog::Discard, o!());
+    let node_id = NodeId::new("test").unwrap();
+    GraphQLService::new(logger, Arc::new(TestGraphQlRunner), 8001, node_id)
+}
+
+/// Send a SQL query for deployment `id` to `service` and return the
+/// status and the body of the response
+async fn sql_query(
+    service: &mut GraphQLService<TestGraphQlRunner>,
+    id: &str,
+) -> (StatusCode, serde_json::Value) {
+    let body = json!({ "query": "select id from things" }).to_string();
+    let request = Request::builder()
+        .method(Method::POST)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, body.len())
+        .uri(format!("http://localhost:8000/subgraphs/id/{}/sql", id))
+        .body(Body::from(body))
+        .unwrap();
+
+    let response = service
+        .call(request)
+        .await
+        .expect("Should return a response");
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body = serde_json::from_slice(&body).expect("Response body is not valid JSON");
+    (status, body)
+}
+
+#[tokio::test]
+async fn sql_queries_are_refused_for_allowlist_only_deployments() {
+    let mut service = service();
+
+    let (_, response) = sql_query(&mut service, "QmOpen").await;
+    assert_eq!(json!({ "data": [{ "id": "1" }] }), response);
+
+    let (_, response) = sql_query(&mut service, "allowlisted").await;
+    assert_eq!(None, response.get("data"));
+    let message = response["errors"][0]["message"].as_str().unwrap();
+    assert!(message.contains("allowlist"), "{}", message);
+}
+
+#[tokio::test]
+async fn sql_queries_are_charged_to_the_complexity_budget() {
+    // Anonymous clients may use 2 units of complexity per minute, and
+    // every row that a SQL query returns costs one unit
+    let config = QueryLimitsConfig {
+        header: "X-Api-Key".to_string(),
+        anonymous: Limits {
+            queries_per_minute: None,
+            complexity_per_minute: Some(2),
+        },
+        keys: vec![],
+    };
+    let limiter = QueryLimiter::new(config, &MetricsRegistry::mock()).unwrap();
+    let mut service = service().with_query_limiter(Arc::new(limiter));
+
+    for _ in 0..2 {
+        let (status, response) = sql_query(&mut service, "QmOpen").await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(json!({ "data": [{ "id": "1" }] }), response);
+    }
+
+    let (status, response) = sql_query(&mut service, "QmOpen").await;
+    assert_eq!(StatusCode::TOO_MANY_REQUESTS, status);
+    assert_eq!(None, response.get("data"));
+    let message = response["errors"][0]["message"].as_str().unwrap();
+    assert!(message.contains("budget"), "{}", message);
+}
@@ -9,6 +9,7 @@ use web3::types::Address;
 use git_testament::{git_testament, CommitKind};
 use graph::blockchain::{Blockchain, BlockchainKind, BlockchainMap};
 use graph::components::store::{BlockPtrForNumber, BlockStore, QueryPermit, Store};
+use graph::components::subgraph::bisect::{self, parse_poi, PoiList, PoiSource, StorePois};
 use graph::components::versions::VERSIONS;
 use graph::data::graphql::{object, IntoValue, ObjectOrInterface, ValueMap};
 use graph::data::subgraph::{status, DeploymentFeatures};
@@ -55,6 +56,28 @@ impl TryFromValue for PublicProofOfIndexingRequest {
     }
 }
 
+/// A proof of indexing that another indexer reported for a block
+#[derive(Clone, Debug)]
+struct ReferenceProofOfIndexing {
+    pub block: BlockPtr,
+    pub proof_of_indexing: [u8; 32],
+}
+
+impl TryFromValue for ReferenceProofOfIndexing {
+    fn try_from_value(value: &r::Value) -> Result<Self, Error> {
+        match value {
+            r::Value::Object(o) => Ok(Self {
+                block: o.get_required::<BlockPtr>("block")?,
+                proof_of_indexing: parse_poi(&o.get_required::<String>("proofOfIndexing")?)?,
+            }),
+            _ => Err(anyhow!(
+                "Cannot parse non-object value as ReferenceProofOfIndexing: {:?}",
+                value
+            )),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 struct Version {
     version: String,
@@ -387,6 +410,100 @@ impl<S: Store> IndexNodeResolver<S> {
         Ok(poi)
     }
 
+    async fn resolve_proof_of_indexing_digests(
+        &self,
+        field: &a::Field,
+    ) -> Result<r::Value, QueryExecutionError> {
+        let deployment_id = field
+            .get_required::<DeploymentHash>("subgraph")
+            .expect("Valid subgraph required");
+
+        let block_number = field
+            .get_required::<BlockNumber>("blockNumber")
+            .expect("Valid blockNumber required");
+
+        let digests = self
+            .store
+            .get_poi_digests(&deployment_id, block_number)
+            .await?;
+
+        Ok(match digests {
+            Some(digests) => r::Value::List(
+                digests
+                    .into_iter()
+                    .map(|(causality_region, digest)| {
+                        object! {
+                            __typename: "ProofOfIndexingDigest",
+                            causalityRegion: causality_region,
+                            digest: format!("0x{}", hex::encode(digest)),
+                        }
+                    })
+                    .collect(),
+            ),
+            None => r::Value::Null,
+        })
+    }
+
+    async fn resolve_proof_of_indexing_bisect(
+        &self,
+        field: &a::Field,
+    ) -> Result<r::Value, QueryExecutionError> {
+        let deployment_id = field
+            .get_required::<DeploymentHash>("subgraph")
+            .expect("Valid subgraph required");
+
+        let reference = field
+            .get_required::<Vec<ReferenceProofOfIndexing>>("reference")
+            .expect("valid reference required, validation should have caught this");
+
+        // Only the blocks that the reference has are compared, so that the
+        // search stays within the blocks the reference covers
+        let (start, end) = match (
+            reference.iter().map(|poi| poi.block.number).min(),
+            reference.iter().map(|poi| poi.block.number).max(),
+        ) {
+            (Some(start), Some(end)) => (start, end),
+            _ => return Ok(r::Value::Null),
+        };
+        let reference = PoiList::new(
+            reference
+                .into_iter()
+                .map(|poi| (poi.block, poi.proof_of_indexing))
+                .collect(),
+        );
+        let local = StorePois::new(self.store.clone(), deployment_id, self);
+
+        let divergence = match bisect::bisect(&local, &reference, start, end).await? {
+            Some(divergence) => divergence,
+            None => return Ok(r::Value::Null),
+        };
+
+        let digests = local
+            .digests(divergence.first_diverging.number)
+            .await?
+            .map(|digests| {
+                digests
+                    .into_iter()
+                    .map(|(causality_region, digest)| {
+                        object! {
+                            __typename: "ProofOfIndexingDigest",
+                            causalityRegion: causality_region,
+                            digest: format!("0x{}", hex::encode(digest)),
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            });
+
+        Ok(object! {
+            __typename: "ProofOfIndexingDivergence",
+            lastAgreeingBlock: divergence.last_agreeing,
+            block: divergence.first_diverging.into_value(),
+            proofOfIndexing: format!("0x{}", hex::encode(divergence.local)),
+            referenceProofOfIndexing: format!("0x{}", hex::encode(divergence.reference)),
+            digests: digests,
+        })
+    }
+
     async fn resolve_public_proofs_of_indexing(
         &self,
         field: &a::Field,
@@ -859,6 +976,9 @@ impl<S: Store> Resolver for IndexNodeResolver<S> {
             (None, "PublicProofOfIndexingResult", "publicProofsOfIndexing") => {
                 self.resolve_public_proofs_of_indexing(field).await
             }
+            (None, "ProofOfIndexingDigest", "proofOfIndexingDigests") => {
+                self.resolve_proof_of_indexing_digests(field).await
+            }
 
             // Resolve fields of `Object` values (e.g. the `chains` field of `ChainIndexingStatus`)
             (value, _, _) => Ok(value.unwrap_or(r::Value::Null)),
@@ -881,6 +1001,7 @@ impl<S: Store> Resolver for IndexNodeResolver<S> {
                 self.resolve_indexing_status_for_version(field, false)
             }
             (None, "subgraphFeatures") => self.resolve_subgraph_features(field).await,
+            (None, "proofOfIndexingBisect") => self.resolve_proof_of_indexing_bisect(field).await,
             (None, "entityChangesInBlock") => self.resolve_entity_changes_in_block(field),
             // The top-level `subgraphVersions` field
             (None, "apiVersions") => self.resolve_api_versions(field),
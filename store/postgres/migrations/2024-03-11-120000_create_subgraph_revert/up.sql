@@ -0,0 +1,12 @@
+create table subgraphs.subgraph_revert(
+  id              bigserial primary key,
+  deployment      int not null
+                  references subgraphs.subgraph_deployment
+                  on delete cascade,
+  block_number    int not null,
+  block_hash      bytea not null,
+  created_at      timestamptz not null default now()
+);
+
+create index subgraph_revert_deployment_id
+    on subgraphs.subgraph_revert(deployment, id);
@@ -0,0 +1,2 @@
+drop table subgraphs.query_allowlist;
+drop table subgraphs.persisted_query;
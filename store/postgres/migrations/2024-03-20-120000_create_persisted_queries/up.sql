@@ -0,0 +1,12 @@
+create table subgraphs.persisted_query(
+  deployment      text not null,
+  hash            text not null,
+  query           text not null,
+  created_at      timestamptz not null default now(),
+  primary key(deployment, hash)
+);
+
+create table subgraphs.query_allowlist(
+  deployment      text primary key,
+  created_at      timestamptz not null default now()
+);
@@ -0,0 +1 @@
+drop table subgraphs.query_workload;
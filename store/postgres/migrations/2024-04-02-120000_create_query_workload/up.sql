@@ -0,0 +1,13 @@
+create table subgraphs.query_workload(
+  deployment      int not null
+                  references subgraphs.subgraph_deployment
+                  on delete cascade,
+  table_name      text not null,
+  filter_columns  text[] not null,
+  order_columns   text[] not null,
+  query_count     int8 not null,
+  total_ms        int8 not null,
+  slow_count      int8 not null,
+  last_seen       timestamptz not null default now(),
+  primary key(deployment, table_name, filter_columns, order_columns)
+);
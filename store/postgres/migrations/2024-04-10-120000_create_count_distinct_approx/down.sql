@@ -0,0 +1,5 @@
+set search_path = public;
+drop aggregate count_distinct_approx(anyelement);
+drop function hll_estimate(int2[]);
+drop function hll_merge(int2[], int2[]);
+drop function hll_add(int2[], anyelement);
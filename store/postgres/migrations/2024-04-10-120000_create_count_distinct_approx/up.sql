@@ -0,0 +1,66 @@
+-- An approximate count of distinct values using HyperLogLog, used by the
+-- `count_distinct` aggregation function. Values are hashed with
+-- `hashtextextended`; the first 12 bits of the hash select one of 4096
+-- registers, and each register keeps the largest position of the first 1
+-- bit in the remaining 52 bits of the hashes that were assigned to it. The
+-- standard error of the estimate is about 1.6%
+set search_path = public;
+
+create or replace function hll_add(registers int2[], value anyelement)
+  returns int2[]
+  language plpgsql immutable parallel safe as
+$$
+declare
+  hash bit(64);
+  register int;
+  rank int2;
+begin
+  if registers is null then
+    registers := array_fill(0::int2, array[4096]);
+  end if;
+  if value is null then
+    return registers;
+  end if;
+  hash := hashtextextended(value::text, 0)::bit(64);
+  register := substring(hash from 1 for 12)::int + 1;
+  rank := position(B'1' in substring(hash from 13));
+  if rank = 0 then
+    rank := 53;
+  end if;
+  if registers[register] < rank then
+    registers[register] := rank;
+  end if;
+  return registers;
+end;
+$$;
+
+create or replace function hll_merge(a int2[], b int2[])
+  returns int2[]
+  language sql immutable strict parallel safe as
+'select array_agg(greatest(x, y) order by i)
+   from unnest(a, b) with ordinality as r(x, y, i)';
+
+-- Use linear counting when the raw estimate is small and registers are
+-- still empty, since the raw estimate is badly biased for small counts
+create or replace function hll_estimate(registers int2[])
+  returns int8
+  language sql immutable parallel safe as
+'select coalesce((
+   select case when raw <= 2.5 * 4096 and zeros > 0
+               then round(4096 * ln(4096.0 / zeros))
+               else round(raw::numeric) end::int8
+     from (select 0.7213 / (1 + 1.079 / 4096) * 4096 * 4096
+                    / sum(power(2, -rank::float8)) as raw,
+                  count(*) filter (where rank = 0) as zeros
+             from unnest(registers) as r(rank)) est), 0)';
+
+create aggregate count_distinct_approx (anyelement) (
+  sfunc       = hll_add,
+  stype       = int2[],
+  finalfunc   = hll_estimate,
+  combinefunc = hll_merge,
+  parallel    = safe
+);
+
+comment on aggregate count_distinct_approx(anyelement) is
+'Estimate the number of distinct non-null values with HyperLogLog';
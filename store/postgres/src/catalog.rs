@@ -18,7 +18,7 @@ use std::time::Duration;
 
 use graph::prelude::anyhow::anyhow;
 use graph::{
-    data::subgraph::schema::POI_TABLE,
+    data::subgraph::schema::{FEED_TABLE, POI_HISTORY_TABLE, POI_TABLE},
     prelude::{lazy_static, StoreError},
 };
 
@@ -175,6 +175,12 @@ pub struct Catalog {
     text_columns: HashMap<String, HashSet<String>>,
 
     pub use_poi: bool,
+    /// Whether the digests of the proof of indexing are recorded for
+    /// every block in `poi_history$`
+    pub use_poi_history: bool,
+    /// Whether the changes to entities are recorded in `feed$` for the
+    /// entity change feed
+    pub use_entity_feed: bool,
     /// Whether `bytea` columns are indexed with just a prefix (`true`) or
     /// in their entirety. This influences both DDL generation and how
     /// queries are generated
@@ -198,12 +204,16 @@ impl Catalog {
     ) -> Result<Self, StoreError> {
         let text_columns = get_text_columns(conn, &site.namespace)?;
         let use_poi = supports_proof_of_indexing(conn, &site.namespace)?;
+        let use_poi_history = has_poi_history(conn, &site.namespace)?;
+        let use_entity_feed = has_entity_feed(conn, &site.namespace)?;
         let has_minmax_multi_ops = has_minmax_multi_ops(conn)?;
 
         Ok(Catalog {
             site,
             text_columns,
             use_poi,
+            use_poi_history,
+            use_entity_feed,
             use_bytea_prefix,
             entities_with_causality_region: entities_with_causality_region.into_iter().collect(),
             has_minmax_multi_ops,
@@ -223,6 +233,8 @@ impl Catalog {
             text_columns: HashMap::default(),
             // DDL generation creates a POI table
             use_poi: true,
+            use_poi_history: false,
+            use_entity_feed: false,
             // DDL generation creates indexes for prefixes of bytes columns
             // see: attr-bytea-prefix
             use_bytea_prefix: true,
@@ -242,6 +254,8 @@ impl Catalog {
             site,
             text_columns: HashMap::default(),
             use_poi: false,
+            use_poi_history: false,
+            use_entity_feed: false,
             use_bytea_prefix: true,
             entities_with_causality_region,
             has_minmax_multi_ops: false,
@@ -331,6 +345,27 @@ pub fn supports_proof_of_indexing(
     table_exists(conn, namespace.as_str(), &POI_TABLE_NAME)
 }
 
+pub fn has_poi_history(
+    conn: &diesel::pg::PgConnection,
+    namespace: &Namespace,
+) -> Result<bool, StoreError> {
+    lazy_static! {
+        static ref POI_HISTORY_TABLE_NAME: SqlName =
+            SqlName::verbatim(POI_HISTORY_TABLE.to_owned());
+    }
+    table_exists(conn, namespace.as_str(), &POI_HISTORY_TABLE_NAME)
+}
+
+pub fn has_entity_feed(
+    conn: &diesel::pg::PgConnection,
+    namespace: &Namespace,
+) -> Result<bool, StoreError> {
+    lazy_static! {
+        static ref FEED_TABLE_NAME: SqlName = SqlName::verbatim(FEED_TABLE.to_owned());
+    }
+    table_exists(conn, namespace.as_str(), &FEED_TABLE_NAME)
+}
+
 pub fn current_servers(conn: &PgConnection) -> Result<Vec<String>, StoreError> {
     #[derive(QueryableByName)]
     struct Srv {
@@ -697,6 +732,50 @@ pub(crate) fn drop_index(
     Ok(())
 }
 
+/// How often an index has been used since the statistics for it were
+/// last reset, and how much space it takes up
+#[derive(Clone, QueryableByName)]
+pub(crate) struct IndexUsage {
+    #[sql_type = "Text"]
+    pub table_name: String,
+    #[sql_type = "Text"]
+    pub index_name: String,
+    #[sql_type = "Text"]
+    pub defn: String,
+    #[sql_type = "BigInt"]
+    pub scans: i64,
+    #[sql_type = "BigInt"]
+    pub size: i64,
+}
+
+/// Return the usage of all indexes in `namespace`. Postgres tracks index
+/// usage separately on each server, so that this only reflects queries
+/// that ran on the server for `conn`; use `workload::add_usage` to combine
+/// the usage from several servers
+pub(crate) fn index_usage(
+    conn: &PgConnection,
+    namespace: &Namespace,
+) -> Result<Vec<IndexUsage>, StoreError> {
+    let query = "
+        select
+            s.relname as table_name,
+            s.indexrelname as index_name,
+            i.indexdef as defn,
+            s.idx_scan as scans,
+            pg_relation_size(s.indexrelid) as size
+        from
+            pg_stat_user_indexes s
+            join pg_indexes i
+              on i.schemaname = s.schemaname and i.indexname = s.indexrelname
+        where
+            s.schemaname = $1
+        order by s.relname, s.indexrelname";
+    sql_query(query)
+        .bind::<Text, _>(namespace.as_str())
+        .load::<IndexUsage>(conn)
+        .map_err(StoreError::from)
+}
+
 pub fn stats(conn: &PgConnection, site: &Site) -> Result<Vec<VersionStats>, StoreError> {
     #[derive(Queryable, QueryableByName)]
     pub struct DbStats {
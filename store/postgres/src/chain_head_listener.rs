@@ -20,8 +20,11 @@ use crate::{
 use graph::blockchain::ChainHeadUpdateListener as ChainHeadUpdateListenerTrait;
 use graph::prelude::serde::{Deserialize, Serialize};
 use graph::prelude::serde_json::{self, json};
-use graph::prelude::tokio::sync::{mpsc::Receiver, watch};
-use graph::prelude::{crit, debug, o, CheapClone, Logger, ENV_VARS};
+use graph::prelude::tokio::sync::{broadcast, mpsc::Receiver, watch};
+use graph::prelude::{crit, debug, o, BlockNumber, CheapClone, Logger, ENV_VARS};
+
+/// How many chain head updates `head_updates` subscribers can fall behind
+const HEAD_UPDATES_CAPACITY: usize = 64;
 
 lazy_static! {
     pub static ref CHANNEL_NAME: SafeChannelName =
@@ -83,6 +86,8 @@ struct ChainHeadUpdate {
 pub struct ChainHeadUpdateListener {
     /// Update watchers keyed by network.
     watchers: Arc<TimedRwLock<BTreeMap<String, Watcher>>>,
+    /// The network and number of every chain head update
+    heads: broadcast::Sender<(String, BlockNumber)>,
     _listener: NotificationListener,
 }
 
@@ -112,6 +117,7 @@ impl ChainHeadUpdateListener {
             BTreeMap::new(),
             "chain_head_listener_watchers",
         ));
+        let (heads, _) = broadcast::channel(HEAD_UPDATES_CAPACITY);
 
         Self::listen(
             logger,
@@ -119,11 +125,13 @@ impl ChainHeadUpdateListener {
             &mut listener,
             receiver,
             watchers.cheap_clone(),
+            heads.clone(),
             counter,
         );
 
         ChainHeadUpdateListener {
             watchers,
+            heads,
 
             // We keep the listener around to tie its stream's lifetime to
             // that of the chain head update listener and prevent it from
@@ -138,6 +146,7 @@ impl ChainHeadUpdateListener {
         listener: &mut NotificationListener,
         mut receiver: Receiver<JsonNotification>,
         watchers: Arc<TimedRwLock<BTreeMap<String, Watcher>>>,
+        heads: broadcast::Sender<(String, BlockNumber)>,
         counter: CounterVec,
     ) {
         // Process chain head updates in a dedicated task
@@ -176,12 +185,22 @@ impl ChainHeadUpdateListener {
                 {
                     watcher.send();
                 }
+
+                // It is fine if nobody is interested in the chain head
+                let number = update.head_block_number as BlockNumber;
+                heads.send((update.network_name, number)).ok();
             }
         });
 
         // We're ready, start listening to chain head updates
         listener.start();
     }
+
+    /// Subscribe to the network and block number of every chain head update.
+    /// Unlike `subscribe`, this does not poll when there are no updates
+    pub fn head_updates(&self) -> broadcast::Receiver<(String, BlockNumber)> {
+        self.heads.subscribe()
+    }
 }
 
 impl ChainHeadUpdateListenerTrait for ChainHeadUpdateListener {
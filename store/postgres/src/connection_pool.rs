@@ -30,7 +30,9 @@ use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use std::{collections::HashMap, sync::RwLock};
 
+use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
 use postgres::config::{Config, Host};
+use postgres_openssl::MakeTlsConnector;
 
 use crate::primary::{self, NAMESPACE_PUBLIC};
 use crate::{advisory_lock, catalog};
@@ -476,6 +478,14 @@ impl ConnectionPool {
         ForeignServer::new(pool.shard.clone(), &pool.postgres_url).map_err(|e| e.into())
     }
 
+    /// Open a new connection to the database that is not managed by this
+    /// pool and that uses the `postgres` crate rather than Diesel. This is
+    /// only needed for operations that Diesel does not support, like `copy
+    /// .. from stdin`
+    pub(crate) fn raw_client(&self) -> Result<postgres::Client, StoreError> {
+        self.get_ready()?.raw_client()
+    }
+
     /// Check that we can connect to the database
     pub fn check(&self) -> bool {
         true
@@ -967,6 +977,21 @@ impl PoolInner {
         ForeignServer::new(self.shard.clone(), &self.postgres_url).map_err(|e| e.into())
     }
 
+    pub(crate) fn raw_client(&self) -> Result<postgres::Client, StoreError> {
+        let mut builder = SslConnector::builder(SslMethod::tls())
+            .map_err(|e| anyhow!("unable to create SslConnector builder: {}", e))?;
+        builder.set_verify(SslVerifyMode::NONE);
+        let connector = MakeTlsConnector::new(builder.build());
+
+        postgres::Client::connect(&self.postgres_url, connector).map_err(|e| {
+            StoreError::Unknown(anyhow!(
+                "failed to connect to the database for shard {}: {}",
+                self.shard,
+                e
+            ))
+        })
+    }
+
     /// Check that we can connect to the database
     pub fn check(&self) -> bool {
         self.pool
@@ -201,6 +201,15 @@ table! {
     }
 }
 
+table! {
+    subgraphs.subgraph_revert (id) {
+        id -> BigInt,
+        deployment -> Integer,
+        block_number -> Integer,
+        block_hash -> Binary,
+    }
+}
+
 allow_tables_to_appear_in_same_query!(subgraph_deployment, subgraph_error, subgraph_manifest);
 
 /// Look up the graft point for the given subgraph in the database and
@@ -552,6 +561,61 @@ pub fn revert_block_ptr(
         .map_err(|e| e.into())
 }
 
+/// Record that `site` was reverted to `ptr`. The entity change feed uses
+/// these records to tell consumers which changes they need to undo
+pub(crate) fn record_revert(
+    conn: &PgConnection,
+    site: &Site,
+    ptr: &BlockPtr,
+) -> Result<(), StoreError> {
+    use subgraph_revert as r;
+
+    insert_into(r::table)
+        .values((
+            r::deployment.eq(site.id),
+            r::block_number.eq(ptr.number),
+            r::block_hash.eq(ptr.hash_slice()),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Return the reverts of `site` whose id is bigger than `after`, in the
+/// order in which they happened
+pub(crate) fn reverts_since(
+    conn: &PgConnection,
+    site: &Site,
+    after: i64,
+) -> Result<Vec<(i64, BlockPtr)>, StoreError> {
+    use subgraph_revert as r;
+
+    r::table
+        .filter(r::deployment.eq(site.id))
+        .filter(r::id.gt(after))
+        .select((r::id, r::block_number, r::block_hash))
+        .order_by(r::id)
+        .load::<(i64, BlockNumber, Vec<u8>)>(conn)?
+        .into_iter()
+        .map(|(id, number, hash)| {
+            BlockPtr::try_from((hash.as_slice(), number as i64))
+                .map(|ptr| (id, ptr))
+                .map_err(StoreError::from)
+        })
+        .collect()
+}
+
+/// Return the id of the last revert of `site`, or 0 if it was never
+/// reverted
+pub(crate) fn last_revert(conn: &PgConnection, site: &Site) -> Result<i64, StoreError> {
+    use subgraph_revert as r;
+
+    let id = r::table
+        .filter(r::deployment.eq(site.id))
+        .select(diesel::dsl::max(r::id))
+        .first::<Option<i64>>(conn)?;
+    Ok(id.unwrap_or(0))
+}
+
 pub fn block_ptr(conn: &PgConnection, id: &DeploymentHash) -> Result<Option<BlockPtr>, StoreError> {
     use subgraph_deployment as d;
 
@@ -5,14 +5,15 @@ use diesel::prelude::*;
 use diesel::r2d2::{ConnectionManager, PooledConnection};
 use graph::anyhow::Context;
 use graph::blockchain::block_stream::FirehoseCursor;
-use graph::blockchain::BlockTime;
+use graph::blockchain::{BlockHash, BlockTime};
 use graph::components::store::write::RowGroup;
 use graph::components::store::{
     Batch, DerivedEntityQuery, PrunePhase, PruneReporter, PruneRequest, PruningStrategy,
     QueryPermit, StoredDynamicDataSource, VersionStats,
 };
 use graph::components::versions::VERSIONS;
-use graph::data::query::Trace;
+use graph::data::query::{FeedCursor, FeedEvent, SqlPlan, SqlQueryObject, Trace};
+use graph::data::store::scalar::Bytes;
 use graph::data::store::{Id, IdList};
 use graph::data::subgraph::{status, SPEC_VERSION_0_0_6};
 use graph::data_source::CausalityRegion;
@@ -30,6 +31,7 @@ use std::collections::{BTreeMap, HashMap};
 use std::convert::Into;
 use std::ops::Bound;
 use std::ops::Deref;
+use std::path::Path;
 use std::str::FromStr;
 use std::sync::{atomic::AtomicUsize, Arc, Mutex};
 use std::time::{Duration, Instant};
@@ -40,8 +42,8 @@ use graph::constraint_violation;
 use graph::data::subgraph::schema::{DeploymentCreate, SubgraphError};
 use graph::prelude::{
     anyhow, debug, info, o, warn, web3, AttributeNames, BlockNumber, BlockPtr, CheapClone,
-    DeploymentHash, DeploymentState, Entity, EntityQuery, Error, Logger, QueryExecutionError,
-    StopwatchMetrics, StoreError, StoreEvent, UnfailOutcome, Value, ENV_VARS,
+    DeploymentHash, DeploymentState, Entity, EntityAggregateQuery, EntityQuery, Error, Logger,
+    QueryExecutionError, StopwatchMetrics, StoreError, StoreEvent, UnfailOutcome, Value, ENV_VARS,
 };
 use graph::schema::{ApiSchema, EntityKey, EntityType, InputSchema};
 use web3::types::Address;
@@ -51,9 +53,11 @@ use crate::deployment::{self, OnSync};
 use crate::detail::ErrorDetail;
 use crate::dynds::DataSourcesTable;
 use crate::primary::DeploymentId;
+use crate::relational::dump::DumpMetadata;
 use crate::relational::index::{CreateIndex, Method};
-use crate::relational::{Layout, LayoutCache, SqlName, Table};
+use crate::relational::{Catalog, Layout, LayoutCache, SqlName, Table};
 use crate::relational_queries::FromEntityData;
+use crate::workload::{self, IndexAdvice, QueryShape, QueryWorkload};
 use crate::{advisory_lock, catalog, retry};
 use crate::{connection_pool::ConnectionPool, detail};
 use crate::{dynds, primary::Site};
@@ -114,6 +118,11 @@ pub struct StoreInner {
     pub(crate) layout_cache: LayoutCache,
 
     prune_handles: Mutex<HashMap<DeploymentId, PruneHandle>>,
+
+    /// The shapes of the queries that were run since they were last
+    /// written to the database; only used if
+    /// `GRAPH_STORE_RECORD_QUERY_WORKLOAD` is set
+    workload: QueryWorkload,
 }
 
 /// Storage of the data for individual deployments. Each `DeploymentStore`
@@ -170,6 +179,7 @@ impl DeploymentStore {
             subgraph_cache: Mutex::new(LruCache::with_capacity(100)),
             layout_cache: LayoutCache::new(ENV_VARS.store.query_stats_refresh_interval),
             prune_handles: Mutex::new(HashMap::new()),
+            workload: QueryWorkload::default(),
         };
 
         DeploymentStore(Arc::new(store))
@@ -273,13 +283,81 @@ impl DeploymentStore {
         site: Arc<Site>,
         query: EntityQuery,
     ) -> Result<(Vec<T>, Trace), QueryExecutionError> {
+        let layout = self.layout(conn, site.cheap_clone())?;
+
+        let logger = query
+            .logger
+            .cheap_clone()
+            .unwrap_or_else(|| self.logger.cheap_clone());
+        if !ENV_VARS.store.record_query_workload {
+            return layout.query(&logger, conn, query);
+        }
+
+        let shapes = QueryShape::for_query(&layout, &query);
+        let start = Instant::now();
+        let res = layout.query(&logger, conn, query);
+        // Queries that failed, e.g., because they timed out, are recorded,
+        // too since they are often the ones that need an index the most
+        self.workload.record(&site, shapes, start.elapsed());
+        res
+    }
+
+    pub(crate) fn execute_aggregate_query(
+        &self,
+        conn: &PgConnection,
+        site: Arc<Site>,
+        query: EntityAggregateQuery,
+    ) -> Result<(Vec<Vec<Value>>, Trace), QueryExecutionError> {
         let layout = self.layout(conn, site)?;
 
         let logger = query
             .logger
             .cheap_clone()
             .unwrap_or_else(|| self.logger.cheap_clone());
-        layout.query(&logger, conn, query)
+        layout.aggregate_query(&logger, conn, query)
+    }
+
+    pub(crate) fn explain_query(
+        &self,
+        conn: &PgConnection,
+        site: Arc<Site>,
+        query: EntityQuery,
+        explain: bool,
+    ) -> Result<SqlPlan, QueryExecutionError> {
+        let layout = self.layout(conn, site)?;
+        layout.explain_query(conn, query, explain)
+    }
+
+    pub(crate) fn explain_aggregate_query(
+        &self,
+        conn: &PgConnection,
+        site: Arc<Site>,
+        query: EntityAggregateQuery,
+        explain: bool,
+    ) -> Result<SqlPlan, QueryExecutionError> {
+        let layout = self.layout(conn, site)?;
+        layout.explain_aggregate_query(conn, query, explain)
+    }
+
+    pub(crate) fn execute_sql(
+        &self,
+        conn: &PgConnection,
+        site: Arc<Site>,
+        sql: &str,
+        block: BlockNumber,
+    ) -> Result<Vec<SqlQueryObject>, QueryExecutionError> {
+        let layout = self.layout(conn, site)?;
+        layout.execute_sql(conn, sql, block)
+    }
+
+    pub(crate) fn entity_feed(
+        &self,
+        conn: &PgConnection,
+        site: Arc<Site>,
+        cursor: Option<FeedCursor>,
+    ) -> Result<Vec<FeedEvent>, QueryExecutionError> {
+        let layout = self.layout(conn, site)?;
+        layout.entity_feed(conn, cursor)
     }
 
     fn check_interface_entity_uniqueness(
@@ -635,6 +713,89 @@ impl DeploymentStore {
         Ok(())
     }
 
+    pub(crate) fn dump(
+        &self,
+        site: Arc<Site>,
+        dir: &Path,
+        history: bool,
+        block: Option<BlockNumber>,
+    ) -> Result<DumpMetadata, StoreError> {
+        let conn = self.get_conn()?;
+        let layout = self.layout(&conn, site)?;
+        layout.dump(&conn, dir, history, block)
+    }
+
+    /// Create the deployment `site` from the dump in `dir`. The `site`
+    /// must have just been allocated and the deployment must not exist
+    /// yet in this shard
+    pub(crate) fn restore(
+        &self,
+        logger: &Logger,
+        site: Arc<Site>,
+        schema: &InputSchema,
+        deployment: DeploymentCreate,
+        metadata: &DumpMetadata,
+        dir: &Path,
+    ) -> Result<(), StoreError> {
+        let conn = self.get_conn()?;
+        if !site.schema_version.private_data_sources() {
+            return Err(constraint_violation!(
+                "deployment {} can not be restored since it does not use private data sources",
+                site.deployment
+            ));
+        }
+
+        let start = Instant::now();
+        let layout = conn.transaction(|| -> Result<_, StoreError> {
+            if deployment::exists(&conn, &site)? {
+                return Err(StoreError::Unknown(anyhow!(
+                    "deployment {} already exists in shard {}",
+                    site.deployment,
+                    site.shard
+                )));
+            }
+            let entities_with_causality_region =
+                deployment.manifest.entities_with_causality_region.clone();
+            deployment::create_deployment(&conn, &site, deployment, false, false)?;
+
+            let query = format!("create schema {}", &site.namespace);
+            conn.batch_execute(&query)?;
+
+            let catalog = Catalog::for_creation(
+                &conn,
+                site.cheap_clone(),
+                entities_with_causality_region.into_iter().collect(),
+            )?;
+            let layout = Layout::new(site.cheap_clone(), schema, catalog)?;
+            conn.batch_execute(&layout.as_table_ddl()?)?;
+            conn.batch_execute(&DataSourcesTable::new(site.namespace.clone()).as_ddl())?;
+            Ok(layout)
+        })?;
+        info!(logger, "Created tables"; "time_ms" => start.elapsed().as_millis());
+
+        let start = Instant::now();
+        let mut client = self.pool.raw_client()?;
+        layout.restore_data(&mut client, dir, metadata)?;
+        info!(logger, "Loaded data"; "time_ms" => start.elapsed().as_millis());
+
+        let start = Instant::now();
+        layout.restore_indexes(&conn, metadata)?;
+        info!(logger, "Created indexes"; "time_ms" => start.elapsed().as_millis());
+
+        conn.transaction(|| -> Result<(), StoreError> {
+            deployment::set_entity_count(&conn, &site, &layout.count_query)?;
+            deployment::set_earliest_block(&conn, &site, metadata.earliest_block_number)?;
+
+            for entity_name in layout.tables.keys() {
+                self.analyze_with_conn(site.cheap_clone(), entity_name.as_str(), &conn)?;
+            }
+
+            // Setting the block pointer is the last step and signals that
+            // the deployment is ready to continue indexing
+            deployment::forward_block_ptr(&conn, &site.deployment, &metadata.head()?)
+        })
+    }
+
     pub(crate) fn stats_targets(
         &self,
         site: Arc<Site>,
@@ -735,21 +896,34 @@ impl DeploymentStore {
                 }
             }
 
-            // This might take a long time.
-            conn.execute(&sql)?;
-            // check if the index creation was successfull
-            let index_is_valid =
-                catalog::check_index_is_valid(conn, schema_name.as_str(), &index_name)?;
-            if index_is_valid {
-                Ok(())
-            } else {
-                // Index creation falied. We should drop the index before returning.
-                let drop_index_sql =
-                    format!("drop index concurrently if exists {schema_name}.{index_name}");
-                conn.execute(&drop_index_sql)?;
-                Err(StoreError::Canceled)
-            }
-            .map_err(Into::into)
+            create_index_concurrently(conn, schema_name.as_str(), &index_name, &sql)
+                .map_err(Into::into)
+        })
+        .await
+    }
+
+    /// Create one of the indexes that `advise_indexes` proposed.
+    ///
+    /// This is a potentially time-consuming operation.
+    pub(crate) async fn create_index(
+        &self,
+        site: Arc<Site>,
+        index: CreateIndex,
+    ) -> Result<(), StoreError> {
+        self.with_conn(move |conn, _| {
+            let index_name = match &index {
+                CreateIndex::Parsed { name, .. } => name.clone(),
+                CreateIndex::Unknown { defn } => {
+                    return Err(
+                        constraint_violation!("can not create unparsed index `{}`", defn).into(),
+                    )
+                }
+            };
+            let sql = index
+                .to_sql(true, true)
+                .map_err(|e| StoreError::Unknown(e.into()))?;
+            create_index_concurrently(conn, site.namespace.as_str(), &index_name, &sql)
+                .map_err(Into::into)
         })
         .await
     }
@@ -789,6 +963,41 @@ impl DeploymentStore {
         .await
     }
 
+    /// Propose indexes for the deployment based on the query workload that
+    /// was recorded for it, and list the indexes that were never used on
+    /// the main server or any of the read replicas
+    pub(crate) async fn advise_indexes(&self, site: Arc<Site>) -> Result<IndexAdvice, StoreError> {
+        let store = self.clone();
+        self.with_conn(move |conn, _| {
+            let layout = store.layout(conn, site.cheap_clone())?;
+            let recorded = workload::load(conn, &site)?;
+            let mut usage = catalog::index_usage(conn, &site.namespace)?;
+            for pool in &store.read_only_pools {
+                let replica = catalog::index_usage(&pool.get()?, &site.namespace)?;
+                workload::add_usage(&mut usage, replica);
+            }
+            Ok(IndexAdvice {
+                replicas: store.read_only_pools.len(),
+                ..workload::advise(&layout, recorded, usage)
+            })
+        })
+        .await
+    }
+
+    /// Write the query workload that was recorded since the last call to
+    /// the database
+    pub(crate) async fn flush_query_workload(&self, logger: &Logger) {
+        let store = self.clone();
+        let res = self
+            .with_conn(move |conn, _| store.workload.flush(conn).map_err(Into::into))
+            .await;
+        if let Err(e) = res {
+            warn!(logger, "Writing the query workload failed";
+                  "error" => e.to_string(),
+                  "shard" => self.pool.shard.as_str());
+        }
+    }
+
     pub(crate) async fn set_account_like(
         &self,
         site: Arc<Site>,
@@ -805,6 +1014,65 @@ impl DeploymentStore {
         .await
     }
 
+    /// Start or stop recording the history of the PoI digests of the
+    /// deployment. When starting, return for how many blocks digests were
+    /// copied from the PoI table into the history
+    pub(crate) async fn set_poi_history(
+        &self,
+        site: Arc<Site>,
+        enabled: bool,
+    ) -> Result<usize, StoreError> {
+        let store = self.clone();
+        self.with_conn(move |conn, _| {
+            let layout = store.layout(conn, site.clone())?;
+            // Take the lock so that we do not race the writer, which also
+            // copies digests into the history
+            let count = deployment::with_lock(conn, &site, || {
+                conn.transaction(|| {
+                    if enabled {
+                        layout.enable_poi_history(conn)
+                    } else {
+                        layout.disable_poi_history(conn).map(|()| 0)
+                    }
+                })
+            })?;
+            // The writer only records the history if the cached layout
+            // knows about it
+            store.layout_cache.remove(&site);
+            Ok(count)
+        })
+        .await
+    }
+
+    /// Start or stop recording the entity change feed of the deployment
+    pub(crate) async fn set_entity_feed(
+        &self,
+        site: Arc<Site>,
+        enabled: bool,
+    ) -> Result<(), StoreError> {
+        let store = self.clone();
+        self.with_conn(move |conn, _| {
+            let layout = store.layout(conn, site.clone())?;
+            // Take the lock so that the feed starts exactly at the block
+            // the writer has written last
+            deployment::with_lock(conn, &site, || {
+                conn.transaction(|| {
+                    if enabled {
+                        let head = deployment::block_ptr(conn, &site.deployment)?;
+                        layout.enable_entity_feed(conn, head.as_ref())
+                    } else {
+                        layout.disable_entity_feed(conn)
+                    }
+                })
+            })?;
+            // The writer only records the feed if the cached layout knows
+            // about it
+            store.layout_cache.remove(&site);
+            Ok(())
+        })
+        .await
+    }
+
     pub(crate) fn set_history_blocks(
         &self,
         site: &Site,
@@ -937,19 +1205,22 @@ impl DeploymentStore {
         .map_err(Into::into)
     }
 
-    pub(crate) async fn get_proof_of_indexing(
+    /// Load the digests of the proof of indexing for each causality region
+    /// as of `block`. Return `None` if the deployment does not support
+    /// proofs of indexing or has not reached `block` yet. For deployments
+    /// that failed deterministically, blocks past the deployment head
+    /// are treated as the block at which the deployment failed; the block
+    /// that was actually used is returned with the digests
+    async fn poi_digests(
         &self,
         site: Arc<Site>,
-        indexer: &Option<Address>,
         block: BlockPtr,
-    ) -> Result<Option<[u8; 32]>, StoreError> {
-        let indexer = *indexer;
-        let site2 = site.cheap_clone();
+    ) -> Result<Option<(HashMap<Id, Bytes>, BlockPtr)>, StoreError> {
         let store = self.cheap_clone();
         let info = self.subgraph_info(&site)?;
         let poi_digest = info.input.poi_digest();
 
-        let entities: Option<(Vec<Entity>, BlockPtr)> = self
+        let digests: Option<(HashMap<Id, Bytes>, BlockPtr)> = self
             .with_conn(move |conn, cancel| {
                 let site = site.clone();
                 cancel.check_cancel()?;
@@ -989,6 +1260,16 @@ impl DeploymentStore {
                         };
                     };
 
+                    // Deployments that record the history of their digests
+                    // can look them up without querying the PoI table
+                    if let Some(digests) = layout.poi_history(conn, block_ptr.number)? {
+                        let by_causality_region = digests
+                            .into_iter()
+                            .map(|(region, digest)| (Id::String(region.into()), digest.into()))
+                            .collect();
+                        return Ok(Some((by_causality_region, block_ptr)));
+                    }
+
                     let query = EntityQuery::new(
                         site.deployment.cheap_clone(),
                         block_ptr.number,
@@ -1002,36 +1283,48 @@ impl DeploymentStore {
                         .map(|(entities, _)| entities)
                         .map_err(anyhow::Error::from)?;
 
-                    Ok(Some((entities, block_ptr)))
+                    let by_causality_region = entities
+                        .into_iter()
+                        .map(|e| {
+                            let causality_region = e.id();
+                            let digest = match e.get(poi_digest.as_str()) {
+                                Some(Value::Bytes(b)) => Ok(b.clone()),
+                                other => Err(anyhow::anyhow!(
+                                    "Entity has non-bytes digest attribute: {:?}",
+                                    other
+                                )),
+                            }?;
+
+                            Ok((causality_region, digest))
+                        })
+                        .collect::<Result<HashMap<_, _>, anyhow::Error>>()?;
+
+                    Ok(Some((by_causality_region, block_ptr)))
                 })
                 .map_err(Into::into)
             })
             .await?;
 
-        let (entities, block_ptr) = if let Some((entities, bp)) = entities {
-            (entities, bp)
-        } else {
-            return Ok(None);
-        };
+        Ok(digests)
+    }
 
-        let mut by_causality_region = entities
-            .into_iter()
-            .map(|e| {
-                let causality_region = e.id();
-                let digest = match e.get(poi_digest.as_str()) {
-                    Some(Value::Bytes(b)) => Ok(b.clone()),
-                    other => Err(anyhow::anyhow!(
-                        "Entity has non-bytes digest attribute: {:?}",
-                        other
-                    )),
-                }?;
-
-                Ok((causality_region, digest))
-            })
-            .collect::<Result<HashMap<_, _>, anyhow::Error>>()?;
+    pub(crate) async fn get_proof_of_indexing(
+        &self,
+        site: Arc<Site>,
+        indexer: &Option<Address>,
+        block: BlockPtr,
+    ) -> Result<Option<[u8; 32]>, StoreError> {
+        let indexer = *indexer;
+        let info = self.subgraph_info(&site)?;
+
+        let (mut by_causality_region, block_ptr) =
+            match self.poi_digests(site.cheap_clone(), block).await? {
+                Some(digests) => digests,
+                None => return Ok(None),
+            };
 
         let mut finisher =
-            ProofOfIndexingFinisher::new(&block_ptr, &site2.deployment, &indexer, info.poi_version);
+            ProofOfIndexingFinisher::new(&block_ptr, &site.deployment, &indexer, info.poi_version);
         for (name, region) in by_causality_region.drain() {
             finisher.add_causality_region(&name, &region);
         }
@@ -1039,6 +1332,24 @@ impl DeploymentStore {
         Ok(Some(finisher.finish()))
     }
 
+    /// Return the digests of the proof of indexing for each causality
+    /// region as of `block`, keyed by the name of the causality region
+    pub(crate) async fn get_poi_digests(
+        &self,
+        site: Arc<Site>,
+        block: BlockNumber,
+    ) -> Result<Option<BTreeMap<String, Vec<u8>>>, StoreError> {
+        // Only the block number matters for finding the digests
+        let block = BlockPtr::new(BlockHash::zero(), block);
+        let digests = self.poi_digests(site, block).await?.map(|(digests, _)| {
+            digests
+                .into_iter()
+                .map(|(region, digest)| (region.to_string(), digest.to_vec()))
+                .collect()
+        });
+        Ok(digests)
+    }
+
     /// Get the entity matching `key` from the deployment `site`. Only
     /// consider entities as of the given `block`
     pub(crate) fn get(
@@ -1141,6 +1452,10 @@ impl DeploymentStore {
 
                 layout.rollup(&conn, last_rollup, &batch.block_times)?;
 
+                layout.record_poi_history(&conn)?;
+
+                layout.record_entity_feed(&conn, batch)?;
+
                 dynds::insert(&conn, &site, &batch.data_sources, manifest_idx_and_name)?;
 
                 dynds::update_offchain_status(&conn, &site, &batch.offchain_to_remove)?;
@@ -1287,6 +1602,7 @@ impl DeploymentStore {
                 // The revert functions want the number of the first block that we need to get rid of
                 let block = block_ptr_to.number + 1;
 
+                deployment::record_revert(conn, &site, &block_ptr_to)?;
                 deployment::revert_block_ptr(
                     conn,
                     &site.deployment,
@@ -1306,6 +1622,7 @@ impl DeploymentStore {
                     deployment::update_entity_count(conn, site.as_ref(), count)?;
                     event
                 };
+                layout.revert_entity_feed(conn, &block_ptr_to)?;
 
                 // Revert the meta data changes that correspond to this subgraph.
                 // Only certain meta data changes need to be reverted, most
@@ -1570,6 +1887,20 @@ impl DeploymentStore {
                 info!(logger, "Rewound subgraph to block {}", block.number;
                       "time_ms" => start.elapsed().as_millis());
 
+                // Keep recording the history of the PoI digests if the
+                // source did; the history is filled from the PoI table we
+                // just copied and rewound
+                if src.catalog.use_poi_history && dst.supports_proof_of_indexing() {
+                    let count = dst.enable_poi_history(&conn)?;
+                    info!(logger, "Copied the PoI history for {} blocks", count);
+                }
+
+                // Keep recording the entity feed if the source did; the
+                // feed of the copy starts at the graft point
+                if src.catalog.use_entity_feed {
+                    dst.enable_entity_feed(&conn, Some(&block))?;
+                }
+
                 let start = Instant::now();
                 deployment::set_entity_count(&conn, &dst.site, &dst.count_query)?;
                 info!(logger, "Counted the entities";
@@ -1602,6 +1933,9 @@ impl DeploymentStore {
                     "time_ms" => start.elapsed().as_millis());
                 Ok(())
             })?;
+            // The cached layout does not know about a PoI history we
+            // might have just created
+            self.layout_cache.remove(&site);
         }
         // Make sure the block pointer is set. This is important for newly
         // deployed subgraphs so that we respect the 'startBlock' setting
@@ -1867,6 +2201,30 @@ fn resolve_table_name<'a>(layout: &'a Layout, name: &'_ str) -> Result<&'a Table
         })
 }
 
+/// Run `sql`, which must create the index `index_name` concurrently, and
+/// check that creating the index succeeded. If it did not, drop the index
+/// again and return `StoreError::Canceled`
+fn create_index_concurrently(
+    conn: &PgConnection,
+    schema_name: &str,
+    index_name: &str,
+    sql: &str,
+) -> Result<(), StoreError> {
+    // This might take a long time.
+    conn.execute(sql)?;
+    // check if the index creation was successfull
+    let index_is_valid = catalog::check_index_is_valid(conn, schema_name, index_name)?;
+    if index_is_valid {
+        Ok(())
+    } else {
+        // Index creation falied. We should drop the index before returning.
+        let drop_index_sql =
+            format!("drop index concurrently if exists {schema_name}.{index_name}");
+        conn.execute(&drop_index_sql)?;
+        Err(StoreError::Canceled)
+    }
+}
+
 /// Resolves column names against the `table`. The `field_names` can be
 /// either GraphQL attributes or the SQL names of columns. We also accept
 /// the names `block_range` and `block$` and map that to the correct name
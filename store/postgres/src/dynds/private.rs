@@ -36,7 +36,21 @@ pub(crate) struct DataSourcesTable {
 }
 
 impl DataSourcesTable {
-    const TABLE_NAME: &'static str = "data_sources$";
+    pub(crate) const TABLE_NAME: &'static str = "data_sources$";
+
+    /// The names of all columns of the table, in the order in which
+    /// `as_ddl` creates them
+    pub(crate) const COLUMNS: [&'static str; 9] = [
+        "vid",
+        "block_range",
+        "causality_region",
+        "manifest_idx",
+        "parent",
+        "id",
+        "param",
+        "context",
+        "done_at",
+    ];
 
     pub(crate) fn new(namespace: Namespace) -> Self {
         let table =
@@ -56,6 +70,10 @@ impl DataSourcesTable {
         }
     }
 
+    pub(crate) fn qualified_name(&self) -> &str {
+        &self.qname
+    }
+
     pub(crate) fn as_ddl(&self) -> String {
         format!(
             "
@@ -47,6 +47,13 @@ pub fn register(
         Arc::new(RefreshMaterializedView::new(store.subgraph_store())),
         6 * ONE_HOUR,
     );
+
+    if ENV_VARS.store.record_query_workload {
+        runner.register(
+            Arc::new(FlushQueryWorkload::new(store.subgraph_store())),
+            ONE_MINUTE,
+        );
+    }
 }
 
 /// A job that vacuums `subgraphs.subgraph_deployment`. With a large number
@@ -177,6 +184,27 @@ impl Job for RefreshMaterializedView {
     }
 }
 
+struct FlushQueryWorkload {
+    store: Arc<SubgraphStore>,
+}
+
+impl FlushQueryWorkload {
+    fn new(store: Arc<SubgraphStore>) -> Self {
+        Self { store }
+    }
+}
+
+#[async_trait]
+impl Job for FlushQueryWorkload {
+    fn name(&self) -> &str {
+        "Write the recorded query workload"
+    }
+
+    async fn run(&self, logger: &Logger) {
+        self.store.flush_query_workload(logger).await;
+    }
+}
+
 struct UnusedJob {
     store: Arc<SubgraphStore>,
 }
@@ -33,10 +33,12 @@ pub mod query_store;
 mod relational;
 mod relational_queries;
 mod retry;
+mod sql;
 mod store;
 mod store_events;
 mod subgraph_store;
 pub mod transaction_receipt;
+mod workload;
 mod writable;
 
 #[cfg(debug_assertions)]
@@ -65,6 +67,7 @@ pub use self::primary::{db_version, UnusedDeployment};
 pub use self::store::Store;
 pub use self::store_events::SubscriptionManager;
 pub use self::subgraph_store::{unused, DeploymentPlacer, Shard, SubgraphStore, PRIMARY_SHARD};
+pub use self::writable::ReplayStore;
 
 /// This module is only meant to support command line tooling. It must not
 /// be used in 'normal' graph-node code
@@ -81,6 +84,12 @@ pub mod command_support {
     }
     pub mod index {
         pub use crate::relational::index::{CreateIndex, Method};
+        pub use crate::workload::{IndexAdvice, IndexProposal, UnusedIndex};
+    }
+    pub mod dump {
+        pub use crate::relational::dump::{
+            DumpMetadata, ManifestDump, TableDump, MANIFEST_FILE, METADATA_FILE, SCHEMA_FILE,
+        };
     }
     pub use crate::deployment::{on_sync, OnSync};
     pub use crate::primary::Namespace;
@@ -25,7 +25,10 @@ use diesel::{
 use graph::{
     components::store::DeploymentLocator,
     constraint_violation,
-    data::subgraph::{status, DeploymentFeatures},
+    data::{
+        query::PersistedQueries,
+        subgraph::{status, DeploymentFeatures},
+    },
     prelude::{
         anyhow, bigdecimal::ToPrimitive, serde_json, DeploymentHash, EntityChange,
         EntityChangeOperation, NodeId, StoreError, SubgraphName, SubgraphVersionSwitchingMode,
@@ -176,6 +179,25 @@ table! {
     }
 }
 
+table! {
+    /// Queries that clients can run against a deployment by sending only
+    /// the hash of the query text
+    subgraphs.persisted_query(deployment, hash) {
+        deployment -> Text,
+        hash -> Text,
+        query -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    /// Deployments that only execute the queries in `persisted_query`
+    subgraphs.query_allowlist(deployment) {
+        deployment -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
 table! {
     public.db_version(version) {
         #[sql_name = "db_version"]
@@ -1761,6 +1783,85 @@ impl<'a> Connection<'a> {
 
         Ok(())
     }
+
+    pub fn persisted_queries(
+        &self,
+        deployment: &DeploymentHash,
+    ) -> Result<PersistedQueries, StoreError> {
+        use persisted_query as pq;
+        use query_allowlist as qa;
+
+        let conn = self.conn.as_ref();
+        let queries = pq::table
+            .filter(pq::deployment.eq(deployment.as_str()))
+            .select((pq::hash, pq::query))
+            .load::<(String, String)>(conn)?;
+        let allowlist_only = select(exists(
+            qa::table.filter(qa::deployment.eq(deployment.as_str())),
+        ))
+        .get_result::<bool>(conn)?;
+        Ok(PersistedQueries::new(queries, allowlist_only))
+    }
+
+    /// Register `query` for `deployment` and return its hash. Registering
+    /// a query that is already registered does nothing
+    pub fn add_persisted_query(
+        &self,
+        deployment: &DeploymentHash,
+        query: &str,
+    ) -> Result<String, StoreError> {
+        use persisted_query as pq;
+
+        let hash = PersistedQueries::hash(query);
+        insert_into(pq::table)
+            .values((
+                pq::deployment.eq(deployment.as_str()),
+                pq::hash.eq(&hash),
+                pq::query.eq(query),
+            ))
+            .on_conflict_do_nothing()
+            .execute(self.conn.as_ref())?;
+        Ok(hash)
+    }
+
+    /// Remove the query with `hash` for `deployment` and return `true` if
+    /// there was such a query
+    pub fn remove_persisted_query(
+        &self,
+        deployment: &DeploymentHash,
+        hash: &str,
+    ) -> Result<bool, StoreError> {
+        use persisted_query as pq;
+
+        let removed = delete(
+            pq::table
+                .filter(pq::deployment.eq(deployment.as_str()))
+                .filter(pq::hash.eq(hash.to_ascii_lowercase())),
+        )
+        .execute(self.conn.as_ref())?;
+        Ok(removed > 0)
+    }
+
+    /// Turn the allowlist for `deployment` on or off. While it is on, the
+    /// deployment only executes its persisted queries
+    pub fn set_allowlist_only(
+        &self,
+        deployment: &DeploymentHash,
+        allowlist_only: bool,
+    ) -> Result<(), StoreError> {
+        use query_allowlist as qa;
+
+        let conn = self.conn.as_ref();
+        if allowlist_only {
+            insert_into(qa::table)
+                .values(qa::deployment.eq(deployment.as_str()))
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+        } else {
+            delete(qa::table.filter(qa::deployment.eq(deployment.as_str()))).execute(conn)?;
+        }
+        Ok(())
+    }
 }
 
 /// Return `true` if we deem this installation to be empty, defined as
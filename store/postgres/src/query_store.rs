@@ -2,7 +2,7 @@ use std::time::Instant;
 
 use crate::deployment_store::{DeploymentStore, ReplicaId};
 use graph::components::store::{DeploymentId, QueryPermit, QueryStore as QueryStoreTrait};
-use graph::data::query::Trace;
+use graph::data::query::{FeedCursor, FeedEvent, SqlPlan, SqlQueryObject, Trace};
 use graph::data::store::QueryObject;
 use graph::prelude::*;
 use graph::schema::{ApiSchema, InputSchema};
@@ -56,6 +56,76 @@ impl QueryStoreTrait for QueryStore {
             })
     }
 
+    fn aggregate_query_values(
+        &self,
+        query: EntityAggregateQuery,
+    ) -> Result<(Vec<Vec<Value>>, Trace), QueryExecutionError> {
+        assert_eq!(&self.site.deployment, &query.subgraph_id);
+        let start = Instant::now();
+        let conn = self
+            .store
+            .get_replica_conn(self.replica_id)
+            .map_err(|e| QueryExecutionError::StoreError(e.into()))?;
+        let wait = start.elapsed();
+        self.store
+            .execute_aggregate_query(&conn, self.site.clone(), query)
+            .map(|(rows, mut trace)| {
+                trace.conn_wait(wait);
+                (rows, trace)
+            })
+    }
+
+    fn explain_query_values(
+        &self,
+        query: EntityQuery,
+        explain: bool,
+    ) -> Result<SqlPlan, QueryExecutionError> {
+        assert_eq!(&self.site.deployment, &query.subgraph_id);
+        let conn = self
+            .store
+            .get_replica_conn(self.replica_id)
+            .map_err(|e| QueryExecutionError::StoreError(e.into()))?;
+        self.store
+            .explain_query(&conn, self.site.clone(), query, explain)
+    }
+
+    fn explain_aggregate_query_values(
+        &self,
+        query: EntityAggregateQuery,
+        explain: bool,
+    ) -> Result<SqlPlan, QueryExecutionError> {
+        assert_eq!(&self.site.deployment, &query.subgraph_id);
+        let conn = self
+            .store
+            .get_replica_conn(self.replica_id)
+            .map_err(|e| QueryExecutionError::StoreError(e.into()))?;
+        self.store
+            .explain_aggregate_query(&conn, self.site.clone(), query, explain)
+    }
+
+    fn execute_sql(
+        &self,
+        sql: &str,
+        block: BlockNumber,
+    ) -> Result<Vec<SqlQueryObject>, QueryExecutionError> {
+        let conn = self
+            .store
+            .get_replica_conn(self.replica_id)
+            .map_err(|e| QueryExecutionError::StoreError(e.into()))?;
+        self.store.execute_sql(&conn, self.site.clone(), sql, block)
+    }
+
+    fn entity_feed(
+        &self,
+        cursor: Option<FeedCursor>,
+    ) -> Result<Vec<FeedEvent>, QueryExecutionError> {
+        let conn = self
+            .store
+            .get_replica_conn(self.replica_id)
+            .map_err(|e| QueryExecutionError::StoreError(e.into()))?;
+        self.store.entity_feed(&conn, self.site.clone(), cursor)
+    }
+
     /// Return true if the deployment with the given id is fully synced,
     /// and return false otherwise. Errors from the store are passed back up
     async fn is_deployment_synced(&self) -> Result<bool, Error> {
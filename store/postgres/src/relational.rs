@@ -14,11 +14,16 @@ mod ddl_tests;
 #[cfg(test)]
 mod query_tests;
 
+pub(crate) mod dump;
+mod feed;
 pub(crate) mod index;
+mod poi_history;
 mod prune;
+pub(crate) mod restore;
 mod rollup;
 
 use diesel::pg::Pg;
+use diesel::query_builder::QueryFragment;
 use diesel::serialize::Output;
 use diesel::sql_types::Text;
 use diesel::types::{FromSql, ToSql};
@@ -30,10 +35,10 @@ use graph::components::store::write::RowGroup;
 use graph::components::subgraph::PoICausalityRegion;
 use graph::constraint_violation;
 use graph::data::graphql::TypeExt as _;
-use graph::data::query::Trace;
+use graph::data::query::{SqlPlan, SqlQueryObject, Trace};
 use graph::data::value::Word;
 use graph::data_source::CausalityRegion;
-use graph::prelude::{q, EntityQuery, StopwatchMetrics, ENV_VARS};
+use graph::prelude::{q, EntityAggregateQuery, EntityQuery, StopwatchMetrics, ENV_VARS};
 use graph::schema::{
     EntityKey, EntityType, Field, FulltextConfig, FulltextDefinition, InputSchema,
 };
@@ -53,16 +58,17 @@ use crate::relational_queries::{FindChangesQuery, FindDerivedQuery, FindPossible
 use crate::{
     primary::{Namespace, Site},
     relational_queries::{
-        ClampRangeQuery, ConflictingEntityQuery, EntityData, EntityDeletion, FilterCollection,
-        FilterQuery, FindManyQuery, FindQuery, InsertQuery, RevertClampQuery, RevertRemoveQuery,
+        AggregateData, AggregateQuery, ClampRangeQuery, ConflictingEntityQuery, EntityData,
+        EntityDeletion, ExplainLine, ExplainQuery, FilterCollection, FilterQuery, FindManyQuery,
+        FindQuery, InsertQuery, RevertClampQuery, RevertRemoveQuery,
     },
 };
 use graph::components::store::DerivedEntityQuery;
-use graph::data::store::{Id, IdList, IdType, BYTES_SCALAR};
+use graph::data::store::{scalar, Id, IdList, IdType, BYTES_SCALAR};
 use graph::data::subgraph::schema::POI_TABLE;
 use graph::prelude::{
     anyhow, info, BlockNumber, DeploymentHash, Entity, EntityChange, EntityOperation, Logger,
-    QueryExecutionError, StoreError, StoreEvent, ValueType, BLOCK_NUMBER_MAX,
+    QueryExecutionError, StoreError, StoreEvent, Value, ValueType, BLOCK_NUMBER_MAX,
 };
 
 use crate::block_range::{BLOCK_COLUMN, BLOCK_RANGE_COLUMN};
@@ -200,6 +206,82 @@ impl std::ops::Deref for SqlName {
     }
 }
 
+/// Log the SQL text and execution time of `query` if logging of SQL
+/// timing is turned on, and return a `Trace` for it if `trace` is `true`
+fn log_query_timing<Q: QueryFragment<Pg>>(
+    logger: &Logger,
+    query: &Q,
+    elapsed: Duration,
+    entity_count: usize,
+    trace: bool,
+) -> Trace {
+    // 20kB
+    const MAXLEN: usize = 20_480;
+
+    if !ENV_VARS.log_sql_timing() && !trace {
+        return Trace::None;
+    }
+
+    let mut text = debug_query::<Pg, _>(query).to_string().replace('\n', "\t");
+
+    let trace = if trace {
+        Trace::query(&text, elapsed, entity_count)
+    } else {
+        Trace::None
+    };
+
+    if ENV_VARS.log_sql_timing() {
+        // If the query + bind variables is more than MAXLEN, truncate it;
+        // this will happen when queries have very large bind variables
+        // (e.g., long arrays of string ids)
+        if text.len() > MAXLEN {
+            text.truncate(MAXLEN);
+            text.push_str(" ...");
+        }
+        info!(
+            logger,
+            "Query timing (SQL)";
+            "query" => text,
+            "time_ms" => elapsed.as_millis(),
+            "entity_count" => entity_count
+        );
+    }
+    trace
+}
+
+/// Return the SQL text of `query` and, if `explain` is `true`, the plan
+/// that Postgres chooses for it. The query itself is not run
+fn explain_query<Q: QueryFragment<Pg>>(
+    conn: &PgConnection,
+    query: Q,
+    explain: bool,
+) -> Result<SqlPlan, QueryExecutionError> {
+    let mut text = String::new();
+    write!(text, "{}", debug_query::<Pg, _>(&query)).map_err(|_| {
+        QueryExecutionError::ResolveEntitiesError("failed to generate the SQL for a query".into())
+    })?;
+
+    let explain = if explain {
+        let lines = conn
+            .transaction(|| {
+                if let Some(ref timeout_sql) = *STATEMENT_TIMEOUT {
+                    conn.batch_execute(timeout_sql)?;
+                }
+                ExplainQuery::new(query).load::<ExplainLine>(conn)
+            })
+            .map_err(|e| {
+                QueryExecutionError::ResolveEntitiesError(format!("{e}, query = {text}"))
+            })?;
+        Some(lines.into_iter().map(|line| line.0).collect())
+    } else {
+        None
+    };
+    Ok(SqlPlan {
+        query: text,
+        explain,
+    })
+}
+
 #[derive(Debug, Clone)]
 pub struct Layout {
     /// Details of where the subgraph is stored
@@ -622,47 +704,6 @@ impl Layout {
         conn: &PgConnection,
         query: EntityQuery,
     ) -> Result<(Vec<T>, Trace), QueryExecutionError> {
-        fn log_query_timing(
-            logger: &Logger,
-            query: &FilterQuery,
-            elapsed: Duration,
-            entity_count: usize,
-            trace: bool,
-        ) -> Trace {
-            // 20kB
-            const MAXLEN: usize = 20_480;
-
-            if !ENV_VARS.log_sql_timing() && !trace {
-                return Trace::None;
-            }
-
-            let mut text = debug_query(&query).to_string().replace('\n', "\t");
-
-            let trace = if trace {
-                Trace::query(&text, elapsed, entity_count)
-            } else {
-                Trace::None
-            };
-
-            if ENV_VARS.log_sql_timing() {
-                // If the query + bind variables is more than MAXLEN, truncate it;
-                // this will happen when queries have very large bind variables
-                // (e.g., long arrays of string ids)
-                if text.len() > MAXLEN {
-                    text.truncate(MAXLEN);
-                    text.push_str(" ...");
-                }
-                info!(
-                    logger,
-                    "Query timing (SQL)";
-                    "query" => text,
-                    "time_ms" => elapsed.as_millis(),
-                    "entity_count" => entity_count
-                );
-            }
-            trace
-        }
-
         let trace = query.trace;
 
         let filter_collection =
@@ -728,6 +769,162 @@ impl Layout {
             .map(|values| (values, trace))
     }
 
+    /// Compute the aggregates for `query`. The result contains one row
+    /// per group, laid out as described in `EntityAggregateQuery`
+    pub fn aggregate_query(
+        &self,
+        logger: &Logger,
+        conn: &PgConnection,
+        query: EntityAggregateQuery,
+    ) -> Result<(Vec<Vec<Value>>, Trace), QueryExecutionError> {
+        let table = self.table_for_entity(&query.entity_type)?;
+        let aggregate_query = AggregateQuery::new(
+            self,
+            table,
+            query.filter.as_ref(),
+            &query.group_by,
+            &query.aggregates,
+            query.range,
+            query.block,
+            query.query_id,
+            &self.site,
+        )?;
+
+        let start = Instant::now();
+        let rows = conn
+            .transaction(|| {
+                if let Some(ref timeout_sql) = *STATEMENT_TIMEOUT {
+                    conn.batch_execute(timeout_sql)?;
+                }
+                aggregate_query.clone().load::<AggregateData>(conn)
+            })
+            .map_err(|e| {
+                QueryExecutionError::ResolveEntitiesError(format!(
+                    "{e}, query = {}",
+                    debug_query(&aggregate_query)
+                ))
+            })?;
+        let trace = log_query_timing(
+            logger,
+            &aggregate_query,
+            start.elapsed(),
+            rows.len(),
+            query.trace,
+        );
+
+        rows.into_iter()
+            .map(|row| {
+                aggregate_query
+                    .values(row)
+                    .map_err(QueryExecutionError::from)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(|rows| (rows, trace))
+    }
+
+    /// Return the SQL of the query that `query` runs, without running
+    /// it, and the plan that Postgres chooses for it if `explain` is `true`
+    pub fn explain_query(
+        &self,
+        conn: &PgConnection,
+        query: EntityQuery,
+        explain: bool,
+    ) -> Result<SqlPlan, QueryExecutionError> {
+        let filter_collection =
+            FilterCollection::new(self, query.collection, query.filter.as_ref(), query.block)?;
+        let query = FilterQuery::new(
+            &filter_collection,
+            self,
+            query.filter.as_ref(),
+            query.order,
+            query.range,
+            query.block,
+            query.query_id,
+            &self.site,
+        )?;
+        explain_query(conn, query, explain)
+    }
+
+    /// Like `explain_query`, but for the query that `aggregate_query` runs
+    pub fn explain_aggregate_query(
+        &self,
+        conn: &PgConnection,
+        query: EntityAggregateQuery,
+        explain: bool,
+    ) -> Result<SqlPlan, QueryExecutionError> {
+        let table = self.table_for_entity(&query.entity_type)?;
+        let aggregate_query = AggregateQuery::new(
+            self,
+            table,
+            query.filter.as_ref(),
+            &query.group_by,
+            &query.aggregates,
+            query.range,
+            query.block,
+            query.query_id,
+            &self.site,
+        )?;
+        explain_query(conn, aggregate_query, explain)
+    }
+
+    /// Run the user-supplied SQL query `sql` against the entities visible
+    /// at `block`. The query is checked and rewritten by `sql::Parser`
+    /// before it is sent to the database, and runs in a read-only
+    /// transaction with the same timeout and result size limits as
+    /// GraphQL queries. Each row of the result is turned into a JSON
+    /// object keyed by column name
+    pub fn execute_sql(
+        &self,
+        conn: &PgConnection,
+        sql: &str,
+        block: BlockNumber,
+    ) -> Result<Vec<SqlQueryObject>, QueryExecutionError> {
+        #[derive(QueryableByName)]
+        struct JsonRow {
+            #[sql_type = "Text"]
+            row: String,
+        }
+
+        let query = crate::sql::Parser::new(self, block).parse_and_validate(sql)?;
+        let max_rows = ENV_VARS.graphql.max_first as usize;
+        // Fetch one more row than allowed so we can tell whether the
+        // query returned too many rows
+        let query = format!(
+            "select to_jsonb(sub.*)::text as row from ({query}) as sub limit {}",
+            max_rows + 1
+        );
+
+        let rows = conn
+            .transaction(|| {
+                conn.batch_execute("set transaction read only")?;
+                if let Some(ref timeout_sql) = *STATEMENT_TIMEOUT {
+                    conn.batch_execute(timeout_sql)?;
+                }
+                diesel::sql_query(&query).load::<JsonRow>(conn)
+            })
+            .map_err(|e| QueryExecutionError::SqlError(e.to_string()))?;
+
+        if rows.len() > max_rows {
+            return Err(QueryExecutionError::SqlError(format!(
+                "the query returned more than {max_rows} rows; use `limit` to return fewer rows"
+            )));
+        }
+        let size = rows.iter().map(|row| row.row.len()).sum::<usize>();
+        if size > ENV_VARS.graphql.error_result_size {
+            return Err(QueryExecutionError::ResultTooBig(
+                size,
+                ENV_VARS.graphql.error_result_size,
+            ));
+        }
+
+        rows.into_iter()
+            .map(|row| {
+                graph::prelude::serde_json::from_str(&row.row)
+                    .map_err(|e| QueryExecutionError::SqlError(e.to_string()))
+            })
+            .collect()
+    }
+
     pub fn update<'a>(
         &'a self,
         conn: &PgConnection,
@@ -812,6 +1009,7 @@ impl Layout {
         for table in self.tables.values() {
             conn.execute(&format!("TRUNCATE TABLE {}", table.qualified_name))?;
         }
+        self.truncate_poi_history(conn)?;
         Ok(StoreEvent::new(vec![]))
     }
 
@@ -869,6 +1067,7 @@ impl Layout {
             });
             changes.extend(set);
         }
+        self.revert_poi_history(conn, block)?;
         Ok((StoreEvent::new(changes), count))
     }
 
@@ -897,8 +1096,9 @@ impl Layout {
 
     /// Update the layout with the latest information from the database; an
     /// update can only change the `is_account_like` flag for tables, the
-    /// layout's site, or the `history_blocks`. If no update is needed, just
-    /// return `self`.
+    /// layout's site, the `history_blocks`, or whether the PoI history or
+    /// the entity feed are recorded. If no update is needed, just return
+    /// `self`.
     ///
     /// This is tied closely to how the `LayoutCache` works and called from
     /// it right after creating a `Layout`, and periodically to update the
@@ -910,6 +1110,8 @@ impl Layout {
     ) -> Result<Arc<Self>, StoreError> {
         let account_like = crate::catalog::account_like(conn, &self.site)?;
         let history_blocks = deployment::history_blocks(conn, &self.site)?;
+        let use_poi_history = crate::catalog::has_poi_history(conn, &self.site.namespace)?;
+        let use_entity_feed = crate::catalog::has_entity_feed(conn, &self.site.namespace)?;
 
         let is_account_like = { |table: &Table| account_like.contains(table.name.as_str()) };
 
@@ -918,7 +1120,12 @@ impl Layout {
             .values()
             .filter(|table| table.is_account_like != is_account_like(table.as_ref()))
             .collect();
-        if changed_tables.is_empty() && site == self.site && history_blocks == self.history_blocks {
+        if changed_tables.is_empty()
+            && site == self.site
+            && history_blocks == self.history_blocks
+            && use_poi_history == self.catalog.use_poi_history
+            && use_entity_feed == self.catalog.use_entity_feed
+        {
             return Ok(self);
         }
 
@@ -930,6 +1137,8 @@ impl Layout {
         }
         layout.site = site;
         layout.history_blocks = history_blocks;
+        layout.catalog.use_poi_history = use_poi_history;
+        layout.catalog.use_entity_feed = use_entity_feed;
         Ok(Arc::new(layout))
     }
 
@@ -1059,11 +1268,12 @@ impl Layout {
                 // `t2`, `t3`, and `t4`.
                 match buckets.first() {
                     None => {
-                        // The rollups are in increasing order of interval size, so
-                        // if a smaller interval doesn't have a bucket between
-                        // last_rollup and block_time, a larger one can't either and
-                        // we are done with this rollup.
-                        break;
+                        // Nothing to roll up for this interval. We can't
+                        // stop looking at the remaining rollups here even
+                        // though they are in increasing order of interval
+                        // size since the boundaries of `week` and `month`
+                        // buckets do not line up with each other
+                        continue;
                     }
                     Some(bucket) => {
                         rollup.insert(conn, &bucket, *block)?;
@@ -1206,6 +1416,43 @@ impl ColumnType {
             )),
         }
     }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            ColumnType::BigDecimal | ColumnType::BigInt | ColumnType::Int | ColumnType::Int8
+        )
+    }
+
+    /// Parse the text representation of a value of this type that Postgres
+    /// produces when casting the value to `text`. `Bytes` must be in the
+    /// form `0x..` rather than Postgres' `\x..`
+    pub(crate) fn parse_text(&self, text: &str) -> Result<Value, StoreError> {
+        let invalid = |e: &dyn fmt::Display| {
+            constraint_violation!("invalid value `{}` for type {:?}: {}", text, self, e)
+        };
+        let value = match self {
+            ColumnType::Boolean => match text {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                _ => return Err(invalid(&"not a boolean")),
+            },
+            ColumnType::BigDecimal => {
+                Value::BigDecimal(scalar::BigDecimal::from_str(text).map_err(|e| invalid(&e))?)
+            }
+            ColumnType::BigInt => {
+                Value::BigInt(scalar::BigInt::from_str(text).map_err(|e| invalid(&e))?)
+            }
+            ColumnType::Bytes => {
+                Value::Bytes(scalar::Bytes::from_str(text).map_err(|e| invalid(&e))?)
+            }
+            ColumnType::Int => Value::Int(text.parse().map_err(|e| invalid(&e))?),
+            ColumnType::Int8 => Value::Int8(text.parse().map_err(|e| invalid(&e))?),
+            ColumnType::String | ColumnType::Enum(_) => Value::String(text.to_string()),
+            ColumnType::TSVector(_) => return Err(invalid(&"fulltext values can not be parsed")),
+        };
+        Ok(value)
+    }
 }
 
 #[derive(Clone, Debug)]
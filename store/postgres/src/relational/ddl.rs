@@ -47,6 +47,24 @@ impl Layout {
         Ok(out)
     }
 
+    /// Generate the DDL for all enums and tables of the layout, but
+    /// without any indexes other than the ones that back the tables'
+    /// constraints. This is used when data is loaded in bulk into the
+    /// tables and indexes are only created once all data has been loaded
+    pub(crate) fn as_table_ddl(&self) -> Result<String, fmt::Error> {
+        let mut out = String::new();
+
+        self.write_enum_ddl(&mut out)?;
+
+        let mut tables = self.tables.values().collect::<Vec<_>>();
+        tables.sort_by_key(|table| table.position);
+        for table in tables {
+            table.create_table(&mut out)?;
+        }
+
+        Ok(out)
+    }
+
     pub(crate) fn write_enum_ddl(&self, out: &mut dyn Write) -> Result<(), fmt::Error> {
         for name in self.input_schema.enum_types() {
             let values = self.input_schema.enum_values(name).unwrap();
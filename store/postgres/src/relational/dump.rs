@@ -0,0 +1,353 @@
+//! Export the entities of a deployment into CSV files, one file per table.
+//!
+//! Every value is written in Postgres' text format, i.e., exactly as
+//! `value::text` would render it, so that the files can be loaded back
+//! with `copy .. from .. (format csv, header true)`. Nulls are written as
+//! empty fields and all other values are quoted so the two can be told
+//! apart.
+//!
+//! A dump either contains a snapshot of the entities that are visible at
+//! one block, or the complete history of all entity versions including
+//! their block ranges. In both cases, the file `dump.json` in the dump
+//! directory describes what was dumped. Dumps with the full history also
+//! contain the deployment's dynamic data sources and everything else that
+//! is needed to recreate the deployment from the dump with `restore`.
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use diesel::{
+    sql_query,
+    sql_types::{Array, BigInt, Nullable, Text},
+    Connection, PgConnection, RunQueryDsl,
+};
+use graph::constraint_violation;
+use graph::prelude::{anyhow, serde_json, BlockNumber, Deserialize, Serialize, StoreError};
+use itertools::Itertools;
+
+use crate::{
+    block_range::{BLOCK_COLUMN, BLOCK_RANGE_COLUMN, CAUSALITY_REGION_COLUMN},
+    catalog, deployment, detail,
+    dynds::DataSourcesTable,
+    relational::{Table, VID_COLUMN},
+};
+
+use super::Layout;
+
+/// The name of the file in a dump directory that describes the dump
+pub const METADATA_FILE: &str = "dump.json";
+/// The name of the file in a dump directory that contains the GraphQL
+/// schema of the deployment
+pub const SCHEMA_FILE: &str = "schema.graphql";
+/// The name of the file in a dump directory that contains the raw manifest
+/// of the deployment
+pub const MANIFEST_FILE: &str = "subgraph.yaml";
+
+/// How many rows to read from the database at once
+const BATCH_SIZE: i64 = 10_000;
+
+/// Description of a dump, stored in `METADATA_FILE`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DumpMetadata {
+    /// The IPFS hash of the deployment
+    pub deployment: String,
+    /// The network the deployment indexes
+    pub network: String,
+    /// The database schema that contained the deployment's tables
+    pub namespace: String,
+    pub manifest: ManifestDump,
+    /// The earliest block for which the deployment has data
+    pub earliest_block_number: BlockNumber,
+    /// The number and hash of the last block the deployment had processed
+    /// when the dump was taken
+    pub head_number: BlockNumber,
+    pub head_hash: String,
+    /// The block at which the snapshot was taken; `None` if the dump
+    /// contains the full history of all entities
+    pub block: Option<BlockNumber>,
+    pub tables: Vec<TableDump>,
+    /// The dump of the table with the deployment's dynamic data sources.
+    /// It is only present in dumps with the full history of deployments
+    /// that keep their data sources in their own schema
+    pub data_sources: Option<TableDump>,
+}
+
+/// The parts of the deployment's manifest that are needed to recreate the
+/// deployment from the dump
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ManifestDump {
+    pub spec_version: String,
+    pub description: Option<String>,
+    pub repository: Option<String>,
+    pub features: Vec<String>,
+    pub entities_with_causality_region: Vec<String>,
+    pub history_blocks: BlockNumber,
+    /// The number and hash of the block at which indexing started
+    pub start_block_number: Option<BlockNumber>,
+    pub start_block_hash: Option<String>,
+}
+
+/// Description of the dump of one table
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TableDump {
+    /// The name of the entity type
+    pub entity: String,
+    /// The name of the table in the database
+    pub table: String,
+    /// The name of the CSV file, relative to the dump directory
+    pub file: String,
+    /// The columns in the CSV file, in the order in which they appear
+    pub columns: Vec<String>,
+    pub rows: usize,
+    /// The definitions of all indexes on the table, as reported by
+    /// `pg_indexes`
+    pub indexes: Vec<String>,
+}
+
+impl Table {
+    /// The names of the columns that we dump for this table
+    fn dump_columns(&self, history: bool) -> Vec<&str> {
+        let mut columns: Vec<&str> = self.columns.iter().map(|c| c.name.as_str()).collect();
+        if history {
+            columns.push(if self.immutable {
+                BLOCK_COLUMN
+            } else {
+                BLOCK_RANGE_COLUMN
+            });
+        }
+        if self.has_causality_region {
+            columns.push(CAUSALITY_REGION_COLUMN);
+        }
+        columns
+    }
+
+    /// Write the rows of this table that are visible at `block` into
+    /// `out`, or all rows if `block` is `None`. Return the number of rows
+    /// written
+    fn dump<W: Write>(
+        &self,
+        conn: &PgConnection,
+        block: Option<BlockNumber>,
+        out: &mut W,
+    ) -> Result<usize, StoreError> {
+        let filter = match block {
+            None => String::new(),
+            Some(block) if self.immutable => format!("and \"{BLOCK_COLUMN}\" <= {block}"),
+            Some(block) => format!("and {BLOCK_RANGE_COLUMN} @> {block}"),
+        };
+        dump_rows(
+            conn,
+            self.qualified_name.as_str(),
+            &self.dump_columns(block.is_none()),
+            &filter,
+            out,
+        )
+    }
+}
+
+/// Write the header and then all rows of the table `qualified_name` that
+/// match `filter` into `out`. The `filter` is added to the `where` clause
+/// of the query and must therefore start with `and` unless it is empty.
+/// Return the number of rows written
+fn dump_rows<W: Write>(
+    conn: &PgConnection,
+    qualified_name: &str,
+    columns: &[&str],
+    filter: &str,
+    out: &mut W,
+) -> Result<usize, StoreError> {
+    #[derive(QueryableByName)]
+    struct Row {
+        #[sql_type = "BigInt"]
+        vid: i64,
+        #[sql_type = "Array<Nullable<Text>>"]
+        data: Vec<Option<String>>,
+    }
+
+    write_row(out, columns.iter().map(|c| Some(*c)))?;
+
+    let values = columns
+        .iter()
+        .map(|column| format!("\"{column}\"::text"))
+        .join(", ");
+    let query = format!(
+        "select {VID_COLUMN}::int8 as vid, array[{values}] as data \
+           from {qualified_name} \
+          where {VID_COLUMN} > $1 {filter} \
+          order by {VID_COLUMN} \
+          limit $2"
+    );
+
+    let mut count = 0;
+    let mut last_vid = -1;
+    loop {
+        let rows = sql_query(&query)
+            .bind::<BigInt, _>(last_vid)
+            .bind::<BigInt, _>(BATCH_SIZE)
+            .load::<Row>(conn)?;
+        for row in &rows {
+            write_row(out, row.data.iter().map(|v| v.as_deref()))?;
+        }
+        count += rows.len();
+        match rows.last() {
+            Some(row) if rows.len() as i64 == BATCH_SIZE => last_vid = row.vid,
+            _ => break,
+        }
+    }
+    Ok(count)
+}
+
+/// Write one line of CSV. Non-null values are always quoted so that
+/// Postgres can distinguish empty strings from nulls when loading the file
+fn write_row<'a, W: Write>(
+    out: &mut W,
+    values: impl Iterator<Item = Option<&'a str>>,
+) -> Result<(), StoreError> {
+    let line = values
+        .map(|value| match value {
+            Some(value) => format!("\"{}\"", value.replace('"', "\"\"")),
+            None => String::new(),
+        })
+        .join(",");
+    writeln!(out, "{line}")?;
+    Ok(())
+}
+
+impl Layout {
+    /// Dump all tables of this layout into CSV files in `dir`, and write
+    /// a description of the dump to `dir/dump.json`. If `history` is set,
+    /// dump all entity versions together with their block ranges;
+    /// otherwise, only dump the entities that are visible at `block`, or
+    /// at the deployment head if `block` is `None`.
+    ///
+    /// All tables are read in one repeatable-read transaction so that the
+    /// dump is consistent even if the deployment is being indexed while
+    /// the dump is taken
+    pub fn dump(
+        &self,
+        conn: &PgConnection,
+        dir: &Path,
+        history: bool,
+        block: Option<BlockNumber>,
+    ) -> Result<DumpMetadata, StoreError> {
+        conn.build_transaction()
+            .read_only()
+            .repeatable_read()
+            .run(|| {
+                let head =
+                    deployment::block_ptr(conn, &self.site.deployment)?.ok_or_else(|| {
+                        anyhow!("deployment {} has not processed any blocks", self.site)
+                    })?;
+                let block = match (history, block) {
+                    (true, None) => None,
+                    (true, Some(_)) => {
+                        return Err(constraint_violation!(
+                            "a dump with the full history can not be taken at a specific block"
+                        ))
+                    }
+                    (false, block) => Some(block.unwrap_or(head.number)),
+                };
+                if let Some(block) = block {
+                    if block > head.number {
+                        return Err(anyhow!(
+                            "deployment {} has only processed blocks up to {}, \
+                             it can not be dumped at block {block}",
+                            self.site,
+                            head.number
+                        )
+                        .into());
+                    }
+                }
+
+                let mut tables = Vec::new();
+                for table in self.tables.values().sorted_by(|a, b| a.name.cmp(&b.name)) {
+                    let file = format!("{}.csv", table.name);
+                    let mut out = BufWriter::new(File::create(dir.join(&file))?);
+                    let rows = table.dump(conn, block, &mut out)?;
+                    out.flush()?;
+
+                    tables.push(TableDump {
+                        entity: table.object.to_string(),
+                        table: table.name.to_string(),
+                        file,
+                        columns: table
+                            .dump_columns(block.is_none())
+                            .into_iter()
+                            .map(str::to_string)
+                            .collect(),
+                        rows,
+                        indexes: catalog::indexes_for_table(
+                            conn,
+                            self.site.namespace.as_str(),
+                            table.name.as_str(),
+                        )?,
+                    });
+                }
+
+                let data_sources =
+                    if block.is_none() && self.site.schema_version.private_data_sources() {
+                        let dds = DataSourcesTable::new(self.site.namespace.clone());
+                        let file = format!("{}.csv", DataSourcesTable::TABLE_NAME);
+                        let mut out = BufWriter::new(File::create(dir.join(&file))?);
+                        let rows = dump_rows(
+                            conn,
+                            dds.qualified_name(),
+                            &DataSourcesTable::COLUMNS,
+                            "",
+                            &mut out,
+                        )?;
+                        out.flush()?;
+
+                        Some(TableDump {
+                            entity: String::new(),
+                            table: DataSourcesTable::TABLE_NAME.to_string(),
+                            file,
+                            columns: DataSourcesTable::COLUMNS
+                                .iter()
+                                .map(|c| c.to_string())
+                                .collect(),
+                            rows,
+                            indexes: vec![],
+                        })
+                    } else {
+                        None
+                    };
+
+                let details = detail::deployment_entity(conn, &self.site, &self.input_schema)?;
+                let manifest = details.manifest;
+                std::fs::write(dir.join(SCHEMA_FILE), &manifest.schema)?;
+                if let Some(raw_yaml) = &manifest.raw_yaml {
+                    std::fs::write(dir.join(MANIFEST_FILE), raw_yaml)?;
+                }
+
+                let metadata = DumpMetadata {
+                    deployment: self.site.deployment.to_string(),
+                    network: self.site.network.clone(),
+                    namespace: self.site.namespace.to_string(),
+                    manifest: ManifestDump {
+                        spec_version: manifest.spec_version,
+                        description: manifest.description,
+                        repository: manifest.repository,
+                        features: manifest.features,
+                        entities_with_causality_region: manifest
+                            .entities_with_causality_region
+                            .iter()
+                            .map(|et| et.to_string())
+                            .collect(),
+                        history_blocks: manifest.history_blocks,
+                        start_block_number: details.start_block.as_ref().map(|ptr| ptr.number),
+                        start_block_hash: details.start_block.as_ref().map(|ptr| ptr.hash_hex()),
+                    },
+                    earliest_block_number: details.earliest_block_number,
+                    head_number: head.number,
+                    head_hash: head.hash_hex(),
+                    block,
+                    tables,
+                    data_sources,
+                };
+                let file = File::create(dir.join(METADATA_FILE))?;
+                serde_json::to_writer_pretty(file, &metadata)?;
+                Ok(metadata)
+            })
+    }
+}
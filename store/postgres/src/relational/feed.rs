@@ -0,0 +1,422 @@
+//! The entity change feed of a deployment.
+//!
+//! Deployments that opt into the feed record the changes to their entities
+//! in the table `feed$` in the same transaction in which the writer makes
+//! them, and the hash of every block they write in `feed_block$`. Reading
+//! the feed for a range of blocks is then a range scan of the primary key
+//! of `feed$` rather than a scan of the history of every entity table, and
+//! every change carries the hash of the block in which it happened.
+//!
+//! The smallest block in `feed_block$` is the block from which on the feed
+//! is complete: all changes in later blocks are recorded. Reverts remove
+//! the feed for the reverted blocks and make the block the deployment was
+//! reverted to part of the feed so that consumers can follow the feed
+//! from there. Every revert is also recorded in `subgraphs.subgraph_revert`;
+//! consumers of the feed keep the id of the last revert they have seen in
+//! their cursor so that they learn about reverts that happened while they
+//! were not connected.
+//!
+//! Since the feed can be turned off at any time, all operations check that
+//! it exists in the database rather than relying only on the cached
+//! `Layout`
+use std::str::FromStr;
+
+use diesel::{
+    connection::SimpleConnection,
+    sql_query,
+    sql_types::{Array, Binary, Integer, Jsonb, Nullable, Text},
+    Connection, OptionalExtension, PgConnection, RunQueryDsl,
+};
+use graph::{
+    blockchain::BlockPtr,
+    components::{sink, store::write::Batch},
+    data::query::{FeedChange, FeedCursor, FeedEvent, FeedOp},
+    data::subgraph::schema::{FEED_BLOCK_TABLE, FEED_TABLE},
+    prelude::{serde_json, BlockNumber, QueryExecutionError, StoreError, ENV_VARS},
+};
+
+use crate::{catalog, deployment, primary::Namespace};
+
+use super::{Layout, SqlName};
+
+#[derive(QueryableByName)]
+struct FirstBlock {
+    #[sql_type = "Nullable<Integer>"]
+    block: Option<BlockNumber>,
+}
+
+#[derive(QueryableByName)]
+struct FeedBlock {
+    #[sql_type = "Integer"]
+    block: BlockNumber,
+    #[sql_type = "Text"]
+    hash: String,
+}
+
+#[derive(QueryableByName)]
+struct FeedRow {
+    #[sql_type = "Integer"]
+    block: BlockNumber,
+    #[sql_type = "Text"]
+    hash: String,
+    #[sql_type = "Text"]
+    entity_type: String,
+    #[sql_type = "Text"]
+    id: String,
+    #[sql_type = "Text"]
+    op: String,
+    #[sql_type = "Nullable<Jsonb>"]
+    data: Option<serde_json::Value>,
+}
+
+fn feed_table(namespace: &Namespace) -> SqlName {
+    SqlName::qualified_name(namespace, &SqlName::verbatim(FEED_TABLE.to_owned()))
+}
+
+fn feed_block_table(namespace: &Namespace) -> SqlName {
+    SqlName::qualified_name(namespace, &SqlName::verbatim(FEED_BLOCK_TABLE.to_owned()))
+}
+
+impl Layout {
+    /// Start recording the entity feed. The feed starts at `head`, the
+    /// block the deployment has processed last, or with the first block
+    /// the deployment writes if it has not processed any blocks yet
+    pub(crate) fn enable_entity_feed(
+        &self,
+        conn: &PgConnection,
+        head: Option<&BlockPtr>,
+    ) -> Result<(), StoreError> {
+        let query = format!(
+            "create table if not exists {blocks}(
+                 block_number int primary key,
+                 block_hash   bytea not null
+             );
+             create table if not exists {feed}(
+                 block_number int not null,
+                 entity_type  text not null,
+                 entity_id    text not null,
+                 op           text not null,
+                 data         jsonb,
+                 primary key(block_number, entity_type, entity_id)
+             )",
+            blocks = feed_block_table(&self.site.namespace),
+            feed = feed_table(&self.site.namespace),
+        );
+        conn.batch_execute(&query)?;
+        if let Some(head) = head {
+            self.add_feed_block(conn, head)?;
+        }
+        Ok(())
+    }
+
+    /// Stop recording the entity feed and remove it
+    pub(crate) fn disable_entity_feed(&self, conn: &PgConnection) -> Result<(), StoreError> {
+        let query = format!(
+            "drop table if exists {}; drop table if exists {}",
+            feed_table(&self.site.namespace),
+            feed_block_table(&self.site.namespace)
+        );
+        Ok(conn.batch_execute(&query)?)
+    }
+
+    /// Whether the feed is recorded. The feed might have been removed
+    /// since this layout was loaded, which is why we check the database,
+    /// too
+    fn has_entity_feed(&self, conn: &PgConnection) -> Result<bool, StoreError> {
+        Ok(self.catalog.use_entity_feed && catalog::has_entity_feed(conn, &self.site.namespace)?)
+    }
+
+    fn add_feed_block(&self, conn: &PgConnection, ptr: &BlockPtr) -> Result<(), StoreError> {
+        let query = format!(
+            "insert into {}(block_number, block_hash) values($1, $2)
+             on conflict(block_number) do update set block_hash = excluded.block_hash",
+            feed_block_table(&self.site.namespace)
+        );
+        sql_query(query)
+            .bind::<Integer, _>(ptr.number)
+            .bind::<Binary, _>(ptr.hash_slice())
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Record the blocks in `batch` and the changes it makes to entities.
+    /// This must be called in the transaction that writes `batch`
+    pub(crate) fn record_entity_feed(
+        &self,
+        conn: &PgConnection,
+        batch: &Batch,
+    ) -> Result<(), StoreError> {
+        if !self.has_entity_feed(conn)? {
+            return Ok(());
+        }
+
+        for ptr in &batch.block_ptrs {
+            self.add_feed_block(conn, ptr)?;
+        }
+
+        let changes = sink::entity_changes(batch, |entity_type| !entity_type.is_poi(), true);
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let mut blocks = Vec::with_capacity(changes.len());
+        let mut entity_types = Vec::with_capacity(changes.len());
+        let mut ids = Vec::with_capacity(changes.len());
+        let mut ops = Vec::with_capacity(changes.len());
+        let mut data = Vec::with_capacity(changes.len());
+        for change in changes {
+            blocks.push(change.block);
+            entity_types.push(change.entity_type);
+            ids.push(change.id);
+            ops.push(change.op.as_str());
+            data.push(change.data);
+        }
+        let query = format!(
+            "insert into {}(block_number, entity_type, entity_id, op, data)
+             select * from unnest($1::int[], $2::text[], $3::text[], $4::text[], $5::jsonb[])",
+            feed_table(&self.site.namespace)
+        );
+        sql_query(query)
+            .bind::<Array<Integer>, _>(blocks)
+            .bind::<Array<Text>, _>(entity_types)
+            .bind::<Array<Text>, _>(ids)
+            .bind::<Array<Text>, _>(ops)
+            .bind::<Array<Nullable<Jsonb>>, _>(data)
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Remove the feed for all blocks after `block_ptr_to`, and continue
+    /// the feed from `block_ptr_to`
+    pub(crate) fn revert_entity_feed(
+        &self,
+        conn: &PgConnection,
+        block_ptr_to: &BlockPtr,
+    ) -> Result<(), StoreError> {
+        if !catalog::has_entity_feed(conn, &self.site.namespace)? {
+            return Ok(());
+        }
+        let query = format!(
+            "delete from {} where block_number > $1",
+            feed_table(&self.site.namespace)
+        );
+        sql_query(query)
+            .bind::<Integer, _>(block_ptr_to.number)
+            .execute(conn)?;
+        let query = format!(
+            "delete from {} where block_number > $1",
+            feed_block_table(&self.site.namespace)
+        );
+        sql_query(query)
+            .bind::<Integer, _>(block_ptr_to.number)
+            .execute(conn)?;
+        self.add_feed_block(conn, block_ptr_to)
+    }
+
+    /// Remove the feed for blocks before `earliest_block`, keeping the
+    /// last recorded block before it so that consumers that have seen
+    /// `earliest_block` can still follow the feed
+    pub(super) fn prune_entity_feed(
+        &self,
+        conn: &PgConnection,
+        earliest_block: BlockNumber,
+    ) -> Result<(), StoreError> {
+        if !catalog::has_entity_feed(conn, &self.site.namespace)? {
+            return Ok(());
+        }
+        let query = format!(
+            "delete from {blocks}
+              where block_number < (select max(block_number)
+                                      from {blocks}
+                                     where block_number <= $1)",
+            blocks = feed_block_table(&self.site.namespace),
+        );
+        sql_query(query)
+            .bind::<Integer, _>(earliest_block)
+            .execute(conn)?;
+        // Changes in the first recorded block happened before the feed
+        // starts and are not needed anymore
+        let query = format!(
+            "delete from {feed}
+              where block_number <= (select min(block_number) from {blocks})",
+            feed = feed_table(&self.site.namespace),
+            blocks = feed_block_table(&self.site.namespace),
+        );
+        conn.batch_execute(&query)?;
+        Ok(())
+    }
+
+    /// The block from which on the feed is complete, or `None` if no
+    /// block has been recorded yet
+    fn entity_feed_start(&self, conn: &PgConnection) -> Result<Option<BlockNumber>, StoreError> {
+        let query = format!(
+            "select min(block_number) as block from {}",
+            feed_block_table(&self.site.namespace)
+        );
+        Ok(sql_query(query).get_result::<FirstBlock>(conn)?.block)
+    }
+
+    /// The last recorded block after `start` and at most `end`, or the
+    /// first recorded block after `start` if there is none up to `end`
+    fn entity_feed_end(
+        &self,
+        conn: &PgConnection,
+        start: BlockNumber,
+        end: BlockNumber,
+    ) -> Result<Option<FeedBlock>, StoreError> {
+        let query = format!(
+            "select block_number as block, encode(block_hash, 'hex') as hash
+               from {blocks}
+              where block_number > $1
+                and block_number <= greatest($2, (select min(block_number)
+                                                    from {blocks}
+                                                   where block_number > $1))
+              order by block_number desc
+              limit 1",
+            blocks = feed_block_table(&self.site.namespace)
+        );
+        Ok(sql_query(query)
+            .bind::<Integer, _>(start)
+            .bind::<Integer, _>(end)
+            .get_result::<FeedBlock>(conn)
+            .optional()?)
+    }
+
+    /// Return all changes to entities in the blocks after `start` up to
+    /// and including `end`, ordered by block, entity type and id
+    fn feed_changes(
+        &self,
+        conn: &PgConnection,
+        start: BlockNumber,
+        end: BlockNumber,
+    ) -> Result<Vec<FeedChange>, StoreError> {
+        let query = format!(
+            "select f.block_number as block, encode(b.block_hash, 'hex') as hash,
+                    f.entity_type, f.entity_id as id, f.op, f.data
+               from {feed} f
+               join {blocks} b on b.block_number = f.block_number
+              where f.block_number > $1 and f.block_number <= $2
+              order by f.block_number, f.entity_type, f.entity_id",
+            feed = feed_table(&self.site.namespace),
+            blocks = feed_block_table(&self.site.namespace),
+        );
+        sql_query(query)
+            .bind::<Integer, _>(start)
+            .bind::<Integer, _>(end)
+            .load::<FeedRow>(conn)?
+            .into_iter()
+            .map(|row| {
+                Ok(FeedChange {
+                    block: row.block,
+                    hash: row.hash,
+                    entity_type: row.entity_type,
+                    id: row.id,
+                    op: FeedOp::from_str(&row.op).map_err(StoreError::Unknown)?,
+                    data: row.data,
+                })
+            })
+            .collect()
+    }
+
+    /// Return the next events of the entity change feed after `cursor`.
+    /// Without a cursor, return one empty event for the deployment head
+    /// so that consumers get a cursor from which to follow the feed.
+    ///
+    /// Events are produced in this order: first a revert for every revert
+    /// of the deployment since the cursor that undid blocks the consumer
+    /// has seen, then the changes in the recorded blocks among the next
+    /// `entity_feed_max_blocks` blocks.
+    /// Everything is read in one repeatable-read transaction so that the
+    /// events are consistent even when the deployment is being indexed
+    pub fn entity_feed(
+        &self,
+        conn: &PgConnection,
+        cursor: Option<FeedCursor>,
+    ) -> Result<Vec<FeedEvent>, QueryExecutionError> {
+        let events = conn.build_transaction().read_only().repeatable_read().run(
+            || -> Result<_, StoreError> {
+                if !catalog::has_entity_feed(conn, &self.site.namespace)? {
+                    return Ok(Err(QueryExecutionError::NotSupported(format!(
+                        "the entity change feed is not recorded for deployment {}; \
+                         turn it on with `graphman entity-feed`",
+                        self.site.deployment
+                    ))));
+                }
+
+                let state = deployment::state(conn, self.site.deployment.clone())?;
+                let head = state.latest_block;
+
+                let cursor = match cursor {
+                    Some(cursor) => cursor,
+                    None => {
+                        let revert = deployment::last_revert(conn, &self.site)?;
+                        return Ok(Ok(vec![FeedEvent::Changes {
+                            block: head.number,
+                            hash: head.hash_hex(),
+                            changes: vec![],
+                            cursor: FeedCursor {
+                                block: head.number,
+                                revert,
+                            },
+                        }]));
+                    }
+                };
+                if let Some(start) = self.entity_feed_start(conn)? {
+                    if cursor.block < start {
+                        return Ok(Err(QueryExecutionError::InvalidCursor(format!(
+                            "the cursor points to block {} but the feed only has changes \
+                             after block {}",
+                            cursor.block, start
+                        ))));
+                    }
+                }
+
+                let mut events = Vec::new();
+                let mut block = cursor.block;
+                let mut revert = cursor.revert;
+                for (id, ptr) in deployment::reverts_since(conn, &self.site, cursor.revert)? {
+                    revert = id;
+                    // Reverts to blocks the consumer has not seen yet do
+                    // not affect it
+                    if ptr.number < block {
+                        block = ptr.number;
+                        events.push(FeedEvent::Revert {
+                            block,
+                            hash: ptr.hash_hex(),
+                            cursor: FeedCursor { block, revert },
+                        });
+                    }
+                }
+                if block > head.number {
+                    // The cursor came from a copy of the deployment that
+                    // had progressed further than this one
+                    block = head.number;
+                    events.push(FeedEvent::Revert {
+                        block,
+                        hash: head.hash_hex(),
+                        cursor: FeedCursor { block, revert },
+                    });
+                }
+
+                if block < head.number {
+                    let end = head
+                        .number
+                        .min(block.saturating_add(ENV_VARS.graphql.entity_feed_max_blocks));
+                    if let Some(end) = self.entity_feed_end(conn, block, end)? {
+                        let changes = self.feed_changes(conn, block, end.block)?;
+                        events.push(FeedEvent::Changes {
+                            block: end.block,
+                            hash: end.hash,
+                            changes,
+                            cursor: FeedCursor {
+                                block: end.block,
+                                revert,
+                            },
+                        });
+                    }
+                }
+                Ok(Ok(events))
+            },
+        )?;
+        events
+    }
+}
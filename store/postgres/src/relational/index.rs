@@ -524,6 +524,34 @@ impl CreateIndex {
         }
     }
 
+    /// Change this index so that it is created on the table with the same
+    /// name in namespace `nsp`. Return `None` if this is an index that we
+    /// could not parse since we can not reliably change its namespace
+    pub fn with_nsp(self, nsp: String) -> Option<Self> {
+        match self {
+            CreateIndex::Unknown { defn: _ } => None,
+            CreateIndex::Parsed {
+                unique,
+                name,
+                nsp: _,
+                table,
+                method,
+                columns,
+                cond,
+                with,
+            } => Some(CreateIndex::Parsed {
+                unique,
+                name,
+                nsp,
+                table,
+                method,
+                columns,
+                cond,
+                with,
+            }),
+        }
+    }
+
     /// Generate a SQL statement that creates this index. If `concurrent` is
     /// `true`, make it a concurrent index creation. If `if_not_exists` is
     /// `true` add a `if not exists` clause to the index creation.
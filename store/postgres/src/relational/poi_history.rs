@@ -0,0 +1,213 @@
+//! The history of the digests of a deployment's proof of indexing.
+//!
+//! The PoI table `poi2$` keeps a version of the digest of each causality
+//! region for every block in which it changed, but pruning removes old
+//! versions together with the history of all other entities, and finding
+//! the digests at a block requires querying it like any other entity
+//! table. Deployments can opt into recording the digests of all causality
+//! regions for every block in which one of them changed in the table
+//! `poi_history$`, which turns looking up the digests at a block into a
+//! single index lookup.
+//!
+//! The history is always filled from `poi2$` by copying the versions that
+//! started after the last block in the history. That makes it possible to
+//! start recording the history while the deployment is being indexed: the
+//! writer catches up with the blocks it wrote before it noticed that the
+//! history exists. Since the history can also be removed at any time, all
+//! operations check that it exists in the database rather than relying
+//! only on the cached `Layout`
+use diesel::{
+    connection::SimpleConnection,
+    sql_query,
+    sql_types::{Array, Binary, Integer, Nullable, Text},
+    OptionalExtension, PgConnection, RunQueryDsl,
+};
+use graph::{
+    data::subgraph::schema::POI_HISTORY_TABLE,
+    prelude::{anyhow::anyhow, BlockNumber, StoreError},
+};
+
+use crate::{block_range::BLOCK_RANGE_COLUMN, catalog, primary::Namespace};
+
+use super::{Layout, SqlName, PRIMARY_KEY_COLUMN};
+
+#[derive(QueryableByName)]
+struct LastBlock {
+    #[sql_type = "Nullable<Integer>"]
+    block: Option<BlockNumber>,
+}
+
+#[derive(QueryableByName)]
+struct Digests {
+    #[sql_type = "Array<Text>"]
+    regions: Vec<String>,
+    #[sql_type = "Array<Binary>"]
+    digests: Vec<Vec<u8>>,
+}
+
+fn history_table(namespace: &Namespace) -> SqlName {
+    SqlName::qualified_name(namespace, &SqlName::verbatim(POI_HISTORY_TABLE.to_owned()))
+}
+
+impl Layout {
+    /// Start recording the history of the PoI digests, and fill it with
+    /// the history that the PoI table still has
+    pub(crate) fn enable_poi_history(&self, conn: &PgConnection) -> Result<usize, StoreError> {
+        if !self.supports_proof_of_indexing() {
+            return Err(StoreError::Unknown(anyhow!(
+                "deployment {} does not have a proof of indexing",
+                self.site.deployment
+            )));
+        }
+        let query = format!(
+            "create table if not exists {}(
+                 block_number int primary key,
+                 regions      text[] not null,
+                 digests      bytea[] not null
+             )",
+            history_table(&self.site.namespace)
+        );
+        conn.batch_execute(&query)?;
+        self.copy_poi_history(conn)
+    }
+
+    /// Stop recording the history of the PoI digests and remove it
+    pub(crate) fn disable_poi_history(&self, conn: &PgConnection) -> Result<(), StoreError> {
+        let query = format!(
+            "drop table if exists {}",
+            history_table(&self.site.namespace)
+        );
+        Ok(conn.batch_execute(&query)?)
+    }
+
+    /// Whether the history is recorded. The history might have been
+    /// removed since this layout was loaded, which is why we check the
+    /// database, too
+    fn has_poi_history(&self, conn: &PgConnection) -> Result<bool, StoreError> {
+        Ok(self.catalog.use_poi_history && catalog::has_poi_history(conn, &self.site.namespace)?)
+    }
+
+    /// Add the digests for the blocks that were written since the history
+    /// was last recorded
+    pub(crate) fn record_poi_history(&self, conn: &PgConnection) -> Result<(), StoreError> {
+        if self.has_poi_history(conn)? {
+            self.copy_poi_history(conn)?;
+        }
+        Ok(())
+    }
+
+    fn last_poi_history_block(
+        &self,
+        conn: &PgConnection,
+    ) -> Result<Option<BlockNumber>, StoreError> {
+        let query = format!(
+            "select max(block_number) as block from {}",
+            history_table(&self.site.namespace)
+        );
+        Ok(sql_query(query).get_result::<LastBlock>(conn)?.block)
+    }
+
+    /// Copy the digests of all blocks after the last block in the history
+    /// from the PoI table into the history
+    fn copy_poi_history(&self, conn: &PgConnection) -> Result<usize, StoreError> {
+        let poi = self.table_for_entity(&self.input_schema.poi_type())?;
+        let digest = SqlName::from(self.input_schema.poi_digest().as_str());
+        let last = self.last_poi_history_block(conn)?.unwrap_or(-1);
+        let query = format!(
+            "insert into {history}(block_number, regions, digests)
+             select b.block,
+                    array_agg(p.{PRIMARY_KEY_COLUMN} order by p.{PRIMARY_KEY_COLUMN}),
+                    array_agg(p.{digest} order by p.{PRIMARY_KEY_COLUMN})
+               from (select distinct lower({BLOCK_RANGE_COLUMN}) as block
+                       from {poi}
+                      where lower({BLOCK_RANGE_COLUMN}) > $1) b
+               join {poi} p on p.{BLOCK_RANGE_COLUMN} @> b.block
+              group by b.block",
+            history = history_table(&self.site.namespace),
+            poi = poi.qualified_name,
+        );
+        Ok(sql_query(query).bind::<Integer, _>(last).execute(conn)?)
+    }
+
+    /// Return the digest of each causality region as of `block` from the
+    /// history. Return `None` if the deployment does not record the
+    /// history or if the history does not reach `block` yet, in which case
+    /// the digests need to be taken from the PoI table
+    pub(crate) fn poi_history(
+        &self,
+        conn: &PgConnection,
+        block: BlockNumber,
+    ) -> Result<Option<Vec<(String, Vec<u8>)>>, StoreError> {
+        if !self.has_poi_history(conn)? {
+            return Ok(None);
+        }
+        let last = self.last_poi_history_block(conn)?;
+        if !matches!(last, Some(last) if last >= block) {
+            return Ok(None);
+        }
+        let query = format!(
+            "select regions, digests from {}
+              where block_number <= $1
+              order by block_number desc
+              limit 1",
+            history_table(&self.site.namespace)
+        );
+        let digests = sql_query(query)
+            .bind::<Integer, _>(block)
+            .get_result::<Digests>(conn)
+            .optional()?
+            .map(|digests| digests.regions.into_iter().zip(digests.digests).collect())
+            .unwrap_or_default();
+        Ok(Some(digests))
+    }
+
+    /// Remove the digests for `block` and all later blocks from the
+    /// history
+    pub(super) fn revert_poi_history(
+        &self,
+        conn: &PgConnection,
+        block: BlockNumber,
+    ) -> Result<(), StoreError> {
+        if !catalog::has_poi_history(conn, &self.site.namespace)? {
+            return Ok(());
+        }
+        let query = format!(
+            "delete from {} where block_number >= $1",
+            history_table(&self.site.namespace)
+        );
+        sql_query(query).bind::<Integer, _>(block).execute(conn)?;
+        Ok(())
+    }
+
+    /// Remove the digests that are not needed to answer lookups for
+    /// `earliest_block` or later blocks from the history
+    pub(super) fn prune_poi_history(
+        &self,
+        conn: &PgConnection,
+        earliest_block: BlockNumber,
+    ) -> Result<(), StoreError> {
+        if !catalog::has_poi_history(conn, &self.site.namespace)? {
+            return Ok(());
+        }
+        let query = format!(
+            "delete from {history}
+              where block_number < (select max(block_number)
+                                      from {history}
+                                     where block_number <= $1)",
+            history = history_table(&self.site.namespace)
+        );
+        sql_query(query)
+            .bind::<Integer, _>(earliest_block)
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Remove the entire history
+    pub(super) fn truncate_poi_history(&self, conn: &PgConnection) -> Result<(), StoreError> {
+        if !catalog::has_poi_history(conn, &self.site.namespace)? {
+            return Ok(());
+        }
+        let query = format!("truncate table {}", history_table(&self.site.namespace));
+        Ok(conn.batch_execute(&query)?)
+    }
+}
@@ -497,6 +497,8 @@ impl Layout {
         for (table, _) in &prunable_tables {
             catalog::set_last_pruned_block(conn, &self.site, &table.name, req.earliest_block)?;
         }
+        self.prune_poi_history(conn, req.earliest_block)?;
+        self.prune_entity_feed(conn, req.earliest_block)?;
 
         // Analyze the new tables
         let tables = prunable_tables.iter().map(|(table, _)| *table).collect();
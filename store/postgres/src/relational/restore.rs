@@ -0,0 +1,264 @@
+//! Recreate a deployment from a dump that was taken with `dump`.
+//!
+//! Only dumps that contain the full history of the deployment can be
+//! restored. Restoring happens in three steps: we first create the tables
+//! of the deployment without any indexes, then load the CSV files from the
+//! dump with `copy .. from stdin`, and finally create all the indexes that
+//! the deployment had when it was dumped. Loading the data before creating
+//! indexes is considerably faster than the other way around.
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use diesel::{connection::SimpleConnection, PgConnection};
+use graph::{
+    constraint_violation,
+    data::subgraph::schema::{DeploymentCreate, SubgraphManifestEntity},
+    prelude::{anyhow, serde_json, BlockPtr, DeploymentHash, StoreError},
+    schema::InputSchema,
+};
+use itertools::Itertools;
+use postgres::Client;
+
+use crate::{dynds::DataSourcesTable, primary::Namespace, relational::index::CreateIndex};
+
+use super::{
+    dump::{DumpMetadata, TableDump, MANIFEST_FILE, METADATA_FILE, SCHEMA_FILE},
+    Layout, SqlName,
+};
+
+impl DumpMetadata {
+    /// Read the description of the dump in `dir`
+    pub fn read(dir: &Path) -> Result<Self, StoreError> {
+        let file = File::open(dir.join(METADATA_FILE))?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    /// The block pointer of the deployment head at the time of the dump
+    pub fn head(&self) -> Result<BlockPtr, StoreError> {
+        BlockPtr::try_from((self.head_hash.as_str(), self.head_number as i64))
+            .map_err(StoreError::from)
+    }
+
+    /// Check that the dump in `dir` can be restored, and return the
+    /// deployment hash, the schema, and the data needed to create the
+    /// deployment from it
+    pub(crate) fn deployment_create(
+        &self,
+        dir: &Path,
+    ) -> Result<(DeploymentHash, InputSchema, DeploymentCreate), StoreError> {
+        if self.block.is_some() {
+            return Err(anyhow!(
+                "the dump in {} only contains a snapshot of the entities; \
+                 only dumps taken with `--history` can be restored",
+                dir.display()
+            )
+            .into());
+        }
+        if self.data_sources.is_none() {
+            return Err(anyhow!(
+                "the dump in {} does not contain the dynamic data sources of the deployment; \
+                 deployments that keep their data sources in the shared table can not be restored",
+                dir.display()
+            )
+            .into());
+        }
+
+        let hash = DeploymentHash::new(self.deployment.clone()).map_err(|hash| {
+            constraint_violation!("the dump contains an invalid deployment hash `{}`", hash)
+        })?;
+
+        let raw_schema = std::fs::read_to_string(dir.join(SCHEMA_FILE))?;
+        let schema = InputSchema::parse_latest(&raw_schema, hash.clone())?;
+
+        let raw_yaml = match std::fs::read_to_string(dir.join(MANIFEST_FILE)) {
+            Ok(raw_yaml) => Some(raw_yaml),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        let entities_with_causality_region = self
+            .manifest
+            .entities_with_causality_region
+            .iter()
+            .map(|name| schema.entity_type(name.as_str()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let start_block = match (
+            &self.manifest.start_block_hash,
+            self.manifest.start_block_number,
+        ) {
+            (Some(hash), Some(number)) => Some(BlockPtr::try_from((hash.as_str(), number as i64))?),
+            _ => None,
+        };
+
+        let deployment = DeploymentCreate {
+            manifest: SubgraphManifestEntity {
+                spec_version: self.manifest.spec_version.clone(),
+                description: self.manifest.description.clone(),
+                repository: self.manifest.repository.clone(),
+                features: self.manifest.features.clone(),
+                schema: raw_schema,
+                raw_yaml,
+                entities_with_causality_region,
+                history_blocks: self.manifest.history_blocks,
+            },
+            start_block,
+            graft_base: None,
+            graft_block: None,
+            debug_fork: None,
+            history_blocks_override: None,
+        };
+
+        Ok((hash, schema, deployment))
+    }
+}
+
+impl TableDump {
+    /// Load the CSV file for this table from `dir` into the table
+    /// `qualified_name` using `client`
+    fn restore(
+        &self,
+        client: &mut Client,
+        dir: &Path,
+        qualified_name: &str,
+    ) -> Result<(), StoreError> {
+        let columns = self.columns.iter().map(|c| format!("\"{c}\"")).join(", ");
+        let query =
+            format!("copy {qualified_name}({columns}) from stdin with (format csv, header true)");
+
+        let mut file = BufReader::new(File::open(dir.join(&self.file))?);
+        let mut writer = client
+            .copy_in(query.as_str())
+            .map_err(|e| anyhow!("failed to load {}: {}", self.file, e))?;
+        io::copy(&mut file, &mut writer)?;
+        let rows = writer
+            .finish()
+            .map_err(|e| anyhow!("failed to load {}: {}", self.file, e))?;
+
+        if rows as usize != self.rows {
+            return Err(anyhow!(
+                "the dump says that {} contains {} rows, but we loaded {} rows from it",
+                self.file,
+                self.rows,
+                rows
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// Turn the definition `defn` of an index in namespace `src` into a
+/// statement that creates the same index in namespace `dst`
+fn index_sql(defn: &str, src: &str, dst: &Namespace) -> Result<String, StoreError> {
+    match CreateIndex::parse(defn.to_string()).with_nsp(dst.to_string()) {
+        Some(index) => Ok(index.to_sql(false, true)?),
+        None => {
+            // We could not parse the definition; since it comes straight
+            // from `pg_indexes`, we still know its general shape
+            Ok(defn.replacen("INDEX ", "INDEX IF NOT EXISTS ", 1).replacen(
+                &format!(" ON {src}."),
+                &format!(" ON {dst}."),
+                1,
+            ))
+        }
+    }
+}
+
+impl Layout {
+    /// Load the data for all tables, including the table with dynamic
+    /// data sources, from the CSV files in `dir`. The tables must have
+    /// been created but must still be empty.
+    ///
+    /// Since Diesel does not support `copy .. from stdin`, the data is
+    /// loaded through a separate `client`
+    pub(crate) fn restore_data(
+        &self,
+        client: &mut Client,
+        dir: &Path,
+        metadata: &DumpMetadata,
+    ) -> Result<(), StoreError> {
+        for table in self.tables.values() {
+            if !metadata
+                .tables
+                .iter()
+                .any(|dump| dump.table == table.name.as_str())
+            {
+                return Err(anyhow!(
+                    "the dump does not contain any data for table {}",
+                    table.name
+                )
+                .into());
+            }
+        }
+
+        for dump in &metadata.tables {
+            let table = self
+                .table(&SqlName::verbatim(dump.table.clone()))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "the dump contains table {} which is not in the schema",
+                        dump.table
+                    )
+                })?;
+            dump.restore(client, dir, table.qualified_name.as_str())?;
+        }
+
+        if let Some(dump) = &metadata.data_sources {
+            let dds = DataSourcesTable::new(self.site.namespace.clone());
+            dump.restore(client, dir, dds.qualified_name())?;
+            // The data sources keep their `vid` since `parent` refers to
+            // it; make sure that new data sources do not reuse one
+            let query = format!(
+                "select setval(pg_get_serial_sequence('{qname}', 'vid'), max(vid)) from {qname}",
+                qname = dds.qualified_name()
+            );
+            client
+                .batch_execute(query.as_str())
+                .map_err(|e| anyhow!("failed to reset the vid sequence for data sources: {e}"))?;
+        }
+        Ok(())
+    }
+
+    /// Create all the indexes that the tables had when they were dumped
+    pub(crate) fn restore_indexes(
+        &self,
+        conn: &PgConnection,
+        metadata: &DumpMetadata,
+    ) -> Result<(), StoreError> {
+        for dump in &metadata.tables {
+            for defn in &dump.indexes {
+                let sql = index_sql(defn, &metadata.namespace, &self.site.namespace)?;
+                conn.batch_execute(&sql)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_sql_changes_namespace() {
+        let dst = Namespace::new("sgd42".to_string()).unwrap();
+
+        let defn =
+            "CREATE INDEX attr_0_0_token_name ON sgd7.token USING btree (\"left\"(name, 256))";
+        let sql = index_sql(defn, "sgd7", &dst).unwrap();
+        assert!(
+            sql.contains("if not exists attr_0_0_token_name on sgd42.token"),
+            "{sql}"
+        );
+
+        // Table names with digits are not parsed by `CreateIndex`
+        let defn = "CREATE UNIQUE INDEX token_v2_pkey ON sgd7.token_v2 USING btree (vid)";
+        let sql = index_sql(defn, "sgd7", &dst).unwrap();
+        assert_eq!(
+            "CREATE UNIQUE INDEX IF NOT EXISTS token_v2_pkey ON sgd42.token_v2 USING btree (vid)",
+            sql
+        );
+    }
+}
@@ -20,6 +20,13 @@
 //!      group by timestamp, <dimensions>
 //! ```
 //!
+//! Every interval is computed from the source timeseries directly, and not
+//! from the aggregations for a smaller interval; daily values are therefore
+//! not formed by rolling up hourly values. That makes it possible to support
+//! aggregation functions like `avg`, `percentile`, or `count_distinct` whose
+//! values for a larger interval can not be computed from the values for its
+//! subintervals.
+//!
 //! When there are cumulative aggregations, things get more complicated. We
 //! form the aggregations for the current interval as in the previous case,
 //! but also need to find the corresponding previous aggregated values for
@@ -73,7 +80,7 @@ use graph::sqlparser::parser::ParserError;
 
 use crate::relational::Table;
 
-use super::{Column, SqlName};
+use super::{Column, ColumnType, SqlName};
 
 /// Rewrite `expr` by replacing field names with column names and return the
 /// rewritten SQL expression and the columns used in the expression
@@ -144,6 +151,8 @@ pub(crate) struct Agg<'a> {
     aggregate: &'a Aggregate,
     src_columns: Vec<&'a str>,
     expr: String,
+    /// The rewritten `weight` expression for weighted averages
+    weight: Option<String>,
     agg_column: &'a Column,
 }
 
@@ -153,12 +162,23 @@ impl<'a> Agg<'a> {
         src_table: &'a Table,
         agg_table: &'a Table,
     ) -> Result<Self, StoreError> {
-        let (expr, src_columns) = rewrite(src_table, &aggregate.arg)?;
+        let (expr, mut src_columns) = rewrite(src_table, &aggregate.arg)?;
+        let weight = match &aggregate.weight {
+            Some(weight) => {
+                let (weight, weight_columns) = rewrite(src_table, weight)?;
+                src_columns.extend(weight_columns);
+                src_columns.sort();
+                src_columns.dedup();
+                Some(weight)
+            }
+            None => None,
+        };
         let agg_column = agg_table.column_for_field(&aggregate.name)?;
         Ok(Self {
             aggregate,
             src_columns,
             expr,
+            weight,
             agg_column,
         })
     }
@@ -179,10 +199,53 @@ impl<'a> Agg<'a> {
                 write!(w, "arg_max_{}(({}, {time}))", sql_type, src)?
             }
             Count => write!(w, "count(*)")?,
+            Avg | Stddev => {
+                // Averages and standard deviations are fractional even
+                // when their inputs are integers; round them explicitly if
+                // they are stored in an integer column since a `BigInt`
+                // column would otherwise keep the fraction
+                let round = self.rounds();
+                if round {
+                    write!(w, "round(")?;
+                }
+                match (&self.aggregate.func, &self.weight) {
+                    (Avg, Some(weight)) => write!(
+                        w,
+                        "sum(({src}) * ({weight}))::numeric / nullif(sum({weight}), 0)"
+                    )?,
+                    (Avg, None) => write!(w, "avg({})", src)?,
+                    _ => write!(w, "stddev_pop({})", src)?,
+                }
+                if round {
+                    write!(w, ")")?;
+                }
+            }
+            Percentile => {
+                let percentile = self.aggregate.percentile.unwrap_or(0.5);
+                write!(
+                    w,
+                    "percentile_disc({percentile}) within group (order by {src})"
+                )?
+            }
+            // Counting distinct values exactly needs to sort or hash all
+            // values in the bucket; an estimate is much cheaper
+            CountDistinct => write!(w, "count_distinct_approx({})", src)?,
         }
         write!(w, " as \"{}\"", self.agg_column.name)
     }
 
+    /// Whether the aggregate is fractional but stored in an integer column
+    /// and therefore needs to be rounded
+    fn rounds(&self) -> bool {
+        use AggregateFn::*;
+
+        matches!(self.aggregate.func, Avg | Stddev)
+            && matches!(
+                self.agg_column.column_type,
+                ColumnType::Int | ColumnType::Int8 | ColumnType::BigInt
+            )
+    }
+
     /// Generate a SQL fragment `func(expr) as agg_column` where
     /// `func` is the aggregation function. The `time` parameter is the name
     /// of the column with respect to which `first` and `last` should decide
@@ -206,6 +269,12 @@ impl<'a> Agg<'a> {
                 return self.aggregate_over(&name, time, w);
             }
             Count => write!(w, "sum(\"{}\")", self.agg_column.name)?,
+            Avg | Stddev | Percentile | CountDistinct => {
+                // These can not be cumulative, and the previous value is
+                // therefore always `null`; we just need to pick the value
+                // from the current bucket
+                write!(w, "max(\"{}\")", self.agg_column.name)?
+            }
         }
         write!(w, " as \"{}\"", self.agg_column.name)
     }
@@ -340,11 +409,9 @@ impl<'a> RollupSql<'a> {
         comma_sep(self.aggregates, self.dimensions.is_empty(), w, |w, agg| {
             agg.aggregate("id", w)
         })?;
-        let secs = self.interval.as_duration().as_secs();
-        write!(
-            w,
-            " from (select id, timestamp/{secs}*{secs} as timestamp, "
-        )?;
+        write!(w, " from (select id, ")?;
+        self.bucket_start(w)?;
+        write!(w, " as timestamp, ")?;
         write_dims(self.dimensions, w)?;
         let agg_srcs: Vec<&str> = {
             let mut agg_srcs: Vec<_> = self
@@ -377,6 +444,29 @@ impl<'a> RollupSql<'a> {
         })
     }
 
+    /// Generate an expression that computes the start of the bucket that
+    /// contains the `timestamp` of a row in the source table. Buckets for
+    /// `month` have different lengths and therefore need to be computed
+    /// with calendar arithmetic
+    fn bucket_start(&self, w: &mut dyn fmt::Write) -> fmt::Result {
+        let offset = self.interval.offset().as_secs();
+        match self.interval.as_duration() {
+            Some(length) if offset == 0 => {
+                let secs = length.as_secs();
+                write!(w, "timestamp/{secs}*{secs}")
+            }
+            Some(length) => {
+                let secs = length.as_secs();
+                write!(w, "(timestamp - {offset})/{secs}*{secs} + {offset}")
+            }
+            None => write!(
+                w,
+                "extract(epoch from date_trunc('{}', to_timestamp(timestamp) at time zone 'utc'))::int8",
+                self.interval.as_str()
+            ),
+        }
+    }
+
     fn select(&self, w: &mut dyn fmt::Write) -> fmt::Result {
         self.select_bucket(true, w)
     }
@@ -592,6 +682,24 @@ mod tests {
         total_count: Int8! @aggregate(fn: "count", cumulative: true)
         total_sum: BigDecimal! @aggregate(fn: "sum", arg: "amount", cumulative: true)
       }
+
+      type Calendar @aggregation(intervals: ["week", "month"], source: "Data") {
+        id: Int8!
+        timestamp: Int8!
+        sum: BigDecimal! @aggregate(fn: "sum", arg: "price")
+      }
+
+      type Summary @aggregation(intervals: ["day"], source: "Data") {
+        id: Int8!
+        timestamp: Int8!
+        avg: BigDecimal! @aggregate(fn: "avg", arg: "price")
+        vwap: BigDecimal @aggregate(fn: "avg", arg: "price", weight: "amount")
+        stddev: BigDecimal! @aggregate(fn: "stddev", arg: "price")
+        median: BigDecimal! @aggregate(fn: "percentile", arg: "price", percentile: 0.5)
+        tokens: Int8! @aggregate(fn: "count_distinct", arg: "token")
+        avg_amt: Int! @aggregate(fn: "avg", arg: "amount")
+        vwap_amt: Int8 @aggregate(fn: "avg", arg: "amount", weight: "amount")
+      }
       "#;
 
         const STATS_HOUR_SQL: &str = r#"\
@@ -664,6 +772,42 @@ mod tests {
         select id, timestamp, $3 as block$, "count", "sum", "total_count", "total_sum" from combined
         "#;
 
+        const SUMMARY_SQL: &str = r#"\
+        insert into "sgd007"."summary_day"(id, timestamp, block$, "avg", "vwap", "stddev", "median", "tokens", "avg_amt", "vwap_amt") \
+        select max(id) as id, timestamp, $3, avg("price") as "avg", \
+               sum(("price") * ("amount"))::numeric / nullif(sum("amount"), 0) as "vwap", \
+               stddev_pop("price") as "stddev", \
+               percentile_disc(0.5) within group (order by "price") as "median", \
+               count_distinct_approx("token") as "tokens", \
+               round(avg("amount")) as "avg_amt", \
+               round(sum(("amount") * ("amount"))::numeric / nullif(sum("amount"), 0)) as "vwap_amt" \
+          from (select id, timestamp/86400*86400 as timestamp, "amount", "price", "token" \
+                  from "sgd007"."data" \
+                 where "sgd007"."data".timestamp >= $1 \
+                   and "sgd007"."data".timestamp < $2 \
+                 order by "sgd007"."data".timestamp) data \
+         group by timestamp"#;
+
+        const CALENDAR_WEEK_SQL: &str = r#"\
+        insert into "sgd007"."calendar_week"(id, timestamp, block$, "sum") \
+        select max(id) as id, timestamp, $3, sum("price") as "sum" \
+          from (select id, (timestamp - 345600)/604800*604800 + 345600 as timestamp, "price" \
+                  from "sgd007"."data" \
+                 where "sgd007"."data".timestamp >= $1 \
+                   and "sgd007"."data".timestamp < $2 \
+                 order by "sgd007"."data".timestamp) data \
+         group by timestamp"#;
+
+        const CALENDAR_MONTH_SQL: &str = r#"\
+        insert into "sgd007"."calendar_month"(id, timestamp, block$, "sum") \
+        select max(id) as id, timestamp, $3, sum("price") as "sum" \
+          from (select id, extract(epoch from date_trunc('month', to_timestamp(timestamp) at time zone 'utc'))::int8 as timestamp, "price" \
+                  from "sgd007"."data" \
+                 where "sgd007"."data".timestamp >= $1 \
+                   and "sgd007"."data".timestamp < $2 \
+                 order by "sgd007"."data".timestamp) data \
+         group by timestamp"#;
+
         #[track_caller]
         fn rollup_for<'a>(layout: &'a Layout, table_name: &str) -> &'a Rollup {
             layout
@@ -679,7 +823,7 @@ mod tests {
         let site = Arc::new(make_dummy_site(hash, nsp, "rollup".to_string()));
         let catalog = Catalog::for_tests(site.clone(), BTreeSet::new()).unwrap();
         let layout = Layout::new(site, &schema, catalog).unwrap();
-        assert_eq!(5, layout.rollups.len());
+        assert_eq!(8, layout.rollups.len());
 
         // Intervals are non-decreasing
         assert!(layout.rollups[0].interval <= layout.rollups[1].interval);
@@ -698,5 +842,13 @@ mod tests {
 
         let lifetime = rollup_for(&layout, "lifetime_day");
         check_eqv(LIFETIME_SQL, &lifetime.insert_sql);
+
+        let calendar_week = rollup_for(&layout, "calendar_week");
+        check_eqv(CALENDAR_WEEK_SQL, &calendar_week.insert_sql);
+        let calendar_month = rollup_for(&layout, "calendar_month");
+        check_eqv(CALENDAR_MONTH_SQL, &calendar_month.insert_sql);
+
+        let summary = rollup_for(&layout, "summary_day");
+        check_eqv(SUMMARY_SQL, &summary.insert_sql);
     }
 }
@@ -5,11 +5,13 @@
 ///!
 ///! Code in this module works very hard to minimize the number of allocations
 ///! that it performs
+use diesel::deserialize::QueryableByName;
 use diesel::pg::{Pg, PgConnection};
 use diesel::query_builder::{AstPass, QueryFragment, QueryId};
 use diesel::query_dsl::{LoadQuery, RunQueryDsl};
 use diesel::result::{Error as DieselError, QueryResult};
-use diesel::sql_types::{Array, BigInt, Binary, Bool, Int8, Integer, Jsonb, Range, Text};
+use diesel::row::NamedRow;
+use diesel::sql_types::{Array, BigInt, Binary, Bool, Int8, Integer, Jsonb, Nullable, Range, Text};
 use diesel::Connection;
 
 use graph::components::store::write::WriteChunk;
@@ -19,9 +21,10 @@ use graph::data::store::{IdList, IdRef, QueryObject};
 use graph::data::value::{Object, Word};
 use graph::data_source::CausalityRegion;
 use graph::prelude::{
-    anyhow, r, serde_json, Attribute, BlockNumber, ChildMultiplicity, Entity, EntityCollection,
-    EntityFilter, EntityLink, EntityOrder, EntityOrderByChild, EntityOrderByChildInfo, EntityRange,
-    EntityWindow, ParentLink, QueryExecutionError, StoreError, Value, ENV_VARS,
+    anyhow, r, serde_json, Attribute, BlockNumber, ChildMultiplicity, Entity, EntityAggregate,
+    EntityCollection, EntityFilter, EntityLink, EntityOrder, EntityOrderByChild,
+    EntityOrderByChildInfo, EntityRange, EntityWindow, ParentLink, QueryExecutionError, StoreError,
+    Value, ENV_VARS,
 };
 use graph::schema::{EntityKey, EntityType, FulltextAlgorithm, InputSchema};
 use graph::{components::store::AttributeNames, data::store::scalar};
@@ -471,6 +474,10 @@ pub struct EntityData {
 }
 
 impl EntityData {
+    pub(crate) fn new(entity: String, data: serde_json::Value) -> Self {
+        EntityData { entity, data }
+    }
+
     pub fn entity_type(&self, schema: &InputSchema) -> EntityType {
         schema.entity_type(&self.entity).unwrap()
     }
@@ -4170,6 +4177,326 @@ impl<'a> LoadQuery<PgConnection, EntityData> for FilterQuery<'a> {
 
 impl<'a, Conn> RunQueryDsl<Conn> for FilterQuery<'a> {}
 
+/// The result of an `AggregateQuery`. All values are returned as text and
+/// turned into `Value` with `AggregateQuery::values`
+#[derive(QueryableByName, Debug)]
+pub struct AggregateData {
+    #[sql_type = "Array<Nullable<Text>>"]
+    data: Vec<Option<String>>,
+}
+
+/// The parallel to `EntityAggregateQuery`. Generates
+///
+///   select array[c.g1::text, .., count(*)::text, sum(c.x)::text, ..] as data
+///     from schema.table c
+///    where block_range @> $block
+///      and query_filter
+///    group by c.g1, ..
+///    order by c.g1, ..
+///    limit $first offset $skip
+#[derive(Debug, Clone)]
+pub struct AggregateQuery<'a> {
+    table: &'a Table,
+    filter: Option<QueryFilter<'a>>,
+    group_by: Vec<&'a Column>,
+    /// The aggregates and the column they are computed over; the column is
+    /// `None` for `count`
+    aggregates: Vec<(&'a EntityAggregate, Option<&'a Column>)>,
+    range: FilterRange,
+    block: BlockNumber,
+    query_id: Option<String>,
+    site: &'a Site,
+}
+
+impl<'a> AggregateQuery<'a> {
+    pub fn new(
+        layout: &'a Layout,
+        table: &'a Table,
+        filter: Option<&'a EntityFilter>,
+        group_by: &'a [Attribute],
+        aggregates: &'a [EntityAggregate],
+        range: EntityRange,
+        block: BlockNumber,
+        query_id: Option<String>,
+        site: &'a Site,
+    ) -> Result<Self, QueryExecutionError> {
+        let filter = filter
+            .map(|filter| QueryFilter::new(filter, table, layout, block))
+            .transpose()?;
+
+        let group_by = group_by
+            .iter()
+            .map(|attr| -> Result<_, QueryExecutionError> {
+                let column = table.column_for_field(attr)?;
+                if column.is_list() || column.is_fulltext() {
+                    return Err(QueryExecutionError::NotSupported(format!(
+                        "can not group by `{}.{}` since it is not a scalar attribute",
+                        table.object, attr
+                    )));
+                }
+                Ok(column)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let aggregates = aggregates
+            .iter()
+            .map(|aggregate| -> Result<_, QueryExecutionError> {
+                let attr = match aggregate {
+                    EntityAggregate::Count => return Ok((aggregate, None)),
+                    EntityAggregate::Sum(attr)
+                    | EntityAggregate::Min(attr)
+                    | EntityAggregate::Max(attr) => attr,
+                };
+                let column = table.column_for_field(attr)?;
+                if column.is_primary_key() {
+                    return Err(QueryExecutionError::NotSupported(format!(
+                        "can not compute `{}` for `{}` since `{}` is the primary key",
+                        aggregate, table.object, attr
+                    )));
+                }
+                if column.is_list() || !column.column_type.is_numeric() {
+                    return Err(QueryExecutionError::NotSupported(format!(
+                        "can not compute `{}` for `{}` since `{}` is not numeric",
+                        aggregate, table.object, attr
+                    )));
+                }
+                Ok((aggregate, Some(column)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(AggregateQuery {
+            table,
+            filter,
+            group_by,
+            aggregates,
+            range: FilterRange(range),
+            block,
+            query_id,
+            site,
+        })
+    }
+
+    /// The type of the values in each row of the result
+    fn column_types(&self) -> impl Iterator<Item = ColumnType> + '_ {
+        let groups = self
+            .group_by
+            .iter()
+            .map(|column| column.column_type.clone());
+        let aggregates =
+            self.aggregates
+                .iter()
+                .map(|(aggregate, column)| match (aggregate, column) {
+                    (EntityAggregate::Count, _) | (_, None) => ColumnType::Int8,
+                    (EntityAggregate::Sum(_), Some(column)) => match column.column_type {
+                        // Postgres returns a `bigint` for the sum of `int` and a
+                        // `numeric` for the sum of `bigint`
+                        ColumnType::Int => ColumnType::Int8,
+                        ColumnType::Int8 => ColumnType::BigInt,
+                        _ => column.column_type.clone(),
+                    },
+                    (EntityAggregate::Min(_), Some(column))
+                    | (EntityAggregate::Max(_), Some(column)) => column.column_type.clone(),
+                });
+        groups.chain(aggregates)
+    }
+
+    /// Turn one row of the result into the values for the group and the
+    /// aggregates
+    pub fn values(&self, row: AggregateData) -> Result<Vec<Value>, StoreError> {
+        if row.data.len() != self.group_by.len() + self.aggregates.len() {
+            return Err(graph::constraint_violation!(
+                "aggregate query for `{}` returned {} values but expected {}",
+                self.table.object,
+                row.data.len(),
+                self.group_by.len() + self.aggregates.len()
+            ));
+        }
+        row.data
+            .into_iter()
+            .zip(self.column_types())
+            .map(|(text, column_type)| match text {
+                Some(text) => column_type.parse_text(&text),
+                None => Ok(Value::Null),
+            })
+            .collect()
+    }
+
+    /// Generate the text representation of `column`
+    fn column_as_text(column: &Column, out: &mut AstPass<Pg>) -> QueryResult<()> {
+        if column.column_type == ColumnType::Bytes {
+            out.push_sql("'0x' || encode(c.");
+            out.push_identifier(column.name.as_str())?;
+            out.push_sql(", 'hex')");
+        } else {
+            out.push_sql("c.");
+            out.push_identifier(column.name.as_str())?;
+            out.push_sql("::text");
+        }
+        Ok(())
+    }
+
+    fn group_by_columns(&self, out: &mut AstPass<Pg>) -> QueryResult<()> {
+        for (i, column) in self.group_by.iter().enumerate() {
+            if i > 0 {
+                out.push_sql(", ");
+            }
+            out.push_sql("c.");
+            out.push_identifier(column.name.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+/// String representation that is useful for debugging when `walk_ast` fails
+impl<'a> fmt::Display for AggregateQuery<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(
+            f,
+            "aggregate {} from {} group by {} {} at {}",
+            self.aggregates
+                .iter()
+                .map(|(aggregate, _)| aggregate.to_string())
+                .join(", "),
+            self.table.qualified_name,
+            self.group_by
+                .iter()
+                .map(|column| column.name.as_str())
+                .join(", "),
+            &self.range,
+            self.block
+        )
+    }
+}
+
+impl<'a> QueryFragment<Pg> for AggregateQuery<'a> {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+
+        if let Some(qid) = &self.query_id {
+            out.push_sql("/* controller='aggregate',application='");
+            out.push_sql(self.site.namespace.as_str());
+            out.push_sql("',route='");
+            out.push_sql(qid);
+            out.push_sql("',action='");
+            out.push_sql(&self.block.to_string());
+            out.push_sql("' */\n");
+        }
+
+        out.push_sql("select array[");
+        let mut first = true;
+        for column in &self.group_by {
+            if !first {
+                out.push_sql(", ");
+            }
+            first = false;
+            Self::column_as_text(column, &mut out)?;
+        }
+        for (aggregate, column) in &self.aggregates {
+            if !first {
+                out.push_sql(", ");
+            }
+            first = false;
+            let func = match aggregate {
+                EntityAggregate::Count => "count",
+                EntityAggregate::Sum(_) => "sum",
+                EntityAggregate::Min(_) => "min",
+                EntityAggregate::Max(_) => "max",
+            };
+            out.push_sql(func);
+            out.push_sql("(");
+            match column {
+                Some(column) => {
+                    out.push_sql("c.");
+                    out.push_identifier(column.name.as_str())?;
+                }
+                None => out.push_sql("*"),
+            }
+            out.push_sql(")::text");
+        }
+        out.push_sql("]::text[] as data");
+
+        out.push_sql("\n  from ");
+        out.push_sql(self.table.qualified_name.as_str());
+        out.push_sql(" c");
+
+        out.push_sql("\n where ");
+        BlockRangeColumn::new(self.table, "c.", self.block).contains(&mut out, false)?;
+        if let Some(filter) = &self.filter {
+            out.push_sql(" and ");
+            filter.walk_ast(out.reborrow())?;
+        }
+
+        if !self.group_by.is_empty() {
+            out.push_sql("\n group by ");
+            self.group_by_columns(&mut out)?;
+            out.push_sql("\n order by ");
+            self.group_by_columns(&mut out)?;
+        }
+        self.range.walk_ast(out)
+    }
+}
+
+impl<'a> QueryId for AggregateQuery<'a> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<'a> LoadQuery<PgConnection, AggregateData> for AggregateQuery<'a> {
+    fn internal_load(self, conn: &PgConnection) -> QueryResult<Vec<AggregateData>> {
+        conn.query_by_name(&self)
+    }
+}
+
+impl<'a, Conn> RunQueryDsl<Conn> for AggregateQuery<'a> {}
+
+/// One line of the output of an `ExplainQuery`
+pub struct ExplainLine(pub String);
+
+impl QueryableByName<Pg> for ExplainLine {
+    fn build<R: NamedRow<Pg>>(row: &R) -> diesel::deserialize::Result<Self> {
+        // Postgres names the only column of the output of `explain` like
+        // this; the name is not a valid identifier, which keeps us from
+        // deriving `QueryableByName`
+        row.get::<Text, String>("QUERY PLAN").map(ExplainLine)
+    }
+}
+
+/// Ask Postgres for the plan it would use to run `query` without running
+/// it
+#[derive(Debug, Clone)]
+pub struct ExplainQuery<Q> {
+    query: Q,
+}
+
+impl<Q: QueryFragment<Pg>> ExplainQuery<Q> {
+    pub fn new(query: Q) -> Self {
+        ExplainQuery { query }
+    }
+}
+
+impl<Q: QueryFragment<Pg>> QueryFragment<Pg> for ExplainQuery<Q> {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+        out.push_sql("explain ");
+        self.query.walk_ast(out)
+    }
+}
+
+impl<Q> QueryId for ExplainQuery<Q> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<Q: QueryFragment<Pg>> LoadQuery<PgConnection, ExplainLine> for ExplainQuery<Q> {
+    fn internal_load(self, conn: &PgConnection) -> QueryResult<Vec<ExplainLine>> {
+        conn.query_by_name(&self)
+    }
+}
+
+impl<Q, Conn> RunQueryDsl<Conn> for ExplainQuery<Q> {}
+
 /// Reduce the upper bound of the current entry's block range to `block` as
 /// long as that does not result in an empty block range
 #[derive(Debug)]
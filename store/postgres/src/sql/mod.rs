@@ -0,0 +1,24 @@
+//! Support for running SQL queries that users write directly against the
+//! tables of a deployment.
+//!
+//! Users write queries as if each entity type was a table with one column
+//! per attribute. Before a query is sent to the database, it is parsed and
+//! checked to only use a small subset of SQL: it must be a single `select`
+//! statement, can only call whitelisted functions, and can only refer to
+//! tables for the deployment's entity types. Each reference to an entity
+//! type is replaced by a subquery that selects the versions of the entities
+//! that are visible at the block at which the query is run, e.g.
+//!
+//! ```sql
+//!   select name from token
+//! ```
+//! becomes
+//! ```sql
+//!   select name
+//!     from (select "id", "name", .. from "sgd1"."token"
+//!            where block_range @> $block) as token
+//! ```
+mod parser;
+mod validation;
+
+pub(crate) use parser::Parser;
@@ -0,0 +1,167 @@
+use graph::prelude::{BlockNumber, QueryExecutionError};
+use graph::sqlparser::dialect::PostgreSqlDialect;
+use graph::sqlparser::parser::Parser as SqlParser;
+
+use crate::relational::Layout;
+
+use super::validation::Validator;
+
+/// Parses user-supplied SQL for a deployment and turns it into a query
+/// that can be run against the deployment's tables
+pub(crate) struct Parser<'a> {
+    layout: &'a Layout,
+    block: BlockNumber,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(layout: &'a Layout, block: BlockNumber) -> Self {
+        Self { layout, block }
+    }
+
+    /// Parse `sql`, check that it only uses constructs we allow, and
+    /// rewrite all references to entity types so that the query only sees
+    /// entities as of `self.block`. Return the rewritten query
+    pub fn parse_and_validate(&self, sql: &str) -> Result<String, QueryExecutionError> {
+        let mut statements = SqlParser::parse_sql(&PostgreSqlDialect {}, sql)
+            .map_err(|e| QueryExecutionError::SqlError(e.to_string()))?;
+
+        let mut statement = match statements.len() {
+            1 => statements.pop().unwrap(),
+            _ => {
+                return Err(QueryExecutionError::SqlError(
+                    "the query must consist of exactly one statement".to_string(),
+                ))
+            }
+        };
+
+        Validator::new(self.layout, self.block).validate_statement(&mut statement)?;
+        Ok(statement.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use graph::{data::subgraph::DeploymentHash, schema::InputSchema};
+
+    use crate::layout_for_tests::{make_dummy_site, Catalog, Namespace};
+
+    use super::*;
+
+    const SCHEMA: &str = r#"
+        type Token @entity {
+            id: ID!
+            name: String!
+            decimals: Int!
+        }
+
+        type Transfer @entity(immutable: true) {
+            id: Bytes!
+            token: Token!
+            amount: BigInt!
+        }
+
+        type _Schema_ @fulltext(
+            name: "tokenSearch",
+            language: en,
+            algorithm: rank,
+            include: [{ entity: "Token", fields: [{ name: "name" }] }]
+        )
+    "#;
+
+    fn layout() -> Layout {
+        let hash = DeploymentHash::new("sql").unwrap();
+        let nsp = Namespace::new("sgd0815".to_string()).unwrap();
+        let schema = InputSchema::parse_latest(SCHEMA, hash.clone()).unwrap();
+        let site = Arc::new(make_dummy_site(hash, nsp, "sql".to_string()));
+        let catalog = Catalog::for_tests(site.clone(), Default::default()).unwrap();
+        Layout::new(site, &schema, catalog).unwrap()
+    }
+
+    fn parse(sql: &str) -> Result<String, QueryExecutionError> {
+        let layout = layout();
+        Parser::new(&layout, 17).parse_and_validate(sql)
+    }
+
+    #[track_caller]
+    fn check_err(sql: &str, msg: &str) {
+        match parse(sql) {
+            Ok(query) => panic!("expected `{sql}` to fail but got `{query}`"),
+            Err(e) => {
+                let e = e.to_string();
+                assert!(e.contains(msg), "expected `{e}` to contain `{msg}`");
+            }
+        }
+    }
+
+    #[test]
+    fn rewrites_tables() {
+        let query = parse("select name from token").unwrap();
+        assert!(query.contains(r#"FROM "sgd0815"."token""#), "{query}");
+        assert!(query.contains("block_range @> 17"), "{query}");
+        assert!(query.contains(") AS token"), "{query}");
+        // Fulltext columns are not visible
+        assert!(!query.contains("token_search"), "{query}");
+
+        let query = parse("select t.amount from Transfer t").unwrap();
+        assert!(query.contains(r#"FROM "sgd0815"."transfer""#), "{query}");
+        assert!(query.contains(r#""block$" <= 17"#), "{query}");
+        assert!(query.contains(") AS t"), "{query}");
+
+        let query = parse(
+            "select t.name, sum(x.amount) from token t \
+               join transfer x on x.token = t.id \
+              group by t.name",
+        )
+        .unwrap();
+        assert!(query.contains(r#""sgd0815"."token""#), "{query}");
+        assert!(query.contains(r#""sgd0815"."transfer""#), "{query}");
+    }
+
+    #[test]
+    fn ctes_shadow_tables() {
+        let query =
+            parse("with token as (select * from token where decimals > 6) select * from token")
+                .unwrap();
+        assert_eq!(1, query.matches(r#""sgd0815"."token""#).count(), "{query}");
+    }
+
+    #[test]
+    fn rejects_unsafe_queries() {
+        check_err("delete from token", "only `select` statements");
+        check_err("select 1; select 2", "exactly one statement");
+        check_err("select * from pg_class", "unknown table pg_class");
+        check_err("select * from sgd0815.token", "can not be qualified");
+        check_err("select * from \"poi2$\"", "unknown table");
+        check_err("select pg_sleep(10)", "the function `pg_sleep`");
+        check_err(
+            "select * from token where id in (select current_setting('role'))",
+            "current_setting",
+        );
+        check_err("select * from token for update", "locking");
+        check_err("select * into evil from token", "SELECT INTO");
+        check_err("select * from generate_series(1, 10)", "table functions");
+    }
+
+    #[test]
+    fn rejects_casts_to_system_types() {
+        check_err("select 'pg_authid'::regclass", "the type `");
+        check_err(
+            "select * from token where 'pg_authid'::regclass::oid > 0",
+            "is not supported",
+        );
+        check_err("select cast('now' as regproc)", "is not supported");
+        check_err("select 'public'::regnamespace", "is not supported");
+        check_err("select 'pg_class'::pg_catalog.regclass", "is not supported");
+        check_err("select '{pg_class}'::regclass[]", "is not supported");
+        check_err("select regclass 'pg_class'", "is not supported");
+        check_err("select 1259::oid", "the type `");
+        check_err("select '(0,1)'::tid", "is not supported");
+
+        // Casts to ordinary types are fine, even when the type name
+        // contains `reg` somewhere other than the start
+        parse("select decimals::text, '1'::numeric, id::bytea from token").unwrap();
+        parse("select cast(decimals as bigint), '2024-01-01'::timestamptz").unwrap();
+    }
+}
@@ -0,0 +1,623 @@
+use graph::prelude::{BlockNumber, QueryExecutionError};
+use graph::sqlparser::ast as p;
+use graph::sqlparser::dialect::PostgreSqlDialect;
+use graph::sqlparser::parser::Parser as SqlParser;
+use itertools::Itertools;
+
+use crate::block_range::{BLOCK_COLUMN, BLOCK_RANGE_COLUMN};
+use crate::relational::{Layout, Table};
+
+/// The functions that queries are allowed to call. Function names are
+/// matched case-insensitively unless they are quoted
+const ALLOWED_FUNCTIONS: &[&str] = &[
+    // Aggregates
+    "avg",
+    "bit_and",
+    "bit_or",
+    "bool_and",
+    "bool_or",
+    "corr",
+    "count",
+    "covar_pop",
+    "covar_samp",
+    "every",
+    "json_agg",
+    "jsonb_agg",
+    "max",
+    "min",
+    "regr_intercept",
+    "regr_slope",
+    "stddev",
+    "stddev_pop",
+    "stddev_samp",
+    "string_agg",
+    "sum",
+    "var_pop",
+    "var_samp",
+    "variance",
+    // Window functions
+    "cume_dist",
+    "dense_rank",
+    "first_value",
+    "lag",
+    "last_value",
+    "lead",
+    "nth_value",
+    "ntile",
+    "percent_rank",
+    "rank",
+    "row_number",
+    // Conditionals
+    "coalesce",
+    "greatest",
+    "least",
+    "nullif",
+    // Math
+    "abs",
+    "cbrt",
+    "ceil",
+    "ceiling",
+    "div",
+    "exp",
+    "floor",
+    "gcd",
+    "lcm",
+    "ln",
+    "log",
+    "log10",
+    "mod",
+    "power",
+    "round",
+    "sign",
+    "sqrt",
+    "trunc",
+    "width_bucket",
+    // Strings and bytes
+    "ascii",
+    "btrim",
+    "char_length",
+    "character_length",
+    "concat",
+    "concat_ws",
+    "decode",
+    "encode",
+    "initcap",
+    "left",
+    "length",
+    "lower",
+    "lpad",
+    "ltrim",
+    "octet_length",
+    "regexp_replace",
+    "replace",
+    "reverse",
+    "right",
+    "rpad",
+    "rtrim",
+    "split_part",
+    "starts_with",
+    "strpos",
+    "substr",
+    "to_hex",
+    "translate",
+    "upper",
+    // Dates and times
+    "date_part",
+    "date_trunc",
+    "to_char",
+    "to_timestamp",
+    // Arrays and JSON
+    "array_length",
+    "array_to_string",
+    "cardinality",
+    "json_build_object",
+    "jsonb_build_object",
+    "to_json",
+    "to_jsonb",
+];
+
+/// Types that queries can not cast to since their values are looked up in,
+/// or refer to, the system catalogs. Casting a string to `regclass`, for
+/// example, resolves it to any table in the database, including ones
+/// outside of the deployment's namespace. Besides these, all types whose
+/// name starts with `reg` are rejected
+const FORBIDDEN_TYPES: &[&str] = &[
+    "aclitem",
+    "cid",
+    "oid",
+    "oidvector",
+    "pg_ddl_command",
+    "pg_lsn",
+    "pg_node_tree",
+    "pg_snapshot",
+    "tid",
+    "txid_snapshot",
+    "xid",
+    "xid8",
+];
+
+/// Check that values can be cast to `data_type`. The check looks at every
+/// identifier in the type so that qualified names like
+/// `pg_catalog.regclass` and arrays like `regclass[]` are also caught
+fn check_type(data_type: &p::DataType) -> Result<(), QueryExecutionError> {
+    let text = data_type.to_string().to_lowercase();
+    let forbidden = text
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .any(|name| name.starts_with("reg") || FORBIDDEN_TYPES.contains(&name));
+    if forbidden {
+        return Err(nope(&format!("the type `{data_type}`")));
+    }
+    Ok(())
+}
+
+fn nope(construct: &str) -> QueryExecutionError {
+    QueryExecutionError::SqlError(format!("{construct} is not supported"))
+}
+
+/// The name under which Postgres will look up `ident`
+fn normalize(ident: &p::Ident) -> String {
+    match ident.quote_style {
+        Some(_) => ident.value.clone(),
+        None => ident.value.to_lowercase(),
+    }
+}
+
+/// Walks the AST of a query, rejects anything that is not allowed, and
+/// replaces references to entity types with subqueries against the
+/// underlying tables that only return entities visible at `block`
+pub(super) struct Validator<'a> {
+    layout: &'a Layout,
+    block: BlockNumber,
+    /// The names of the common table expressions that are in scope
+    ctes: Vec<String>,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new(layout: &'a Layout, block: BlockNumber) -> Self {
+        Self {
+            layout,
+            block,
+            ctes: Vec::new(),
+        }
+    }
+
+    pub fn validate_statement(
+        &mut self,
+        statement: &mut p::Statement,
+    ) -> Result<(), QueryExecutionError> {
+        match statement {
+            p::Statement::Query(query) => self.visit_query(query),
+            _ => Err(QueryExecutionError::SqlError(
+                "only `select` statements are allowed".to_string(),
+            )),
+        }
+    }
+
+    fn visit_query(&mut self, query: &mut p::Query) -> Result<(), QueryExecutionError> {
+        if query.fetch.is_some() {
+            return Err(nope("FETCH"));
+        }
+        if !query.locks.is_empty() {
+            return Err(nope("locking"));
+        }
+
+        let scope = self.ctes.len();
+        if let Some(with) = &mut query.with {
+            for cte in &mut with.cte_tables {
+                // A recursive CTE can refer to itself; a normal one can
+                // only refer to the ones that come before it
+                let name = normalize(&cte.alias.name);
+                if with.recursive {
+                    self.ctes.push(name);
+                    self.visit_query(&mut cte.query)?;
+                } else {
+                    self.visit_query(&mut cte.query)?;
+                    self.ctes.push(name);
+                }
+            }
+        }
+        self.visit_set_expr(&mut query.body)?;
+        for order_by in &mut query.order_by {
+            self.visit_expr(&mut order_by.expr)?;
+        }
+        if let Some(limit) = &mut query.limit {
+            self.visit_expr(limit)?;
+        }
+        if let Some(offset) = &mut query.offset {
+            self.visit_expr(&mut offset.value)?;
+        }
+        self.ctes.truncate(scope);
+        Ok(())
+    }
+
+    fn visit_set_expr(&mut self, body: &mut p::SetExpr) -> Result<(), QueryExecutionError> {
+        match body {
+            p::SetExpr::Select(select) => self.visit_select(select),
+            p::SetExpr::Query(query) => self.visit_query(query),
+            p::SetExpr::SetOperation { left, right, .. } => {
+                self.visit_set_expr(left)?;
+                self.visit_set_expr(right)
+            }
+            p::SetExpr::Values(values) => {
+                for row in &mut values.rows {
+                    for expr in row {
+                        self.visit_expr(expr)?;
+                    }
+                }
+                Ok(())
+            }
+            _ => Err(QueryExecutionError::SqlError(
+                "only `select` statements are allowed".to_string(),
+            )),
+        }
+    }
+
+    fn visit_select(&mut self, select: &mut p::Select) -> Result<(), QueryExecutionError> {
+        let p::Select {
+            distinct,
+            top,
+            projection,
+            into,
+            from,
+            lateral_views,
+            selection,
+            group_by,
+            cluster_by,
+            distribute_by,
+            sort_by,
+            having,
+            named_window,
+            qualify,
+            ..
+        } = select;
+
+        if top.is_some() {
+            return Err(nope("TOP"));
+        }
+        if into.is_some() {
+            return Err(nope("SELECT INTO"));
+        }
+        if !lateral_views.is_empty() {
+            return Err(nope("LATERAL VIEW"));
+        }
+        if !cluster_by.is_empty() || !distribute_by.is_empty() || !sort_by.is_empty() {
+            return Err(nope("CLUSTER BY, DISTRIBUTE BY and SORT BY"));
+        }
+        if !named_window.is_empty() {
+            return Err(nope("WINDOW"));
+        }
+        if qualify.is_some() {
+            return Err(nope("QUALIFY"));
+        }
+
+        if let Some(p::Distinct::On(exprs)) = distinct {
+            for expr in exprs {
+                self.visit_expr(expr)?;
+            }
+        }
+        for table in from {
+            self.visit_table_with_joins(table)?;
+        }
+        for item in projection {
+            match item {
+                p::SelectItem::UnnamedExpr(expr) | p::SelectItem::ExprWithAlias { expr, .. } => {
+                    self.visit_expr(expr)?
+                }
+                p::SelectItem::QualifiedWildcard(..) | p::SelectItem::Wildcard(..) => {}
+            }
+        }
+        if let Some(selection) = selection {
+            self.visit_expr(selection)?;
+        }
+        if let p::GroupByExpr::Expressions(exprs) = group_by {
+            for expr in exprs {
+                self.visit_expr(expr)?;
+            }
+        }
+        if let Some(having) = having {
+            self.visit_expr(having)?;
+        }
+        Ok(())
+    }
+
+    fn visit_table_with_joins(
+        &mut self,
+        table: &mut p::TableWithJoins,
+    ) -> Result<(), QueryExecutionError> {
+        self.visit_table_factor(&mut table.relation)?;
+        for join in &mut table.joins {
+            self.visit_table_factor(&mut join.relation)?;
+            use p::JoinOperator::*;
+            match &mut join.join_operator {
+                Inner(constraint)
+                | LeftOuter(constraint)
+                | RightOuter(constraint)
+                | FullOuter(constraint) => {
+                    if let p::JoinConstraint::On(expr) = constraint {
+                        self.visit_expr(expr)?;
+                    }
+                }
+                CrossJoin => {}
+                _ => return Err(nope("this kind of join")),
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_table_factor(
+        &mut self,
+        factor: &mut p::TableFactor,
+    ) -> Result<(), QueryExecutionError> {
+        let replacement = match factor {
+            p::TableFactor::Table {
+                name,
+                alias,
+                args,
+                with_hints,
+                ..
+            } => {
+                if args.is_some() {
+                    return Err(nope("calling table functions"));
+                }
+                if !with_hints.is_empty() {
+                    return Err(nope("table hints"));
+                }
+                let ident = match name.0.as_slice() {
+                    [ident] => ident.clone(),
+                    _ => {
+                        return Err(QueryExecutionError::SqlError(format!(
+                            "table names can not be qualified: {name}"
+                        )))
+                    }
+                };
+                if self.ctes.contains(&normalize(&ident)) {
+                    return Ok(());
+                }
+                let table = self.find_table(&ident)?;
+                let subquery = self.table_query(table)?;
+                // Keep the name the user used so that qualified column
+                // references like `token.id` continue to work
+                let alias = alias.take().unwrap_or_else(|| p::TableAlias {
+                    name: ident,
+                    columns: vec![],
+                });
+                p::TableFactor::Derived {
+                    lateral: false,
+                    subquery: Box::new(subquery),
+                    alias: Some(alias),
+                }
+            }
+            p::TableFactor::Derived { subquery, .. } => return self.visit_query(subquery),
+            p::TableFactor::NestedJoin {
+                table_with_joins, ..
+            } => return self.visit_table_with_joins(table_with_joins),
+            _ => return Err(nope("this kind of table expression")),
+        };
+        *factor = replacement;
+        Ok(())
+    }
+
+    /// Find the table for the entity type `ident`. Unquoted names are
+    /// matched case-insensitively against both the name of the entity
+    /// type and the name of the table
+    fn find_table(&self, ident: &p::Ident) -> Result<&'a Table, QueryExecutionError> {
+        let matches = |name: &str| match ident.quote_style {
+            Some(_) => name == ident.value,
+            None => name.eq_ignore_ascii_case(&ident.value),
+        };
+        self.layout
+            .tables
+            .values()
+            .filter(|table| !table.object.is_poi())
+            .find(|table| matches(table.object.as_str()) || matches(table.name.as_str()))
+            .map(|table| table.as_ref())
+            .ok_or_else(|| QueryExecutionError::SqlError(format!("unknown table {ident}")))
+    }
+
+    /// A query that returns the versions of the entities in `table` that
+    /// are visible at `self.block`
+    fn table_query(&self, table: &Table) -> Result<p::Query, QueryExecutionError> {
+        let columns = table
+            .columns
+            .iter()
+            .filter(|column| !column.is_fulltext())
+            .map(|column| format!("\"{}\"", column.name))
+            .join(", ");
+        let filter = if table.immutable {
+            format!("\"{}\" <= {}", BLOCK_COLUMN, self.block)
+        } else {
+            format!("{} @> {}", BLOCK_RANGE_COLUMN, self.block)
+        };
+        let sql = format!(
+            "select {columns} from {} where {filter}",
+            table.qualified_name
+        );
+        let mut statements = SqlParser::parse_sql(&PostgreSqlDialect {}, &sql)
+            .map_err(|e| QueryExecutionError::SqlError(e.to_string()))?;
+        match statements.pop() {
+            Some(p::Statement::Query(query)) => Ok(*query),
+            _ => Err(QueryExecutionError::SqlError(format!(
+                "could not build query for table {}",
+                table.qualified_name
+            ))),
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &mut p::Expr) -> Result<(), QueryExecutionError> {
+        use p::Expr::*;
+
+        match expr {
+            Identifier(_) | CompoundIdentifier(_) | Value(_) => Ok(()),
+            TypedString { data_type, .. } => check_type(data_type),
+            BinaryOp { left, op, right } => {
+                if let p::BinaryOperator::Custom(_) | p::BinaryOperator::PGCustomBinaryOperator(_) =
+                    op
+                {
+                    return Err(nope(&format!("the operator `{op}`")));
+                }
+                self.visit_expr(left)?;
+                self.visit_expr(right)
+            }
+            Cast {
+                expr, data_type, ..
+            } => {
+                check_type(data_type)?;
+                self.visit_expr(expr)
+            }
+            UnaryOp { expr, .. }
+            | Nested(expr)
+            | IsFalse(expr)
+            | IsNotFalse(expr)
+            | IsTrue(expr)
+            | IsNotTrue(expr)
+            | IsNull(expr)
+            | IsNotNull(expr)
+            | Extract { expr, .. }
+            | Ceil { expr, .. }
+            | Floor { expr, .. } => self.visit_expr(expr),
+            AtTimeZone { timestamp, .. } => self.visit_expr(timestamp),
+            IsDistinctFrom(left, right) | IsNotDistinctFrom(left, right) => {
+                self.visit_expr(left)?;
+                self.visit_expr(right)
+            }
+            Like { expr, pattern, .. }
+            | ILike { expr, pattern, .. }
+            | SimilarTo { expr, pattern, .. } => {
+                self.visit_expr(expr)?;
+                self.visit_expr(pattern)
+            }
+            Between {
+                expr, low, high, ..
+            } => {
+                self.visit_expr(expr)?;
+                self.visit_expr(low)?;
+                self.visit_expr(high)
+            }
+            InList { expr, list, .. } => {
+                self.visit_expr(expr)?;
+                for expr in list {
+                    self.visit_expr(expr)?;
+                }
+                Ok(())
+            }
+            InSubquery { expr, subquery, .. } => {
+                self.visit_expr(expr)?;
+                self.visit_query(subquery)
+            }
+            Exists { subquery, .. } | Subquery(subquery) => self.visit_query(subquery),
+            Tuple(exprs) => {
+                for expr in exprs {
+                    self.visit_expr(expr)?;
+                }
+                Ok(())
+            }
+            Substring {
+                expr,
+                substring_from,
+                substring_for,
+                ..
+            } => {
+                self.visit_expr(expr)?;
+                if let Some(from) = substring_from {
+                    self.visit_expr(from)?;
+                }
+                if let Some(len) = substring_for {
+                    self.visit_expr(len)?;
+                }
+                Ok(())
+            }
+            Interval(interval) => self.visit_expr(&mut interval.value),
+            Case {
+                operand,
+                conditions,
+                results,
+                else_result,
+            } => {
+                if let Some(operand) = operand {
+                    self.visit_expr(operand)?;
+                }
+                for expr in conditions.iter_mut().chain(results.iter_mut()) {
+                    self.visit_expr(expr)?;
+                }
+                if let Some(else_result) = else_result {
+                    self.visit_expr(else_result)?;
+                }
+                Ok(())
+            }
+            Function(func) => self.visit_func(func),
+            _ => Err(nope(&format!("the expression `{expr}`"))),
+        }
+    }
+
+    fn visit_func(&mut self, func: &mut p::Function) -> Result<(), QueryExecutionError> {
+        let p::Function {
+            name,
+            args,
+            filter,
+            null_treatment: _,
+            over,
+            distinct: _,
+            special: _,
+            order_by,
+        } = func;
+
+        let allowed = match name.0.as_slice() {
+            [ident] => match ident.quote_style {
+                Some(_) => ALLOWED_FUNCTIONS.contains(&ident.value.as_str()),
+                None => ALLOWED_FUNCTIONS
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(&ident.value)),
+            },
+            _ => false,
+        };
+        if !allowed {
+            return Err(nope(&format!("the function `{name}`")));
+        }
+
+        for arg in args {
+            match arg {
+                p::FunctionArg::Named { .. } => return Err(nope("named function arguments")),
+                p::FunctionArg::Unnamed(p::FunctionArgExpr::Expr(expr)) => self.visit_expr(expr)?,
+                p::FunctionArg::Unnamed(
+                    p::FunctionArgExpr::Wildcard | p::FunctionArgExpr::QualifiedWildcard(_),
+                ) => {}
+            }
+        }
+        if let Some(filter) = filter {
+            self.visit_expr(filter)?;
+        }
+        for order_by in order_by {
+            self.visit_expr(&mut order_by.expr)?;
+        }
+        if let Some(p::WindowType::WindowSpec(spec)) = over {
+            for expr in &mut spec.partition_by {
+                self.visit_expr(expr)?;
+            }
+            for order_by in &mut spec.order_by {
+                self.visit_expr(&mut order_by.expr)?;
+            }
+            if let Some(frame) = &mut spec.window_frame {
+                self.visit_frame_bound(&mut frame.start_bound)?;
+                if let Some(end_bound) = &mut frame.end_bound {
+                    self.visit_frame_bound(end_bound)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_frame_bound(
+        &mut self,
+        bound: &mut p::WindowFrameBound,
+    ) -> Result<(), QueryExecutionError> {
+        match bound {
+            p::WindowFrameBound::CurrentRow => Ok(()),
+            p::WindowFrameBound::Preceding(expr) | p::WindowFrameBound::Following(expr) => {
+                match expr {
+                    Some(expr) => self.visit_expr(expr),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use async_trait::async_trait;
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
 use graph::{
@@ -10,7 +11,7 @@ use graph::{
         },
     },
     constraint_violation,
-    data::subgraph::status,
+    data::{query::PersistedQueries, subgraph::status},
     prelude::{
         web3::types::Address, BlockNumber, BlockPtr, CheapClone, DeploymentHash, PartialBlockPtr,
         QueryExecutionError, StoreError,
@@ -103,6 +104,19 @@ impl QueryStoreManager for Store {
             Arc::new(api_version.clone()),
         )))
     }
+
+    async fn persisted_queries(
+        &self,
+        target: graph::data::query::QueryTarget,
+    ) -> Result<Arc<PersistedQueries>, QueryExecutionError> {
+        let store = self.subgraph_store.cheap_clone();
+        graph::spawn_blocking_allow_panic(move || {
+            store.persisted_queries(target).map_err(|e| e.into())
+        })
+        .await
+        .map_err(|e| QueryExecutionError::Panic(e.to_string()))
+        .and_then(|x| x)
+    }
 }
 
 #[async_trait]
@@ -152,6 +166,16 @@ impl StatusStore for Store {
             .await
     }
 
+    async fn get_poi_digests(
+        &self,
+        subgraph_id: &DeploymentHash,
+        block_number: BlockNumber,
+    ) -> Result<Option<BTreeMap<String, Vec<u8>>>, StoreError> {
+        self.subgraph_store
+            .get_poi_digests(subgraph_id, block_number)
+            .await
+    }
+
     async fn get_public_proof_of_indexing(
         &self,
         subgraph_id: &DeploymentHash,
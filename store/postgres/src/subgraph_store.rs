@@ -8,24 +8,25 @@ use std::{
     collections::{BTreeMap, HashMap},
     sync::{atomic::AtomicU8, Arc, Mutex},
 };
-use std::{fmt, io::Write};
+use std::{fmt, io::Write, path::Path};
 use std::{iter::FromIterator, time::Duration};
 
 use graph::{
     cheap_clone::CheapClone,
     components::{
         server::index_node::VersionInfo,
+        sink::DeploymentSink,
         store::{
             self, BlockPtrForNumber, BlockStore, DeploymentLocator, EnsLookup as EnsLookupTrait,
             PruneReporter, PruneRequest, SubgraphFork,
         },
     },
     constraint_violation,
-    data::query::QueryTarget,
+    data::query::{PersistedQueries, QueryTarget},
     data::subgraph::{schema::DeploymentCreate, status, DeploymentFeatures},
     prelude::{
-        anyhow, futures03::future::join_all, lazy_static, o, web3::types::Address, ApiVersion,
-        BlockNumber, BlockPtr, ChainStore, DeploymentHash, EntityOperation, Logger,
+        anyhow, error, futures03::future::join_all, lazy_static, o, web3::types::Address,
+        ApiVersion, BlockNumber, BlockPtr, ChainStore, DeploymentHash, EntityOperation, Logger,
         MetricsRegistry, NodeId, PartialBlockPtr, StoreError, SubgraphDeploymentEntity,
         SubgraphName, SubgraphStore as SubgraphStoreTrait, SubgraphVersionSwitchingMode,
     },
@@ -40,8 +41,9 @@ use crate::{
     deployment::{OnSync, SubgraphHealth},
     primary,
     primary::{DeploymentId, Mirror as PrimaryMirror, Site},
-    relational::{index::Method, Layout},
-    writable::WritableStore,
+    relational::{dump::DumpMetadata, index::Method, Layout},
+    workload::IndexAdvice,
+    writable::{ReplayStore, WritableStore},
     NotificationSender,
 };
 use crate::{
@@ -63,6 +65,10 @@ lazy_static! {
 /// How long to cache information about a deployment site
 const SITES_CACHE_TTL: Duration = Duration::from_secs(120);
 
+/// How long to cache the persisted queries of a deployment. Changes made
+/// with `graphman persisted-query` take at most this long to take effect
+const PERSISTED_QUERIES_CACHE_TTL: Duration = Duration::from_secs(60);
+
 impl Shard {
     pub fn new(name: String) -> Result<Self, StoreError> {
         if name.is_empty() {
@@ -227,10 +233,11 @@ impl SubgraphStore {
         sender: Arc<NotificationSender>,
         fork_base: Option<Url>,
         registry: Arc<MetricsRegistry>,
+        sinks: Vec<Arc<dyn DeploymentSink>>,
     ) -> Self {
         Self {
             inner: Arc::new(SubgraphStoreInner::new(
-                logger, stores, placer, sender, registry,
+                logger, stores, placer, sender, registry, sinks,
             )),
             fork_base,
         }
@@ -245,6 +252,14 @@ impl SubgraphStore {
         self.inner.get_proof_of_indexing(id, indexer, block).await
     }
 
+    pub(crate) async fn get_poi_digests(
+        &self,
+        id: &DeploymentHash,
+        block_number: BlockNumber,
+    ) -> Result<Option<BTreeMap<String, Vec<u8>>>, StoreError> {
+        self.inner.get_poi_digests(id, block_number).await
+    }
+
     pub(crate) async fn get_public_proof_of_indexing(
         &self,
         id: &DeploymentHash,
@@ -260,6 +275,22 @@ impl SubgraphStore {
     pub fn notification_sender(&self) -> Arc<NotificationSender> {
         self.sender.clone()
     }
+
+    /// Return a store for replaying the blocks of `deployment` after
+    /// `start`. Unlike the store returned by `writable`, it never changes
+    /// the deployment; see `ReplayStore`
+    pub async fn replay_writable(
+        &self,
+        logger: Logger,
+        deployment: &DeploymentLocator,
+        start: BlockPtr,
+        manifest_idx_and_name: Arc<Vec<(u32, String)>>,
+    ) -> Result<Arc<ReplayStore>, StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        let store =
+            ReplayStore::new(self.clone(), logger, site, manifest_idx_and_name, start).await?;
+        Ok(Arc::new(store))
+    }
 }
 
 impl std::ops::Deref for SubgraphStore {
@@ -280,10 +311,19 @@ pub struct SubgraphStoreInner {
     /// different deployment for the same hash propagate across different
     /// graph-node processes over time.
     sites: TimedCache<DeploymentHash, Site>,
+    /// Cache for the persisted queries of each deployment, which are
+    /// needed for every query against the deployment
+    persisted_queries: TimedCache<DeploymentHash, PersistedQueries>,
+    /// Cache for the current deployment of subgraphs that are queried by
+    /// name, so that looking up their persisted queries does not need a
+    /// trip to the primary for every request
+    current_deployments: TimedCache<SubgraphName, DeploymentHash>,
     placer: Arc<dyn DeploymentPlacer + Send + Sync + 'static>,
     sender: Arc<NotificationSender>,
     writables: Mutex<HashMap<DeploymentId, Arc<WritableStore>>>,
     registry: Arc<MetricsRegistry>,
+    /// Sinks that get notified about changes that writable stores make
+    sinks: Vec<Arc<dyn DeploymentSink>>,
 }
 
 impl SubgraphStoreInner {
@@ -307,6 +347,7 @@ impl SubgraphStoreInner {
         placer: Arc<dyn DeploymentPlacer + Send + Sync + 'static>,
         sender: Arc<NotificationSender>,
         registry: Arc<MetricsRegistry>,
+        sinks: Vec<Arc<dyn DeploymentSink>>,
     ) -> Self {
         let mirror = {
             let pools = HashMap::from_iter(
@@ -332,14 +373,19 @@ impl SubgraphStoreInner {
             },
         ));
         let sites = TimedCache::new(SITES_CACHE_TTL);
+        let persisted_queries = TimedCache::new(PERSISTED_QUERIES_CACHE_TTL);
+        let current_deployments = TimedCache::new(PERSISTED_QUERIES_CACHE_TTL);
         SubgraphStoreInner {
             mirror,
             stores,
             sites,
+            persisted_queries,
+            current_deployments,
             placer,
             sender,
             writables: Mutex::new(HashMap::new()),
             registry,
+            sinks,
         }
     }
 
@@ -350,6 +396,8 @@ impl SubgraphStoreInner {
             store.layout_cache.clear();
         }
         self.sites.clear();
+        self.persisted_queries.clear();
+        self.current_deployments.clear();
     }
 
     // Only needed for tests
@@ -706,6 +754,10 @@ impl SubgraphStoreInner {
         self.create_deployment_internal(name, schema, deployment, node_id, network_name, mode, true)
     }
 
+    pub(crate) fn sinks(&self) -> &[Arc<dyn DeploymentSink>] {
+        &self.sinks
+    }
+
     pub(crate) fn send_store_event(&self, event: &StoreEvent) -> Result<(), StoreError> {
         let conn = self.primary_conn()?;
         conn.send_store_event(&self.sender, event)
@@ -750,6 +802,32 @@ impl SubgraphStoreInner {
         Ok((store.clone(), site, replica))
     }
 
+    /// Return the persisted queries of the deployment for `target`
+    pub(crate) fn persisted_queries(
+        &self,
+        target: QueryTarget,
+    ) -> Result<Arc<PersistedQueries>, StoreError> {
+        let id = match target {
+            QueryTarget::Name(name, _) => match self.current_deployments.get(&name) {
+                Some(id) => id.as_ref().clone(),
+                None => {
+                    let id = self.mirror.current_deployment_for_subgraph(&name)?;
+                    self.current_deployments.set(name, Arc::new(id.clone()));
+                    id
+                }
+            },
+            QueryTarget::Deployment(id, _) => id,
+        };
+
+        if let Some(queries) = self.persisted_queries.get(&id) {
+            return Ok(queries);
+        }
+
+        let queries = Arc::new(self.primary_conn()?.persisted_queries(&id)?);
+        self.persisted_queries.set(id, queries.cheap_clone());
+        Ok(queries)
+    }
+
     /// Delete all entities. This function exists solely for integration tests
     /// and should never be called from any other code. Unfortunately, Rust makes
     /// it very hard to export items just for testing
@@ -991,6 +1069,15 @@ impl SubgraphStoreInner {
         store.get_proof_of_indexing(site, indexer, block).await
     }
 
+    pub(crate) async fn get_poi_digests(
+        &self,
+        id: &DeploymentHash,
+        block_number: BlockNumber,
+    ) -> Result<Option<BTreeMap<String, Vec<u8>>>, StoreError> {
+        let (store, site) = self.store(id)?;
+        store.get_poi_digests(site, block_number).await
+    }
+
     pub(crate) async fn get_public_proof_of_indexing(
         &self,
         id: &DeploymentHash,
@@ -1072,6 +1159,15 @@ impl SubgraphStoreInner {
         .await;
     }
 
+    pub async fn flush_query_workload(&self, logger: &Logger) {
+        join_all(
+            self.stores
+                .values()
+                .map(|store| store.flush_query_workload(logger)),
+        )
+        .await;
+    }
+
     pub async fn refresh_materialized_views(&self, logger: &Logger) {
         join_all(
             self.stores
@@ -1090,6 +1186,88 @@ impl SubgraphStoreInner {
         store.analyze(site, entity_name)
     }
 
+    /// Write the entities of `deployment` as CSV files into `dir`, either
+    /// with their full history or as of `block`. See `Layout::dump` for
+    /// details
+    pub fn dump(
+        &self,
+        deployment: &DeploymentLocator,
+        dir: &Path,
+        history: bool,
+        block: Option<BlockNumber>,
+    ) -> Result<DumpMetadata, StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        let store = self.for_site(&site)?;
+        store.dump(site, dir, history, block)
+    }
+
+    /// Create a new deployment in `shard` from the dump in `dir` and
+    /// assign it to `node`. The dump must contain the full history of the
+    /// deployment, and the deployment must not exist yet. Once the
+    /// deployment has been restored, indexing continues from the block at
+    /// which the dump was taken
+    pub fn restore(
+        &self,
+        logger: &Logger,
+        dir: &Path,
+        shard: Shard,
+        node: NodeId,
+    ) -> Result<DeploymentLocator, StoreError> {
+        let metadata = DumpMetadata::read(dir)?;
+        let (hash, schema, deployment) = metadata.deployment_create(dir)?;
+
+        if let Some(site) = self.mirror.find_active_site(&hash)? {
+            return Err(StoreError::Unknown(anyhow!(
+                "deployment {} already exists in shard {}",
+                hash,
+                site.shard
+            )));
+        }
+
+        let deployment_store = self
+            .stores
+            .get(&shard)
+            .ok_or_else(|| StoreError::UnknownShard(shard.to_string()))?;
+
+        let (site, created) =
+            self.primary_conn()?
+                .allocate_site(shard, &hash, metadata.network.clone(), None)?;
+        if !created {
+            return Err(StoreError::Unknown(anyhow!(
+                "deployment {} was created while it was being restored",
+                hash
+            )));
+        }
+        let site = Arc::new(site);
+
+        let res = deployment_store
+            .restore(logger, site.clone(), &schema, deployment, &metadata, dir)
+            .and_then(|()| {
+                let pconn = self.primary_conn()?;
+                pconn.transaction(|| -> Result<_, StoreError> {
+                    let changes = pconn.assign_subgraph(site.as_ref(), &node)?;
+                    let event = StoreEvent::new(changes);
+                    pconn.send_store_event(&self.sender, &event)
+                })
+            });
+        if let Err(e) = res {
+            // The site was committed when it was allocated; remove it and
+            // whatever was restored so far so that the restore can be
+            // retried
+            if let Err(cleanup) = deployment_store
+                .drop_deployment(&site)
+                .and_then(|()| self.primary_conn()?.drop_site(site.as_ref()))
+            {
+                error!(logger, "Failed to clean up after failed restore";
+                    "deployment" => site.deployment.as_str(),
+                    "sgd" => site.namespace.as_str(),
+                    "error" => cleanup.to_string());
+            }
+            return Err(e);
+        }
+        Ok(site.as_ref().into())
+    }
+
     /// Return the statistics targets for all tables of `deployment`. The
     /// first return value is the default target, and the second value maps
     /// the name of each table to a map of column name to its statistics
@@ -1148,6 +1326,23 @@ impl SubgraphStoreInner {
         store.drop_index(site, index_name).await
     }
 
+    pub async fn advise_indexes(
+        &self,
+        deployment: &DeploymentLocator,
+    ) -> Result<IndexAdvice, StoreError> {
+        let (store, site) = self.store(&deployment.hash)?;
+        store.advise_indexes(site).await
+    }
+
+    pub async fn create_index(
+        &self,
+        deployment: &DeploymentLocator,
+        index: CreateIndex,
+    ) -> Result<(), StoreError> {
+        let (store, site) = self.store(&deployment.hash)?;
+        store.create_index(site, index).await
+    }
+
     pub async fn set_account_like(
         &self,
         deployment: &DeploymentLocator,
@@ -1158,6 +1353,29 @@ impl SubgraphStoreInner {
         store.set_account_like(site, table, is_account_like).await
     }
 
+    /// Start or stop recording the history of the PoI digests of
+    /// `deployment`; see `DeploymentStore::set_poi_history`
+    pub async fn set_poi_history(
+        &self,
+        deployment: &DeploymentLocator,
+        enabled: bool,
+    ) -> Result<usize, StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        let store = self.for_site(&site)?;
+        store.set_poi_history(site, enabled).await
+    }
+
+    /// Start or stop recording the entity change feed of `deployment`
+    pub async fn set_entity_feed(
+        &self,
+        deployment: &DeploymentLocator,
+        enabled: bool,
+    ) -> Result<(), StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        let store = self.for_site(&site)?;
+        store.set_entity_feed(site, enabled).await
+    }
+
     /// Prune the history according to the parameters in `req`.
     ///
     /// Pruning can take a long time, and is structured into multiple
@@ -0,0 +1,560 @@
+//! Record which columns GraphQL queries filter and sort by and how long
+//! they take, and use that to advise on which indexes a deployment should
+//! have. See `graphman index advise`
+use diesel::pg::PgConnection;
+use diesel::sql_types::{Array, BigInt, Integer, Text};
+use diesel::{sql_query, ExpressionMethods, QueryDsl, RunQueryDsl};
+use graph::components::store::{
+    EntityCollection, EntityFilter, EntityLink, EntityOrder, EntityQuery,
+};
+use graph::prelude::{StoreError, ENV_VARS};
+use itertools::Itertools;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::catalog::IndexUsage;
+use crate::primary::{DeploymentId, Site};
+use crate::relational::index::{CreateIndex, Expr, Method, PrefixKind};
+use crate::relational::{Column, ColumnType, Layout, SqlName, Table, PRIMARY_KEY_COLUMN};
+
+table! {
+    subgraphs.query_workload(deployment, table_name, filter_columns, order_columns) {
+        deployment -> Integer,
+        table_name -> Text,
+        filter_columns -> Array<Text>,
+        order_columns -> Array<Text>,
+        query_count -> BigInt,
+        total_ms -> BigInt,
+        slow_count -> BigInt,
+        last_seen -> Timestamptz,
+    }
+}
+
+/// The columns that the SQL query for one table filters and sorts by. The
+/// filter columns are sorted by name since their order in the query does
+/// not matter
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct QueryShape {
+    table: String,
+    filter: Vec<String>,
+    order: Vec<String>,
+}
+
+impl QueryShape {
+    /// The shapes of the SQL queries that `query` turns into, one for each
+    /// table that it reads from
+    pub(crate) fn for_query(layout: &Layout, query: &EntityQuery) -> Vec<QueryShape> {
+        let tables: Vec<(&Table, Option<&str>)> = match &query.collection {
+            EntityCollection::All(types) => types
+                .iter()
+                .filter_map(|(entity_type, _)| layout.table_for_entity(entity_type).ok())
+                .map(|table| (table.as_ref(), None))
+                .collect(),
+            EntityCollection::Window(windows) => windows
+                .iter()
+                .filter_map(|window| {
+                    let link = match &window.link {
+                        EntityLink::Direct(attr, _) => Some(attr.name()),
+                        EntityLink::Parent(_, _) => None,
+                    };
+                    layout
+                        .table_for_entity(&window.child_type)
+                        .ok()
+                        .map(|table| (table.as_ref(), link))
+                })
+                .collect(),
+        };
+
+        tables
+            .into_iter()
+            .map(|(table, link)| {
+                let mut filter = Vec::new();
+                if let Some(filter_) = &query.filter {
+                    filter_columns(table, filter_, &mut filter);
+                }
+                if let Some(column) = link.and_then(|link| table.column_for_field(link).ok()) {
+                    filter.push(column.name.to_string());
+                }
+                filter.sort();
+                filter.dedup();
+
+                let order = match &query.order {
+                    EntityOrder::Ascending(attr, _) | EntityOrder::Descending(attr, _) => table
+                        .column_for_field(attr)
+                        .map(|column| vec![column.name.to_string()])
+                        .unwrap_or_default(),
+                    EntityOrder::Default => vec![PRIMARY_KEY_COLUMN.to_string()],
+                    EntityOrder::ChildAscending(_)
+                    | EntityOrder::ChildDescending(_)
+                    | EntityOrder::Unordered => vec![],
+                };
+
+                QueryShape {
+                    table: table.name.to_string(),
+                    filter,
+                    order,
+                }
+            })
+            .unique()
+            .collect()
+    }
+}
+
+/// Add the columns of `table` that `filter` compares to `columns`.
+/// Fulltext searches and filters on child entities that are stored in
+/// other tables are ignored since a btree index on `table` would not help
+/// with them
+fn filter_columns(table: &Table, filter: &EntityFilter, columns: &mut Vec<String>) {
+    use EntityFilter::*;
+
+    let attr = match filter {
+        And(filters) | Or(filters) => {
+            for filter in filters {
+                filter_columns(table, filter, columns);
+            }
+            return;
+        }
+        Equal(attr, _)
+        | Not(attr, _)
+        | GreaterThan(attr, _)
+        | LessThan(attr, _)
+        | GreaterOrEqual(attr, _)
+        | LessOrEqual(attr, _)
+        | In(attr, _)
+        | NotIn(attr, _)
+        | Contains(attr, _)
+        | ContainsNoCase(attr, _)
+        | NotContains(attr, _)
+        | NotContainsNoCase(attr, _)
+        | StartsWith(attr, _)
+        | StartsWithNoCase(attr, _)
+        | NotStartsWith(attr, _)
+        | NotStartsWithNoCase(attr, _)
+        | EndsWith(attr, _)
+        | EndsWithNoCase(attr, _)
+        | NotEndsWith(attr, _)
+        | NotEndsWithNoCase(attr, _) => attr,
+        Child(child) if !child.derived => &child.attr,
+        Child(_) | Fulltext(_, _) | ChangeBlockGte(_) => return,
+    };
+    if let Ok(column) = table.column_for_field(attr) {
+        columns.push(column.name.to_string());
+    }
+}
+
+/// How often queries with a certain shape ran, how long they took in
+/// total, and how many of them took longer than
+/// `GRAPH_STORE_SLOW_QUERY_THRESHOLD`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ShapeStats {
+    count: i64,
+    total_ms: i64,
+    slow_count: i64,
+}
+
+impl ShapeStats {
+    fn add(&mut self, other: &ShapeStats) {
+        self.count += other.count;
+        self.total_ms += other.total_ms;
+        self.slow_count += other.slow_count;
+    }
+}
+
+/// The query workload that this node has seen since it last wrote it to
+/// the database. If writing fails, what was recorded is lost; since these
+/// are only statistics, that is preferable to letting them grow without
+/// bounds
+#[derive(Default)]
+pub(crate) struct QueryWorkload {
+    shapes: Mutex<HashMap<(DeploymentId, QueryShape), ShapeStats>>,
+}
+
+impl QueryWorkload {
+    pub(crate) fn record(&self, site: &Site, shapes: Vec<QueryShape>, elapsed: Duration) {
+        let stats = ShapeStats {
+            count: 1,
+            total_ms: elapsed.as_millis() as i64,
+            slow_count: (elapsed >= ENV_VARS.store.slow_query_threshold) as i64,
+        };
+        let mut recorded = self.shapes.lock().unwrap();
+        for shape in shapes {
+            recorded.entry((site.id, shape)).or_default().add(&stats);
+        }
+    }
+
+    /// Add everything that was recorded since the last call to
+    /// `subgraphs.query_workload`
+    pub(crate) fn flush(&self, conn: &PgConnection) -> Result<(), StoreError> {
+        // Deployments might have been deleted since we recorded queries
+        // for them, and we silently skip them
+        const QUERY: &str = "
+            insert into subgraphs.query_workload as w(
+                deployment, table_name, filter_columns, order_columns,
+                query_count, total_ms, slow_count)
+            select $1, $2, $3, $4, $5, $6, $7
+             where exists (select 1 from subgraphs.subgraph_deployment where id = $1)
+            on conflict(deployment, table_name, filter_columns, order_columns)
+            do update set query_count = w.query_count + excluded.query_count,
+                          total_ms = w.total_ms + excluded.total_ms,
+                          slow_count = w.slow_count + excluded.slow_count,
+                          last_seen = now()";
+
+        let shapes = std::mem::take(&mut *self.shapes.lock().unwrap());
+        for ((deployment, shape), stats) in shapes {
+            sql_query(QUERY)
+                .bind::<Integer, _>(deployment)
+                .bind::<Text, _>(&shape.table)
+                .bind::<Array<Text>, _>(&shape.filter)
+                .bind::<Array<Text>, _>(&shape.order)
+                .bind::<BigInt, _>(stats.count)
+                .bind::<BigInt, _>(stats.total_ms)
+                .bind::<BigInt, _>(stats.slow_count)
+                .execute(conn)?;
+        }
+        Ok(())
+    }
+}
+
+/// Load the query workload that was recorded for `site`
+pub(crate) fn load(
+    conn: &PgConnection,
+    site: &Site,
+) -> Result<Vec<(QueryShape, ShapeStats)>, StoreError> {
+    use query_workload as w;
+
+    let rows = w::table
+        .filter(w::deployment.eq(site.id))
+        .select((
+            w::table_name,
+            w::filter_columns,
+            w::order_columns,
+            w::query_count,
+            w::total_ms,
+            w::slow_count,
+        ))
+        .load::<(String, Vec<String>, Vec<String>, i64, i64, i64)>(conn)?;
+    Ok(rows
+        .into_iter()
+        .map(|(table, filter, order, count, total_ms, slow_count)| {
+            let shape = QueryShape {
+                table,
+                filter,
+                order,
+            };
+            let stats = ShapeStats {
+                count,
+                total_ms,
+                slow_count,
+            };
+            (shape, stats)
+        })
+        .collect())
+}
+
+/// An index that would help with queries that were slow
+pub struct IndexProposal {
+    pub table: String,
+    pub index: CreateIndex,
+    /// The number of queries that the index would help with
+    pub queries: i64,
+    /// How many of these queries were slow
+    pub slow_queries: i64,
+    /// How long these queries took in total, in milliseconds
+    pub total_ms: i64,
+}
+
+/// An index that no query has used since Postgres' statistics for it were
+/// last reset
+pub struct UnusedIndex {
+    pub table: String,
+    pub name: String,
+    /// The size of the index in bytes
+    pub size: i64,
+}
+
+pub struct IndexAdvice {
+    /// Proposed indexes, the ones that would help with the most slow
+    /// queries first
+    pub proposals: Vec<IndexProposal>,
+    pub unused: Vec<UnusedIndex>,
+    /// The number of read replicas whose index usage was added to that of
+    /// the main server to find unused indexes
+    pub replicas: usize,
+}
+
+/// Propose a btree index on the filter columns followed by the sort
+/// columns for each query shape that had slow queries and that no
+/// existing index covers, and list the indexes in `usage` that were never
+/// used. Indexes that every deployment needs, like the ones on `vid` and
+/// `id`, are never considered unused
+pub(crate) fn advise(
+    layout: &Layout,
+    workload: Vec<(QueryShape, ShapeStats)>,
+    usage: Vec<IndexUsage>,
+) -> IndexAdvice {
+    let indexes: Vec<_> = usage
+        .into_iter()
+        .map(|usage| (CreateIndex::parse(usage.defn.clone()), usage))
+        .collect();
+
+    let mut proposals: Vec<IndexProposal> = Vec::new();
+    for (shape, stats) in workload {
+        if stats.slow_count == 0 {
+            continue;
+        }
+        let table = match layout.table(&SqlName::verbatim(shape.table)) {
+            Some(table) => table,
+            None => continue,
+        };
+        let columns: Vec<&Column> = shape
+            .filter
+            .iter()
+            .chain(shape.order.iter())
+            .unique()
+            .filter_map(|name| table.column(&SqlName::verbatim(name.clone())))
+            .filter(|column| !column.is_list() && !column.is_fulltext())
+            .collect();
+        if columns.is_empty() {
+            continue;
+        }
+        let exprs: Vec<Expr> = columns.iter().map(|column| index_expr(column)).collect();
+
+        let covered = indexes
+            .iter()
+            .any(|(index, usage)| usage.table_name == table.name.as_str() && covers(index, &exprs));
+        if covered {
+            continue;
+        }
+
+        match proposals.iter_mut().find(|proposal| {
+            proposal.table == table.name.as_str() && covers(&proposal.index, &exprs)
+        }) {
+            Some(proposal) => {
+                proposal.queries += stats.count;
+                proposal.slow_queries += stats.slow_count;
+                proposal.total_ms += stats.total_ms;
+            }
+            None => {
+                let name = format!(
+                    "manual_{}_{}",
+                    table.name,
+                    columns.iter().map(|column| column.name.as_str()).join("_")
+                );
+                let index = CreateIndex::create(
+                    &name,
+                    layout.site.namespace.as_str(),
+                    table.name.as_str(),
+                    false,
+                    Method::BTree,
+                    exprs,
+                    None,
+                    None,
+                );
+                proposals.push(IndexProposal {
+                    table: table.name.to_string(),
+                    index,
+                    queries: stats.count,
+                    slow_queries: stats.slow_count,
+                    total_ms: stats.total_ms,
+                });
+            }
+        }
+    }
+    proposals.sort_by(|a, b| {
+        b.slow_queries
+            .cmp(&a.slow_queries)
+            .then(b.total_ms.cmp(&a.total_ms))
+    });
+
+    let unused = indexes
+        .into_iter()
+        .filter(|(index, usage)| {
+            usage.scans == 0
+                && matches!(index, CreateIndex::Parsed { unique: false, .. })
+                && (index.is_attribute_index() || !index.is_default_index())
+        })
+        .map(|(_, usage)| UnusedIndex {
+            table: usage.table_name,
+            name: usage.index_name,
+            size: usage.size,
+        })
+        .collect();
+
+    IndexAdvice {
+        proposals,
+        unused,
+        replicas: 0,
+    }
+}
+
+/// Add the number of scans in `other` to the ones in `usage` for the same
+/// index. Postgres counts index usage separately on each server, and an
+/// index is only unused if none of the servers used it
+pub(crate) fn add_usage(usage: &mut [IndexUsage], other: Vec<IndexUsage>) {
+    let scans: HashMap<_, _> = other
+        .into_iter()
+        .map(|other| (other.index_name, other.scans))
+        .collect();
+    for usage in usage {
+        usage.scans += scans.get(&usage.index_name).copied().unwrap_or(0);
+    }
+}
+
+/// The expression with which `column` would appear in an index so that
+/// the queries we generate can use it
+fn index_expr(column: &Column) -> Expr {
+    let name = column.name.to_string();
+    match (column.use_prefix_comparison, &column.column_type) {
+        (true, ColumnType::String) => Expr::Prefix(name, PrefixKind::Left),
+        (true, ColumnType::Bytes) => Expr::Prefix(name, PrefixKind::Substring),
+        _ => Expr::Column(name),
+    }
+}
+
+/// Return `true` if queries that filter and sort by `exprs` can use
+/// `index`, i.e., if `exprs` are the leading columns of a btree index.
+/// Gist indexes only cover lookups of a single column
+fn covers(index: &CreateIndex, exprs: &[Expr]) -> bool {
+    match index {
+        CreateIndex::Unknown { .. } => false,
+        CreateIndex::Parsed {
+            method,
+            columns,
+            cond,
+            ..
+        } => {
+            let usable = match method {
+                Method::BTree => true,
+                Method::Gist => exprs.len() == 1,
+                Method::Brin | Method::Gin | Method::Unknown(_) => false,
+            };
+            usable && cond.is_none() && columns.starts_with(exprs)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::sync::Arc;
+
+    use graph::prelude::{AttributeNames, DeploymentHash, Value, ValueType};
+    use graph::schema::InputSchema;
+
+    use crate::primary::{make_dummy_site, Namespace};
+    use crate::relational::Catalog;
+
+    use super::*;
+
+    const SCHEMA: &str = "
+        type Token @entity {
+            id: ID!
+            owner: String!
+            balance: BigInt!
+            tags: [String!]!
+        }";
+
+    fn test_layout() -> Layout {
+        let subgraph = DeploymentHash::new("subgraph").unwrap();
+        let schema = InputSchema::parse_latest(SCHEMA, subgraph.clone()).unwrap();
+        let namespace = Namespace::new("sgd0815".to_owned()).unwrap();
+        let site = Arc::new(make_dummy_site(subgraph, namespace, "anet".to_string()));
+        let catalog = Catalog::for_tests(site.clone(), BTreeSet::new()).unwrap();
+        Layout::new(site, &schema, catalog).unwrap()
+    }
+
+    fn usage(table: &str, name: &str, defn: &str, scans: i64) -> IndexUsage {
+        IndexUsage {
+            table_name: table.to_string(),
+            index_name: name.to_string(),
+            defn: defn.to_string(),
+            scans,
+            size: 8192,
+        }
+    }
+
+    #[test]
+    fn query_shapes() {
+        let layout = test_layout();
+        let token = layout.input_schema.entity_type("Token").unwrap();
+        let query = EntityQuery::new(
+            layout.site.deployment.clone(),
+            1,
+            EntityCollection::All(vec![(token, AttributeNames::All)]),
+        )
+        .filter(EntityFilter::And(vec![
+            EntityFilter::Equal("owner".to_string(), Value::String("0xa1".to_string())),
+            EntityFilter::Contains("tags".to_string(), Value::String("a".to_string())),
+        ]))
+        .order(EntityOrder::Descending(
+            "balance".to_string(),
+            ValueType::BigInt,
+        ));
+
+        let shapes = QueryShape::for_query(&layout, &query);
+        assert_eq!(
+            vec![QueryShape {
+                table: "token".to_string(),
+                filter: vec!["owner".to_string(), "tags".to_string()],
+                order: vec!["balance".to_string()],
+            }],
+            shapes
+        );
+
+        let slow = ShapeStats {
+            count: 3,
+            total_ms: 9000,
+            slow_count: 2,
+        };
+        let replica = vec![usage(
+            "token",
+            "attr_0_1_token_balance",
+            "CREATE INDEX attr_0_1_token_balance ON sgd0815.token USING btree (balance)",
+            3,
+        )];
+        let usage = vec![
+            usage(
+                "token",
+                "token_id_block_range_excl",
+                "CREATE INDEX token_id_block_range_excl ON sgd0815.token \
+                 USING gist (id, block_range)",
+                10,
+            ),
+            usage(
+                "token",
+                "attr_0_1_token_balance",
+                "CREATE INDEX attr_0_1_token_balance ON sgd0815.token USING btree (balance)",
+                0,
+            ),
+        ];
+        let advice = advise(
+            &layout,
+            vec![(shapes[0].clone(), slow.clone())],
+            usage.clone(),
+        );
+
+        // The list column `tags` is left out of the proposal
+        assert_eq!(1, advice.proposals.len());
+        let proposal = &advice.proposals[0];
+        assert_eq!(2, proposal.slow_queries);
+        assert_eq!(
+            "create index concurrently if not exists manual_token_owner_balance \
+             on sgd0815.token using btree (left(owner, 256), balance)",
+            proposal.index.to_sql(true, true).unwrap()
+        );
+
+        assert_eq!(1, advice.unused.len());
+        assert_eq!("attr_0_1_token_balance", advice.unused[0].name);
+
+        // An index that only a read replica used is not unused
+        let mut usage = usage;
+        add_usage(&mut usage, replica);
+        assert_eq!(
+            vec![10, 3],
+            usage.iter().map(|u| u.scans).collect::<Vec<_>>()
+        );
+        let advice = advise(&layout, vec![(shapes[0].clone(), slow)], usage);
+        assert!(advice.unused.is_empty());
+    }
+}
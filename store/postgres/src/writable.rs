@@ -7,6 +7,7 @@ use std::{collections::BTreeMap, sync::Arc};
 
 use graph::blockchain::block_stream::FirehoseCursor;
 use graph::blockchain::BlockTime;
+use graph::components::sink::DeploymentSink;
 use graph::components::store::{Batch, DeploymentCursorTracker, DerivedEntityQuery, ReadStore};
 use graph::constraint_violation;
 use graph::data::store::IdList;
@@ -27,8 +28,8 @@ use graph::{
     components::store::{self, write::EntityOp, WritableStore as WritableStoreTrait},
     data::subgraph::schema::SubgraphError,
     prelude::{
-        BlockPtr, DeploymentHash, EntityModification, Error, Logger, StopwatchMetrics, StoreError,
-        StoreEvent, UnfailOutcome, ENV_VARS,
+        anyhow::anyhow, BlockPtr, DeploymentHash, EntityModification, EntityOperation, Error,
+        Logger, StopwatchMetrics, StoreError, StoreEvent, UnfailOutcome, ENV_VARS,
     },
     slog::error,
 };
@@ -185,6 +186,13 @@ impl SyncStore {
         })
     }
 
+    /// Call `f` for each of the configured sinks
+    fn notify_sinks(&self, f: impl Fn(&dyn DeploymentSink)) {
+        for sink in self.store.0.sinks() {
+            f(sink.as_ref());
+        }
+    }
+
     /// Try to send a `StoreEvent`; if sending fails, log the error but
     /// return `Ok(())`
     fn try_send_store_event(&self, event: StoreEvent) -> Result<(), StoreError> {
@@ -250,7 +258,10 @@ impl SyncStore {
             self.last_rollup.set(block_time)?;
 
             self.try_send_store_event(event)
-        })
+        })?;
+
+        self.notify_sinks(|sink| sink.reverted(&self.site.deployment, &block_ptr_to));
+        Ok(())
     }
 
     fn unfail_deterministic_error(
@@ -284,7 +295,10 @@ impl SyncStore {
                     .await
             }
         })
-        .await
+        .await?;
+
+        self.notify_sinks(|sink| sink.failed(&self.site.deployment, &error));
+        Ok(())
     }
 
     async fn supports_proof_of_indexing(&self) -> Result<bool, StoreError> {
@@ -323,7 +337,15 @@ impl SyncStore {
             let _section = stopwatch.start_section("send_store_event");
             self.try_send_store_event(event)?;
             Ok(())
-        })
+        })?;
+
+        self.notify_sinks(|sink| {
+            sink.written(&self.site.deployment, batch);
+            if batch.is_non_fatal_errors_active && !batch.deterministic_errors.is_empty() {
+                sink.unhealthy(&self.site.deployment, &batch.deterministic_errors);
+            }
+        });
+        Ok(())
     }
 
     fn get_many(
@@ -447,7 +469,10 @@ impl SyncStore {
             self.writable.deployment_synced(&self.site.deployment)?;
 
             self.store.send_store_event(&event)
-        })
+        })?;
+
+        self.notify_sinks(|sink| sink.synced(&self.site.deployment));
+        Ok(())
     }
 
     fn shard(&self) -> &str {
@@ -1585,9 +1610,13 @@ impl WritableStoreTrait for WritableStore {
             .map_err(Error::from)??;
 
         // Refresh all in memory state in case this instance was used before
-        *self.block_ptr.lock().unwrap() = self.store.block_ptr().await?;
+        let block_ptr = self.store.block_ptr().await?;
+        *self.block_ptr.lock().unwrap() = block_ptr.clone();
         *self.block_cursor.lock().unwrap() = self.store.block_cursor().await?;
 
+        self.store
+            .notify_sinks(|sink| sink.started(&self.store.site.deployment, block_ptr.as_ref()));
+
         Ok(())
     }
 
@@ -1752,3 +1781,310 @@ impl WritableStoreTrait for WritableStore {
         }
     }
 }
+
+/// A `WritableStore` for replaying the blocks of a deployment after
+/// `start`. Entities are read as they were at `start`, and everything
+/// that is written is kept in memory so that the deployment itself is
+/// never changed. Replaying can not go back past `start`, and reverts are
+/// therefore not supported
+pub struct ReplayStore {
+    store: SyncStore,
+    start: BlockNumber,
+    block_ptr: Mutex<BlockPtr>,
+    /// Everything that was written since `start`
+    batch: Mutex<Option<Batch>>,
+    /// The changes to entities in each block that was replayed
+    changes: Mutex<BTreeMap<BlockNumber, Vec<EntityOperation>>>,
+    /// The error that stopped the replay
+    error: Mutex<Option<SubgraphError>>,
+}
+
+impl ReplayStore {
+    pub(crate) async fn new(
+        subgraph_store: SubgraphStore,
+        logger: Logger,
+        site: Arc<Site>,
+        manifest_idx_and_name: Arc<Vec<(u32, String)>>,
+        start: BlockPtr,
+    ) -> Result<Self, StoreError> {
+        let store = SyncStore::new(
+            subgraph_store,
+            logger,
+            site,
+            manifest_idx_and_name,
+            Some(start.number),
+        )
+        .await?;
+        Ok(Self {
+            store,
+            start: start.number,
+            block_ptr: Mutex::new(start),
+            batch: Mutex::new(None),
+            changes: Mutex::new(BTreeMap::new()),
+            error: Mutex::new(None),
+        })
+    }
+
+    /// The changes to entities that replaying `block` produced
+    pub fn entity_changes(&self, block: BlockNumber) -> Vec<EntityOperation> {
+        self.changes
+            .lock()
+            .unwrap()
+            .get(&block)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// The error that stopped the replay, if there was one
+    pub fn error(&self) -> Option<SubgraphError> {
+        self.error.lock().unwrap().clone()
+    }
+}
+
+impl ReadStore for ReplayStore {
+    fn get(&self, key: &EntityKey) -> Result<Option<Entity>, StoreError> {
+        let batch = self.batch.lock().unwrap();
+        match batch
+            .as_ref()
+            .and_then(|batch| batch.last_op(key, BLOCK_NUMBER_MAX))
+        {
+            Some(EntityOp::Write { key: _, entity }) => Ok(Some(entity.clone())),
+            Some(EntityOp::Remove { .. }) => Ok(None),
+            None => self.store.get(key, self.start),
+        }
+    }
+
+    fn get_many(
+        &self,
+        mut keys: BTreeSet<EntityKey>,
+    ) -> Result<BTreeMap<EntityKey, Entity>, StoreError> {
+        let mut replayed = BTreeMap::new();
+        if let Some(batch) = self.batch.lock().unwrap().as_ref() {
+            for key in &keys {
+                match batch.last_op(key, BLOCK_NUMBER_MAX) {
+                    Some(EntityOp::Write { key: _, entity }) => {
+                        replayed.insert(key.clone(), Some(entity.clone()));
+                    }
+                    Some(EntityOp::Remove { .. }) => {
+                        replayed.insert(key.clone(), None);
+                    }
+                    None => { /* nothing to do */ }
+                }
+            }
+        }
+
+        keys.retain(|key| !replayed.contains_key(key));
+        let mut map = self.store.get_many(keys, self.start)?;
+        map.extend(
+            replayed
+                .into_iter()
+                .filter_map(|(key, entity)| entity.map(|entity| (key, entity))),
+        );
+        Ok(map)
+    }
+
+    fn get_derived(
+        &self,
+        query: &DerivedEntityQuery,
+    ) -> Result<BTreeMap<EntityKey, Entity>, StoreError> {
+        let mut replayed = BTreeMap::new();
+        if let Some(batch) = self.batch.lock().unwrap().as_ref() {
+            for op in batch.effective_ops(&query.entity_type, BLOCK_NUMBER_MAX) {
+                match op {
+                    EntityOp::Write { key, entity } => {
+                        let related = entity
+                            .get(&query.entity_field)
+                            .map(|value| &query.value == value)
+                            .unwrap_or(false);
+                        if related {
+                            replayed.insert(key.clone(), Some(entity.clone()));
+                        }
+                    }
+                    EntityOp::Remove { key } => {
+                        replayed.insert(key.clone(), None);
+                    }
+                }
+            }
+        }
+
+        let excluded_keys = replayed.keys().cloned().collect();
+        let mut map = self.store.get_derived(query, self.start, excluded_keys)?;
+        map.extend(
+            replayed
+                .into_iter()
+                .filter_map(|(key, entity)| entity.map(|entity| (key, entity))),
+        );
+        Ok(map)
+    }
+
+    fn input_schema(&self) -> InputSchema {
+        self.store.input_schema()
+    }
+}
+
+impl DeploymentCursorTracker for ReplayStore {
+    fn block_ptr(&self) -> Option<BlockPtr> {
+        Some(self.block_ptr.lock().unwrap().clone())
+    }
+
+    fn firehose_cursor(&self) -> FirehoseCursor {
+        FirehoseCursor::None
+    }
+
+    fn input_schema(&self) -> InputSchema {
+        self.store.input_schema()
+    }
+}
+
+#[async_trait::async_trait]
+impl WritableStoreTrait for ReplayStore {
+    async fn start_subgraph_deployment(&self, _logger: &Logger) -> Result<(), StoreError> {
+        Ok(())
+    }
+
+    async fn revert_block_operations(
+        &self,
+        block_ptr_to: BlockPtr,
+        _firehose_cursor: FirehoseCursor,
+    ) -> Result<(), StoreError> {
+        Err(StoreError::Unknown(anyhow!(
+            "can not revert to block {} while replaying blocks after {}",
+            block_ptr_to,
+            self.start
+        )))
+    }
+
+    async fn unfail_deterministic_error(
+        &self,
+        _current_ptr: &BlockPtr,
+        _parent_ptr: &BlockPtr,
+    ) -> Result<UnfailOutcome, StoreError> {
+        Ok(UnfailOutcome::Noop)
+    }
+
+    fn unfail_non_deterministic_error(
+        &self,
+        _current_ptr: &BlockPtr,
+    ) -> Result<UnfailOutcome, StoreError> {
+        Ok(UnfailOutcome::Noop)
+    }
+
+    async fn fail_subgraph(&self, error: SubgraphError) -> Result<(), StoreError> {
+        *self.error.lock().unwrap() = Some(error);
+        Ok(())
+    }
+
+    async fn supports_proof_of_indexing(&self) -> Result<bool, StoreError> {
+        self.store.supports_proof_of_indexing().await
+    }
+
+    async fn transact_block_operations(
+        &self,
+        block_ptr_to: BlockPtr,
+        block_time: BlockTime,
+        firehose_cursor: FirehoseCursor,
+        mods: Vec<EntityModification>,
+        _stopwatch: &StopwatchMetrics,
+        data_sources: Vec<StoredDynamicDataSource>,
+        deterministic_errors: Vec<SubgraphError>,
+        processed_data_sources: Vec<StoredDynamicDataSource>,
+        is_non_fatal_errors_active: bool,
+        _is_caught_up_with_chain_head: bool,
+    ) -> Result<(), StoreError> {
+        let ops = mods
+            .iter()
+            .map(|emod| match emod {
+                EntityModification::Insert { key, data, .. }
+                | EntityModification::Overwrite { key, data, .. } => EntityOperation::Set {
+                    key: key.clone(),
+                    data: data.as_ref().clone(),
+                },
+                EntityModification::Remove { key, .. } => {
+                    EntityOperation::Remove { key: key.clone() }
+                }
+            })
+            .collect();
+
+        let batch = Batch::new(
+            block_ptr_to.clone(),
+            block_time,
+            firehose_cursor,
+            mods,
+            data_sources,
+            deterministic_errors,
+            processed_data_sources,
+            is_non_fatal_errors_active,
+        )?;
+        {
+            let mut replayed = self.batch.lock().unwrap();
+            match replayed.as_mut() {
+                Some(replayed) => replayed.append(batch)?,
+                None => *replayed = Some(batch),
+            }
+        }
+
+        self.changes
+            .lock()
+            .unwrap()
+            .insert(block_ptr_to.number, ops);
+        *self.block_ptr.lock().unwrap() = block_ptr_to;
+        Ok(())
+    }
+
+    fn deployment_synced(&self) -> Result<(), StoreError> {
+        Ok(())
+    }
+
+    fn is_deployment_synced(&self) -> bool {
+        false
+    }
+
+    fn unassign_subgraph(&self) -> Result<(), StoreError> {
+        Ok(())
+    }
+
+    async fn load_dynamic_data_sources(
+        &self,
+        manifest_idx_and_name: Vec<(u32, String)>,
+    ) -> Result<Vec<StoredDynamicDataSource>, StoreError> {
+        let mut dds = self
+            .store
+            .load_dynamic_data_sources(self.start, manifest_idx_and_name)
+            .await?;
+        if let Some(batch) = self.batch.lock().unwrap().as_ref() {
+            let mut replayed: Vec<_> = batch.new_data_sources(BLOCK_NUMBER_MAX).cloned().collect();
+            replayed.sort_by_key(|dds| dds.creation_block);
+            dds.append(&mut replayed);
+        }
+        Ok(dds)
+    }
+
+    async fn causality_region_curr_val(&self) -> Result<Option<CausalityRegion>, StoreError> {
+        // The data sources that existed at `start` determine the causality
+        // regions that replaying assigns to new data sources
+        let manifest_idx_and_name = self.store.manifest_idx_and_name.as_ref().clone();
+        let dds = self
+            .load_dynamic_data_sources(manifest_idx_and_name)
+            .await?;
+        Ok(dds.iter().map(|ds| ds.causality_region).max())
+    }
+
+    fn shard(&self) -> &str {
+        self.store.shard()
+    }
+
+    async fn health(&self) -> Result<schema::SubgraphHealth, StoreError> {
+        match self.error.lock().unwrap().as_ref() {
+            Some(_) => Ok(schema::SubgraphHealth::Failed),
+            None => Ok(schema::SubgraphHealth::Healthy),
+        }
+    }
+
+    async fn flush(&self) -> Result<(), StoreError> {
+        Ok(())
+    }
+
+    async fn restart(self: Arc<Self>) -> Result<Option<Arc<dyn WritableStoreTrait>>, StoreError> {
+        Ok(None)
+    }
+}
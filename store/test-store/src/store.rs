@@ -414,6 +414,39 @@ pub fn insert_ens_name(hash: &str, name: &str) {
         .unwrap();
 }
 
+/// The names of all deployment schemas in the primary database
+pub fn deployment_schemas() -> Vec<String> {
+    use diesel::dsl::sql;
+    use diesel::prelude::*;
+    use diesel::sql_types::{Array, Text};
+
+    let conn = PRIMARY_POOL.get().unwrap();
+
+    diesel::select(sql::<Array<Text>>(
+        "array(select nspname::text from pg_namespace where nspname like 'sgd%' order by nspname)",
+    ))
+    .get_result::<Vec<String>>(&conn)
+    .unwrap()
+}
+
+/// The blocks for which the history of the PoI digests of `deployment`
+/// has an entry. The deployment must be in the primary database
+pub fn poi_history_blocks(deployment: &DeploymentLocator) -> Vec<BlockNumber> {
+    use diesel::dsl::sql;
+    use diesel::prelude::*;
+    use diesel::sql_types::{Array, Integer};
+
+    let conn = PRIMARY_POOL.get().unwrap();
+
+    let query = format!(
+        "array(select block_number from sgd{}.\"poi_history$\" order by block_number)",
+        deployment.id
+    );
+    diesel::select(sql::<Array<Integer>>(&query))
+        .get_result::<Vec<BlockNumber>>(&conn)
+        .unwrap()
+}
+
 /// Insert the given entities and wait until all writes have been processed.
 /// The inserts all happen at `GENESIS_PTR`, i.e., block 0
 pub async fn insert_entities(
@@ -18,7 +18,7 @@ use hex_literal::hex;
 use graph::semver::Version;
 use lazy_static::lazy_static;
 use slog::Logger;
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::marker::PhantomData;
 use std::sync::Arc;
 use web3::types::H256;
@@ -309,6 +309,72 @@ fn consecutive_modifications() {
     );
 }
 
+#[test]
+fn track_entity_types() {
+    let store = Arc::new(MockStore::new(BTreeMap::new()));
+    let mut cache = EntityCache::new(store);
+
+    // Nothing is tracked unless asked for
+    cache
+        .get(&make_band_key("mogwai"), GetScope::Store)
+        .unwrap();
+    assert!(cache.accessed_entity_types().is_none());
+
+    cache.track_entity_types();
+    assert!(cache.accessed_entity_types().unwrap().is_empty());
+
+    cache
+        .get(&make_band_key("mogwai"), GetScope::InBlock)
+        .unwrap();
+    let band = SCHEMA.entity_type("Band").unwrap();
+    assert_eq!(
+        Some(HashSet::from([band.clone()])),
+        cache.accessed_entity_types()
+    );
+
+    cache.remove(make_band_key("sigurros"));
+    assert_eq!(Some(HashSet::from([band])), cache.accessed_entity_types());
+    assert!(!cache.generated_ids());
+}
+
+#[test]
+fn prefetch_and_track_loads() {
+    let mogwai_data = entity! { SCHEMA => id: "mogwai", name: "Mogwai" };
+    let store = Arc::new(MockStore::new(BTreeMap::from([(
+        make_band_key("mogwai"),
+        mogwai_data.clone(),
+    )])));
+    let mut cache = EntityCache::new(store);
+
+    let keys = BTreeSet::from([make_band_key("mogwai"), make_band_key("sigurros")]);
+    assert_eq!(2, cache.prefetch(keys.clone()).unwrap());
+    // Entities that are in the cache already are not loaded again
+    assert_eq!(0, cache.prefetch(keys).unwrap());
+
+    cache.track_loads();
+    assert_eq!(
+        Some(mogwai_data),
+        cache
+            .get(&make_band_key("mogwai"), GetScope::Store)
+            .unwrap()
+            .map(|entity| entity.as_ref().clone())
+    );
+    assert_eq!(
+        None,
+        cache
+            .get(&make_band_key("sigurros"), GetScope::Store)
+            .unwrap()
+    );
+    cache
+        .get(&make_band_key("mogwai"), GetScope::InBlock)
+        .unwrap();
+    assert_eq!(
+        vec![make_band_key("mogwai"), make_band_key("sigurros")],
+        cache.take_loads()
+    );
+    assert!(cache.take_loads().is_empty());
+}
+
 const ACCOUNT_GQL: &str = "
     type Account @entity {
         id: ID!
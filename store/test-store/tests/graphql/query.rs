@@ -26,7 +26,7 @@ use graph::{
         subgraph::SubgraphFeature,
     },
     prelude::{
-        futures03::stream::StreamExt, lazy_static, o, q, r, serde_json, slog, BlockPtr,
+        futures03::stream::StreamExt, hex, lazy_static, o, q, r, serde_json, slog, BlockPtr,
         DeploymentHash, Entity, EntityOperation, FutureExtension, GraphQlRunner as _, Logger,
         NodeId, Query, QueryError, QueryExecutionError, QueryResult, QueryStoreManager,
         QueryVariables, SubgraphManifest, SubgraphName, SubgraphStore,
@@ -1641,6 +1641,313 @@ fn query_variables_are_used() {
     );
 }
 
+#[test]
+fn can_page_through_collections_with_cursors() {
+    const QUERY: &str = "
+    query musicians($after: String) {
+      musicians(first: 2, orderBy: name, after: $after) {
+        name
+        _cursor
+      }
+    }";
+
+    // The cursor for `m2` ('Lisa') when sorting by `name`
+    let after = hex::encode(r#"{"o":"name","v":"Lisa","id":"m2"}"#);
+    run_query((QUERY, object! { after: after }), |result, _| {
+        let data = extract_data!(result).unwrap();
+        let musicians = match &data {
+            r::Value::Object(obj) => match obj.get("musicians") {
+                Some(r::Value::List(musicians)) => musicians.clone(),
+                _ => panic!("expected a list of musicians but got {data:?}"),
+            },
+            _ => panic!("expected an object but got {data:?}"),
+        };
+        let names: Vec<_> = musicians
+            .iter()
+            .map(|musician| match musician {
+                r::Value::Object(obj) => {
+                    assert!(matches!(obj.get("_cursor"), Some(r::Value::String(_))));
+                    obj.get("name").cloned().unwrap()
+                }
+                _ => panic!("expected an object but got {musician:?}"),
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                r::Value::String("Tom".to_string()),
+                r::Value::String("Valerie".to_string())
+            ],
+            names
+        );
+    });
+
+    // Cursors can not be used with a different sort order
+    const MISMATCH: &str = "
+    query musicians($after: String) {
+      musicians(first: 2, orderBy: id, after: $after) {
+        name
+      }
+    }";
+    let after = hex::encode(r#"{"o":"name","v":"Lisa","id":"m2"}"#);
+    run_query((MISMATCH, object! { after: after }), |result, _| {
+        assert!(result.has_errors());
+    });
+}
+
+/// Run `musicians(<args>) { id _cursor }` against the read-only test
+/// deployment and return the id and the cursor of each musician
+async fn musicians_page(
+    deployment: &DeploymentLocator,
+    args: &str,
+) -> Result<Vec<(String, String)>, Vec<QueryError>> {
+    let query = format!("query {{ musicians({args}) {{ id _cursor }} }}");
+    let query = Query::new(
+        graphql_parser::parse_query(&query).unwrap().into_static(),
+        None,
+        false,
+    );
+    let target = QueryTarget::Deployment(deployment.hash.clone(), Default::default());
+    let result = execute_subgraph_query(query, target).await;
+    let data = result.first().unwrap().duplicate().to_result()?.unwrap();
+
+    let musicians = match &data {
+        r::Value::Object(obj) => match obj.get("musicians") {
+            Some(r::Value::List(musicians)) => musicians.clone(),
+            _ => panic!("expected a list of musicians but got {data:?}"),
+        },
+        _ => panic!("expected an object but got {data:?}"),
+    };
+    let page = musicians
+        .into_iter()
+        .map(|musician| match musician {
+            r::Value::Object(obj) => match (obj.get("id"), obj.get("_cursor")) {
+                (Some(r::Value::String(id)), Some(r::Value::String(cursor))) => {
+                    (id.clone(), cursor.clone())
+                }
+                _ => panic!("expected an id and a cursor but got {obj:?}"),
+            },
+            _ => panic!("expected an object but got {musician:?}"),
+        })
+        .collect();
+    Ok(page)
+}
+
+fn page_ids(page: &[(String, String)]) -> Vec<&str> {
+    page.iter().map(|(id, _)| id.as_str()).collect()
+}
+
+/// Page through all musicians sorted by `order` one musician at a time by
+/// feeding the `_cursor` of each page back in as `after`, and return the
+/// ids in the order in which they were returned
+async fn page_through_musicians(deployment: &DeploymentLocator, order: &str) -> Vec<String> {
+    let mut ids = Vec::new();
+    let mut after: Option<String> = None;
+    loop {
+        let args = match &after {
+            Some(cursor) => format!("first: 1, {order}, after: \"{cursor}\""),
+            None => format!("first: 1, {order}"),
+        };
+        let page = musicians_page(deployment, &args).await.unwrap();
+        match page.into_iter().next() {
+            Some((id, cursor)) => {
+                ids.push(id);
+                after = Some(cursor);
+            }
+            None => return ids,
+        }
+        assert!(ids.len() <= 4, "paging does not terminate: {ids:?}");
+    }
+}
+
+#[test]
+fn cursors_round_trip() {
+    run_test_sequentially(|store| async move {
+        let deployment = setup_readonly(store.as_ref()).await;
+
+        // `mainBand` has ties (`m1` and `m2` are both in `b1`) and a null
+        // value (`m4`), which sorts last for `asc` and first for `desc`
+        let checks = [
+            ("orderBy: id", vec!["m1", "m2", "m3", "m4"]),
+            ("orderBy: name", vec!["m1", "m2", "m3", "m4"]),
+            (
+                "orderBy: name, orderDirection: desc",
+                vec!["m4", "m3", "m2", "m1"],
+            ),
+            ("orderBy: favoriteCount", vec!["m3", "m1", "m4", "m2"]),
+            (
+                "orderBy: favoriteCount, orderDirection: desc",
+                vec!["m2", "m4", "m1", "m3"],
+            ),
+            ("orderBy: mainBand", vec!["m1", "m2", "m3", "m4"]),
+            (
+                "orderBy: mainBand, orderDirection: desc",
+                vec!["m4", "m3", "m2", "m1"],
+            ),
+        ];
+        for (order, exp) in checks {
+            // Paging has to produce the same order as one big query
+            let all = musicians_page(&deployment, order).await.unwrap();
+            assert_eq!(exp, page_ids(&all), "{order}");
+            let ids = page_through_musicians(&deployment, order).await;
+            assert_eq!(exp, ids, "{order}");
+        }
+    })
+}
+
+#[test]
+fn cursors_restrict_collections_from_both_ends() {
+    run_test_sequentially(|store| async move {
+        let deployment = setup_readonly(store.as_ref()).await;
+
+        // Look up the cursor of `id` when sorting by `order`
+        async fn cursor(deployment: &DeploymentLocator, order: &str, id: &str) -> String {
+            musicians_page(deployment, order)
+                .await
+                .unwrap()
+                .into_iter()
+                .find(|(musician, _)| musician == id)
+                .map(|(_, cursor)| cursor)
+                .unwrap()
+        }
+
+        let checks = [
+            ("orderBy: name", "before", "m3", vec!["m1", "m2"]),
+            (
+                "orderBy: name, orderDirection: desc",
+                "before",
+                "m2",
+                vec!["m4", "m3"],
+            ),
+            (
+                "orderBy: name, orderDirection: desc",
+                "after",
+                "m3",
+                vec!["m2", "m1"],
+            ),
+            // Ties are broken by `id`
+            ("orderBy: mainBand", "after", "m1", vec!["m2", "m3", "m4"]),
+            ("orderBy: mainBand", "before", "m2", vec!["m1"]),
+            (
+                "orderBy: mainBand, orderDirection: desc",
+                "after",
+                "m2",
+                vec!["m1"],
+            ),
+            // Nulls come last for `asc` and first for `desc`
+            ("orderBy: mainBand", "after", "m3", vec!["m4"]),
+            ("orderBy: mainBand", "after", "m4", vec![]),
+            ("orderBy: mainBand", "before", "m4", vec!["m1", "m2", "m3"]),
+            (
+                "orderBy: mainBand, orderDirection: desc",
+                "after",
+                "m4",
+                vec!["m3", "m2", "m1"],
+            ),
+            (
+                "orderBy: mainBand, orderDirection: desc",
+                "before",
+                "m3",
+                vec!["m4"],
+            ),
+        ];
+        for (order, arg, id, exp) in checks {
+            let cursor = cursor(&deployment, order, id).await;
+            let args = format!("{order}, {arg}: \"{cursor}\"");
+            let page = musicians_page(&deployment, &args).await.unwrap();
+            assert_eq!(exp, page_ids(&page), "{args} with {arg} {id}");
+        }
+
+        // `after` and `before` can be combined
+        let after = cursor(&deployment, "orderBy: name", "m1").await;
+        let before = cursor(&deployment, "orderBy: name", "m4").await;
+        let args = format!("orderBy: name, after: \"{after}\", before: \"{before}\"");
+        let page = musicians_page(&deployment, &args).await.unwrap();
+        assert_eq!(vec!["m2", "m3"], page_ids(&page));
+
+        // A cursor can only be used with the `orderBy` it was created for
+        let by_name = cursor(&deployment, "orderBy: name", "m2").await;
+        let by_id = cursor(&deployment, "orderBy: id", "m2").await;
+        for (order, cursor) in [
+            ("orderBy: favoriteCount", &by_name),
+            ("orderBy: id", &by_name),
+            ("orderBy: name", &by_id),
+        ] {
+            let args = format!("{order}, after: \"{cursor}\"");
+            let errors = musicians_page(&deployment, &args).await.unwrap_err();
+            assert!(
+                errors
+                    .iter()
+                    .any(|e| e.to_string().contains("Invalid cursor")),
+                "{args}: {errors:?}"
+            );
+        }
+    })
+}
+
+#[test]
+fn can_aggregate_collections() {
+    const QUERY: &str = "
+    query {
+        all: musicians_aggregate {
+            count
+            group { name }
+            sum { favoriteCount }
+            min { favoriteCount }
+            max { favoriteCount }
+        }
+        filtered: musicians_aggregate(where: { name_not: \"John\" }) {
+            count
+        }
+        byBand: musicians_aggregate(groupBy: [mainBand], first: 2) {
+            count
+            group { mainBand name }
+            total: sum { favoriteCount }
+        }
+        songStats_aggregate {
+            count
+            sum { played }
+            max { played }
+        }
+    }
+    ";
+
+    run_query(QUERY, |result, _| {
+        let exp = object! {
+            all: vec![
+                object! {
+                    count: "4",
+                    group: r::Value::Null,
+                    sum: object! { favoriteCount: "135" },
+                    min: object! { favoriteCount: "5" },
+                    max: object! { favoriteCount: "100" },
+                }
+            ],
+            filtered: vec![object! { count: "3" }],
+            byBand: vec![
+                object! {
+                    count: "2",
+                    group: object! { mainBand: "b1", name: r::Value::Null },
+                    total: object! { favoriteCount: "110" },
+                },
+                object! {
+                    count: "1",
+                    group: object! { mainBand: "b2", name: r::Value::Null },
+                    total: object! { favoriteCount: "5" },
+                },
+            ],
+            songStats_aggregate: vec![
+                object! {
+                    count: "2",
+                    sum: object! { played: "25" },
+                    max: object! { played: 15 },
+                }
+            ]
+        };
+        let data = extract_data!(result).unwrap();
+        assert_eq!(data, exp);
+    })
+}
+
 #[test]
 fn mixed_parent_child_id() {
     // Check that any combination of parent and child id type (String or
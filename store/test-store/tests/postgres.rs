@@ -1,7 +1,10 @@
 pub mod postgres {
     pub mod aggregation;
     pub mod chain_head;
+    pub mod dump;
+    pub mod entity_feed;
     pub mod graft;
+    pub mod poi_history;
     pub mod relational;
     pub mod relational_bytes;
     pub mod store;
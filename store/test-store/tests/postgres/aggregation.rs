@@ -1,4 +1,8 @@
+use diesel::dsl::sql;
+use diesel::sql_types::BigInt;
+use diesel::RunQueryDsl as _;
 use std::fmt::Write;
+use std::str::FromStr;
 use std::{future::Future, sync::Arc};
 
 use graph::{
@@ -22,7 +26,10 @@ use graph::{
     schema::InputSchema,
 };
 use graph_store_postgres::{Store as DieselStore, SubgraphStore};
-use test_store::{create_test_subgraph, run_test_sequentially, BLOCKS, LOGGER, METRICS_REGISTRY};
+use test_store::{
+    create_test_subgraph, run_test_sequentially, run_test_with_conn, BLOCKS, LOGGER,
+    METRICS_REGISTRY,
+};
 
 const SCHEMA: &str = r#"
 type Data @entity(timeseries: true) {
@@ -31,6 +38,7 @@ type Data @entity(timeseries: true) {
     token: Bytes!
     price: BigDecimal!
     amount: BigDecimal!
+    volume: Int!
   }
 
   type Stats @aggregation(intervals: ["day", "hour"], source: "Data") {
@@ -51,6 +59,16 @@ type Data @entity(timeseries: true) {
     timestamp: Int8!
     max: BigDecimal! @aggregate(fn: "max", arg: "price")
   }
+
+  type Dist @aggregation(intervals: ["hour"], source: "Data") {
+    id: Int8!
+    timestamp: Int8!
+    avg: BigDecimal! @aggregate(fn: "avg", arg: "price")
+    avgVolume: Int! @aggregate(fn: "avg", arg: "volume")
+    stddev: BigDecimal! @aggregate(fn: "stddev", arg: "price")
+    median: BigDecimal! @aggregate(fn: "percentile", arg: "amount", percentile: 0.5)
+    tokens: Int8! @aggregate(fn: "count_distinct", arg: "token")
+  }
   "#;
 
 fn minutes(n: u32) -> BlockTime {
@@ -120,13 +138,17 @@ fn bd(n: i32) -> Value {
     Value::BigDecimal(BigDecimal::from(n))
 }
 
+fn bds(s: &str) -> Value {
+    Value::BigDecimal(BigDecimal::from_str(s).unwrap())
+}
+
 async fn insert_test_data(store: Arc<dyn WritableStore>, deployment: DeploymentLocator) {
     let schema = ReadStore::input_schema(&store);
 
     let ts64 = TIMES[0].as_secs_since_epoch();
     let entities = vec![
-        entity! { schema => id: 1i64, timestamp: ts64, token: TOKEN1.clone(), price: bd(1), amount: bd(10) },
-        entity! { schema => id: 2i64, timestamp: ts64, token: TOKEN2.clone(), price: bd(1), amount: bd(1) },
+        entity! { schema => id: 1i64, timestamp: ts64, token: TOKEN1.clone(), price: bd(1), amount: bd(10), volume: 1 },
+        entity! { schema => id: 2i64, timestamp: ts64, token: TOKEN2.clone(), price: bd(1), amount: bd(1), volume: 2 },
     ];
 
     insert(&store, &deployment, BLOCKS[0].clone(), TIMES[0], entities)
@@ -135,8 +157,8 @@ async fn insert_test_data(store: Arc<dyn WritableStore>, deployment: DeploymentL
 
     let ts64 = TIMES[1].as_secs_since_epoch();
     let entities = vec![
-        entity! { schema => id: 11i64, timestamp: ts64, token: TOKEN1.clone(), price: bd(2), amount: bd(2) },
-        entity! { schema => id: 12i64, timestamp: ts64, token: TOKEN2.clone(), price: bd(2), amount: bd(20) },
+        entity! { schema => id: 11i64, timestamp: ts64, token: TOKEN1.clone(), price: bd(2), amount: bd(2), volume: 4 },
+        entity! { schema => id: 12i64, timestamp: ts64, token: TOKEN2.clone(), price: bd(2), amount: bd(20), volume: 4 },
     ];
     insert(&store, &deployment, BLOCKS[1].clone(), TIMES[1], entities)
         .await
@@ -144,8 +166,8 @@ async fn insert_test_data(store: Arc<dyn WritableStore>, deployment: DeploymentL
 
     let ts64 = TIMES[2].as_secs_since_epoch();
     let entities = vec![
-        entity! { schema => id: 21i64, timestamp: ts64, token: TOKEN1.clone(), price: bd(3), amount: bd(30) },
-        entity! { schema => id: 22i64, timestamp: ts64, token: TOKEN2.clone(), price: bd(3), amount: bd(3) },
+        entity! { schema => id: 21i64, timestamp: ts64, token: TOKEN1.clone(), price: bd(3), amount: bd(30), volume: 5 },
+        entity! { schema => id: 22i64, timestamp: ts64, token: TOKEN2.clone(), price: bd(3), amount: bd(3), volume: 6 },
     ];
     insert(&store, &deployment, BLOCKS[2].clone(), TIMES[2], entities)
         .await
@@ -153,8 +175,8 @@ async fn insert_test_data(store: Arc<dyn WritableStore>, deployment: DeploymentL
 
     let ts64 = TIMES[3].as_secs_since_epoch();
     let entities = vec![
-        entity! { schema => id: 31i64, timestamp: ts64, token: TOKEN1.clone(), price: bd(4), amount: bd(4) },
-        entity! { schema => id: 32i64, timestamp: ts64, token: TOKEN2.clone(), price: bd(4), amount: bd(40) },
+        entity! { schema => id: 31i64, timestamp: ts64, token: TOKEN1.clone(), price: bd(4), amount: bd(4), volume: 7 },
+        entity! { schema => id: 32i64, timestamp: ts64, token: TOKEN2.clone(), price: bd(4), amount: bd(40), volume: 8 },
     ];
     insert(&store, &deployment, BLOCKS[3].clone(), TIMES[3], entities)
         .await
@@ -299,3 +321,73 @@ fn simple() {
         }
     })
 }
+
+#[test]
+fn statistics() {
+    run_test(|env| async move {
+        let schema = env.writable.input_schema();
+
+        // Hour 0 has prices 1, 1, 2, 2, amounts 10, 1, 2, 20 and volumes
+        // 1, 2, 4, 4; hour 1 has prices 3, 3, amounts 30, 3 and volumes 5,
+        // 6. The average volume is rounded since `avgVolume` is an `Int`
+        let hour0 = entity! { schema => id: 12i64, timestamp: 0i64,
+        avg: bds("1.5"), avgVolume: 3,
+        stddev: bds("0.5"), median: bd(2), tokens: 2i64 };
+        let hour1 = entity! { schema => id: 22i64, timestamp: 3600i64,
+        avg: bd(3), avgVolume: 6,
+        stddev: bd(0), median: bd(3), tokens: 2i64 };
+        let exp = vec![vec![], vec![], vec![hour0.clone()], vec![hour0, hour1]];
+
+        for i in 0..4 {
+            let act = env.all_entities("Dist_hour", BLOCKS[i].number);
+            let diff = entity_diff(&exp[i], &act).unwrap();
+            if !diff.is_empty() {
+                panic!("entities for BLOCKS[{}] differ:\n{}", i, diff);
+            }
+            assert_eq!(exp[i], act, "entities for BLOCKS[{}] are the same", i);
+        }
+    })
+}
+
+#[test]
+fn count_distinct_is_approximate() {
+    run_test_with_conn(|conn| {
+        let count = |from: &str| -> i64 {
+            diesel::select(sql::<BigInt>(&format!(
+                "(select count_distinct_approx(v) from {from})"
+            )))
+            .get_result(conn)
+            .unwrap()
+        };
+
+        // Small counts are exact, and nulls are not counted
+        assert_eq!(0, count("generate_series(1, 0) v"));
+        assert_eq!(
+            0,
+            count("(select null::int4 as v from generate_series(1, 10)) s")
+        );
+        assert_eq!(
+            1,
+            count("(select 'a'::bytea as v from generate_series(1, 10)) s")
+        );
+        assert_eq!(
+            3,
+            count("(values ('\\x01'::bytea), ('\\x02'), (null), ('\\x01'), ('\\x03')) t(v)")
+        );
+
+        // Large counts are within a few percent of the actual count, no
+        // matter how often each value occurs
+        for n in [1_000, 10_000, 100_000] {
+            for repeat in [1, 3] {
+                let est = count(&format!(
+                    "(select v from generate_series(1, {n}) v, generate_series(1, {repeat})) s"
+                ));
+                let error = (est - n).abs() as f64 / n as f64;
+                assert!(
+                    error < 0.05,
+                    "estimate {est} for {n} values is off by {error}"
+                );
+            }
+        }
+    })
+}
@@ -0,0 +1,248 @@
+use graph::schema::{EntityType, InputSchema};
+use lazy_static::lazy_static;
+use std::fs;
+use std::path::{Path, PathBuf};
+use test_store::*;
+
+use graph::components::store::{DeploymentLocator, EntityQuery};
+use graph::{entity, prelude::*};
+use graph_store_postgres::command_support::dump::{DumpMetadata, METADATA_FILE};
+use graph_store_postgres::{SubgraphStore as DieselSubgraphStore, PRIMARY_SHARD};
+
+const THING_GQL: &str = "
+    type Thing @entity {
+        id: ID!,
+        name: String,
+        count: Int!,
+    }
+
+    type Event @entity(immutable: true) {
+        id: ID!,
+        thing: Thing!,
+    }
+";
+
+lazy_static! {
+    static ref SOURCE_ID: DeploymentHash = DeploymentHash::new("dumpSource").unwrap();
+    static ref RESTORED_ID: DeploymentHash = DeploymentHash::new("dumpRestored").unwrap();
+    static ref SCHEMA: InputSchema =
+        InputSchema::parse_latest(THING_GQL, SOURCE_ID.clone()).unwrap();
+    static ref THING_TYPE: EntityType = SCHEMA.entity_type("Thing").unwrap();
+    static ref EVENT_TYPE: EntityType = SCHEMA.entity_type("Event").unwrap();
+}
+
+/// A directory for the dump that is unique to the test `name` and empty
+fn dump_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("graph-dump-{}-{}", std::process::id(), name));
+    if dir.exists() {
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn set_thing(id: &str, name: Option<&str>, count: i32) -> EntityOperation {
+    let data = match name {
+        Some(name) => entity! { SCHEMA => id: id, name: name, count: count },
+        None => entity! { SCHEMA => id: id, count: count },
+    };
+    EntityOperation::Set {
+        key: THING_TYPE.parse_key(id).unwrap(),
+        data,
+    }
+}
+
+fn add_event(id: &str, thing: &str) -> EntityOperation {
+    EntityOperation::Set {
+        key: EVENT_TYPE.parse_key(id).unwrap(),
+        data: entity! { SCHEMA => id: id, thing: thing },
+    }
+}
+
+/// Create the source deployment and give it some history: entities are
+/// created, updated and removed across `BLOCKS[0..=2]`, and one of them
+/// has a name that needs quoting in CSV
+async fn insert_test_data(store: &Arc<DieselSubgraphStore>) -> DeploymentLocator {
+    let deployment = create_test_subgraph(&SOURCE_ID, THING_GQL).await;
+
+    let ops = vec![
+        set_thing("1", Some("one"), 1),
+        set_thing("2", Some("two, \"quoted\""), 2),
+        set_thing("3", None, 3),
+        add_event("e1", "1"),
+    ];
+    transact_and_wait(store, &deployment, BLOCKS[0].clone(), ops)
+        .await
+        .unwrap();
+
+    let ops = vec![set_thing("1", Some("uno"), 10), add_event("e2", "2")];
+    transact_and_wait(store, &deployment, BLOCKS[1].clone(), ops)
+        .await
+        .unwrap();
+
+    let ops = vec![EntityOperation::Remove {
+        key: THING_TYPE.parse_key("3").unwrap(),
+    }];
+    transact_and_wait(store, &deployment, BLOCKS[2].clone(), ops)
+        .await
+        .unwrap();
+
+    deployment
+}
+
+/// Take a dump with the full history of `deployment` and change it so
+/// that it restores into `RESTORED_ID`
+fn dump_for_restore(
+    store: &DieselSubgraphStore,
+    deployment: &DeploymentLocator,
+    dir: &Path,
+) -> DumpMetadata {
+    let mut metadata = store.dump(deployment, dir, true, None).unwrap();
+    metadata.deployment = RESTORED_ID.to_string();
+    let file = fs::File::create(dir.join(METADATA_FILE)).unwrap();
+    serde_json::to_writer_pretty(file, &metadata).unwrap();
+    metadata
+}
+
+fn all_entities(
+    store: &DieselSubgraphStore,
+    id: &DeploymentHash,
+    entity_type: &EntityType,
+    block: BlockNumber,
+) -> Vec<Entity> {
+    let query = EntityQuery::new(
+        id.clone(),
+        block,
+        EntityCollection::All(vec![(entity_type.clone(), AttributeNames::All)]),
+    );
+    store.find(query).unwrap()
+}
+
+fn run_test<R, F>(test: F)
+where
+    F: FnOnce(Arc<DieselSubgraphStore>, DeploymentLocator) -> R + Send + 'static,
+    R: std::future::Future<Output = ()> + Send + 'static,
+{
+    run_test_sequentially(|store| async move {
+        let subgraph_store = store.subgraph_store();
+        remove_subgraphs();
+
+        let deployment = insert_test_data(&subgraph_store).await;
+
+        test(subgraph_store, deployment).await;
+    });
+}
+
+#[test]
+fn dump_at_block() {
+    run_test(|store, deployment| async move {
+        let dir = dump_dir("at-block");
+
+        let metadata = store
+            .dump(&deployment, &dir, false, Some(BLOCKS[0].number))
+            .unwrap();
+        assert_eq!(Some(BLOCKS[0].number), metadata.block);
+        assert_eq!(BLOCKS[2].number, metadata.head_number);
+        assert!(metadata.data_sources.is_none());
+
+        let thing = metadata
+            .tables
+            .iter()
+            .find(|table| table.entity == "Thing")
+            .unwrap();
+        assert_eq!(3, thing.rows);
+        // A snapshot does not contain block ranges
+        assert_eq!(vec!["id", "name", "count"], thing.columns);
+
+        let csv = fs::read_to_string(dir.join(&thing.file)).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(
+            vec![
+                "\"id\",\"name\",\"count\"",
+                "\"1\",\"one\",\"1\"",
+                "\"2\",\"two, \"\"quoted\"\"\",\"2\"",
+                "\"3\",,\"3\"",
+            ],
+            lines
+        );
+
+        // A dump can not be taken beyond the deployment head
+        assert!(store
+            .dump(&deployment, &dir, false, Some(BLOCKS[3].number))
+            .is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    })
+}
+
+#[test]
+fn dump_and_restore() {
+    run_test(|store, deployment| async move {
+        let dir = dump_dir("restore");
+
+        let metadata = dump_for_restore(&store, &deployment, &dir);
+        assert_eq!(None, metadata.block);
+        assert!(metadata.data_sources.is_some());
+        let thing = metadata
+            .tables
+            .iter()
+            .find(|table| table.entity == "Thing")
+            .unwrap();
+        // Every version of every entity is in the dump
+        assert_eq!(4, thing.rows);
+
+        let restored = store
+            .restore(&LOGGER, &dir, PRIMARY_SHARD.clone(), NODE_ID.clone())
+            .unwrap();
+        assert_eq!(*RESTORED_ID, restored.hash);
+
+        // The restored deployment continues from where the source was
+        let state = deployment_state(STORE.as_ref(), &RESTORED_ID).await;
+        assert_eq!(BLOCKS[2].number, state.latest_block.number);
+
+        for entity_type in [&*THING_TYPE, &*EVENT_TYPE] {
+            for block in &BLOCKS[0..=2] {
+                let exp = all_entities(&store, &SOURCE_ID, entity_type, block.number);
+                let act = all_entities(&store, &RESTORED_ID, entity_type, block.number);
+                assert_eq!(exp, act, "{} at block {}", entity_type, block.number);
+            }
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    })
+}
+
+#[test]
+fn failed_restore_cleans_up() {
+    run_test(|store, deployment| async move {
+        let dir = dump_dir("failed-restore");
+
+        let metadata = dump_for_restore(&store, &deployment, &dir);
+        let thing = metadata
+            .tables
+            .iter()
+            .find(|table| table.entity == "Thing")
+            .unwrap();
+        let csv = dir.join(&thing.file);
+        let good = fs::read_to_string(&csv).unwrap();
+        fs::write(&csv, format!("{good}\"not enough columns\"\n")).unwrap();
+
+        let schemas = deployment_schemas();
+        let res = store.restore(&LOGGER, &dir, PRIMARY_SHARD.clone(), NODE_ID.clone());
+        assert!(res.is_err());
+
+        // Neither the site nor the schema of the restored deployment are
+        // left behind
+        assert!(store.locators(RESTORED_ID.as_str()).unwrap().is_empty());
+        assert_eq!(schemas, deployment_schemas());
+
+        // Once the dump is fixed, the restore can be retried
+        fs::write(&csv, good).unwrap();
+        store
+            .restore(&LOGGER, &dir, PRIMARY_SHARD.clone(), NODE_ID.clone())
+            .unwrap();
+        assert_eq!(1, store.locators(RESTORED_ID.as_str()).unwrap().len());
+
+        fs::remove_dir_all(&dir).unwrap();
+    })
+}
@@ -0,0 +1,277 @@
+use graph::schema::{EntityType, InputSchema};
+use lazy_static::lazy_static;
+use test_store::*;
+
+use graph::components::store::{DeploymentLocator, PruneReporter, PruneRequest};
+use graph::data::query::{FeedCursor, FeedEvent, FeedOp};
+use graph::{entity, prelude::*};
+use graph_store_postgres::{Store as DieselStore, SubgraphStore as DieselSubgraphStore};
+
+const THING_GQL: &str = "
+    type Thing @entity {
+        id: ID!,
+        count: Int!,
+    }
+";
+
+lazy_static! {
+    static ref TEST_SUBGRAPH_ID: DeploymentHash = DeploymentHash::new("entityFeed").unwrap();
+    static ref SCHEMA: InputSchema =
+        InputSchema::parse_latest(THING_GQL, TEST_SUBGRAPH_ID.clone()).unwrap();
+    static ref THING_TYPE: EntityType = SCHEMA.entity_type("Thing").unwrap();
+}
+
+fn set_thing(id: &str, count: i32) -> EntityOperation {
+    EntityOperation::Set {
+        key: THING_TYPE.parse_key(id).unwrap(),
+        data: entity! { SCHEMA => id: id, count: count },
+    }
+}
+
+fn remove_thing(id: &str) -> EntityOperation {
+    EntityOperation::Remove {
+        key: THING_TYPE.parse_key(id).unwrap(),
+    }
+}
+
+/// Write the changes for `block`: thing 1 is inserted in block 0, updated
+/// in block 1 and deleted in block 3; thing 2 is inserted in block 1.
+/// Block 2 does not change anything
+async fn transact(store: &Arc<DieselSubgraphStore>, deployment: &DeploymentLocator, block: usize) {
+    let ops = match block {
+        0 => vec![set_thing("1", 0)],
+        1 => vec![set_thing("1", 1), set_thing("2", 1)],
+        3 => vec![remove_thing("1")],
+        _ => vec![],
+    };
+    transact_and_wait(store, deployment, BLOCKS[block].clone(), ops)
+        .await
+        .unwrap();
+}
+
+async fn feed(
+    store: &DieselStore,
+    cursor: Option<FeedCursor>,
+) -> Result<Vec<FeedEvent>, QueryExecutionError> {
+    let target = QueryTarget::Deployment(TEST_SUBGRAPH_ID.clone(), Default::default());
+    store
+        .query_store(target, false)
+        .await
+        .unwrap()
+        .entity_feed(cursor)
+}
+
+/// The block, block hash, id and operation of the changes in the
+/// `changes` event `event`
+fn changes(event: &FeedEvent) -> Vec<(BlockNumber, String, String, FeedOp)> {
+    match event {
+        FeedEvent::Changes { changes, .. } => changes
+            .iter()
+            .map(|change| {
+                (
+                    change.block,
+                    change.hash.clone(),
+                    change.id.clone(),
+                    change.op,
+                )
+            })
+            .collect(),
+        FeedEvent::Revert { .. } => panic!("expected a changes event but got {:?}", event),
+    }
+}
+
+fn change(block: usize, id: &str, op: FeedOp) -> (BlockNumber, String, String, FeedOp) {
+    (
+        BLOCKS[block].number,
+        BLOCKS[block].hash_hex(),
+        id.to_string(),
+        op,
+    )
+}
+
+fn cursor(block: BlockNumber, revert: i64) -> Option<FeedCursor> {
+    Some(FeedCursor { block, revert })
+}
+
+/// Create the deployment and write block 0 before the feed is turned on,
+/// so that the feed starts at block 0
+fn run_test<R, F>(test: F)
+where
+    F: FnOnce(Arc<DieselStore>, DeploymentLocator) -> R + Send + 'static,
+    R: std::future::Future<Output = ()> + Send + 'static,
+{
+    run_test_sequentially(|store| async move {
+        let subgraph_store = store.subgraph_store();
+        remove_subgraphs();
+
+        let deployment = create_test_subgraph(&TEST_SUBGRAPH_ID, THING_GQL).await;
+        transact(&subgraph_store, &deployment, 0).await;
+
+        // The feed is only available once it is recorded
+        let res = feed(&store, None).await;
+        assert!(matches!(res, Err(QueryExecutionError::NotSupported(_))));
+
+        subgraph_store
+            .set_entity_feed(&deployment, true)
+            .await
+            .unwrap();
+
+        test(store, deployment).await;
+    });
+}
+
+#[test]
+fn record_and_follow() {
+    run_test(|store, deployment| async move {
+        let subgraph_store = store.subgraph_store();
+        for block in 1..=3 {
+            transact(&subgraph_store, &deployment, block).await;
+        }
+
+        // Without a cursor, the feed starts at the head
+        let events = feed(&store, None).await.unwrap();
+        assert_eq!(1, events.len());
+        assert_eq!(
+            FeedEvent::Changes {
+                block: 3,
+                hash: BLOCKS[3].hash_hex(),
+                changes: vec![],
+                cursor: FeedCursor {
+                    block: 3,
+                    revert: 0
+                },
+            },
+            events[0]
+        );
+
+        // The changes in block 0 were made before the feed started
+        let events = feed(&store, cursor(0, 0)).await.unwrap();
+        assert_eq!(1, events.len());
+        let exp = vec![
+            change(1, "1", FeedOp::Update),
+            change(1, "2", FeedOp::Insert),
+            change(3, "1", FeedOp::Delete),
+        ];
+        assert_eq!(exp, changes(&events[0]));
+        assert_eq!(cursor(3, 0), Some(events[0].cursor()));
+
+        // A consumer that has seen everything gets no events
+        let events = feed(&store, cursor(3, 0)).await.unwrap();
+        assert!(events.is_empty());
+
+        // Turning the feed off removes it
+        subgraph_store
+            .set_entity_feed(&deployment, false)
+            .await
+            .unwrap();
+        let res = feed(&store, cursor(0, 0)).await;
+        assert!(matches!(res, Err(QueryExecutionError::NotSupported(_))));
+    })
+}
+
+#[test]
+fn revert() {
+    run_test(|store, deployment| async move {
+        let subgraph_store = store.subgraph_store();
+        for block in 1..=3 {
+            transact(&subgraph_store, &deployment, block).await;
+        }
+
+        revert_block(&store, &deployment, &BLOCKS[1]).await;
+
+        // A consumer that has seen block 3 has to undo blocks 2 and 3
+        let events = feed(&store, cursor(3, 0)).await.unwrap();
+        assert_eq!(1, events.len());
+        let revert = match &events[0] {
+            FeedEvent::Revert {
+                block,
+                hash,
+                cursor,
+            } => {
+                assert_eq!(1, *block);
+                assert_eq!(&BLOCKS[1].hash_hex(), hash);
+                assert_eq!(1, cursor.block);
+                cursor.revert
+            }
+            event => panic!("expected a revert event but got {:?}", event),
+        };
+
+        // A consumer that had not seen the reverted blocks only gets the
+        // changes that are still there
+        let events = feed(&store, cursor(0, 0)).await.unwrap();
+        assert_eq!(1, events.len());
+        let exp = vec![
+            change(1, "1", FeedOp::Update),
+            change(1, "2", FeedOp::Insert),
+        ];
+        assert_eq!(exp, changes(&events[0]));
+
+        // Writing the reverted blocks again records them again
+        for block in 2..=3 {
+            transact(&subgraph_store, &deployment, block).await;
+        }
+        let events = feed(&store, cursor(1, revert)).await.unwrap();
+        assert_eq!(1, events.len());
+        assert_eq!(vec![change(3, "1", FeedOp::Delete)], changes(&events[0]));
+    })
+}
+
+#[test]
+fn revert_before_start() {
+    run_test(|store, deployment| async move {
+        let subgraph_store = store.subgraph_store();
+        transact(&subgraph_store, &deployment, 1).await;
+        subgraph_store
+            .set_entity_feed(&deployment, false)
+            .await
+            .unwrap();
+        // The feed starts at block 1 now
+        subgraph_store
+            .set_entity_feed(&deployment, true)
+            .await
+            .unwrap();
+        let res = feed(&store, cursor(0, 0)).await;
+        assert!(matches!(res, Err(QueryExecutionError::InvalidCursor(_))));
+
+        // Reverting to block 0 makes the feed start there
+        revert_block(&store, &deployment, &BLOCKS[0]).await;
+        transact(&subgraph_store, &deployment, 1).await;
+        let revert = feed(&store, None).await.unwrap()[0].cursor().revert;
+        let events = feed(&store, cursor(0, revert)).await.unwrap();
+        assert_eq!(1, events.len());
+        let exp = vec![
+            change(1, "1", FeedOp::Update),
+            change(1, "2", FeedOp::Insert),
+        ];
+        assert_eq!(exp, changes(&events[0]));
+    })
+}
+
+#[test]
+fn prune() {
+    struct Progress;
+    impl PruneReporter for Progress {}
+
+    run_test(|store, deployment| async move {
+        let subgraph_store = store.subgraph_store();
+        for block in 1..=3 {
+            transact(&subgraph_store, &deployment, block).await;
+        }
+
+        // Keep 2 blocks of history with blocks [0, 3], i.e., the earliest
+        // block is 1
+        let req = PruneRequest::new(&deployment, 2, 1, 0, 3).unwrap();
+        subgraph_store
+            .prune(Box::new(Progress), &deployment, req)
+            .await
+            .unwrap();
+
+        // Consumers that have seen block 1 can still follow the feed, but
+        // earlier cursors are rejected
+        let res = feed(&store, cursor(0, 0)).await;
+        assert!(matches!(res, Err(QueryExecutionError::InvalidCursor(_))));
+        let events = feed(&store, cursor(1, 0)).await.unwrap();
+        assert_eq!(1, events.len());
+        assert_eq!(vec![change(3, "1", FeedOp::Delete)], changes(&events[0]));
+    })
+}
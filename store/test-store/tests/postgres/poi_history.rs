@@ -0,0 +1,213 @@
+use graph::schema::{EntityType, InputSchema};
+use lazy_static::lazy_static;
+use std::collections::BTreeMap;
+use test_store::*;
+
+use graph::components::store::{DeploymentLocator, PruneReporter, PruneRequest, StatusStore};
+use graph::data::store::{scalar, ID};
+use graph::{entity, prelude::*};
+use graph_store_postgres::{Store as DieselStore, SubgraphStore as DieselSubgraphStore};
+
+const THING_GQL: &str = "
+    type Thing @entity {
+        id: ID!,
+        count: Int!,
+    }
+";
+
+lazy_static! {
+    static ref TEST_SUBGRAPH_ID: DeploymentHash = DeploymentHash::new("poiHistory").unwrap();
+    static ref GRAFTED_ID: DeploymentHash = DeploymentHash::new("poiHistoryGrafted").unwrap();
+    static ref SCHEMA: InputSchema =
+        InputSchema::parse_latest(THING_GQL, TEST_SUBGRAPH_ID.clone()).unwrap();
+    static ref THING_TYPE: EntityType = SCHEMA.entity_type("Thing").unwrap();
+    static ref POI_TYPE: EntityType = SCHEMA.poi_type();
+}
+
+/// Set the count of the one `Thing` so that every block has a change
+fn set_thing(count: i32) -> EntityOperation {
+    EntityOperation::Set {
+        key: THING_TYPE.parse_key("1").unwrap(),
+        data: entity! { SCHEMA => id: "1", count: count },
+    }
+}
+
+/// Set the PoI digest of causality region `region` to `digest`, the way
+/// the block processing does it
+fn set_digest(region: &str, digest: u8) -> EntityOperation {
+    let data = SCHEMA
+        .make_entity(vec![
+            (ID.clone(), Value::from(region)),
+            (
+                SCHEMA.poi_digest(),
+                Value::from(scalar::Bytes::from(&[digest][..])),
+            ),
+        ])
+        .unwrap();
+    EntityOperation::Set {
+        key: POI_TYPE.parse_key(region).unwrap(),
+        data,
+    }
+}
+
+async fn transact(store: &Arc<DieselSubgraphStore>, deployment: &DeploymentLocator, block: usize) {
+    // Block numbers double as the digests so they are easy to tell apart
+    let digests = match block {
+        0 => vec![set_digest("a", 0), set_digest("b", 0)],
+        1 => vec![set_digest("a", 1)],
+        3 => vec![set_digest("b", 3)],
+        _ => vec![],
+    };
+    let mut ops = vec![set_thing(block as i32)];
+    ops.extend(digests);
+    transact_and_wait(store, deployment, BLOCKS[block].clone(), ops)
+        .await
+        .unwrap();
+}
+
+/// The digests as of `block` keyed by causality region
+async fn digests(
+    store: &DieselStore,
+    id: &DeploymentHash,
+    block: BlockNumber,
+) -> BTreeMap<String, Vec<u8>> {
+    store.get_poi_digests(id, block).await.unwrap().unwrap()
+}
+
+fn exp_digests(a: u8, b: u8) -> BTreeMap<String, Vec<u8>> {
+    BTreeMap::from([("a".to_string(), vec![a]), ("b".to_string(), vec![b])])
+}
+
+/// Create the deployment and write blocks 0 and 1 before the history is
+/// enabled, so that enabling it has to pick up existing digests
+fn run_test<R, F>(test: F)
+where
+    F: FnOnce(Arc<DieselStore>, DeploymentLocator) -> R + Send + 'static,
+    R: std::future::Future<Output = ()> + Send + 'static,
+{
+    run_test_sequentially(|store| async move {
+        let subgraph_store = store.subgraph_store();
+        remove_subgraphs();
+
+        let deployment = create_test_subgraph(&TEST_SUBGRAPH_ID, THING_GQL).await;
+        for block in 0..=1 {
+            transact(&subgraph_store, &deployment, block).await;
+        }
+
+        let count = subgraph_store
+            .set_poi_history(&deployment, true)
+            .await
+            .unwrap();
+        assert_eq!(2, count);
+        assert_eq!(vec![0, 1], poi_history_blocks(&deployment));
+
+        test(store, deployment).await;
+    });
+}
+
+#[test]
+fn record_and_look_up() {
+    run_test(|store, deployment| async move {
+        let subgraph_store = store.subgraph_store();
+        for block in 2..=3 {
+            transact(&subgraph_store, &deployment, block).await;
+        }
+
+        // Block 2 did not change any digests and therefore has no entry
+        assert_eq!(vec![0, 1, 3], poi_history_blocks(&deployment));
+
+        let id = &*TEST_SUBGRAPH_ID;
+        assert_eq!(exp_digests(0, 0), digests(&store, id, 0).await);
+        assert_eq!(exp_digests(1, 0), digests(&store, id, 1).await);
+        assert_eq!(exp_digests(1, 0), digests(&store, id, 2).await);
+        assert_eq!(exp_digests(1, 3), digests(&store, id, 3).await);
+
+        // Turning the history off removes it, but digests can still be
+        // found in the PoI table
+        let count = subgraph_store
+            .set_poi_history(&deployment, false)
+            .await
+            .unwrap();
+        assert_eq!(0, count);
+        assert_eq!(exp_digests(1, 3), digests(&store, id, 3).await);
+    })
+}
+
+#[test]
+fn revert() {
+    run_test(|store, deployment| async move {
+        let subgraph_store = store.subgraph_store();
+        for block in 2..=3 {
+            transact(&subgraph_store, &deployment, block).await;
+        }
+
+        revert_block(&store, &deployment, &BLOCKS[1]).await;
+        assert_eq!(vec![0, 1], poi_history_blocks(&deployment));
+        assert_eq!(
+            exp_digests(1, 0),
+            digests(&store, &TEST_SUBGRAPH_ID, 1).await
+        );
+
+        // Writing the reverted blocks again records them again
+        for block in 2..=3 {
+            transact(&subgraph_store, &deployment, block).await;
+        }
+        assert_eq!(vec![0, 1, 3], poi_history_blocks(&deployment));
+        assert_eq!(
+            exp_digests(1, 3),
+            digests(&store, &TEST_SUBGRAPH_ID, 3).await
+        );
+    })
+}
+
+#[test]
+fn prune() {
+    struct Progress;
+    impl PruneReporter for Progress {}
+
+    run_test(|store, deployment| async move {
+        let subgraph_store = store.subgraph_store();
+        for block in 2..=3 {
+            transact(&subgraph_store, &deployment, block).await;
+        }
+        assert_eq!(vec![0, 1, 3], poi_history_blocks(&deployment));
+
+        // Keep 2 blocks of history with blocks [0, 3], i.e., the earliest
+        // block is 1
+        let req = PruneRequest::new(&deployment, 2, 1, 0, 3).unwrap();
+        subgraph_store
+            .prune(Box::new(Progress), &deployment, req)
+            .await
+            .unwrap();
+
+        // The entry for block 1 is still needed to look up the digests at
+        // blocks 1 and 2, but the one for block 0 is not
+        assert_eq!(vec![1, 3], poi_history_blocks(&deployment));
+        let id = &*TEST_SUBGRAPH_ID;
+        assert_eq!(exp_digests(1, 0), digests(&store, id, 1).await);
+        assert_eq!(exp_digests(1, 0), digests(&store, id, 2).await);
+        assert_eq!(exp_digests(1, 3), digests(&store, id, 3).await);
+    })
+}
+
+#[test]
+fn copy() {
+    run_test(|store, deployment| async move {
+        let subgraph_store = store.subgraph_store();
+        for block in 2..=3 {
+            transact(&subgraph_store, &deployment, block).await;
+        }
+
+        // Grafting copies the PoI table up to the graft point, and the
+        // history has to follow it
+        let base = Some((TEST_SUBGRAPH_ID.clone(), BLOCKS[2].clone()));
+        let grafted = create_subgraph(&GRAFTED_ID, THING_GQL, base).await.unwrap();
+        assert_eq!(vec![0, 1], poi_history_blocks(&grafted));
+        assert_eq!(exp_digests(1, 0), digests(&store, &GRAFTED_ID, 2).await);
+
+        // The grafted deployment keeps recording the history
+        transact(&subgraph_store, &grafted, 3).await;
+        assert_eq!(vec![0, 1, 3], poi_history_blocks(&grafted));
+        assert_eq!(exp_digests(1, 3), digests(&store, &GRAFTED_ID, 3).await);
+    })
+}
@@ -752,6 +752,30 @@ fn count_scalar_entities(conn: &PgConnection, layout: &Layout) -> usize {
         .len()
 }
 
+#[test]
+fn explain_query() {
+    run_test(|conn, layout| {
+        insert_entity(conn, layout, &*SCALAR_TYPE, vec![SCALAR_ENTITY.clone()]);
+
+        let collection = EntityCollection::All(vec![(SCALAR_TYPE.to_owned(), AttributeNames::All)]);
+        let query = EntityQuery::new(layout.site.deployment.clone(), BLOCK_NUMBER_MAX, collection)
+            .filter(EntityFilter::Equal("bool".into(), true.into()));
+
+        let plan = layout
+            .explain_query(conn, query.clone(), false)
+            .expect("Failed to generate SQL");
+        assert!(plan.query.contains("scalar"));
+        assert!(plan.explain.is_none());
+
+        let plan = layout
+            .explain_query(conn, query, true)
+            .expect("Failed to explain query");
+        let explain = plan.explain.expect("explain output is present");
+        assert!(!explain.is_empty());
+        assert!(explain.iter().any(|line| line.contains("scalar")));
+    });
+}
+
 #[test]
 fn delete() {
     run_test(|conn, layout| {
@@ -1,4 +1,5 @@
 use graph::blockchain::block_stream::FirehoseCursor;
+use graph::blockchain::BlockTime;
 use graph::data::subgraph::schema::DeploymentCreate;
 use graph::data::value::Word;
 use graph::data_source::CausalityRegion;
@@ -8,12 +9,17 @@ use std::collections::BTreeSet;
 use std::marker::PhantomData;
 use test_store::*;
 
-use graph::components::store::{DeploymentLocator, DerivedEntityQuery, WritableStore};
+use graph::components::store::{
+    DeploymentCursorTracker, DeploymentLocator, DerivedEntityQuery, WritableStore,
+};
+use graph::components::subgraph::bisect;
 use graph::data::subgraph::*;
 use graph::semver::Version;
 use graph::{entity, prelude::*};
 use graph_store_postgres::layout_for_tests::writable;
-use graph_store_postgres::{Store as DieselStore, SubgraphStore as DieselSubgraphStore};
+use graph_store_postgres::{
+    ReplayStore, Store as DieselStore, SubgraphStore as DieselSubgraphStore,
+};
 use web3::types::H256;
 
 const SCHEMA_GQL: &str = "
@@ -286,3 +292,93 @@ fn restart() {
         writable.flush().await.unwrap();
     })
 }
+
+/// Set the count to `count` in block `block` in the `ReplayStore`
+async fn replay_count(replay: &Arc<ReplayStore>, block: u8, count: i32) {
+    let data = entity! { TEST_SUBGRAPH_SCHEMA => id: "1", count: count };
+    let mut entity_cache = EntityCache::new(replay.cheap_clone());
+    entity_cache.append(vec![EntityOperation::Set {
+        key: count_key("1"),
+        data,
+    }]);
+    let mods = entity_cache
+        .as_modifications(block as BlockNumber)
+        .unwrap()
+        .modifications;
+    let block_ptr = block_pointer(block);
+    let stopwatch = StopwatchMetrics::new(
+        LOGGER.clone(),
+        TEST_SUBGRAPH_ID.clone(),
+        "replay",
+        Arc::new(MetricsRegistry::mock()),
+        replay.shard().to_string(),
+    );
+    let block_time = BlockTime::for_test(&block_ptr);
+    replay
+        .transact_block_operations(
+            block_ptr,
+            block_time,
+            FirehoseCursor::None,
+            mods,
+            &stopwatch,
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            false,
+            false,
+        )
+        .await
+        .unwrap();
+}
+
+#[test]
+fn replay() {
+    run_test(|store, writable, deployment| async move {
+        let subgraph_store = store.subgraph_store();
+        for count in 1..5 {
+            insert_count(&subgraph_store, &deployment, count).await;
+        }
+        writable.flush().await.unwrap();
+
+        let replay = subgraph_store
+            .replay_writable(
+                LOGGER.clone(),
+                &deployment,
+                block_pointer(2),
+                Arc::new(Vec::new()),
+            )
+            .await
+            .unwrap();
+        // Replaying starts with the entities as they were at the start
+        assert_eq!(2, count_get(replay.as_ref()));
+
+        // Block 3 is replayed faithfully, block 4 is not
+        replay_count(&replay, 3, 3).await;
+        replay_count(&replay, 4, 40).await;
+        assert_eq!(40, count_get(replay.as_ref()));
+        assert_eq!(Some(block_pointer(4)), replay.block_ptr());
+        replay
+            .revert_block_operations(block_pointer(3), FirehoseCursor::None)
+            .await
+            .expect_err("replaying can not revert");
+
+        // Nothing was written to the deployment
+        writable.flush().await.unwrap();
+        assert_eq!(4, count_get(writable.as_ref()));
+        let state = deployment_state(store.as_ref(), &deployment.hash).await;
+        assert_eq!(4, state.latest_block.number);
+
+        // Only the changes in block 4 differ from the stored changes
+        for (block, same) in [(3, true), (4, false)] {
+            let replayed = bisect::entity_changes(replay.entity_changes(block));
+            let stored = bisect::entity_changes(
+                subgraph_store
+                    .entity_changes_in_block(&deployment.hash, block)
+                    .unwrap(),
+            );
+            assert_eq!(1, replayed.len());
+            assert_eq!(same, replayed == stored, "changes in block {}", block);
+        }
+        assert!(replay.entity_changes(5).is_empty());
+    })
+}
@@ -39,7 +39,7 @@ use graph::prelude::{
 };
 use graph::schema::InputSchema;
 use graph_chain_ethereum::Chain;
-use graph_core::polling_monitor::{arweave_service, ipfs_service};
+use graph_core::polling_monitor::{arweave_service, http_service, ipfs_service};
 use graph_core::{
     SubgraphAssignmentProvider as IpfsSubgraphAssignmentProvider, SubgraphInstanceManager,
     SubgraphRegistrar as IpfsSubgraphRegistrar, SubgraphTriggerProcessor,
@@ -462,6 +462,7 @@ pub async fn setup<C: Blockchain>(
         env_vars.mappings.max_ipfs_file_bytes as u64,
         env_vars.mappings.ipfs_timeout,
         env_vars.mappings.ipfs_request_limit,
+        None,
     );
 
     let arweave_resolver = Arc::new(ArweaveClient::default());
@@ -473,6 +474,13 @@ pub async fn setup<C: Blockchain>(
             0 => FileSizeLimit::Unlimited,
             n => FileSizeLimit::MaxBytes(n as u64),
         },
+        None,
+    );
+    let http_service = http_service(
+        vec![],
+        env_vars.mappings.max_ipfs_file_bytes as u64,
+        env_vars.mappings.ipfs_timeout,
+        env_vars.mappings.ipfs_request_limit,
     );
     let sg_count = Arc::new(SubgraphCountMetric::new(mock_registry.cheap_clone()));
 
@@ -487,6 +495,7 @@ pub async fn setup<C: Blockchain>(
         link_resolver.cheap_clone(),
         ipfs_service,
         arweave_service,
+        http_service,
         static_filters,
     );
 